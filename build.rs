@@ -0,0 +1,5 @@
+//! Records build metadata for `sark_rust.build_info()`
+
+fn main() {
+    sark_build::emit();
+}
@@ -0,0 +1,58 @@
+//! Build metadata for SARK's Rust artifacts
+//!
+//! The gateway reports it on `/health` and the Python module returns it
+//! from `sark_rust.build_info()`, so operators can tell exactly what is
+//! deployed from either side. A crate reporting it calls [`emit`] from its
+//! build script (with this crate as a build dependency too), then builds
+//! the value with [`build_info!`], naming what it was built with:
+//!
+//! ```ignore
+//! // build.rs
+//! fn main() {
+//!     sark_build::emit();
+//! }
+//!
+//! // src/main.rs
+//! let info = sark_build::build_info! { "redis" => true, "wasm" => false };
+//! ```
+
+mod script;
+
+pub use script::emit;
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// What an artifact is and was built from
+#[derive(Debug, Clone, Serialize)]
+pub struct BuildInfo {
+    /// Crate version
+    pub version: &'static str,
+    /// Commit built from (`SARK_GIT_SHA` when building outside a checkout),
+    /// or `unknown`
+    pub git_sha: &'static str,
+    /// Cargo profile: `release` or `debug`
+    pub profile: &'static str,
+    /// Compiler, as `rustc --version` reports it
+    pub rustc: &'static str,
+    /// regorus version in `Cargo.lock`, or `unknown`
+    pub regorus: &'static str,
+    /// Capabilities and whether they were built in
+    pub features: BTreeMap<&'static str, bool>,
+}
+
+/// The calling crate's [`BuildInfo`], with the given capabilities. Needs
+/// [`emit`] to have run in its build script.
+#[macro_export]
+macro_rules! build_info {
+    ($($feature:literal => $enabled:expr),* $(,)?) => {
+        $crate::BuildInfo {
+            version: env!("CARGO_PKG_VERSION"),
+            git_sha: env!("SARK_GIT_SHA"),
+            profile: env!("SARK_BUILD_PROFILE"),
+            rustc: env!("SARK_RUSTC_VERSION"),
+            regorus: env!("SARK_REGORUS_VERSION"),
+            features: [$(($feature, $enabled)),*].into_iter().collect(),
+        }
+    };
+}
@@ -0,0 +1,83 @@
+//! The build script half: working out the metadata and handing it to the
+//! compiler as environment variables
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Set by image builds, which have no `.git` to ask
+const GIT_SHA: &str = "SARK_GIT_SHA";
+
+/// Set the variables [`build_info!`](crate::build_info) reads. Call from a
+/// build script.
+pub fn emit() {
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap_or_default());
+
+    println!("cargo:rerun-if-env-changed={}", GIT_SHA);
+    let git_sha = env::var(GIT_SHA)
+        .ok()
+        .filter(|sha| !sha.is_empty())
+        .or_else(|| git_sha(&manifest_dir))
+        .unwrap_or_else(|| "unknown".to_string());
+    set("SARK_GIT_SHA", &git_sha);
+
+    set(
+        "SARK_BUILD_PROFILE",
+        &env::var("PROFILE").unwrap_or_else(|_| "unknown".to_string()),
+    );
+
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let rustc = output(Command::new(rustc).arg("--version")).unwrap_or_else(|| "unknown".into());
+    set("SARK_RUSTC_VERSION", &rustc);
+
+    let regorus = lockfile(&manifest_dir)
+        .and_then(|(path, lock)| {
+            println!("cargo:rerun-if-changed={}", path.display());
+            locked_version(&lock, "regorus")
+        })
+        .unwrap_or_else(|| "unknown".to_string());
+    set("SARK_REGORUS_VERSION", &regorus);
+}
+
+fn set(name: &str, value: &str) {
+    println!("cargo:rustc-env={}={}", name, value);
+}
+
+/// Short sha of `HEAD`, rebuilding when it moves
+fn git_sha(dir: &Path) -> Option<String> {
+    let git = |args: &[&str]| output(Command::new("git").args(args).current_dir(dir));
+    let git_dir = PathBuf::from(git(&["rev-parse", "--absolute-git-dir"])?);
+    let mut watched = vec![git_dir.join("HEAD"), git_dir.join("packed-refs")];
+    if let Some(head) = git(&["symbolic-ref", "-q", "HEAD"]) {
+        watched.push(git_dir.join(head));
+    }
+    // A missing path would rerun the script on every build
+    for path in watched.iter().filter(|path| path.exists()) {
+        println!("cargo:rerun-if-changed={}", path.display());
+    }
+    git(&["rev-parse", "--short=12", "HEAD"])
+}
+
+fn output(command: &mut Command) -> Option<String> {
+    let output = command.output().ok().filter(|o| o.status.success())?;
+    let text = String::from_utf8(output.stdout).ok()?;
+    Some(text.trim().to_string()).filter(|text| !text.is_empty())
+}
+
+/// The workspace's `Cargo.lock` and its contents, from `dir` or above
+fn lockfile(dir: &Path) -> Option<(PathBuf, String)> {
+    dir.ancestors().find_map(|dir| {
+        let path = dir.join("Cargo.lock");
+        let lock = std::fs::read_to_string(&path).ok()?;
+        Some((path, lock))
+    })
+}
+
+/// Version of `package` in a `Cargo.lock`
+fn locked_version(lock: &str, package: &str) -> Option<String> {
+    let name = format!("name = \"{}\"", package);
+    let mut lines = lock.lines().map(str::trim);
+    lines.find(|line| *line == name)?;
+    let version = lines.next()?.strip_prefix("version = \"")?;
+    Some(version.trim_end_matches('"').to_string())
+}
@@ -0,0 +1,21 @@
+//! Package errors
+
+/// Why a package couldn't be loaded or evaluated
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// Not TOML, or not laid out as a package
+    #[error("Invalid CEL package: {0}")]
+    Invalid(String),
+    #[error("Failed to compile rule {rule}: {message}")]
+    Compile { rule: String, message: String },
+    /// The input or data couldn't be made CEL values
+    #[error("Invalid CEL input: {0}")]
+    Input(String),
+    /// Evaluating a rule failed, e.g. on a missing field not guarded with
+    /// `has()`
+    #[error("Failed to evaluate rule {rule}: {message}")]
+    Evaluate { rule: String, message: String },
+    /// The query names a rule the package doesn't define
+    #[error("Undefined rule {0}")]
+    UndefinedRule(String),
+}
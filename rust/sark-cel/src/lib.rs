@@ -0,0 +1,187 @@
+//! CEL policy packages
+//!
+//! A policy package can be written in CEL instead of rego, where its rules
+//! are conditions simple enough to read better as expressions. A package
+//! is a TOML file naming it and defining each rule as a CEL expression over
+//! `input` and `data`:
+//!
+//! ```toml
+//! package = "sark.gateway"
+//!
+//! [rules]
+//! allow = '"admin" in input.user.roles || input.tool.name in data.tools.allowed'
+//! reason = '"admin" in input.user.roles ? "Admin access" : "Tool not allowed"'
+//! cache_ttl = "60"
+//! ```
+//!
+//! Evaluated, a package is the same document a rego package would be, its
+//! rules' values by name, so `allow`, `reason`, `filtered_parameters`,
+//! `obligations` and `cache_ttl` are read from either alike. Rules can't
+//! refer to one another, and a rule that fails to evaluate (on a field not
+//! guarded with `has()`, say) fails the whole query rather than being left
+//! undefined as in rego.
+//!
+//! The gateway evaluates a query naming a CEL package (`data.sark.gateway`,
+//! or one of its rules) by the package instead of its rego engine, and so
+//! does `RustOPAEngine` in the Python API.
+
+mod error;
+
+pub use error::Error;
+
+use cel_interpreter::{Context, Program};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// A package's file, before its rules compile
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Source {
+    package: String,
+    rules: BTreeMap<String, String>,
+}
+
+/// A compiled CEL policy package
+pub struct Package {
+    package: String,
+    source: String,
+    /// `(name, program)` pairs, by name
+    rules: Vec<(String, Program)>,
+}
+
+impl Package {
+    /// Parse and compile a package file
+    pub fn parse(source: &str) -> Result<Self, Error> {
+        let parsed: Source = toml::from_str(source).map_err(|e| Error::Invalid(e.to_string()))?;
+        if !parsed.package.split('.').all(is_identifier) {
+            return Err(Error::Invalid(format!(
+                "package {:?} is not a dotted name",
+                parsed.package
+            )));
+        }
+        if parsed.rules.is_empty() {
+            return Err(Error::Invalid("no rules".to_string()));
+        }
+
+        let mut rules = Vec::with_capacity(parsed.rules.len());
+        for (name, expression) in parsed.rules {
+            if !is_identifier(&name) {
+                return Err(Error::Invalid(format!("rule {:?} is not a name", name)));
+            }
+            let program = Program::compile(&expression).map_err(|e| Error::Compile {
+                rule: name.clone(),
+                message: e.to_string(),
+            })?;
+            rules.push((name, program));
+        }
+        Ok(Self {
+            package: parsed.package,
+            source: source.to_string(),
+            rules,
+        })
+    }
+
+    /// The dotted package name, e.g. `sark.gateway`
+    pub fn name(&self) -> &str {
+        &self.package
+    }
+
+    /// The package file as parsed
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Names of the package's rules
+    pub fn rules(&self) -> impl Iterator<Item = &str> {
+        self.rules.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Whether `query` names the package (`data.<package>`) or one of its
+    /// rules (`data.<package>.<rule>`)
+    pub fn answers(&self, query: &str) -> bool {
+        self.rule_of(query).is_some()
+    }
+
+    /// Evaluate `query`, which [`answers`](Self::answers) must hold for,
+    /// against `input` and `data`: the package's document, or one rule's
+    /// value
+    pub fn evaluate(
+        &self,
+        query: &str,
+        input: &Value,
+        data: &Map<String, Value>,
+    ) -> Result<Value, Error> {
+        let Some(rule) = self.rule_of(query) else {
+            return Err(Error::UndefinedRule(query.to_string()));
+        };
+        let mut context = Context::default();
+        context
+            .add_variable("input", input)
+            .map_err(|e| Error::Input(e.to_string()))?;
+        context
+            .add_variable("data", data)
+            .map_err(|e| Error::Input(e.to_string()))?;
+
+        match rule {
+            Some(rule) => {
+                let Some((name, program)) = self.rules.iter().find(|(name, _)| name == rule) else {
+                    return Err(Error::UndefinedRule(query.to_string()));
+                };
+                execute(name, program, &context)
+            }
+            None => {
+                let mut document = Map::new();
+                for (name, program) in &self.rules {
+                    document.insert(name.clone(), execute(name, program, &context)?);
+                }
+                Ok(Value::Object(document))
+            }
+        }
+    }
+
+    /// The rule `query` names, `None` for the whole package, if it is this
+    /// package's
+    fn rule_of<'q>(&self, query: &'q str) -> Option<Option<&'q str>> {
+        let rest = query
+            .strip_prefix("data.")?
+            .strip_prefix(self.package.as_str())?;
+        match rest {
+            "" => Some(None),
+            rest => rest
+                .strip_prefix('.')
+                .filter(|rule| is_identifier(rule))
+                .map(Some),
+        }
+    }
+}
+
+impl fmt::Debug for Package {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Package")
+            .field("package", &self.package)
+            .field("rules", &self.rules().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// Run `program`, the rule `name`, with its value as JSON
+fn execute(name: &str, program: &Program, context: &Context) -> Result<Value, Error> {
+    let failed = |message: String| Error::Evaluate {
+        rule: name.to_string(),
+        message,
+    };
+    let value = program
+        .execute(context)
+        .map_err(|e| failed(e.to_string()))?;
+    value.json().map_err(|e| failed(e.to_string()))
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
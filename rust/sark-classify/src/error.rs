@@ -0,0 +1,20 @@
+//! Classifier errors
+
+/// Why a level or a set of rules was refused
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Unknown sensitivity level {0:?} (expected low, medium, high or critical)")]
+    UnknownLevel(String),
+    /// A rule without criteria, which would match every tool call
+    #[error("Rule {0} has no servers, tools, keywords or parameters to match")]
+    Unconditional(String),
+    #[error("Empty keyword in rule {0}")]
+    EmptyKeyword(String),
+    #[error("Invalid pattern {pattern:?} in rule {rule}: {source}")]
+    Pattern {
+        rule: String,
+        pattern: String,
+        #[source]
+        source: regex::Error,
+    },
+}
@@ -0,0 +1,425 @@
+//! Sensitivity levels of tool calls
+//!
+//! Policies weigh a tool call by `input.resource.sensitivity`. A caller
+//! that doesn't give one used to get `medium`, whatever the tool did; the
+//! gateway and the Python API instead classify the call by rules over what
+//! they know of it:
+//!
+//! ```toml
+//! [classify]
+//! default_level = "medium"
+//!
+//! [[classify.rules]]
+//! name = "payments"
+//! level = "critical"
+//! servers = ["payments-*"]
+//!
+//! [[classify.rules]]
+//! name = "destructive sql"
+//! level = "high"
+//! tools = ["*sql*", "query_db"]
+//! parameters = ['(?i)\b(drop|truncate|delete)\b']
+//! ```
+//!
+//! A rule matches on any of:
+//!
+//! - `servers`, `tools` - the server's and tool's names, where `*` matches
+//!   any run of characters
+//! - `keywords` - words in the tool's name, description or parameter names
+//!   (`credit_card` matches `credit card` too)
+//! - `parameters` - regular expressions over the parameters' string
+//!   values, however deeply nested
+//!
+//! Each criterion a rule gives has to match, by any of its entries. A call
+//! gets the most sensitive level of the rules it matches. One no rule
+//! matches is classified by the keyword lists the tool registry has always
+//! used (unless `builtin_rules = false`): `password`, `token`, `payment`
+//! and the like are critical; `delete`, `exec`, `admin` are high; `write`,
+//! `update`, `create` are medium; `read`, `get`, `list` are low. Anything
+//! else is `default_level`.
+//!
+//! With the `python` feature it is exposed to the Python API as
+//! `SensitivityClassifier`.
+
+mod error;
+#[cfg(feature = "python")]
+pub mod python;
+
+pub use error::Error;
+
+use regex::{Regex, RegexSet};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::str::FromStr;
+
+/// How sensitive a tool call is, least first
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Low,
+    #[default]
+    Medium,
+    High,
+    Critical,
+}
+
+impl Level {
+    pub const ALL: [Level; 4] = [Level::Low, Level::Medium, Level::High, Level::Critical];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Level::Low => "low",
+            Level::Medium => "medium",
+            Level::High => "high",
+            Level::Critical => "critical",
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Level {
+    type Err = Error;
+
+    fn from_str(level: &str) -> Result<Self, Error> {
+        Level::ALL
+            .into_iter()
+            .find(|candidate| candidate.as_str() == level)
+            .ok_or_else(|| Error::UnknownLevel(level.to_string()))
+    }
+}
+
+/// Tool calls to give a level
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    /// Named in logs; `rules[<index>]` where unset
+    #[serde(default)]
+    pub name: Option<String>,
+    pub level: Level,
+    #[serde(default)]
+    pub servers: Vec<String>,
+    #[serde(default)]
+    pub tools: Vec<String>,
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    #[serde(default)]
+    pub parameters: Vec<String>,
+}
+
+/// The `[classify]` section
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClassifyConfig {
+    /// Level of calls no rule matches
+    pub default_level: Level,
+    /// Classify calls no rule matches by the built-in keyword lists
+    pub builtin_rules: bool,
+    pub rules: Vec<Rule>,
+}
+
+impl Default for ClassifyConfig {
+    fn default() -> Self {
+        Self {
+            default_level: Level::Medium,
+            builtin_rules: true,
+            rules: Vec::new(),
+        }
+    }
+}
+
+/// Keywords of the built-in rules, most sensitive first
+const BUILTIN_KEYWORDS: [(Level, &[&str]); 4] = [
+    (
+        Level::Critical,
+        &[
+            "payment",
+            "transaction",
+            "credit_card",
+            "password",
+            "secret",
+            "key",
+            "token",
+            "credential",
+            "auth",
+            "permission",
+            "access_control",
+            "encrypt",
+            "decrypt",
+        ],
+    ),
+    (
+        Level::High,
+        &[
+            "delete", "drop", "exec", "execute", "admin", "root", "sudo", "kill", "destroy",
+            "remove", "purge", "truncate",
+        ],
+    ),
+    (
+        Level::Medium,
+        &[
+            "write", "update", "modify", "change", "edit", "create", "insert", "save", "upload",
+            "put", "post", "patch",
+        ],
+    ),
+    (
+        Level::Low,
+        &[
+            "read", "get", "list", "fetch", "view", "show", "display", "query", "search", "find",
+            "retrieve",
+        ],
+    ),
+];
+
+/// The tool call to classify
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Call<'a> {
+    pub server: &'a str,
+    pub tool: &'a str,
+    pub description: Option<&'a str>,
+    /// The call's arguments, or the tool's JSON schema for them
+    pub parameters: Option<&'a Value>,
+}
+
+/// A call's level, and the rule that gave it (none for `default_level`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Classification<'a> {
+    pub level: Level,
+    pub rule: Option<&'a str>,
+}
+
+/// Rules compiled from a [`ClassifyConfig`]
+#[derive(Debug, Clone)]
+pub struct Classifier {
+    default_level: Level,
+    rules: Vec<Compiled>,
+    builtin: Vec<Compiled>,
+}
+
+#[derive(Debug, Clone)]
+struct Compiled {
+    name: String,
+    level: Level,
+    servers: Option<Regex>,
+    tools: Option<Regex>,
+    keywords: Option<Regex>,
+    parameters: Option<RegexSet>,
+}
+
+impl Classifier {
+    pub fn new(config: &ClassifyConfig) -> Result<Self, Error> {
+        let rules = config
+            .rules
+            .iter()
+            .enumerate()
+            .map(|(index, rule)| {
+                let name = rule
+                    .name
+                    .clone()
+                    .unwrap_or_else(|| format!("rules[{}]", index));
+                Compiled::new(name, rule)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let builtin = BUILTIN_KEYWORDS
+            .into_iter()
+            .filter(|_| config.builtin_rules)
+            .map(|(level, keywords)| {
+                let rule = Rule {
+                    name: None,
+                    level,
+                    servers: Vec::new(),
+                    tools: Vec::new(),
+                    keywords: keywords.iter().map(|keyword| keyword.to_string()).collect(),
+                    parameters: Vec::new(),
+                };
+                Compiled::new(format!("builtin:{}", level), &rule)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            default_level: config.default_level,
+            rules,
+            builtin,
+        })
+    }
+
+    /// `call`'s level: that of the most sensitive configured rule it
+    /// matches (the first of them if several are as sensitive), else of the
+    /// most sensitive built-in one, else the default
+    pub fn classify(&self, call: &Call<'_>) -> Classification<'_> {
+        let text = keyword_text(call);
+        match most_sensitive(&self.rules, call, &text)
+            .or_else(|| most_sensitive(&self.builtin, call, &text))
+        {
+            Some(rule) => Classification {
+                level: rule.level,
+                rule: Some(&rule.name),
+            },
+            None => Classification {
+                level: self.default_level,
+                rule: None,
+            },
+        }
+    }
+
+    pub fn default_level(&self) -> Level {
+        self.default_level
+    }
+
+    /// Number of rules, the built-in ones included
+    pub fn rule_count(&self) -> usize {
+        self.rules.len() + self.builtin.len()
+    }
+}
+
+fn most_sensitive<'a>(rules: &'a [Compiled], call: &Call<'_>, text: &str) -> Option<&'a Compiled> {
+    let mut best: Option<&Compiled> = None;
+    for rule in rules {
+        if best.is_some_and(|current| rule.level <= current.level) || !rule.matches(call, text) {
+            continue;
+        }
+        best = Some(rule);
+        if rule.level == Level::Critical {
+            break;
+        }
+    }
+    best
+}
+
+impl Compiled {
+    fn new(name: String, rule: &Rule) -> Result<Self, Error> {
+        if rule.servers.is_empty()
+            && rule.tools.is_empty()
+            && rule.keywords.is_empty()
+            && rule.parameters.is_empty()
+        {
+            return Err(Error::Unconditional(name));
+        }
+        if rule
+            .keywords
+            .iter()
+            .any(|keyword| keyword.trim().is_empty())
+        {
+            return Err(Error::EmptyKeyword(name));
+        }
+        let invalid = |pattern: &str| {
+            let name = name.clone();
+            let pattern = pattern.to_string();
+            move |source| Error::Pattern {
+                rule: name,
+                pattern,
+                source,
+            }
+        };
+
+        // An alternation of `alternatives` between `prefix` and `suffix`,
+        // none if there are no alternatives
+        let either = |alternatives: Vec<String>, prefix: &str, suffix: &str| {
+            if alternatives.is_empty() {
+                return Ok(None);
+            }
+            let pattern = format!("{}(?:{}){}", prefix, alternatives.join("|"), suffix);
+            Regex::new(&pattern).map(Some).map_err(invalid(&pattern))
+        };
+        let globs = |patterns: &[String]| -> Vec<String> {
+            patterns
+                .iter()
+                .map(|pattern| {
+                    pattern
+                        .split('*')
+                        .map(regex::escape)
+                        .collect::<Vec<_>>()
+                        .join(".*")
+                })
+                .collect()
+        };
+        let servers = either(globs(&rule.servers), "^", "$")?;
+        let tools = either(globs(&rule.tools), "^", "$")?;
+        let keywords = either(
+            rule.keywords
+                .iter()
+                .map(|keyword| regex::escape(&keyword.trim().to_lowercase()).replace('_', "[ _]"))
+                .collect(),
+            "(?:^|[^a-z])",
+            "(?:$|[^a-z])",
+        )?;
+        for pattern in &rule.parameters {
+            Regex::new(pattern).map_err(invalid(pattern))?;
+        }
+        let parameters = (!rule.parameters.is_empty())
+            .then(|| RegexSet::new(&rule.parameters))
+            .transpose()
+            .map_err(invalid(&rule.parameters.join(", ")))?;
+
+        Ok(Self {
+            servers,
+            tools,
+            keywords,
+            parameters,
+            name,
+            level: rule.level,
+        })
+    }
+
+    /// Whether `call` meets every criterion; `text` is its lowercase name,
+    /// description and parameter names
+    fn matches(&self, call: &Call<'_>, text: &str) -> bool {
+        self.servers
+            .as_ref()
+            .map_or(true, |servers| servers.is_match(call.server))
+            && self
+                .tools
+                .as_ref()
+                .map_or(true, |tools| tools.is_match(call.tool))
+            && self
+                .keywords
+                .as_ref()
+                .map_or(true, |keywords| keywords.is_match(text))
+            && self.parameters.as_ref().map_or(true, |parameters| {
+                call.parameters
+                    .is_some_and(|value| any_string(value, &|s: &str| parameters.is_match(s)))
+            })
+    }
+}
+
+/// The text keywords are looked for in
+fn keyword_text(call: &Call<'_>) -> String {
+    let mut text = call.tool.to_lowercase();
+    if let Some(description) = call.description {
+        text.push(' ');
+        text.push_str(&description.to_lowercase());
+    }
+    for name in parameter_names(call.parameters) {
+        text.push(' ');
+        text.push_str(&name.to_lowercase());
+    }
+    text
+}
+
+/// Names of the parameters: a JSON schema's `properties`, or an object's
+/// keys
+fn parameter_names(parameters: Option<&Value>) -> impl Iterator<Item = &String> {
+    let object = match parameters {
+        Some(Value::Object(object)) => match object.get("properties") {
+            Some(Value::Object(properties)) => Some(properties),
+            _ => Some(object),
+        },
+        _ => None,
+    };
+    object.into_iter().flat_map(|object| object.keys())
+}
+
+fn any_string(value: &Value, matches: &impl Fn(&str) -> bool) -> bool {
+    match value {
+        Value::String(s) => matches(s),
+        Value::Array(items) => items.iter().any(|item| any_string(item, matches)),
+        Value::Object(object) => object.values().any(|item| any_string(item, matches)),
+        _ => false,
+    }
+}
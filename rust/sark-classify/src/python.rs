@@ -0,0 +1,123 @@
+//! Python bindings
+//!
+//! `SensitivityClassifier` gives tools the level the gateway would give
+//! calls to them, for the tool registry and the Python API:
+//!
+//! ```python
+//! from sark.sark_rust import SensitivityClassifier
+//!
+//! classifier = SensitivityClassifier(
+//!     [{"name": "payments", "level": "critical", "servers": ["payments-*"]}],
+//! )
+//! classifier.classify("query_db", "Run a query", {"sql": "DROP TABLE users"})
+//! level, rule = classifier.explain("get_invoice", server="payments-eu")
+//! ```
+//!
+//! Rules are dicts shaped as the gateway's `[[classify.rules]]`; invalid
+//! ones, and unknown levels, raise `ValueError`.
+
+use crate::{Call, Classifier, ClassifyConfig, Level, Rule};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pythonize::depythonize;
+use serde_json::Value;
+
+/// Sensitivity levels of tool calls, by the gateway's rules
+#[pyclass(module = "sark_rust", name = "SensitivityClassifier", frozen)]
+pub struct PySensitivityClassifier {
+    classifier: Classifier,
+}
+
+#[pymethods]
+impl PySensitivityClassifier {
+    #[new]
+    #[pyo3(signature = (rules = None, *, default_level = "medium", builtin_rules = true))]
+    fn new(
+        rules: Option<&Bound<'_, PyAny>>,
+        default_level: &str,
+        builtin_rules: bool,
+    ) -> PyResult<Self> {
+        let rules: Vec<Rule> = match rules {
+            Some(rules) => depythonize(rules)
+                .map_err(|e| PyValueError::new_err(format!("Invalid classify rules: {}", e)))?,
+            None => Vec::new(),
+        };
+        let config = ClassifyConfig {
+            default_level: default_level.parse().map_err(value_error)?,
+            builtin_rules,
+            rules,
+        };
+        Ok(Self {
+            classifier: Classifier::new(&config).map_err(value_error)?,
+        })
+    }
+
+    /// The level of a call to `tool_name`; `parameters` may be the call's
+    /// arguments or the tool's JSON schema
+    #[pyo3(signature = (tool_name, description = None, parameters = None, *, server = ""))]
+    fn classify(
+        &self,
+        tool_name: &str,
+        description: Option<&str>,
+        parameters: Option<&Bound<'_, PyAny>>,
+        server: &str,
+    ) -> PyResult<&'static str> {
+        Ok(self.explain(tool_name, description, parameters, server)?.0)
+    }
+
+    /// The level of a call to `tool_name` and the name of the rule that
+    /// gave it, `None` for the default level
+    #[pyo3(signature = (tool_name, description = None, parameters = None, *, server = ""))]
+    fn explain(
+        &self,
+        tool_name: &str,
+        description: Option<&str>,
+        parameters: Option<&Bound<'_, PyAny>>,
+        server: &str,
+    ) -> PyResult<(&'static str, Option<String>)> {
+        let parameters: Option<Value> = parameters
+            .filter(|parameters| !parameters.is_none())
+            .map(|parameters| {
+                depythonize(parameters)
+                    .map_err(|e| PyValueError::new_err(format!("Invalid parameters: {}", e)))
+            })
+            .transpose()?;
+        let classification = self.classifier.classify(&Call {
+            server,
+            tool: tool_name,
+            description,
+            parameters: parameters.as_ref(),
+        });
+        Ok((
+            classification.level.as_str(),
+            classification.rule.map(str::to_string),
+        ))
+    }
+
+    #[getter]
+    fn default_level(&self) -> &'static str {
+        self.classifier.default_level().as_str()
+    }
+
+    /// Number of rules, the built-in ones included
+    fn rule_count(&self) -> usize {
+        self.classifier.rule_count()
+    }
+
+    #[classattr]
+    fn levels() -> Vec<&'static str> {
+        Level::ALL.into_iter().map(Level::as_str).collect()
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "SensitivityClassifier(default_level={:?}, rules={})",
+            self.default_level(),
+            self.rule_count()
+        )
+    }
+}
+
+fn value_error(e: crate::Error) -> PyErr {
+    PyValueError::new_err(e.to_string())
+}
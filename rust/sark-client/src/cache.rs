@@ -0,0 +1,79 @@
+//! Local decision cache
+//!
+//! Decisions are kept for the `cache_ttl` the gateway gave them, so a
+//! client asking the same thing again within it gets the answer without a
+//! round trip. Keys are the request's JSON body with the endpoint's route,
+//! so any difference in the request (parameters, context) is a miss. Dry
+//! runs and decisions with no TTL aren't kept, nor are decisions made
+//! without the gateway. When full, expired entries are dropped first, then
+//! whichever is closest to expiring.
+
+use crate::types::Decision;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+pub(crate) struct DecisionCache {
+    entries: Mutex<HashMap<String, (Instant, Decision)>>,
+    max_entries: usize,
+    /// Upper bound on any decision's lifetime here, below the gateway's TTL
+    max_ttl: Option<Duration>,
+}
+
+impl DecisionCache {
+    pub fn new(max_entries: usize, max_ttl: Option<Duration>) -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+            max_entries,
+            max_ttl,
+        }
+    }
+
+    pub fn key(route: &str, body: &[u8]) -> String {
+        format!("{route}\n{}", String::from_utf8_lossy(body))
+    }
+
+    pub fn get(&self, key: &str) -> Option<Decision> {
+        let mut entries = self.entries.lock().expect("decision cache lock poisoned");
+        match entries.get(key) {
+            Some((expires, decision)) if *expires > Instant::now() => Some(decision.clone()),
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub fn insert(&self, key: String, decision: &Decision) {
+        if self.max_entries == 0 || decision.cache_ttl == 0 || decision.dry_run {
+            return;
+        }
+        let mut ttl = Duration::from_secs(decision.cache_ttl.into());
+        if let Some(max_ttl) = self.max_ttl {
+            ttl = ttl.min(max_ttl);
+        }
+        let now = Instant::now();
+        let mut entries = self.entries.lock().expect("decision cache lock poisoned");
+        if entries.len() >= self.max_entries && !entries.contains_key(&key) {
+            entries.retain(|_, (expires, _)| *expires > now);
+            if entries.len() >= self.max_entries {
+                let soonest = entries
+                    .iter()
+                    .min_by_key(|(_, (expires, _))| *expires)
+                    .map(|(key, _)| key.clone());
+                if let Some(soonest) = soonest {
+                    entries.remove(&soonest);
+                }
+            }
+        }
+        entries.insert(key, (now + ttl, decision.clone()));
+    }
+
+    pub fn clear(&self) {
+        self.entries
+            .lock()
+            .expect("decision cache lock poisoned")
+            .clear();
+    }
+}
@@ -0,0 +1,61 @@
+//! Errors calling the gateway
+
+use crate::types::Problem;
+use std::time::Duration;
+
+/// A request the gateway didn't answer with a decision
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The gateway couldn't be reached, or didn't answer in time
+    #[error("gateway unreachable: {0}")]
+    Transport(#[source] reqwest::Error),
+    /// The gateway failed the request with a problem document
+    #[error("{} ({}): {}", .problem.title, .problem.status, .problem.detail)]
+    Problem {
+        problem: Problem,
+        /// From `Retry-After`, when the gateway sent one
+        retry_after: Option<Duration>,
+    },
+    /// The gateway answered with something other than a decision or a
+    /// problem
+    #[error("unexpected response ({status}): {detail}")]
+    UnexpectedResponse { status: u16, detail: String },
+    /// The client was configured wrongly (e.g. a base URL that isn't one)
+    #[error("invalid client configuration: {0}")]
+    Config(String),
+}
+
+impl Error {
+    /// Whether the same request may succeed if sent again later
+    pub fn retryable(&self) -> bool {
+        match self {
+            Error::Transport(error) => error.is_connect() || error.is_timeout(),
+            Error::Problem { problem, .. } => problem.retryable,
+            // A proxy in front of the gateway giving up on it
+            Error::UnexpectedResponse { status, .. } => matches!(status, 502..=504),
+            Error::Config(_) => false,
+        }
+    }
+
+    /// Whether the gateway couldn't make a decision, as opposed to refusing
+    /// the request. Fail-open applies to these only: a malformed request or
+    /// a bad token is the caller's to fix, not to allow.
+    pub fn unavailable(&self) -> bool {
+        match self {
+            Error::Transport(_) => true,
+            Error::Problem { problem, .. } => problem.retryable || problem.status >= 500,
+            Error::UnexpectedResponse { status, .. } => *status >= 500,
+            Error::Config(_) => false,
+        }
+    }
+
+    /// The problem the gateway failed the request with, if it did
+    pub fn problem(&self) -> Option<&Problem> {
+        match self {
+            Error::Problem { problem, .. } => Some(problem),
+            _ => None,
+        }
+    }
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
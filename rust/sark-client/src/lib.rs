@@ -0,0 +1,456 @@
+//! Async client for the SARK gateway's authorization API
+//!
+//! A typed client for `POST /gateway/authorize`, `/gateway/authorize-a2a`
+//! and `/gateway/authorize/batch`, for Rust services to ask the gateway
+//! rather than hand-roll requests against its JSON:
+//!
+//! ```no_run
+//! # async fn run() -> sark_client::Result<()> {
+//! use sark_client::{AuthorizeRequest, Client, FailureMode};
+//!
+//! let client = Client::builder("http://sark-gateway:8080")
+//!     .bearer_token("eyJ...")
+//!     .failure_mode(FailureMode::Closed)
+//!     .build()?;
+//! let decision = client
+//!     .authorize(&AuthorizeRequest::new("tool:invoke", "github", "create_issue"))
+//!     .await?;
+//! if !decision.allow {
+//!     println!("denied: {}", decision.reason);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! A denial is a [`Decision`] like any other; [`Error`] is for requests the
+//! gateway didn't decide. Failures worth retrying are retried with jittered
+//! backoff (see [`RetryPolicy`]), and decisions are cached locally for the
+//! TTL the gateway gave them. When the gateway is unavailable after
+//! retries, [`FailureMode`] decides between failing the call (the default)
+//! and allowing it.
+
+mod cache;
+mod error;
+mod retry;
+mod types;
+
+pub use error::{Error, Result};
+pub use retry::RetryPolicy;
+pub use types::{
+    A2aRequest, AgentIdentity, AuthorizeRequest, BatchItem, Decision, LogLevel, Obligations,
+    Problem, StepUp, Watermark,
+};
+
+use cache::DecisionCache;
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE, RETRY_AFTER};
+use serde::de::DeserializeOwned;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, warn};
+use types::{BatchRequest, BatchResponse};
+
+const AUTHORIZE_ROUTE: &str = "/gateway/authorize";
+const AUTHORIZE_A2A_ROUTE: &str = "/gateway/authorize-a2a";
+const BATCH_ROUTE: &str = "/gateway/authorize/batch";
+
+/// Header the gateway takes API keys in
+const API_KEY_HEADER: &str = "x-api-key";
+
+/// Most requests the gateway takes in one batch; larger batches are split
+const MAX_BATCH_SIZE: usize = 1000;
+
+/// Revision reported for decisions made without the gateway
+pub const FAIL_OPEN_REVISION: &str = "fail-open";
+
+/// What to do when the gateway can't make a decision
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FailureMode {
+    /// Allow the request, with [`FAIL_OPEN_REVISION`] as its revision
+    Open,
+    /// Fail the call with the error
+    #[default]
+    Closed,
+}
+
+/// How the client identifies itself to the gateway
+#[derive(Clone)]
+enum Credentials {
+    None,
+    Bearer(String),
+    ApiKey(String),
+}
+
+impl Credentials {
+    /// Part of the cache key, since decisions depend on the caller
+    fn cache_scope(&self) -> &str {
+        match self {
+            Credentials::None => "",
+            Credentials::Bearer(token) | Credentials::ApiKey(token) => token,
+        }
+    }
+
+    fn headers(&self) -> Result<HeaderMap> {
+        let mut headers = HeaderMap::new();
+        match self {
+            Credentials::None => {}
+            Credentials::Bearer(token) => {
+                headers.insert(AUTHORIZATION, sensitive(&format!("Bearer {token}"))?);
+            }
+            Credentials::ApiKey(key) => {
+                headers.insert(API_KEY_HEADER, sensitive(key)?);
+            }
+        }
+        Ok(headers)
+    }
+}
+
+/// Builds a [`Client`]
+pub struct ClientBuilder {
+    base_url: String,
+    credentials: Credentials,
+    timeout: Duration,
+    connect_timeout: Duration,
+    pool_max_idle_per_host: usize,
+    retry: RetryPolicy,
+    cache_entries: usize,
+    cache_max_ttl: Option<Duration>,
+    failure_mode: FailureMode,
+    http: Option<reqwest::Client>,
+}
+
+impl ClientBuilder {
+    /// Authenticate with a JWT, as `Authorization: Bearer`
+    pub fn bearer_token(mut self, token: impl Into<String>) -> Self {
+        self.credentials = Credentials::Bearer(token.into());
+        self
+    }
+
+    /// Authenticate with an API key, as `X-API-Key`
+    pub fn api_key(mut self, key: impl Into<String>) -> Self {
+        self.credentials = Credentials::ApiKey(key.into());
+        self
+    }
+
+    /// Time allowed for each attempt, including reading the response
+    /// (default 2s)
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Time allowed to connect (default 500ms)
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// Idle connections kept open to the gateway (default 32)
+    pub fn pool_max_idle_per_host(mut self, max: usize) -> Self {
+        self.pool_max_idle_per_host = max;
+        self
+    }
+
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Decisions kept locally (default 10,000; 0 disables the cache)
+    pub fn cache_entries(mut self, entries: usize) -> Self {
+        self.cache_entries = entries;
+        self
+    }
+
+    /// Longest a decision is kept locally, if shorter than the gateway's
+    /// TTL for it
+    pub fn cache_max_ttl(mut self, ttl: Duration) -> Self {
+        self.cache_max_ttl = Some(ttl);
+        self
+    }
+
+    pub fn failure_mode(mut self, mode: FailureMode) -> Self {
+        self.failure_mode = mode;
+        self
+    }
+
+    /// Send requests with `http` (e.g. one set up for mTLS), whose own
+    /// timeouts and pool then apply instead of this builder's
+    pub fn http_client(mut self, http: reqwest::Client) -> Self {
+        self.http = Some(http);
+        self
+    }
+
+    pub fn build(self) -> Result<Client> {
+        let base_url = self.base_url.trim_end_matches('/').to_string();
+        reqwest::Url::parse(&base_url)
+            .map_err(|e| Error::Config(format!("base URL {base_url:?}: {e}")))?;
+        let headers = self.credentials.headers()?;
+        let http = match self.http {
+            Some(http) => http,
+            None => reqwest::Client::builder()
+                .timeout(self.timeout)
+                .connect_timeout(self.connect_timeout)
+                .pool_max_idle_per_host(self.pool_max_idle_per_host)
+                .build()
+                .map_err(|e| Error::Config(format!("HTTP client: {e}")))?,
+        };
+        Ok(Client {
+            inner: Arc::new(Inner {
+                http,
+                base_url,
+                retry: self.retry,
+                cache: DecisionCache::new(self.cache_entries, self.cache_max_ttl),
+                failure_mode: self.failure_mode,
+            }),
+            headers,
+            credentials: self.credentials,
+        })
+    }
+}
+
+/// A credential as a header value, kept out of `Debug` output
+fn sensitive(value: &str) -> Result<HeaderValue> {
+    let mut value = HeaderValue::from_str(value)
+        .map_err(|_| Error::Config("credential isn't a valid header value".to_string()))?;
+    value.set_sensitive(true);
+    Ok(value)
+}
+
+/// Client for the gateway's decision endpoints
+///
+/// Cheap to clone: clones share the connection pool and decision cache.
+#[derive(Clone)]
+pub struct Client {
+    inner: Arc<Inner>,
+    headers: HeaderMap,
+    credentials: Credentials,
+}
+
+/// What clients share, whatever their credentials
+struct Inner {
+    http: reqwest::Client,
+    base_url: String,
+    retry: RetryPolicy,
+    cache: DecisionCache,
+    failure_mode: FailureMode,
+}
+
+impl Client {
+    /// A builder for a client of the gateway at `base_url` (e.g.
+    /// `http://sark-gateway:8080`)
+    pub fn builder(base_url: impl Into<String>) -> ClientBuilder {
+        ClientBuilder {
+            base_url: base_url.into(),
+            credentials: Credentials::None,
+            timeout: Duration::from_secs(2),
+            connect_timeout: Duration::from_millis(500),
+            pool_max_idle_per_host: 32,
+            retry: RetryPolicy::default(),
+            cache_entries: 10_000,
+            cache_max_ttl: None,
+            failure_mode: FailureMode::default(),
+            http: None,
+        }
+    }
+
+    /// This client authenticating with `token` instead, as for a service
+    /// forwarding its caller's JWT. It shares the connection pool and
+    /// cache, where decisions are kept apart by credential.
+    pub fn with_bearer_token(&self, token: impl Into<String>) -> Result<Self> {
+        let credentials = Credentials::Bearer(token.into());
+        Ok(Self {
+            inner: self.inner.clone(),
+            headers: credentials.headers()?,
+            credentials,
+        })
+    }
+
+    /// Authorize a tool invocation
+    pub async fn authorize(&self, request: &AuthorizeRequest) -> Result<Decision> {
+        self.decide(AUTHORIZE_ROUTE, request).await
+    }
+
+    /// Authorize a request from one agent to another
+    pub async fn authorize_a2a(&self, request: &A2aRequest) -> Result<Decision> {
+        self.decide(AUTHORIZE_A2A_ROUTE, request).await
+    }
+
+    /// Authorize several tool invocations, with results in request order
+    ///
+    /// Cached decisions are answered locally and the rest sent in batches
+    /// the gateway takes. A request the gateway couldn't decide is an
+    /// [`BatchItem::Error`] without failing the others.
+    pub async fn authorize_batch(&self, requests: &[AuthorizeRequest]) -> Result<Vec<BatchItem>> {
+        let mut results: Vec<Option<BatchItem>> = vec![None; requests.len()];
+        let mut misses = Vec::new();
+        for (index, request) in requests.iter().enumerate() {
+            let key = self.cache_key(AUTHORIZE_ROUTE, request)?;
+            match self.inner.cache.get(&key) {
+                Some(decision) => results[index] = Some(BatchItem::Decision(decision)),
+                None => misses.push((index, key)),
+            }
+        }
+        for chunk in misses.chunks(MAX_BATCH_SIZE) {
+            let batch: Vec<AuthorizeRequest> = chunk
+                .iter()
+                .map(|(index, _)| requests[*index].clone())
+                .collect();
+            let items = match self
+                .send::<BatchResponse, _>(BATCH_ROUTE, &BatchRequest { requests: &batch })
+                .await
+            {
+                Ok(response) if response.results.len() == batch.len() => response.results,
+                Ok(response) => {
+                    return Err(Error::UnexpectedResponse {
+                        status: 200,
+                        detail: format!(
+                            "{} results for {} requests",
+                            response.results.len(),
+                            batch.len()
+                        ),
+                    })
+                }
+                Err(error) => match self.fail_open(BATCH_ROUTE, &error) {
+                    Some(decision) => vec![BatchItem::Decision(decision); batch.len()],
+                    None => return Err(error),
+                },
+            };
+            for ((index, key), item) in chunk.iter().zip(items) {
+                if let BatchItem::Decision(decision) = &item {
+                    if decision.policy_revision != FAIL_OPEN_REVISION {
+                        self.inner.cache.insert(key.clone(), decision);
+                    }
+                }
+                results[*index] = Some(item);
+            }
+        }
+        Ok(results
+            .into_iter()
+            .map(|item| item.expect("every request has a result"))
+            .collect())
+    }
+
+    /// Drop every locally cached decision, e.g. after a policy change the
+    /// caller knows of
+    pub fn clear_cache(&self) {
+        self.inner.cache.clear();
+    }
+
+    /// One decision, from the cache or the gateway at `route`
+    async fn decide<T: serde::Serialize>(&self, route: &str, request: &T) -> Result<Decision> {
+        let key = self.cache_key(route, request)?;
+        if let Some(decision) = self.inner.cache.get(&key) {
+            debug!(route, "decision cache hit");
+            return Ok(decision);
+        }
+        match self.send::<Decision, _>(route, request).await {
+            Ok(decision) => {
+                self.inner.cache.insert(key, &decision);
+                Ok(decision)
+            }
+            Err(error) => self.fail_open(route, &error).ok_or(error),
+        }
+    }
+
+    fn cache_key<T: serde::Serialize>(&self, route: &str, request: &T) -> Result<String> {
+        let body = serde_json::to_vec(request)
+            .map_err(|e| Error::Config(format!("request doesn't serialize: {e}")))?;
+        Ok(format!(
+            "{}\n{}",
+            self.credentials.cache_scope(),
+            DecisionCache::key(route, &body)
+        ))
+    }
+
+    /// The decision to return for a request failed with `error`, if the
+    /// client fails open and the gateway was unavailable
+    fn fail_open(&self, route: &str, error: &Error) -> Option<Decision> {
+        if self.inner.failure_mode != FailureMode::Open || !error.unavailable() {
+            return None;
+        }
+        warn!(route, error = %error, "gateway unavailable, failing open");
+        Some(Decision {
+            allow: true,
+            reason: format!("gateway unavailable: {error}"),
+            reason_code: None,
+            filtered_parameters: None,
+            obligations: None,
+            cache_ttl: 0,
+            policy_revision: FAIL_OPEN_REVISION.to_string(),
+            dry_run: false,
+        })
+    }
+
+    /// POST `body` to `route`, retrying as the policy allows
+    async fn send<R: DeserializeOwned, T: serde::Serialize + ?Sized>(
+        &self,
+        route: &str,
+        body: &T,
+    ) -> Result<R> {
+        let url = format!("{}{route}", self.inner.base_url);
+        let mut attempt = 0;
+        loop {
+            let error = match self.attempt(&url, body).await {
+                Ok(response) => return Ok(response),
+                Err(error) => error,
+            };
+            attempt += 1;
+            let Some(delay) = self.inner.retry.delay(attempt, &error) else {
+                return Err(error);
+            };
+            debug!(route, attempt, ?delay, error = %error, "retrying gateway request");
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    async fn attempt<R: DeserializeOwned, T: serde::Serialize + ?Sized>(
+        &self,
+        url: &str,
+        body: &T,
+    ) -> Result<R> {
+        let response = self
+            .inner
+            .http
+            .post(url)
+            .headers(self.headers.clone())
+            .json(body)
+            .send()
+            .await
+            .map_err(Error::Transport)?;
+        let status = response.status();
+        if status.is_success() {
+            return response.json().await.map_err(|e| {
+                if e.is_decode() {
+                    Error::UnexpectedResponse {
+                        status: status.as_u16(),
+                        detail: e.to_string(),
+                    }
+                } else {
+                    Error::Transport(e)
+                }
+            });
+        }
+        let retry_after = response
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse().ok())
+            .map(Duration::from_secs);
+        let is_problem = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("application/problem+json"));
+        let text = response.text().await.map_err(Error::Transport)?;
+        match is_problem.then(|| serde_json::from_str::<Problem>(&text)) {
+            Some(Ok(problem)) => Err(Error::Problem {
+                problem,
+                retry_after,
+            }),
+            _ => Err(Error::UnexpectedResponse {
+                status: status.as_u16(),
+                detail: text.chars().take(200).collect(),
+            }),
+        }
+    }
+}
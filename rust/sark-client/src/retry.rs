@@ -0,0 +1,62 @@
+//! Retries of failed requests
+//!
+//! Retryable failures (see [`Error::retryable`]) are sent again up to
+//! `max_retries` times, after exponential backoff with full jitter: the
+//! nth retry waits a random time up to `base_delay * 2^(n-1)`, capped at
+//! `max_delay`, so clients that failed together don't retry together. A
+//! `Retry-After` from the gateway is waited out instead, if it fits under
+//! the cap; the request is otherwise failed rather than held longer.
+
+use crate::error::Error;
+use rand::Rng;
+use std::time::Duration;
+
+/// How failed requests are retried
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt (0 to never retry)
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    /// Longest wait before any one retry
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 2,
+            base_delay: Duration::from_millis(50),
+            max_delay: Duration::from_secs(1),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// No retries
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            ..Self::default()
+        }
+    }
+
+    /// Time to wait before retry `attempt` (from 1) after `error`, or
+    /// `None` if it shouldn't be retried
+    pub(crate) fn delay(&self, attempt: u32, error: &Error) -> Option<Duration> {
+        if attempt > self.max_retries || !error.retryable() {
+            return None;
+        }
+        if let Error::Problem {
+            retry_after: Some(retry_after),
+            ..
+        } = error
+        {
+            return (*retry_after <= self.max_delay).then_some(*retry_after);
+        }
+        let ceiling = self
+            .base_delay
+            .saturating_mul(1 << (attempt - 1).min(16))
+            .min(self.max_delay);
+        Some(rand::thread_rng().gen_range(Duration::ZERO..=ceiling))
+    }
+}
@@ -0,0 +1,196 @@
+//! Request and response bodies of the gateway's decision endpoints
+//!
+//! These mirror the gateway's JSON shapes. Optional members left as `None`
+//! are not sent, so the gateway applies its own defaults.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// A tool invocation to authorize (`POST /gateway/authorize`)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AuthorizeRequest {
+    pub action: String,
+    pub server_name: String,
+    pub tool_name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parameters: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sensitivity_level: Option<String>,
+    /// Evaluate and log without caching, on the gateway or here
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub dry_run: bool,
+}
+
+impl AuthorizeRequest {
+    pub fn new(
+        action: impl Into<String>,
+        server_name: impl Into<String>,
+        tool_name: impl Into<String>,
+    ) -> Self {
+        Self {
+            action: action.into(),
+            server_name: server_name.into(),
+            tool_name: tool_name.into(),
+            ..Self::default()
+        }
+    }
+}
+
+/// A request from one agent to another (`POST /gateway/authorize-a2a`)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct A2aRequest {
+    pub source_agent: AgentIdentity,
+    pub target_agent: AgentIdentity,
+    /// Requested capability (e.g. execute, query, delegate)
+    pub capability: String,
+    /// Agents the request was delegated through, originator first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub delegation_chain: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parameters: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<Value>,
+}
+
+/// An agent as described to the A2A policy
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AgentIdentity {
+    /// Left empty for source agents when the gateway takes it from the
+    /// client's SPIFFE ID
+    #[serde(default)]
+    pub id: String,
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub agent_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trust_level: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub capabilities: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub environment: Option<String>,
+}
+
+impl AgentIdentity {
+    pub fn new(id: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            ..Self::default()
+        }
+    }
+}
+
+/// The gateway's decision
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Decision {
+    pub allow: bool,
+    pub reason: String,
+    /// Stable code for the reason, where the policy gives one, for callers
+    /// to branch on rather than the reason's text
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason_code: Option<String>,
+    #[serde(default)]
+    pub filtered_parameters: Option<Value>,
+    /// Conditions that come with the decision, for the caller to enforce
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub obligations: Option<Obligations>,
+    /// Seconds the decision may be cached for (0 if it mustn't be)
+    #[serde(default)]
+    pub cache_ttl: u32,
+    /// Revision of the policy that made the decision: `fallback` for the
+    /// gateway's own fallback, and [`FAIL_OPEN_REVISION`] for decisions
+    /// this client made without the gateway
+    ///
+    /// [`FAIL_OPEN_REVISION`]: crate::FAIL_OPEN_REVISION
+    #[serde(default)]
+    pub policy_revision: String,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub dry_run: bool,
+}
+
+/// What a decision obliges the caller to do
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Obligations {
+    /// The caller must authenticate again before going ahead
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub step_up: Option<StepUp>,
+    /// Text to mark the tool's output with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub watermark: Option<Watermark>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_level: Option<LogLevel>,
+    /// `redact`, `quota` and obligations of the policy's own
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+/// Authentication the caller must complete again first
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StepUp {
+    /// Authentication context class to reach
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acr: Option<String>,
+    /// Longest ago, in seconds, the caller may have authenticated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_age: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Watermark {
+    pub text: String,
+}
+
+/// Level the decision was logged at
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Info,
+    Warn,
+    Error,
+}
+
+/// Batch of authorizations (`POST /gateway/authorize/batch`)
+#[derive(Debug, Serialize)]
+pub(crate) struct BatchRequest<'a> {
+    pub requests: &'a [AuthorizeRequest],
+}
+
+/// Batch results, in request order
+#[derive(Debug, Deserialize)]
+pub(crate) struct BatchResponse {
+    pub results: Vec<BatchItem>,
+}
+
+/// One batch result: a decision, or the error that request hit
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum BatchItem {
+    Decision(Decision),
+    Error { error: String },
+}
+
+/// An RFC 7807 problem document, as the gateway fails requests with
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Problem {
+    /// `urn:sark:problem:<kind>`, stable across releases
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    #[serde(default)]
+    pub detail: String,
+    #[serde(default)]
+    pub request_id: Option<String>,
+    /// Whether the same request may succeed if sent again later
+    #[serde(default)]
+    pub retryable: bool,
+}
+
+impl Problem {
+    /// The last part of `type` (e.g. `rate-limited`)
+    pub fn kind(&self) -> &str {
+        self.problem_type
+            .strip_prefix("urn:sark:problem:")
+            .unwrap_or(&self.problem_type)
+    }
+}
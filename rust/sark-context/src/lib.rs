@@ -0,0 +1,104 @@
+//! Request context in policy input
+//!
+//! Policies see where a request comes from in `input.context`. The gateway
+//! and the Python API build it alike, from the same headers, so a policy
+//! gets the same input shape whichever path a request takes:
+//!
+//! - `trace_id` - the trace id of the W3C `traceparent` header
+//! - `tenant` - the caller's tenant, once authentication settled it
+//! - `session_id` - the `X-Session-ID` header
+//! - `client_ip` - the client's address
+//! - `user_agent` - the `User-Agent` header
+//!
+//! Keys the context has replace the caller's own in `input.context`; any
+//! others the caller sent are kept.
+//!
+//! With the `python` feature it is exposed to the Python API as
+//! `RequestContext`, built from a FastAPI request.
+
+#[cfg(feature = "python")]
+pub mod python;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::net::IpAddr;
+
+/// Header naming the caller's trace
+pub const TRACEPARENT: &str = "traceparent";
+
+/// Header naming the caller's session
+pub const SESSION_ID: &str = "x-session-id";
+
+pub const USER_AGENT: &str = "user-agent";
+
+/// Where a request comes from, for `input.context`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RequestContext {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_ip: Option<IpAddr>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+}
+
+impl RequestContext {
+    /// The context of a request from `client_ip` with the headers `header`
+    /// looks up (by lowercase name); its tenant is left to
+    /// [`with_tenant`](Self::with_tenant)
+    pub fn from_headers(
+        header: impl Fn(&str) -> Option<String>,
+        client_ip: Option<IpAddr>,
+    ) -> Self {
+        let present = |name: &str| header(name).filter(|value| !value.is_empty());
+        Self {
+            trace_id: present(TRACEPARENT).as_deref().and_then(trace_id),
+            tenant: None,
+            session_id: present(SESSION_ID),
+            client_ip,
+            user_agent: present(USER_AGENT),
+        }
+    }
+
+    pub fn with_tenant(mut self, tenant: Option<String>) -> Self {
+        self.tenant = tenant;
+        self
+    }
+
+    /// Write the context into `input.context`, creating it if missing; a
+    /// `context` that isn't an object is left as the caller sent it
+    pub fn apply(&self, input: &mut Value) {
+        let Value::Object(input) = input else {
+            return;
+        };
+        let context = input
+            .entry("context")
+            .or_insert_with(|| Value::Object(Default::default()));
+        if context.is_null() {
+            *context = Value::Object(Default::default());
+        }
+        let (Value::Object(context), Ok(Value::Object(fields))) =
+            (context, serde_json::to_value(self))
+        else {
+            return;
+        };
+        context.extend(fields);
+    }
+}
+
+/// The trace id of a `traceparent` header (`00-<trace id>-<parent id>-<flags>`),
+/// unless it is malformed or all zeros
+pub fn trace_id(traceparent: &str) -> Option<String> {
+    let mut parts = traceparent.trim().split('-');
+    let (_version, trace_id) = (parts.next()?, parts.next()?);
+    let valid = trace_id.len() == 32
+        && trace_id
+            .bytes()
+            .all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+        && trace_id.bytes().any(|b| b != b'0');
+    valid.then(|| trace_id.to_string())
+}
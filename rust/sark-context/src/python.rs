@@ -0,0 +1,140 @@
+//! Python bindings
+//!
+//! `RequestContext` builds the context the gateway would for a request to
+//! the Python API, so both paths give policies the same input:
+//!
+//! ```python
+//! from sark.sark_rust import RequestContext
+//!
+//! context = RequestContext.from_request(request, tenant=user.tenant)
+//! decision = engine.evaluate("data.sark.gateway", context.to_input(policy_input))
+//! ```
+//!
+//! `from_request` reads a FastAPI (Starlette) request's `headers` and
+//! `client`; any object with those does.
+
+use crate::RequestContext;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pythonize::{depythonize, pythonize};
+use serde_json::Value;
+use std::net::IpAddr;
+
+/// Where a request comes from, as `input.context` gets it
+#[pyclass(module = "sark_rust", name = "RequestContext", frozen, eq)]
+#[derive(PartialEq)]
+pub struct PyRequestContext {
+    context: RequestContext,
+}
+
+#[pymethods]
+impl PyRequestContext {
+    #[new]
+    #[pyo3(signature = (*, trace_id = None, tenant = None, session_id = None, client_ip = None, user_agent = None))]
+    fn new(
+        trace_id: Option<String>,
+        tenant: Option<String>,
+        session_id: Option<String>,
+        client_ip: Option<&str>,
+        user_agent: Option<String>,
+    ) -> PyResult<Self> {
+        Ok(Self {
+            context: RequestContext {
+                trace_id,
+                tenant,
+                session_id,
+                client_ip: client_ip.map(parse_ip).transpose()?,
+                user_agent,
+            },
+        })
+    }
+
+    /// The context of `request`, a FastAPI request, for `tenant`; a
+    /// `session_id` given replaces the request's `X-Session-ID`
+    #[staticmethod]
+    #[pyo3(signature = (request, *, tenant = None, session_id = None))]
+    fn from_request(
+        request: &Bound<'_, PyAny>,
+        tenant: Option<String>,
+        session_id: Option<String>,
+    ) -> PyResult<Self> {
+        let headers = request.getattr("headers")?;
+        let client = request.getattr("client")?;
+        let client_ip = if client.is_none() {
+            None
+        } else {
+            // Test clients name themselves ("testclient"), not an address
+            client
+                .getattr("host")?
+                .extract::<Option<String>>()?
+                .and_then(|host| host.parse().ok())
+        };
+        let header = |name: &str| {
+            headers
+                .call_method1("get", (name,))
+                .and_then(|value| value.extract::<Option<String>>())
+                .ok()
+                .flatten()
+        };
+        let mut context = RequestContext::from_headers(header, client_ip).with_tenant(tenant);
+        if session_id.is_some() {
+            context.session_id = session_id;
+        }
+        Ok(Self { context })
+    }
+
+    #[getter]
+    fn trace_id(&self) -> Option<&str> {
+        self.context.trace_id.as_deref()
+    }
+
+    #[getter]
+    fn tenant(&self) -> Option<&str> {
+        self.context.tenant.as_deref()
+    }
+
+    #[getter]
+    fn session_id(&self) -> Option<&str> {
+        self.context.session_id.as_deref()
+    }
+
+    #[getter]
+    fn client_ip(&self) -> Option<String> {
+        self.context.client_ip.map(|ip| ip.to_string())
+    }
+
+    #[getter]
+    fn user_agent(&self) -> Option<&str> {
+        self.context.user_agent.as_deref()
+    }
+
+    /// A copy of `input`, a policy input dict, with the context in its
+    /// `context`
+    fn to_input(&self, py: Python<'_>, input: &Bound<'_, PyAny>) -> PyResult<PyObject> {
+        let mut input: Value = depythonize(input)
+            .map_err(|e| PyValueError::new_err(format!("Invalid policy input: {}", e)))?;
+        self.context.apply(&mut input);
+        Ok(pythonize(py, &input)?.unbind())
+    }
+
+    /// The context's keys that are set, as `input.context` gets them
+    fn to_dict(&self, py: Python<'_>) -> PyResult<PyObject> {
+        Ok(pythonize(py, &self.context)?.unbind())
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "RequestContext(trace_id={:?}, tenant={:?}, session_id={:?}, client_ip={:?}, user_agent={:?})",
+            self.context.trace_id.as_deref().unwrap_or_default(),
+            self.context.tenant.as_deref().unwrap_or_default(),
+            self.context.session_id.as_deref().unwrap_or_default(),
+            self.client_ip().unwrap_or_default(),
+            self.context.user_agent.as_deref().unwrap_or_default(),
+        )
+    }
+}
+
+fn parse_ip(ip: &str) -> PyResult<IpAddr> {
+    ip.parse()
+        .map_err(|e| PyValueError::new_err(format!("Invalid client_ip {:?}: {}", ip, e)))
+}
@@ -0,0 +1,80 @@
+//! Compiles the gRPC APIs and the SPIFFE Workload API client (`proto/`)
+//! with protox, so building needs no system `protoc`, and lists the policy
+//! files to compile into the binary (the directory named by
+//! `SARK_GATEWAY_EMBEDDED_POLICIES`, relative to this crate, if set), and
+//! records build metadata for `/health`
+
+use std::path::{Path, PathBuf};
+
+/// Names the directory of policies to embed
+const EMBEDDED_POLICIES: &str = "SARK_GATEWAY_EMBEDDED_POLICIES";
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    sark_build::emit();
+
+    let protos = [
+        "proto/sark/gateway/v1/authorization.proto",
+        "proto/envoy/service/auth/v3/external_auth.proto",
+        "proto/grpc/health/v1/health.proto",
+    ];
+    println!("cargo:rerun-if-changed=proto");
+
+    let descriptors = protox::compile(protos, ["proto"])?;
+    tonic_build::configure()
+        .build_client(false)
+        .compile_fds(descriptors)?;
+
+    // Called, not served
+    let descriptors = protox::compile(["proto/spiffe/workload/workload.proto"], ["proto"])?;
+    tonic_build::configure()
+        .build_server(false)
+        .compile_fds(descriptors)?;
+
+    embed_policies()
+}
+
+/// Write `embedded_policies.rs`: a `(path, contents)` slice of the `.rego`,
+/// `.rego.tmpl`, `.cel.toml` and `data.json` files to embed, empty without
+/// any
+fn embed_policies() -> Result<(), Box<dyn std::error::Error>> {
+    println!("cargo:rerun-if-env-changed={}", EMBEDDED_POLICIES);
+    let mut entries = String::new();
+    if let Some(dir) = std::env::var_os(EMBEDDED_POLICIES) {
+        let dir = Path::new(&std::env::var("CARGO_MANIFEST_DIR")?).join(dir);
+        println!("cargo:rerun-if-changed={}", dir.display());
+        let mut paths = Vec::new();
+        collect_files(&dir, &mut paths)
+            .map_err(|e| format!("{} {}: {}", EMBEDDED_POLICIES, dir.display(), e))?;
+        paths.sort();
+        for path in paths {
+            let name = path
+                .strip_prefix(&dir)?
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            entries.push_str(&format!("    ({:?}, include_bytes!({:?})),\n", name, path));
+        }
+    }
+
+    let out = PathBuf::from(std::env::var("OUT_DIR")?).join("embedded_policies.rs");
+    std::fs::write(out, format!("&[\n{}]\n", entries))?;
+    Ok(())
+}
+
+/// The files `PolicySet::from_dir` reads, under `dir`
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else if path.extension().is_some_and(|ext| ext == "rego")
+            || path.to_string_lossy().ends_with(".rego.tmpl")
+            || path.to_string_lossy().ends_with(".cel.toml")
+            || path.file_name().is_some_and(|name| name == "data.json")
+        {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,21 @@
+# Baseline agent-to-agent policy
+#
+# Compiled into the binary for deployments without a policy directory or
+# bundle: deny every agent call except for callers with an admin role.
+
+package a2a.gateway
+
+import rego.v1
+
+default allow := false
+
+default reason := "Denied by the baseline policy"
+
+allow if admin
+
+reason := "Allowed by the baseline policy: admin" if admin
+
+admin if {
+    some role in input.user.roles
+    role in data.baseline.admin_roles
+}
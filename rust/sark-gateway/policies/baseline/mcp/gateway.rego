@@ -0,0 +1,39 @@
+# Baseline MCP Gateway policy
+#
+# Compiled into the binary for deployments without a policy directory or
+# bundle: deny everything except to admins and the tool exceptions listed
+# in data.json.
+
+package mcp.gateway
+
+import rego.v1
+
+default allow := false
+
+default reason := "Denied by the baseline policy"
+
+allow if admin
+
+allow if exception
+
+reason := "Allowed by the baseline policy: admin" if admin
+
+reason := "Allowed by the baseline policy: exception" if {
+    not admin
+    exception
+}
+
+admin if {
+    some role in input.user.roles
+    role in data.baseline.admin_roles
+}
+
+# An exception names a server and tool ("*" for any) and the roles
+# allowed them
+exception if {
+    some e in data.baseline.exceptions
+    e.server in {"*", input.resource.server}
+    e.tool in {"*", input.resource.tool}
+    some role in input.user.roles
+    role in e.roles
+}
@@ -0,0 +1,379 @@
+//! Admin API
+//!
+//! Operational endpoints, served on their own listener (`--admin-listen`)
+//! so they never share the public hot-path port, and guarded by
+//! `Authorization: Bearer <token>` (`--admin-token`). The listener speaks
+//! plain HTTP; bind it to loopback or a private interface.
+//!
+//! - `GET /admin/policies` - the active policy revision and its modules
+//! - `POST /admin/policies/reload` - load the configured policy directory
+//!   or bundle again
+//! - `PUT /admin/data/{path}` - replace the data document at `path`
+//! - `DELETE /admin/data/{path}` - remove the data document at `path`
+//! - `PATCH /admin/data` - apply a JSON Patch to the whole data document
+//! - `POST /admin/policies/profile` - time each rule of the active policies
+//!   against given or recently decided inputs
+//! - `GET /admin/policies/revisions` - active and previous policy revisions
+//! - `POST /admin/policies/rollback` - re-activate the previous revision
+//! - `POST /admin/policies/revisions/{revision}/activate` - re-activate a
+//!   stored revision
+//! - `PUT /admin/policies/canary` - change the share of callers the canary
+//!   revision decides for, from `{"weight": 25}`
+//! - `POST /admin/policies/canary/promote` - make the canary the active
+//!   policy
+//! - `DELETE /admin/policies/canary` - stop rolling the canary out
+//! - `POST /admin/shadow/promote` - make the shadow candidate the active
+//!   policy
+//! - `DELETE /admin/shadow` - discard the shadow candidate
+//! - `POST /admin/cache/flush` - drop every cached decision
+//! - `POST /admin/cache/warm` - cache decisions made elsewhere, given with
+//!   their inputs
+//! - `GET /admin/state/export` - stream the policy revision, data document
+//!   and cached decisions, for a new replica to import
+//! - `POST /admin/state/import` - take a peer's exported state
+//! - `GET /admin/config` - the config in effect, without the admin token
+//! - `POST /admin/config/reload` - re-read the config file and apply its
+//!   reloadable settings, as SIGHUP does
+//! - `POST /admin/replay` - evaluate a logged decision or an input again
+//!   against the active policy
+//! - `GET /admin/events` - live decisions as server-sent events, filtered
+//!   by caller, server or verdict
+//! - `GET /admin/tenants` - each tenant's active policy revision and modules
+//! - `POST /admin/tenants/{tenant}/policies/reload` - load a tenant's policy
+//!   directory again
+//! - `GET /admin/overrides` - the decision overrides in force
+//! - `PUT /admin/overrides/{server}/{tool}` - override decisions of a tool
+//!   (`*` for every tool of the server)
+//! - `DELETE /admin/overrides/{server}/{tool}` - lift an override
+//!
+//! Data updates recompile the active policy set as a new revision. Policy
+//! changes and cache flushes drop cached decisions and apply to this replica only; data
+//! updates are replaced when a new bundle revision activates.
+
+use crate::config::GatewayConfig;
+use crate::policy::{ActivePolicy, PolicySet, PolicyStore, RevisionInfo};
+use crate::problem::{JsonBody, Problem};
+use crate::reload::ReloadReport;
+use crate::AppState;
+use anyhow::bail;
+use axum::{
+    extract::{Path, Request, State},
+    http::{header::AUTHORIZATION, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{delete, get, patch, post, put},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// The active policy, as listed by `GET /admin/policies`
+#[derive(Debug, Serialize)]
+struct PolicyInfo {
+    #[serde(flatten)]
+    revision: RevisionInfo,
+    /// Module names, after their path without `.rego`
+    modules: Vec<String>,
+}
+
+/// Admin routes, protected by `token`
+pub fn router(token: &str) -> Router<AppState> {
+    let token: Arc<[u8; 32]> = Arc::new(Sha256::digest(token.as_bytes()).into());
+
+    Router::new()
+        .route("/admin/data", patch(patch_data))
+        .route("/admin/data/*path", put(put_data).delete(delete_data))
+        .route("/admin/policies", get(active_policy))
+        .route("/admin/policies/reload", post(reload_policies))
+        .route("/admin/policies/revisions", get(list_revisions))
+        .route(crate::profile::ROUTE, post(crate::profile::profile))
+        .route("/admin/policies/rollback", post(rollback))
+        .route(
+            "/admin/policies/revisions/:revision/activate",
+            post(activate_revision),
+        )
+        .route(
+            "/admin/policies/canary",
+            put(weigh_canary).delete(discard_canary),
+        )
+        .route("/admin/policies/canary/promote", post(promote_canary))
+        .route("/admin/shadow", delete(discard_shadow))
+        .route("/admin/shadow/promote", post(promote_shadow))
+        .route("/admin/cache/flush", post(flush_cache))
+        .route("/admin/cache/warm", post(crate::warm::warm_cache))
+        .route("/admin/state/export", get(crate::snapshot::export))
+        .route("/admin/state/import", post(crate::snapshot::import))
+        .route("/admin/config", get(show_config))
+        .route("/admin/config/reload", post(reload_config))
+        .route(crate::replay::ROUTE, post(crate::replay::replay))
+        .route(crate::events::ROUTE, get(crate::events::subscribe))
+        .route("/admin/tenants", get(list_tenants))
+        .route(
+            "/admin/tenants/:tenant/policies/reload",
+            post(reload_tenant),
+        )
+        .route("/admin/overrides", get(crate::overrides::list))
+        .route(
+            "/admin/overrides/:server/:tool",
+            put(crate::overrides::set).delete(crate::overrides::lift),
+        )
+        .route_layer(middleware::from_fn_with_state(token, require_token))
+}
+
+async fn require_token(
+    State(token): State<Arc<[u8; 32]>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let authorized = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .is_some_and(|presented| token_matches(&token, presented));
+
+    if !authorized {
+        warn!(path = %request.uri().path(), "Rejected admin request");
+        return Problem::Unauthenticated("Invalid admin token".to_string()).into_response();
+    }
+    next.run(request).await
+}
+
+/// Whether `presented` is the token whose SHA-256 digest is `token`,
+/// compared in constant time so response times don't tell an attacker how
+/// much of a guess was right
+fn token_matches(token: &[u8; 32], presented: &str) -> bool {
+    let presented: [u8; 32] = Sha256::digest(presented.as_bytes()).into();
+    let diff = token
+        .iter()
+        .zip(presented)
+        .fold(0u8, |diff, (a, b)| diff | (a ^ b));
+    std::hint::black_box(diff) == 0
+}
+
+async fn put_data(
+    State(state): State<AppState>,
+    Path(path): Path<String>,
+    JsonBody(value): JsonBody<serde_json::Value>,
+) -> Result<StatusCode, Problem> {
+    update_data(&state, |set| set.set_data(&path, value)).await?;
+    info!(path = %path, "Data document replaced");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn delete_data(
+    State(state): State<AppState>,
+    Path(path): Path<String>,
+) -> Result<StatusCode, Problem> {
+    update_data(&state, |set| {
+        if !set.remove_data(&path) {
+            bail!("No data document at {}", path);
+        }
+        Ok(())
+    })
+    .await?;
+    info!(path = %path, "Data document removed");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn patch_data(
+    State(state): State<AppState>,
+    JsonBody(patch): JsonBody<json_patch::Patch>,
+) -> Result<StatusCode, Problem> {
+    let operations = patch.0.len();
+    update_data(&state, |set| set.patch_data(&patch)).await?;
+    info!(operations = operations, "Data document patched");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn active_policy(State(state): State<AppState>) -> Json<PolicyInfo> {
+    Json(policy_info(&*state.policy.lock().await))
+}
+
+fn policy_info(policy: &PolicyStore) -> PolicyInfo {
+    let modules = policy
+        .active()
+        .set
+        .modules()
+        .map(|(name, _)| name.to_string())
+        .collect();
+    let mut revisions = policy.revisions();
+    PolicyInfo {
+        revision: revisions.swap_remove(0),
+        modules,
+    }
+}
+
+async fn reload_policies(State(state): State<AppState>) -> Result<Json<RevisionInfo>, Problem> {
+    let result = state.reloader.reload_policies(&state).await;
+    result.map_err(|e| {
+        warn!(error = %format!("{:#}", e), "Policy reload failed; keeping current policy");
+        Problem::InvalidPolicy(format!("{:#}", e))
+    })?;
+
+    let revision = active_revision(&state).await;
+    info!(revision = %revision.revision, "Reloaded policies");
+    Ok(Json(revision))
+}
+
+async fn list_revisions(State(state): State<AppState>) -> Json<Vec<RevisionInfo>> {
+    Json(state.policy.lock().await.revisions())
+}
+
+async fn rollback(State(state): State<AppState>) -> Result<Json<RevisionInfo>, Problem> {
+    let result = state.policy.lock().await.rollback();
+    let revision = result.map_err(|e| Problem::Conflict(format!("{:#}", e)))?;
+
+    state.clear_decisions().await;
+    warn!(revision = %revision, "Rolled back policy");
+    Ok(Json(active_revision(&state).await))
+}
+
+async fn activate_revision(
+    State(state): State<AppState>,
+    Path(revision): Path<String>,
+) -> Result<Json<RevisionInfo>, Problem> {
+    let result = state.policy.lock().await.activate_revision(&revision);
+    result.map_err(|e| Problem::NotFound(format!("{:#}", e)))?;
+
+    state.clear_decisions().await;
+    warn!(revision = %revision, "Re-activated policy revision");
+    Ok(Json(active_revision(&state).await))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CanaryWeight {
+    weight: u8,
+}
+
+async fn weigh_canary(
+    State(state): State<AppState>,
+    JsonBody(request): JsonBody<CanaryWeight>,
+) -> Result<Json<RevisionInfo>, Problem> {
+    if request.weight > 100 {
+        return Err(Problem::InvalidRequest(
+            "A canary weight must be from 0 to 100".to_string(),
+        ));
+    }
+    let mut policy = state.policy.lock().await;
+    policy
+        .weigh_canary(request.weight)
+        .map_err(|e| Problem::NotFound(format!("{:#}", e)))?;
+    let revisions = policy.revisions();
+    drop(policy);
+
+    info!(weight = request.weight, "Canary policy weight changed");
+    let canary = revisions.into_iter().find(|r| r.canary_weight.is_some());
+    Ok(Json(canary.expect("canary was just weighed")))
+}
+
+async fn promote_canary(State(state): State<AppState>) -> Result<Json<RevisionInfo>, Problem> {
+    let result = state.policy.lock().await.promote_canary();
+    let revision = result.map_err(|e| Problem::NotFound(format!("{:#}", e)))?;
+
+    state.clear_decisions().await;
+    state.metrics.end_rollout();
+    warn!(revision = %revision, "Promoted canary policy");
+    Ok(Json(active_revision(&state).await))
+}
+
+async fn discard_canary(State(state): State<AppState>) -> Result<StatusCode, Problem> {
+    let result = state.policy.lock().await.discard_canary();
+    let revision = result.map_err(|e| Problem::NotFound(format!("{:#}", e)))?;
+
+    state.metrics.end_rollout();
+    warn!(revision = %revision, "Discarded canary policy");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn promote_shadow(State(state): State<AppState>) -> Result<Json<RevisionInfo>, Problem> {
+    let candidate = take_shadow(&state).await?;
+    let revision = candidate.revision().to_string();
+    state.policy.lock().await.activate(candidate);
+
+    state.clear_decisions().await;
+    warn!(revision = %revision, "Promoted shadow policy");
+    Ok(Json(active_revision(&state).await))
+}
+
+async fn discard_shadow(State(state): State<AppState>) -> Result<StatusCode, Problem> {
+    let candidate = take_shadow(&state).await?;
+    info!(revision = %candidate.revision(), "Discarded shadow policy");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn flush_cache(State(state): State<AppState>) -> StatusCode {
+    state.clear_decisions().await;
+    for namespace in state.tenants.decision_caches() {
+        namespace.clear().await;
+    }
+    info!("Flushed decision cache");
+    StatusCode::NO_CONTENT
+}
+
+async fn list_tenants(State(state): State<AppState>) -> Json<BTreeMap<String, PolicyInfo>> {
+    let mut tenants = BTreeMap::new();
+    for (name, tenant) in state.tenants.iter() {
+        let policy = tenant.policy.lock().await;
+        tenants.insert(name.to_string(), policy_info(&policy));
+    }
+    Json(tenants)
+}
+
+async fn reload_tenant(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<PolicyInfo>, Problem> {
+    let tenant = state
+        .tenants
+        .get(&name)
+        .ok_or_else(|| Problem::NotFound(format!("Unknown tenant {:?}", name)))?;
+    if let Err(e) = tenant.reload().await {
+        return Err(Problem::InvalidPolicy(format!("{:#}", e)));
+    }
+    let policy = tenant.policy.lock().await;
+    info!(tenant = %name, revision = %policy.active().revision(), "Tenant policies reloaded");
+    Ok(Json(policy_info(&policy)))
+}
+
+async fn show_config(State(state): State<AppState>) -> Json<GatewayConfig> {
+    Json(state.reloader.config().await)
+}
+
+async fn reload_config(State(state): State<AppState>) -> Result<Json<ReloadReport>, Problem> {
+    let result = state.reloader.reload(&state).await;
+    result
+        .map(Json)
+        .map_err(|e| Problem::InvalidConfig(format!("{:#}", e)))
+}
+
+async fn take_shadow(state: &AppState) -> Result<ActivePolicy, Problem> {
+    let candidate = match &state.shadow {
+        Some(shadow) => shadow.take().await,
+        None => None,
+    };
+    candidate.ok_or_else(|| Problem::NotFound("No shadow policy loaded".to_string()))
+}
+
+async fn active_revision(state: &AppState) -> RevisionInfo {
+    let mut revisions = state.policy.lock().await.revisions();
+    revisions.swap_remove(0)
+}
+
+/// Recompile the active policy with updated data and drop cached decisions
+pub(crate) async fn update_data(
+    state: &AppState,
+    update: impl FnOnce(&mut PolicySet) -> anyhow::Result<()>,
+) -> Result<(), Problem> {
+    let result = state.policy.lock().await.update_data(update);
+    if let Err(e) = result {
+        return Err(Problem::InvalidPolicy(format!("{:#}", e)));
+    }
+
+    state.clear_decisions().await;
+    Ok(())
+}
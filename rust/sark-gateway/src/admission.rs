@@ -0,0 +1,116 @@
+//! Kubernetes admission webhook
+//!
+//! With `--admission-webhook` (or `[admission] enabled = true`) the gateway
+//! answers `admission.k8s.io/v1` AdmissionReview requests on
+//! `/admission/validate`, so cluster admission control runs on the same
+//! policy engine as tool authorization:
+//!
+//! ```yaml
+//! apiVersion: admissionregistration.k8s.io/v1
+//! kind: ValidatingWebhookConfiguration
+//! webhooks:
+//!   - name: sark.example.com
+//!     clientConfig:
+//!       service: { name: sark-gateway, namespace: sark, path: /admission/validate }
+//!     admissionReviewVersions: ["v1"]
+//!     sideEffects: None
+//! ```
+//!
+//! The review is evaluated against `data.kubernetes.admission` as it
+//! arrives (`input.request.operation`, `input.request.object`,
+//! `input.request.userInfo`, ...), with `input.client` added under mutual
+//! TLS. `allow` decides and a deny returns `reason` to the user; optional
+//! `warnings` (strings) are shown to the client either way.
+//!
+//! The API server doesn't present a bearer token, so the route isn't
+//! authenticated; serve it with mutual TLS or keep it off untrusted
+//! networks. Reviews aren't cached (each is unique), and an evaluation
+//! error fails the call with 500 so the webhook's `failurePolicy` applies.
+
+use crate::audit::AuditRecord;
+use crate::problem::{JsonBody, Problem};
+use crate::tls::ClientIdentity;
+use crate::AppState;
+use axum::{extract::State, Extension, Json};
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+pub const ROUTE: &str = "/admission/validate";
+
+/// Package evaluated for admission reviews unless `queries.admission` names
+/// another, read like the gateway packages (`data.kubernetes.admission.allow`
+/// decides)
+const QUERY: &str = "data.kubernetes.admission";
+
+const API_VERSION: &str = "admission.k8s.io/v1";
+
+/// Validating admission endpoint
+pub async fn review(
+    State(state): State<AppState>,
+    client: Option<Extension<ClientIdentity>>,
+    JsonBody(review): JsonBody<Value>,
+) -> Result<Json<Value>, Problem> {
+    if review["apiVersion"] != API_VERSION || review["kind"] != "AdmissionReview" {
+        return Err(Problem::InvalidRequest(format!(
+            "Expected an {} AdmissionReview",
+            API_VERSION
+        )));
+    }
+    let request = &review["request"];
+    let Some(uid) = request["uid"].as_str().map(str::to_string) else {
+        return Err(Problem::InvalidRequest(
+            "AdmissionReview has no request uid".to_string(),
+        ));
+    };
+    info!(
+        operation = %request["operation"].as_str().unwrap_or_default(),
+        kind = %request["kind"]["kind"].as_str().unwrap_or_default(),
+        namespace = %request["namespace"].as_str().unwrap_or_default(),
+        name = %request["name"].as_str().unwrap_or_default(),
+        "Admission review"
+    );
+
+    let audit = state.audited().then(|| {
+        AuditRecord::new(
+            uid.clone(),
+            ROUTE,
+            request["userInfo"]["username"]
+                .as_str()
+                .unwrap_or("unknown"),
+            json!({
+                "operation": request["operation"],
+                "kind": request["kind"],
+                "namespace": request["namespace"],
+                "name": request["name"],
+            }),
+        )
+    });
+
+    let mut input = review;
+    if let Some(Extension(client)) = client {
+        input["client"] = json!(client);
+    }
+
+    let query = state.queries.admission.as_deref().unwrap_or(QUERY);
+    let (document, _) = crate::decide_uncached(&state, query, ROUTE, &input, audit).await?;
+    let (allow, reason) = crate::verdict(&document);
+    info!(allow = allow, reason = %reason, "Admission decision");
+
+    let mut response = json!({ "uid": uid, "allowed": allow });
+    if !allow {
+        response["status"] = json!({ "code": 403, "message": reason });
+    }
+    match document.get("warnings") {
+        Some(Value::Array(warnings)) if !warnings.is_empty() => {
+            response["warnings"] = warnings.iter().filter(|w| w.is_string()).cloned().collect();
+        }
+        Some(Value::Array(_)) | None => {}
+        Some(_) => warn!("Ignoring non-array warnings from admission policy"),
+    }
+
+    Ok(Json(json!({
+        "apiVersion": API_VERSION,
+        "kind": "AdmissionReview",
+        "response": response,
+    })))
+}
@@ -0,0 +1,138 @@
+//! API key authentication
+//!
+//! Service-to-service callers without a user JWT can present an API key in
+//! `X-API-Key` instead of a bearer token. Keys are configured by the
+//! SHA-256 of the key (`printf %s "$KEY" | sha256sum`), never the key
+//! itself, inline or in a file of their own:
+//!
+//! ```toml
+//! [api_keys]
+//! file = "/etc/sark/api-keys.toml"
+//!
+//! [api_keys.keys.ci-runner]
+//! sha256 = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"
+//! scopes = ["mcp:invoke"]
+//! roles = ["service"]
+//! rate_limit = { rate = 10, burst = 20 }
+//! ```
+//!
+//! A key's caller is `input.user.id = "api-key:<name>"` with
+//! `input.user.api_key` set to the name, its scopes as
+//! `input.user.permissions` and its roles as `input.user.roles`, so
+//! policies check them as they would a token's. A key with a `rate_limit`
+//! gets that budget on each decision route in place of the route's own,
+//! and one with a `tenant` is decided under that tenant's policies.
+//!
+//! An unknown key is refused with 401 even if the request also carries a
+//! token. Keys (and the file) are re-read on config reload. Callers
+//! authenticated by key have no token for the fallback to forward, so
+//! their failed evaluations are settled by `failure_mode` alone.
+
+use crate::auth::{Authentication, UserContext};
+use crate::config::{ApiKeyConfig, ApiKeysConfig, RouteLimit};
+use crate::problem::Problem;
+use anyhow::{bail, Context, Result};
+use axum::http::HeaderMap;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Header callers present their key in
+pub const HEADER: &str = "x-api-key";
+
+/// Prefix of the user id API key callers are known by
+const USER_PREFIX: &str = "api-key:";
+
+#[derive(Debug, Clone, PartialEq)]
+struct Key {
+    name: String,
+    scopes: Vec<String>,
+    roles: Vec<String>,
+    tenant: Option<String>,
+}
+
+/// The configured keys
+#[derive(Debug, Default, PartialEq)]
+pub struct ApiKeys {
+    /// By their hex SHA-256
+    by_hash: HashMap<String, Key>,
+    /// Rate limits by key name
+    limits: HashMap<String, RouteLimit>,
+}
+
+impl ApiKeys {
+    /// The keys `config` lists, inline and in its file
+    pub fn load(config: &ApiKeysConfig) -> Result<Self> {
+        let mut keys = config.keys.clone();
+        if let Some(path) = &config.file {
+            for (name, key) in read_file(path)? {
+                if keys.insert(name.clone(), key).is_some() {
+                    bail!("API key {:?} is configured twice", name);
+                }
+            }
+        }
+
+        let mut by_hash = HashMap::new();
+        let mut limits = HashMap::new();
+        for (name, key) in keys {
+            key.validate(&name)?;
+            if let Some(limit) = key.rate_limit {
+                limits.insert(name.clone(), limit);
+            }
+            let hash = key.sha256.to_ascii_lowercase();
+            let key = Key {
+                name,
+                scopes: key.scopes,
+                roles: key.roles,
+                tenant: key.tenant,
+            };
+            if let Some(other) = by_hash.insert(hash, key) {
+                bail!("API key {:?} has the same hash as another key", other.name);
+            }
+        }
+        Ok(Self { by_hash, limits })
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_hash.len()
+    }
+
+    /// The caller identified by the key in `headers`, or `None` if it
+    /// presents none
+    pub fn verify(&self, headers: &HeaderMap) -> Result<Option<UserContext>, Problem> {
+        let Some(presented) = headers.get(HEADER) else {
+            return Ok(None);
+        };
+        let hash = hex::encode(Sha256::digest(presented.as_bytes()));
+        let key = self
+            .by_hash
+            .get(&hash)
+            .ok_or_else(|| Problem::Unauthenticated("Invalid API key".to_string()))?;
+        Ok(Some(UserContext {
+            user_id: format!("{}{}", USER_PREFIX, key.name),
+            email: String::new(),
+            roles: key.roles.clone(),
+            permissions: key.scopes.clone(),
+            token: String::new(),
+            api_key: Some(key.name.clone()),
+            tenant: key.tenant.clone(),
+            authentication: Authentication::default(),
+        }))
+    }
+
+    /// The rate limit of the key named `name`, if it has one
+    pub fn rate_limit(&self, name: &str) -> Option<RouteLimit> {
+        self.limits.get(name).copied()
+    }
+}
+
+/// The `[<name>]` tables of a key file
+fn read_file(path: &Path) -> Result<HashMap<String, ApiKeyConfig>> {
+    let config = config::Config::builder()
+        .add_source(crate::config::file(path))
+        .build()
+        .with_context(|| format!("Failed to read API key file {}", path.display()))?;
+    config
+        .try_deserialize()
+        .with_context(|| format!("Invalid API key file {}", path.display()))
+}
@@ -0,0 +1,322 @@
+//! Load testing
+//!
+//! `sark-gateway bench --target <url> --rps <n> --duration <s>
+//! --requests-file <json>` replays recorded authorization requests against
+//! a running gateway at a fixed rate, to check a deployment's sizing before
+//! it takes traffic. The file holds a JSON array (or JSON lines) of
+//! requests, sent round robin: either `/gateway/authorize` bodies, or
+//! objects naming the route and headers too:
+//!
+//! ```json
+//! [
+//!   {"action": "gateway:tool:invoke", "server_name": "db", "tool_name": "query"},
+//!   {"path": "/gateway/authorize-a2a", "headers": {"x-sark-tenant": "finance"},
+//!    "body": {"source_agent": {"id": "a"}, "target_agent": {"id": "b"}, "capability": "query"}}
+//! ]
+//! ```
+//!
+//! A decision capture file (see `capture`) can be replayed as it is: each
+//! record names its route and body, and the rest is ignored.
+//!
+//! Requests start on schedule whether or not earlier ones have finished,
+//! up to `--concurrency` in flight; past that the achieved rate drops
+//! below `--rps`, which the report shows. The report gives latency
+//! percentiles over every answered request, statuses and verdicts, and the
+//! decision cache hit rate, read from the target's `/metrics` before and
+//! after the run (so it counts other traffic to the target too). The
+//! command fails if any request got no response.
+
+use crate::OutputFormat;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+
+/// Time allowed for one request before it counts as failed
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct Options<'a> {
+    pub target: &'a str,
+    pub rps: u32,
+    pub duration: u64,
+    pub requests_file: &'a Path,
+    pub token: Option<&'a str>,
+    pub concurrency: usize,
+    pub format: OutputFormat,
+}
+
+/// A recorded request, as sent
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Recorded {
+    Full {
+        #[serde(default = "authorize_route")]
+        path: String,
+        #[serde(default)]
+        headers: HashMap<String, String>,
+        body: Value,
+    },
+    Body(Value),
+}
+
+fn authorize_route() -> String {
+    crate::Endpoint::Authorize.route().to_string()
+}
+
+/// What became of one request
+struct Outcome {
+    latency: Duration,
+    status: Option<u16>,
+    allow: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+struct Report {
+    requests: usize,
+    seconds: f64,
+    rate: f64,
+    /// Requests without a response (connection errors, timeouts)
+    failed: usize,
+    statuses: BTreeMap<u16, usize>,
+    allowed: usize,
+    denied: usize,
+    latency_ms: BTreeMap<&'static str, f64>,
+    cache_hits: Option<u64>,
+    cache_misses: Option<u64>,
+    cache_hit_rate: Option<f64>,
+}
+
+/// Run the load test `options` describes, returning whether every request
+/// was answered
+pub async fn run(options: Options<'_>) -> Result<bool> {
+    if options.rps == 0 || options.duration == 0 || options.concurrency == 0 {
+        bail!("--rps, --duration and --concurrency must be positive");
+    }
+    let target = options.target.trim_end_matches('/').to_string();
+    let requests = Arc::new(load(options.requests_file)?);
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .pool_max_idle_per_host(options.concurrency)
+        .build()
+        .context("Failed to build HTTP client")?;
+
+    let before = cache_counts(&client, &target).await;
+    let total = options.rps as u64 * options.duration;
+    let permits = Arc::new(Semaphore::new(options.concurrency));
+    let mut ticker = tokio::time::interval(Duration::from_secs(1) / options.rps);
+    let mut running = Vec::with_capacity(total as usize);
+    let started = Instant::now();
+    for i in 0..total {
+        ticker.tick().await;
+        let permit = permits.clone().acquire_owned().await?;
+        let client = client.clone();
+        let requests = requests.clone();
+        let target = target.clone();
+        let token = options.token.map(str::to_string);
+        running.push(tokio::spawn(async move {
+            let _permit = permit;
+            let request = &requests[i as usize % requests.len()];
+            send(&client, &target, token.as_deref(), request).await
+        }));
+    }
+    let mut outcomes = Vec::with_capacity(running.len());
+    for outcome in futures::future::join_all(running).await {
+        outcomes.push(outcome?);
+    }
+    let elapsed = started.elapsed();
+    let after = cache_counts(&client, &target).await;
+
+    let report = Report::new(&outcomes, elapsed, before.zip(after));
+    match options.format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+        OutputFormat::Text => report.print(),
+    }
+    Ok(report.failed == 0)
+}
+
+fn load(path: &Path) -> Result<Vec<Recorded>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let requests: Vec<Recorded> = match serde_json::from_str(&text) {
+        Ok(requests) => requests,
+        Err(_) => text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()
+            .with_context(|| format!("{} is not a JSON array or JSON lines", path.display()))?,
+    };
+    if requests.is_empty() {
+        bail!("No requests in {}", path.display());
+    }
+    Ok(requests)
+}
+
+async fn send(
+    client: &reqwest::Client,
+    target: &str,
+    token: Option<&str>,
+    request: &Recorded,
+) -> Outcome {
+    let (path, headers, body) = match request {
+        Recorded::Full {
+            path,
+            headers,
+            body,
+        } => (path.as_str(), Some(headers), body),
+        Recorded::Body(body) => (crate::Endpoint::Authorize.route(), None, body),
+    };
+    let mut builder = client.post(format!("{}{}", target, path)).json(body);
+    if let Some(token) = token {
+        builder = builder.bearer_auth(token);
+    }
+    for (name, value) in headers.into_iter().flatten() {
+        builder = builder.header(name, value);
+    }
+
+    let started = Instant::now();
+    let response = match builder.send().await {
+        Ok(response) => response,
+        Err(_) => {
+            return Outcome {
+                latency: started.elapsed(),
+                status: None,
+                allow: None,
+            }
+        }
+    };
+    let status = response.status().as_u16();
+    let body = response.json::<Value>().await;
+    Outcome {
+        latency: started.elapsed(),
+        status: Some(status),
+        allow: body.ok().and_then(|body| body["allow"].as_bool()),
+    }
+}
+
+/// Decision cache hits and misses the target has counted so far, if its
+/// metrics can be read
+async fn cache_counts(client: &reqwest::Client, target: &str) -> Option<(u64, u64)> {
+    let text = client
+        .get(format!("{}/metrics", target))
+        .send()
+        .await
+        .ok()?
+        .error_for_status()
+        .ok()?
+        .text()
+        .await
+        .ok()?;
+    let total = |metric: &str| -> u64 {
+        text.lines()
+            .filter(|line| {
+                line.strip_prefix(metric)
+                    .is_some_and(|rest| rest.starts_with(['{', ' ']))
+            })
+            .filter_map(|line| line.rsplit(' ').next()?.parse::<f64>().ok())
+            .sum::<f64>() as u64
+    };
+    Some((
+        total("sark_gateway_cache_hits_total"),
+        total("sark_gateway_cache_misses_total"),
+    ))
+}
+
+impl Report {
+    fn new(
+        outcomes: &[Outcome],
+        elapsed: Duration,
+        cache: Option<((u64, u64), (u64, u64))>,
+    ) -> Self {
+        let mut statuses = BTreeMap::new();
+        for status in outcomes.iter().filter_map(|o| o.status) {
+            *statuses.entry(status).or_default() += 1;
+        }
+
+        let mut latencies: Vec<Duration> = outcomes
+            .iter()
+            .filter(|o| o.status.is_some())
+            .map(|o| o.latency)
+            .collect();
+        latencies.sort();
+        let mut latency_ms = BTreeMap::new();
+        if let Some(max) = latencies.last() {
+            for (name, quantile) in [("p50", 0.50), ("p90", 0.90), ("p99", 0.99), ("p999", 0.999)] {
+                // Nearest rank
+                let rank = ((quantile * latencies.len() as f64).ceil() as usize).max(1);
+                latency_ms.insert(name, millis(latencies[rank - 1]));
+            }
+            latency_ms.insert("max", millis(*max));
+        }
+
+        let cache = cache.map(|((hits, misses), (hits_after, misses_after))| {
+            (
+                hits_after.saturating_sub(hits),
+                misses_after.saturating_sub(misses),
+            )
+        });
+        let seconds = elapsed.as_secs_f64();
+        Self {
+            requests: outcomes.len(),
+            seconds,
+            rate: outcomes.len() as f64 / seconds,
+            failed: outcomes.iter().filter(|o| o.status.is_none()).count(),
+            statuses,
+            allowed: outcomes.iter().filter(|o| o.allow == Some(true)).count(),
+            denied: outcomes.iter().filter(|o| o.allow == Some(false)).count(),
+            latency_ms,
+            cache_hits: cache.map(|(hits, _)| hits),
+            cache_misses: cache.map(|(_, misses)| misses),
+            cache_hit_rate: cache
+                .filter(|(hits, misses)| hits + misses > 0)
+                .map(|(hits, misses)| hits as f64 / (hits + misses) as f64),
+        }
+    }
+
+    fn print(&self) {
+        println!(
+            "Requests:  {} in {:.1}s ({:.1}/s), {} without response",
+            self.requests, self.seconds, self.rate, self.failed
+        );
+        let statuses: Vec<String> = self
+            .statuses
+            .iter()
+            .map(|(status, count)| format!("{}: {}", status, count))
+            .collect();
+        println!("Statuses:  {}", or_none(statuses.join(", ")));
+        println!(
+            "Decisions: {} allowed, {} denied",
+            self.allowed, self.denied
+        );
+        let latency: Vec<String> = ["p50", "p90", "p99", "p999", "max"]
+            .iter()
+            .filter_map(|name| Some(format!("{} {:.2}ms", name, self.latency_ms.get(name)?)))
+            .collect();
+        println!("Latency:   {}", or_none(latency.join("  ")));
+        match (self.cache_hits, self.cache_misses) {
+            (Some(hits), Some(misses)) => println!(
+                "Cache:     {} hits, {} misses ({:.1}% hit rate)",
+                hits,
+                misses,
+                self.cache_hit_rate.unwrap_or(0.0) * 100.0
+            ),
+            _ => println!("Cache:     unknown (target's /metrics unreadable)"),
+        }
+    }
+}
+
+fn or_none(text: String) -> String {
+    if text.is_empty() {
+        "none".to_string()
+    } else {
+        text
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
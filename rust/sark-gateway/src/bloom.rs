@@ -0,0 +1,182 @@
+//! Bloom filter answering "never written" without touching the store
+//!
+//! Lookups of keys no one has set (an unknown server/tool pair, a first
+//! request from a user) are the common case on a cold or miss-heavy path
+//! like `/gateway/authorize-batch`, and each one takes the store's lock to
+//! learn nothing. A `KeyFilter` remembers every key written to a namespace
+//! in a few lock-free bit sets; a key it hasn't seen is a definite miss.
+//! A key it has seen may still be missing (at `cache.bloom_fp_rate`, for
+//! `cache.max_entries` keys), in which case the store is asked as before.
+//!
+//! Bits can't be cleared one key at a time, so the filter keeps two
+//! generations: keys go into the current one, lookups check both, and once
+//! the current one has taken its share of keys it replaces the previous
+//! one, which is wiped. That only happens after the previous generation is
+//! older than the longest TTL, so no key it remembers can still be
+//! cached; until then an overfull filter only lets more lookups through.
+
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How a namespace's filter is sized
+#[derive(Debug, Clone, Copy)]
+pub struct Sizing {
+    /// Keys written per generation before false positives pass `fp_rate`
+    pub expected: usize,
+    /// False-positive rate at `expected` keys
+    pub fp_rate: f64,
+    /// Longest an entry lives in the store: `cache.max_ttl` at startup. A
+    /// reload raising it can make entries outlive their generation, which
+    /// costs hits, never a stale decision
+    pub max_ttl: Duration,
+}
+
+pub struct KeyFilter {
+    generations: [Bits; 2],
+    /// Index of the generation keys are written to
+    current: AtomicUsize,
+    /// Keys written to the current generation
+    inserted: AtomicUsize,
+    expected: usize,
+    hashes: u32,
+    max_ttl: Duration,
+    /// When the current generation started; held while rotating
+    rotated: Mutex<Instant>,
+    hasher: (RandomState, RandomState),
+}
+
+impl KeyFilter {
+    pub fn new(sizing: Sizing) -> Self {
+        let expected = sizing.expected.max(1);
+        // Optimal bits per key and hash count for the target rate
+        let ln2 = std::f64::consts::LN_2;
+        let bits = (-(expected as f64) * sizing.fp_rate.ln() / (ln2 * ln2)).ceil() as usize;
+        let bits = bits.max(64);
+        let hashes = ((bits as f64 / expected as f64) * ln2)
+            .round()
+            .clamp(1.0, 16.0) as u32;
+        Self {
+            generations: [Bits::new(bits), Bits::new(bits)],
+            current: AtomicUsize::new(0),
+            inserted: AtomicUsize::new(0),
+            expected,
+            hashes,
+            max_ttl: sizing.max_ttl,
+            rotated: Mutex::new(Instant::now()),
+            hasher: (RandomState::new(), RandomState::new()),
+        }
+    }
+
+    /// Remember that `key` was written
+    pub fn insert(&self, key: &str) {
+        let current = self.current.load(Ordering::Acquire);
+        let positions = self.positions(key);
+        self.generations[current].set(positions);
+        if self.inserted.fetch_add(1, Ordering::Relaxed) + 1 >= self.expected {
+            self.rotate();
+        }
+    }
+
+    /// Whether `key` may have been written; `false` is certain
+    pub fn may_contain(&self, key: &str) -> bool {
+        let positions = self.positions(key);
+        self.generations
+            .iter()
+            .any(|bits| bits.contains(positions.clone()))
+    }
+
+    /// Start a new generation in place of the previous one, if that one is
+    /// old enough for all its keys to have expired
+    fn rotate(&self) {
+        // Another writer is rotating; its new generation takes this key
+        // count too
+        let Ok(mut rotated) = self.rotated.try_lock() else {
+            return;
+        };
+        if rotated.elapsed() < self.max_ttl || self.inserted.load(Ordering::Relaxed) < self.expected
+        {
+            return;
+        }
+        let previous = 1 - self.current.load(Ordering::Acquire);
+        self.generations[previous].clear();
+        self.current.store(previous, Ordering::Release);
+        self.inserted.store(0, Ordering::Relaxed);
+        *rotated = Instant::now();
+    }
+
+    /// Bit positions for `key`, by double hashing
+    fn positions(&self, key: &str) -> Positions {
+        let h1 = self.hasher.0.hash_one(key);
+        // Odd, so every step moves to a new position
+        let h2 = self.hasher.1.hash_one(key) | 1;
+        Positions {
+            h1,
+            h2,
+            next: 0,
+            count: self.hashes,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Positions {
+    h1: u64,
+    h2: u64,
+    next: u32,
+    count: u32,
+}
+
+impl Iterator for Positions {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        if self.next == self.count {
+            return None;
+        }
+        let position = self
+            .h1
+            .wrapping_add(u64::from(self.next).wrapping_mul(self.h2));
+        self.next += 1;
+        Some(position)
+    }
+}
+
+/// Fixed-size bit set safe to set and test concurrently
+struct Bits {
+    words: Vec<AtomicU64>,
+}
+
+impl Bits {
+    fn new(bits: usize) -> Self {
+        Self {
+            words: (0..bits.div_ceil(64)).map(|_| AtomicU64::new(0)).collect(),
+        }
+    }
+
+    fn len(&self) -> u64 {
+        self.words.len() as u64 * 64
+    }
+
+    fn set(&self, positions: Positions) {
+        for position in positions {
+            let bit = position % self.len();
+            self.words[(bit / 64) as usize].fetch_or(1 << (bit % 64), Ordering::Relaxed);
+        }
+    }
+
+    fn contains(&self, mut positions: Positions) -> bool {
+        positions.all(|position| {
+            let bit = position % self.len();
+            self.words[(bit / 64) as usize].load(Ordering::Relaxed) & (1 << (bit % 64)) != 0
+        })
+    }
+
+    fn clear(&self) {
+        for word in &self.words {
+            word.store(0, Ordering::Relaxed);
+        }
+    }
+}
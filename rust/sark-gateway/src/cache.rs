@@ -0,0 +1,846 @@
+//! Decision cache helpers
+//!
+//! The cache itself is a `Store` (see `sark-store`), optionally backed by a
+//! shared Redis tier; this module holds the gateway-side policies layered
+//! on top of them.
+
+use crate::bloom::{KeyFilter, Sizing};
+use crate::config::CacheConfig;
+use crate::overrides::{Override, Overrides};
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use futures::StreamExt;
+use sark_store::Store;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tracing::{debug, warn};
+
+/// Periodically sweep expired entries out of the decision cache
+///
+/// Without this, expired decisions stay resident until their key is looked
+/// up again, which keeps the cache at capacity and forces LRU evictions of
+/// still-valid entries.
+pub async fn janitor(cache: Arc<Store>, every: Duration) {
+    let mut interval = tokio::time::interval(every);
+    // The first tick completes immediately; skip it so the first sweep
+    // happens one full interval after startup.
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+        let removed = cache.cleanup_expired();
+        if removed > 0 {
+            debug!(removed = removed, "Cache janitor removed expired entries");
+        }
+    }
+}
+
+/// TTLs applied to newly cached decisions; replaced on config reload
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ttls {
+    /// Allows on /gateway/authorize
+    pub allow: u64,
+    /// Allows on /gateway/authorize-a2a
+    pub a2a_allow: u64,
+    pub deny: u64,
+    /// Ceiling on every TTL, policy-set ones included
+    pub max: u64,
+    /// Age after which a cached decision is served stale and refreshed in
+    /// the background (0 disables)
+    pub soft: u64,
+    /// Jitter applied to each TTL, in percent
+    pub jitter_pct: u8,
+}
+
+impl From<&CacheConfig> for Ttls {
+    fn from(config: &CacheConfig) -> Self {
+        Self {
+            allow: config.allow_ttl,
+            a2a_allow: config.a2a_allow_ttl,
+            deny: config.deny_ttl,
+            max: config.max_ttl,
+            soft: config.soft_ttl,
+            jitter_pct: config.ttl_jitter_pct,
+        }
+    }
+}
+
+/// Digest of only the `fields` (dotted paths) of a policy input
+///
+/// Lets operators key cached decisions on the parts of the input their
+/// policy actually reads, so per-request noise (trace ids, timestamps in
+/// `context`) doesn't defeat the cache. Absent fields are left out.
+pub fn key_digest(input: &serde_json::Value, fields: &[String]) -> String {
+    let mut projected = serde_json::Value::Object(Default::default());
+
+    for field in fields {
+        let path: Vec<&str> = field.split('.').collect();
+        let Some(value) = path.iter().try_fold(input, |v, key| v.get(key)) else {
+            continue;
+        };
+
+        let mut target = &mut projected;
+        for key in &path[..path.len() - 1] {
+            target = target
+                .as_object_mut()
+                .expect("projection only holds objects along key paths")
+                .entry(key.to_string())
+                .or_insert_with(|| serde_json::Value::Object(Default::default()));
+        }
+        if let Some(map) = target.as_object_mut() {
+            map.insert(path[path.len() - 1].to_string(), value.clone());
+        }
+    }
+
+    crate::decision_log::input_digest(&projected)
+}
+
+/// A cached decision together with the time it was computed
+///
+/// The cache stores this envelope rather than the bare decision so the
+/// gateway can tell how old an entry is without asking the cache.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CachedDecision<T> {
+    /// Unix timestamp (seconds) at which the decision was evaluated
+    pub cached_at: i64,
+    pub decision: T,
+}
+
+impl<T: Clone> CachedDecision<T> {
+    pub fn new(decision: &T) -> Self {
+        Self {
+            cached_at: Utc::now().timestamp(),
+            decision: decision.clone(),
+        }
+    }
+}
+
+impl<T> CachedDecision<T> {
+    /// Whether the entry has outlived `soft_ttl` seconds (0 means never)
+    pub fn is_stale(&self, soft_ttl: u64) -> bool {
+        soft_ttl > 0 && Utc::now().timestamp() - self.cached_at >= soft_ttl as i64
+    }
+}
+
+/// A view of the shared cache scoped to one key prefix
+///
+/// Several kinds of entries (decisions, JWKS documents, policy metadata)
+/// share a single `Store` so capacity is pooled; each gets its own
+/// `Namespace` so keys can't collide and hit rates can be told apart.
+///
+/// With a Redis tier configured, L1 misses fall through to Redis and writes
+/// go to both, so decisions are shared between gateway replicas.
+///
+/// L1 keys are stored as `{name}:g{generation}:{key}`. Clearing bumps the
+/// generation instead of walking the store, so entries from before the
+/// clear become unreachable at once and age out through TTL and LRU.
+#[derive(Clone)]
+pub struct Namespace {
+    name: Arc<str>,
+    store: Arc<Store>,
+    l2: Option<RedisTier>,
+    generation: Arc<AtomicU64>,
+    stats: Arc<NamespaceCounters>,
+    /// Serializes in-process counter increments
+    counting: Arc<Mutex<()>>,
+    /// Keys set, and when they expire, where kept for snapshots
+    journal: Option<Arc<Journal>>,
+    /// Keys set in process, where kept to skip lookups of others
+    filter: Option<Arc<KeyFilter>>,
+    /// Number of times cleared, watched by precomputation
+    clears: Arc<watch::Sender<u64>>,
+}
+
+/// Keys a namespace has set, so its entries can be listed (the store
+/// can't list them); at most `capacity`, later ones going unlisted
+struct Journal {
+    capacity: usize,
+    keys: Mutex<HashMap<String, Instant>>,
+}
+
+impl Journal {
+    fn insert(&self, key: &str, ttl: u64) {
+        let mut keys = self.keys.lock().expect("journal lock poisoned");
+        if keys.len() >= self.capacity && !keys.contains_key(key) {
+            let now = Instant::now();
+            keys.retain(|_, expires| *expires > now);
+            if keys.len() >= self.capacity {
+                return;
+            }
+        }
+        keys.insert(key.to_string(), Instant::now() + Duration::from_secs(ttl));
+    }
+}
+
+#[derive(Default)]
+struct NamespaceCounters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    l2_hits: AtomicU64,
+    sets: AtomicU64,
+    filtered: AtomicU64,
+}
+
+/// Point-in-time counters for one namespace
+///
+/// Entry counts are only available for the whole store, since evictions
+/// and expirations inside the `Store` aren't visible per namespace.
+#[derive(Debug, Serialize)]
+pub struct NamespaceStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub hit_rate: f64,
+    /// Subset of `hits` served from the Redis tier
+    pub l2_hits: u64,
+    pub sets: u64,
+    /// In-process lookups skipped by the Bloom filter, the key never
+    /// having been set here
+    pub filtered: u64,
+}
+
+impl Namespace {
+    pub fn new(store: Arc<Store>, name: &str, l2: Option<RedisTier>) -> Self {
+        Self {
+            name: Arc::from(name),
+            store,
+            l2,
+            generation: Arc::new(AtomicU64::new(0)),
+            stats: Arc::new(NamespaceCounters::default()),
+            counting: Arc::new(Mutex::new(())),
+            journal: None,
+            filter: None,
+            clears: Arc::new(watch::Sender::new(0)),
+        }
+    }
+
+    /// Skip in-process lookups of keys this namespace never set (see
+    /// `bloom`)
+    pub fn filtered(mut self, sizing: Sizing) -> Self {
+        self.filter = Some(Arc::new(KeyFilter::new(sizing)));
+        self
+    }
+
+    /// Keep track of up to `capacity` keys set, for `keys`
+    pub fn journaled(mut self, capacity: usize) -> Self {
+        self.journal = Some(Arc::new(Journal {
+            capacity,
+            keys: Mutex::new(HashMap::new()),
+        }));
+        self
+    }
+
+    /// Key and remaining TTL (seconds) of each entry set in process and
+    /// not yet expired, for a journaled namespace; empty for others
+    pub fn keys(&self) -> Vec<(String, u64)> {
+        let Some(journal) = &self.journal else {
+            return Vec::new();
+        };
+        let now = Instant::now();
+        journal
+            .keys
+            .lock()
+            .expect("journal lock poisoned")
+            .iter()
+            .map(|(key, expires)| {
+                (
+                    key.clone(),
+                    expires.saturating_duration_since(now).as_secs(),
+                )
+            })
+            .filter(|(_, ttl)| *ttl > 0)
+            .collect()
+    }
+
+    /// Notified each time the namespace is cleared
+    pub fn cleared(&self) -> watch::Receiver<u64> {
+        self.clears.subscribe()
+    }
+
+    /// The in-process value under `key`, without counting a lookup
+    pub fn peek(&self, key: &str) -> Option<String> {
+        self.l1_get(&self.l1_key(key))
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The shared tier, if one is configured
+    pub fn l2(&self) -> Option<&RedisTier> {
+        self.l2.as_ref()
+    }
+
+    /// Key in the in-process store, which includes the local generation
+    fn l1_key(&self, key: &str) -> String {
+        format!(
+            "{}:g{}:{}",
+            self.name,
+            self.generation.load(Ordering::Acquire),
+            key
+        )
+    }
+
+    /// The in-process value under the scoped key, unless the filter knows
+    /// it was never set
+    fn l1_get(&self, scoped: &str) -> Option<String> {
+        match &self.filter {
+            Some(filter) if !filter.may_contain(scoped) => None,
+            _ => self.store.get(scoped),
+        }
+    }
+
+    fn l1_set(
+        &self,
+        scoped: String,
+        value: String,
+        ttl: u64,
+        cost: Option<Duration>,
+    ) -> Result<()> {
+        if let Some(filter) = &self.filter {
+            filter.insert(&scoped);
+        }
+        Ok(self.store.set_with_cost(scoped, value, Some(ttl), cost)?)
+    }
+
+    /// `l1_set` where eviction can't reach it, if the store has room to
+    /// pin more
+    fn l1_set_pinned(&self, scoped: String, value: String, ttl: u64) -> Result<()> {
+        if let Some(filter) = &self.filter {
+            filter.insert(&scoped);
+        }
+        Ok(self.store.set_pinned(scoped, value, Some(ttl))?)
+    }
+
+    /// Key in the shared tier, identical on every replica
+    fn l2_key(&self, key: &str) -> String {
+        format!("{}:{}", self.name, key)
+    }
+
+    /// `ttl`, within the namespace's own ceiling if its partition has one
+    fn capped(&self, ttl: u64) -> u64 {
+        self.store
+            .max_ttl(&self.name)
+            .map_or(ttl, |max_ttl| ttl.min(max_ttl))
+    }
+
+    pub async fn get(&self, key: &str) -> Option<String> {
+        let scoped = self.l1_key(key);
+
+        match &self.filter {
+            Some(filter) if !filter.may_contain(&scoped) => {
+                self.stats.filtered.fetch_add(1, Ordering::Relaxed);
+            }
+            _ => {
+                if let Some(value) = self.store.get(&scoped) {
+                    self.stats.hits.fetch_add(1, Ordering::Relaxed);
+                    return Some(value);
+                }
+            }
+        }
+
+        if let Some(l2) = &self.l2 {
+            if let Some((value, ttl)) = l2.get(&self.l2_key(key)).await {
+                self.stats.hits.fetch_add(1, Ordering::Relaxed);
+                self.stats.l2_hits.fetch_add(1, Ordering::Relaxed);
+                // Promote into L1 for the remainder of the entry's lifetime
+                if let Err(e) = self.l1_set(scoped, value.clone(), ttl, None) {
+                    warn!(error = %e, "Failed to promote L2 cache entry");
+                }
+                return Some(value);
+            }
+        }
+
+        self.stats.misses.fetch_add(1, Ordering::Relaxed);
+        None
+    }
+
+    pub async fn set(&self, key: &str, value: String, ttl: u64) -> Result<()> {
+        self.write(key, value, ttl, None).await
+    }
+
+    /// `set`, recording how long the value took to compute for cost-aware
+    /// eviction; the Redis tier doesn't keep it
+    pub async fn set_with_cost(
+        &self,
+        key: &str,
+        value: String,
+        ttl: u64,
+        cost: Duration,
+    ) -> Result<()> {
+        self.write(key, value, ttl, Some(cost)).await
+    }
+
+    async fn write(
+        &self,
+        key: &str,
+        value: String,
+        ttl: u64,
+        cost: Option<Duration>,
+    ) -> Result<()> {
+        self.stats.sets.fetch_add(1, Ordering::Relaxed);
+        let ttl = self.capped(ttl);
+
+        if let Some(l2) = &self.l2 {
+            l2.set(&self.l2_key(key), &value, ttl).await;
+        }
+
+        if let Some(journal) = &self.journal {
+            journal.insert(key, ttl);
+        }
+        self.l1_set(self.l1_key(key), value, ttl, cost)
+    }
+
+    /// Add one to the counter at `key`, which expires `ttl` seconds after
+    /// it is created, and return the new count
+    ///
+    /// With a Redis tier the count is shared between replicas; if Redis
+    /// fails, this replica's own count is used instead.
+    pub async fn increment(&self, key: &str, ttl: u64) -> u64 {
+        let ttl = self.capped(ttl);
+        if let Some(l2) = &self.l2 {
+            if let Some(count) = l2.increment(&self.l2_key(key), ttl).await {
+                return count;
+            }
+        }
+
+        // Stored as `<count> <deadline>` (Unix milliseconds), so later
+        // increments keep the expiry the first one set, as `SET NX EX` does.
+        // Pinned, so a burst of decisions can't evict a caller's count.
+        let _guard = self.counting.lock().expect("counter lock poisoned");
+        let scoped = self.l1_key(key);
+        let now = Utc::now().timestamp_millis();
+        let counter = self.l1_get(&scoped).and_then(|counter| {
+            let (count, deadline) = counter.split_once(' ')?;
+            Some((count.parse::<u64>().ok()?, deadline.parse::<i64>().ok()?))
+        });
+        let (count, deadline) = match counter {
+            Some((count, deadline)) if deadline > now => (count + 1, deadline),
+            _ => (1, now + ttl.max(1) as i64 * 1000),
+        };
+        let remaining = ((deadline - now) as u64).div_ceil(1000);
+        let counter = format!("{} {}", count, deadline);
+        if let Err(e) = self.l1_set_pinned(scoped.clone(), counter.clone(), remaining) {
+            debug!(error = %e, "Storing counter unpinned");
+            if let Err(e) = self.l1_set(scoped, counter, remaining, None) {
+                warn!(error = %e, "Failed to store counter");
+            }
+        }
+        count
+    }
+
+    /// Remove one entry from both tiers
+    pub async fn delete(&self, key: &str) {
+        if let Some(l2) = &self.l2 {
+            l2.delete(&self.l2_key(key)).await;
+        }
+        self.delete_local(key);
+    }
+
+    /// Remove this replica's in-process entry only
+    fn delete_local(&self, key: &str) {
+        self.store.delete(&self.l1_key(key));
+        if let Some(journal) = &self.journal {
+            journal
+                .keys
+                .lock()
+                .expect("journal lock poisoned")
+                .remove(key);
+        }
+    }
+
+    /// Drop every entry in this namespace, leaving other namespaces intact
+    pub async fn clear(&self) {
+        // Purge the shared tier first so a concurrent L1 miss can't promote
+        // a pre-clear entry back into the new generation.
+        if let Some(l2) = &self.l2 {
+            l2.delete_prefix(&self.l2_key("")).await;
+        }
+        self.clear_local();
+    }
+
+    /// Drop this namespace's in-process entries only
+    fn clear_local(&self) {
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        if let Some(journal) = &self.journal {
+            journal.keys.lock().expect("journal lock poisoned").clear();
+        }
+        self.clears.send_modify(|clears| *clears += 1);
+    }
+
+    pub fn stats(&self) -> NamespaceStats {
+        let hits = self.stats.hits.load(Ordering::Relaxed);
+        let misses = self.stats.misses.load(Ordering::Relaxed);
+        let lookups = hits + misses;
+
+        NamespaceStats {
+            hits,
+            misses,
+            hit_rate: if lookups == 0 {
+                0.0
+            } else {
+                hits as f64 / lookups as f64
+            },
+            l2_hits: self.stats.l2_hits.load(Ordering::Relaxed),
+            sets: self.stats.sets.load(Ordering::Relaxed),
+            filtered: self.stats.filtered.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Shared second cache tier in Redis
+///
+/// Redis is an optimization, never a dependency of a decision: errors are
+/// logged and treated as misses so an unavailable Redis only costs hit rate.
+#[derive(Clone)]
+pub struct RedisTier {
+    conn: redis::aio::ConnectionManager,
+}
+
+impl RedisTier {
+    /// Key prefix keeping gateway entries apart from other Redis users
+    const PREFIX: &'static str = "sark:gateway:";
+
+    pub async fn connect(url: &str) -> Result<Self> {
+        let client = redis::Client::open(url)?;
+        let conn = redis::aio::ConnectionManager::new(client).await?;
+        Ok(Self { conn })
+    }
+
+    /// Round-trip a `PING`, failing after `timeout`
+    pub async fn ping(&self, timeout: Duration) -> Result<()> {
+        let mut conn = self.conn.clone();
+        let ping = redis::cmd("PING");
+        match tokio::time::timeout(timeout, ping.query_async::<()>(&mut conn)).await {
+            Ok(result) => result.map_err(Into::into),
+            Err(_) => Err(anyhow!("No reply within {}ms", timeout.as_millis())),
+        }
+    }
+
+    /// Fetch a value together with its remaining TTL in seconds
+    async fn get(&self, key: &str) -> Option<(String, u64)> {
+        let key = format!("{}{}", Self::PREFIX, key);
+        let mut conn = self.conn.clone();
+        let result: redis::RedisResult<(Option<String>, i64)> = redis::pipe()
+            .get(&key)
+            .ttl(&key)
+            .query_async(&mut conn)
+            .await;
+
+        match result {
+            // A negative TTL means no expiry (-1) or a race with expiry (-2);
+            // either way the entry isn't safe to promote with a deadline.
+            Ok((Some(value), ttl)) if ttl > 0 => Some((value, ttl as u64)),
+            Ok(_) => None,
+            Err(e) => {
+                warn!(error = %e, "Redis cache lookup failed");
+                None
+            }
+        }
+    }
+
+    async fn delete(&self, key: &str) {
+        let key = format!("{}{}", Self::PREFIX, key);
+        let mut conn = self.conn.clone();
+        let result: redis::RedisResult<()> =
+            redis::cmd("UNLINK").arg(&key).query_async(&mut conn).await;
+
+        if let Err(e) = result {
+            warn!(error = %e, "Redis cache delete failed");
+        }
+    }
+
+    /// Remove every key starting with `prefix`, using SCAN so Redis is never
+    /// blocked on a large keyspace
+    async fn delete_prefix(&self, prefix: &str) {
+        let pattern = format!("{}{}*", Self::PREFIX, prefix);
+        let mut conn = self.conn.clone();
+        let mut cursor: u64 = 0;
+
+        loop {
+            let result: redis::RedisResult<(u64, Vec<String>)> = redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(&pattern)
+                .arg("COUNT")
+                .arg(500)
+                .query_async(&mut conn)
+                .await;
+
+            let (next, keys) = match result {
+                Ok(page) => page,
+                Err(e) => {
+                    warn!(error = %e, "Redis cache prefix scan failed");
+                    return;
+                }
+            };
+
+            if !keys.is_empty() {
+                let result: redis::RedisResult<()> =
+                    redis::cmd("UNLINK").arg(&keys).query_async(&mut conn).await;
+                if let Err(e) = result {
+                    warn!(error = %e, "Redis cache prefix delete failed");
+                }
+            }
+
+            if next == 0 {
+                return;
+            }
+            cursor = next;
+        }
+    }
+
+    /// Increment the counter at `key`, creating it with `ttl` if absent
+    async fn increment(&self, key: &str, ttl: u64) -> Option<u64> {
+        let key = format!("{}{}", Self::PREFIX, key);
+        let mut conn = self.conn.clone();
+        // INCR keeps the expiry SET gave the counter when it was created
+        let result: redis::RedisResult<(u64,)> = redis::pipe()
+            .atomic()
+            .cmd("SET")
+            .arg(&key)
+            .arg(0)
+            .arg("NX")
+            .arg("EX")
+            .arg(ttl.max(1))
+            .ignore()
+            .incr(&key, 1)
+            .query_async(&mut conn)
+            .await;
+
+        match result {
+            Ok((count,)) => Some(count),
+            Err(e) => {
+                warn!(error = %e, "Redis counter increment failed");
+                None
+            }
+        }
+    }
+
+    async fn set(&self, key: &str, value: &str, ttl: u64) {
+        let key = format!("{}{}", Self::PREFIX, key);
+        let mut conn = self.conn.clone();
+        let result: redis::RedisResult<()> = redis::cmd("SET")
+            .arg(&key)
+            .arg(value)
+            .arg("EX")
+            .arg(ttl.max(1))
+            .query_async(&mut conn)
+            .await;
+
+        if let Err(e) = result {
+            warn!(error = %e, "Redis cache write failed");
+        }
+    }
+
+    /// Take up to `wanted` tokens from the token bucket at `key`, which
+    /// holds `burst` and refills at `rate` per second, created full; with
+    /// none to take, also say how long until one is available
+    ///
+    /// The bucket is refilled and taken from atomically, by Redis's clock,
+    /// so every replica sharing it sees the same count.
+    pub async fn take_tokens(
+        &self,
+        key: &str,
+        rate: f64,
+        burst: u32,
+        wanted: u32,
+    ) -> Result<(u32, Duration)> {
+        let key = format!("{}{}", Self::PREFIX, key);
+        let mut conn = self.conn.clone();
+        let (granted, wait): (u32, String) = redis::Script::new(TAKE_TOKENS)
+            .key(&key)
+            .arg(rate)
+            .arg(burst)
+            .arg(wanted)
+            .invoke_async(&mut conn)
+            .await?;
+        let wait = wait.parse::<f64>().unwrap_or(0.0).max(0.0);
+        Ok((granted, Duration::from_secs_f64(wait)))
+    }
+
+    /// Publish `invalidation` on [`INVALIDATION_CHANNEL`]
+    pub async fn announce(&self, invalidation: &Invalidation) -> Result<()> {
+        let payload = serde_json::to_string(invalidation)?;
+        let mut conn = self.conn.clone();
+        redis::cmd("PUBLISH")
+            .arg(INVALIDATION_CHANNEL)
+            .arg(payload)
+            .query_async::<()>(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Set `field` of the hash at `key`
+    pub async fn hash_set(&self, key: &str, field: &str, value: &str) -> Result<()> {
+        let key = format!("{}{}", Self::PREFIX, key);
+        let mut conn = self.conn.clone();
+        redis::cmd("HSET")
+            .arg(&key)
+            .arg(field)
+            .arg(value)
+            .query_async::<()>(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Remove `field` from the hash at `key`
+    pub async fn hash_delete(&self, key: &str, field: &str) -> Result<()> {
+        let key = format!("{}{}", Self::PREFIX, key);
+        let mut conn = self.conn.clone();
+        redis::cmd("HDEL")
+            .arg(&key)
+            .arg(field)
+            .query_async::<()>(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Every field of the hash at `key`
+    pub async fn hash_all(&self, key: &str) -> Result<HashMap<String, String>> {
+        let key = format!("{}{}", Self::PREFIX, key);
+        let mut conn = self.conn.clone();
+        Ok(redis::cmd("HGETALL")
+            .arg(&key)
+            .query_async(&mut conn)
+            .await?)
+    }
+}
+
+/// Refill and take from a token bucket kept as a hash of `tokens` and
+/// `updated`; the wait is a string, as Lua numbers reach Redis truncated
+const TAKE_TOKENS: &str = r#"
+local rate = tonumber(ARGV[1])
+local burst = tonumber(ARGV[2])
+local wanted = tonumber(ARGV[3])
+local time = redis.call('TIME')
+local now = tonumber(time[1]) + tonumber(time[2]) / 1000000
+local state = redis.call('HMGET', KEYS[1], 'tokens', 'updated')
+local tokens = tonumber(state[1]) or burst
+local updated = tonumber(state[2]) or now
+tokens = math.min(burst, tokens + math.max(0, now - updated) * rate)
+local granted = math.min(wanted, math.floor(tokens))
+tokens = tokens - granted
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'updated', tostring(now))
+redis.call('EXPIRE', KEYS[1], math.ceil(burst / rate) + 1)
+local wait = 0
+if granted == 0 then
+    wait = (1 - tokens) / rate
+end
+return {granted, tostring(wait)}
+"#;
+
+/// Redis pub/sub channel carrying cache invalidations between replicas
+pub const INVALIDATION_CHANNEL: &str = "sark:gateway:invalidate";
+
+/// An invalidation broadcast on [`INVALIDATION_CHANNEL`]
+///
+/// Published as JSON by whichever component changed the underlying state
+/// (e.g. the Python API after a role change):
+///
+/// ```text
+/// {"op": "delete", "namespace": "auth", "key": "user123:mcp:invoke:db01"}
+/// {"op": "clear", "namespace": "auth"}
+/// {"op": "clear"}                          // every namespace
+/// ```
+///
+/// A publisher deletes the Redis tier's copies itself before publishing a
+/// delete or clear (they are `sark:gateway:<namespace>:<key>`); replicas
+/// only drop their in-process entries, so a clear scans Redis once rather
+/// than once per replica, and can't wipe entries written back after it.
+///
+/// Replicas announce runtime decision overrides (see `overrides`) on it
+/// too, as `{"op": "override", "server": ..., "tool": ..., "effect": ...}`
+/// and `{"op": "remove_override", "server": ..., "tool": ...}`.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Invalidation {
+    Delete {
+        namespace: String,
+        key: String,
+    },
+    Clear {
+        #[serde(default)]
+        namespace: Option<String>,
+    },
+    Override(Override),
+    RemoveOverride {
+        server: String,
+        tool: String,
+    },
+}
+
+/// Apply invalidations published by other nodes to the local namespaces
+///
+/// Reconnects after connection loss. Because messages sent while
+/// disconnected are lost, every (re)subscription starts by dropping the
+/// local entries of all namespaces and reading the shared overrides again.
+pub async fn invalidation_listener(
+    redis_url: String,
+    namespaces: Vec<Namespace>,
+    overrides: Arc<Overrides>,
+) {
+    loop {
+        if let Err(e) = listen_for_invalidations(&redis_url, &namespaces, &overrides).await {
+            warn!(error = %e, "Cache invalidation subscription failed");
+        }
+        tokio::time::sleep(Duration::from_secs(5)).await;
+    }
+}
+
+async fn listen_for_invalidations(
+    redis_url: &str,
+    namespaces: &[Namespace],
+    overrides: &Overrides,
+) -> Result<()> {
+    let client = redis::Client::open(redis_url)?;
+    let mut pubsub = client.get_async_pubsub().await?;
+    pubsub.subscribe(INVALIDATION_CHANNEL).await?;
+
+    for namespace in namespaces {
+        namespace.clear_local();
+    }
+    overrides.load_shared().await;
+    debug!(
+        channel = INVALIDATION_CHANNEL,
+        "Subscribed to cache invalidations"
+    );
+
+    let mut messages = pubsub.on_message();
+    while let Some(message) = messages.next().await {
+        let payload: String = message.get_payload()?;
+        let invalidation = match serde_json::from_str::<Invalidation>(&payload) {
+            Ok(invalidation) => invalidation,
+            Err(e) => {
+                warn!(error = %e, payload = %payload, "Ignoring malformed cache invalidation");
+                continue;
+            }
+        };
+
+        debug!(invalidation = ?invalidation, "Applying cache invalidation");
+        match invalidation {
+            Invalidation::Delete { namespace, key } => {
+                for ns in namespaces.iter().filter(|ns| ns.name() == namespace) {
+                    ns.delete_local(&key);
+                }
+            }
+            Invalidation::Clear { namespace } => {
+                for ns in namespaces
+                    .iter()
+                    .filter(|ns| namespace.as_deref().map_or(true, |name| ns.name() == name))
+                {
+                    ns.clear_local();
+                }
+            }
+            Invalidation::Override(rule) => overrides.apply(rule),
+            Invalidation::RemoveOverride { server, tool } => {
+                overrides.remove(&server, &tool);
+            }
+        }
+    }
+
+    Err(anyhow!("invalidation subscription closed"))
+}
@@ -0,0 +1,128 @@
+//! Cache store benchmark
+//!
+//! `sark-gateway bench-cache --threads 32` measures the in-process store
+//! (see `sark-store`) under many worker threads. Each thread looks up keys
+//! drawn uniformly from `--keys`, storing the ones it misses, for
+//! `--duration` seconds: once with the store in a single shard, so every
+//! lookup takes the same lock (as an unsharded cache does), and once split
+//! into `--shards`. The report gives lookups per second for each and the
+//! speedup, to pick `cache.shards` for a host's core count.
+
+use crate::config::Eviction;
+use crate::OutputFormat;
+use anyhow::{bail, Result};
+use rand::Rng;
+use sark_store::Store;
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// A cached decision's worth of value
+const VALUE: &str = r#"{"allow":true,"reason":"Allowed by policy","cache_ttl":300}"#;
+
+pub struct Options {
+    pub threads: usize,
+    pub shards: usize,
+    pub entries: usize,
+    pub keys: u64,
+    pub duration: u64,
+    pub eviction: Eviction,
+    pub format: OutputFormat,
+}
+
+#[derive(Debug, Serialize)]
+struct Report {
+    threads: usize,
+    entries: usize,
+    keys: u64,
+    eviction: Eviction,
+    runs: Vec<Run>,
+    /// Lookups per second sharded over unsharded
+    speedup: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct Run {
+    shards: usize,
+    lookups: u64,
+    hit_rate: f64,
+    lookups_per_sec: f64,
+}
+
+pub fn run(options: Options) -> Result<bool> {
+    if options.threads == 0 || options.shards == 0 || options.entries == 0 || options.keys == 0 {
+        bail!("--threads, --shards, --entries and --keys must be at least 1");
+    }
+    let runs = vec![measure(&options, 1), measure(&options, options.shards)];
+    let report = Report {
+        threads: options.threads,
+        entries: options.entries,
+        keys: options.keys,
+        eviction: options.eviction,
+        speedup: runs[1].lookups_per_sec / runs[0].lookups_per_sec.max(1.0),
+        runs,
+    };
+    match options.format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+        OutputFormat::Text => report.print(),
+    }
+    Ok(true)
+}
+
+/// Look keys up from every thread for the run's duration, in a store of
+/// `shards`
+fn measure(options: &Options, shards: usize) -> Run {
+    let store = Store::new(options.eviction, options.entries, 300, shards, 0);
+    let stop = AtomicBool::new(false);
+    let lookups = AtomicU64::new(0);
+    let hits = AtomicU64::new(0);
+    let started = Instant::now();
+    std::thread::scope(|scope| {
+        for _ in 0..options.threads {
+            scope.spawn(|| {
+                let mut rng = rand::thread_rng();
+                let (mut looked_up, mut hit) = (0, 0);
+                while !stop.load(Ordering::Relaxed) {
+                    let key = format!("auth:{}", rng.gen_range(0..options.keys));
+                    if store.get(&key).is_some() {
+                        hit += 1;
+                    } else {
+                        let _ = store.set(key, VALUE.to_string(), None);
+                    }
+                    looked_up += 1;
+                }
+                lookups.fetch_add(looked_up, Ordering::Relaxed);
+                hits.fetch_add(hit, Ordering::Relaxed);
+            });
+        }
+        std::thread::sleep(Duration::from_secs(options.duration));
+        stop.store(true, Ordering::Relaxed);
+    });
+    let elapsed = started.elapsed().as_secs_f64();
+    let lookups = lookups.into_inner();
+    Run {
+        shards,
+        lookups,
+        hit_rate: hits.into_inner() as f64 / lookups.max(1) as f64,
+        lookups_per_sec: lookups as f64 / elapsed,
+    }
+}
+
+impl Report {
+    fn print(&self) {
+        println!(
+            "Store:     {} entries, {:?} eviction, {} keys, {} threads",
+            self.entries, self.eviction, self.keys, self.threads
+        );
+        for run in &self.runs {
+            println!(
+                "{:>3} {}: {:.0} lookups/s ({:.1}% hit rate)",
+                run.shards,
+                if run.shards == 1 { "shard " } else { "shards" },
+                run.lookups_per_sec,
+                run.hit_rate * 100.0
+            );
+        }
+        println!("Speedup:   {:.2}x", self.speedup);
+    }
+}
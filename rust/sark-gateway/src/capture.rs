@@ -0,0 +1,254 @@
+//! Decision capture
+//!
+//! Offline tools need real traffic to run against. A `rate` fraction of
+//! decisions (picked at random, cached ones included, dry runs and failed
+//! evaluations not) is captured with what it was decided on, one JSON
+//! record per decision:
+//!
+//! ```json
+//! {"timestamp": "2024-05-01T12:00:00Z", "path": "/gateway/authorize",
+//!  "body": {"action": "gateway:tool:invoke", "server_name": "db", ...},
+//!  "input": {"user": {...}, "action": "gateway:tool:invoke", ...},
+//!  "decision": {"allow": true, "reason": "...", ...}}
+//! ```
+//!
+//! `body` is the request as its route takes it, so `bench
+//! --requests-file` replays a capture as it is (batched requests are
+//! captured one by one, as `/gateway/authorize`); `input` is the policy
+//! input built from it, for `eval --input` or `POST /admin/replay`. Each
+//! `redact` path, dotted from the record's root with `*` matching every
+//! member or item, is masked with `"[redacted]"` before the record is
+//! queued, so user details and tool parameters never reach the target.
+//!
+//! A file `target` has records appended as JSON lines. With an
+//! `http(s)://` URL, each batch is PUT as an object named
+//! `<target>/<timestamp>-<replica>-<n>.jsonl` with `headers`, which suits
+//! object stores taking a bearer token or a pre-authorized prefix; stores
+//! that need requests signed (S3) want a signing proxy in front. Batches
+//! are written once `batch_size` records wait, at least every
+//! `flush_interval` seconds, and at shutdown. As with the decision log,
+//! the hot path never waits on the target: records past a full queue are
+//! dropped.
+
+use crate::config::CaptureConfig;
+use crate::redact;
+use anyhow::{Context, Result};
+use chrono::Utc;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+/// What redacted fields are replaced with
+const REDACTED: &str = "[redacted]";
+
+/// Handle for capturing decisions
+pub struct Capture {
+    rate: f64,
+    redact: Vec<String>,
+    tx: mpsc::Sender<Value>,
+    dropped: AtomicU64,
+    /// Tells the writer to finish, and the writer to wait for
+    shutdown: Mutex<Option<(oneshot::Sender<()>, JoinHandle<()>)>>,
+}
+
+impl Capture {
+    /// Open the capture `config` describes, if it names a target, and start
+    /// its background writer
+    pub async fn open(config: &CaptureConfig) -> Result<Option<Self>> {
+        let Some(target) = &config.target else {
+            return Ok(None);
+        };
+        let mut sink = Sink::open(target, config).await?;
+        let (tx, mut rx) = mpsc::channel::<Value>(config.queue_size);
+        let batch_size = config.batch_size;
+        let flush_interval = Duration::from_secs(config.flush_interval);
+
+        let (close, mut closed) = oneshot::channel();
+        let writer = tokio::spawn(async move {
+            let mut batch = Vec::with_capacity(batch_size);
+            let mut flush = tokio::time::interval(flush_interval);
+            let mut closing = false;
+            loop {
+                let done = tokio::select! {
+                    record = rx.recv() => match record {
+                        Some(record) => {
+                            batch.push(record);
+                            if batch.len() < batch_size {
+                                continue;
+                            }
+                            false
+                        }
+                        None => true,
+                    },
+                    _ = flush.tick() => false,
+                    // Refuse new records but write queued ones
+                    _ = &mut closed, if !closing => {
+                        closing = true;
+                        rx.close();
+                        continue;
+                    }
+                };
+
+                if !batch.is_empty() {
+                    if let Err(e) = sink.write(&batch).await {
+                        warn!(
+                            error = %format!("{:#}", e),
+                            records = batch.len(),
+                            "Failed to write captured decisions"
+                        );
+                    }
+                    batch.clear();
+                }
+                if done {
+                    break;
+                }
+            }
+        });
+
+        info!(target = %target, rate = config.rate, "Decision capture enabled");
+        Ok(Some(Self {
+            rate: config.rate,
+            redact: config.redact.clone(),
+            tx,
+            dropped: AtomicU64::new(0),
+            shutdown: Mutex::new(Some((close, writer))),
+        }))
+    }
+
+    /// Whether to capture the next decision
+    pub fn sampled(&self) -> bool {
+        rand::random::<f64>() < self.rate
+    }
+
+    /// Queue the decision `path` made for `body`, whose policy input was
+    /// `input`, without waiting; dropped if the queue is full
+    pub fn record(
+        &self,
+        path: &str,
+        body: &impl Serialize,
+        input: Value,
+        decision: &impl Serialize,
+    ) {
+        let mut record = json!({
+            "timestamp": Utc::now(),
+            "path": path,
+            "body": body,
+            "input": input,
+            "decision": decision,
+        });
+        let mask = Value::from(REDACTED);
+        for path in &self.redact {
+            redact::mask(&mut record, path, &mask);
+        }
+
+        if self.tx.try_send(record).is_err() {
+            let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+            if dropped.is_power_of_two() {
+                warn!(dropped = dropped, "Capture queue full; dropping records");
+            }
+        }
+    }
+
+    /// Write out queued records and stop; records sent afterwards are lost
+    pub async fn close(&self) {
+        let shutdown = self.shutdown.lock().expect("shutdown lock poisoned").take();
+        if let Some((close, writer)) = shutdown {
+            let _ = close.send(());
+            let _ = writer.await;
+        }
+    }
+}
+
+/// Where records are written
+enum Sink {
+    /// JSON lines appended to a file
+    File(tokio::fs::File),
+    /// One JSON lines object per batch, PUT under a URL
+    Objects {
+        client: reqwest::Client,
+        prefix: String,
+        /// Tells this replica's objects from others' written the same
+        /// moment
+        replica: String,
+        written: u64,
+    },
+}
+
+impl Sink {
+    async fn open(target: &str, config: &CaptureConfig) -> Result<Self> {
+        if target.starts_with("http://") || target.starts_with("https://") {
+            let mut headers = HeaderMap::new();
+            for (header, value) in &config.headers {
+                headers.insert(
+                    HeaderName::from_bytes(header.as_bytes())?,
+                    HeaderValue::from_str(value)?,
+                );
+            }
+            let client = reqwest::Client::builder()
+                .timeout(Duration::from_secs(30))
+                .default_headers(headers)
+                .build()
+                .context("Failed to build capture HTTP client")?;
+            return Ok(Sink::Objects {
+                client,
+                prefix: target.trim_end_matches('/').to_string(),
+                replica: format!("{:08x}", rand::random::<u32>()),
+                written: 0,
+            });
+        }
+
+        let file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(target)
+            .await
+            .with_context(|| format!("Failed to open capture file {}", target))?;
+        Ok(Sink::File(file))
+    }
+
+    async fn write(&mut self, batch: &[Value]) -> Result<()> {
+        let mut lines = Vec::new();
+        for record in batch {
+            serde_json::to_writer(&mut lines, record)?;
+            lines.push(b'\n');
+        }
+
+        match self {
+            Sink::File(file) => {
+                file.write_all(&lines).await?;
+                file.flush().await?;
+            }
+            Sink::Objects {
+                client,
+                prefix,
+                replica,
+                written,
+            } => {
+                let url = format!(
+                    "{}/{}-{}-{}.jsonl",
+                    prefix,
+                    Utc::now().format("%Y%m%dT%H%M%S%.3fZ"),
+                    replica,
+                    written
+                );
+                client
+                    .put(&url)
+                    .header(CONTENT_TYPE, "application/x-ndjson")
+                    .body(lines)
+                    .send()
+                    .await
+                    .with_context(|| format!("Failed to upload {}", url))?
+                    .error_for_status()?;
+                *written += 1;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,438 @@
+//! Client addresses
+//!
+//! Behind a load balancer every connection comes from the balancer, so the
+//! caller's address has to come from what the balancer reports, and only
+//! from balancers the gateway trusts:
+//!
+//! ```toml
+//! [client_ip]
+//! trusted_proxies = ["10.0.0.0/8"]
+//! proxy_protocol = false
+//! allow = ["10.20.0.0/16", "192.0.2.7"]
+//! deny = ["10.20.6.0/24"]
+//! ```
+//!
+//! A request's client address is its connection's peer, unless the peer is
+//! one of `trusted_proxies`: then `X-Forwarded-For` is read from the right,
+//! and the first address that isn't itself a trusted proxy is the client
+//! (the leftmost, if all are). With `proxy_protocol`, every TCP connection
+//! must open with a PROXY protocol header (v1 or v2, as HAProxy and AWS
+//! NLBs send), whose source address stands in for the peer; enable it only
+//! if nothing but the proxy can reach the port.
+//!
+//! Requests from a `deny` address, or from outside `allow` when it is set,
+//! are refused with 403 on every public route but the liveness, readiness
+//! and gRPC health probes. Callers on a Unix socket have no address, and are
+//! refused only when `allow` is set. The client address is also the rate
+//! limit key of callers without a token, and policies see it as
+//! `input.context.client_ip`, replacing any the caller sent. Being part of
+//! the input, it is part of decisions' cache keys unless `cache.key_fields`
+//! leaves it out.
+//!
+//! Entries are CIDR blocks or single addresses. The lists apply again on
+//! config reload; `proxy_protocol` is read at startup.
+
+use crate::problem::Problem;
+use crate::AppState;
+use anyhow::{bail, Context, Result};
+use axum::{
+    extract::{ConnectInfo, MatchedPath, Request, State},
+    http::HeaderMap,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::net::TcpStream;
+use tracing::{debug, warn};
+
+/// Routes served whatever the caller's address, so probes need no `allow`
+/// entry
+const UNFILTERED_ROUTES: &[&str] = &["/livez", "/readyz", crate::health::GRPC_ROUTE];
+
+/// Connections that haven't sent their PROXY protocol header by now are
+/// dropped
+const HEADER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Longest PROXY protocol v1 header, CRLF included
+const MAX_V1_HEADER: usize = 107;
+
+/// Start of every PROXY protocol v2 header
+const V2_SIGNATURE: &[u8; 12] = b"\r\n\r\n\0\r\nQUIT\n";
+
+/// An address block, written as CIDR or as a single address
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Cidr(IpNet);
+
+impl Cidr {
+    fn contains(&self, ip: &IpAddr) -> bool {
+        self.0.contains(ip)
+    }
+}
+
+impl TryFrom<String> for Cidr {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, String> {
+        if let Ok(net) = text.parse::<IpNet>() {
+            return Ok(Self(net.trunc()));
+        }
+        text.parse::<IpAddr>()
+            .map(|ip| Self(IpNet::from(ip)))
+            .map_err(|_| format!("invalid address or CIDR block {:?}", text))
+    }
+}
+
+impl From<Cidr> for String {
+    fn from(cidr: Cidr) -> Self {
+        cidr.to_string()
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// A request's client address, as resolved
+#[derive(Debug, Clone, Copy)]
+pub struct ClientIp(pub IpAddr);
+
+/// Which forwarded addresses to believe, and which addresses to serve
+#[derive(Debug, Default, PartialEq)]
+pub struct ClientIps {
+    trusted_proxies: Vec<Cidr>,
+    allow: Vec<Cidr>,
+    deny: Vec<Cidr>,
+}
+
+impl ClientIps {
+    pub fn new(config: &crate::config::ClientIpConfig) -> Self {
+        Self {
+            trusted_proxies: config.trusted_proxies.clone(),
+            allow: config.allow.clone(),
+            deny: config.deny.clone(),
+        }
+    }
+
+    fn trusted(&self, ip: &IpAddr) -> bool {
+        self.trusted_proxies.iter().any(|cidr| cidr.contains(ip))
+    }
+
+    /// The client address of a request from `peer` with `headers`
+    fn resolve(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        let mut client = peer.to_canonical();
+        if !self.trusted(&client) {
+            return client;
+        }
+        let hops: Vec<&str> = headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .collect();
+        for hop in hops.into_iter().rev() {
+            // Whatever a malformed hop's proxy was told can't be relied on
+            let Ok(hop) = hop.trim().parse::<IpAddr>() else {
+                break;
+            };
+            client = hop.to_canonical();
+            if !self.trusted(&client) {
+                break;
+            }
+        }
+        client
+    }
+
+    fn admits(&self, client: Option<IpAddr>) -> bool {
+        let Some(ip) = client else {
+            return self.allow.is_empty();
+        };
+        !self.deny.iter().any(|cidr| cidr.contains(&ip))
+            && (self.allow.is_empty() || self.allow.iter().any(|cidr| cidr.contains(&ip)))
+    }
+}
+
+/// Middleware resolving each request's client address and refusing the
+/// addresses the lists exclude
+pub async fn filter(State(state): State<AppState>, mut request: Request, next: Next) -> Response {
+    let ips = state.client_ips();
+    let client = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(peer)| ips.resolve(peer.ip(), request.headers()));
+    let filtered = !request
+        .extensions()
+        .get::<MatchedPath>()
+        .is_some_and(|route| UNFILTERED_ROUTES.contains(&route.as_str()));
+
+    if filtered && !ips.admits(client) {
+        warn!(
+            client = %client.map_or_else(|| "unknown".to_string(), |ip| ip.to_string()),
+            path = %request.uri().path(),
+            "Refused client address"
+        );
+        return Problem::Forbidden("Client address not allowed".to_string()).into_response();
+    }
+    if let Some(ip) = client {
+        request.extensions_mut().insert(ClientIp(ip));
+    }
+    next.run(request).await
+}
+
+/// The peer of a connection from `proxy` opening with a PROXY protocol
+/// header: the source it reports, or `proxy` itself for the proxy's own
+/// connections (health checks). None, after logging why, if the header
+/// is missing, invalid or late.
+pub async fn accept_proxied(stream: &mut TcpStream, proxy: SocketAddr) -> Option<SocketAddr> {
+    match tokio::time::timeout(HEADER_TIMEOUT, read_proxy_header(stream)).await {
+        Ok(Ok(source)) => Some(source.unwrap_or(proxy)),
+        Ok(Err(e)) => {
+            debug!(peer = %proxy, error = %format!("{:#}", e), "Refused connection");
+            None
+        }
+        Err(_) => {
+            debug!(peer = %proxy, "PROXY protocol header timed out");
+            None
+        }
+    }
+}
+
+/// The source address of the PROXY protocol header `stream` opens with;
+/// none for the proxy's own connections and for sources that aren't IP
+async fn read_proxy_header<S: AsyncRead + Unpin>(stream: &mut S) -> Result<Option<SocketAddr>> {
+    let mut start = [0u8; 8];
+    stream.read_exact(&mut start).await?;
+
+    if start.starts_with(b"PROXY ") {
+        let mut line = start.to_vec();
+        while !line.ends_with(b"\r\n") {
+            if line.len() == MAX_V1_HEADER {
+                bail!("PROXY protocol header too long");
+            }
+            line.push(stream.read_u8().await?);
+        }
+        line.truncate(line.len() - 2);
+        return parse_v1(&line);
+    }
+
+    if start != V2_SIGNATURE[..8] {
+        bail!("Connection did not open with a PROXY protocol header");
+    }
+    let mut rest = [0u8; 8];
+    stream.read_exact(&mut rest).await?;
+    if rest[..4] != V2_SIGNATURE[8..] {
+        bail!("Invalid PROXY protocol v2 signature");
+    }
+    let (version_command, family) = (rest[4], rest[5]);
+    let mut addresses = vec![0; u16::from_be_bytes([rest[6], rest[7]]) as usize];
+    stream.read_exact(&mut addresses).await?;
+    if version_command >> 4 != 2 {
+        bail!("Unsupported PROXY protocol version");
+    }
+
+    let invalid = || anyhow::anyhow!("PROXY protocol v2 addresses too short");
+    match (version_command & 0x0f, family >> 4) {
+        // LOCAL
+        (0, _) => Ok(None),
+        // PROXY, over IPv4 and IPv6: source and destination addresses, then ports
+        (1, 1) => {
+            let ip: [u8; 4] = addresses.get(..4).ok_or_else(invalid)?.try_into()?;
+            let port = addresses.get(8..10).ok_or_else(invalid)?;
+            Ok(Some(SocketAddr::new(
+                Ipv4Addr::from(ip).into(),
+                u16::from_be_bytes([port[0], port[1]]),
+            )))
+        }
+        (1, 2) => {
+            let ip: [u8; 16] = addresses.get(..16).ok_or_else(invalid)?.try_into()?;
+            let port = addresses.get(32..34).ok_or_else(invalid)?;
+            Ok(Some(SocketAddr::new(
+                Ipv6Addr::from(ip).into(),
+                u16::from_be_bytes([port[0], port[1]]),
+            )))
+        }
+        (1, _) => Ok(None),
+        _ => bail!("Invalid PROXY protocol v2 command"),
+    }
+}
+
+/// Source address of a v1 header, without its CRLF
+fn parse_v1(line: &[u8]) -> Result<Option<SocketAddr>> {
+    let line = std::str::from_utf8(line).context("Invalid PROXY protocol header")?;
+    let fields: Vec<&str> = line.split(' ').collect();
+    match fields[..] {
+        ["PROXY", "UNKNOWN", ..] => Ok(None),
+        ["PROXY", "TCP4" | "TCP6", source, _, port, _] => {
+            let ip: IpAddr = source
+                .parse()
+                .with_context(|| format!("Invalid PROXY protocol header {:?}", line))?;
+            let port: u16 = port
+                .parse()
+                .with_context(|| format!("Invalid PROXY protocol header {:?}", line))?;
+            Ok(Some(SocketAddr::new(ip, port)))
+        }
+        _ => bail!("Invalid PROXY protocol header {:?}", line),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cidrs(entries: &[&str]) -> Vec<Cidr> {
+        entries
+            .iter()
+            .map(|entry| Cidr::try_from(entry.to_string()).unwrap())
+            .collect()
+    }
+
+    fn ips(trusted_proxies: &[&str], allow: &[&str], deny: &[&str]) -> ClientIps {
+        ClientIps {
+            trusted_proxies: cidrs(trusted_proxies),
+            allow: cidrs(allow),
+            deny: cidrs(deny),
+        }
+    }
+
+    fn ip(text: &str) -> IpAddr {
+        text.parse().unwrap()
+    }
+
+    fn forwarded(values: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append("x-forwarded-for", value.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn cidrs_parse_blocks_and_single_addresses() {
+        assert_eq!(cidrs(&["10.1.2.3/8"])[0].to_string(), "10.0.0.0/8");
+        assert_eq!(cidrs(&["192.0.2.7"])[0].to_string(), "192.0.2.7/32");
+        assert_eq!(cidrs(&["2001:db8::1"])[0].to_string(), "2001:db8::1/128");
+        assert!(Cidr::try_from("10.0.0.0/33".to_string()).is_err());
+        assert!(Cidr::try_from("proxy.internal".to_string()).is_err());
+    }
+
+    #[test]
+    fn untrusted_peers_forwarded_header_is_ignored() {
+        let ips = ips(&["10.0.0.0/8"], &[], &[]);
+        let headers = forwarded(&["198.51.100.1"]);
+        assert_eq!(ips.resolve(ip("203.0.113.9"), &headers), ip("203.0.113.9"));
+    }
+
+    #[test]
+    fn forwarded_hops_are_read_from_the_right() {
+        let ips = ips(&["10.0.0.0/8"], &[], &[]);
+        // The caller can prepend anything; only what the proxies added counts
+        let headers = forwarded(&["192.0.2.66, 198.51.100.1, 10.0.0.2"]);
+        assert_eq!(ips.resolve(ip("10.0.0.1"), &headers), ip("198.51.100.1"));
+        // Repeated headers are one list
+        let headers = forwarded(&["192.0.2.66", "198.51.100.1", "10.0.0.2"]);
+        assert_eq!(ips.resolve(ip("10.0.0.1"), &headers), ip("198.51.100.1"));
+    }
+
+    #[test]
+    fn all_trusted_hops_resolve_to_the_leftmost() {
+        let ips = ips(&["10.0.0.0/8"], &[], &[]);
+        let headers = forwarded(&["10.0.0.3, 10.0.0.2"]);
+        assert_eq!(ips.resolve(ip("10.0.0.1"), &headers), ip("10.0.0.3"));
+        assert_eq!(
+            ips.resolve(ip("10.0.0.1"), &HeaderMap::new()),
+            ip("10.0.0.1")
+        );
+    }
+
+    #[test]
+    fn malformed_hop_stops_the_walk() {
+        let ips = ips(&["10.0.0.0/8"], &[], &[]);
+        let headers = forwarded(&["198.51.100.1, unknown, 10.0.0.2"]);
+        assert_eq!(ips.resolve(ip("10.0.0.1"), &headers), ip("10.0.0.2"));
+    }
+
+    #[test]
+    fn mapped_ipv4_addresses_match_ipv4_blocks() {
+        let ips = ips(&["10.0.0.0/8"], &[], &[]);
+        let headers = forwarded(&["::ffff:198.51.100.1"]);
+        assert_eq!(
+            ips.resolve(ip("::ffff:10.0.0.1"), &headers),
+            ip("198.51.100.1")
+        );
+    }
+
+    #[test]
+    fn deny_overrides_allow() {
+        let ips = ips(&[], &["10.20.0.0/16", "192.0.2.7"], &["10.20.6.0/24"]);
+        assert!(ips.admits(Some(ip("10.20.5.1"))));
+        assert!(ips.admits(Some(ip("192.0.2.7"))));
+        assert!(!ips.admits(Some(ip("10.20.6.1"))));
+        assert!(!ips.admits(Some(ip("192.0.2.8"))));
+        // Unix socket callers have no address to allow
+        assert!(!ips.admits(None));
+    }
+
+    #[test]
+    fn without_allow_everything_but_deny_is_admitted() {
+        let ips = ips(&[], &[], &["10.20.6.0/24"]);
+        assert!(ips.admits(Some(ip("198.51.100.1"))));
+        assert!(!ips.admits(Some(ip("10.20.6.1"))));
+        assert!(ips.admits(None));
+    }
+
+    async fn header(mut bytes: &[u8]) -> Result<Option<SocketAddr>> {
+        read_proxy_header(&mut bytes).await
+    }
+
+    #[tokio::test]
+    async fn proxy_protocol_v1() {
+        let source = header(b"PROXY TCP4 198.51.100.1 10.0.0.1 51234 8000\r\nGET /")
+            .await
+            .unwrap();
+        assert_eq!(source, Some("198.51.100.1:51234".parse().unwrap()));
+        let source = header(b"PROXY TCP6 2001:db8::1 2001:db8::2 51234 8000\r\n")
+            .await
+            .unwrap();
+        assert_eq!(source, Some("[2001:db8::1]:51234".parse().unwrap()));
+        assert_eq!(header(b"PROXY UNKNOWN\r\n").await.unwrap(), None);
+        assert!(header(b"PROXY TCP4 nowhere 10.0.0.1 1 2\r\n")
+            .await
+            .is_err());
+        assert!(header(b"GET / HTTP/1.1\r\n").await.is_err());
+        let mut long = b"PROXY ".to_vec();
+        long.resize(200, b'1');
+        assert!(header(&long).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn proxy_protocol_v2() {
+        let v2 = |command: u8, family: u8, addresses: &[u8]| {
+            let mut bytes = V2_SIGNATURE.to_vec();
+            bytes.extend([0x20 | command, family]);
+            bytes.extend((addresses.len() as u16).to_be_bytes());
+            bytes.extend(addresses);
+            bytes
+        };
+        let ipv4 = [198, 51, 100, 1, 10, 0, 0, 1, 0xc8, 0x22, 0x1f, 0x40];
+        let source = header(&v2(1, 0x11, &ipv4)).await.unwrap();
+        assert_eq!(source, Some("198.51.100.1:51234".parse().unwrap()));
+
+        let mut ipv6 = [0u8; 36];
+        ipv6[..16].copy_from_slice(&"2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+        ipv6[32..].copy_from_slice(&51234u16.to_be_bytes());
+        let source = header(&v2(1, 0x21, &ipv6)).await.unwrap();
+        assert_eq!(source, Some("[2001:db8::1]:51234".parse().unwrap()));
+
+        // LOCAL: the proxy's own connection
+        assert_eq!(header(&v2(0, 0, &[])).await.unwrap(), None);
+        assert!(header(&v2(1, 0x11, &ipv4[..6])).await.is_err());
+        assert!(header(&v2(2, 0x11, &ipv4)).await.is_err());
+    }
+}
@@ -0,0 +1,371 @@
+//! Offline subcommands for policy authoring and CI
+//!
+//! `sark-gateway test-policies <dir>` runs rego unit tests without a
+//! separate OPA binary: every rule named `test_*` in the directory is
+//! evaluated against the compiled policy set (data documents included) and
+//! passes if it evaluates to `true`. Each module's line coverage follows:
+//! the percent of its lines the tests evaluated, and those they didn't
+//! (see `sark_policy::run_tests`).
+//!
+//! `sark-gateway check-policy <path>` compiles each `.rego` file (or a
+//! single file) and prints diagnostics as `file:line:col: error: message`,
+//! or as JSON with `--format json`, for pre-commit hooks and editors.
+//! With `--policy-cache-dir`, unchanged files aren't compiled again (see
+//! `policy_cache`).
+//!
+//! `sark-gateway eval --policy-dir <dir> --input <file> --query <query>`
+//! evaluates one query against the directory, data documents included, and
+//! prints the result. Without `--query`, the package is the one the server
+//! would evaluate the input against by the config file's `[queries]` (the
+//! gateway authorization package, unless mapped). A query naming a CEL
+//! package (`.cel.toml`) is evaluated by it, as the server would.
+//! With `--request authorize` or `--request a2a`, the input is instead a
+//! request body to that route, shaped into policy input exactly as the
+//! server does: the caller is built from the token claims in `--claims`
+//! through the config file's `[claims]` mapping, a tool call without a
+//! `sensitivity_level` is classified by its `[classify]` rules, and the
+//! request id is `eval`. `--explain` also prints the input evaluated and, for a rule,
+//! the whole document of its package.
+//!
+//! `sark-gateway audit verify <file>...` checks a chained audit log (see
+//! `audit`) and, with `--key`, its checkpoints' signatures.
+
+use crate::audit;
+use crate::auth::UserContext;
+use crate::config::QueriesConfig;
+use crate::policy::{self, PolicySet};
+use crate::policy_cache::PolicyCache;
+use crate::template::{self, Vars};
+use crate::{A2AAuthRequest, Endpoint, GatewayAuthRequest};
+use anyhow::{bail, Context, Result};
+use sark_classify::{Classifier, ClassifyConfig};
+use sark_context::RequestContext;
+use sark_jwt::ClaimMapping;
+use serde_json::{json, Value};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Run every `test_*` rule under `dir`, with templates rendered from
+/// `vars`, returning whether all passed
+pub fn test_policies(dir: &Path, vars: &Vars) -> Result<bool> {
+    let set = PolicySet::from_dir(dir)
+        .and_then(|set| set.rendered(vars))
+        .with_context(|| format!("Failed to load policies from {}", dir.display()))?;
+    let modules: Vec<(String, String)> = set
+        .modules()
+        .map(|(name, source)| (format!("{}.rego", name), source.to_string()))
+        .collect();
+    let report = sark_policy::run_tests(&modules, set.data())?;
+
+    if report.results.is_empty() {
+        println!("No test_* rules found under {}", dir.display());
+        return Ok(true);
+    }
+
+    for result in &report.results {
+        let elapsed = Duration::from_micros(result.duration_us);
+        match (&result.got, &result.error) {
+            (_, Some(e)) => println!("{}: ERROR ({})", result.name, e),
+            (Some(got), None) => println!("{}: FAIL (got {})", result.name, got),
+            (None, None) => println!("{}: PASS ({:.1?})", result.name, elapsed),
+        }
+    }
+
+    let (passed, failed) = (report.passed(), report.failed());
+    println!("{}", "-".repeat(80));
+    println!("PASS: {}/{}", passed, report.results.len());
+    if failed > 0 {
+        println!("FAIL: {}/{}", failed, report.results.len());
+    }
+    for file in &report.coverage {
+        let lines = file.covered.len() + file.not_covered.len();
+        print!(
+            "COVERAGE: {}: {:.1}% ({}/{} lines)",
+            file.file,
+            file.percent(),
+            file.covered.len(),
+            lines
+        );
+        if file.not_covered.is_empty() {
+            println!();
+        } else {
+            println!(", not covered: {}", line_ranges(&file.not_covered));
+        }
+    }
+    Ok(failed == 0)
+}
+
+/// Sorted `lines` as ranges, `3, 7-9`
+fn line_ranges(lines: &[u32]) -> String {
+    let mut ranges: Vec<(u32, u32)> = Vec::new();
+    for &line in lines {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == line => *end = line,
+            _ => ranges.push((line, line)),
+        }
+    }
+    ranges
+        .iter()
+        .map(|&(start, end)| {
+            if start == end {
+                start.to_string()
+            } else {
+                format!("{}-{}", start, end)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Check every `.rego` and `.rego.tmpl` file at `path`, rendering
+/// templates from `vars`, returning whether all compiled; with `cache_dir`,
+/// files checked before are not compiled again
+pub fn check_policy(
+    path: &Path,
+    vars: &Vars,
+    cache_dir: Option<&Path>,
+    json: bool,
+) -> Result<bool> {
+    let cache = cache_dir.map(PolicyCache::open).transpose()?;
+    let files = if path.is_dir() {
+        policy::rego_files(path)?
+    } else {
+        vec![path.to_path_buf()]
+    };
+
+    let mut diagnostics = Vec::new();
+    for file in &files {
+        let source = std::fs::read_to_string(file)
+            .with_context(|| format!("Failed to read {}", file.display()))?;
+        let name = file.display().to_string();
+        let source = if policy::is_template(file) {
+            match template::render(&source, vars) {
+                Ok(rendered) => rendered,
+                Err(e) => {
+                    diagnostics.push(policy::Diagnostic {
+                        severity: "error",
+                        message: e.message,
+                        file: name,
+                        line: Some(e.line),
+                        column: None,
+                    });
+                    continue;
+                }
+            }
+        } else {
+            source
+        };
+        diagnostics.extend(match &cache {
+            Some(cache) => cache.validate(&name, &source)?,
+            None => policy::validate_policy(&name, &source)?,
+        });
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&diagnostics)?);
+    } else {
+        for d in &diagnostics {
+            match (d.line, d.column) {
+                (Some(line), Some(column)) => {
+                    println!(
+                        "{}:{}:{}: {}: {}",
+                        d.file, line, column, d.severity, d.message
+                    )
+                }
+                _ => println!("{}: {}: {}", d.file, d.severity, d.message),
+            }
+        }
+        println!(
+            "{} file(s) checked, {} error(s)",
+            files.len(),
+            diagnostics.len()
+        );
+    }
+
+    Ok(diagnostics.is_empty())
+}
+
+/// Check the audit log chain through `files` (oldest first), and its
+/// checkpoints' signatures with `key`, returning whether it holds
+pub fn audit_verify(files: &[PathBuf], key: Option<&Path>, json: bool) -> Result<bool> {
+    let verification = audit::verify(files, key)?;
+    let ok = verification.errors.is_empty();
+    if json {
+        println!("{}", serde_json::to_string_pretty(&verification)?);
+        return Ok(ok);
+    }
+    for error in &verification.errors {
+        println!("{}", error);
+    }
+    println!(
+        "{} record(s), {} checkpoint(s), {} error(s)",
+        verification.records,
+        verification.checkpoints,
+        verification.errors.len()
+    );
+    if let Some(start) = &verification.chain_start {
+        println!("Chain picked up at {}", start);
+    }
+    match key {
+        Some(_) => println!(
+            "{} checkpoint signature(s) verified",
+            verification.signatures_verified
+        ),
+        None => println!("Checkpoint signatures not checked (no --key)"),
+    }
+    if verification.unsigned_records > 0 {
+        println!(
+            "{} record(s) after the last checkpoint, unsigned",
+            verification.unsigned_records
+        );
+    }
+    Ok(ok)
+}
+
+pub struct Eval<'a> {
+    pub policy_dir: &'a Path,
+    /// JSON file, or `-` for stdin
+    pub input: &'a Path,
+    pub query: Option<&'a str>,
+    /// Route whose request body `input` is
+    pub request: Option<Endpoint>,
+    pub claims: Option<&'a Path>,
+    /// Variables the policy directory's templates are rendered with
+    pub vars: &'a Vars,
+    pub mapping: &'a ClaimMapping,
+    pub classify: &'a ClassifyConfig,
+    pub queries: &'a QueriesConfig,
+    pub explain: bool,
+    pub json: bool,
+}
+
+/// Evaluate one query as `options` describe and print the result
+pub fn eval(options: Eval<'_>) -> Result<bool> {
+    let set = PolicySet::from_dir(options.policy_dir)
+        .and_then(|set| set.rendered(options.vars))
+        .with_context(|| {
+            format!(
+                "Failed to load policies from {}",
+                options.policy_dir.display()
+            )
+        })?;
+    let mut engine = set.compile()?;
+
+    let input = read_json(options.input)?;
+    let input = match options.request {
+        Some(endpoint) => shape(
+            endpoint,
+            input,
+            options.claims,
+            options.mapping,
+            options.classify,
+        )?,
+        None => input,
+    };
+    let query = options.query.unwrap_or_else(|| {
+        options
+            .request
+            .unwrap_or(Endpoint::Authorize)
+            .query(options.queries, &input)
+    });
+
+    let started = Instant::now();
+    let result = policy::evaluate(&mut engine, &set, query, &input)
+        .with_context(|| format!("Failed to evaluate {}", query))?;
+    let elapsed = started.elapsed();
+
+    // A rule's value alone rarely shows why; its package's other rules may
+    let is_package = |query: &str| {
+        set.modules()
+            .filter_map(|(_, source)| policy::package_of(source))
+            .chain(set.cel_packages().map(|(_, package)| package.name()))
+            .any(|package| query.strip_prefix("data.") == Some(package))
+    };
+    let package = match query.rsplit_once('.') {
+        Some((package, _)) if options.explain && is_package(package) => {
+            let document = policy::evaluate(&mut engine, &set, package, &input)
+                .with_context(|| format!("Failed to evaluate {}", package))?;
+            Some((package, document))
+        }
+        _ => None,
+    };
+
+    if options.json {
+        let mut output = json!({
+            "query": query,
+            "result": result,
+            "elapsed_us": elapsed.as_micros() as u64,
+        });
+        if options.explain {
+            output["input"] = input;
+        }
+        if let Some((package, document)) = package {
+            output["package"] = json!({"query": package, "document": document});
+        }
+        println!("{}", serde_json::to_string_pretty(&output)?);
+    } else {
+        if options.explain {
+            println!("Input:\n{}\n", serde_json::to_string_pretty(&input)?);
+        }
+        println!("{} ({:.1?}):", query, elapsed);
+        println!("{}", serde_json::to_string_pretty(&result)?);
+        if let Some((package, document)) = package {
+            println!(
+                "\n{}:\n{}",
+                package,
+                serde_json::to_string_pretty(&document)?
+            );
+        }
+    }
+    Ok(true)
+}
+
+fn read_json(path: &Path) -> Result<Value> {
+    let text = if path == Path::new("-") {
+        let mut text = String::new();
+        std::io::stdin()
+            .read_to_string(&mut text)
+            .context("Failed to read stdin")?;
+        text
+    } else {
+        std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?
+    };
+    serde_json::from_str(&text).with_context(|| format!("Invalid JSON in {}", path.display()))
+}
+
+/// The policy input the server builds for `body` on `endpoint`'s route
+fn shape(
+    endpoint: Endpoint,
+    body: Value,
+    claims: Option<&Path>,
+    mapping: &ClaimMapping,
+    classify: &ClassifyConfig,
+) -> Result<Value> {
+    let Some(claims) = claims else {
+        bail!("Shaping a request requires token claims");
+    };
+    let user = UserContext::from(mapping.identity(&read_json(claims)?)?);
+    let invalid = || format!("Input is not a {} request body", endpoint.route());
+    let mut input = match endpoint {
+        Endpoint::Authorize => {
+            let request: GatewayAuthRequest = serde_json::from_value(body).with_context(invalid)?;
+            let classifier = Classifier::new(classify).context("Invalid classify rules")?;
+            crate::gateway_input(
+                &user,
+                None,
+                &RequestContext::default(),
+                &classifier,
+                &request,
+            )
+        }
+        Endpoint::AuthorizeA2a => {
+            let request: A2AAuthRequest = serde_json::from_value(body).with_context(invalid)?;
+            // Delegation tokens need the IdP's keys, so offline inputs go without
+            crate::a2a_input(&user, None, &RequestContext::default(), &request, None)
+        }
+    };
+    input["request_id"] = json!("eval");
+    Ok(input)
+}
@@ -0,0 +1,1487 @@
+//! Gateway config file
+//!
+//! Settings are layered, later sources winning:
+//!
+//! 1. built-in defaults
+//! 2. the file at `--config` (TOML, or YAML/JSON by `.yaml`/`.yml`/`.json`
+//!    extension)
+//! 3. `SARK_GATEWAY_*` environment variables, with `__` between section
+//!    and key: `SARK_GATEWAY_LISTEN`, `SARK_GATEWAY_CACHE__DENY_TTL`
+//! 4. flags given on the command line
+//!
+//! ```toml
+//! listen = "0.0.0.0:8443"
+//!
+//! [log]
+//! level = "info"
+//! format = "json"
+//!
+//! [tls]
+//! cert = "/etc/sark/tls/tls.crt"
+//! key = "/etc/sark/tls/tls.key"
+//!
+//! [policy]
+//! dir = "/etc/sark/policies"
+//! watch = true
+//!
+//! [cache]
+//! max_entries = 50000
+//! deny_ttl = 30
+//!
+//! [jwt]
+//! jwks_url = "https://idp.example.com/.well-known/jwks.json"
+//! audience = "sark"
+//!
+//! [admin]
+//! listen = "127.0.0.1:8081"
+//! ```
+//!
+//! The default `--config` path may be absent (defaults apply); a path
+//! given explicitly must exist. Unknown keys and inconsistent settings
+//! stop startup.
+
+use crate::clientip::Cidr;
+use crate::detect;
+use crate::listen::ListenAddr;
+use crate::overrides::Effect;
+use crate::telemetry::LogFormat;
+use crate::template::Vars;
+use anyhow::{bail, Context, Result};
+use sark_classify::ClassifyConfig;
+use sark_jwt::ClaimMapping;
+pub use sark_store::Eviction;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+/// Prefix of environment variable overrides
+const ENV_PREFIX: &str = "SARK_GATEWAY";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GatewayConfig {
+    /// TCP address or `unix:<path>`
+    pub listen: ListenAddr,
+    pub unix_socket: UnixSocketConfig,
+    pub connections: ConnectionsConfig,
+    pub log: LogConfig,
+    pub tls: TlsConfig,
+    pub policy: PolicyConfig,
+    pub rollout: RolloutConfig,
+    pub cache: CacheConfig,
+    pub jwt: JwtConfig,
+    /// Where user context fields are found in caller tokens
+    pub claims: ClaimMapping,
+    pub api_keys: ApiKeysConfig,
+    pub step_up: StepUpConfig,
+    pub shutdown: ShutdownConfig,
+    pub admin: AdminConfig,
+    pub rate_limit: RateLimitConfig,
+    pub concurrency: ConcurrencyConfig,
+    pub requests: RequestsConfig,
+    pub parameters: ParametersConfig,
+    pub detection: DetectionConfig,
+    /// Sensitivity of tool calls that don't give one
+    pub classify: ClassifyConfig,
+    pub admission: AdmissionConfig,
+    pub fallback: FallbackConfig,
+    pub proxy: ProxyConfig,
+    pub tenants: TenantsConfig,
+    pub signing: SigningConfig,
+    pub response_headers: ResponseHeadersConfig,
+    pub overrides: Vec<OverrideConfig>,
+    pub spiffe: SpiffeConfig,
+    pub client_ip: ClientIpConfig,
+    pub enrich: EnrichConfig,
+    pub reasons: ReasonsConfig,
+    pub data: DataConfig,
+    pub capture: CaptureConfig,
+    pub queries: QueriesConfig,
+    pub metrics: MetricsConfig,
+}
+
+impl Default for GatewayConfig {
+    fn default() -> Self {
+        Self {
+            listen: ListenAddr::Tcp(SocketAddr::from(([0, 0, 0, 0], 8080))),
+            unix_socket: UnixSocketConfig::default(),
+            connections: ConnectionsConfig::default(),
+            log: LogConfig::default(),
+            tls: TlsConfig::default(),
+            policy: PolicyConfig::default(),
+            rollout: RolloutConfig::default(),
+            cache: CacheConfig::default(),
+            jwt: JwtConfig::default(),
+            claims: ClaimMapping::default(),
+            api_keys: ApiKeysConfig::default(),
+            step_up: StepUpConfig::default(),
+            shutdown: ShutdownConfig::default(),
+            admin: AdminConfig::default(),
+            rate_limit: RateLimitConfig::default(),
+            concurrency: ConcurrencyConfig::default(),
+            requests: RequestsConfig::default(),
+            parameters: ParametersConfig::default(),
+            detection: DetectionConfig::default(),
+            classify: ClassifyConfig::default(),
+            admission: AdmissionConfig::default(),
+            fallback: FallbackConfig::default(),
+            proxy: ProxyConfig::default(),
+            tenants: TenantsConfig::default(),
+            signing: SigningConfig::default(),
+            response_headers: ResponseHeadersConfig::default(),
+            overrides: Vec::new(),
+            spiffe: SpiffeConfig::default(),
+            client_ip: ClientIpConfig::default(),
+            enrich: EnrichConfig::default(),
+            reasons: ReasonsConfig::default(),
+            data: DataConfig::default(),
+            capture: CaptureConfig::default(),
+            queries: QueriesConfig::default(),
+            metrics: MetricsConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UnixSocketConfig {
+    /// Octal file mode of the socket (e.g. `"0660"`); the umask applies
+    /// otherwise
+    pub mode: Option<String>,
+}
+
+/// How the public listener serves the connections it accepts
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConnectionsConfig {
+    /// Serve HTTP/2 (by ALPN over TLS, by prior knowledge in cleartext) as
+    /// well as HTTP/1.1; gRPC needs it
+    pub http2: bool,
+    /// Send small responses at once rather than coalescing them (sets
+    /// `TCP_NODELAY`)
+    pub tcp_nodelay: bool,
+    /// Keep HTTP/1.1 connections open for further requests
+    pub keep_alive: bool,
+    /// Seconds an HTTP/1.1 connection may wait for, or take sending, a
+    /// request's headers before it is closed (0 disables); longer than
+    /// load balancers' own idle timeouts, so they close first
+    pub idle_timeout: u64,
+    /// Requests an HTTP/2 connection may have in flight at once
+    pub max_concurrent_streams: u32,
+    /// Seconds between pings on HTTP/2 connections, which are closed when
+    /// a ping goes unanswered for `http2_keep_alive_timeout` (0 disables)
+    pub http2_keep_alive_interval: u64,
+    pub http2_keep_alive_timeout: u64,
+}
+
+impl Default for ConnectionsConfig {
+    fn default() -> Self {
+        Self {
+            http2: true,
+            tcp_nodelay: true,
+            keep_alive: true,
+            idle_timeout: 75,
+            max_concurrent_streams: 200,
+            http2_keep_alive_interval: 0,
+            http2_keep_alive_timeout: 20,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    /// Level or `tracing` filter directive (`info`, `sark_gateway=debug`)
+    pub level: String,
+    pub format: LogFormat,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".to_string(),
+            format: LogFormat::Text,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+    /// CA bundle client certificates must chain to (mutual TLS)
+    pub client_ca: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PolicyConfig {
+    pub dir: Option<PathBuf>,
+    /// Recompile `dir` when its files change
+    pub watch: bool,
+    pub bundle_url: Option<String>,
+    /// Seconds between bundle re-fetches (0 loads it once)
+    pub bundle_poll_interval: u64,
+    /// Variables `.rego.tmpl` templates are rendered with (see
+    /// [`template`](crate::template))
+    pub vars: Vars,
+    /// Engines compiled from each policy revision at most; evaluations
+    /// past it wait for one to be free (0 takes
+    /// `concurrency.max_evaluations`, itself 0 for unlimited)
+    pub max_engines: usize,
+}
+
+/// Gradual rollout of new policy revisions (see `policy`)
+///
+/// ```toml
+/// [rollout]
+/// canary_weight = 10
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RolloutConfig {
+    /// Percent of callers a newly loaded revision decides for, as the
+    /// canary, until promoted (0 activates it for everyone at once)
+    pub canary_weight: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    /// Decisions kept in memory across the namespaces without a partition
+    /// in `namespaces`
+    pub max_entries: usize,
+    /// Which entries make room when the cache is full
+    pub eviction: Eviction,
+    /// Shards the store and each partition are split into, each locked on
+    /// its own (see `sark-store`)
+    pub shards: usize,
+    /// Quota counters the store and each partition keep pinned, never
+    /// evicted to make room; at most half of `max_entries`
+    pub max_pinned: usize,
+    /// False-positive rate of the Bloom filter letting decision lookups
+    /// of keys never written skip the cache, e.g. `0.01` (0 disables)
+    pub bloom_fp_rate: f64,
+    /// TTL in seconds for cached allows on /gateway/authorize
+    pub allow_ttl: u64,
+    /// TTL in seconds for cached allows on /gateway/authorize-a2a; agent
+    /// grants are kept shorter
+    pub a2a_allow_ttl: u64,
+    pub deny_ttl: u64,
+    /// Longest a decision is cached, whatever the defaults above or a
+    /// policy's `cache_ttl` say (a TTL of 0 isn't cached)
+    pub max_ttl: u64,
+    /// Age after which cached decisions are revalidated in the background
+    /// (0 disables)
+    pub soft_ttl: u64,
+    pub ttl_jitter_pct: u8,
+    /// Seconds between expired-entry sweeps (0 disables)
+    pub cleanup_interval: u64,
+    /// Input fields (dotted paths) each decision namespace is keyed on,
+    /// e.g. `auth = ["user", "action", "resource", "parameters"]`. Without
+    /// an entry the whole input is. Leaving out a field the policy reads
+    /// makes requests that differ in it share a decision.
+    pub key_fields: HashMap<String, Vec<String>>,
+    /// Namespaces (`auth`, `a2a`, `quota`, or a tenant's `auth@<tenant>`)
+    /// kept apart from the rest, each sized and evicting on its own (see
+    /// `sark-store`)
+    pub namespaces: HashMap<String, NamespaceCacheConfig>,
+    /// Decisions re-evaluated after the cache is cleared (see `precompute`)
+    pub precompute: PrecomputeConfig,
+}
+
+/// Namespaces a partition can be configured for; tenants have their own
+/// of the decision ones, `auth@<tenant>` and `a2a@<tenant>`
+const CACHE_NAMESPACES: [&str; 3] = ["auth", "a2a", "quota"];
+
+impl CacheConfig {
+    /// Entries kept of namespace `name`, in its partition or the store it
+    /// shares
+    pub fn capacity_of(&self, name: &str) -> usize {
+        let base = name.split_once('@').map_or(name, |(base, _)| base);
+        self.namespaces
+            .get(name)
+            .or_else(|| self.namespaces.get(base))
+            .map_or(self.max_entries, |namespace| namespace.max_entries)
+    }
+
+    /// Entries kept across every namespace
+    pub fn capacity(&self) -> usize {
+        self.max_entries
+            + self
+                .namespaces
+                .values()
+                .map(|namespace| namespace.max_entries)
+                .sum::<usize>()
+    }
+}
+
+/// A namespace's own partition of the in-process cache
+///
+/// ```toml
+/// [cache.namespaces.quota]
+/// max_entries = 50000
+/// max_ttl = 86400
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NamespaceCacheConfig {
+    /// Entries the namespace keeps, on top of `cache.max_entries`
+    pub max_entries: usize,
+    /// Which entries make room when the partition is full; `cache.eviction`
+    /// if unset
+    pub eviction: Option<Eviction>,
+    /// Longest an entry of the namespace is kept, in both tiers, whatever
+    /// TTL it was set with; entries keep their own TTLs if unset
+    pub max_ttl: Option<u64>,
+}
+
+/// The decisions looked up most often, evaluated again after a policy
+/// change clears them
+///
+/// ```toml
+/// [cache.precompute]
+/// top_n = 500
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PrecomputeConfig {
+    /// Inputs re-evaluated per namespace cleared, most looked up first (0
+    /// disables, and lookups aren't counted)
+    pub top_n: usize,
+    /// Distinct inputs whose lookups are counted; 4 × `top_n` if unset
+    pub tracked: Option<usize>,
+}
+
+impl PrecomputeConfig {
+    pub fn tracked(&self) -> usize {
+        self.tracked.unwrap_or(self.top_n.saturating_mul(4))
+    }
+}
+
+impl Default for NamespaceCacheConfig {
+    fn default() -> Self {
+        Self {
+            max_entries: 10_000,
+            eviction: None,
+            max_ttl: None,
+        }
+    }
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            max_entries: 10_000,
+            eviction: Eviction::default(),
+            shards: 16,
+            max_pinned: 1000,
+            bloom_fp_rate: 0.0,
+            allow_ttl: 300,
+            a2a_allow_ttl: 60,
+            deny_ttl: 60,
+            max_ttl: 3600,
+            soft_ttl: 0,
+            ttl_jitter_pct: 0,
+            cleanup_interval: 60,
+            key_fields: HashMap::new(),
+            namespaces: HashMap::new(),
+            precompute: PrecomputeConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JwtConfig {
+    pub jwks_url: Option<String>,
+    pub audience: Option<String>,
+    pub issuer: Option<String>,
+    /// Seconds between JWKS refetches
+    pub refresh_interval: u64,
+    /// Seconds a verified JWT's caller is reused without checking its
+    /// signature again, never past its `exp` (0 disables)
+    pub cache_ttl: u64,
+    /// Verified JWTs held at once
+    pub cache_entries: usize,
+    /// Where opaque tokens are checked
+    pub introspection: IntrospectionConfig,
+}
+
+impl Default for JwtConfig {
+    fn default() -> Self {
+        Self {
+            jwks_url: None,
+            audience: None,
+            issuer: None,
+            refresh_interval: 300,
+            cache_ttl: 60,
+            cache_entries: 10_000,
+            introspection: IntrospectionConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IntrospectionConfig {
+    /// RFC 7662 introspection endpoint of the IdP
+    pub url: Option<String>,
+    /// Credentials the gateway authenticates to the endpoint with
+    pub client_id: Option<String>,
+    /// Never shown by `GET /admin/config`
+    #[serde(skip_serializing)]
+    pub client_secret: Option<String>,
+    /// Seconds an active token's introspection is reused (0 disables)
+    pub cache_ttl: u64,
+    /// Introspections held at once
+    pub cache_entries: usize,
+    /// Seconds to wait for the endpoint
+    pub timeout: u64,
+}
+
+impl Default for IntrospectionConfig {
+    fn default() -> Self {
+        Self {
+            url: None,
+            client_id: None,
+            client_secret: None,
+            cache_ttl: 30,
+            cache_entries: 10_000,
+            timeout: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ApiKeysConfig {
+    /// Keys by the name that identifies their caller
+    pub keys: HashMap<String, ApiKeyConfig>,
+    /// File of more keys, as `[<name>]` tables of the same settings (TOML,
+    /// or YAML/JSON by extension)
+    pub file: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ApiKeyConfig {
+    /// Hex SHA-256 of the key; the key itself is never configured
+    pub sha256: String,
+    /// Permissions the key grants, as `input.user.permissions`
+    #[serde(default)]
+    pub scopes: Vec<String>,
+    #[serde(default)]
+    pub roles: Vec<String>,
+    /// The key's own limit on each decision route, in place of the
+    /// route's
+    pub rate_limit: Option<RouteLimit>,
+    /// Tenant the key's caller belongs to
+    pub tenant: Option<String>,
+}
+
+/// Step-up obligations the gateway checks callers' tokens against (see
+/// `stepup`)
+///
+/// ```toml
+/// [step_up]
+/// enforce = true
+/// auth_url = "https://idp.example.com/authorize"
+/// acr_levels = ["pwd", "mfa", "phr"]
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StepUpConfig {
+    /// Answer decisions whose step-up the caller's token doesn't meet with
+    /// a challenge, rather than pass the obligation on
+    pub enforce: bool,
+    /// Where callers authenticate again, sent in challenges with the
+    /// requirement as query parameters
+    pub auth_url: Option<String>,
+    /// Authentication context classes from weakest to strongest; a token
+    /// at one meets a requirement of any before it
+    pub acr_levels: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpiffeConfig {
+    /// Workload API socket (a path, or `unix://` URI like
+    /// `SPIFFE_ENDPOINT_SOCKET`) to serve TLS with the gateway's X.509 SVID
+    pub socket: Option<String>,
+    /// Trust domains whose workloads may connect; the gateway's own if empty
+    pub trust_domains: Vec<String>,
+    /// Take A2A source agents' ids from their clients' SPIFFE IDs
+    pub agent_identity: bool,
+}
+
+impl SpiffeConfig {
+    /// The Workload API socket's path
+    pub fn socket_path(&self) -> Option<PathBuf> {
+        self.socket.as_deref().map(|socket| {
+            let path = socket.strip_prefix("unix://").unwrap_or(socket);
+            PathBuf::from(path.strip_prefix("unix:").unwrap_or(path))
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClientIpConfig {
+    /// Proxies whose `X-Forwarded-For` is believed
+    pub trusted_proxies: Vec<Cidr>,
+    /// Read a PROXY protocol header at the start of each TCP connection
+    pub proxy_protocol: bool,
+    /// Addresses that may call the gateway; any if empty
+    pub allow: Vec<Cidr>,
+    /// Addresses refused, even if allowed
+    pub deny: Vec<Cidr>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EnrichConfig {
+    /// MaxMind DB client countries are looked up in (Country or City)
+    pub country_db: Option<PathBuf>,
+    /// MaxMind DB client autonomous systems are looked up in (ASN)
+    pub asn_db: Option<PathBuf>,
+}
+
+/// Messages for policies' reason codes (see `reasons`)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReasonsConfig {
+    /// File of messages by code and locale (TOML, or YAML/JSON by
+    /// extension)
+    pub catalog: Option<PathBuf>,
+    /// Locale used when the caller asks for none the catalog has
+    pub default_locale: Option<String>,
+}
+
+/// Data documents kept fresh from HTTP endpoints (see `refresh`)
+///
+/// ```toml
+/// [data.sources.roles]
+/// url = "http://sark-api:8000/api/v1/policies/data/roles"
+/// path = "sark/roles"
+/// interval = 60
+/// headers = { Authorization = "Bearer ..." }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DataConfig {
+    /// Each source, by a name for logs
+    pub sources: HashMap<String, DataSourceConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DataSourceConfig {
+    /// Where the JSON document is fetched from
+    pub url: String,
+    /// Slash-separated data path it replaces (`sark/roles` is
+    /// `data.sark.roles`)
+    pub path: String,
+    /// Seconds between fetches (60 where unset)
+    pub interval: Option<u64>,
+    /// Longest wait in seconds between retries of a failing source, which
+    /// back off from `interval` (600 where unset)
+    pub max_backoff: Option<u64>,
+    /// Seconds to wait for a response (10 where unset)
+    pub timeout: Option<u64>,
+    /// Headers sent with each request
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+}
+
+/// Decisions sampled for offline analysis (see `capture`)
+///
+/// ```toml
+/// [capture]
+/// target = "/var/lib/sark/capture.jsonl"
+/// rate = 0.01
+/// redact = ["input.user.email", "body.parameters.*.ssn"]
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CaptureConfig {
+    /// A file path (JSON lines appended), or an `http(s)://` URL objects
+    /// are PUT under
+    pub target: Option<String>,
+    /// Fraction of decisions captured, from 0 to 1
+    pub rate: f64,
+    /// Dotted paths into each record (`*` matching every member) whose
+    /// values are masked before it is written
+    pub redact: Vec<String>,
+    /// Records written at once, and per object with a URL target
+    pub batch_size: usize,
+    /// Longest in seconds a record waits to be written
+    pub flush_interval: u64,
+    /// Records waiting to be written; more are dropped
+    pub queue_size: usize,
+    /// Headers sent with each object, with a URL target
+    pub headers: HashMap<String, String>,
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        Self {
+            target: None,
+            rate: 0.01,
+            redact: Vec::new(),
+            batch_size: 1000,
+            flush_interval: 60,
+            queue_size: 10_000,
+            headers: HashMap::new(),
+        }
+    }
+}
+
+/// Policy packages decisions are evaluated against, where not the built-in
+/// ones. A request's action (or capability) picks its package before the
+/// endpoint's does:
+///
+/// ```toml
+/// [queries]
+/// authorize = "data.mcp.gateway"
+///
+/// [queries.actions]
+/// "gateway:server:register" = "data.mcp.registration"
+/// ```
+///
+/// Every package is read like the built-in ones, `allow` deciding.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QueriesConfig {
+    /// Package for `/gateway/authorize` (`data.mcp.gateway`)
+    pub authorize: Option<String>,
+    /// Package for `/gateway/authorize-a2a` (`data.a2a.gateway`)
+    pub a2a: Option<String>,
+    /// Package for the admission webhook (`data.kubernetes.admission`)
+    pub admission: Option<String>,
+    /// Package for Envoy external authorization (`data.envoy.authz`)
+    pub envoy: Option<String>,
+    /// Packages for gateway authorizations by `action`, over `authorize`
+    pub actions: HashMap<String, String>,
+    /// Packages for agent-to-agent authorizations by `capability`, over
+    /// `a2a`
+    pub capabilities: HashMap<String, String>,
+}
+
+impl QueriesConfig {
+    fn validate(&self) -> Result<()> {
+        let endpoints = [
+            ("authorize", &self.authorize),
+            ("a2a", &self.a2a),
+            ("admission", &self.admission),
+            ("envoy", &self.envoy),
+        ];
+        for (name, query) in endpoints {
+            if let Some(query) = query.as_deref().filter(|q| !valid_query(q)) {
+                bail!("Invalid queries.{} {:?}", name, query);
+            }
+        }
+        for (table, queries) in [
+            ("actions", &self.actions),
+            ("capabilities", &self.capabilities),
+        ] {
+            if let Some((key, query)) = queries.iter().find(|(_, q)| !valid_query(q)) {
+                bail!("Invalid queries.{} entry for {:?}: {:?}", table, key, query);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    pub tool_decisions: ToolDecisionsConfig,
+}
+
+/// Series of `sark_gateway_tool_decisions_total`, which counts gateway
+/// decisions by server, tool, outcome and policy revision
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ToolDecisionsConfig {
+    pub enabled: bool,
+    /// Servers labeled by name, the rest as `other`; all if empty
+    pub servers: Vec<String>,
+    /// Tools labeled by name, the rest as `other`; all if empty
+    pub tools: Vec<String>,
+    /// Server/tool pairs with series of their own, first come; later pairs
+    /// are counted as `other`
+    pub max_tools: usize,
+    /// Policy revisions whose series are kept, the newest; older ones'
+    /// are dropped
+    pub revisions: usize,
+}
+
+impl Default for ToolDecisionsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            servers: Vec::new(),
+            tools: Vec::new(),
+            max_tools: 200,
+            revisions: 2,
+        }
+    }
+}
+
+/// Decision metadata put in response headers (see `provenance`)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ResponseHeadersConfig {
+    /// `X-SARK-Decision: allow|deny`
+    pub decision: bool,
+    /// `X-SARK-Policy-Revision`
+    pub policy_revision: bool,
+    /// `X-SARK-Cache: hit|miss`
+    pub cache: bool,
+    /// `X-SARK-Request-Id`
+    pub request_id: bool,
+}
+
+/// A decision made ahead of the policy (see `overrides`)
+///
+/// ```toml
+/// [[overrides]]
+/// server = "github"
+/// tool = "delete_repo"
+/// effect = "deny"
+/// reason = "INC-4711: repository deletion disabled"
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OverrideConfig {
+    pub server: String,
+    /// The tool overridden; every tool of the server where unset
+    pub tool: Option<String>,
+    /// `allow` or `deny`
+    pub effect: Effect,
+    /// Put in the decision's reason
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SigningConfig {
+    /// PEM private key decisions are signed with (PKCS#8 for EC keys)
+    pub key: Option<PathBuf>,
+    /// RS*, PS*, ES256 or ES384, matching the key
+    pub algorithm: jsonwebtoken::Algorithm,
+    /// `kid` of the signatures; the key's RFC 7638 thumbprint by default
+    pub key_id: Option<String>,
+}
+
+impl Default for SigningConfig {
+    fn default() -> Self {
+        Self {
+            key: None,
+            algorithm: jsonwebtoken::Algorithm::ES256,
+            key_id: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TenantsConfig {
+    /// Header naming the tenant of callers whose token or API key doesn't
+    pub header: Option<String>,
+    /// Refuse requests without a tenant rather than deciding them with the
+    /// default policy
+    pub required: bool,
+    /// Each tenant's policies, by tenant name
+    pub policies: HashMap<String, TenantPolicyConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TenantPolicyConfig {
+    /// Directory of the tenant's `.rego` and `data.json` files
+    pub dir: PathBuf,
+    /// Recompile on changes under `dir`
+    #[serde(default)]
+    pub watch: bool,
+    /// Template variables for this tenant, over `policy.vars` as they were
+    /// at startup
+    #[serde(default)]
+    pub vars: Vars,
+}
+
+/// Whether `name` can name a tenant (and a cache namespace)
+fn valid_tenant(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-' || b == b'_')
+}
+
+/// Whether `query` is a path into `data` (`data.<package>...`)
+fn valid_query(query: &str) -> bool {
+    query.strip_prefix("data.").is_some_and(|path| {
+        path.split('.').all(|part| {
+            part.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                && part.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_')
+        })
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShutdownConfig {
+    /// Seconds open connections get to finish after SIGTERM before they
+    /// are dropped; with the log flush this fits Kubernetes' default 30s
+    /// grace period
+    pub drain_timeout: u64,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self { drain_timeout: 25 }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdminConfig {
+    /// Address the admin API listens on, apart from the public port
+    pub listen: Option<SocketAddr>,
+    /// Bearer token admin requests must present (never shown by
+    /// `GET /admin/config`)
+    #[serde(skip_serializing)]
+    pub token: Option<String>,
+    /// Serve the OpenAPI document and a Swagger UI page for it
+    pub swagger_ui: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    /// Per-client limits by route, e.g.
+    /// `"/gateway/authorize" = { rate = 50, burst = 100 }`
+    pub routes: HashMap<String, RouteLimit>,
+    /// Keep buckets in Redis (`--redis-url`), so limits hold across
+    /// replicas rather than per replica
+    pub shared: bool,
+    /// Tokens a replica takes from a shared bucket at a time, to spend
+    /// before asking Redis again
+    pub lease: u32,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            routes: HashMap::new(),
+            shared: false,
+            lease: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RouteLimit {
+    /// Requests per second a client may sustain
+    pub rate: f64,
+    /// Requests a client may make at once after being idle
+    pub burst: u32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConcurrencyConfig {
+    /// Decision requests handled at once; more are shed with 503 (0 is
+    /// unlimited)
+    pub max_in_flight: usize,
+    /// Policy evaluations running or waiting at once; more cache misses are
+    /// shed with 503 while cached decisions are still served (0 is
+    /// unlimited)
+    pub max_evaluations: usize,
+    /// Milliseconds an evaluation may take, waiting for the engine
+    /// included, before it fails as timed out (0 is unlimited)
+    pub evaluation_timeout_ms: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RequestsConfig {
+    /// Seconds a decision request has to arrive and be answered (0
+    /// disables)
+    pub timeout: u64,
+    /// Largest decision request body accepted
+    pub max_body_bytes: usize,
+    /// Overrides by route, e.g.
+    /// `"/gateway/authorize/batch" = { max_body_bytes = 8388608 }`
+    pub routes: HashMap<String, RouteRequestsConfig>,
+}
+
+impl Default for RequestsConfig {
+    fn default() -> Self {
+        Self {
+            timeout: 10,
+            max_body_bytes: 1024 * 1024,
+            routes: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RouteRequestsConfig {
+    pub timeout: Option<u64>,
+    pub max_body_bytes: Option<usize>,
+}
+
+/// Long parameter strings summarized as they are parsed (see `scan`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ParametersConfig {
+    /// Longest string kept whole (0 disables scanning)
+    pub max_string_bytes: usize,
+    /// Bytes of a summarized string kept as its `prefix`
+    pub prefix_bytes: usize,
+    /// Dotted paths under `parameters` (`*` matching every member or item)
+    /// kept whole however long
+    pub keep: Vec<String>,
+}
+
+impl Default for ParametersConfig {
+    fn default() -> Self {
+        Self {
+            max_string_bytes: 0,
+            prefix_bytes: 256,
+            keep: Vec::new(),
+        }
+    }
+}
+
+/// Credentials flagged in parameters for policies (see `detect`)
+///
+/// ```toml
+/// [detection]
+/// enabled = true
+/// disable = ["jwt"]
+///
+/// [detection.rules.internal_token]
+/// pattern = "itk_[A-Za-z0-9]{32}"
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DetectionConfig {
+    /// Scan parameters and set `input.findings`
+    pub enabled: bool,
+    /// Built-in rules to leave out
+    pub disable: Vec<String>,
+    /// Rules of the operator's own, by name; one named as a built-in
+    /// replaces it
+    pub rules: HashMap<String, DetectionRuleConfig>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DetectionRuleConfig {
+    /// Regex a string value matches, or its whole value with `key` alone
+    pub pattern: Option<String>,
+    /// Regex the name of the field holding the value matches
+    pub key: Option<String>,
+    /// Least Shannon entropy, in bits per byte, of the matched text
+    pub min_entropy: f64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdmissionConfig {
+    /// Serve the Kubernetes validating admission webhook
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FallbackConfig {
+    /// Base URL of the SARK API to ask when local evaluation fails (e.g.
+    /// `http://sark-api:8000`)
+    pub url: Option<String>,
+    /// Seconds to wait for the API's decision
+    pub timeout: u64,
+    /// Consecutive API failures that open the circuit
+    pub failure_threshold: u32,
+    /// Seconds the circuit stays open before a trial request
+    pub open_duration: u64,
+    /// Outcome by sensitivity level when no decision can be had at all
+    /// (`closed` where unset)
+    pub failure_mode: HashMap<String, FailureMode>,
+}
+
+impl Default for FallbackConfig {
+    fn default() -> Self {
+        Self {
+            url: None,
+            timeout: 2,
+            failure_threshold: 5,
+            open_duration: 30,
+            failure_mode: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FailureMode {
+    /// Allow the request
+    Open,
+    /// Fail the request with the evaluation error
+    #[default]
+    Closed,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProxyConfig {
+    /// MCP servers requests are forwarded to, by the name policies see as
+    /// `resource.server`
+    pub servers: HashMap<String, UpstreamConfig>,
+    /// Seconds to wait for a connection to a server
+    pub connect_timeout: u64,
+    /// Idle connections kept open per server host
+    pub pool_max_idle: usize,
+    /// Largest JSON response read whole to apply redaction obligations;
+    /// larger ones are refused
+    pub max_response_bytes: usize,
+}
+
+impl Default for ProxyConfig {
+    fn default() -> Self {
+        Self {
+            servers: HashMap::new(),
+            connect_timeout: 5,
+            pool_max_idle: 32,
+            max_response_bytes: 16 * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UpstreamConfig {
+    /// The server's MCP endpoint (e.g. `http://github-mcp:8080/mcp`)
+    pub url: String,
+    /// Sensitivity level of the server's tools (classified by `[classify]`
+    /// where unset)
+    pub sensitivity: Option<String>,
+    /// Pass the caller's bearer token (or API key) on to the server
+    #[serde(default)]
+    pub forward_token: bool,
+}
+
+impl ApiKeyConfig {
+    /// Reject a key that can't be matched or limited
+    pub fn validate(&self, name: &str) -> Result<()> {
+        if self.sha256.len() != 64 || !self.sha256.bytes().all(|b| b.is_ascii_hexdigit()) {
+            bail!("API key {:?} needs a hex SHA-256 sha256", name);
+        }
+        if let Some(limit) = &self.rate_limit {
+            if !(limit.rate.is_finite() && limit.rate > 0.0) || limit.burst == 0 {
+                bail!(
+                    "API key {:?} rate_limit needs a positive rate and burst",
+                    name
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The file at `path` as a config source, TOML unless its extension says
+/// YAML or JSON
+pub fn file(path: &Path) -> config::File<config::FileSourceFile, config::FileFormat> {
+    let format = match path.extension().and_then(|e| e.to_str()) {
+        Some("yaml" | "yml") => config::FileFormat::Yaml,
+        Some("json") => config::FileFormat::Json,
+        _ => config::FileFormat::Toml,
+    };
+    config::File::new(&path.to_string_lossy(), format)
+}
+
+/// Sensitivity levels requests are classified by
+pub const SENSITIVITY_LEVELS: &[&str] = &["low", "medium", "high", "critical"];
+
+impl GatewayConfig {
+    /// Read `path` (which must exist if `required`) and the environment
+    /// over the defaults
+    pub fn load(path: &Path, required: bool) -> Result<Self> {
+        config::Config::builder()
+            .add_source(file(path).required(required))
+            .add_source(
+                config::Environment::with_prefix(ENV_PREFIX)
+                    .prefix_separator("_")
+                    .separator("__")
+                    .try_parsing(true),
+            )
+            .build()
+            .and_then(|c| c.try_deserialize())
+            .with_context(|| format!("Invalid config {}", path.display()))
+    }
+
+    /// Reject settings that can't work together
+    pub fn validate(&self) -> Result<()> {
+        tracing_subscriber::EnvFilter::try_new(&self.log.level)
+            .with_context(|| format!("Invalid log level {:?}", self.log.level))?;
+
+        match (&self.tls.cert, &self.tls.key) {
+            (Some(_), None) => bail!("tls.cert is set without tls.key"),
+            (None, Some(_)) => bail!("tls.key is set without tls.cert"),
+            _ => {}
+        }
+        if self.tls.client_ca.is_some() && self.tls.cert.is_none() {
+            bail!("tls.client_ca requires tls.cert and tls.key");
+        }
+        if self.spiffe.socket.is_some() && self.tls != TlsConfig::default() {
+            bail!("spiffe.socket replaces tls.cert, tls.key and tls.client_ca");
+        }
+        if !self.spiffe.trust_domains.is_empty() && self.spiffe.socket.is_none() {
+            bail!("spiffe.trust_domains requires spiffe.socket");
+        }
+        if self.spiffe.agent_identity
+            && self.spiffe.socket.is_none()
+            && self.tls.client_ca.is_none()
+        {
+            bail!("spiffe.agent_identity requires spiffe.socket or tls.client_ca");
+        }
+
+        if self.policy.dir.is_some() && self.policy.bundle_url.is_some() {
+            bail!("policy.dir and policy.bundle_url are mutually exclusive");
+        }
+        if self.policy.watch && self.policy.dir.is_none() {
+            bail!("policy.watch requires policy.dir");
+        }
+        if self.policy.bundle_poll_interval > 0 && self.policy.bundle_url.is_none() {
+            bail!("policy.bundle_poll_interval requires policy.bundle_url");
+        }
+
+        if self.reasons.default_locale.is_some() && self.reasons.catalog.is_none() {
+            bail!("reasons.default_locale requires reasons.catalog");
+        }
+
+        if self.rollout.canary_weight > 100 {
+            bail!("rollout.canary_weight must be from 0 to 100");
+        }
+
+        if self.cache.max_entries == 0 {
+            bail!("cache.max_entries must be at least 1");
+        }
+        if self.cache.max_ttl == 0 {
+            bail!("cache.max_ttl must be at least 1");
+        }
+        if self.cache.shards == 0 {
+            bail!("cache.shards must be at least 1");
+        }
+        if !(0.0..1.0).contains(&self.cache.bloom_fp_rate) {
+            bail!("cache.bloom_fp_rate must be at least 0 and below 1");
+        }
+        if self.cache.ttl_jitter_pct > 100 {
+            bail!("cache.ttl_jitter_pct must be between 0 and 100");
+        }
+        for (name, fields) in &self.cache.key_fields {
+            if fields.is_empty() || fields.iter().any(|f| f.split('.').any(str::is_empty)) {
+                bail!("Invalid cache.key_fields entry for {:?}", name);
+            }
+        }
+        for (name, namespace) in &self.cache.namespaces {
+            let known = match name.split_once('@') {
+                Some((base, tenant)) => ["auth", "a2a"].contains(&base) && !tenant.is_empty(),
+                None => CACHE_NAMESPACES.contains(&name.as_str()),
+            };
+            if !known {
+                bail!(
+                    "Unknown cache namespace {:?} (expected one of {}, or auth@<tenant> or a2a@<tenant>)",
+                    name,
+                    CACHE_NAMESPACES.join(", ")
+                );
+            }
+            if namespace.max_entries == 0 {
+                bail!("cache.namespaces.{}.max_entries must be at least 1", name);
+            }
+            if namespace.max_ttl == Some(0) {
+                bail!("cache.namespaces.{}.max_ttl must be at least 1", name);
+            }
+        }
+        if self.cache.precompute.tracked() < self.cache.precompute.top_n {
+            bail!("cache.precompute.tracked must be at least cache.precompute.top_n");
+        }
+
+        if self.connections.max_concurrent_streams == 0 {
+            bail!("connections.max_concurrent_streams must be at least 1");
+        }
+        if self.connections.http2_keep_alive_interval > 0
+            && self.connections.http2_keep_alive_timeout == 0
+        {
+            bail!("connections.http2_keep_alive_timeout must be at least 1");
+        }
+        self.queries.validate()?;
+        if self.metrics.tool_decisions.revisions == 0 {
+            bail!("metrics.tool_decisions.revisions must be at least 1");
+        }
+
+        let introspection = &self.jwt.introspection;
+        if self.jwt.jwks_url.is_none()
+            && introspection.url.is_none()
+            && (self.jwt.audience.is_some() || self.jwt.issuer.is_some())
+        {
+            bail!("jwt.audience and jwt.issuer require jwt.jwks_url or jwt.introspection.url");
+        }
+        if self.jwt.refresh_interval == 0 {
+            bail!("jwt.refresh_interval must be at least 1");
+        }
+        if self.jwt.cache_entries == 0 {
+            bail!("jwt.cache_entries must be at least 1");
+        }
+        if introspection.client_id.is_some() != introspection.client_secret.is_some() {
+            bail!("jwt.introspection.client_id and client_secret must be set together");
+        }
+        if introspection.timeout == 0 {
+            bail!("jwt.introspection.timeout must be at least 1");
+        }
+        if introspection.cache_entries == 0 {
+            bail!("jwt.introspection.cache_entries must be at least 1");
+        }
+
+        for (route, limit) in &self.rate_limit.routes {
+            if !(limit.rate.is_finite() && limit.rate > 0.0) || limit.burst == 0 {
+                bail!(
+                    "rate_limit.routes entry for {:?} needs a positive rate and burst",
+                    route
+                );
+            }
+        }
+        if self.rate_limit.lease == 0 {
+            bail!("rate_limit.lease must be at least 1");
+        }
+
+        for (name, key) in &self.api_keys.keys {
+            key.validate(name)?;
+        }
+
+        if let Some(url) = &self.step_up.auth_url {
+            reqwest::Url::parse(url)
+                .with_context(|| format!("Invalid step_up.auth_url {:?}", url))?;
+        }
+        if let Some(acr) = self
+            .step_up
+            .acr_levels
+            .iter()
+            .enumerate()
+            .find_map(|(i, acr)| self.step_up.acr_levels[..i].contains(acr).then_some(acr))
+        {
+            bail!("step_up.acr_levels lists {:?} twice", acr);
+        }
+
+        let body_limits = std::iter::once(Some(self.requests.max_body_bytes))
+            .chain(self.requests.routes.values().map(|r| r.max_body_bytes));
+        if body_limits.flatten().any(|limit| limit == 0) {
+            bail!("requests.max_body_bytes must be at least 1");
+        }
+        let parameters = &self.parameters;
+        if parameters.max_string_bytes > 0 && parameters.prefix_bytes > parameters.max_string_bytes
+        {
+            bail!("parameters.prefix_bytes can't exceed parameters.max_string_bytes");
+        }
+        if let Some(path) = parameters
+            .keep
+            .iter()
+            .find(|path| path.is_empty() || path.split('.').any(str::is_empty))
+        {
+            bail!("Invalid parameters.keep path {:?}", path);
+        }
+        if let Some(name) = self
+            .detection
+            .disable
+            .iter()
+            .find(|name| !detect::BUILTIN.iter().any(|rule| rule.name == *name))
+        {
+            bail!("detection.disable names no built-in rule: {:?}", name);
+        }
+        for (name, rule) in &self.detection.rules {
+            if rule.pattern.is_none() && rule.key.is_none() {
+                bail!("detection.rules.{} needs a pattern or a key", name);
+            }
+            for regex in [&rule.pattern, &rule.key].into_iter().flatten() {
+                regex::Regex::new(regex)
+                    .with_context(|| format!("Invalid regex in detection.rules.{}", name))?;
+            }
+            if !(0.0..=8.0).contains(&rule.min_entropy) {
+                bail!("detection.rules.{}.min_entropy must be from 0 to 8", name);
+            }
+        }
+
+        sark_classify::Classifier::new(&self.classify).context("Invalid classify rules")?;
+
+        for rule in &self.overrides {
+            if rule.server.is_empty() || rule.tool.as_deref() == Some("") {
+                bail!("overrides need a server, and a tool name where one is set");
+            }
+        }
+
+        if let Some(url) = &self.fallback.url {
+            reqwest::Url::parse(url).with_context(|| format!("Invalid fallback.url {:?}", url))?;
+        }
+        if self.fallback.failure_threshold == 0 {
+            bail!("fallback.failure_threshold must be at least 1");
+        }
+        if let Some(level) = self
+            .fallback
+            .failure_mode
+            .keys()
+            .find(|level| !SENSITIVITY_LEVELS.contains(&level.as_str()))
+        {
+            bail!(
+                "Unknown sensitivity level {:?} in fallback.failure_mode",
+                level
+            );
+        }
+
+        if self.proxy.max_response_bytes == 0 {
+            bail!("proxy.max_response_bytes must be at least 1");
+        }
+        for (name, upstream) in &self.proxy.servers {
+            let url = reqwest::Url::parse(&upstream.url)
+                .with_context(|| format!("Invalid proxy.servers url for {:?}", name))?;
+            if !matches!(url.scheme(), "http" | "https") {
+                bail!("proxy.servers url for {:?} must be http or https", name);
+            }
+            if let Some(level) = upstream
+                .sensitivity
+                .as_deref()
+                .filter(|level| !SENSITIVITY_LEVELS.contains(level))
+            {
+                bail!(
+                    "Unknown sensitivity level {:?} for proxy server {:?}",
+                    level,
+                    name
+                );
+            }
+        }
+
+        let mut data_paths = Vec::new();
+        for (name, source) in &self.data.sources {
+            let url = reqwest::Url::parse(&source.url)
+                .with_context(|| format!("Invalid data.sources url for {:?}", name))?;
+            if !matches!(url.scheme(), "http" | "https") {
+                bail!("data.sources url for {:?} must be http or https", name);
+            }
+            let path = source.path.trim_matches('/');
+            if path.is_empty() || path.split('/').any(str::is_empty) {
+                bail!(
+                    "data.sources path for {:?} must name a document below the root",
+                    name
+                );
+            }
+            if data_paths.contains(&path) {
+                bail!(
+                    "data.sources path {:?} is refreshed by more than one source",
+                    path
+                );
+            }
+            data_paths.push(path);
+            if source.interval == Some(0) || source.timeout == Some(0) {
+                bail!(
+                    "data.sources interval and timeout for {:?} must be at least 1",
+                    name
+                );
+            }
+            for (header, value) in &source.headers {
+                axum::http::HeaderName::from_bytes(header.as_bytes())
+                    .with_context(|| format!("Invalid data.sources header {:?}", header))?;
+                axum::http::HeaderValue::from_str(value).with_context(|| {
+                    format!("Invalid value for data.sources header {:?}", header)
+                })?;
+            }
+        }
+
+        let capture = &self.capture;
+        if !(0.0..=1.0).contains(&capture.rate) {
+            bail!("capture.rate must be between 0 and 1");
+        }
+        if let Some(url) = capture
+            .target
+            .as_deref()
+            .filter(|t| t.starts_with("http://") || t.starts_with("https://"))
+        {
+            reqwest::Url::parse(url)
+                .with_context(|| format!("Invalid capture.target {:?}", url))?;
+        }
+        if capture.batch_size == 0 || capture.flush_interval == 0 || capture.queue_size == 0 {
+            bail!("capture.batch_size, flush_interval and queue_size must be at least 1");
+        }
+        if let Some(path) = capture
+            .redact
+            .iter()
+            .find(|path| path.is_empty() || path.split('.').any(str::is_empty))
+        {
+            bail!("Invalid capture.redact path {:?}", path);
+        }
+        for (header, value) in &capture.headers {
+            axum::http::HeaderName::from_bytes(header.as_bytes())
+                .with_context(|| format!("Invalid capture.headers header {:?}", header))?;
+            axum::http::HeaderValue::from_str(value).with_context(|| {
+                format!("Invalid value for capture.headers header {:?}", header)
+            })?;
+        }
+
+        let tenants = &self.tenants;
+        if let Some(header) = &tenants.header {
+            axum::http::HeaderName::from_bytes(header.as_bytes())
+                .with_context(|| format!("Invalid tenants.header {:?}", header))?;
+        }
+        if (tenants.header.is_some() || tenants.required) && tenants.policies.is_empty() {
+            bail!("tenants.header and tenants.required need tenants.policies");
+        }
+        if let Some(name) = tenants.policies.keys().find(|name| !valid_tenant(name)) {
+            bail!(
+                "Invalid tenant name {:?}; use lowercase letters, digits, - and _",
+                name
+            );
+        }
+
+        use jsonwebtoken::Algorithm::*;
+        if matches!(self.signing.algorithm, HS256 | HS384 | HS512 | EdDSA) {
+            bail!("signing.algorithm must be one of RS*, PS*, ES256 or ES384");
+        }
+
+        self.unix_socket.permissions()?;
+        let tls = self.tls.cert.is_some() || self.spiffe.socket.is_some();
+        if matches!(self.listen, ListenAddr::Unix(_)) && tls {
+            bail!("TLS requires a TCP listen address");
+        }
+        if matches!(self.listen, ListenAddr::Unix(_)) && self.client_ip.proxy_protocol {
+            bail!("client_ip.proxy_protocol requires a TCP listen address");
+        }
+
+        match (&self.admin.listen, &self.admin.token) {
+            (Some(_), None) => bail!("admin.listen requires admin.token"),
+            (None, Some(_)) => bail!("admin.token requires admin.listen"),
+            (None, None) if self.admin.swagger_ui => {
+                bail!("admin.swagger_ui requires admin.listen")
+            }
+            (Some(admin), _) if ListenAddr::Tcp(*admin) == self.listen => {
+                bail!("admin.listen must differ from listen")
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Engines compiled from each policy revision at most (0 is
+    /// unlimited)
+    pub fn max_engines(&self) -> usize {
+        match self.policy.max_engines {
+            0 => self.concurrency.max_evaluations,
+            max => max,
+        }
+    }
+}
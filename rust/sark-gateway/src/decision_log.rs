@@ -0,0 +1,325 @@
+//! Decision logging
+//!
+//! Every authorization decision can be recorded for audit: a decision id,
+//! a hash of the policy input (inputs carry user data, so they are not
+//! logged verbatim), the result, latency, and the policy revision that
+//! produced it. Records are queued and written in batches by a background
+//! task so the hot path never waits on the sink; if the sink falls behind
+//! and the queue fills, records are dropped and counted.
+//!
+//! Sinks, chosen by the `--decision-log` target:
+//! - a file path: JSON lines appended to the file
+//! - `http(s)://...`: JSON arrays POSTed to a collector
+//! - `kafka://broker[,broker...]/topic`: one message per record, keyed by
+//!   decision id, batches spread round-robin over the topic's partitions
+//! - `nats://host:port/subject`: one message per record
+
+use crate::replay::Inputs;
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use rskafka::client::partition::{Compression, PartitionClient, UnknownTopicHandling};
+use rskafka::record::Record;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+pub use sark_policy::input_digest;
+
+/// Default records buffered between the hot path and the sink
+pub const DEFAULT_QUEUE_CAPACITY: usize = 10_000;
+
+/// Default records written to the sink at once
+pub const DEFAULT_BATCH_SIZE: usize = 500;
+
+/// One logged decision
+#[derive(Debug, Serialize)]
+pub struct DecisionRecord {
+    pub decision_id: String,
+    pub timestamp: DateTime<Utc>,
+    /// Rule the decision was evaluated against
+    pub query: String,
+    /// SHA-256 of the policy input document
+    pub input_hash: String,
+    /// Decision returned to the caller, absent if evaluation failed
+    pub result: Option<serde_json::Value>,
+    pub error: Option<String>,
+    /// Whether the decision was served from cache
+    pub cached: bool,
+    pub latency_us: u64,
+    pub revision: Option<String>,
+    /// Whether the decision was a dry run, not enforced
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub dry_run: bool,
+    /// The input itself, kept in memory for replay and never logged
+    #[serde(skip)]
+    pub input: Option<serde_json::Value>,
+}
+
+impl DecisionRecord {
+    pub fn new(query: &str, input_hash: String) -> Self {
+        Self {
+            decision_id: format!("{:032x}", rand::random::<u128>()),
+            timestamp: Utc::now(),
+            query: query.to_string(),
+            input_hash,
+            result: None,
+            error: None,
+            cached: false,
+            latency_us: 0,
+            revision: None,
+            dry_run: false,
+            input: None,
+        }
+    }
+}
+
+/// Where records are written
+enum Sink {
+    /// JSON lines appended to a file
+    File(tokio::fs::File),
+    /// JSON arrays POSTed to a collector
+    Http {
+        client: reqwest::Client,
+        url: String,
+    },
+    Kafka {
+        /// One client per partition of the topic
+        partitions: Vec<PartitionClient>,
+        next: usize,
+    },
+    Nats {
+        client: async_nats::Client,
+        subject: String,
+    },
+}
+
+impl Sink {
+    async fn open(target: &str) -> Result<Self> {
+        if let Some(rest) = target.strip_prefix("kafka://") {
+            let Some((brokers, topic)) = rest.split_once('/').filter(|(_, t)| !t.is_empty()) else {
+                bail!("Kafka decision log target must be kafka://brokers/topic");
+            };
+            let brokers = brokers.split(',').map(str::to_string).collect();
+            let client = rskafka::client::ClientBuilder::new(brokers)
+                .build()
+                .await
+                .context("Failed to connect to Kafka")?;
+
+            let topics = client.list_topics().await?;
+            let Some(found) = topics.into_iter().find(|t| t.name == topic) else {
+                bail!("Kafka topic {} does not exist", topic);
+            };
+            let mut partitions = Vec::new();
+            for partition in found.partitions {
+                partitions.push(
+                    client
+                        .partition_client(topic, partition, UnknownTopicHandling::Retry)
+                        .await?,
+                );
+            }
+            return Ok(Sink::Kafka {
+                partitions,
+                next: 0,
+            });
+        }
+
+        if let Some(rest) = target.strip_prefix("nats://") {
+            let Some((server, subject)) = rest.split_once('/').filter(|(_, s)| !s.is_empty())
+            else {
+                bail!("NATS decision log target must be nats://host:port/subject");
+            };
+            let client = async_nats::connect(server)
+                .await
+                .with_context(|| format!("Failed to connect to NATS at {}", server))?;
+            return Ok(Sink::Nats {
+                client,
+                subject: subject.to_string(),
+            });
+        }
+
+        if target.starts_with("http://") || target.starts_with("https://") {
+            let client = reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .context("Failed to build decision log HTTP client")?;
+            return Ok(Sink::Http {
+                client,
+                url: target.to_string(),
+            });
+        }
+
+        let file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(target)
+            .await
+            .with_context(|| format!("Failed to open decision log {}", target))?;
+        Ok(Sink::File(file))
+    }
+
+    async fn write(&mut self, batch: &[DecisionRecord]) -> Result<()> {
+        match self {
+            Sink::File(file) => {
+                let mut lines = Vec::new();
+                for record in batch {
+                    serde_json::to_writer(&mut lines, record)?;
+                    lines.push(b'\n');
+                }
+                file.write_all(&lines).await?;
+                file.flush().await?;
+            }
+            Sink::Http { client, url } => {
+                client
+                    .post(url.as_str())
+                    .json(batch)
+                    .send()
+                    .await?
+                    .error_for_status()?;
+            }
+            Sink::Kafka { partitions, next } => {
+                let mut records = Vec::with_capacity(batch.len());
+                for record in batch {
+                    records.push(Record {
+                        key: Some(record.decision_id.clone().into_bytes()),
+                        value: Some(serde_json::to_vec(record)?),
+                        headers: BTreeMap::new(),
+                        timestamp: record.timestamp,
+                    });
+                }
+                let partition = &partitions[*next % partitions.len()];
+                *next = next.wrapping_add(1);
+                partition
+                    .produce(records, Compression::NoCompression)
+                    .await?;
+            }
+            Sink::Nats { client, subject } => {
+                for record in batch {
+                    client
+                        .publish(subject.clone(), serde_json::to_vec(record)?.into())
+                        .await?;
+                }
+                client.flush().await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Handle for recording decisions
+pub struct DecisionLog {
+    tx: mpsc::Sender<DecisionRecord>,
+    dropped: AtomicU64,
+    /// Tells the writer to finish, and the writer to wait for
+    shutdown: Mutex<Option<(oneshot::Sender<()>, JoinHandle<()>)>>,
+    /// Inputs of recent decisions, if kept for replay
+    inputs: Option<Inputs>,
+}
+
+impl DecisionLog {
+    /// Open `target` and start the background writer, which writes up to
+    /// `batch_size` records at a time with up to `queue_capacity` waiting
+    pub async fn open(target: &str, batch_size: usize, queue_capacity: usize) -> Result<Self> {
+        let mut sink = Sink::open(target).await?;
+        let (tx, mut rx) = mpsc::channel::<DecisionRecord>(queue_capacity);
+
+        let (close, mut closed) = oneshot::channel();
+        let writer = tokio::spawn(async move {
+            let mut batch = Vec::with_capacity(batch_size);
+            let mut closing = false;
+            loop {
+                let record = tokio::select! {
+                    record = rx.recv() => record,
+                    // Refuse new records but keep writing queued ones
+                    _ = &mut closed, if !closing => {
+                        closing = true;
+                        rx.close();
+                        continue;
+                    }
+                };
+                let Some(record) = record else { break };
+                batch.push(record);
+                while batch.len() < batch_size {
+                    match rx.try_recv() {
+                        Ok(record) => batch.push(record),
+                        Err(_) => break,
+                    }
+                }
+
+                if let Err(e) = sink.write(&batch).await {
+                    warn!(error = %e, records = batch.len(), "Failed to write decision log");
+                }
+                batch.clear();
+            }
+        });
+
+        info!(target = %target, "Decision logging enabled");
+        Ok(Self {
+            tx,
+            dropped: AtomicU64::new(0),
+            shutdown: Mutex::new(Some((close, writer))),
+            inputs: None,
+        })
+    }
+
+    /// Keep the inputs of the last `capacity` decisions for replay
+    pub fn retaining_inputs(mut self, capacity: usize) -> Self {
+        self.inputs = (capacity > 0).then(|| Inputs::new(capacity));
+        self
+    }
+
+    /// Recent decisions' inputs, if kept for replay
+    pub fn inputs(&self) -> Option<&Inputs> {
+        self.inputs.as_ref()
+    }
+
+    /// A record of a decision on `input`, which keeps the input if inputs
+    /// are kept for replay
+    pub fn new_record(
+        &self,
+        query: &str,
+        input_hash: String,
+        input: &serde_json::Value,
+    ) -> DecisionRecord {
+        let mut record = DecisionRecord::new(query, input_hash);
+        if self.inputs.is_some() {
+            record.input = Some(input.clone());
+        }
+        record
+    }
+
+    /// Write out queued records and stop; records sent afterwards are lost
+    pub async fn close(&self) {
+        let shutdown = self.shutdown.lock().expect("shutdown lock poisoned").take();
+        if let Some((close, writer)) = shutdown {
+            let _ = close.send(());
+            let _ = writer.await;
+        }
+    }
+
+    /// Queue `record` without waiting; dropped if the queue is full
+    pub fn record(&self, mut record: DecisionRecord) {
+        if let Some(inputs) = &self.inputs {
+            inputs.keep(&mut record);
+        }
+        if self.tx.try_send(record).is_err() {
+            let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+            if dropped.is_power_of_two() {
+                warn!(
+                    dropped = dropped,
+                    "Decision log queue full; dropping records"
+                );
+            }
+        }
+    }
+
+    /// Records dropped because the sink could not keep up
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,305 @@
+//! Agent-to-agent delegation chains
+//!
+//! When agent A calls for user U through agent B, the `delegation_chain`
+//! of an A2A request only says so. To prove it, the request carries
+//! `delegation_tokens`, one JWT per link with the originator's first, in
+//! the shape of RFC 8693's delegation tokens:
+//!
+//! ```json
+//! {"sub": "user-123", "act": {"sub": "agent-b"}, "scope": "query execute", "exp": ...}
+//! {"sub": "agent-b", "act": {"sub": "agent-a"}, "scope": "query", "exp": ...}
+//! ```
+//!
+//! Each is verified as a caller token is (signature, `exp`, and `aud` and
+//! `iss` when configured). The gateway then checks that the links join up
+//! and that authority only narrows along them. Each link must be from the
+//! actor (`act.sub`) of the one before. It must grant no scope the one
+//! before lacks. The last must delegate to the source agent. A token that
+//! fails verification gets a 401; a chain that doesn't hold gets a 403.
+//!
+//! Policies see the verified chain as `input.delegation`:
+//!
+//! ```rego
+//! allow if {
+//!     count(input.delegation.chain) <= 3
+//!     input.capability in input.delegation.scopes
+//! }
+//! ```
+//!
+//! `principal` is the chain's originator, on whose authority the request
+//! is made. `scopes` are those the last link grants, and `chain` holds each
+//! link's `principal`, `actor`, `scopes`, `issuer` and `expires_at`.
+//! `input.delegation_chain` stays as the caller gave it. Delegation tokens
+//! are credentials, so decision capture leaves them out.
+
+use crate::auth::JwtVerifier;
+use crate::problem::Problem;
+use serde::Serialize;
+use serde_json::Value;
+
+/// Most links one chain may have
+const MAX_LINKS: usize = 10;
+
+/// A verified delegation chain
+#[derive(Debug, Clone, Serialize)]
+pub struct Delegation {
+    /// Whose authority the request is made on: the chain's originator
+    pub principal: String,
+    /// What the last link grants, which every earlier one covers
+    pub scopes: Vec<String>,
+    pub chain: Vec<Link>,
+}
+
+/// One delegation token's grant
+#[derive(Debug, Clone, Serialize)]
+pub struct Link {
+    /// Who delegates (`sub`)
+    pub principal: String,
+    /// Who is delegated to (`act.sub`)
+    pub actor: String,
+    /// What is delegated (`scope`, space-separated)
+    pub scopes: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub issuer: Option<String>,
+    pub expires_at: i64,
+}
+
+impl Link {
+    fn from_claims(claims: &Value) -> Result<Self, String> {
+        let principal = claims["sub"]
+            .as_str()
+            .filter(|sub| !sub.is_empty())
+            .ok_or("no sub claim")?;
+        let actor = claims["act"]["sub"]
+            .as_str()
+            .filter(|sub| !sub.is_empty())
+            .ok_or("no act.sub claim")?;
+        let scopes = claims["scope"].as_str().ok_or("no scope claim")?;
+        Ok(Self {
+            principal: principal.to_string(),
+            actor: actor.to_string(),
+            scopes: scopes.split_whitespace().map(str::to_string).collect(),
+            issuer: claims["iss"].as_str().map(str::to_string),
+            // Validation requires `exp`
+            expires_at: claims["exp"].as_i64().unwrap_or_default(),
+        })
+    }
+}
+
+/// Verify `tokens` as a chain delegating to `source_agent`, or `None` if
+/// there are none
+pub async fn resolve(
+    verifier: Option<&JwtVerifier>,
+    tokens: &[String],
+    source_agent: &str,
+) -> Result<Option<Delegation>, Problem> {
+    if tokens.is_empty() {
+        return Ok(None);
+    }
+    if tokens.len() > MAX_LINKS {
+        return Err(Problem::InvalidRequest(format!(
+            "A delegation chain has at most {} tokens",
+            MAX_LINKS
+        )));
+    }
+    let Some(verifier) = verifier else {
+        return Err(Problem::Unauthenticated(
+            "Token verification is not configured".to_string(),
+        ));
+    };
+
+    let mut chain: Vec<Link> = Vec::with_capacity(tokens.len());
+    for (i, token) in tokens.iter().enumerate() {
+        let claims = verifier
+            .verify_jwt(token)
+            .await
+            .map_err(|e| Problem::Unauthenticated(format!("Delegation token {}: {}", i, e)))?;
+        let link = Link::from_claims(&claims)
+            .map_err(|e| Problem::Unauthenticated(format!("Delegation token {}: {}", i, e)))?;
+        chain.push(link);
+    }
+    join(chain, source_agent).map(Some)
+}
+
+/// `chain`, verified links in order, as a delegation to `source_agent` if
+/// its links join up and only narrow
+fn join(chain: Vec<Link>, source_agent: &str) -> Result<Delegation, Problem> {
+    for (i, pair) in chain.windows(2).enumerate() {
+        let (previous, link, i) = (&pair[0], &pair[1], i + 1);
+        if link.principal != previous.actor {
+            return Err(Problem::Forbidden(format!(
+                "Delegation token {} is from {:?}, not {:?}, whom token {} delegates to",
+                i,
+                link.principal,
+                previous.actor,
+                i - 1
+            )));
+        }
+        if let Some(scope) = link
+            .scopes
+            .iter()
+            .find(|scope| !previous.scopes.contains(scope))
+        {
+            return Err(Problem::Forbidden(format!(
+                "Delegation token {} grants {:?}, which token {} does not",
+                i,
+                scope,
+                i - 1
+            )));
+        }
+    }
+
+    let last = chain.last().expect("chain has a link");
+    if last.actor != source_agent {
+        return Err(Problem::Forbidden(format!(
+            "Delegation chain ends at {:?}, not the source agent {:?}",
+            last.actor, source_agent
+        )));
+    }
+    Ok(Delegation {
+        principal: chain[0].principal.clone(),
+        scopes: last.scopes.clone(),
+        chain,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn link(principal: &str, actor: &str, scopes: &str) -> Link {
+        Link::from_claims(&json!({
+            "sub": principal,
+            "act": {"sub": actor},
+            "scope": scopes,
+            "iss": "https://idp.example.com",
+            "exp": 2_000_000_000,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn link_reads_the_grant_from_claims() {
+        let link = link("user-123", "agent-b", "query  execute");
+        assert_eq!(link.principal, "user-123");
+        assert_eq!(link.actor, "agent-b");
+        assert_eq!(link.scopes, ["query", "execute"]);
+        assert_eq!(link.issuer.as_deref(), Some("https://idp.example.com"));
+        assert_eq!(link.expires_at, 2_000_000_000);
+    }
+
+    #[test]
+    fn link_needs_sub_act_and_scope() {
+        let claims = json!({"sub": "u", "act": {"sub": "a"}, "scope": "q"});
+        assert!(Link::from_claims(&claims).is_ok());
+        for (claim, error) in [
+            ("/sub", "no sub claim"),
+            ("/act/sub", "no act.sub claim"),
+            ("/scope", "no scope claim"),
+        ] {
+            let mut missing = claims.clone();
+            *missing.pointer_mut(claim).unwrap() = Value::Null;
+            assert_eq!(Link::from_claims(&missing).unwrap_err(), error);
+            let mut empty = claims.clone();
+            *empty.pointer_mut(claim).unwrap() = json!("");
+            if claim != "/scope" {
+                assert_eq!(Link::from_claims(&empty).unwrap_err(), error);
+            }
+        }
+    }
+
+    #[test]
+    fn joined_chain_is_on_the_originators_authority() {
+        let delegation = join(
+            vec![
+                link("user-123", "agent-b", "query execute"),
+                link("agent-b", "agent-a", "query"),
+            ],
+            "agent-a",
+        )
+        .unwrap();
+        assert_eq!(delegation.principal, "user-123");
+        assert_eq!(delegation.scopes, ["query"]);
+        assert_eq!(delegation.chain.len(), 2);
+    }
+
+    #[test]
+    fn single_link_chain() {
+        let delegation = join(vec![link("user-123", "agent-a", "query")], "agent-a").unwrap();
+        assert_eq!(delegation.principal, "user-123");
+        assert_eq!(delegation.scopes, ["query"]);
+    }
+
+    #[test]
+    fn link_must_be_from_the_previous_actor() {
+        let error = join(
+            vec![
+                link("user-123", "agent-b", "query"),
+                link("agent-c", "agent-a", "query"),
+            ],
+            "agent-a",
+        )
+        .unwrap_err();
+        assert!(matches!(&error, Problem::Forbidden(message)
+            if message.contains("token 1 is from \"agent-c\", not \"agent-b\"")));
+    }
+
+    #[test]
+    fn scopes_may_only_narrow() {
+        let error = join(
+            vec![
+                link("user-123", "agent-b", "query"),
+                link("agent-b", "agent-a", "query execute"),
+            ],
+            "agent-a",
+        )
+        .unwrap_err();
+        assert!(matches!(&error, Problem::Forbidden(message)
+            if message.contains("grants \"execute\"")));
+
+        // The same scopes, or none at all, narrow nothing
+        for scopes in ["query", ""] {
+            let chain = vec![
+                link("user-123", "agent-b", "query"),
+                link("agent-b", "agent-a", scopes),
+            ];
+            assert!(join(chain, "agent-a").is_ok());
+        }
+    }
+
+    #[test]
+    fn chain_must_end_at_the_source_agent() {
+        let error = join(
+            vec![
+                link("user-123", "agent-b", "query"),
+                link("agent-b", "agent-a", "query"),
+            ],
+            "agent-b",
+        )
+        .unwrap_err();
+        assert!(matches!(&error, Problem::Forbidden(message)
+            if message.contains("ends at \"agent-a\"")));
+    }
+
+    #[tokio::test]
+    async fn no_tokens_no_delegation() {
+        assert!(resolve(None, &[], "agent-a").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn chain_length_is_capped() {
+        let tokens = vec!["token".to_string(); MAX_LINKS + 1];
+        assert!(matches!(
+            resolve(None, &tokens, "agent-a").await,
+            Err(Problem::InvalidRequest(_))
+        ));
+
+        // At the cap, it gets as far as verification
+        let tokens = vec!["token".to_string(); MAX_LINKS];
+        assert!(matches!(
+            resolve(None, &tokens, "agent-a").await,
+            Err(Problem::Unauthenticated(_))
+        ));
+    }
+}
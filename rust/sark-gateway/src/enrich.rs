@@ -0,0 +1,129 @@
+//! Network enrichment of policy input
+//!
+//! With MaxMind databases configured, each request's client address (see
+//! `clientip`) is looked up before evaluation and what they know of it is
+//! put in `input.context.network`, so policies can decide by where a
+//! request comes from without an enrichment proxy in front:
+//!
+//! ```toml
+//! [enrich]
+//! country_db = "/var/lib/GeoIP/GeoLite2-Country.mmdb"
+//! asn_db = "/var/lib/GeoIP/GeoLite2-ASN.mmdb"
+//! ```
+//!
+//! ```rego
+//! deny if {
+//!     input.resource.sensitivity == "high"
+//!     not input.context.network.asn in data.corporate_asns
+//! }
+//! ```
+//!
+//! `network` has `country` (ISO 3166 code, from a Country or City
+//! database) and `asn` and `as_org` (from an ASN database), each where the
+//! address is found. It replaces any `network` the caller sent, and is
+//! empty for callers without an address. Like the address, it is part of
+//! decisions' cache keys.
+//!
+//! The databases are read into memory at startup and again on every config
+//! reload, so a `geoipupdate` run takes effect on SIGHUP.
+
+use crate::config::EnrichConfig;
+use crate::mmdb::Reader;
+use anyhow::Result;
+use serde_json::{Map, Value};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+struct Database {
+    path: PathBuf,
+    reader: Reader,
+}
+
+impl Database {
+    fn open(path: &Path) -> Result<Self> {
+        let reader = Reader::open(path)?;
+        info!(
+            path = %path.display(),
+            database_type = %reader.database_type,
+            build_epoch = reader.build_epoch,
+            "Loaded MaxMind DB"
+        );
+        Ok(Self {
+            path: path.to_path_buf(),
+            reader,
+        })
+    }
+
+    /// The record for `ip`; lookup failures (a corrupt file) are logged
+    /// and count as not found
+    fn lookup(&self, ip: IpAddr) -> Option<Value> {
+        self.reader.lookup(ip).unwrap_or_else(|e| {
+            warn!(
+                path = %self.path.display(),
+                ip = %ip,
+                error = %format!("{:#}", e),
+                "MaxMind DB lookup failed"
+            );
+            None
+        })
+    }
+
+    fn build(&self) -> (&Path, u64) {
+        (&self.path, self.reader.build_epoch)
+    }
+}
+
+/// Looks client addresses up in the configured databases
+pub struct Enricher {
+    country: Option<Database>,
+    asn: Option<Database>,
+}
+
+impl Enricher {
+    pub fn load(config: &EnrichConfig) -> Result<Self> {
+        let open = |path: &Option<PathBuf>| path.as_deref().map(Database::open).transpose();
+        Ok(Self {
+            country: open(&config.country_db)?,
+            asn: open(&config.asn_db)?,
+        })
+    }
+
+    /// Whether `other` has the same builds of the same databases
+    pub fn same_as(&self, other: &Enricher) -> bool {
+        self.builds() == other.builds()
+    }
+
+    fn builds(&self) -> [Option<(&Path, u64)>; 2] {
+        [&self.country, &self.asn].map(|db| db.as_ref().map(Database::build))
+    }
+
+    /// Set `input.context.network` from what the databases know of
+    /// `client_ip`
+    pub fn enrich(&self, input: &mut Value, client_ip: Option<IpAddr>) {
+        if self.country.is_none() && self.asn.is_none() {
+            return;
+        }
+        let mut network = Map::new();
+        if let Some(ip) = client_ip {
+            let country = self.country.as_ref().and_then(|db| db.lookup(ip));
+            if let Some(code) = country
+                .as_ref()
+                .and_then(|r| r["country"]["iso_code"].as_str())
+            {
+                network.insert("country".to_string(), code.into());
+            }
+            if let Some(record) = self.asn.as_ref().and_then(|db| db.lookup(ip)) {
+                if let Some(asn) = record["autonomous_system_number"].as_u64() {
+                    network.insert("asn".to_string(), asn.into());
+                }
+                if let Some(org) = record["autonomous_system_organization"].as_str() {
+                    network.insert("as_org".to_string(), org.into());
+                }
+            }
+        }
+        if let Some(context) = crate::context_mut(input) {
+            context.insert("network".to_string(), Value::Object(network));
+        }
+    }
+}
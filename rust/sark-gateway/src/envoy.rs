@@ -0,0 +1,384 @@
+//! Envoy external authorization
+//!
+//! Serves `envoy.service.auth.v3.Authorization/Check` on the main listener,
+//! so the gateway can be configured directly as an ext_authz gRPC service:
+//!
+//! ```yaml
+//! http_filters:
+//!   - name: envoy.filters.http.ext_authz
+//!     typed_config:
+//!       "@type": type.googleapis.com/envoy.extensions.filters.http.ext_authz.v3.ExtAuthz
+//!       transport_api_version: V3
+//!       grpc_service:
+//!         envoy_grpc: { cluster_name: sark-gateway }
+//! ```
+//!
+//! Each check evaluates `data.envoy.authz` with the checked request's
+//! attributes as input, laid out as OPA's Envoy plugin does so its policies
+//! carry over: `input.attributes` (`source`, `destination`, `request.http`,
+//! `contextExtensions`), `input.parsed_path`, `input.parsed_query` and, for
+//! JSON bodies the filter buffered, `input.parsed_body`. A bearer token on
+//! the checked request is verified and its caller added as `input.user`; a
+//! request with an invalid token is denied with 401 without evaluating.
+//!
+//! `allow` decides. Optional rules shape the response:
+//!
+//! - `headers`: object of headers added upstream on allow, or to the
+//!   client response on deny
+//! - `request_headers_to_remove`: headers stripped before forwarding
+//! - `http_status` and `body`: the deny response (403 and `reason` by
+//!   default)
+//!
+//! Checks carry the whole request (ids, timestamps), so they are evaluated
+//! every time rather than cached. They appear in metrics and the decision
+//! and audit logs like other decisions; rate limits, request limits and
+//! in-flight shedding don't apply, but evaluations are still shed at the
+//! evaluation limit.
+
+use crate::audit::AuditRecord;
+use crate::auth::UserContext;
+use crate::AppState;
+use axum::http::{header::AUTHORIZATION, HeaderMap, HeaderName, HeaderValue, StatusCode};
+use serde_json::{json, Map, Value};
+use tonic::server::NamedService;
+use tonic::{Request, Response, Status};
+use tracing::{info, warn};
+
+pub mod pb {
+    pub mod envoy {
+        pub mod config {
+            pub mod core {
+                pub mod v3 {
+                    tonic::include_proto!("envoy.config.core.v3");
+                }
+            }
+        }
+        pub mod r#type {
+            pub mod v3 {
+                tonic::include_proto!("envoy.r#type.v3");
+            }
+        }
+        pub mod service {
+            pub mod auth {
+                pub mod v3 {
+                    tonic::include_proto!("envoy.service.auth.v3");
+                }
+            }
+        }
+    }
+    pub mod google {
+        pub mod rpc {
+            tonic::include_proto!("google.rpc");
+        }
+    }
+}
+
+use pb::envoy::config::core::v3 as core;
+use pb::envoy::service::auth::v3::{
+    attribute_context, authorization_server::Authorization,
+    authorization_server::AuthorizationServer, check_response::HttpResponse, AttributeContext,
+    CheckRequest, CheckResponse, DeniedHttpResponse, OkHttpResponse,
+};
+
+/// Package evaluated for ext_authz checks unless `queries.envoy` names
+/// another, read like the gateway packages (`data.envoy.authz.allow` decides)
+const QUERY: &str = "data.envoy.authz";
+
+/// gRPC method path, reported as the route in metrics and logs
+const ROUTE: &str = "/envoy.service.auth.v3.Authorization/Check";
+
+/// google.rpc.Code values
+const OK: i32 = 0;
+const PERMISSION_DENIED: i32 = 7;
+const UNAUTHENTICATED: i32 = 16;
+
+/// Route pattern the service is mounted under
+pub fn route() -> String {
+    format!("/{}/*rpc", AuthorizationServer::<Service>::NAME)
+}
+
+pub fn service(state: AppState) -> AuthorizationServer<Service> {
+    AuthorizationServer::new(Service { state })
+}
+
+pub struct Service {
+    state: AppState,
+}
+
+#[tonic::async_trait]
+impl Authorization for Service {
+    async fn check(
+        &self,
+        request: Request<CheckRequest>,
+    ) -> Result<Response<CheckResponse>, Status> {
+        let attributes = request.into_inner().attributes.unwrap_or_default();
+        let http = attributes
+            .request
+            .as_ref()
+            .and_then(|r| r.http.clone())
+            .unwrap_or_default();
+        info!(
+            method = %http.method,
+            host = %http.host,
+            path = %http.path,
+            "Envoy authorization check"
+        );
+
+        // Envoy lowercases header names and sets `x-request-id`
+        let checked: HeaderMap = http
+            .headers
+            .iter()
+            .filter_map(|(name, value)| {
+                Some((
+                    HeaderName::from_bytes(name.as_bytes()).ok()?,
+                    HeaderValue::from_str(value).ok()?,
+                ))
+            })
+            .collect();
+        let user = match self.verify(&checked).await {
+            Ok(user) => user,
+            Err(message) => {
+                self.state.metrics.decision(ROUTE, "", "deny");
+                return Ok(deny(
+                    UNAUTHENTICATED,
+                    StatusCode::UNAUTHORIZED,
+                    Vec::new(),
+                    message,
+                ));
+            }
+        };
+
+        let mut input = json!({
+            "attributes": attributes_input(&attributes),
+            "parsed_path": [],
+            "parsed_query": {},
+        });
+        if let Ok(url) = reqwest::Url::parse(&format!("http://localhost{}", http.path)) {
+            input["parsed_path"] = url
+                .path_segments()
+                .map(|segments| segments.filter(|s| !s.is_empty()).collect::<Vec<_>>())
+                .unwrap_or_default()
+                .into();
+            let mut query = Map::new();
+            for (key, value) in url.query_pairs() {
+                let values = query.entry(key.into_owned()).or_insert_with(|| json!([]));
+                if let Value::Array(values) = values {
+                    values.push(value.into_owned().into());
+                }
+            }
+            input["parsed_query"] = Value::Object(query);
+        }
+        let is_json = http
+            .headers
+            .get("content-type")
+            .is_some_and(|t| t.starts_with("application/json"));
+        if is_json {
+            if let Ok(body) = serde_json::from_str::<Value>(&http.body) {
+                input["parsed_body"] = body;
+            }
+        }
+        if let Some(user) = &user {
+            input["user"] = crate::user_input(user);
+        }
+
+        let request_id = crate::request_id(&checked);
+        let user_id = user.as_ref().map_or("anonymous", |u| u.user_id.as_str());
+        let audit = self.state.audited().then(|| {
+            AuditRecord::new(
+                request_id,
+                ROUTE,
+                user_id,
+                json!({ "method": http.method, "host": http.host, "path": http.path }),
+            )
+        });
+        self.decide(input, audit).await
+    }
+}
+
+impl Service {
+    /// The caller named by a bearer token on the checked request, if it
+    /// carries one
+    async fn verify(&self, headers: &HeaderMap) -> Result<Option<UserContext>, String> {
+        let bearer = headers
+            .get(AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.starts_with("Bearer "));
+        if !bearer {
+            return Ok(None);
+        }
+        let Some(jwt) = self.state.jwt() else {
+            return Err("Token verification is not configured".to_string());
+        };
+        let verified = match jwt.verify(headers).await {
+            Ok(mut user) => self
+                .state
+                .tenants
+                .resolve(&mut user, headers)
+                .map(|()| user),
+            Err(problem) => Err(problem),
+        };
+        match verified {
+            Ok(user) => Ok(Some(user)),
+            Err(problem) => {
+                warn!(error = %problem, "Rejected check with an invalid token or tenant");
+                Err(problem.to_string())
+            }
+        }
+    }
+
+    /// Evaluate the check and build Envoy's response from the policy's
+    /// document
+    async fn decide(
+        &self,
+        input: Value,
+        audit: Option<AuditRecord>,
+    ) -> Result<Response<CheckResponse>, Status> {
+        let query = self.state.queries.envoy.as_deref().unwrap_or(QUERY);
+        let (document, _) = crate::decide_uncached(&self.state, query, ROUTE, &input, audit)
+            .await
+            .map_err(crate::grpc::status)?;
+        let (allow, reason) = crate::verdict(&document);
+        info!(allow = allow, reason = %reason, "Authorization decision");
+        let headers = headers(document.get("headers"));
+        if allow {
+            let headers_to_remove = match document.get("request_headers_to_remove") {
+                Some(Value::Array(names)) => names
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .map(str::to_string)
+                    .collect(),
+                _ => Vec::new(),
+            };
+            return Ok(Response::new(CheckResponse {
+                status: Some(pb::google::rpc::Status {
+                    code: OK,
+                    ..Default::default()
+                }),
+                http_response: Some(HttpResponse::OkResponse(OkHttpResponse {
+                    headers,
+                    headers_to_remove,
+                })),
+            }));
+        }
+
+        let status = document
+            .get("http_status")
+            .and_then(Value::as_u64)
+            .and_then(|code| StatusCode::from_u16(code as u16).ok())
+            .unwrap_or(StatusCode::FORBIDDEN);
+        let body = match document.get("body") {
+            Some(Value::String(body)) => body.clone(),
+            Some(body) => body.to_string(),
+            None => reason,
+        };
+        Ok(deny(PERMISSION_DENIED, status, headers, body))
+    }
+}
+
+fn deny(
+    code: i32,
+    status: StatusCode,
+    headers: Vec<core::HeaderValueOption>,
+    body: String,
+) -> Response<CheckResponse> {
+    Response::new(CheckResponse {
+        status: Some(pb::google::rpc::Status {
+            code,
+            message: body.clone(),
+            ..Default::default()
+        }),
+        http_response: Some(HttpResponse::DeniedResponse(DeniedHttpResponse {
+            status: Some(pb::envoy::r#type::v3::HttpStatus {
+                code: status.as_u16().into(),
+            }),
+            headers,
+            body,
+        })),
+    })
+}
+
+/// Header options for a policy's `headers` object; values that aren't
+/// valid header names or values are dropped
+fn headers(headers: Option<&Value>) -> Vec<core::HeaderValueOption> {
+    let Some(Value::Object(headers)) = headers else {
+        return Vec::new();
+    };
+    headers
+        .iter()
+        .filter_map(|(key, value)| {
+            let value = match value {
+                Value::String(value) => value.clone(),
+                value => value.to_string(),
+            };
+            HeaderName::from_bytes(key.as_bytes()).ok()?;
+            HeaderValue::from_str(&value).ok()?;
+            Some(core::HeaderValueOption {
+                header: Some(core::HeaderValue {
+                    key: key.clone(),
+                    value,
+                }),
+            })
+        })
+        .collect()
+}
+
+/// Check attributes in the protobuf JSON layout OPA's Envoy plugin uses
+fn attributes_input(attributes: &AttributeContext) -> Value {
+    let peer = |peer: &Option<attribute_context::Peer>| {
+        let Some(peer) = peer else {
+            return Value::Null;
+        };
+        let address = match peer.address.as_ref().and_then(|a| a.address.as_ref()) {
+            Some(core::address::Address::SocketAddress(socket)) => {
+                let mut address = json!({ "address": socket.address });
+                match &socket.port_specifier {
+                    Some(core::socket_address::PortSpecifier::PortValue(port)) => {
+                        address["portValue"] = (*port).into();
+                    }
+                    Some(core::socket_address::PortSpecifier::NamedPort(port)) => {
+                        address["namedPort"] = port.clone().into();
+                    }
+                    None => {}
+                }
+                json!({ "socketAddress": address })
+            }
+            Some(core::address::Address::Pipe(pipe)) => json!({ "pipe": { "path": pipe.path } }),
+            None => Value::Null,
+        };
+        json!({
+            "address": address,
+            "service": peer.service,
+            "labels": peer.labels,
+            "principal": peer.principal,
+            "certificate": peer.certificate,
+        })
+    };
+
+    let request = attributes.request.as_ref();
+    let http = request.and_then(|r| r.http.as_ref()).map(|http| {
+        json!({
+            "id": http.id,
+            "method": http.method,
+            "headers": http.headers,
+            "path": http.path,
+            "host": http.host,
+            "scheme": http.scheme,
+            "query": http.query,
+            "fragment": http.fragment,
+            "size": http.size,
+            "protocol": http.protocol,
+            "body": http.body,
+        })
+    });
+    let time = request
+        .and_then(|r| r.time.as_ref())
+        .and_then(|t| chrono::DateTime::from_timestamp(t.seconds, t.nanos.max(0) as u32))
+        .map(|t| t.to_rfc3339());
+
+    json!({
+        "source": peer(&attributes.source),
+        "destination": peer(&attributes.destination),
+        "request": { "time": time, "http": http },
+        "contextExtensions": attributes.context_extensions,
+    })
+}
@@ -0,0 +1,113 @@
+//! Live decision events
+//!
+//! `GET /admin/events` on the admin port streams decisions as they are
+//! made, as server-sent events, for watching a replica during an incident
+//! without tailing its logs. Each `decision` event's data is the decision's
+//! audit record (whether or not `--audit-log` is set). Query parameters
+//! narrow the stream:
+//!
+//! - `user=<id>` - decisions for this caller only
+//! - `server=<name>` - decisions on this MCP server's tools only
+//! - `deny_only=true` - denials and failed evaluations only
+//!
+//! ```text
+//! curl -N -H "Authorization: Bearer $ADMIN_TOKEN" \
+//!     'http://127.0.0.1:9090/admin/events?deny_only=true'
+//! ```
+//!
+//! Events are only gathered while someone is subscribed, and never hold up
+//! requests: a subscriber that falls more than a buffer's worth behind
+//! misses events and is told how many with a `lagged` event. Streams cover
+//! this replica's decisions only.
+
+use crate::audit::AuditRecord;
+use crate::problem::Problem;
+use crate::AppState;
+use axum::{
+    extract::{rejection::QueryRejection, Query, State},
+    response::sse::{Event, KeepAlive, Sse},
+};
+use futures::Stream;
+use serde::Deserialize;
+use std::convert::Infallible;
+use std::sync::Arc;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::info;
+
+/// Route of the event stream, on the admin port
+pub const ROUTE: &str = "/admin/events";
+
+/// Events buffered for each subscriber before it starts missing them
+const BUFFER: usize = 1024;
+
+/// Publishes decisions to subscribed streams
+pub struct Events {
+    tx: broadcast::Sender<Arc<AuditRecord>>,
+}
+
+impl Events {
+    pub fn new() -> Self {
+        Self {
+            tx: broadcast::channel(BUFFER).0,
+        }
+    }
+
+    /// Whether any stream is subscribed, so decisions need recording
+    pub fn watched(&self) -> bool {
+        self.tx.receiver_count() > 0
+    }
+
+    pub fn publish(&self, record: &AuditRecord) {
+        if self.watched() {
+            let _ = self.tx.send(Arc::new(record.clone()));
+        }
+    }
+}
+
+/// Which decisions a stream carries
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Filter {
+    user: Option<String>,
+    server: Option<String>,
+    #[serde(default)]
+    deny_only: bool,
+}
+
+impl Filter {
+    fn matches(&self, record: &AuditRecord) -> bool {
+        self.user.as_ref().map_or(true, |user| *user == record.user)
+            && self
+                .server
+                .as_ref()
+                .map_or(true, |server| record.resource["server"] == **server)
+            && !(self.deny_only && record.decision == "allow")
+    }
+}
+
+/// `GET /admin/events`: stream decisions matching the query's filter
+pub async fn subscribe(
+    State(state): State<AppState>,
+    filter: Result<Query<Filter>, QueryRejection>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, Problem> {
+    let Query(filter) = filter.map_err(|e| Problem::InvalidRequest(e.body_text()))?;
+    info!(filter = ?filter, "Decision event stream opened");
+    let rx = state.events.tx.subscribe();
+    let stream = futures::stream::unfold((rx, filter), |(mut rx, filter)| async move {
+        loop {
+            let event = match rx.recv().await {
+                Ok(record) if filter.matches(&record) => Event::default()
+                    .event("decision")
+                    .json_data(&*record)
+                    .expect("audit records serialize"),
+                Ok(_) => continue,
+                Err(RecvError::Lagged(missed)) => Event::default()
+                    .event("lagged")
+                    .data(format!("{{\"missed\":{}}}", missed)),
+                Err(RecvError::Closed) => return None,
+            };
+            return Some((Ok(event), (rx, filter)));
+        }
+    });
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
@@ -0,0 +1,264 @@
+//! Decisions when local evaluation fails
+//!
+//! When a gateway authorization can't be evaluated locally (the engine
+//! errors or times out, or no policies are loaded), it can be asked of the SARK API's
+//! `POST /api/v1/gateway/authorize` instead, with the caller's token and
+//! request id forwarded. A circuit breaker stops asking once the API fails
+//! `failure_threshold` times in a row, and lets a single trial request
+//! through after `open_duration` to see whether it has recovered.
+//!
+//! Without an answer from either (no URL, circuit open, API down or
+//! refusing the request), `failure_mode` decides by the request's
+//! sensitivity level: `open` allows it, `closed` (the default) fails it
+//! with the evaluation's problem as before.
+//!
+//! ```toml
+//! [fallback]
+//! url = "http://sark-api:8000"
+//!
+//! [fallback.failure_mode]
+//! low = "open"
+//! ```
+//!
+//! Fallback decisions aren't cached, so the local policy takes over again
+//! as soon as it evaluates, and carry `policy_revision = "fallback"` in
+//! responses and logs. Tenants' requests aren't asked of the API, which
+//! doesn't know their policies, but are settled by `failure_mode` all the
+//! same. Agent-to-agent requests fail as before.
+
+use crate::config::{FailureMode, FallbackConfig};
+use crate::metrics::Metrics;
+use crate::problem::Problem;
+use crate::GatewayAuthResponse;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+/// Revision reported for decisions not made by the local policy
+const REVISION: &str = "fallback";
+
+/// A decision to settle without the local policy
+pub struct Request {
+    token: String,
+    request_id: String,
+    body: serde_json::Value,
+    sensitivity: String,
+    /// Whether the API may be asked
+    remote: bool,
+}
+
+impl Request {
+    /// The API request for a gateway authorization's policy input
+    pub fn new(token: &str, request_id: &str, input: &serde_json::Value) -> Self {
+        let or_empty = |value: &serde_json::Value| match value {
+            serde_json::Value::Null => serde_json::json!({}),
+            value => value.clone(),
+        };
+        let sensitivity = &input["resource"]["sensitivity"];
+        Self {
+            token: token.to_string(),
+            request_id: request_id.to_string(),
+            body: serde_json::json!({
+                "action": input["action"],
+                "server_name": input["resource"]["server"],
+                "tool_name": input["resource"]["tool"],
+                "sensitivity_level": sensitivity,
+                "parameters": or_empty(&input["parameters"]),
+                "context": or_empty(&input["context"]),
+            }),
+            sensitivity: sensitivity.as_str().unwrap_or_default().to_string(),
+            // API key callers have no token for the API to check
+            remote: !token.is_empty(),
+        }
+    }
+
+    /// The request, to be settled by `failure_mode` alone
+    pub fn local(self) -> Self {
+        Self {
+            remote: false,
+            ..self
+        }
+    }
+}
+
+/// The API's decision
+#[derive(Deserialize)]
+struct RemoteDecision {
+    allow: bool,
+    reason: String,
+    filtered_parameters: Option<serde_json::Value>,
+}
+
+#[derive(Default)]
+struct Circuit {
+    failures: u32,
+    /// Set while open
+    open_until: Option<Instant>,
+    /// Whether the trial request after opening is out
+    probing: bool,
+}
+
+pub struct Fallback {
+    client: reqwest::Client,
+    /// `.../api/v1/gateway/authorize`, if configured
+    url: Option<String>,
+    failure_threshold: u32,
+    open_duration: Duration,
+    failure_mode: HashMap<String, FailureMode>,
+    circuit: Mutex<Circuit>,
+}
+
+impl Fallback {
+    /// The fallback `config` describes, if it has anything to do
+    pub fn new(config: &FallbackConfig) -> Result<Option<Self>> {
+        if config.url.is_none() && config.failure_mode.is_empty() {
+            return Ok(None);
+        }
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout))
+            .build()
+            .context("Failed to build fallback HTTP client")?;
+        Ok(Some(Self {
+            client,
+            url: config
+                .url
+                .as_ref()
+                .map(|url| format!("{}/api/v1/gateway/authorize", url.trim_end_matches('/'))),
+            failure_threshold: config.failure_threshold,
+            open_duration: Duration::from_secs(config.open_duration),
+            failure_mode: config.failure_mode.clone(),
+            circuit: Mutex::new(Circuit::default()),
+        }))
+    }
+
+    /// Settle `request`, which local evaluation failed with `problem`
+    pub async fn decide(
+        &self,
+        metrics: &Metrics,
+        request: Request,
+        problem: Problem,
+    ) -> Result<GatewayAuthResponse, Problem> {
+        if let Some(url) = self
+            .url
+            .as_deref()
+            .filter(|_| request.remote && self.try_acquire())
+        {
+            match self.ask(url, &request).await {
+                Ok(Some(decision)) => {
+                    self.succeeded();
+                    metrics.fallback("remote");
+                    info!(allow = decision.allow, reason = %decision.reason, "Fallback decision");
+                    return Ok(GatewayAuthResponse {
+                        allow: decision.allow,
+                        reason: decision.reason,
+                        reason_code: None,
+                        filtered_parameters: decision.filtered_parameters,
+                        obligations: None,
+                        cache_ttl: 0,
+                        policy_revision: REVISION.to_string(),
+                        dry_run: false,
+                        cached: false,
+                    });
+                }
+                // Reachable but unwilling; no reason to stop asking
+                Ok(None) => {
+                    self.succeeded();
+                    metrics.fallback("rejected");
+                }
+                Err(e) => {
+                    warn!(error = %format!("{:#}", e), "Fallback decision failed");
+                    self.failed();
+                }
+            }
+        }
+
+        match self.failure_mode.get(&request.sensitivity) {
+            Some(FailureMode::Open) => {
+                metrics.fallback("fail_open");
+                warn!(sensitivity = %request.sensitivity, error = %problem, "Failing open");
+                Ok(GatewayAuthResponse {
+                    allow: true,
+                    reason: format!("Policy evaluation unavailable; failing open: {}", problem),
+                    reason_code: None,
+                    filtered_parameters: None,
+                    obligations: None,
+                    cache_ttl: 0,
+                    policy_revision: REVISION.to_string(),
+                    dry_run: false,
+                    cached: false,
+                })
+            }
+            _ => {
+                metrics.fallback("fail_closed");
+                Err(problem)
+            }
+        }
+    }
+
+    /// The API's decision, or `None` if it refused the request (4xx)
+    async fn ask(&self, url: &str, request: &Request) -> Result<Option<RemoteDecision>> {
+        let response = self
+            .client
+            .post(url)
+            .bearer_auth(&request.token)
+            .header(crate::REQUEST_ID_HEADER, &request.request_id)
+            .json(&request.body)
+            .send()
+            .await
+            .context("SARK API unreachable")?;
+
+        let status = response.status();
+        if status.is_client_error() {
+            warn!(status = %status, "SARK API refused the fallback request");
+            return Ok(None);
+        }
+        let decision = response
+            .error_for_status()
+            .context("SARK API failed")?
+            .json()
+            .await
+            .context("Invalid decision from SARK API")?;
+        Ok(Some(decision))
+    }
+
+    /// Whether the circuit lets a request through
+    fn try_acquire(&self) -> bool {
+        let mut circuit = self.circuit.lock().expect("circuit lock poisoned");
+        match circuit.open_until {
+            None => true,
+            Some(until) if Instant::now() < until || circuit.probing => {
+                debug!("Fallback circuit open");
+                false
+            }
+            Some(_) => {
+                circuit.probing = true;
+                true
+            }
+        }
+    }
+
+    fn succeeded(&self) {
+        let mut circuit = self.circuit.lock().expect("circuit lock poisoned");
+        if circuit.open_until.is_some() {
+            info!("SARK API recovered; fallback circuit closed");
+        }
+        *circuit = Circuit::default();
+    }
+
+    fn failed(&self) {
+        let mut circuit = self.circuit.lock().expect("circuit lock poisoned");
+        circuit.failures += 1;
+        if circuit.probing || circuit.failures >= self.failure_threshold {
+            warn!(
+                failures = circuit.failures,
+                open_secs = self.open_duration.as_secs(),
+                "Fallback circuit opened"
+            );
+            circuit.open_until = Some(Instant::now() + self.open_duration);
+            circuit.probing = false;
+        }
+    }
+}
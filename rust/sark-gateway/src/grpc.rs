@@ -0,0 +1,241 @@
+//! gRPC authorization API
+//!
+//! `sark.gateway.v1.Authorization` (`proto/sark/gateway/v1/authorization.proto`)
+//! serves the same decisions as `/gateway/authorize` and
+//! `/gateway/authorize-a2a`, through the same cache, policy engine, audit
+//! and decision logs. It is mounted on the main listener, so callers reach
+//! it over HTTP/2 on the same port (h2c without TLS). The token goes in
+//! `authorization: Bearer <token>` metadata.
+//!
+//! Rate limits, request limits and in-flight shedding are configured for
+//! the HTTP routes; gRPC calls are still shed at the evaluation limit.
+
+use crate::auth::UserContext;
+use crate::clientip::ClientIp;
+use crate::problem::Problem;
+use crate::tls::ClientIdentity;
+use crate::{AppState, GatewayAuthRequest, GatewayAuthResponse};
+use prost_types::value::Kind;
+use sark_context::RequestContext;
+use tonic::server::NamedService;
+use tonic::{Request, Response, Status};
+
+pub mod pb {
+    tonic::include_proto!("sark.gateway.v1");
+}
+
+use pb::authorization_server::{Authorization, AuthorizationServer};
+
+/// Route pattern the service is mounted under
+pub fn route() -> String {
+    format!("/{}/*rpc", AuthorizationServer::<Service>::NAME)
+}
+
+pub fn service(state: AppState) -> AuthorizationServer<Service> {
+    AuthorizationServer::new(Service { state })
+}
+
+pub struct Service {
+    state: AppState,
+}
+
+impl Service {
+    /// The verified caller, its client certificate (with mutual TLS), its
+    /// address and the request id
+    async fn caller<T>(
+        &self,
+        request: &Request<T>,
+    ) -> Result<(UserContext, Option<ClientIdentity>, RequestContext, String), Status> {
+        let headers = request.metadata().clone().into_headers();
+        let user = crate::authenticate(&self.state, None, &headers)
+            .await
+            .map_err(status)?;
+        let client = request.extensions().get::<ClientIdentity>().cloned();
+        let client_ip = request
+            .extensions()
+            .get::<ClientIp>()
+            .map(|ClientIp(ip)| *ip);
+        let context = crate::request_context(&headers, client_ip, &user);
+        Ok((user, client, context, crate::request_id(&headers)))
+    }
+
+    /// The response for `decision`, its reason localized for
+    /// `accept_language` (see `reasons`)
+    fn response(
+        &self,
+        mut decision: GatewayAuthResponse,
+        accept_language: Option<&str>,
+    ) -> Response<pb::AuthorizeResponse> {
+        self.state
+            .reasons()
+            .localize(&mut decision, accept_language);
+        Response::new(pb::AuthorizeResponse {
+            allow: decision.allow,
+            reason: decision.reason,
+            filtered_parameters: decision.filtered_parameters.map(from_json),
+            obligations: decision
+                .obligations
+                .map(|o| from_json(serde_json::to_value(o).expect("obligations serialize"))),
+            cache_ttl: decision.cache_ttl,
+            policy_revision: decision.policy_revision,
+            reason_code: decision.reason_code.unwrap_or_default(),
+        })
+    }
+}
+
+#[tonic::async_trait]
+impl Authorization for Service {
+    async fn authorize(
+        &self,
+        request: Request<pb::AuthorizeRequest>,
+    ) -> Result<Response<pb::AuthorizeResponse>, Status> {
+        let (user, client, context, request_id) = self.caller(&request).await?;
+        let accept_language = accept_language(&request);
+        let request = request.into_inner();
+        let request = GatewayAuthRequest {
+            action: request.action,
+            server_name: request.server_name,
+            tool_name: request.tool_name,
+            parameters: request.parameters.map(to_json),
+            context: request.context.map(to_json),
+            sensitivity_level: request.sensitivity_level,
+            dry_run: false,
+        };
+
+        crate::authorize_request(
+            &self.state,
+            &user,
+            client.as_ref(),
+            &context,
+            request_id,
+            request,
+        )
+        .await
+        .map(|decision| self.response(decision, accept_language.as_deref()))
+        .map_err(status)
+    }
+
+    async fn authorize_a2a(
+        &self,
+        request: Request<pb::AuthorizeA2aRequest>,
+    ) -> Result<Response<pb::AuthorizeResponse>, Status> {
+        let (user, client, context, request_id) = self.caller(&request).await?;
+        let accept_language = accept_language(&request);
+        let request = request.into_inner();
+        let agent = |agent: Option<pb::AgentIdentity>, field: &str| {
+            let agent =
+                agent.ok_or_else(|| Status::invalid_argument(format!("{} is required", field)))?;
+            Ok::<_, Status>(crate::AgentIdentity {
+                id: agent.id,
+                agent_type: agent.r#type,
+                trust_level: agent.trust_level,
+                capabilities: agent.capabilities,
+                environment: agent.environment,
+            })
+        };
+        let request = crate::A2AAuthRequest {
+            source_agent: agent(request.source_agent, "source_agent")?,
+            target_agent: agent(request.target_agent, "target_agent")?,
+            capability: request.capability,
+            delegation_chain: request.delegation_chain,
+            delegation_tokens: request.delegation_tokens,
+            parameters: request.parameters.map(to_json),
+            context: request.context.map(to_json),
+        };
+
+        crate::authorize_a2a_request(
+            &self.state,
+            &user,
+            client.as_ref(),
+            &context,
+            request_id,
+            request,
+        )
+        .await
+        .map(|decision| self.response(decision, accept_language.as_deref()))
+        .map_err(status)
+    }
+}
+
+/// The caller's `accept-language` metadata
+fn accept_language<T>(request: &Request<T>) -> Option<String> {
+    request
+        .metadata()
+        .get("accept-language")
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+}
+
+/// The gRPC status for an HTTP handler error
+pub fn status(problem: Problem) -> Status {
+    let message = problem.to_string();
+    match problem {
+        Problem::Unauthenticated(_) | Problem::StepUpRequired { .. } => {
+            Status::unauthenticated(message)
+        }
+        Problem::Forbidden(_) => Status::permission_denied(message),
+        Problem::InvalidRequest(_) | Problem::UnsupportedMediaType(_) => {
+            Status::invalid_argument(message)
+        }
+        Problem::PayloadTooLarge(_) => Status::out_of_range(message),
+        Problem::RequestTimeout(_) | Problem::EvaluationTimeout(_) => {
+            Status::deadline_exceeded(message)
+        }
+        Problem::NotFound(_) => Status::not_found(message),
+        Problem::Conflict(_) | Problem::InvalidPolicy(_) | Problem::InvalidConfig(_) => {
+            Status::failed_precondition(message)
+        }
+        Problem::RateLimited(_) | Problem::QuotaExceeded { .. } => {
+            Status::resource_exhausted(message)
+        }
+        Problem::Overloaded(_) | Problem::Upstream(_) => Status::unavailable(message),
+        Problem::PolicyEvaluation(_)
+        | Problem::MalformedResult(_)
+        | Problem::Unredactable(_)
+        | Problem::Internal(_) => Status::internal(message),
+    }
+}
+
+/// Protobuf numbers are doubles; whole ones come back as JSON integers so
+/// policies and cache keys see the same input as over HTTP
+fn to_json(value: prost_types::Value) -> serde_json::Value {
+    use serde_json::Value as Json;
+
+    match value.kind {
+        None | Some(Kind::NullValue(_)) => Json::Null,
+        Some(Kind::BoolValue(b)) => Json::Bool(b),
+        Some(Kind::NumberValue(n)) if n.fract() == 0.0 && n.abs() < i64::MAX as f64 => {
+            Json::from(n as i64)
+        }
+        Some(Kind::NumberValue(n)) => {
+            serde_json::Number::from_f64(n).map_or(Json::Null, Json::Number)
+        }
+        Some(Kind::StringValue(s)) => Json::String(s),
+        Some(Kind::ListValue(list)) => Json::Array(list.values.into_iter().map(to_json).collect()),
+        Some(Kind::StructValue(object)) => Json::Object(
+            object
+                .fields
+                .into_iter()
+                .map(|(k, v)| (k, to_json(v)))
+                .collect(),
+        ),
+    }
+}
+
+fn from_json(value: serde_json::Value) -> prost_types::Value {
+    use serde_json::Value as Json;
+
+    let kind = match value {
+        Json::Null => Kind::NullValue(0),
+        Json::Bool(b) => Kind::BoolValue(b),
+        Json::Number(n) => Kind::NumberValue(n.as_f64().unwrap_or_default()),
+        Json::String(s) => Kind::StringValue(s),
+        Json::Array(items) => Kind::ListValue(prost_types::ListValue {
+            values: items.into_iter().map(from_json).collect(),
+        }),
+        Json::Object(object) => Kind::StructValue(prost_types::Struct {
+            fields: object.into_iter().map(|(k, v)| (k, from_json(v))).collect(),
+        }),
+    };
+    prost_types::Value { kind: Some(kind) }
+}
@@ -0,0 +1,288 @@
+//! Health checks
+//!
+//! `GET /health` checks the compiled policies, the bundle source, data
+//! sources and JWKS (where configured), cache occupancy and the Redis L2
+//! (where configured), and reports the worst as `status`: `unhealthy`
+//! (with 503) without policies, a first bundle sync or signing keys;
+//! `degraded` after a failed bundle sync, data refresh or JWKS refetch,
+//! before a data source's first refresh, or with Redis unreachable. `build` says
+//! what is deployed, as `sark_rust.build_info()` does for the Python side.
+//!
+//! The same checks back `grpc.health.v1.Health`
+//! (`proto/grpc/health/v1/health.proto`), mounted on the main listener
+//! beside the gRPC APIs for load balancers and Kubernetes `grpc` probes:
+//!
+//! ```yaml
+//! livenessProbe:
+//!   grpc:
+//!     port: 8080
+//! ```
+//!
+//! The server as a whole (`""`) and each gRPC service it serves are
+//! `SERVING` unless the gateway is unhealthy; degraded still serves. Other
+//! service names are `NOT_FOUND` to `Check` and `SERVICE_UNKNOWN` to
+//! `Watch`, which sends the status and then each change to it. Probes go
+//! over HTTP/2, so they need `connections.http2`.
+
+use crate::AppState;
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use futures::stream::{self, Stream};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+use tonic::server::NamedService;
+use tonic::{Request, Response, Status};
+
+pub mod pb {
+    tonic::include_proto!("grpc.health.v1");
+}
+
+use pb::health_check_response::ServingStatus;
+use pb::health_server::{Health as HealthRpc, HealthServer};
+use pb::{HealthCheckRequest, HealthCheckResponse};
+
+/// Route pattern the gRPC health service is mounted under
+pub const GRPC_ROUTE: &str = "/grpc.health.v1.Health/*rpc";
+
+/// Longest a health check waits for Redis to answer
+const REDIS_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How often `Watch` checks again for a change
+const WATCH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Health of one dependency, or of the gateway as a whole (the worst of
+/// its checks)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Health {
+    Healthy,
+    /// Serving, but something it relies on is failing (decisions may be
+    /// stale or slower)
+    Degraded,
+    /// Unable to make real decisions
+    Unhealthy,
+}
+
+/// The gateway's health, and each check's status and detail by name
+pub async fn check(state: &AppState) -> (Health, Map<String, Value>) {
+    let mut checks = Map::new();
+    let mut check = |name: &str, health: Health, mut detail: Value| {
+        detail["status"] = json!(health);
+        checks.insert(name.to_string(), detail);
+        health
+    };
+
+    let (revision, modules) = {
+        let policy = state.policy.lock().await;
+        let active = policy.active();
+        (active.revision().to_string(), active.set.modules().count())
+    };
+    let mut status = check(
+        "policies",
+        if modules > 0 {
+            Health::Healthy
+        } else {
+            Health::Unhealthy
+        },
+        json!({ "revision": revision, "modules": modules }),
+    );
+
+    let bundle = state
+        .bundle
+        .read()
+        .expect("bundle lock poisoned")
+        .as_ref()
+        .map(|status| status.report());
+    if let Some(bundle) = bundle {
+        let health = match (&bundle.last_sync, &bundle.last_error) {
+            (None, _) => Health::Unhealthy,
+            (Some(_), Some(_)) => Health::Degraded,
+            (Some(_), None) => Health::Healthy,
+        };
+        status = status.max(check("bundle", health, json!(bundle)));
+    }
+
+    // Policies still decide with the last good data, so a stale source
+    // only degrades
+    for (name, source) in state.data_sources.iter() {
+        let report = source.report();
+        let health = match (&report.last_sync, &report.last_error) {
+            (Some(_), None) => Health::Healthy,
+            _ => Health::Degraded,
+        };
+        status = status.max(check(&format!("data:{}", name), health, json!(report)));
+    }
+
+    if let Some(jwt) = state.jwt().filter(|jwt| jwt.uses_jwks()) {
+        let keys = jwt.key_status().await;
+        let health = match (keys.keys, &keys.last_error) {
+            // Opaque tokens can still be introspected
+            (0, _) if !jwt.introspects() => Health::Unhealthy,
+            (0, _) | (_, Some(_)) => Health::Degraded,
+            _ => Health::Healthy,
+        };
+        let age = keys
+            .synced_at
+            .map(|at| (chrono::Utc::now() - at).num_seconds().max(0));
+        let mut detail = json!(keys);
+        detail["age_secs"] = json!(age);
+        status = status.max(check("jwks", health, detail));
+    }
+
+    let entries = state.cache.size();
+    check(
+        "cache",
+        Health::Healthy,
+        json!({
+            "entries": entries,
+            "capacity": state.cache_capacity,
+            "occupancy": entries as f64 / state.cache_capacity as f64,
+        }),
+    );
+
+    if let Some(l2) = state.decisions.l2() {
+        let started = Instant::now();
+        let health = match l2.ping(REDIS_TIMEOUT).await {
+            Ok(()) => check(
+                "redis",
+                Health::Healthy,
+                json!({ "latency_ms": started.elapsed().as_secs_f64() * 1000.0 }),
+            ),
+            // Decisions fall back to the in-process cache
+            Err(e) => check(
+                "redis",
+                Health::Degraded,
+                json!({ "error": format!("{:#}", e) }),
+            ),
+        };
+        status = status.max(health);
+    }
+
+    (status, checks)
+}
+
+/// Version, commit, profile and capabilities of this binary
+pub fn build_info() -> sark_build::BuildInfo {
+    sark_build::build_info! {
+        "redis" => true,
+        "wasm" => false,
+        "tracing" => true,
+        "grpc" => true,
+        "tls" => true,
+        "cel" => true,
+    }
+}
+
+/// Health check endpoint
+pub async fn health(State(state): State<AppState>) -> impl IntoResponse {
+    let (status, checks) = check(&state).await;
+    let code = match status {
+        Health::Unhealthy => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::OK,
+    };
+    (
+        code,
+        Json(json!({
+            "status": status,
+            "service": "sark-gateway",
+            "version": env!("CARGO_PKG_VERSION"),
+            "implementation": "rust",
+            "build": build_info(),
+            "policy": {
+                "revision": checks["policies"]["revision"],
+            },
+            "shadow": match &state.shadow {
+                Some(shadow) => Some(shadow.stats().await),
+                None => None,
+            },
+            "decision_log": state.decision_log.as_ref().map(|log| json!({
+                "dropped": log.dropped(),
+            })),
+            "cache": {
+                "entries": checks["cache"]["entries"],
+                "namespaces": {
+                    state.decisions.name(): state.decisions.stats(),
+                    state.a2a_decisions.name(): state.a2a_decisions.stats(),
+                },
+            },
+            "checks": checks,
+        })),
+    )
+}
+
+pub fn grpc_service(state: AppState) -> HealthServer<GrpcHealth> {
+    HealthServer::new(GrpcHealth { state })
+}
+
+pub struct GrpcHealth {
+    state: AppState,
+}
+
+/// Whether `service` names the server (`""`) or a service it serves
+fn known(service: &str) -> bool {
+    [
+        "",
+        crate::grpc::pb::authorization_server::AuthorizationServer::<crate::grpc::Service>::NAME,
+        crate::envoy::pb::envoy::service::auth::v3::authorization_server::AuthorizationServer::<
+            crate::envoy::Service,
+        >::NAME,
+        HealthServer::<GrpcHealth>::NAME,
+    ]
+    .contains(&service)
+}
+
+/// `service`'s status, given the gateway is in `health`
+fn serving(service: &str, health: Health) -> ServingStatus {
+    match (known(service), health) {
+        (false, _) => ServingStatus::ServiceUnknown,
+        (true, Health::Unhealthy) => ServingStatus::NotServing,
+        (true, _) => ServingStatus::Serving,
+    }
+}
+
+fn response(status: ServingStatus) -> HealthCheckResponse {
+    HealthCheckResponse {
+        status: status.into(),
+    }
+}
+
+#[tonic::async_trait]
+impl HealthRpc for GrpcHealth {
+    async fn check(
+        &self,
+        request: Request<HealthCheckRequest>,
+    ) -> Result<Response<HealthCheckResponse>, Status> {
+        let service = request.into_inner().service;
+        if !known(&service) {
+            return Err(Status::not_found(format!("Unknown service {:?}", service)));
+        }
+        let (health, _) = check(&self.state).await;
+        Ok(Response::new(response(serving(&service, health))))
+    }
+
+    type WatchStream =
+        Pin<Box<dyn Stream<Item = Result<HealthCheckResponse, Status>> + Send + 'static>>;
+
+    async fn watch(
+        &self,
+        request: Request<HealthCheckRequest>,
+    ) -> Result<Response<Self::WatchStream>, Status> {
+        let service = request.into_inner().service;
+        let updates = stream::unfold(
+            (self.state.clone(), service, None),
+            |(state, service, last)| async move {
+                loop {
+                    if last.is_some() {
+                        tokio::time::sleep(WATCH_INTERVAL).await;
+                    }
+                    let status = serving(&service, check(&state).await.0);
+                    if Some(status) != last {
+                        return Some((Ok(response(status)), (state, service, Some(status))));
+                    }
+                }
+            },
+        );
+        Ok(Response::new(Box::pin(updates)))
+    }
+}
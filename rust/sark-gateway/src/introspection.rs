@@ -0,0 +1,156 @@
+//! Token introspection
+//!
+//! Opaque access tokens can't be verified locally. With an RFC 7662
+//! introspection endpoint configured, bearer tokens that aren't JWTs (or
+//! every token, without a JWKS URL) are posted to it and the caller is
+//! built from its response, through the same `[claims]` mapping as a JWT's
+//! claims:
+//!
+//! ```toml
+//! [jwt.introspection]
+//! url = "https://idp.example.com/oauth2/introspect"
+//! client_id = "sark-gateway"
+//! client_secret = "..."
+//! cache_ttl = 30
+//! ```
+//!
+//! Tokens the endpoint reports inactive, expired or (when `jwt.audience` or
+//! `jwt.issuer` are set) for someone else are refused with 401, as is every
+//! token while the endpoint can't be reached. Active results are cached by
+//! the token's SHA-256 for `cache_ttl` seconds, never past the token's
+//! `exp`, so a revoked token may be accepted for up to that long.
+
+use crate::config::IntrospectionConfig;
+use crate::problem::Problem;
+use anyhow::{bail, Context, Result};
+use grid_cache::LRUTTLCache;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+use tracing::{debug, warn};
+
+pub struct Introspector {
+    client: reqwest::Client,
+    url: String,
+    /// Client id and secret, if the endpoint wants them
+    credentials: Option<(String, String)>,
+    audience: Option<String>,
+    issuer: Option<String>,
+    cache: LRUTTLCache,
+    cache_ttl: u64,
+}
+
+impl Introspector {
+    /// The introspector `config` describes, if it names an endpoint
+    pub fn new(
+        config: &IntrospectionConfig,
+        audience: Option<String>,
+        issuer: Option<String>,
+    ) -> Result<Option<Self>> {
+        let Some(url) = &config.url else {
+            return Ok(None);
+        };
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout))
+            .build()
+            .context("Failed to build introspection client")?;
+        Ok(Some(Self {
+            client,
+            url: url.clone(),
+            credentials: config.client_id.clone().zip(config.client_secret.clone()),
+            audience,
+            issuer,
+            cache: LRUTTLCache::new(config.cache_entries, config.cache_ttl.max(1)),
+            cache_ttl: config.cache_ttl,
+        }))
+    }
+
+    /// The claims of `token`, if the endpoint says it is active
+    pub async fn introspect(&self, token: &str) -> Result<Value, Problem> {
+        let key = hex::encode(Sha256::digest(token.as_bytes()));
+        if let Some(claims) = self
+            .cache
+            .get(&key)
+            .and_then(|cached| serde_json::from_str(&cached).ok())
+        {
+            return Ok(claims);
+        }
+
+        let claims = match self.ask(token).await {
+            Ok(claims) => claims,
+            Err(e) => {
+                warn!(error = %format!("{:#}", e), "Token introspection failed");
+                return Err(unauthorized("Token introspection failed"));
+            }
+        };
+        self.check(&claims)?;
+
+        let ttl = match claims["exp"].as_i64() {
+            Some(exp) => {
+                let left = exp.saturating_sub(chrono::Utc::now().timestamp());
+                self.cache_ttl.min(u64::try_from(left).unwrap_or(0))
+            }
+            None => self.cache_ttl,
+        };
+        if ttl > 0 {
+            if let Err(e) = self.cache.set(key, claims.to_string(), Some(ttl)) {
+                debug!(error = %e, "Failed to cache token introspection");
+            }
+        }
+        Ok(claims)
+    }
+
+    async fn ask(&self, token: &str) -> Result<Value> {
+        let mut request = self
+            .client
+            .post(&self.url)
+            .form(&[("token", token), ("token_type_hint", "access_token")]);
+        if let Some((id, secret)) = &self.credentials {
+            request = request.basic_auth(id, Some(secret));
+        }
+        let response = request.send().await.context("Endpoint unreachable")?;
+        if !response.status().is_success() {
+            bail!("Endpoint returned {}", response.status());
+        }
+        let claims: Value = response
+            .json()
+            .await
+            .context("Invalid introspection response")?;
+        if !claims.is_object() {
+            bail!("Introspection response is not an object");
+        }
+        Ok(claims)
+    }
+
+    /// Refuse tokens that aren't active here and now
+    fn check(&self, claims: &Value) -> Result<(), Problem> {
+        if claims["active"] != Value::Bool(true) {
+            return Err(unauthorized("Token is not active"));
+        }
+        if let Some(exp) = claims["exp"].as_i64() {
+            if exp <= chrono::Utc::now().timestamp() {
+                return Err(unauthorized("Token has expired"));
+            }
+        }
+        if let Some(audience) = &self.audience {
+            let matches = match &claims["aud"] {
+                Value::String(aud) => aud == audience,
+                Value::Array(auds) => auds.iter().any(|aud| aud.as_str() == Some(audience)),
+                _ => false,
+            };
+            if !matches {
+                return Err(unauthorized("Token is not for this audience"));
+            }
+        }
+        if let Some(issuer) = &self.issuer {
+            if claims["iss"].as_str() != Some(issuer) {
+                return Err(unauthorized("Token is from another issuer"));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn unauthorized(message: &str) -> Problem {
+    Problem::Unauthenticated(message.to_string())
+}
@@ -0,0 +1,103 @@
+//! Request timeouts and body size limits
+//!
+//! Decision requests must arrive and be answered within the route's
+//! timeout, or the caller gets 408; bodies over the route's size limit are
+//! refused with 413 before they are parsed, both as problems (see
+//! `problem`).
+//!
+//! Evaluation itself runs to completion once started (the engine can't be
+//! interrupted); the timeout bounds slow clients, token verification and
+//! time spent waiting for the engine.
+
+use crate::config::RequestsConfig;
+use crate::problem::Problem;
+use axum::{
+    body::Body,
+    extract::{MatchedPath, Request, State},
+    http::header::CONTENT_LENGTH,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use http_body_util::LengthLimitError;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::debug;
+
+/// Middleware applying the matched decision route's timeout and body limit
+pub async fn enforce(
+    State(config): State<Arc<RequestsConfig>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_string())
+        .unwrap_or_default();
+    if !crate::DECISION_ROUTES.contains(&route.as_str()) {
+        return next.run(request).await;
+    }
+    let (timeout, max_body_bytes) = config.for_route(&route);
+
+    let declared = request
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    if declared.is_some_and(|length| length > max_body_bytes as u64) {
+        return too_large(&route, max_body_bytes);
+    }
+
+    let handle = async {
+        let (parts, body) = request.into_parts();
+        let bytes = match axum::body::to_bytes(body, max_body_bytes).await {
+            Ok(bytes) => bytes,
+            Err(e) if is_length_limit(&e) => return too_large(&route, max_body_bytes),
+            Err(_) => {
+                return Problem::InvalidRequest("Failed to read request body".to_string())
+                    .into_response()
+            }
+        };
+        next.run(Request::from_parts(parts, Body::from(bytes)))
+            .await
+    };
+
+    match timeout {
+        Some(timeout) => match tokio::time::timeout(timeout, handle).await {
+            Ok(response) => response,
+            Err(_) => {
+                debug!(route = %route, "Request timed out");
+                Problem::RequestTimeout(format!(
+                    "Request not completed within {}s",
+                    timeout.as_secs()
+                ))
+                .into_response()
+            }
+        },
+        None => handle.await,
+    }
+}
+
+fn is_length_limit(e: &axum::Error) -> bool {
+    std::error::Error::source(e).is_some_and(|source| source.is::<LengthLimitError>())
+}
+
+fn too_large(route: &str, limit: usize) -> Response {
+    debug!(route = %route, limit_bytes = limit, "Rejected oversized request");
+    Problem::PayloadTooLarge(format!("Request body exceeds {} bytes", limit)).into_response()
+}
+
+impl RequestsConfig {
+    /// Timeout (if any) and body limit for `route`
+    pub fn for_route(&self, route: &str) -> (Option<Duration>, usize) {
+        let overrides = self.routes.get(route);
+        let timeout = overrides.and_then(|o| o.timeout).unwrap_or(self.timeout);
+        let max_body_bytes = overrides
+            .and_then(|o| o.max_body_bytes)
+            .unwrap_or(self.max_body_bytes);
+        (
+            (timeout > 0).then(|| Duration::from_secs(timeout)),
+            max_body_bytes,
+        )
+    }
+}
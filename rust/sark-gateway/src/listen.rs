@@ -0,0 +1,409 @@
+//! Listen addresses
+//!
+//! `listen` is a TCP address (`0.0.0.0:8080`) or a Unix domain socket
+//! (`unix:/run/sark/gateway.sock`). A socket suits sidecars sharing a pod
+//! or host with their caller, which then skip the loopback TCP stack. Its
+//! file mode comes from `[unix_socket]`:
+//!
+//! ```toml
+//! listen = "unix:/run/sark/gateway.sock"
+//!
+//! [unix_socket]
+//! mode = "0660"
+//! ```
+//!
+//! A socket file left behind by an earlier run is replaced, but not one
+//! another process is still serving on. The file is removed once the
+//! gateway stops accepting on shutdown. TLS isn't offered on a socket, and
+//! callers without a token share one rate limit budget there, having no
+//! peer address to tell them apart by.
+//!
+//! How accepted connections are served comes from `[connections]`:
+//!
+//! ```toml
+//! [connections]
+//! http2 = true
+//! max_concurrent_streams = 200
+//! keep_alive = true
+//! idle_timeout = 75
+//! tcp_nodelay = true
+//! ```
+//!
+//! Callers making many short requests should reuse connections (HTTP/1.1
+//! keep-alive, or HTTP/2 streams); setting up a connection, let alone a
+//! TLS session, costs more than a cached decision. `http2 = false` serves
+//! HTTP/1.1 only, and offers only `http/1.1` by ALPN. These settings apply
+//! to the public listener, are read at startup, and leave the admin API's
+//! listener at its defaults.
+
+use crate::config::{ConnectionsConfig, UnixSocketConfig};
+use crate::tls;
+use anyhow::{bail, Context, Result};
+use axum::Router;
+use hyper_util::rt::{TokioExecutor, TokioTimer};
+use hyper_util::server::conn::auto;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+#[cfg(unix)]
+use tokio::net::UnixListener;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::rustls::ServerConfig;
+
+const UNIX_PREFIX: &str = "unix:";
+
+/// Offered by ALPN when HTTP/2 is off
+const HTTP1_ALPN: &[u8] = b"http/1.1";
+
+/// Where the public listener accepts connections
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum ListenAddr {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl FromStr for ListenAddr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix(UNIX_PREFIX) {
+            Some("") => Err("unix: needs a socket path".to_string()),
+            Some(path) => Ok(ListenAddr::Unix(PathBuf::from(path))),
+            None => s
+                .parse()
+                .map(ListenAddr::Tcp)
+                .map_err(|_| format!("{:?} is neither a socket address nor unix:<path>", s)),
+        }
+    }
+}
+
+impl TryFrom<String> for ListenAddr {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<ListenAddr> for String {
+    fn from(addr: ListenAddr) -> Self {
+        addr.to_string()
+    }
+}
+
+impl fmt::Display for ListenAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ListenAddr::Tcp(addr) => addr.fmt(f),
+            ListenAddr::Unix(path) => write!(f, "{}{}", UNIX_PREFIX, path.display()),
+        }
+    }
+}
+
+impl UnixSocketConfig {
+    /// The socket's file mode, if configured
+    pub fn permissions(&self) -> Result<Option<u32>> {
+        let Some(mode) = &self.mode else {
+            return Ok(None);
+        };
+        match u32::from_str_radix(mode.trim_start_matches("0o"), 8) {
+            Ok(bits) if bits <= 0o777 => Ok(Some(bits)),
+            _ => bail!("unix_socket.mode {:?} is not an octal file mode", mode),
+        }
+    }
+}
+
+/// Serves accepted connections as `[connections]` says
+pub struct Connections {
+    builder: auto::Builder<TokioExecutor>,
+    http2: bool,
+    tcp_nodelay: bool,
+}
+
+impl Connections {
+    pub fn new(config: &ConnectionsConfig) -> Self {
+        let seconds = |secs: u64| (secs > 0).then(|| Duration::from_secs(secs));
+        let mut builder = auto::Builder::new(TokioExecutor::new());
+        builder
+            .http1()
+            .timer(TokioTimer::new())
+            .keep_alive(config.keep_alive)
+            .header_read_timeout(seconds(config.idle_timeout));
+        builder
+            .http2()
+            .timer(TokioTimer::new())
+            .max_concurrent_streams(config.max_concurrent_streams)
+            .keep_alive_interval(seconds(config.http2_keep_alive_interval))
+            .keep_alive_timeout(Duration::from_secs(config.http2_keep_alive_timeout));
+        if !config.http2 {
+            builder = builder.http1_only();
+        }
+        Self {
+            builder,
+            http2: config.http2,
+            tcp_nodelay: config.tcp_nodelay,
+        }
+    }
+
+    /// The builder each connection is served with, by `serve_connection`:
+    /// no route upgrades connections, and `http1_only` would have no effect
+    /// on `serve_connection_with_upgrades`
+    pub fn builder(&self) -> &auto::Builder<TokioExecutor> {
+        &self.builder
+    }
+
+    /// Apply socket options to a newly accepted connection
+    pub fn accepted(&self, stream: &TcpStream) {
+        if let Err(e) = stream.set_nodelay(self.tcp_nodelay) {
+            tracing::debug!(error = %e, "Failed to set TCP_NODELAY");
+        }
+    }
+
+    /// `tls` offering only the protocols served
+    fn alpn(&self, tls: Arc<ServerConfig>) -> Arc<ServerConfig> {
+        if self.http2 {
+            return tls;
+        }
+        let mut tls = (*tls).clone();
+        tls.alpn_protocols = vec![HTTP1_ALPN.to_vec()];
+        Arc::new(tls)
+    }
+}
+
+/// A bound public listener
+pub enum Listener {
+    Tcp(TcpListener),
+    /// With the socket's path if the gateway created it (and removes it on
+    /// shutdown)
+    #[cfg(unix)]
+    Unix(UnixListener, Option<PathBuf>),
+}
+
+impl fmt::Display for Listener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Listener::Tcp(listener) => match listener.local_addr() {
+                Ok(addr) => addr.fmt(f),
+                Err(_) => f.write_str("TCP socket"),
+            },
+            #[cfg(unix)]
+            Listener::Unix(listener, _) => {
+                match listener
+                    .local_addr()
+                    .ok()
+                    .and_then(|a| a.as_pathname().map(PathBuf::from))
+                {
+                    Some(path) => write!(f, "{}{}", UNIX_PREFIX, path.display()),
+                    None => f.write_str("Unix socket"),
+                }
+            }
+        }
+    }
+}
+
+impl Listener {
+    pub async fn bind(addr: &ListenAddr, socket: &UnixSocketConfig) -> Result<Self> {
+        match addr {
+            ListenAddr::Tcp(addr) => Ok(Listener::Tcp(
+                TcpListener::bind(addr)
+                    .await
+                    .with_context(|| format!("Failed to bind {}", addr))?,
+            )),
+            #[cfg(unix)]
+            ListenAddr::Unix(path) => Ok(Listener::Unix(
+                bind_unix(path, socket).await?,
+                Some(path.clone()),
+            )),
+            #[cfg(not(unix))]
+            ListenAddr::Unix(_) => {
+                let _ = socket;
+                bail!("Unix domain sockets are not supported on this platform")
+            }
+        }
+    }
+
+    /// Serve `app` (over TLS with `tls`, after a PROXY protocol header with
+    /// `proxy_protocol`) until `shutdown` resolves, then wait for open
+    /// connections to finish
+    pub async fn serve(
+        self,
+        app: Router,
+        tls: Option<Arc<ServerConfig>>,
+        proxy_protocol: bool,
+        connections: Connections,
+        shutdown: impl Future<Output = ()> + Send + 'static,
+    ) -> Result<()> {
+        let connections = Arc::new(connections);
+        match (self, tls) {
+            (Listener::Tcp(listener), Some(tls)) => {
+                let tls = connections.alpn(tls);
+                tls::serve(listener, app, tls, proxy_protocol, connections, shutdown).await
+            }
+            (Listener::Tcp(listener), None) => {
+                serve_tcp(listener, app, proxy_protocol, connections, shutdown).await
+            }
+            #[cfg(unix)]
+            (Listener::Unix(..), Some(_)) => bail!("TLS is not supported on a Unix socket"),
+            #[cfg(unix)]
+            (Listener::Unix(..), None) if proxy_protocol => {
+                bail!("The PROXY protocol is not supported on a Unix socket")
+            }
+            #[cfg(unix)]
+            (Listener::Unix(listener, path), None) => {
+                serve_unix(listener, path, app, connections, shutdown).await
+            }
+        }
+    }
+}
+
+/// Serve `app` on `listener` in cleartext (taking each connection's peer
+/// from the PROXY protocol header it opens with, with `proxy_protocol`),
+/// like `tls::serve`
+async fn serve_tcp(
+    listener: TcpListener,
+    app: Router,
+    proxy_protocol: bool,
+    connections: Arc<Connections>,
+    shutdown: impl Future<Output = ()>,
+) -> Result<()> {
+    use axum::{extract::ConnectInfo, http::Request};
+    use hyper_util::rt::TokioIo;
+    use hyper_util::server::graceful::GracefulShutdown;
+    use hyper_util::service::TowerToHyperService;
+    use tower::ServiceExt;
+    use tracing::{debug, warn};
+
+    let graceful = GracefulShutdown::new();
+    tokio::pin!(shutdown);
+
+    loop {
+        let (mut stream, peer) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    warn!(error = %e, "Failed to accept connection");
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    continue;
+                }
+            },
+            _ = &mut shutdown => break,
+        };
+        connections.accepted(&stream);
+
+        let app = app.clone();
+        let connections = connections.clone();
+        let watcher = graceful.watcher();
+        tokio::spawn(async move {
+            let peer = match proxy_protocol {
+                true => match crate::clientip::accept_proxied(&mut stream, peer).await {
+                    Some(peer) => peer,
+                    None => return,
+                },
+                false => peer,
+            };
+            let service =
+                TowerToHyperService::new(app.map_request(move |mut request: Request<_>| {
+                    request.extensions_mut().insert(ConnectInfo(peer));
+                    request
+                }));
+            let connection = connections
+                .builder()
+                .serve_connection(TokioIo::new(stream), service)
+                .into_owned();
+            if let Err(e) = watcher.watch(connection).await {
+                debug!(peer = %peer, error = %e, "Connection closed with error");
+            }
+        });
+    }
+
+    drop(listener);
+    graceful.shutdown().await;
+    Ok(())
+}
+
+/// Bind a socket at `path`, replacing a stale one
+#[cfg(unix)]
+async fn bind_unix(path: &std::path::Path, config: &UnixSocketConfig) -> Result<UnixListener> {
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+    use tokio::net::UnixStream;
+
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            bail!("{} exists and is not a socket", path.display());
+        }
+        if UnixStream::connect(path).await.is_ok() {
+            bail!("{} is in use by another process", path.display());
+        }
+        std::fs::remove_file(path)
+            .with_context(|| format!("Failed to remove stale socket {}", path.display()))?;
+    }
+
+    let listener =
+        UnixListener::bind(path).with_context(|| format!("Failed to bind {}", path.display()))?;
+    if let Some(mode) = config.permissions()? {
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+            .with_context(|| format!("Failed to set permissions of {}", path.display()))?;
+    }
+    Ok(listener)
+}
+
+/// Serve `app` on a socket until `shutdown` resolves, like `tls::serve`
+#[cfg(unix)]
+async fn serve_unix(
+    listener: UnixListener,
+    path: Option<PathBuf>,
+    app: Router,
+    connections: Arc<Connections>,
+    shutdown: impl Future<Output = ()>,
+) -> Result<()> {
+    use hyper_util::rt::TokioIo;
+    use hyper_util::server::graceful::GracefulShutdown;
+    use hyper_util::service::TowerToHyperService;
+    use tracing::{debug, warn};
+
+    let graceful = GracefulShutdown::new();
+    tokio::pin!(shutdown);
+
+    loop {
+        let stream = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    warn!(error = %e, "Failed to accept connection");
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    continue;
+                }
+            },
+            _ = &mut shutdown => break,
+        };
+
+        let service = TowerToHyperService::new(app.clone());
+        let connections = connections.clone();
+        let watcher = graceful.watcher();
+        tokio::spawn(async move {
+            let connection = connections
+                .builder()
+                .serve_connection(TokioIo::new(stream), service)
+                .into_owned();
+            if let Err(e) = watcher.watch(connection).await {
+                debug!(error = %e, "Connection closed with error");
+            }
+        });
+    }
+
+    // New instances may bind the path while these connections drain
+    drop(listener);
+    if let Some(path) = path {
+        if let Err(e) = std::fs::remove_file(&path) {
+            warn!(path = %path.display(), error = %e, "Failed to remove socket");
+        }
+    }
+    graceful.shutdown().await;
+    Ok(())
+}
@@ -1,8 +1,12 @@
 //! SARK Gateway - High-Performance Authorization Service
 //!
 //! This binary handles the hot path for SARK:
+//! - /livez, /readyz - Liveness and readiness probes
 //! - /gateway/authorize - Policy evaluation for MCP requests
+//! - /gateway/authorize/batch - Many authorizations in one round trip
 //! - /gateway/authorize-a2a - Agent-to-agent authorization
+//! - sark.gateway.v1.Authorization - Both decisions over gRPC
+//! - /mcp/{server} - Authorized requests forwarded to MCP servers
 //!
 //! Cold path (admin, UI, complex logic) stays in Python/FastAPI.
 //!
@@ -13,230 +17,2863 @@
 //!              SARK API (Python) ← Admin/UI requests
 //! ```
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use axum::{
-    extract::State,
-    http::StatusCode,
+    extract::{DefaultBodyLimit, FromRef, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware,
     response::IntoResponse,
-    routing::{get, post},
-    Json, Router,
+    routing::{any, get, post},
+    Extension, Json, Router,
 };
-use clap::Parser;
-use grid_cache::LRUTTLCache;
-use grid_opa::OPAEngine;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand};
+use futures::FutureExt;
+use sark_classify::{Call, Classifier};
+use sark_context::RequestContext;
+use sark_policy::Outcome;
 use serde::{Deserialize, Serialize};
-use std::net::SocketAddr;
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, SocketAddr};
 use std::path::PathBuf;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 use tokio::sync::Mutex;
-use tracing::{error, info};
-use tracing_subscriber;
+use tracing::{debug, error, info, info_span, warn, Instrument};
 
+mod admin;
+mod admission;
+mod apikey;
+mod audit;
+mod auth;
+mod bench;
+mod bloom;
+mod bundle;
+mod cache;
+mod cachebench;
+mod capture;
+mod clientip;
+mod commands;
+mod config;
+mod decision_log;
+mod delegation;
+mod detect;
+mod enrich;
+mod envoy;
+mod events;
+mod fallback;
+mod grpc;
+mod health;
+mod introspection;
+mod limits;
+mod listen;
+mod mcp;
+mod metrics;
+mod mmdb;
+mod obligations;
+mod openapi;
+mod overload;
+mod overrides;
+mod policy;
+mod policy_cache;
+mod precompute;
+mod problem;
+mod profile;
+mod provenance;
+mod proxy;
+mod quota;
+mod ratelimit;
+mod reasons;
+mod redact;
+mod refresh;
+mod reload;
+mod replay;
+mod scan;
+mod schema;
+mod shadow;
+mod signing;
+mod singleflight;
+mod smoke;
+mod snapshot;
+#[cfg(unix)]
+mod spiffe;
+mod stepup;
+#[cfg(unix)]
+mod systemd;
+mod telemetry;
+mod template;
+mod tenant;
+mod tls;
+mod warm;
+mod watch;
+
+use apikey::ApiKeys;
+use audit::{AuditLog, AuditRecord, Chaining, Rotation};
+use auth::{JwtVerifier, UserContext};
+use bundle::SyncStatus;
+use cache::{CachedDecision, Namespace, RedisTier, Ttls};
+use capture::Capture;
+use clientip::{ClientIp, ClientIps};
+use config::{Eviction, GatewayConfig, QueriesConfig};
+use decision_log::DecisionLog;
+use delegation::Delegation;
+use detect::Detector;
+use enrich::Enricher;
+use events::Events;
+use fallback::Fallback;
+use listen::{Connections, ListenAddr, Listener};
+use metrics::Metrics;
+use obligations::{LogLevel, Obligations};
+use overload::Shedder;
+use overrides::Overrides;
+use policy::{ActivePolicy, CurrentRevision, PolicySet, PolicyStore};
+use precompute::Precompute;
+use problem::Problem;
+use provenance::DecisionHeaders;
+use proxy::Proxy;
+use quota::{Quota, Quotas};
+use ratelimit::RateLimiter;
+use reasons::Catalog;
+use refresh::Refreshers;
+use reload::Reloader;
+use sark_store::Store;
+use scan::{ScannedJson, Scanner};
+use schema::{InputSchema, MalformedResult, ResultSchema};
+use shadow::Shadow;
+use signing::Signer;
+use singleflight::SingleFlight;
+use smoke::StartupChecks;
+use stepup::StepUps;
+use telemetry::LogFormat;
+use tenant::{Tenant, Tenants};
+use tls::ClientIdentity;
+
+/// Command line; flags given here override the config file and
+/// environment (see [`config`])
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Configuration file path
     #[arg(short, long, default_value = "/etc/sark/gateway.conf")]
     config: PathBuf,
 
-    /// Listen address
+    /// Listen address: host:port, or unix:<path> for a Unix domain socket
     #[arg(short, long, default_value = "0.0.0.0:8080")]
-    listen: SocketAddr,
+    listen: ListenAddr,
 
     /// Log level (trace, debug, info, warn, error)
     #[arg(short = 'v', long, default_value = "info")]
     log_level: String,
+
+    /// Log line format: text (or pretty) to read, json for log pipelines
+    #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+
+    /// Directory of .rego policies to compile at startup (searched recursively)
+    #[arg(long)]
+    policy_dir: Option<PathBuf>,
+
+    /// Recompile --policy-dir when its files change, keeping the current
+    /// policy if the new one fails to compile
+    #[arg(long)]
+    watch_policies: bool,
+
+    /// JSON Schema that policy input is validated against before evaluation
+    #[arg(long)]
+    input_schema: Option<PathBuf>,
+
+    /// JSON Schema that policy results are validated against after
+    /// evaluation
+    #[arg(long)]
+    result_schema: Option<PathBuf>,
+
+    /// What a result failing --result-schema becomes: an uncached deny, or
+    /// a malformed-result error
+    #[arg(long, value_enum, default_value_t = MalformedResult::Deny)]
+    malformed_result: MalformedResult,
+
+    /// Candidate policy directory evaluated alongside the active policy;
+    /// disagreements are logged and counted, never returned
+    #[arg(long)]
+    shadow_policy_dir: Option<PathBuf>,
+
+    /// Number of previous policy revisions kept for rollback
+    #[arg(long, default_value_t = 5)]
+    policy_history: usize,
+
+    /// Directory of canned inputs and the decisions policies must make for
+    /// them, checked at startup (the gateway isn't ready until they pass)
+    /// and before each policy set is activated
+    #[arg(long)]
+    startup_checks: Option<PathBuf>,
+
+    /// OPA bundle (.tar.gz) to load policies and data from: an http(s) URL
+    /// of a bundle server, or a local path
+    #[arg(long)]
+    bundle_url: Option<String>,
+
+    /// Interval in seconds between bundle re-fetches (0 loads the bundle
+    /// once at startup)
+    #[arg(long, default_value_t = 0)]
+    bundle_poll_interval: u64,
+
+    /// Public key (PEM) that bundle signatures are verified against
+    #[arg(long)]
+    bundle_verification_key: Option<PathBuf>,
+
+    /// Signing algorithm of bundle signatures (RS256, ES256, EdDSA, ...)
+    #[arg(long, default_value = "RS256")]
+    bundle_verification_alg: String,
+
+    /// Refuse bundles that carry no signature
+    #[arg(long, requires = "bundle_verification_key")]
+    bundle_require_signature: bool,
+
+    /// Interval in seconds between expired-entry sweeps of the decision cache (0 disables)
+    #[arg(long, default_value_t = 60)]
+    cache_cleanup_interval: u64,
+
+    /// Randomize each cached decision's TTL within +/- this percentage
+    #[arg(long, default_value_t = 0)]
+    cache_ttl_jitter_pct: u8,
+
+    /// Serve cached decisions older than this many seconds while re-evaluating
+    /// them in the background (0 disables stale-while-revalidate)
+    #[arg(long, default_value_t = 0)]
+    cache_soft_ttl: u64,
+
+    /// TTL in seconds for cached deny decisions, kept shorter than allows so
+    /// a permission fix takes effect quickly while retry storms still hit
+    /// the cache
+    #[arg(long, default_value_t = 60)]
+    cache_deny_ttl: u64,
+
+    /// Longest TTL in seconds any decision is cached for, including TTLs the
+    /// policy sets
+    #[arg(long, default_value_t = 3600)]
+    cache_max_ttl: u64,
+
+    /// Decisions to cache before serving, as POST /admin/cache/warm takes
+    /// them (JSON)
+    #[arg(long)]
+    cache_preload: Option<PathBuf>,
+
+    /// File the cached decisions are written to on shutdown and read back
+    /// from on boot, dropping those that expired meanwhile (see `snapshot`)
+    #[arg(long)]
+    cache_snapshot: Option<PathBuf>,
+
+    /// Redis URL for a shared L2 decision cache across gateway replicas
+    #[arg(long)]
+    redis_url: Option<String>,
+
+    /// Record every decision to this file (JSON lines), http(s) collector,
+    /// kafka://brokers/topic or nats://host:port/subject
+    #[arg(long)]
+    decision_log: Option<String>,
+
+    /// Decision records shipped to the sink at once
+    #[arg(long, default_value_t = decision_log::DEFAULT_BATCH_SIZE, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    decision_log_batch_size: usize,
+
+    /// Decision records buffered before new ones are dropped
+    #[arg(long, default_value_t = decision_log::DEFAULT_QUEUE_CAPACITY, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    decision_log_queue_size: usize,
+
+    /// Logged decisions whose inputs are kept for /admin/replay and
+    /// /admin/policies/profile (0 keeps none)
+    #[arg(long, default_value_t = replay::DEFAULT_BUFFER)]
+    decision_log_replay_buffer: usize,
+
+    /// Address to serve the /admin API on, apart from the public listener
+    #[arg(long)]
+    admin_listen: Option<SocketAddr>,
+
+    /// Bearer token for the /admin API (the admin listener is off if unset)
+    #[arg(long)]
+    admin_token: Option<String>,
+
+    /// JWKS URL of the identity provider whose tokens callers present
+    #[arg(long)]
+    jwks_url: Option<String>,
+
+    /// Required `aud` claim
+    #[arg(long)]
+    jwt_audience: Option<String>,
+
+    /// Required `iss` claim
+    #[arg(long)]
+    jwt_issuer: Option<String>,
+
+    /// Seconds between JWKS refetches
+    #[arg(long, default_value_t = 300)]
+    jwks_refresh_interval: u64,
+
+    /// Audit trail target: a file path (rotated by size), `syslog`, or
+    /// `syslog://host:port`
+    #[arg(long)]
+    audit_log: Option<String>,
+
+    /// Size in MiB at which a file audit log is rotated
+    #[arg(long, default_value_t = 100)]
+    audit_log_max_size: u64,
+
+    /// Rotated audit log files to keep
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u64).range(1..))]
+    audit_log_max_files: u64,
+
+    /// Chain file audit records by hash and, with a [signing] key, write
+    /// signed checkpoints, for `audit verify` to prove the log unaltered
+    #[arg(long, requires = "audit_log")]
+    audit_chain: bool,
+
+    /// Seconds between signed checkpoints of a chained audit log (0 writes
+    /// them only before rotations and at shutdown)
+    #[arg(long, default_value_t = 300)]
+    audit_checkpoint_interval: u64,
+
+    /// PEM certificate chain to serve HTTPS with (reloaded when it changes)
+    #[arg(long)]
+    tls_cert: Option<PathBuf>,
+
+    /// PEM private key for --tls-cert
+    #[arg(long)]
+    tls_key: Option<PathBuf>,
+
+    /// PEM CA bundle client certificates must chain to; enables mutual TLS
+    /// and passes the client's identity to policies as `input.client`
+    #[arg(long)]
+    tls_client_ca: Option<PathBuf>,
+
+    /// Seconds in-flight requests get to finish on shutdown
+    #[arg(long, default_value_t = 25)]
+    drain_timeout: u64,
+
+    /// OTLP/gRPC collector to export trace spans to (e.g.
+    /// http://localhost:4317)
+    #[arg(long)]
+    otlp_endpoint: Option<String>,
+
+    /// Answer Kubernetes AdmissionReview requests on /admission/validate
+    /// with data.kubernetes.admission
+    #[arg(long)]
+    admission_webhook: bool,
+}
+
+/// Subcommands; without one the gateway serves requests
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Run the test_* rules in a policy directory and report pass/fail
+    TestPolicies {
+        /// Directory of .rego policies and tests (searched recursively)
+        dir: PathBuf,
+    },
+
+    /// Compile .rego files and templates (rendered with the config's
+    /// policy.vars) and report errors with their locations
+    CheckPolicy {
+        /// A .rego or .rego.tmpl file, or a directory searched recursively
+        path: PathBuf,
+
+        /// Directory keeping each file's results, so unchanged files
+        /// aren't compiled again
+        #[arg(long)]
+        policy_cache_dir: Option<PathBuf>,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+    },
+
+    /// Evaluate one policy query offline and print the result
+    Eval {
+        /// Directory of .rego policies and data documents
+        #[arg(long)]
+        policy_dir: PathBuf,
+
+        /// Input document (JSON), or - for stdin
+        #[arg(long)]
+        input: PathBuf,
+
+        /// Query to evaluate [default: the package the server would
+        /// evaluate the input against, per the config's [queries]]
+        #[arg(long)]
+        query: Option<String>,
+
+        /// Treat the input as a request body to this route and shape it
+        /// as the server would
+        #[arg(long, value_enum, requires = "claims")]
+        request: Option<Endpoint>,
+
+        /// Token claims (JSON) the caller is built from, with --request
+        #[arg(long, requires = "request")]
+        claims: Option<PathBuf>,
+
+        /// Also print the input and the queried rule's whole package
+        #[arg(long)]
+        explain: bool,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+    },
+
+    /// Replay recorded requests against a running gateway and report
+    /// latency and cache hit rates
+    Bench {
+        /// Base URL of the gateway (e.g. http://127.0.0.1:8080)
+        #[arg(long)]
+        target: String,
+
+        /// Requests started per second
+        #[arg(long, default_value_t = 100)]
+        rps: u32,
+
+        /// Seconds to run for
+        #[arg(long, default_value_t = 10)]
+        duration: u64,
+
+        /// JSON array or JSON lines of requests, sent round robin
+        #[arg(long)]
+        requests_file: PathBuf,
+
+        /// Bearer token sent with every request
+        #[arg(long)]
+        token: Option<String>,
+
+        /// Upper bound on requests in flight
+        #[arg(long, default_value_t = 256)]
+        concurrency: usize,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+    },
+
+    /// Measure the in-process cache store's lookup throughput from many
+    /// threads, in one shard and in several
+    BenchCache {
+        /// Threads looking keys up at once
+        #[arg(long, default_value_t = 32)]
+        threads: usize,
+
+        /// Shards to compare with a single one
+        #[arg(long, default_value_t = 16)]
+        shards: usize,
+
+        /// Entries the store holds
+        #[arg(long, default_value_t = 10_000)]
+        entries: usize,
+
+        /// Distinct keys looked up, uniformly at random
+        #[arg(long, default_value_t = 20_000)]
+        keys: u64,
+
+        /// Seconds each run lasts
+        #[arg(long, default_value_t = 5)]
+        duration: u64,
+
+        /// Which entries make room when the store is full
+        #[arg(long, value_enum, default_value_t = Eviction::Lru)]
+        eviction: Eviction,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+    },
+
+    /// Work with audit logs
+    Audit {
+        #[command(subcommand)]
+        command: AuditCommand,
+    },
+}
+
+/// Audit log subcommands
+#[derive(Subcommand, Debug)]
+enum AuditCommand {
+    /// Check a chained audit log's links and signed checkpoints
+    Verify {
+        /// Audit log files, oldest first (e.g. audit.log.1 audit.log)
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+
+        /// Public key checkpoints are verified against: PEM, or the
+        /// gateway's JWK set [default: signatures are not checked]
+        #[arg(long)]
+        key: Option<PathBuf>,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+    },
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum OutputFormat {
+    Text,
+    Json,
 }
 
 /// Shared application state
 #[derive(Clone)]
 struct AppState {
-    opa_engine: Arc<Mutex<OPAEngine>>,
-    cache: Arc<LRUTTLCache>,
+    /// Active policy engine and the revisions it replaced
+    policy: Arc<Mutex<PolicyStore>>,
+    /// Revision of the active policy, which cached decisions must match
+    revision: CurrentRevision,
+    /// Backing store shared by all cache namespaces
+    cache: Arc<Store>,
+    /// Entries the backing store holds at most
+    cache_capacity: usize,
+    /// Cached authorization decisions (`auth:` namespace)
+    decisions: Namespace,
+    /// Cached agent-to-agent decisions (`a2a:` namespace)
+    a2a_decisions: Namespace,
+    /// Input fields each namespace's cache keys cover, where narrowed
+    key_fields: Arc<HashMap<String, Vec<String>>>,
+    /// Policy packages decisions are evaluated against, where configured
+    queries: Arc<QueriesConfig>,
+    /// Coalesces concurrent evaluations of the same uncached decision
+    inflight: Arc<SingleFlight<AuthResult>>,
+    /// Lookup counts of cached inputs, to re-evaluate after clears
+    precompute: Option<Arc<Precompute>>,
+    /// TTLs for newly cached decisions (replaced on config reload)
+    ttls: Arc<RwLock<Ttls>>,
+    /// Audit log of decisions, if enabled
+    decision_log: Option<Arc<DecisionLog>>,
+    /// Identity-bearing audit trail, if enabled
+    audit_log: Option<Arc<AuditLog>>,
+    /// Sampled decisions kept for offline analysis, if enabled
+    capture: Option<Arc<Capture>>,
+    /// Streams of decisions as they are made
+    events: Arc<Events>,
+    /// Summarizes long parameter strings, if configured
+    scanner: Option<Arc<Scanner>>,
+    /// Flags credentials in parameters, if enabled
+    detector: Option<Arc<Detector>>,
+    /// Schema policy input must satisfy, if configured
+    input_schema: Option<Arc<InputSchema>>,
+    result_schema: Option<Arc<ResultSchema>>,
+    /// Candidate policy under dry-run comparison, if configured
+    shadow: Option<Arc<Shadow>>,
+    /// Caller token verification; without it every request is rejected
+    jwt: Arc<RwLock<Option<Arc<JwtVerifier>>>>,
+    /// API keys callers may present instead of a token
+    api_keys: Arc<RwLock<Arc<ApiKeys>>>,
+    /// Checks step-up obligations against callers' tokens
+    step_ups: Arc<StepUps>,
+    /// Decision metadata set on responses and proxied requests
+    decision_headers: Arc<DecisionHeaders>,
+    /// Emergency decisions for servers' tools, ahead of the policy
+    overrides: Arc<Overrides>,
+    /// Proxies believed and addresses served (replaced on config reload)
+    client_ips: Arc<RwLock<Arc<ClientIps>>>,
+    /// Databases client addresses are looked up in (replaced on config
+    /// reload)
+    enricher: Arc<RwLock<Arc<Enricher>>>,
+    /// Messages for reason codes (replaced on config reload)
+    reasons: Arc<RwLock<Arc<Catalog>>>,
+    /// Sensitivity of tool calls that don't give one (replaced on config
+    /// reload)
+    classifier: Arc<RwLock<Arc<Classifier>>>,
+    /// Per-client request budgets (replaced on config reload)
+    rate_limit: Arc<RateLimiter>,
+    /// Concurrency limits past which requests are shed
+    shedder: Arc<Shedder>,
+    /// Sync status of the bundle source, if policies come from one
+    bundle: Arc<RwLock<Option<Arc<SyncStatus>>>>,
+    /// Sync status of each refreshed data source, by name
+    data_sources: Arc<BTreeMap<String, Arc<SyncStatus>>>,
+    /// What settles decisions local evaluation can't, if configured
+    fallback: Option<Arc<Fallback>>,
+    /// MCP servers decided requests are forwarded to, if configured
+    proxy: Option<Arc<Proxy>>,
+    /// Tenants with policies of their own
+    tenants: Arc<Tenants>,
+    /// Use of the quotas policies set, by quota key
+    quotas: Arc<Quotas>,
+    /// Whether A2A source agents are their clients' SPIFFE IDs
+    agent_identity: bool,
+    metrics: Arc<Metrics>,
+    reloader: Arc<Reloader>,
 }
 
+impl FromRef<AppState> for Arc<Metrics> {
+    fn from_ref(state: &AppState) -> Self {
+        state.metrics.clone()
+    }
+}
+
+impl AppState {
+    /// Every decision cache, for dropping decisions after a policy change
+    fn decision_caches(&self) -> Vec<Namespace> {
+        vec![self.decisions.clone(), self.a2a_decisions.clone()]
+    }
+
+    async fn clear_decisions(&self) {
+        for namespace in self.decision_caches() {
+            namespace.clear().await;
+        }
+    }
+
+    /// Whether decisions need audit records, for the audit log or a
+    /// decision event stream
+    fn audited(&self) -> bool {
+        self.audit_log.is_some() || self.events.watched()
+    }
+
+    async fn audit(&self, record: AuditRecord) {
+        self.events.publish(&record);
+        if let Some(log) = &self.audit_log {
+            log.record(record).await;
+        }
+    }
+
+    fn ttls(&self) -> Ttls {
+        *self.ttls.read().expect("ttls lock poisoned")
+    }
+
+    fn jwt(&self) -> Option<Arc<JwtVerifier>> {
+        self.jwt.read().expect("jwt lock poisoned").clone()
+    }
+
+    fn api_keys(&self) -> Arc<ApiKeys> {
+        self.api_keys
+            .read()
+            .expect("api keys lock poisoned")
+            .clone()
+    }
+
+    fn client_ips(&self) -> Arc<ClientIps> {
+        self.client_ips
+            .read()
+            .expect("client ips lock poisoned")
+            .clone()
+    }
+
+    fn enricher(&self) -> Arc<Enricher> {
+        self.enricher
+            .read()
+            .expect("enricher lock poisoned")
+            .clone()
+    }
+
+    fn reasons(&self) -> Arc<Catalog> {
+        self.reasons.read().expect("reasons lock poisoned").clone()
+    }
+
+    fn classifier(&self) -> Arc<Classifier> {
+        self.classifier
+            .read()
+            .expect("classifier lock poisoned")
+            .clone()
+    }
+}
+
+/// Outcome of a policy evaluation, shared between coalesced requests
+type AuthResult = Result<GatewayAuthResponse, Problem>;
+
 /// Gateway authorization request
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 struct GatewayAuthRequest {
     action: String,
     server_name: String,
     tool_name: String,
+    #[serde(default, deserialize_with = "scan::parameters")]
     parameters: Option<serde_json::Value>,
     context: Option<serde_json::Value>,
     sensitivity_level: Option<String>,
+    /// Evaluate and log without caching (also `X-SARK-Dry-Run: true`)
+    #[serde(default)]
+    dry_run: bool,
 }
 
 /// Gateway authorization response
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct GatewayAuthResponse {
     allow: bool,
     reason: String,
+    /// Stable code for the reason, where the policy gives one (see
+    /// `reasons`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reason_code: Option<String>,
     filtered_parameters: Option<serde_json::Value>,
+    /// Conditions that come with the decision (see `obligations`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    obligations: Option<Obligations>,
+    /// Seconds the decision is cached for (0 if it isn't)
     cache_ttl: u32,
+    /// Revision of the policy that made the decision
+    #[serde(default)]
+    policy_revision: String,
+    /// Whether this was a dry run, for the caller to test the policy with
+    /// rather than enforce
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    dry_run: bool,
+    /// Whether the decision was served from cache, for `provenance`
+    #[serde(skip)]
+    cached: bool,
+}
+
+/// Package evaluated for gateway authorization, unless `queries` maps the
+/// request's action (or the endpoint) to another. The whole document is
+/// evaluated once and `allow`, `reason`, `filtered_parameters` and
+/// `obligations` are read from it, rather than querying each rule.
+const AUTHORIZE_QUERY: &str = "data.mcp.gateway";
+
+/// Package evaluated for agent-to-agent authorization, read the same way
+/// (`data.a2a.gateway.allow` decides)
+const AUTHORIZE_A2A_QUERY: &str = "data.a2a.gateway";
+
+/// Decision endpoint: which package it evaluates and where results are
+/// cached
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Endpoint {
+    Authorize,
+    #[value(name = "a2a")]
+    #[serde(rename = "a2a")]
+    AuthorizeA2a,
+}
+
+impl Endpoint {
+    const fn route(self) -> &'static str {
+        match self {
+            Endpoint::Authorize => "/gateway/authorize",
+            Endpoint::AuthorizeA2a => "/gateway/authorize-a2a",
+        }
+    }
+
+    /// Package `input` is evaluated against: the one `queries` maps its
+    /// action (or capability) to, else the endpoint's
+    fn query<'a>(self, queries: &'a QueriesConfig, input: &serde_json::Value) -> &'a str {
+        let (selected, field, configured, default) = match self {
+            Endpoint::Authorize => (
+                &queries.actions,
+                "action",
+                &queries.authorize,
+                AUTHORIZE_QUERY,
+            ),
+            Endpoint::AuthorizeA2a => (
+                &queries.capabilities,
+                "capability",
+                &queries.a2a,
+                AUTHORIZE_A2A_QUERY,
+            ),
+        };
+        input[field]
+            .as_str()
+            .and_then(|value| selected.get(value))
+            .or(configured.as_ref())
+            .map_or(default, String::as_str)
+    }
+
+    /// Name of the default cache namespace, which `cache.key_fields` go by
+    const fn namespace(self) -> &'static str {
+        match self {
+            Endpoint::Authorize => "auth",
+            Endpoint::AuthorizeA2a => "a2a",
+        }
+    }
+
+    /// Decision cache for the default policy or `tenant`'s
+    fn cache<'a>(self, state: &'a AppState, tenant: Option<&'a Tenant>) -> &'a Namespace {
+        match (self, tenant) {
+            (Endpoint::Authorize, None) => &state.decisions,
+            (Endpoint::AuthorizeA2a, None) => &state.a2a_decisions,
+            (Endpoint::Authorize, Some(tenant)) => &tenant.decisions,
+            (Endpoint::AuthorizeA2a, Some(tenant)) => &tenant.a2a_decisions,
+        }
+    }
+
+    /// Key `input`'s decision is cached under (scoped to the endpoint's
+    /// namespace on access), `input_digest` being the input's digest. The
+    /// decision depends on the whole input (tool, parameters, context), so
+    /// the key carries a digest of it after a readable prefix, unless the
+    /// config narrows it to selected fields.
+    fn cache_key(self, state: &AppState, input: &serde_json::Value, input_digest: &str) -> String {
+        let field = |value: &serde_json::Value| value.as_str().unwrap_or_default().to_string();
+        let prefix = match self {
+            Endpoint::Authorize => [
+                &input["user"]["id"],
+                &input["action"],
+                &input["resource"]["server"],
+            ],
+            Endpoint::AuthorizeA2a => [
+                &input["source_agent"]["id"],
+                &input["target_agent"]["id"],
+                &input["capability"],
+            ],
+        }
+        .map(field)
+        .join(":");
+        let key_digest = match state.key_fields.get(self.namespace()) {
+            Some(fields) => cache::key_digest(input, fields),
+            None => input_digest.to_string(),
+        };
+        format!("{}:{}", prefix, &key_digest[..32])
+    }
+
+    /// TTL for cached allows; agent grants are kept shorter
+    fn allow_ttl(self, ttls: &Ttls) -> u64 {
+        match self {
+            Endpoint::Authorize => ttls.allow,
+            Endpoint::AuthorizeA2a => ttls.a2a_allow,
+        }
+    }
+}
+
+/// Agent-to-agent authorization request
+#[derive(Debug, Serialize, Deserialize)]
+struct A2AAuthRequest {
+    source_agent: AgentIdentity,
+    target_agent: AgentIdentity,
+    /// Requested capability (e.g. execute, query, delegate)
+    capability: String,
+    /// Agents the request was delegated through, originator first
+    #[serde(default)]
+    delegation_chain: Vec<String>,
+    /// Delegation JWTs the chain is proven by, originator's first (see
+    /// `delegation`); never captured
+    #[serde(default, skip_serializing)]
+    delegation_tokens: Vec<String>,
+    #[serde(default, deserialize_with = "scan::parameters")]
+    parameters: Option<serde_json::Value>,
+    context: Option<serde_json::Value>,
 }
 
-/// User context extracted from JWT
+/// An agent as described to the A2A policy
+#[derive(Debug, Serialize, Deserialize)]
+struct AgentIdentity {
+    /// The client's SPIFFE ID for source agents, with
+    /// `spiffe.agent_identity`
+    #[serde(default)]
+    id: String,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    agent_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trust_level: Option<String>,
+    #[serde(default)]
+    capabilities: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    environment: Option<String>,
+}
+
+const BATCH_ROUTE: &str = "/gateway/authorize/batch";
+
+/// Routes that make decisions, which rate limits and load shedding apply
+/// to
+const DECISION_ROUTES: &[&str] = &[
+    Endpoint::Authorize.route(),
+    BATCH_ROUTE,
+    Endpoint::AuthorizeA2a.route(),
+    mcp::ROUTE,
+    admission::ROUTE,
+    proxy::ROUTE,
+    proxy::SUBPATH_ROUTE,
+];
+
+/// Header asking for a dry run of a gateway authorization
+const DRY_RUN_HEADER: &str = "x-sark-dry-run";
+
+/// Header correlating a request across the SARK API, gateway and logs
+const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Upper bound on requests in one batch
+const MAX_BATCH_SIZE: usize = 1000;
+
+/// Time allowed on shutdown for queued audit and decision records to be
+/// written
+const LOG_FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Batch of gateway authorization requests
 #[derive(Debug, Deserialize)]
-struct UserContext {
-    user_id: String,
-    email: String,
-    roles: Vec<String>,
-    permissions: Vec<String>,
+struct GatewayBatchRequest {
+    requests: Vec<GatewayAuthRequest>,
+}
+
+/// Batch results, in request order
+#[derive(Debug, Serialize)]
+struct GatewayBatchResponse {
+    results: Vec<BatchItem>,
+}
+
+/// One batch result: a decision, or the error that request hit
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum BatchItem {
+    Decision(GatewayAuthResponse),
+    Error { error: String },
 }
 
-/// Health check endpoint
-async fn health() -> impl IntoResponse {
-    Json(serde_json::json!({
-        "status": "healthy",
-        "service": "sark-gateway",
-        "version": env!("CARGO_PKG_VERSION"),
-        "implementation": "rust"
-    }))
+/// Liveness probe: the process is up and serving requests
+async fn livez() -> impl IntoResponse {
+    Json(serde_json::json!({ "status": "alive" }))
+}
+
+/// Readiness probe: 503 until the gateway can make real decisions
+///
+/// Each check reports `ready` plus detail. An unreachable Redis L2 is not
+/// checked: decisions fall back to the in-process cache, and failing every
+/// replica's readiness on a shared dependency would take the fleet out.
+async fn readyz(State(state): State<AppState>) -> impl IntoResponse {
+    let (revision, modules, checks) = {
+        let policy = state.policy.lock().await;
+        let active = policy.active();
+        (
+            active.revision().to_string(),
+            active.set.modules().count(),
+            policy.checks().cloned(),
+        )
+    };
+    // Without a JWKS, tokens are all introspected and no keys are needed
+    let (keys, introspection) = match state.jwt() {
+        Some(jwt) => (
+            jwt.uses_jwks().then_some(jwt.key_count().await),
+            jwt.introspects(),
+        ),
+        None => (None, false),
+    };
+    let bundle = state
+        .bundle
+        .read()
+        .expect("bundle lock poisoned")
+        .as_ref()
+        .map(|status| status.report());
+
+    let mut tenants = serde_json::Map::new();
+    for (name, tenant) in state.tenants.iter() {
+        let policy = tenant.policy.lock().await;
+        let modules = policy.active().set.modules().count();
+        tenants.insert(
+            name.to_string(),
+            serde_json::json!({
+                "ready": modules > 0,
+                "revision": policy.active().revision(),
+                "modules": modules,
+            }),
+        );
+    }
+
+    let policies_ready = modules > 0;
+    let tenants_ready = tenants.values().all(|tenant| tenant["ready"] == true);
+    let jwks_ready = keys.map_or(introspection, |keys| keys > 0);
+    let bundle_ready = bundle.as_ref().map_or(true, |b| b.last_sync.is_some());
+    let startup_checks = checks.map(|checks| (checks.count(), checks.status()));
+    let checks_ready = startup_checks
+        .as_ref()
+        .map_or(true, |(_, status)| status.passed);
+    let ready = policies_ready && jwks_ready && bundle_ready && tenants_ready && checks_ready;
+
+    let mut checks = serde_json::json!({
+        "policies": {
+            "ready": policies_ready,
+            "revision": revision,
+            "modules": modules,
+        },
+        "jwks": {
+            "ready": jwks_ready,
+            "configured": keys.is_some(),
+            "keys": keys.unwrap_or(0),
+            "introspection": introspection,
+        },
+        "cache": {
+            "ready": true,
+            "entries": state.cache.size(),
+        },
+    });
+    if !tenants.is_empty() {
+        checks["tenants"] = serde_json::json!({
+            "ready": tenants_ready,
+            "tenants": tenants,
+        });
+    }
+    if let Some((count, status)) = startup_checks {
+        checks["startup_checks"] = serde_json::json!({
+            "ready": checks_ready,
+            "checks": count,
+            "failures": status.failures,
+        });
+    }
+    if let Some(bundle) = bundle {
+        checks["bundle"] = serde_json::json!({
+            "ready": bundle_ready,
+            "last_sync": bundle.last_sync,
+            "last_error": bundle.last_error,
+        });
+    }
+
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (
+        status,
+        Json(serde_json::json!({
+            "status": if ready { "ready" } else { "not_ready" },
+            "checks": checks,
+        })),
+    )
 }
 
 /// Gateway authorization endpoint (HOT PATH)
 async fn authorize(
     State(state): State<AppState>,
-    Json(request): Json<GatewayAuthRequest>,
-) -> Result<Json<GatewayAuthResponse>, (StatusCode, String)> {
+    client: Option<Extension<ClientIdentity>>,
+    client_ip: Option<Extension<ClientIp>>,
+    verified: Option<Extension<UserContext>>,
+    headers: HeaderMap,
+    ScannedJson(mut request): ScannedJson<GatewayAuthRequest>,
+) -> Result<(HeaderMap, Json<GatewayAuthResponse>), Problem> {
+    let user = authenticate(&state, verified, &headers).await?;
+    let client = client.map(|Extension(c)| c);
+    let client_ip = client_ip.map(|Extension(ClientIp(ip))| ip);
+    let dry_run = headers
+        .get(DRY_RUN_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.eq_ignore_ascii_case("true"));
+    request.dry_run |= dry_run;
+    let request_id = request_id(&headers);
+    let context = request_context(&headers, client_ip, &user);
+    let mut decision = authorize_request(
+        &state,
+        &user,
+        client.as_ref(),
+        &context,
+        request_id.clone(),
+        request,
+    )
+    .await?;
+    let locale = state
+        .reasons()
+        .localize(&mut decision, accept_language(&headers));
+    let mut headers = state
+        .decision_headers
+        .headers(std::slice::from_ref(&decision), &request_id);
+    content_language(&mut headers, locale);
+    Ok((headers, Json(decision)))
+}
+
+/// Agent-to-agent authorization endpoint
+///
+/// The bearer token identifies the caller (`input.user`); policies decide
+/// whether that caller may speak for `source_agent`.
+async fn authorize_a2a(
+    State(state): State<AppState>,
+    client: Option<Extension<ClientIdentity>>,
+    client_ip: Option<Extension<ClientIp>>,
+    verified: Option<Extension<UserContext>>,
+    headers: HeaderMap,
+    ScannedJson(request): ScannedJson<A2AAuthRequest>,
+) -> Result<(HeaderMap, Json<GatewayAuthResponse>), Problem> {
+    let user = authenticate(&state, verified, &headers).await?;
+    let client = client.map(|Extension(c)| c);
+    let client_ip = client_ip.map(|Extension(ClientIp(ip))| ip);
+    let request_id = request_id(&headers);
+    let context = request_context(&headers, client_ip, &user);
+    let mut decision = authorize_a2a_request(
+        &state,
+        &user,
+        client.as_ref(),
+        &context,
+        request_id.clone(),
+        request,
+    )
+    .await?;
+    let locale = state
+        .reasons()
+        .localize(&mut decision, accept_language(&headers));
+    let mut headers = state
+        .decision_headers
+        .headers(std::slice::from_ref(&decision), &request_id);
+    content_language(&mut headers, locale);
+    Ok((headers, Json(decision)))
+}
+
+/// Batch authorization endpoint
+///
+/// Lets callers that authorize whole tool catalogs (e.g. on MCP discovery)
+/// pay one round trip instead of one per tool. Each request goes through
+/// the same cache and coalescing as `/gateway/authorize`; a failing
+/// request yields an error entry rather than failing the batch.
+async fn authorize_batch(
+    State(state): State<AppState>,
+    client: Option<Extension<ClientIdentity>>,
+    client_ip: Option<Extension<ClientIp>>,
+    verified: Option<Extension<UserContext>>,
+    headers: HeaderMap,
+    ScannedJson(batch): ScannedJson<GatewayBatchRequest>,
+) -> Result<(HeaderMap, Json<GatewayBatchResponse>), Problem> {
+    let user = authenticate(&state, verified, &headers).await?;
+    let client = client.map(|Extension(c)| c);
+    let client_ip = client_ip.map(|Extension(ClientIp(ip))| ip);
+    if batch.requests.len() > MAX_BATCH_SIZE {
+        return Err(Problem::PayloadTooLarge(format!(
+            "Batch exceeds {} requests",
+            MAX_BATCH_SIZE
+        )));
+    }
+
+    let batch_id = request_id(&headers);
+    let context = request_context(&headers, client_ip, &user);
+    let reasons = state.reasons();
+    let results =
+        futures::future::join_all(batch.requests.into_iter().enumerate().map(|(i, request)| {
+            authorize_request(
+                &state,
+                &user,
+                client.as_ref(),
+                &context,
+                format!("{}/{}", batch_id, i),
+                request,
+            )
+        }))
+        .await
+        .into_iter()
+        .map(|result| match result {
+            Ok(mut decision) => {
+                reasons.localize(&mut decision, accept_language(&headers));
+                BatchItem::Decision(decision)
+            }
+            Err(problem) => BatchItem::Error {
+                error: problem.to_string(),
+            },
+        })
+        .collect();
+
+    let headers = state.decision_headers.headers(&[], &batch_id);
+    Ok((headers, Json(GatewayBatchResponse { results })))
+}
+
+/// The caller's `Accept-Language`, for localized reasons
+fn accept_language(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::ACCEPT_LANGUAGE)
+        .and_then(|v| v.to_str().ok())
+}
+
+/// Say which language the reason is in, where it was localized
+fn content_language(headers: &mut HeaderMap, locale: Option<String>) {
+    if let Some(value) = locale.and_then(|locale| HeaderValue::from_str(&locale).ok()) {
+        headers.insert(header::CONTENT_LANGUAGE, value);
+    }
+}
+
+/// The caller's `X-Request-Id`, or a fresh one if it sent none (or one
+/// unfit to log: over 128 characters, or not printable ASCII)
+fn request_id(headers: &HeaderMap) -> String {
+    headers
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|id| !id.is_empty() && id.len() <= 128 && id.bytes().all(|b| b.is_ascii_graphic()))
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("{:032x}", rand::random::<u128>()))
+}
+
+/// Verify the caller's API key or bearer token, unless the rate limiter
+/// already did, and settle its tenant
+async fn authenticate(
+    state: &AppState,
+    verified: Option<Extension<UserContext>>,
+    headers: &HeaderMap,
+) -> Result<UserContext, Problem> {
+    let mut user = match verified {
+        Some(Extension(user)) => user,
+        None => verify_caller(state, headers).await?,
+    };
+    tracing::Span::current().record("user_id", user.user_id.as_str());
+    state.tenants.resolve(&mut user, headers).map_err(|e| {
+        warn!(user = %user.user_id, error = %e, "Rejected request for a tenant");
+        e
+    })?;
+    Ok(user)
+}
+
+async fn verify_caller(state: &AppState, headers: &HeaderMap) -> Result<UserContext, Problem> {
+    if let Some(user) = state.api_keys().verify(headers).map_err(|e| {
+        warn!(error = %e, "Rejected request with an invalid API key");
+        e
+    })? {
+        return Ok(user);
+    }
+    let Some(jwt) = state.jwt() else {
+        return Err(Problem::Unauthenticated(
+            "Token verification is not configured".to_string(),
+        ));
+    };
+    jwt.verify(headers)
+        .instrument(info_span!("jwt.verify"))
+        .await
+        .map_err(|e| {
+            warn!(error = %e, "Rejected unauthenticated request");
+            e
+        })
+}
+
+/// Authorize a single request: cache lookup, then policy evaluation
+async fn authorize_request(
+    state: &AppState,
+    user: &UserContext,
+    client: Option<&ClientIdentity>,
+    context: &RequestContext,
+    request_id: String,
+    request: GatewayAuthRequest,
+) -> AuthResult {
     info!(
         action = %request.action,
         server = %request.server_name,
         tool = %request.tool_name,
+        dry_run = request.dry_run,
         "Gateway authorization request"
     );
 
-    // TODO: Extract user context from JWT token (passed in Authorization header)
-    // For now, placeholder
-    let user = UserContext {
-        user_id: "user123".to_string(),
-        email: "user@example.com".to_string(),
-        roles: vec!["developer".to_string()],
-        permissions: vec!["mcp:invoke".to_string()],
+    let mut opa_input_json = gateway_input(user, client, context, &state.classifier(), &request);
+    state
+        .enricher()
+        .enrich(&mut opa_input_json, context.client_ip);
+    with_findings(state, &mut opa_input_json);
+    if let Some(schema) = &state.input_schema {
+        if let Err(message) = schema.check(&opa_input_json) {
+            warn!(error = %message, "Rejected malformed authorization request");
+            return Err(Problem::InvalidRequest(message));
+        }
+    }
+
+    let audit = state.audited().then(|| {
+        AuditRecord::new(
+            request_id.clone(),
+            Endpoint::Authorize.route(),
+            &user.user_id,
+            serde_json::json!({
+                "action": request.action,
+                "server": request.server_name,
+                "tool": request.tool_name,
+                "sensitivity": opa_input_json["resource"]["sensitivity"],
+            }),
+        )
+    });
+
+    let fallback = state
+        .fallback
+        .as_ref()
+        .filter(|_| !request.dry_run)
+        .map(|_| fallback::Request::new(&user.token, &request_id, &opa_input_json));
+    let audit = audit.map(|record| AuditRecord {
+        dry_run: request.dry_run,
+        ..record
+    });
+    if request.dry_run {
+        return dry_run(state, &request_id, opa_input_json, audit).await;
+    }
+    let captured = state
+        .capture
+        .as_ref()
+        .filter(|capture| capture.sampled())
+        .map(|capture| (capture, opa_input_json.clone()));
+    let decision = authorize_input(
+        state,
+        Endpoint::Authorize,
+        &request_id,
+        opa_input_json,
+        audit,
+        fallback,
+    )
+    .await;
+    if let (Some((capture, input)), Ok(decision)) = (captured, &decision) {
+        capture.record(Endpoint::Authorize.route(), &request, input, decision);
+    }
+    let (outcome, revision) = match &decision {
+        Ok(decision) if decision.allow => ("allow", decision.policy_revision.as_str()),
+        Ok(decision) => ("deny", decision.policy_revision.as_str()),
+        Err(_) => ("error", ""),
     };
+    state
+        .metrics
+        .tool_decision(&request.server_name, &request.tool_name, outcome, revision);
+    let decision = enforce_step_up(state, Endpoint::Authorize, user, decision?)?;
+    enforce_quotas(state, Endpoint::Authorize, user, decision).await
+}
 
-    // Build cache key
-    let cache_key = format!(
-        "auth:{}:{}:{}",
-        user.user_id, request.action, request.server_name
-    );
+/// Evaluate a gateway authorization fresh and log it as a dry run, leaving
+/// the cache, fallback and shadow comparison out
+async fn dry_run(
+    state: &AppState,
+    request_id: &str,
+    mut opa_input_json: serde_json::Value,
+    audit: Option<AuditRecord>,
+) -> AuthResult {
+    let endpoint = Endpoint::Authorize;
+    let started = Instant::now();
+    let input_digest = decision_log::input_digest(&opa_input_json);
+    opa_input_json["request_id"] = serde_json::json!(request_id);
+    let query = endpoint.query(&state.queries, &opa_input_json);
+    let record = state
+        .decision_log
+        .as_ref()
+        .map(|log| log.new_record(query, input_digest, &opa_input_json));
 
-    // Try cache first
-    if let Some(cached) = state.cache.get(&cache_key) {
-        info!(cache_key = %cache_key, "Cache hit");
-        if let Ok(response) = serde_json::from_str::<GatewayAuthResponse>(&cached) {
-            return Ok(Json(response));
+    let result = evaluate_decision(state, endpoint, &opa_input_json)
+        .await
+        .map(|decision| GatewayAuthResponse {
+            cache_ttl: 0,
+            dry_run: true,
+            ..decision
+        });
+    let outcome = match &result {
+        Ok(decision) => {
+            info!(
+                allow = decision.allow,
+                reason = %decision.reason,
+                filtered_parameters = ?decision.filtered_parameters,
+                "Dry-run decision"
+            );
+            if decision.allow {
+                "allow"
+            } else {
+                "deny"
+            }
+        }
+        Err(_) => "error",
+    };
+    state.metrics.dry_run(outcome);
+
+    if let (Some(log), Some(mut record)) = (&state.decision_log, record) {
+        match &result {
+            Ok(decision) => {
+                record.result = serde_json::to_value(decision).ok();
+                record.revision = Some(decision.policy_revision.clone());
+            }
+            Err(e) => record.error = Some(e.to_string()),
         }
+        record.dry_run = true;
+        record.latency_us = started.elapsed().as_micros() as u64;
+        log.record(record);
     }
 
-    // Build OPA input as regorus Value via JSON round-trip
-    let opa_input_json = serde_json::json!({
-        "user": {
-            "id": user.user_id,
-            "email": user.email,
-            "roles": user.roles,
-            "permissions": user.permissions,
-        },
+    if let Some(mut record) = audit {
+        record.decision = outcome;
+        match &result {
+            Ok(decision) => {
+                record.reason = Some(decision.reason.clone());
+                record.policy_revision = Some(decision.policy_revision.clone());
+            }
+            Err(e) => record.reason = Some(e.to_string()),
+        }
+        record.latency_us = started.elapsed().as_micros() as u64;
+        state.audit(record).await;
+    }
+
+    result
+}
+
+/// Authorize a single agent-to-agent request
+async fn authorize_a2a_request(
+    state: &AppState,
+    user: &UserContext,
+    client: Option<&ClientIdentity>,
+    context: &RequestContext,
+    request_id: String,
+    mut request: A2AAuthRequest,
+) -> AuthResult {
+    if state.agent_identity {
+        let Some(spiffe_id) = client.and_then(|c| c.spiffe_id.as_deref()) else {
+            return Err(Problem::Unauthenticated(
+                "A2A requests must present an X.509 SVID".to_string(),
+            ));
+        };
+        if !request.source_agent.id.is_empty() && request.source_agent.id != spiffe_id {
+            return Err(Problem::Forbidden(format!(
+                "Source agent {:?} is not the caller, {:?}",
+                request.source_agent.id, spiffe_id
+            )));
+        }
+        request.source_agent.id = spiffe_id.to_string();
+    }
+    info!(
+        source = %request.source_agent.id,
+        target = %request.target_agent.id,
+        capability = %request.capability,
+        "A2A authorization request"
+    );
+    let delegation = delegation::resolve(
+        state.jwt().as_deref(),
+        &request.delegation_tokens,
+        &request.source_agent.id,
+    )
+    .await
+    .map_err(|e| {
+        warn!(source = %request.source_agent.id, error = %e, "Rejected A2A delegation chain");
+        e
+    })?;
+
+    let mut opa_input_json = a2a_input(user, client, context, &request, delegation.as_ref());
+    state
+        .enricher()
+        .enrich(&mut opa_input_json, context.client_ip);
+    with_findings(state, &mut opa_input_json);
+    let audit = state.audited().then(|| {
+        AuditRecord::new(
+            request_id.clone(),
+            Endpoint::AuthorizeA2a.route(),
+            &user.user_id,
+            serde_json::json!({
+                "source_agent": request.source_agent.id,
+                "target_agent": request.target_agent.id,
+                "capability": request.capability,
+                "principal": delegation.as_ref().map(|d| &d.principal),
+            }),
+        )
+    });
+
+    let captured = state
+        .capture
+        .as_ref()
+        .filter(|capture| capture.sampled())
+        .map(|capture| (capture, opa_input_json.clone()));
+    let decision = authorize_input(
+        state,
+        Endpoint::AuthorizeA2a,
+        &request_id,
+        opa_input_json,
+        audit,
+        None,
+    )
+    .await?;
+    if let Some((capture, input)) = captured {
+        capture.record(Endpoint::AuthorizeA2a.route(), &request, input, &decision);
+    }
+    let decision = enforce_step_up(state, Endpoint::AuthorizeA2a, user, decision)?;
+    enforce_quotas(state, Endpoint::AuthorizeA2a, user, decision).await
+}
+
+/// Check a decision's step-up obligation against the caller's token, where
+/// the gateway enforces it, dropping the obligation once it is met
+fn enforce_step_up(
+    state: &AppState,
+    endpoint: Endpoint,
+    user: &UserContext,
+    mut decision: GatewayAuthResponse,
+) -> AuthResult {
+    if !state.step_ups.enforced() {
+        return Ok(decision);
+    }
+    let Some(obligations) = decision.obligations.as_mut() else {
+        return Ok(decision);
+    };
+    let Some(step_up) = &obligations.step_up else {
+        return Ok(decision);
+    };
+    if let Err(problem) = state.step_ups.check(user, step_up) {
+        state.metrics.step_up_challenge(endpoint.route());
+        return Err(problem);
+    }
+    obligations.step_up = None;
+    Ok(decision)
+}
+
+/// Count an allowed decision against the quotas its obligations set
+async fn enforce_quotas(
+    state: &AppState,
+    endpoint: Endpoint,
+    user: &UserContext,
+    decision: GatewayAuthResponse,
+) -> AuthResult {
+    if !decision.allow {
+        return Ok(decision);
+    }
+    let quotas = Quota::from_obligations(decision.obligations.as_ref())
+        .map_err(Problem::PolicyEvaluation)?;
+    if quotas.is_empty() {
+        return Ok(decision);
+    }
+    if let Err(problem) = state.quotas.consume(user.tenant.as_deref(), &quotas).await {
+        state.metrics.quota_exceeded(endpoint.route());
+        return Err(problem);
+    }
+    Ok(decision)
+}
+
+/// Policy input of a gateway authorization, before its request id; a call
+/// that doesn't give its sensitivity is classified by `classifier`
+fn gateway_input(
+    user: &UserContext,
+    client: Option<&ClientIdentity>,
+    context: &RequestContext,
+    classifier: &Classifier,
+    request: &GatewayAuthRequest,
+) -> serde_json::Value {
+    let sensitivity = match request.sensitivity_level.as_deref() {
+        Some(level) => level,
+        None => {
+            let classification = classifier.classify(&Call {
+                server: &request.server_name,
+                tool: &request.tool_name,
+                description: None,
+                parameters: request.parameters.as_ref(),
+            });
+            debug!(
+                tool = %request.tool_name,
+                level = %classification.level,
+                rule = classification.rule.unwrap_or("default"),
+                "Classified tool call"
+            );
+            classification.level.as_str()
+        }
+    };
+    let mut input = serde_json::json!({
+        "user": user_input(user),
         "action": request.action,
         "resource": {
             "server": request.server_name,
             "tool": request.tool_name,
-            "sensitivity": request.sensitivity_level.unwrap_or_else(|| "medium".to_string()),
+            "sensitivity": sensitivity,
         },
         "parameters": request.parameters,
         "context": request.context,
     });
+    if let Some(client) = client {
+        input["client"] = serde_json::json!(client);
+    }
+    context.apply(&mut input);
+    input
+}
 
-    let opa_input = match grid_opa::Value::from_json_str(&opa_input_json.to_string()) {
-        Ok(v) => v,
-        Err(e) => {
-            error!(error = %e, "Failed to build OPA input");
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Failed to build OPA input: {}", e),
-            ));
-        }
+/// Who the input's caller is, for routing them to a canary policy
+fn caller(input: &serde_json::Value) -> &str {
+    input["user"]["id"].as_str().unwrap_or_default()
+}
+
+/// Policy input of an A2A authorization, before its request id
+fn a2a_input(
+    user: &UserContext,
+    client: Option<&ClientIdentity>,
+    context: &RequestContext,
+    request: &A2AAuthRequest,
+    delegation: Option<&Delegation>,
+) -> serde_json::Value {
+    let mut input = serde_json::json!({
+        "user": user_input(user),
+        "source_agent": request.source_agent,
+        "target_agent": request.target_agent,
+        "capability": request.capability,
+        "delegation_chain": request.delegation_chain,
+        "parameters": request.parameters,
+        "context": request.context,
+    });
+    if let Some(delegation) = delegation {
+        input["delegation"] = serde_json::json!(delegation);
+    }
+    if let Some(client) = client {
+        input["client"] = serde_json::json!(client);
+    }
+    context.apply(&mut input);
+    input
+}
+
+/// Set `input.findings` from what detection finds in `input.parameters`,
+/// if enabled
+fn with_findings(state: &AppState, input: &mut serde_json::Value) {
+    let Some(detector) = &state.detector else {
+        return;
     };
+    let findings = detector.detect(&input["parameters"]);
+    for finding in &findings {
+        state.metrics.finding(&finding.rule);
+    }
+    input["findings"] = serde_json::json!(findings);
+}
 
-    // Evaluate policy with Rust OPA engine
-    let result = {
-        let mut engine = state.opa_engine.lock().await;
-        engine.evaluate("data.mcp.gateway.allow", opa_input)
+/// The context of a request from `client_ip` with `headers`, by `user`,
+/// for `input.context` (see `sark_context`)
+fn request_context(
+    headers: &HeaderMap,
+    client_ip: Option<IpAddr>,
+    user: &UserContext,
+) -> RequestContext {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
     };
+    RequestContext::from_headers(header, client_ip).with_tenant(user.tenant.clone())
+}
 
-    match result {
-        Ok(value) => {
-            // Extract allow bool from regorus Value — stub: treat Bool(true) as allow
-            let allow = matches!(value, grid_opa::Value::Bool(true));
-            let reason = if allow {
-                "Policy evaluated: allowed".to_string()
-            } else {
-                "Policy evaluated: denied".to_string()
-            };
+/// `input.context`, for the gateway to add to; none if the caller sent
+/// one that isn't an object
+fn context_mut(
+    input: &mut serde_json::Value,
+) -> Option<&mut serde_json::Map<String, serde_json::Value>> {
+    let context = &mut input["context"];
+    if context.is_null() {
+        *context = serde_json::json!({});
+    }
+    context.as_object_mut()
+}
+
+/// The verified caller, as policy input
+fn user_input(user: &UserContext) -> serde_json::Value {
+    let mut input = serde_json::json!({
+        "id": user.user_id,
+        "email": user.email,
+        "roles": user.roles,
+        "permissions": user.permissions,
+    });
+    if let Some(name) = &user.api_key {
+        input["api_key"] = name.as_str().into();
+    }
+    if let Some(tenant) = &user.tenant {
+        input["tenant"] = tenant.as_str().into();
+    }
+    let authentication = &user.authentication;
+    if let Some(acr) = &authentication.acr {
+        input["acr"] = acr.as_str().into();
+    }
+    if !authentication.amr.is_empty() {
+        input["amr"] = serde_json::json!(authentication.amr);
+    }
+    if let Some(auth_time) = authentication.auth_time {
+        input["auth_time"] = auth_time.into();
+    }
+    input
+}
+
+/// Decide a built policy input for `endpoint`, recording it in the
+/// decision and audit logs and comparing against the shadow policy.
+/// Evaluation failures go to the fallback along with `fallback`, if given.
+/// An override (see `overrides`) decides in place of the cache and policy.
+///
+/// The input gets the request's id as `input.request_id`, after its cache
+/// key is taken: a cached or coalesced decision was evaluated with the id
+/// of the request that first made it, so policies can log the id but
+/// should not decide by it. `input.context.trace_id` is likewise left out
+/// of the key.
+async fn authorize_input(
+    state: &AppState,
+    endpoint: Endpoint,
+    request_id: &str,
+    mut opa_input_json: serde_json::Value,
+    audit: Option<AuditRecord>,
+    fallback: Option<fallback::Request>,
+) -> AuthResult {
+    let tenant = state.tenants.of(&opa_input_json)?;
+    let trace_id = context_mut(&mut opa_input_json).and_then(|context| context.remove("trace_id"));
+    let input_digest = decision_log::input_digest(&opa_input_json);
+    let cache_key = endpoint.cache_key(state, &opa_input_json, &input_digest);
+    opa_input_json["request_id"] = serde_json::json!(request_id);
+    if let (Some(trace_id), Some(context)) = (trace_id, context_mut(&mut opa_input_json)) {
+        context.insert("trace_id".to_string(), trace_id);
+    }
+
+    let started = Instant::now();
+    let query = endpoint.query(&state.queries, &opa_input_json).to_string();
+    let record = state
+        .decision_log
+        .as_ref()
+        .map(|log| log.new_record(&query, input_digest, &opa_input_json));
+
+    let shadow_input = state.shadow.as_ref().map(|_| opa_input_json.clone());
+
+    let metric_tenant = opa_input_json["user"]["tenant"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string();
+    // Overrides come before the cache, so a cached allow can't outlast one
+    let overridden = match endpoint {
+        Endpoint::Authorize => {
+            let resource = &opa_input_json["resource"];
+            let server = resource["server"].as_str().unwrap_or_default();
+            let tool = resource["tool"].as_str().unwrap_or_default();
+            state.overrides.decide(server, tool)
+        }
+        Endpoint::AuthorizeA2a => None,
+    };
+    let is_override = overridden.is_some();
+    let (mut result, cached) = match overridden {
+        Some(decision) => (Ok(decision), false),
+        None => decide(state, endpoint, tenant, cache_key, opa_input_json).await,
+    };
+    let mut fell_back = false;
+    // The SARK API doesn't know tenants' policies
+    let fallback = fallback.map(|request| match tenant {
+        Some(_) => request.local(),
+        None => request,
+    });
+    if let (Some(handler), Some(request)) = (&state.fallback, fallback) {
+        if let Err(
+            problem @ (Problem::PolicyEvaluation(_)
+            | Problem::EvaluationTimeout(_)
+            | Problem::Internal(_)),
+        ) = &result
+        {
+            result = handler
+                .decide(&state.metrics, request, problem.clone())
+                .await;
+            fell_back = true;
+        }
+    }
 
-            let response = GatewayAuthResponse {
-                allow,
-                reason: reason.clone(),
-                filtered_parameters: None,
-                cache_ttl: 300,
+    if !is_override {
+        state
+            .metrics
+            .cache_lookup(endpoint.cache(state, tenant).name(), cached);
+    }
+    let outcome = match &result {
+        Ok(decision) if decision.allow => "allow",
+        Ok(_) => "deny",
+        Err(_) => "error",
+    };
+    state
+        .metrics
+        .decision(endpoint.route(), &metric_tenant, outcome);
+    let revision = tenant.map_or(&state.revision, |tenant| &tenant.revision);
+    if let (Ok(decision), false) = (&result, fell_back || is_override) {
+        if revision.rolling_out() {
+            state
+                .metrics
+                .rollout_decision(&decision.policy_revision, outcome);
+        }
+    }
+    // Fallback decisions are logged as such
+    if let (Ok(decision), false) = (&result, fell_back) {
+        macro_rules! log_decision {
+            ($level:ident) => {
+                $level!(
+                    decision = outcome,
+                    allow = decision.allow,
+                    reason = %decision.reason,
+                    cached = cached,
+                    policy_revision = %decision.policy_revision,
+                    cache_ttl = decision.cache_ttl,
+                    "Authorization decision"
+                )
             };
+        }
+        // At the level the policy obliges, to make sensitive access stand out
+        match decision.obligations.as_ref().and_then(|o| o.log_level) {
+            Some(LogLevel::Error) => log_decision!(error),
+            Some(LogLevel::Warn) => log_decision!(warn),
+            _ => log_decision!(info),
+        }
+    }
 
-            // Cache the decision
-            if let Ok(cached_value) = serde_json::to_string(&response) {
-                if let Err(e) = state.cache.set(cache_key.clone(), cached_value, Some(300)) {
-                    error!(error = %e, "Failed to cache authorization decision");
-                }
+    let shadow_input = shadow_input.filter(|_| !fell_back && !is_override);
+    if let (Some(shadow), Some(input), Ok(decision)) = (&state.shadow, shadow_input, &result) {
+        let shadow = shadow.clone();
+        let decision = decision.clone();
+        tokio::spawn(async move { shadow.compare(&query, &input, &decision).await });
+    }
+
+    if let (Some(log), Some(mut record)) = (&state.decision_log, record) {
+        match &result {
+            Ok(decision) => {
+                record.result = serde_json::to_value(decision).ok();
+                record.revision = Some(decision.policy_revision.clone());
             }
+            Err(e) => record.error = Some(e.to_string()),
+        }
+        record.cached = cached;
+        record.latency_us = started.elapsed().as_micros() as u64;
+        log.record(record);
+    }
 
-            info!(allow = allow, reason = %reason, "Authorization decision");
-            Ok(Json(response))
+    if let Some(mut record) = audit {
+        record.decision = outcome;
+        match &result {
+            Ok(decision) => {
+                record.reason = Some(decision.reason.clone());
+                record.policy_revision = Some(decision.policy_revision.clone());
+            }
+            Err(e) => record.reason = Some(e.to_string()),
         }
-        Err(e) => {
-            error!(error = %e, "Policy evaluation failed");
-            Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Policy evaluation error: {}", e),
-            ))
+        record.cached = cached;
+        record.latency_us = started.elapsed().as_micros() as u64;
+        state.audit(record).await;
+    }
+
+    result
+}
+
+/// Look the decision up in cache, evaluating on a miss; the flag reports
+/// whether it was served from cache. Decisions cached under another policy
+/// revision count as misses.
+async fn decide(
+    state: &AppState,
+    endpoint: Endpoint,
+    tenant: Option<&Tenant>,
+    cache_key: String,
+    opa_input_json: serde_json::Value,
+) -> (AuthResult, bool) {
+    // Coalescing is shared across endpoints, so its key names the namespace
+    let cache = endpoint.cache(state, tenant);
+    let inflight_key = format!("{}:{}", cache.name(), cache_key);
+    let revision = tenant.map_or(&state.revision, |tenant| &tenant.revision);
+    if let Some(precompute) = &state.precompute {
+        precompute.record(cache.name(), endpoint, &cache_key, &opa_input_json);
+    }
+
+    // Try cache first
+    let lookup = cache
+        .get(&cache_key)
+        .instrument(info_span!("cache.lookup", namespace = cache.name()))
+        .await;
+    let entry = lookup
+        .and_then(|cached| {
+            serde_json::from_str::<CachedDecision<GatewayAuthResponse>>(&cached).ok()
+        })
+        .filter(|entry| {
+            let current = revision.serves(&entry.decision.policy_revision, caller(&opa_input_json));
+            if !current {
+                debug!(
+                    cache_key = %cache_key,
+                    revision = %entry.decision.policy_revision,
+                    "Cached decision from another policy revision; re-evaluating"
+                );
+            }
+            current
+        });
+    if let Some(entry) = entry {
+        if entry.is_stale(state.ttls().soft) {
+            // Serve the stale decision now and refresh it in the
+            // background; concurrent stale hits join the same refresh.
+            info!(
+                cache_key = %cache_key,
+                negative = !entry.decision.allow,
+                "Cache hit (stale, revalidating)"
+            );
+            let state = state.clone();
+            tokio::spawn(async move {
+                let _ = state
+                    .inflight
+                    .run(&inflight_key, || {
+                        evaluate_and_cache(&state, endpoint, cache_key, opa_input_json)
+                    })
+                    .await;
+            });
+        } else {
+            info!(
+                cache_key = %cache_key,
+                negative = !entry.decision.allow,
+                "Cache hit"
+            );
+        }
+        let decision = GatewayAuthResponse {
+            cached: true,
+            ..entry.decision
+        };
+        return (Ok(decision), true);
+    }
+
+    // Concurrent misses on the same key share a single evaluation
+    let result = state
+        .inflight
+        .run(&inflight_key, || {
+            evaluate_and_cache(state, endpoint, cache_key.clone(), opa_input_json)
+        })
+        .await;
+    (result, false)
+}
+
+/// Evaluate `endpoint`'s policy for `opa_input_json` and cache the decision
+async fn evaluate_and_cache(
+    state: &AppState,
+    endpoint: Endpoint,
+    cache_key: String,
+    opa_input_json: serde_json::Value,
+) -> AuthResult {
+    let started = Instant::now();
+    let response = evaluate_decision(state, endpoint, &opa_input_json).await?;
+    // What a hit saves, for cost-aware eviction
+    let cost = started.elapsed();
+
+    // Cache the decision, unless its TTL is 0
+    let cached_value = serde_json::to_string(&CachedDecision::new(&response))
+        .ok()
+        .filter(|_| response.cache_ttl > 0);
+    if let Some(cached_value) = cached_value {
+        let cache = endpoint.cache(state, state.tenants.of(&opa_input_json)?);
+        if let Err(e) = cache
+            .set_with_cost(&cache_key, cached_value, response.cache_ttl.into(), cost)
+            .instrument(info_span!("cache.write", namespace = cache.name()))
+            .await
+        {
+            error!(error = %e, "Failed to cache authorization decision");
+        }
+    }
+
+    Ok(response)
+}
+
+/// Evaluate `endpoint`'s policy for `opa_input_json` into a decision, with
+/// the TTL it should be cached for
+async fn evaluate_decision(
+    state: &AppState,
+    endpoint: Endpoint,
+    opa_input_json: &serde_json::Value,
+) -> AuthResult {
+    let query = endpoint.query(&state.queries, opa_input_json);
+    let (document, policy_revision) =
+        evaluate(state, query, endpoint.route(), opa_input_json).await?;
+
+    let (allow, reason) = verdict(&document);
+    let reason_code = document.get("reason_code").and_then(|code| {
+        let code = code.as_str();
+        if code.is_none() {
+            warn!("Ignoring reason_code from policy that isn't a string");
         }
+        code.map(str::to_string)
+    });
+
+    // A `cache_ttl` from the policy wins. Otherwise denies are cached too
+    // (negative caching) so retry storms don't re-evaluate, but for less
+    // time. Spread expiry so decisions cached in a burst don't all expire
+    // (and hit OPA) at once, and never keep one past the ceiling.
+    let ttls = state.ttls();
+    let policy_ttl = document.get("cache_ttl").and_then(|ttl| {
+        let seconds = ttl.as_u64();
+        if seconds.is_none() {
+            warn!(cache_ttl = %ttl, "Ignoring invalid cache_ttl from policy");
+        }
+        seconds
+    });
+    let base_ttl = policy_ttl.unwrap_or(if allow {
+        endpoint.allow_ttl(&ttls)
+    } else {
+        ttls.deny
+    });
+    let ttl = sark_store::jittered_ttl(base_ttl, ttls.jitter_pct).min(ttls.max);
+
+    Ok(GatewayAuthResponse {
+        allow,
+        reason,
+        reason_code,
+        filtered_parameters: document.get("filtered_parameters").cloned(),
+        obligations: Obligations::from_document(&document).map_err(Problem::PolicyEvaluation)?,
+        policy_revision,
+        cache_ttl: ttl as u32,
+        dry_run: false,
+        cached: false,
+    })
+}
+
+/// `allow` and `reason` from a policy's document
+fn verdict(document: &serde_json::Value) -> (bool, String) {
+    let allow = matches!(document.get("allow"), Some(serde_json::Value::Bool(true)));
+    let reason = match document.get("reason").and_then(|r| r.as_str()) {
+        Some(reason) => reason.to_string(),
+        None if allow => "Policy evaluated: allowed".to_string(),
+        None => "Policy evaluated: denied".to_string(),
+    };
+    (allow, reason)
+}
+
+/// Evaluate a decision that isn't cached (its input is unique to the
+/// request), recording it in metrics and the decision and audit logs like
+/// cached ones. Returns the policy's document and revision.
+async fn decide_uncached(
+    state: &AppState,
+    query: &str,
+    route: &'static str,
+    opa_input_json: &serde_json::Value,
+    audit: Option<AuditRecord>,
+) -> Result<(serde_json::Value, String), Problem> {
+    let started = Instant::now();
+    let record = state.decision_log.as_ref().map(|log| {
+        log.new_record(
+            query,
+            decision_log::input_digest(opa_input_json),
+            opa_input_json,
+        )
+    });
+
+    let result = evaluate(state, query, route, opa_input_json).await;
+
+    let outcome = match &result {
+        Ok((document, _)) if verdict(document).0 => "allow",
+        Ok(_) => "deny",
+        Err(_) => "error",
+    };
+    let tenant = opa_input_json["user"]["tenant"]
+        .as_str()
+        .unwrap_or_default();
+    state.metrics.decision(route, tenant, outcome);
+
+    if let (Some(log), Some(mut record)) = (&state.decision_log, record) {
+        match &result {
+            Ok((document, revision)) => {
+                record.result = Some(document.clone());
+                record.revision = Some(revision.clone());
+            }
+            Err(e) => record.error = Some(e.to_string()),
+        }
+        record.latency_us = started.elapsed().as_micros() as u64;
+        log.record(record);
+    }
+
+    if let Some(mut record) = audit {
+        record.decision = outcome;
+        match &result {
+            Ok((document, revision)) => {
+                record.reason = Some(verdict(document).1);
+                record.policy_revision = Some(revision.clone());
+            }
+            Err(e) => record.reason = Some(e.to_string()),
+        }
+        record.latency_us = started.elapsed().as_micros() as u64;
+        state.audit(record).await;
+    }
+
+    result
+}
+
+/// Evaluate `query` for `opa_input_json` with the active policy (of the
+/// input's tenant, if it has one), or the canary for callers it decides
+/// for, returning the resulting document and the policy's revision.
+/// `route` labels evaluations shed at the concurrency limit.
+async fn evaluate(
+    state: &AppState,
+    query: &str,
+    route: &str,
+    opa_input_json: &serde_json::Value,
+) -> Result<(serde_json::Value, String), Problem> {
+    let tenant = state.tenants.of(opa_input_json)?;
+    let policy = tenant.map_or(&state.policy, |tenant| &tenant.policy);
+    let Some(_slot) = state.shedder.evaluation() else {
+        state.metrics.shed(route, "evaluations");
+        return Err(Problem::Overloaded("Overloaded; retry shortly".to_string()));
+    };
+
+    // Every decision would be a deny; let the fallback make it instead
+    let unloaded_fails = tenant.is_none() && state.fallback.is_some();
+    let metrics = state.metrics.clone();
+    let span = info_span!("opa.evaluate", query = query);
+    let owned_query = query.to_string();
+    let input = opa_input_json.clone();
+    let caller = caller(opa_input_json).to_string();
+    // The lock is only held to pick the policy; evaluations take their own
+    // engine from its pool, so one that times out holds up no others
+    let evaluator = policy.lock().await.serving(&caller).evaluator();
+    // Evaluate policy with Rust OPA engine, or its CEL package
+    let run = move || {
+        if unloaded_fails && evaluator.is_empty() {
+            return Err(Problem::PolicyEvaluation("No policies loaded".to_string()));
+        }
+        let started = Instant::now();
+        let result = span.in_scope(|| evaluator.evaluate(&owned_query, &input));
+        let outcome = match &result {
+            Ok(document) => Outcome::of(document),
+            Err(_) => Outcome::Error,
+        };
+        metrics.evaluation(&owned_query, outcome, started.elapsed());
+        match result {
+            Ok(document) => Ok((document, evaluator.revision().to_string())),
+            Err(e) => {
+                error!(error = %format!("{:#}", e), "Policy evaluation failed");
+                Err(Problem::PolicyEvaluation(format!(
+                    "Policy evaluation error: {:#}",
+                    e
+                )))
+            }
+        }
+    };
+
+    let (document, policy_revision) = match state.shedder.evaluation_timeout() {
+        None => run()?,
+        // On a blocking thread, which a timed-out evaluation is left on
+        // with its engine
+        Some(timeout) => {
+            match tokio::time::timeout(timeout, tokio::task::spawn_blocking(run)).await {
+                Ok(Ok(result)) => result?,
+                Ok(Err(e)) => {
+                    error!(error = %e, "Policy evaluation panicked");
+                    return Err(Problem::Internal(format!(
+                        "Policy evaluation panicked: {}",
+                        e
+                    )));
+                }
+                Err(_) => {
+                    state.metrics.evaluation_timeout(query);
+                    error!(
+                        query = query,
+                        timeout_ms = timeout.as_millis() as u64,
+                        "Policy evaluation timed out"
+                    );
+                    return Err(Problem::EvaluationTimeout(format!(
+                        "Policy evaluation exceeded {}ms",
+                        timeout.as_millis()
+                    )));
+                }
+            }
+        }
+    };
+
+    let Some(schema) = &state.result_schema else {
+        return Ok((document, policy_revision));
+    };
+    let Err(message) = schema.check(&document) else {
+        return Ok((document, policy_revision));
+    };
+    state.metrics.malformed_result(query);
+    error!(query = query, error = %message, "Policy returned a malformed result");
+    match schema.replacement(&message) {
+        Some(deny) => Ok((deny, policy_revision)),
+        None => Err(Problem::MalformedResult(message)),
     }
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let args = Args::parse();
+    let matches = Args::command().get_matches();
+    let args = Args::from_arg_matches(&matches)?;
+
+    if let Some(command) = &args.command {
+        let ok = match command {
+            Command::TestPolicies { dir } => {
+                // Templates render with the variables the server would use
+                let config = GatewayConfig::load(&args.config, given(&matches, "config"))?;
+                commands::test_policies(dir, &config.policy.vars)?
+            }
+            Command::CheckPolicy {
+                path,
+                policy_cache_dir,
+                format,
+            } => {
+                let config = GatewayConfig::load(&args.config, given(&matches, "config"))?;
+                commands::check_policy(
+                    path,
+                    &config.policy.vars,
+                    policy_cache_dir.as_deref(),
+                    *format == OutputFormat::Json,
+                )?
+            }
+            Command::Eval {
+                policy_dir,
+                input,
+                query,
+                request,
+                claims,
+                explain,
+                format,
+            } => {
+                // Callers are built with the claim mapping the server uses,
+                // and evaluated against the packages it would
+                let config = GatewayConfig::load(&args.config, given(&matches, "config"))?;
+                commands::eval(commands::Eval {
+                    policy_dir,
+                    input,
+                    query: query.as_deref(),
+                    request: *request,
+                    claims: claims.as_deref(),
+                    vars: &config.policy.vars,
+                    mapping: &config.claims,
+                    classify: &config.classify,
+                    queries: &config.queries,
+                    explain: *explain,
+                    json: *format == OutputFormat::Json,
+                })?
+            }
+            Command::Bench {
+                target,
+                rps,
+                duration,
+                requests_file,
+                token,
+                concurrency,
+                format,
+            } => {
+                bench::run(bench::Options {
+                    target,
+                    rps: *rps,
+                    duration: *duration,
+                    requests_file,
+                    token: token.as_deref(),
+                    concurrency: *concurrency,
+                    format: *format,
+                })
+                .await?
+            }
+            Command::BenchCache {
+                threads,
+                shards,
+                entries,
+                keys,
+                duration,
+                eviction,
+                format,
+            } => cachebench::run(cachebench::Options {
+                threads: *threads,
+                shards: *shards,
+                entries: *entries,
+                keys: *keys,
+                duration: *duration,
+                eviction: *eviction,
+                format: *format,
+            })?,
+            Command::Audit {
+                command: AuditCommand::Verify { files, key, format },
+            } => commands::audit_verify(files, key.as_deref(), *format == OutputFormat::Json)?,
+        };
+        std::process::exit(if ok { 0 } else { 1 });
+    }
+
+    let config_required = given(&matches, "config");
+    let mut config = GatewayConfig::load(&args.config, config_required)?;
+    apply_flags(&mut config, &args, &matches);
+    check_config(&config, &args)?;
 
-    // Initialize logging
-    tracing_subscriber::fmt()
-        .with_env_filter(&args.log_level)
-        .init();
+    // Initialize logging (and trace export)
+    let (tracer_provider, log_filter) = telemetry::init(
+        &config.log.level,
+        config.log.format,
+        args.otlp_endpoint.as_deref(),
+    )?;
 
     info!(
         version = env!("CARGO_PKG_VERSION"),
-        listen = %args.listen,
+        git_sha = env!("SARK_GIT_SHA"),
+        listen = %config.listen,
+        config = %args.config.display(),
         "Starting SARK Gateway (Rust hot path)"
     );
 
     // Initialize OPA engine
-    // TODO: Load policies from config
-    let opa_engine = Arc::new(Mutex::new(
-        OPAEngine::new().context("Failed to initialize OPA engine")?,
+    if config.policy.dir.is_none() && config.policy.bundle_url.is_none() {
+        if PolicySet::has_embedded() {
+            info!("No policy directory or bundle configured; using the embedded policies");
+        } else if config.fallback.url.is_none() && config.fallback.failure_mode.is_empty() {
+            warn!("No policy directory or bundle configured; every request will be denied");
+        } else {
+            warn!("No policy directory or bundle configured; decisions go to the fallback");
+        }
+    }
+    let (set, bundle_loader) = reload::load_policies(&config.policy, &args).await?;
+    let active = ActivePolicy::new(set.compile()?, set, config.max_engines());
+    let checks = match &args.startup_checks {
+        Some(dir) => Some(Arc::new(StartupChecks::load(dir, &config.queries)?)),
+        None => None,
+    };
+    if let Some(checks) = &checks {
+        if let Err(e) = checks.verify(&active) {
+            error!(
+                error = %format!("{:#}", e),
+                "Policy failed startup checks; not ready until one passes"
+            );
+        }
+    }
+    info!(revision = %active.revision(), "Policy active");
+    let mut store = PolicyStore::new(active, args.policy_history);
+    store.set_canary_weight(config.rollout.canary_weight);
+    store.set_max_engines(config.max_engines());
+    store.set_checks(checks.clone());
+    let revision = store.current_revision();
+    let policy = Arc::new(Mutex::new(store));
+
+    let cache = Arc::new(config.cache.namespaces.iter().fold(
+        Store::new(
+            config.cache.eviction,
+            config.cache.max_entries,
+            config.cache.allow_ttl,
+            config.cache.shards,
+            config.cache.max_pinned,
+        ),
+        |store, (name, namespace)| {
+            store.partition(
+                name,
+                namespace.eviction.unwrap_or(config.cache.eviction),
+                namespace.max_entries,
+                namespace.max_ttl,
+            )
+        },
+    ));
+
+    let l2 = match &args.redis_url {
+        Some(url) => {
+            let tier = RedisTier::connect(url)
+                .await
+                .context("Failed to connect to Redis L2 cache")?;
+            info!("Redis L2 decision cache enabled");
+            Some(tier)
+        }
+        None => None,
+    };
+
+    if config.cache.cleanup_interval > 0 {
+        tokio::spawn(cache::janitor(
+            cache.clone(),
+            Duration::from_secs(config.cache.cleanup_interval),
+        ));
+    }
+
+    let bloom = (config.cache.bloom_fp_rate > 0.0).then(|| bloom::Sizing {
+        expected: config.cache.max_entries,
+        fp_rate: config.cache.bloom_fp_rate,
+        max_ttl: Duration::from_secs(config.cache.max_ttl),
+    });
+    // Only the admin API and --cache-snapshot snapshot the decision caches
+    let journaled = config.admin.token.is_some() || args.cache_snapshot.is_some();
+    let decision_cache = |namespace: Namespace| {
+        let capacity = config.cache.capacity_of(namespace.name());
+        let namespace = match bloom {
+            Some(sizing) => namespace.filtered(bloom::Sizing {
+                expected: capacity,
+                ..sizing
+            }),
+            None => namespace,
+        };
+        if journaled {
+            namespace.journaled(capacity)
+        } else {
+            namespace
+        }
+    };
+    let decisions = decision_cache(Namespace::new(
+        cache.clone(),
+        Endpoint::Authorize.namespace(),
+        l2.clone(),
     ));
+    let a2a_decisions = decision_cache(Namespace::new(
+        cache.clone(),
+        Endpoint::AuthorizeA2a.namespace(),
+        l2.clone(),
+    ));
+    let quotas = Quotas::new(Namespace::new(cache.clone(), "quota", l2.clone()));
+    let decision_caches = vec![decisions.clone(), a2a_decisions.clone()];
+
+    let tenants = Tenants::load(
+        &config.tenants,
+        &config.policy.vars,
+        &cache,
+        &l2,
+        bloom,
+        args.policy_history,
+        config.max_engines(),
+    )?;
+    if tenants.len() > 0 {
+        info!(tenants = tenants.len(), "Tenant policies loaded");
+    }
+
+    for name in config.cache.key_fields.keys() {
+        if !decision_caches.iter().any(|ns| ns.name() == name) {
+            bail!("Unknown cache namespace {:?} in cache.key_fields", name);
+        }
+    }
+
+    let overrides = Arc::new(Overrides::new(&config.overrides, l2.clone()));
+    if !config.overrides.is_empty() {
+        info!(
+            overrides = config.overrides.len(),
+            "Decision overrides configured"
+        );
+    }
+
+    if let Some(url) = &args.redis_url {
+        let mut namespaces = decision_caches.clone();
+        namespaces.extend(tenants.decision_caches());
+        tokio::spawn(cache::invalidation_listener(
+            url.clone(),
+            namespaces,
+            overrides.clone(),
+        ));
+    }
+
+    let bundle = bundle_loader.as_ref().map(bundle::BundleLoader::status);
+    let followers = reload::follow_policies(
+        &config.policy,
+        bundle_loader,
+        policy.clone(),
+        &decision_caches,
+    )?;
+
+    let decision_log = match &args.decision_log {
+        Some(target) => Some(Arc::new(
+            DecisionLog::open(
+                target,
+                args.decision_log_batch_size,
+                args.decision_log_queue_size,
+            )
+            .await?
+            // Only the admin API replays them
+            .retaining_inputs(if config.admin.token.is_some() {
+                args.decision_log_replay_buffer
+            } else {
+                0
+            }),
+        )),
+        None => None,
+    };
 
-    // Initialize cache: 10K entries, 5-minute default TTL
-    let cache = Arc::new(LRUTTLCache::new(10_000, 300));
+    let signer = Signer::load(&config.signing)?.map(Arc::new);
+    let audit_log = match &args.audit_log {
+        Some(target) => {
+            let rotation = Rotation {
+                max_bytes: args.audit_log_max_size * 1024 * 1024,
+                keep: args.audit_log_max_files as usize,
+            };
+            let chaining = args.audit_chain.then(|| Chaining {
+                signer: signer.clone(),
+                checkpoint_interval: args.audit_checkpoint_interval,
+            });
+            Some(Arc::new(AuditLog::open(target, rotation, chaining).await?))
+        }
+        None => None,
+    };
+    let capture = Capture::open(&config.capture).await?.map(Arc::new);
+
+    let input_schema = match &args.input_schema {
+        Some(path) => Some(Arc::new(InputSchema::load(path)?)),
+        None => None,
+    };
+    let result_schema = match &args.result_schema {
+        Some(path) => Some(Arc::new(ResultSchema::load(path, args.malformed_result)?)),
+        None => None,
+    };
+
+    let shadow = match &args.shadow_policy_dir {
+        Some(dir) => {
+            let set = PolicySet::from_dir(dir).with_context(|| {
+                format!("Failed to load shadow policies from {}", dir.display())
+            })?;
+            let candidate = ActivePolicy::new(set.compile()?, set, config.max_engines());
+            info!(revision = %candidate.revision(), "Shadow policy loaded");
+            Some(Arc::new(Shadow::new(candidate)))
+        }
+        None => None,
+    };
+
+    let jwt = reload::verifier(&config.jwt, &config.claims).await?;
+    let api_keys = ApiKeys::load(&config.api_keys)?;
+    let enricher = Enricher::load(&config.enrich)?;
+    let reasons = Catalog::load(&config.reasons)?;
+    let classifier = Classifier::new(&config.classify)?;
+    if api_keys.len() > 0 {
+        info!(keys = api_keys.len(), "API key authentication enabled");
+    }
+
+    let metrics = Arc::new(
+        Metrics::new(&config.metrics.tool_decisions).context("Failed to register metrics")?,
+    );
 
-    let state = AppState { opa_engine, cache };
+    let rate_limit = Arc::new(RateLimiter::new(&config.rate_limit, l2.clone()));
+    if config.rate_limit.shared {
+        info!("Rate limits shared through Redis");
+    }
+    tokio::spawn(ratelimit::sweeper(
+        rate_limit.clone(),
+        Duration::from_secs(60),
+    ));
 
-    // Build router
-    let app = Router::new()
-        .route("/health", get(health))
-        .route("/gateway/authorize", post(authorize))
+    let refreshers = Refreshers::new(&config.data)?;
+    let state = AppState {
+        policy,
+        revision,
+        decisions,
+        a2a_decisions,
+        key_fields: Arc::new(config.cache.key_fields.clone()),
+        queries: Arc::new(config.queries.clone()),
+        cache,
+        cache_capacity: config.cache.capacity(),
+        inflight: Arc::new(SingleFlight::new()),
+        precompute: Precompute::new(&config.cache.precompute).map(Arc::new),
+        ttls: Arc::new(RwLock::new(Ttls::from(&config.cache))),
+        decision_log: decision_log.clone(),
+        capture: capture.clone(),
+        audit_log: audit_log.clone(),
+        events: Arc::new(Events::new()),
+        scanner: Scanner::new(&config.parameters).map(Arc::new),
+        detector: Detector::new(&config.detection).map(Arc::new),
+        input_schema,
+        result_schema,
+        shadow,
+        jwt: Arc::new(RwLock::new(jwt)),
+        api_keys: Arc::new(RwLock::new(Arc::new(api_keys))),
+        step_ups: Arc::new(StepUps::new(&config.step_up)),
+        decision_headers: Arc::new(DecisionHeaders::new(&config.response_headers)),
+        overrides: overrides.clone(),
+        client_ips: Arc::new(RwLock::new(Arc::new(ClientIps::new(&config.client_ip)))),
+        enricher: Arc::new(RwLock::new(Arc::new(enricher))),
+        reasons: Arc::new(RwLock::new(Arc::new(reasons))),
+        classifier: Arc::new(RwLock::new(Arc::new(classifier))),
+        rate_limit,
+        shedder: Arc::new(Shedder::new(&config.concurrency)),
+        bundle: Arc::new(RwLock::new(bundle)),
+        data_sources: Arc::new(refreshers.statuses()),
+        fallback: Fallback::new(&config.fallback)?.map(Arc::new),
+        proxy: Proxy::new(&config.proxy)?.map(Arc::new),
+        tenants: Arc::new(tenants),
+        quotas: Arc::new(quotas),
+        agent_identity: config.spiffe.agent_identity,
+        metrics: metrics.clone(),
+        reloader: Arc::new(Reloader::new(
+            args.config.clone(),
+            config_required,
+            matches,
+            log_filter,
+            config.clone(),
+            followers,
+        )),
+    };
+
+    #[cfg(unix)]
+    tokio::spawn(reload::on_hangup(state.clone()));
+    refreshers.start(state.clone());
+    precompute::start(&state);
+
+    if let Some(path) = &args.cache_snapshot {
+        snapshot::load(&state, path).await?;
+    }
+    if let Some(path) = &args.cache_preload {
+        warm::preload(&state, path).await?;
+    }
+    let snapshot_state = args.cache_snapshot.as_ref().map(|_| state.clone());
+
+    // Build routers; admin routes get a listener of their own
+    let spec = Arc::new(openapi::document(&config));
+    let serve_spec = {
+        let spec = spec.clone();
+        move || async move { Json(spec) }
+    };
+    let admin_app = config.admin.token.as_deref().map(|token| {
+        let mut router = admin::router(token);
+        // Outside the token check, for browsers
+        if config.admin.swagger_ui {
+            router = router
+                .route(openapi::ROUTE, get(serve_spec.clone()))
+                .route(openapi::DOCS_ROUTE, get(openapi::swagger_ui));
+        }
+        router
+            .route_layer(middleware::from_fn_with_state(
+                metrics.clone(),
+                metrics::track,
+            ))
+            .route_layer(middleware::from_fn(telemetry::trace_request))
+            .with_state(state.clone())
+    });
+    let mut app = Router::new()
+        .route("/health", get(health::health))
+        .route("/livez", get(livez))
+        .route("/readyz", get(readyz))
+        .route("/metrics", get(metrics::render))
+        .route(openapi::ROUTE, get(serve_spec))
+        .route(Endpoint::Authorize.route(), post(authorize))
+        .route(BATCH_ROUTE, post(authorize_batch))
+        .route(Endpoint::AuthorizeA2a.route(), post(authorize_a2a))
+        .route(mcp::ROUTE, post(mcp::authorize))
+        .route_service(&grpc::route(), grpc::service(state.clone()))
+        .route_service(health::GRPC_ROUTE, health::grpc_service(state.clone()))
+        .route_service(&envoy::route(), envoy::service(state.clone()));
+    if config.admission.enabled {
+        app = app.route(admission::ROUTE, post(admission::review));
+    }
+    if !config.proxy.servers.is_empty() {
+        app = app
+            .route(proxy::ROUTE, any(proxy::forward))
+            .route(proxy::SUBPATH_ROUTE, any(proxy::forward));
+    }
+    if let Some(signer) = &signer {
+        let jwks = signer.jwks().clone();
+        app = app
+            .route(signing::JWKS_ROUTE, get(|| async { Json(jwks) }))
+            .route_layer(middleware::from_fn_with_state(
+                signer.clone(),
+                signing::sign,
+            ));
+    }
+    let app = app
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            ratelimit::limit,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            overload::limit,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            clientip::filter,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            Arc::new(config.requests.clone()),
+            limits::enforce,
+        ))
+        .route_layer(middleware::from_fn_with_state(metrics, metrics::track))
+        .route_layer(middleware::from_fn(telemetry::trace_request))
+        // Decision routes enforce their own configured body limits
+        .layer(DefaultBodyLimit::disable())
         .with_state(state);
 
-    // Start server
-    info!("Listening on {}", args.listen);
-    let listener = tokio::net::TcpListener::bind(args.listen).await?;
-    axum::serve(listener, app).await?;
+    // Start servers
+    #[cfg(unix)]
+    let inherited = systemd::listener()?;
+    #[cfg(not(unix))]
+    let inherited = None;
+    let listener = match inherited {
+        Some(listener) => listener,
+        None => Listener::bind(&config.listen, &config.unix_socket).await?,
+    };
+    let admin_listener = match config.admin.listen {
+        Some(addr) => Some(
+            tokio::net::TcpListener::bind(addr)
+                .await
+                .with_context(|| format!("Failed to bind admin listener {}", addr))?,
+        ),
+        None => None,
+    };
+    #[cfg(unix)]
+    let spiffe_tls = match config.spiffe.socket_path() {
+        Some(socket) => Some(spiffe::server_config(&socket, &config.spiffe.trust_domains).await?),
+        None => None,
+    };
+    #[cfg(not(unix))]
+    let spiffe_tls = match config.spiffe.socket {
+        Some(_) => bail!("spiffe.socket requires a Unix platform"),
+        None => None,
+    };
+
+    let signal = shutdown_signal().shared();
+    let server = async {
+        let tls = match (spiffe_tls, config.tls.cert, config.tls.key) {
+            (Some(tls), _, _) => {
+                info!("Listening on {} (TLS, SPIFFE)", listener);
+                Some(tls)
+            }
+            (None, Some(cert), Some(key)) => {
+                info!("Listening on {} (TLS)", listener);
+                Some(tls::server_config(
+                    cert,
+                    key,
+                    config.tls.client_ca.as_deref(),
+                )?)
+            }
+            _ => {
+                info!("Listening on {}", listener);
+                None
+            }
+        };
+        listener
+            .serve(
+                app,
+                tls,
+                config.client_ip.proxy_protocol,
+                Connections::new(&config.connections),
+                signal.clone(),
+            )
+            .await
+    };
+    let admin_server = async {
+        let (Some(listener), Some(app)) = (admin_listener, admin_app) else {
+            return Ok(());
+        };
+        info!("Admin API listening on {}", listener.local_addr()?);
+        axum::serve(listener, app)
+            .with_graceful_shutdown(signal.clone())
+            .await
+            .map_err(anyhow::Error::from)
+    };
+
+    // Policies are compiled and the listeners bound; with startup checks,
+    // ready once a policy passes them
+    #[cfg(unix)]
+    {
+        tokio::spawn(async move {
+            if let Some(checks) = checks {
+                checks.passed().await;
+            }
+            systemd::notify("READY=1");
+        });
+        if let Some(every) = systemd::watchdog_interval() {
+            tokio::spawn(systemd::watchdog(every));
+        }
+    }
+
+    // Once signalled, the servers stop accepting and return when open
+    // connections finish; past the drain timeout they are abandoned
+    let drain_timeout = Duration::from_secs(config.shutdown.drain_timeout);
+    let drain_expired = async {
+        signal.clone().await;
+        tokio::time::sleep(drain_timeout).await;
+    };
+    tokio::select! {
+        result = async { tokio::try_join!(server, admin_server) } => {
+            result?;
+        }
+        _ = drain_expired => warn!(
+            timeout_secs = drain_timeout.as_secs(),
+            "Drain timeout elapsed; dropping open connections"
+        ),
+    }
+
+    // Nothing more is cached once connections are gone
+    if let (Some(path), Some(state)) = (&args.cache_snapshot, &snapshot_state) {
+        if let Err(e) = snapshot::save(state, path).await {
+            warn!(error = %e, "Failed to save cache snapshot");
+        }
+    }
+
+    // Write out queued audit, decision and capture records
+    let flush = async {
+        if let Some(log) = &audit_log {
+            log.close().await;
+        }
+        if let Some(log) = &decision_log {
+            log.close().await;
+        }
+        if let Some(capture) = &capture {
+            capture.close().await;
+        }
+    };
+    if tokio::time::timeout(LOG_FLUSH_TIMEOUT, flush)
+        .await
+        .is_err()
+    {
+        warn!("Timed out flushing audit and decision logs and captures; queued records lost");
+    }
+
+    // Flush spans still queued for export
+    if let Some(provider) = tracer_provider {
+        if let Err(e) = provider.shutdown() {
+            warn!(error = %e, "Failed to flush trace spans");
+        }
+    }
+
+    info!("Shutdown complete");
+    Ok(())
+}
+
+/// Validate the merged config, including flags only the command line sets
+fn check_config(config: &GatewayConfig, args: &Args) -> Result<()> {
+    config.validate()?;
+    if args.bundle_verification_key.is_some() && config.policy.bundle_url.is_none() {
+        bail!("--bundle-verification-key requires a bundle URL");
+    }
+    if config.rate_limit.shared && args.redis_url.is_none() {
+        bail!("rate_limit.shared requires --redis-url");
+    }
 
+    let rate_limited = config.rate_limit.routes.keys();
+    let request_limited = config.requests.routes.keys();
+    let routes = (rate_limited.map(|r| ("rate_limit.routes", r)))
+        .chain(request_limited.map(|r| ("requests.routes", r)));
+    for (section, route) in routes {
+        if !DECISION_ROUTES.contains(&route.as_str()) {
+            bail!("Unknown route {:?} in {}", route, section);
+        }
+    }
     Ok(())
 }
+
+/// Whether `id` was given on the command line rather than defaulted
+fn given(matches: &ArgMatches, id: &str) -> bool {
+    matches.value_source(id) == Some(ValueSource::CommandLine)
+}
+
+/// Override `config` with the flags given on the command line
+fn apply_flags(config: &mut GatewayConfig, args: &Args, matches: &ArgMatches) {
+    if given(matches, "listen") {
+        config.listen = args.listen.clone();
+    }
+    if given(matches, "log_level") {
+        config.log.level = args.log_level.clone();
+    }
+    if given(matches, "log_format") {
+        config.log.format = args.log_format;
+    }
+
+    if let Some(cert) = &args.tls_cert {
+        config.tls.cert = Some(cert.clone());
+    }
+    if let Some(key) = &args.tls_key {
+        config.tls.key = Some(key.clone());
+    }
+    if let Some(ca) = &args.tls_client_ca {
+        config.tls.client_ca = Some(ca.clone());
+    }
+
+    if let Some(dir) = &args.policy_dir {
+        config.policy.dir = Some(dir.clone());
+    }
+    if args.watch_policies {
+        config.policy.watch = true;
+    }
+    if let Some(url) = &args.bundle_url {
+        config.policy.bundle_url = Some(url.clone());
+    }
+    if given(matches, "bundle_poll_interval") {
+        config.policy.bundle_poll_interval = args.bundle_poll_interval;
+    }
+
+    if given(matches, "cache_cleanup_interval") {
+        config.cache.cleanup_interval = args.cache_cleanup_interval;
+    }
+    if given(matches, "cache_ttl_jitter_pct") {
+        config.cache.ttl_jitter_pct = args.cache_ttl_jitter_pct;
+    }
+    if given(matches, "cache_soft_ttl") {
+        config.cache.soft_ttl = args.cache_soft_ttl;
+    }
+    if given(matches, "cache_deny_ttl") {
+        config.cache.deny_ttl = args.cache_deny_ttl;
+    }
+    if given(matches, "cache_max_ttl") {
+        config.cache.max_ttl = args.cache_max_ttl;
+    }
+
+    if let Some(url) = &args.jwks_url {
+        config.jwt.jwks_url = Some(url.clone());
+    }
+    if let Some(audience) = &args.jwt_audience {
+        config.jwt.audience = Some(audience.clone());
+    }
+    if let Some(issuer) = &args.jwt_issuer {
+        config.jwt.issuer = Some(issuer.clone());
+    }
+    if given(matches, "jwks_refresh_interval") {
+        config.jwt.refresh_interval = args.jwks_refresh_interval;
+    }
+
+    if given(matches, "drain_timeout") {
+        config.shutdown.drain_timeout = args.drain_timeout;
+    }
+
+    if let Some(listen) = args.admin_listen {
+        config.admin.listen = Some(listen);
+    }
+    if let Some(token) = &args.admin_token {
+        config.admin.token = Some(token.clone());
+    }
+
+    if args.admission_webhook {
+        config.admission.enabled = true;
+    }
+}
+
+/// Resolve on Ctrl-C or SIGTERM
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+    info!("Shutting down");
+    #[cfg(unix)]
+    systemd::notify("STOPPING=1");
+}
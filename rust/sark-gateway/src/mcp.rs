@@ -0,0 +1,253 @@
+//! MCP JSON-RPC messages as authorization requests
+//!
+//! MCP clients speak JSON-RPC, not `GatewayAuthRequest`. The gateway reads
+//! their messages itself, so they can point straight at it with no
+//! translation layer in between. Each JSON-RPC request in a message
+//! (notifications included, replies to the server left out) is decided as
+//! a `/gateway/authorize` request for the server, with the same caching,
+//! logging and fallback:
+//!
+//! - `tools/call`: `gateway:tool:invoke` of the named tool, its arguments
+//!   as parameters
+//! - `tools/list`: `gateway:tool:discover`
+//! - `resources/read`: `gateway:resource:read`, the resource's `uri` in the
+//!   parameters
+//! - any other method: `gateway:server:info`
+//!
+//! `context.mcp_method` and `context.http_method` are set either way. For
+//! a server the proxy fronts, its `sensitivity` applies.
+//!
+//! `POST /gateway/mcp/<server>` decides a message without forwarding it,
+//! for an enforcement point that holds the raw message. A single request
+//! and a batch are both accepted:
+//!
+//! ```json
+//! {"jsonrpc": "2.0", "id": 7, "method": "tools/call",
+//!  "params": {"name": "create_issue", "arguments": {"title": "..."}}}
+//! ```
+//!
+//! It answers with each request's decision, by `id` and `method`, and
+//! `allow` only if every request is allowed:
+//!
+//! ```json
+//! {"allow": true, "decisions": [{"id": 7, "method": "tools/call", "allow": true, ...}]}
+//! ```
+//!
+//! The MCP proxy (see `proxy`) decides its bodies the same way, then
+//! forwards or refuses them.
+
+use crate::auth::UserContext;
+use crate::clientip::ClientIp;
+use crate::problem::{JsonBody, Problem};
+use crate::tls::ClientIdentity;
+use crate::{AppState, GatewayAuthRequest, GatewayAuthResponse};
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, Method},
+    Extension, Json,
+};
+use sark_context::RequestContext;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+/// Route messages are decided on without being forwarded
+pub const ROUTE: &str = "/gateway/mcp/:server";
+
+/// A JSON-RPC request in a message, authorized on its own
+pub struct Call {
+    /// Position in a batch message, or `None` for a single one
+    pub index: Option<usize>,
+    pub id: Value,
+    pub method: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct McpAuthResponse {
+    /// Whether every request in the message is allowed
+    allow: bool,
+    decisions: Vec<CallDecision>,
+}
+
+#[derive(Debug, Serialize)]
+struct CallDecision {
+    id: Value,
+    method: String,
+    #[serde(flatten)]
+    decision: GatewayAuthResponse,
+}
+
+/// Decision endpoint for raw MCP messages
+pub async fn authorize(
+    State(state): State<AppState>,
+    Path(server): Path<String>,
+    client: Option<Extension<ClientIdentity>>,
+    client_ip: Option<Extension<ClientIp>>,
+    verified: Option<Extension<UserContext>>,
+    headers: HeaderMap,
+    JsonBody(message): JsonBody<Value>,
+) -> Result<(HeaderMap, Json<McpAuthResponse>), Problem> {
+    let user = crate::authenticate(&state, verified, &headers).await?;
+    let client = client.map(|Extension(c)| c);
+    let client_ip = client_ip.map(|Extension(ClientIp(ip))| ip);
+
+    let calls = calls(&message);
+    if calls.is_empty() {
+        return Err(Problem::InvalidRequest(
+            "The message has no JSON-RPC requests to authorize".to_string(),
+        ));
+    }
+    let sensitivity = state
+        .proxy
+        .as_ref()
+        .and_then(|proxy| proxy.sensitivity(&server));
+    let requests = requests(&server, sensitivity, &Method::POST, &calls, Some(&message));
+    let request_id = crate::request_id(&headers);
+    let context = crate::request_context(&headers, client_ip, &user);
+    let decisions = decide(
+        &state,
+        &user,
+        client.as_ref(),
+        &context,
+        request_id.clone(),
+        requests,
+    )
+    .await?;
+    let headers = state.decision_headers.headers(&decisions, &request_id);
+
+    let decisions: Vec<CallDecision> = calls
+        .into_iter()
+        .zip(decisions)
+        .map(|(call, decision)| CallDecision {
+            id: call.id,
+            method: call.method,
+            decision,
+        })
+        .collect();
+    Ok((
+        headers,
+        Json(McpAuthResponse {
+            allow: decisions.iter().all(|d| d.decision.allow),
+            decisions,
+        }),
+    ))
+}
+
+/// The JSON-RPC requests in a message (notifications included, replies to
+/// the server left out)
+pub fn calls(message: &Value) -> Vec<Call> {
+    let call = |index, message: &Value| {
+        Some(Call {
+            index,
+            id: message.get("id").cloned().unwrap_or(Value::Null),
+            method: message.get("method")?.as_str()?.to_string(),
+        })
+    };
+    match message {
+        Value::Array(messages) => messages
+            .iter()
+            .enumerate()
+            .filter_map(|(i, m)| call(Some(i), m))
+            .collect(),
+        message => call(None, message).into_iter().collect(),
+    }
+}
+
+/// The gateway authorizations `calls` in `message` amount to, or, without
+/// any, the one the HTTP request itself does
+pub fn requests(
+    server: &str,
+    sensitivity: Option<&str>,
+    http_method: &Method,
+    calls: &[Call],
+    message: Option<&Value>,
+) -> Vec<GatewayAuthRequest> {
+    let (Some(message), false) = (message, calls.is_empty()) else {
+        return vec![auth_request(
+            server,
+            sensitivity,
+            http_method,
+            None,
+            Value::Null,
+        )];
+    };
+    calls
+        .iter()
+        .map(|call| {
+            let params = &entry(message, call.index)["params"];
+            auth_request(server, sensitivity, http_method, Some(call), params.clone())
+        })
+        .collect()
+}
+
+/// Decide `requests` together, each under its own request id when there
+/// are several
+pub async fn decide(
+    state: &AppState,
+    user: &UserContext,
+    client: Option<&ClientIdentity>,
+    context: &RequestContext,
+    request_id: String,
+    requests: Vec<GatewayAuthRequest>,
+) -> Result<Vec<GatewayAuthResponse>, Problem> {
+    let several = requests.len() > 1;
+    futures::future::join_all(requests.into_iter().enumerate().map(|(i, request)| {
+        let request_id = if several {
+            format!("{}/{}", request_id, i)
+        } else {
+            request_id.clone()
+        };
+        crate::authorize_request(state, user, client, context, request_id, request)
+    }))
+    .await
+    .into_iter()
+    .collect()
+}
+
+pub fn entry(message: &Value, index: Option<usize>) -> &Value {
+    match index {
+        Some(i) => &message[i],
+        None => message,
+    }
+}
+
+pub fn entry_mut(message: &mut Value, index: Option<usize>) -> &mut Value {
+    match index {
+        Some(i) => &mut message[i],
+        None => message,
+    }
+}
+
+/// The gateway authorization a call (or, without one, the request itself)
+/// amounts to
+fn auth_request(
+    server: &str,
+    sensitivity: Option<&str>,
+    http_method: &Method,
+    call: Option<&Call>,
+    params: Value,
+) -> GatewayAuthRequest {
+    let mcp_method = call.map(|c| c.method.as_str());
+    let (action, tool_name, parameters) = match mcp_method {
+        Some("tools/call") => (
+            "gateway:tool:invoke",
+            params["name"].as_str().unwrap_or_default().to_string(),
+            params.get("arguments").cloned(),
+        ),
+        Some("tools/list") => ("gateway:tool:discover", String::new(), Some(params)),
+        Some("resources/read") => ("gateway:resource:read", String::new(), Some(params)),
+        Some(_) => ("gateway:server:info", String::new(), Some(params)),
+        None => ("gateway:server:info", String::new(), None),
+    };
+    GatewayAuthRequest {
+        action: action.to_string(),
+        server_name: server.to_string(),
+        tool_name,
+        parameters: parameters.filter(|p| !p.is_null()),
+        context: Some(json!({
+            "mcp_method": mcp_method,
+            "http_method": http_method.as_str(),
+        })),
+        sensitivity_level: sensitivity.map(str::to_string),
+        dry_run: false,
+    }
+}
@@ -0,0 +1,482 @@
+//! Prometheus metrics
+//!
+//! Served in text format at `/metrics`. Names follow the ones the SARK
+//! dashboards already chart (`sark_gateway_*`); labels are kept to routes,
+//! decisions, namespaces, policy queries and configured detection rules so
+//! cardinality stays bounded regardless of traffic.
+//!
+//! The exception is `sark_gateway_tool_decisions_total`, which counts
+//! gateway decisions by `server`, `tool`, `decision` and policy `revision`
+//! (empty for failed evaluations), for charting which tools a policy change
+//! denies. Server and tool names come from callers, so `[metrics]` bounds
+//! them:
+//!
+//! ```toml
+//! [metrics.tool_decisions]
+//! servers = ["github", "postgres"]
+//! max_tools = 200
+//! revisions = 2
+//! ```
+//!
+//! Names outside the `servers` and `tools` allowlists (when set) are
+//! labeled `other`, as are all pairs after the first `max_tools`. Only the
+//! `revisions` newest revisions' series are kept; with tenant policies,
+//! each tenant's revisions count too.
+//!
+//! While a canary policy is rolled out (see `policy`),
+//! `sark_gateway_rollout_decisions_total` counts decisions by the
+//! `revision` that made them, the canary's or the active one's, so the two
+//! can be compared side by side. Its series are dropped when the canary is
+//! promoted or discarded.
+
+use crate::config::ToolDecisionsConfig;
+use crate::problem::Problem;
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::header::CONTENT_TYPE,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+use sark_policy::{Outcome, EVALUATION_BUCKETS};
+use std::collections::{HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Request latency buckets, matching the Python gateway's
+const REQUEST_BUCKETS: &[f64] = &[
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Label of servers and tools without series of their own
+const OTHER: &str = "other";
+
+pub struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    request_duration: HistogramVec,
+    in_flight: IntGauge,
+    decisions: IntCounterVec,
+    cache_hits: IntCounterVec,
+    cache_misses: IntCounterVec,
+    evaluation_duration: HistogramVec,
+    evaluations: IntCounterVec,
+    rate_limited: IntCounterVec,
+    shed: IntCounterVec,
+    fallback: IntCounterVec,
+    dry_runs: IntCounterVec,
+    quota_exceeded: IntCounterVec,
+    step_up_challenges: IntCounterVec,
+    findings: IntCounterVec,
+    malformed_results: IntCounterVec,
+    evaluation_timeouts: IntCounterVec,
+    rollout_decisions: IntCounterVec,
+    precomputed: IntCounterVec,
+    tool_decisions: Option<ToolDecisions>,
+}
+
+/// `sark_gateway_tool_decisions_total` and the series it has
+struct ToolDecisions {
+    counter: IntCounterVec,
+    servers: HashSet<String>,
+    tools: HashSet<String>,
+    max_tools: usize,
+    revisions: usize,
+    series: Mutex<ToolSeries>,
+}
+
+#[derive(Default)]
+struct ToolSeries {
+    /// Server/tool pairs labeled by name
+    pairs: HashSet<(String, String)>,
+    /// Revisions with series, oldest first, and their server, tool and
+    /// decision labels
+    revisions: VecDeque<(String, HashSet<[String; 3]>)>,
+}
+
+impl ToolDecisions {
+    fn count(&self, server: &str, tool: &str, decision: &str, revision: &str) {
+        let server = labeled(&self.servers, server);
+        let tool = labeled(&self.tools, tool);
+
+        let mut series = self.series.lock().expect("tool metrics lock poisoned");
+        let pair = (server.to_string(), tool.to_string());
+        let (server, tool) = if series.pairs.contains(&pair) || series.pairs.len() < self.max_tools
+        {
+            series.pairs.insert(pair);
+            (server, tool)
+        } else {
+            (OTHER, OTHER)
+        };
+        // Failed evaluations have no revision, and don't make one current
+        if !revision.is_empty() {
+            let labels = [server, tool, decision].map(str::to_string);
+            for (old, labels) in series.record(revision, labels, self.revisions) {
+                for [server, tool, decision] in labels {
+                    let _ = self
+                        .counter
+                        .remove_label_values(&[&server, &tool, &decision, &old]);
+                }
+            }
+        }
+        drop(series);
+
+        self.counter
+            .with_label_values(&[server, tool, decision, revision])
+            .inc();
+    }
+}
+
+impl ToolSeries {
+    /// Note a series of `revision`, returning the revisions (and their
+    /// labels) beyond the newest `keep`
+    fn record(
+        &mut self,
+        revision: &str,
+        labels: [String; 3],
+        keep: usize,
+    ) -> Vec<(String, HashSet<[String; 3]>)> {
+        if let Some((_, seen)) = self.revisions.iter_mut().find(|(r, _)| r == revision) {
+            seen.insert(labels);
+            return Vec::new();
+        }
+        self.revisions
+            .push_back((revision.to_string(), HashSet::from([labels])));
+        let dropped = self.revisions.len().saturating_sub(keep);
+        self.revisions.drain(..dropped).collect()
+    }
+}
+
+/// `name`, if `names` allows it (or is empty), else `other`
+fn labeled<'a>(names: &HashSet<String>, name: &'a str) -> &'a str {
+    if names.is_empty() || names.contains(name) {
+        name
+    } else {
+        OTHER
+    }
+}
+
+impl Metrics {
+    pub fn new(tool_decisions: &ToolDecisionsConfig) -> prometheus::Result<Self> {
+        let registry = Registry::new();
+
+        let requests = IntCounterVec::new(
+            Opts::new("sark_gateway_requests_total", "Total gateway requests"),
+            &["method", "endpoint", "status"],
+        )?;
+        let request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "sark_gateway_request_duration_seconds",
+                "Gateway request duration in seconds",
+            )
+            .buckets(REQUEST_BUCKETS.to_vec()),
+            &["method", "endpoint"],
+        )?;
+        let in_flight = IntGauge::new(
+            "sark_gateway_requests_in_flight",
+            "Gateway requests being processed",
+        )?;
+        let decisions = IntCounterVec::new(
+            Opts::new(
+                "sark_gateway_decisions_total",
+                "Authorization decisions by outcome (allow, deny, error) and tenant (empty for the default policy)",
+            ),
+            &["endpoint", "tenant", "decision"],
+        )?;
+        let cache_hits = IntCounterVec::new(
+            Opts::new("sark_gateway_cache_hits_total", "Decision cache hits"),
+            &["namespace"],
+        )?;
+        let cache_misses = IntCounterVec::new(
+            Opts::new("sark_gateway_cache_misses_total", "Decision cache misses"),
+            &["namespace"],
+        )?;
+        let evaluation_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "sark_gateway_opa_evaluation_duration_seconds",
+                "Policy evaluation time in seconds, by query",
+            )
+            .buckets(EVALUATION_BUCKETS.to_vec()),
+            &["query"],
+        )?;
+        let evaluations = IntCounterVec::new(
+            Opts::new(
+                "sark_gateway_opa_evaluations_total",
+                "Policy evaluations, by query and outcome (allow, deny, error)",
+            ),
+            &["query", "outcome"],
+        )?;
+
+        let rate_limited = IntCounterVec::new(
+            Opts::new(
+                "sark_gateway_rate_limited_total",
+                "Requests rejected for exceeding a client's rate limit",
+            ),
+            &["endpoint"],
+        )?;
+        let shed = IntCounterVec::new(
+            Opts::new(
+                "sark_gateway_shed_total",
+                "Requests shed under overload, by the limit hit (in_flight, evaluations)",
+            ),
+            &["endpoint", "limit"],
+        )?;
+        let fallback = IntCounterVec::new(
+            Opts::new(
+                "sark_gateway_fallback_total",
+                "Decisions local evaluation couldn't make, by how they were settled (remote, rejected, fail_open, fail_closed)",
+            ),
+            &["result"],
+        )?;
+        let dry_runs = IntCounterVec::new(
+            Opts::new(
+                "sark_gateway_dry_runs_total",
+                "Dry-run decisions by outcome (allow, deny, error), kept out of sark_gateway_decisions_total",
+            ),
+            &["decision"],
+        )?;
+        let quota_exceeded = IntCounterVec::new(
+            Opts::new(
+                "sark_gateway_quota_exceeded_total",
+                "Allowed requests refused for exceeding a policy-set quota",
+            ),
+            &["endpoint"],
+        )?;
+        let step_up_challenges = IntCounterVec::new(
+            Opts::new(
+                "sark_gateway_step_up_challenges_total",
+                "Decisions answered with a step-up challenge, the caller's token not meeting it",
+            ),
+            &["endpoint"],
+        )?;
+        let findings = IntCounterVec::new(
+            Opts::new(
+                "sark_gateway_findings_total",
+                "Credentials found in request parameters, by detection rule",
+            ),
+            &["rule"],
+        )?;
+        let malformed_results = IntCounterVec::new(
+            Opts::new(
+                "sark_gateway_malformed_results_total",
+                "Policy results failing --result-schema, by query",
+            ),
+            &["query"],
+        )?;
+        let evaluation_timeouts = IntCounterVec::new(
+            Opts::new(
+                "sark_gateway_evaluation_timeouts_total",
+                "Policy evaluations past concurrency.evaluation_timeout_ms, by query",
+            ),
+            &["query"],
+        )?;
+        let rollout_decisions = IntCounterVec::new(
+            Opts::new(
+                "sark_gateway_rollout_decisions_total",
+                "Authorization decisions made while a canary policy is rolled out, by policy revision and outcome (allow, deny)",
+            ),
+            &["revision", "decision"],
+        )?;
+        let precomputed = IntCounterVec::new(
+            Opts::new(
+                "sark_gateway_cache_precomputed_total",
+                "Frequently looked up decisions re-evaluated after the cache was cleared, by result (cached, skipped, error)",
+            ),
+            &["namespace", "result"],
+        )?;
+
+        registry.register(Box::new(requests.clone()))?;
+        registry.register(Box::new(request_duration.clone()))?;
+        registry.register(Box::new(in_flight.clone()))?;
+        registry.register(Box::new(decisions.clone()))?;
+        registry.register(Box::new(cache_hits.clone()))?;
+        registry.register(Box::new(cache_misses.clone()))?;
+        registry.register(Box::new(evaluation_duration.clone()))?;
+        registry.register(Box::new(evaluations.clone()))?;
+        registry.register(Box::new(rate_limited.clone()))?;
+        registry.register(Box::new(shed.clone()))?;
+        registry.register(Box::new(fallback.clone()))?;
+        registry.register(Box::new(dry_runs.clone()))?;
+        registry.register(Box::new(quota_exceeded.clone()))?;
+        registry.register(Box::new(step_up_challenges.clone()))?;
+        registry.register(Box::new(findings.clone()))?;
+        registry.register(Box::new(malformed_results.clone()))?;
+        registry.register(Box::new(evaluation_timeouts.clone()))?;
+        registry.register(Box::new(rollout_decisions.clone()))?;
+        registry.register(Box::new(precomputed.clone()))?;
+
+        let tool_decisions = match tool_decisions {
+            ToolDecisionsConfig { enabled: false, .. } => None,
+            config => {
+                let counter = IntCounterVec::new(
+                    Opts::new(
+                        "sark_gateway_tool_decisions_total",
+                        "Gateway authorization decisions by server, tool, outcome (allow, deny, error) and policy revision",
+                    ),
+                    &["server", "tool", "decision", "revision"],
+                )?;
+                registry.register(Box::new(counter.clone()))?;
+                Some(ToolDecisions {
+                    counter,
+                    servers: config.servers.iter().cloned().collect(),
+                    tools: config.tools.iter().cloned().collect(),
+                    max_tools: config.max_tools,
+                    revisions: config.revisions,
+                    series: Mutex::default(),
+                })
+            }
+        };
+
+        Ok(Self {
+            registry,
+            requests,
+            request_duration,
+            in_flight,
+            decisions,
+            cache_hits,
+            cache_misses,
+            evaluation_duration,
+            evaluations,
+            rate_limited,
+            shed,
+            fallback,
+            dry_runs,
+            quota_exceeded,
+            step_up_challenges,
+            findings,
+            malformed_results,
+            evaluation_timeouts,
+            rollout_decisions,
+            precomputed,
+            tool_decisions,
+        })
+    }
+
+    /// Count a decision (`allow`, `deny` or `error`) for `endpoint`, made
+    /// under `tenant`'s policy (empty for the default)
+    pub fn decision(&self, endpoint: &str, tenant: &str, decision: &str) {
+        self.decisions
+            .with_label_values(&[endpoint, tenant, decision])
+            .inc();
+    }
+
+    /// Count a decision made while a canary policy is rolled out, by the
+    /// `revision` that made it
+    pub fn rollout_decision(&self, revision: &str, decision: &str) {
+        self.rollout_decisions
+            .with_label_values(&[revision, decision])
+            .inc();
+    }
+
+    /// Drop the rollout's series, once its canary is promoted or discarded
+    pub fn end_rollout(&self) {
+        self.rollout_decisions.reset();
+    }
+
+    /// Count a gateway decision on `server`'s `tool`, made by policy
+    /// `revision` (empty if evaluation failed)
+    pub fn tool_decision(&self, server: &str, tool: &str, decision: &str, revision: &str) {
+        if let Some(tool_decisions) = &self.tool_decisions {
+            tool_decisions.count(server, tool, decision, revision);
+        }
+    }
+
+    pub fn cache_lookup(&self, namespace: &str, hit: bool) {
+        let counter = if hit {
+            &self.cache_hits
+        } else {
+            &self.cache_misses
+        };
+        counter.with_label_values(&[namespace]).inc();
+    }
+
+    pub fn evaluation(&self, query: &str, outcome: Outcome, elapsed: Duration) {
+        self.evaluation_duration
+            .with_label_values(&[query])
+            .observe(elapsed.as_secs_f64());
+        self.evaluations
+            .with_label_values(&[query, outcome.label()])
+            .inc();
+    }
+
+    pub fn rate_limited(&self, endpoint: &str) {
+        self.rate_limited.with_label_values(&[endpoint]).inc();
+    }
+
+    pub fn shed(&self, endpoint: &str, limit: &str) {
+        self.shed.with_label_values(&[endpoint, limit]).inc();
+    }
+
+    pub fn fallback(&self, result: &str) {
+        self.fallback.with_label_values(&[result]).inc();
+    }
+
+    pub fn dry_run(&self, decision: &str) {
+        self.dry_runs.with_label_values(&[decision]).inc();
+    }
+
+    pub fn quota_exceeded(&self, endpoint: &str) {
+        self.quota_exceeded.with_label_values(&[endpoint]).inc();
+    }
+
+    pub fn step_up_challenge(&self, endpoint: &str) {
+        self.step_up_challenges.with_label_values(&[endpoint]).inc();
+    }
+
+    pub fn finding(&self, rule: &str) {
+        self.findings.with_label_values(&[rule]).inc();
+    }
+
+    pub fn malformed_result(&self, query: &str) {
+        self.malformed_results.with_label_values(&[query]).inc();
+    }
+
+    pub fn evaluation_timeout(&self, query: &str) {
+        self.evaluation_timeouts.with_label_values(&[query]).inc();
+    }
+
+    pub fn precomputed(&self, namespace: &str, result: &str) {
+        self.precomputed
+            .with_label_values(&[namespace, result])
+            .inc();
+    }
+}
+
+/// Middleware recording request counts, latency and concurrency per route
+pub async fn track(State(metrics): State<Arc<Metrics>>, request: Request, next: Next) -> Response {
+    // Label by route pattern, not raw path, so path parameters don't
+    // create a series per value
+    let endpoint = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+    let method = request.method().to_string();
+
+    metrics.in_flight.inc();
+    let started = Instant::now();
+    let response = next.run(request).await;
+    metrics.in_flight.dec();
+
+    metrics
+        .request_duration
+        .with_label_values(&[&method, &endpoint])
+        .observe(started.elapsed().as_secs_f64());
+    metrics
+        .requests
+        .with_label_values(&[&method, &endpoint, response.status().as_str()])
+        .inc();
+    response
+}
+
+/// `/metrics` handler
+pub async fn render(State(metrics): State<Arc<Metrics>>) -> Response {
+    let mut buffer = Vec::new();
+    let encoder = TextEncoder::new();
+    match encoder.encode(&metrics.registry.gather(), &mut buffer) {
+        Ok(()) => ([(CONTENT_TYPE, encoder.format_type().to_string())], buffer).into_response(),
+        Err(e) => Problem::Internal(e.to_string()).into_response(),
+    }
+}
@@ -0,0 +1,265 @@
+//! MaxMind DB reader
+//!
+//! Just enough of the MaxMind DB format (version 2) to look addresses up in
+//! GeoLite2/GeoIP2 databases and ones `mmdbwriter` builds: the binary search
+//! tree over address bits, and the data section decoded to JSON. The whole
+//! file is read into memory.
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde_json::{Map, Value};
+use std::net::IpAddr;
+use std::path::Path;
+
+/// Precedes the metadata, in the last 128 KiB of the file
+const METADATA_MARKER: &[u8] = b"\xab\xcd\xefMaxMind.com";
+const METADATA_WINDOW: usize = 128 * 1024;
+
+/// Separates the search tree from the data section
+const DATA_SEPARATOR: usize = 16;
+
+/// Nesting past this is taken for a corrupt (or looping) file
+const MAX_DEPTH: usize = 32;
+
+pub struct Reader {
+    buf: Vec<u8>,
+    node_count: usize,
+    record_size: usize,
+    ip_version: u64,
+    /// Node IPv4 lookups start from, in an IPv6 tree
+    ipv4_start: usize,
+    data_start: usize,
+    /// `database_type` of the metadata (e.g. `GeoLite2-ASN`)
+    pub database_type: String,
+    /// When the database was built, as a Unix timestamp
+    pub build_epoch: u64,
+}
+
+impl Reader {
+    pub fn open(path: &Path) -> Result<Self> {
+        let buf =
+            std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        Self::from_bytes(buf).with_context(|| format!("Invalid MaxMind DB {}", path.display()))
+    }
+
+    fn from_bytes(buf: Vec<u8>) -> Result<Self> {
+        let window = buf.len().saturating_sub(METADATA_WINDOW);
+        let marker = buf[window..]
+            .windows(METADATA_MARKER.len())
+            .rposition(|w| w == METADATA_MARKER)
+            .ok_or_else(|| anyhow!("no metadata"))?;
+        let metadata_start = window + marker + METADATA_MARKER.len();
+        let (metadata, _) = Decoder {
+            buf: &buf[metadata_start..],
+        }
+        .decode(0, 0)?;
+        let number = |key: &str| {
+            metadata[key]
+                .as_u64()
+                .ok_or_else(|| anyhow!("metadata has no {}", key))
+        };
+
+        let node_count = number("node_count")? as usize;
+        let record_size = number("record_size")? as usize;
+        let ip_version = number("ip_version")?;
+        if number("binary_format_major_version")? != 2 {
+            bail!("unsupported format version");
+        }
+        if !matches!(record_size, 24 | 28 | 32) {
+            bail!("unsupported record size {}", record_size);
+        }
+        let tree_size = node_count * record_size / 4;
+        if tree_size + DATA_SEPARATOR > metadata_start {
+            bail!("search tree overruns the file");
+        }
+
+        let mut reader = Self {
+            buf,
+            node_count,
+            record_size,
+            ip_version,
+            ipv4_start: 0,
+            data_start: tree_size + DATA_SEPARATOR,
+            database_type: metadata["database_type"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            build_epoch: metadata["build_epoch"].as_u64().unwrap_or_default(),
+        };
+        if ip_version == 6 {
+            let mut node = 0;
+            for _ in 0..96 {
+                if node >= node_count {
+                    break;
+                }
+                node = reader.record(node, 0)?;
+            }
+            reader.ipv4_start = node;
+        }
+        reader.buf.truncate(metadata_start - METADATA_MARKER.len());
+        Ok(reader)
+    }
+
+    /// The record for `ip`, if the database has one
+    pub fn lookup(&self, ip: IpAddr) -> Result<Option<Value>> {
+        let (bytes, mut node) = match ip.to_canonical() {
+            IpAddr::V4(ip) => (ip.octets().to_vec(), self.ipv4_start),
+            IpAddr::V6(_) if self.ip_version == 4 => return Ok(None),
+            IpAddr::V6(ip) => (ip.octets().to_vec(), 0),
+        };
+        for bit in 0..bytes.len() * 8 {
+            if node >= self.node_count {
+                break;
+            }
+            node = self.record(node, (bytes[bit / 8] >> (7 - bit % 8)) & 1)?;
+        }
+        if node <= self.node_count {
+            return Ok(None);
+        }
+
+        let offset = node - self.node_count - DATA_SEPARATOR;
+        let decoder = Decoder {
+            buf: self
+                .buf
+                .get(self.data_start..)
+                .ok_or_else(|| anyhow!("no data section"))?,
+        };
+        Ok(Some(decoder.decode(offset, 0)?.0))
+    }
+
+    /// The left (`bit` 0) or right record of `node`
+    fn record(&self, node: usize, bit: u8) -> Result<usize> {
+        let size = self.record_size / 4;
+        let bytes = self
+            .buf
+            .get(node * size..(node + 1) * size)
+            .ok_or_else(|| anyhow!("search tree node {} out of bounds", node))?;
+        let be = |b: &[u8]| b.iter().fold(0usize, |n, &b| n << 8 | b as usize);
+        Ok(match (self.record_size, bit) {
+            (24, 0) => be(&bytes[..3]),
+            (24, _) => be(&bytes[3..]),
+            (28, 0) => (bytes[3] as usize & 0xf0) << 20 | be(&bytes[..3]),
+            (28, _) => (bytes[3] as usize & 0x0f) << 24 | be(&bytes[4..]),
+            (_, 0) => be(&bytes[..4]),
+            (_, _) => be(&bytes[4..]),
+        })
+    }
+}
+
+/// Decodes the data section (or the metadata, which is encoded alike)
+struct Decoder<'a> {
+    buf: &'a [u8],
+}
+
+impl Decoder<'_> {
+    fn bytes(&self, offset: usize, len: usize) -> Result<&[u8]> {
+        self.buf
+            .get(offset..offset + len)
+            .ok_or_else(|| anyhow!("data at {} out of bounds", offset))
+    }
+
+    fn uint(&self, offset: usize, len: usize) -> Result<u128> {
+        if len > 16 {
+            bail!("integer at {} too long", offset);
+        }
+        Ok(self
+            .bytes(offset, len)?
+            .iter()
+            .fold(0u128, |n, &b| n << 8 | b as u128))
+    }
+
+    /// The value at `offset` and the offset after it
+    fn decode(&self, offset: usize, depth: usize) -> Result<(Value, usize)> {
+        if depth > MAX_DEPTH {
+            bail!("data nested too deeply");
+        }
+        let control = self.bytes(offset, 1)?[0];
+        let mut offset = offset + 1;
+        let mut kind = control >> 5;
+        if kind == 1 {
+            // Pointers carry their size in the control byte's other bits
+            let size = (control >> 3 & 0x3) as usize + 1;
+            let low = (control & 0x7) as usize;
+            let value = self.uint(offset, size)? as usize;
+            let target = match size {
+                1 => low << 8 | value,
+                2 => (low << 16 | value) + 2048,
+                3 => (low << 24 | value) + 526_336,
+                _ => value,
+            };
+            let (value, _) = self.decode(target, depth + 1)?;
+            return Ok((value, offset + size));
+        }
+        if kind == 0 {
+            kind = self.bytes(offset, 1)?[0].saturating_add(7);
+            offset += 1;
+        }
+        let mut size = (control & 0x1f) as usize;
+        if size >= 29 {
+            let extra = size - 28;
+            let value = self.uint(offset, extra)? as usize;
+            offset += extra;
+            size = match extra {
+                1 => 29 + value,
+                2 => 285 + value,
+                _ => 65_821 + value,
+            };
+        }
+
+        let value = match kind {
+            2 => {
+                let text = std::str::from_utf8(self.bytes(offset, size)?)
+                    .with_context(|| format!("invalid string at {}", offset))?;
+                (Value::from(text), offset + size)
+            }
+            3 | 15 => {
+                let float = match size {
+                    8 => f64::from_be_bytes(self.bytes(offset, 8)?.try_into()?),
+                    4 => f32::from_be_bytes(self.bytes(offset, 4)?.try_into()?) as f64,
+                    _ => bail!("invalid float at {}", offset),
+                };
+                (Value::from(float), offset + size)
+            }
+            4 => (
+                Value::from(hex::encode(self.bytes(offset, size)?)),
+                offset + size,
+            ),
+            5 | 6 | 9 => (Value::from(self.uint(offset, size)? as u64), offset + size),
+            8 => (
+                Value::from(self.uint(offset, size)? as u32 as i32),
+                offset + size,
+            ),
+            10 => {
+                let value = self.uint(offset, size)?;
+                let value = u64::try_from(value)
+                    .map(Value::from)
+                    .unwrap_or_else(|_| Value::from(value.to_string()));
+                (value, offset + size)
+            }
+            7 => {
+                let mut map = Map::new();
+                for _ in 0..size {
+                    let (key, next) = self.decode(offset, depth + 1)?;
+                    let Value::String(key) = key else {
+                        bail!("map key at {} is not a string", offset);
+                    };
+                    let (value, next) = self.decode(next, depth + 1)?;
+                    map.insert(key, value);
+                    offset = next;
+                }
+                (Value::Object(map), offset)
+            }
+            11 => {
+                let mut array = Vec::with_capacity(size.min(1024));
+                for _ in 0..size {
+                    let (value, next) = self.decode(offset, depth + 1)?;
+                    array.push(value);
+                    offset = next;
+                }
+                (Value::Array(array), offset)
+            }
+            14 => (Value::from(size != 0), offset),
+            _ => bail!("unsupported data type {} at {}", kind, offset),
+        };
+        Ok(value)
+    }
+}
@@ -0,0 +1,122 @@
+//! Decision obligations
+//!
+//! A policy can attach conditions to its decision, beyond allow or deny,
+//! in an `obligations` object returned to the caller with it for the
+//! enforcement point to act on:
+//!
+//! ```rego
+//! obligations := {
+//!     "step_up": {"acr": "mfa", "max_age": 300},
+//!     "watermark": {"text": sprintf("Retrieved by %s", [input.user.email])},
+//!     "log_level": "warn",
+//! } if input.resource.sensitivity == "high"
+//! ```
+//!
+//! The gateway knows these, and fails a decision carrying a malformed one
+//! (500) rather than pass it on:
+//!
+//! - `step_up` (or `require_step_up`): the caller must authenticate again
+//!   before going ahead, to authentication context class `acr`, with each
+//!   of the methods in `amr`, and at most `max_age` seconds ago (any may be
+//!   left out), as in OIDC's `acr_values` and `max_age`; with
+//!   `step_up.enforce`, the gateway checks this itself (see `stepup`)
+//! - `watermark`: `text` to mark the tool's output with
+//! - `log_level`: `info`, `warn` or `error`; the gateway logs the decision
+//!   at that level rather than `info`, so sensitive access stands out
+//!
+//! beside `redact` and `quota`, which the gateway enforces itself (see
+//! those modules). Any other members are passed through as the policy set
+//! them, for enforcement points with obligations of their own. The MCP
+//! proxy, as the enforcement point for its calls, refuses those obliged to
+//! be watermarked, which it can't do, and to step up unless the gateway
+//! enforces it.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// What a decision obliges its enforcement point to do
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Obligations {
+    #[serde(
+        default,
+        alias = "require_step_up",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub step_up: Option<StepUp>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub watermark: Option<Watermark>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_level: Option<LogLevel>,
+    /// `redact`, `quota` and obligations the gateway doesn't know
+    #[serde(flatten)]
+    other: Map<String, Value>,
+}
+
+/// Authentication the caller must complete again first
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StepUp {
+    /// Authentication context class to reach
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acr: Option<String>,
+    /// Authentication methods the caller must all have used
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub amr: Vec<String>,
+    /// Longest ago, in seconds, the caller may have authenticated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_age: Option<u64>,
+}
+
+/// Text to mark the tool's output with
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Watermark {
+    pub text: String,
+}
+
+/// Level the gateway logs the decision at
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Info,
+    Warn,
+    Error,
+}
+
+impl Obligations {
+    /// The obligations in a policy's document, if it has any
+    pub fn from_document(document: &Value) -> Result<Option<Self>, String> {
+        let obligations = match document.get("obligations") {
+            None | Some(Value::Null) => return Ok(None),
+            Some(obligations @ Value::Object(_)) => obligations,
+            Some(other) => return Err(format!("Obligations must be an object, not {}", other)),
+        };
+        let obligations: Self = serde_json::from_value(obligations.clone())
+            .map_err(|e| format!("Invalid obligations: {}", e))?;
+        if obligations
+            .watermark
+            .as_ref()
+            .is_some_and(|w| w.text.is_empty())
+        {
+            return Err("Invalid watermark obligation: empty text".to_string());
+        }
+        Ok(Some(obligations))
+    }
+
+    /// An obligation the gateway passes through as the policy set it
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.other.get(name)
+    }
+
+    /// Names of the obligations the MCP proxy can't carry out
+    pub fn unfulfillable(&self) -> Vec<&'static str> {
+        let mut names = Vec::new();
+        if self.step_up.is_some() {
+            names.push("step_up");
+        }
+        if self.watermark.is_some() {
+            names.push("watermark");
+        }
+        names
+    }
+}
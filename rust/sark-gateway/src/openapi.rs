@@ -0,0 +1,474 @@
+//! OpenAPI description
+//!
+//! `GET /openapi.json` describes the public HTTP API as an OpenAPI 3
+//! document, for client teams to generate typed clients from. It is built
+//! from the running config, so optional routes (admission webhook, MCP
+//! proxy, signing keys) and headers (tenant, signature, decision metadata)
+//! appear only when enabled. The gRPC, Envoy and admin APIs are not
+//! described.
+//!
+//! With `admin.swagger_ui`, the admin port also serves the document and a
+//! Swagger UI page for it at `/admin/docs`, without the admin token. The
+//! page loads Swagger UI's scripts from unpkg.com, so the browser viewing
+//! it needs to reach that site.
+
+use crate::config::GatewayConfig;
+use axum::response::Html;
+use serde_json::{json, Value};
+
+/// Route the document is served on
+pub const ROUTE: &str = "/openapi.json";
+
+/// Admin route of the Swagger UI page
+pub const DOCS_ROUTE: &str = "/admin/docs";
+
+/// Swagger UI release the page loads
+const SWAGGER_UI: &str = "https://unpkg.com/swagger-ui-dist@5";
+
+/// The document for the API `config` enables
+pub fn document(config: &GatewayConfig) -> Value {
+    let mut parameters = vec![json!({"$ref": "#/components/parameters/RequestId"})];
+    if let Some(header) = &config.tenants.header {
+        parameters.push(json!({
+            "name": header,
+            "in": "header",
+            "required": config.tenants.required,
+            "description": "Tenant whose policies decide the request, unless the caller's token or API key names one",
+            "schema": {"type": "string"},
+        }));
+    }
+    let mut decision_headers = json!({"X-Request-Id": {"$ref": "#/components/headers/RequestId"}});
+    if config.signing.key.is_some() {
+        decision_headers["X-Sark-Signature"] = json!({
+            "description": format!(
+                "Detached JWS of the response body, verifiable with the keys at {}",
+                crate::signing::JWKS_ROUTE
+            ),
+            "schema": {"type": "string"},
+        });
+    }
+    if config.reasons.catalog.is_some() {
+        decision_headers["Content-Language"] = json!({
+            "description": "Language of the reason, where it is the catalog's message for the reason code in a language asked for with Accept-Language (absent for a batch)",
+            "schema": {"type": "string"},
+        });
+    }
+    let response_headers = &config.response_headers;
+    for (enabled, name, description, schema) in [
+        (
+            response_headers.decision,
+            "X-SARK-Decision",
+            "The decision (for a batch, absent; for an MCP message, allow only if every request is allowed)",
+            json!({"type": "string", "enum": ["allow", "deny"]}),
+        ),
+        (
+            response_headers.policy_revision,
+            "X-SARK-Policy-Revision",
+            "Revision of the policy that decided (absent for a batch)",
+            json!({"type": "string"}),
+        ),
+        (
+            response_headers.cache,
+            "X-SARK-Cache",
+            "Whether the decision was served from cache (absent for a batch)",
+            json!({"type": "string", "enum": ["hit", "miss"]}),
+        ),
+        (
+            response_headers.request_id,
+            "X-SARK-Request-Id",
+            "The request id the decision was made for",
+            json!({"type": "string"}),
+        ),
+    ] {
+        if enabled {
+            decision_headers[name] = json!({"description": description, "schema": schema});
+        }
+    }
+    let decision = |summary: &str, request: &str, response: &str, extra: &[Value]| {
+        let mut parameters = parameters.clone();
+        parameters.extend_from_slice(extra);
+        json!({
+            "post": {
+                "summary": summary,
+                "security": [{"bearer": []}, {"apiKey": []}],
+                "parameters": parameters,
+                "requestBody": {
+                    "required": true,
+                    "content": {"application/json": {"schema": schema(request)}},
+                },
+                "responses": with_problems(json!({
+                    "200": {
+                        "description": "The decision",
+                        "headers": decision_headers,
+                        "content": {"application/json": {"schema": schema(response)}},
+                    },
+                })),
+            },
+        })
+    };
+    let dry_run = json!({
+        "name": "X-SARK-Dry-Run",
+        "in": "header",
+        "description": "Evaluate and log the decision without caching or enforcing it",
+        "schema": {"type": "boolean"},
+    });
+
+    let mut paths = json!({
+        "/health": probe("Gateway and dependency health, policy revision and cache statistics (503 when unhealthy)"),
+        "/livez": probe("Liveness: the process is serving"),
+        "/readyz": probe("Readiness: policies, keys and tenants are loaded (503 until they are)"),
+        "/metrics": {
+            "get": {
+                "summary": "Prometheus metrics",
+                "responses": {"200": {
+                    "description": "Metrics in the Prometheus text format",
+                    "content": {"text/plain": {"schema": {"type": "string"}}},
+                }},
+            },
+        },
+    });
+    paths[crate::Endpoint::Authorize.route()] = decision(
+        "Authorize an MCP tool invocation",
+        "GatewayAuthRequest",
+        "GatewayAuthResponse",
+        std::slice::from_ref(&dry_run),
+    );
+    paths[crate::BATCH_ROUTE] = decision(
+        "Authorize several MCP tool invocations at once",
+        "GatewayBatchRequest",
+        "GatewayBatchResponse",
+        &[],
+    );
+    paths[crate::Endpoint::AuthorizeA2a.route()] = decision(
+        "Authorize one agent acting on another",
+        "A2AAuthRequest",
+        "GatewayAuthResponse",
+        &[],
+    );
+    paths["/gateway/mcp/{server}"] = decision(
+        "Authorize the requests in a raw MCP JSON-RPC message",
+        "McpMessage",
+        "McpAuthResponse",
+        &[json!({
+            "name": "server",
+            "in": "path",
+            "required": true,
+            "schema": {"type": "string"},
+        })],
+    );
+    if config.admission.enabled {
+        paths[crate::admission::ROUTE] = json!({
+            "post": {
+                "summary": "Kubernetes validating admission webhook",
+                "requestBody": {
+                    "required": true,
+                    "content": {"application/json": {"schema": {
+                        "type": "object",
+                        "description": "An admission.k8s.io/v1 AdmissionReview",
+                    }}},
+                },
+                "responses": with_problems(json!({"200": {
+                    "description": "The AdmissionReview with its response",
+                    "content": {"application/json": {"schema": {"type": "object"}}},
+                }})),
+            },
+        });
+    }
+    if !config.proxy.servers.is_empty() {
+        let mut servers: Vec<&String> = config.proxy.servers.keys().collect();
+        servers.sort();
+        let server = json!({
+            "name": "server",
+            "in": "path",
+            "required": true,
+            "schema": {"type": "string", "enum": servers},
+        });
+        let forward = |parameters: Vec<Value>| {
+            json!({
+                "post": {
+                    "summary": "Forward an MCP JSON-RPC request to the server, authorizing tool calls",
+                    "security": [{"bearer": []}, {"apiKey": []}],
+                    "parameters": parameters,
+                    "requestBody": {"content": {"application/json": {"schema": {"type": "object"}}}},
+                    "responses": with_problems(json!({"200": {
+                        "description": "The server's response, with denied results refused and redacted fields masked",
+                    }})),
+                },
+            })
+        };
+        paths["/mcp/{server}"] = forward(vec![server.clone()]);
+        paths["/mcp/{server}/{path}"] = forward(vec![
+            server,
+            json!({"name": "path", "in": "path", "required": true, "schema": {"type": "string"}}),
+        ]);
+    }
+    if config.signing.key.is_some() {
+        paths[crate::signing::JWKS_ROUTE] = json!({
+            "get": {
+                "summary": "Public key decision signatures verify with",
+                "responses": {"200": {
+                    "description": "A JWK set",
+                    "content": {"application/json": {"schema": {"type": "object"}}},
+                }},
+            },
+        });
+    }
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "SARK gateway",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "Authorization decisions for MCP tool invocations and agent-to-agent requests",
+        },
+        "paths": paths,
+        "components": components(),
+    })
+}
+
+fn schema(name: &str) -> Value {
+    json!({"$ref": format!("#/components/schemas/{}", name)})
+}
+
+fn probe(summary: &str) -> Value {
+    json!({
+        "get": {
+            "summary": summary,
+            "responses": {
+                "200": {
+                    "description": "Status",
+                    "content": {"application/json": {"schema": {"type": "object"}}},
+                },
+                "503": {
+                    "description": "Not ready",
+                    "content": {"application/json": {"schema": {"type": "object"}}},
+                },
+            },
+        },
+    })
+}
+
+/// `responses` with the problems any decision route can answer with
+fn with_problems(mut responses: Value) -> Value {
+    for (status, description) in [
+        ("400", "Malformed request"),
+        ("401", "Missing or invalid credentials"),
+        ("403", "Refused before evaluation (e.g. unknown tenant)"),
+        ("408", "Request not completed in time"),
+        ("413", "Body too large"),
+        ("429", "Rate limit or quota exceeded"),
+        ("500", "Policy evaluation failed"),
+        ("503", "Overloaded"),
+    ] {
+        responses[status] = json!({
+            "description": description,
+            "content": {"application/problem+json": {"schema": schema("Problem")}},
+        });
+    }
+    responses
+}
+
+fn components() -> Value {
+    let object = json!({"type": "object", "additionalProperties": true});
+    json!({
+        "securitySchemes": {
+            "bearer": {"type": "http", "scheme": "bearer", "bearerFormat": "JWT"},
+            "apiKey": {"type": "apiKey", "in": "header", "name": crate::apikey::HEADER},
+        },
+        "parameters": {
+            "RequestId": {
+                "name": "X-Request-Id",
+                "in": "header",
+                "description": "Correlates the request across logs; generated if absent",
+                "schema": {"type": "string", "maxLength": 128},
+            },
+        },
+        "headers": {
+            "RequestId": {
+                "description": "The request's id, as sent or generated",
+                "schema": {"type": "string"},
+            },
+        },
+        "schemas": {
+            "GatewayAuthRequest": {
+                "type": "object",
+                "required": ["action", "server_name", "tool_name"],
+                "properties": {
+                    "action": {"type": "string", "example": "gateway:tool:invoke"},
+                    "server_name": {"type": "string"},
+                    "tool_name": {"type": "string"},
+                    "parameters": object,
+                    "context": object,
+                    "sensitivity_level": {
+                        "type": "string",
+                        "enum": crate::config::SENSITIVITY_LEVELS,
+                        "description": "Classified from the tool and its parameters where omitted",
+                    },
+                    "dry_run": {"type": "boolean", "default": false},
+                },
+            },
+            "GatewayAuthResponse": {
+                "type": "object",
+                "required": ["allow", "reason", "filtered_parameters", "cache_ttl", "policy_revision"],
+                "properties": {
+                    "allow": {"type": "boolean"},
+                    "reason": {"type": "string"},
+                    "reason_code": {
+                        "type": "string",
+                        "description": "Stable code for the reason, where the policy gives one",
+                    },
+                    "filtered_parameters": {
+                        "type": "object",
+                        "nullable": true,
+                        "additionalProperties": true,
+                        "description": "The parameters the policy lets through",
+                    },
+                    "obligations": {
+                        "type": "object",
+                        "additionalProperties": true,
+                        "description": "Conditions that come with the decision for the enforcement point to act on (also redact, quota and policy-defined members)",
+                        "properties": {
+                            "step_up": {
+                                "type": "object",
+                                "description": "Authenticate again first",
+                                "properties": {
+                                    "acr": {"type": "string"},
+                                    "max_age": {"type": "integer", "minimum": 0},
+                                },
+                            },
+                            "watermark": {
+                                "type": "object",
+                                "required": ["text"],
+                                "properties": {"text": {"type": "string"}},
+                            },
+                            "log_level": {"type": "string", "enum": ["info", "warn", "error"]},
+                        },
+                    },
+                    "cache_ttl": {"type": "integer", "minimum": 0},
+                    "policy_revision": {"type": "string"},
+                    "dry_run": {"type": "boolean"},
+                },
+            },
+            "GatewayBatchRequest": {
+                "type": "object",
+                "required": ["requests"],
+                "properties": {
+                    "requests": {
+                        "type": "array",
+                        "maxItems": crate::MAX_BATCH_SIZE,
+                        "items": schema("GatewayAuthRequest"),
+                    },
+                },
+            },
+            "GatewayBatchResponse": {
+                "type": "object",
+                "required": ["results"],
+                "properties": {
+                    "results": {
+                        "type": "array",
+                        "description": "One result per request, in request order",
+                        "items": {"oneOf": [
+                            schema("GatewayAuthResponse"),
+                            {
+                                "type": "object",
+                                "required": ["error"],
+                                "properties": {"error": {"type": "string"}},
+                            },
+                        ]},
+                    },
+                },
+            },
+            "McpMessage": {
+                "description": "A JSON-RPC request, or a batch of them",
+                "oneOf": [object, {"type": "array", "items": object}],
+            },
+            "McpAuthResponse": {
+                "type": "object",
+                "required": ["allow", "decisions"],
+                "properties": {
+                    "allow": {"type": "boolean", "description": "Whether every request is allowed"},
+                    "decisions": {
+                        "type": "array",
+                        "description": "Each request's decision, with its JSON-RPC id and method",
+                        "items": {"allOf": [
+                            schema("GatewayAuthResponse"),
+                            {
+                                "type": "object",
+                                "properties": {
+                                    "id": {},
+                                    "method": {"type": "string", "example": "tools/call"},
+                                },
+                            },
+                        ]},
+                    },
+                },
+            },
+            "A2AAuthRequest": {
+                "type": "object",
+                "required": ["source_agent", "target_agent", "capability"],
+                "properties": {
+                    "source_agent": schema("AgentIdentity"),
+                    "target_agent": schema("AgentIdentity"),
+                    "capability": {"type": "string", "example": "execute"},
+                    "delegation_chain": {
+                        "type": "array",
+                        "items": {"type": "string"},
+                        "description": "Agents the request was delegated through, originator first",
+                    },
+                    "delegation_tokens": {
+                        "type": "array",
+                        "items": {"type": "string"},
+                        "description": "Delegation JWTs proving the chain, originator's first",
+                    },
+                    "parameters": object,
+                    "context": object,
+                },
+            },
+            "AgentIdentity": {
+                "type": "object",
+                "properties": {
+                    "id": {"type": "string"},
+                    "type": {"type": "string"},
+                    "trust_level": {"type": "string"},
+                    "capabilities": {"type": "array", "items": {"type": "string"}},
+                    "environment": {"type": "string"},
+                },
+            },
+            "Problem": {
+                "type": "object",
+                "description": "RFC 7807 problem details",
+                "required": ["type", "title", "status", "detail", "retryable"],
+                "properties": {
+                    "type": {"type": "string", "example": "urn:sark:problem:forbidden"},
+                    "title": {"type": "string"},
+                    "status": {"type": "integer"},
+                    "detail": {"type": "string"},
+                    "retryable": {"type": "boolean"},
+                    "request_id": {"type": "string"},
+                },
+            },
+        },
+    })
+}
+
+/// Swagger UI for the document on the admin port
+pub async fn swagger_ui() -> Html<String> {
+    Html(format!(
+        r##"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>SARK gateway API</title>
+<link rel="stylesheet" href="{ui}/swagger-ui.css">
+</head>
+<body>
+<div id="swagger-ui"></div>
+<script src="{ui}/swagger-ui-bundle.js"></script>
+<script>SwaggerUIBundle({{url: "{spec}", dom_id: "#swagger-ui"}});</script>
+</body>
+</html>
+"##,
+        ui = SWAGGER_UI,
+        spec = ROUTE,
+    ))
+}
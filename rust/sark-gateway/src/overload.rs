@@ -0,0 +1,107 @@
+//! Load shedding
+//!
+//! Under overload the gateway turns requests away at once with 503 rather
+//! than queueing them until callers time out and retry on top. Two limits
+//! apply, both optional:
+//!
+//! - `max_in_flight` caps decision requests being handled at once
+//! - `max_evaluations` caps policy evaluations (cache misses) running or
+//!   waiting for an engine; misses past it are shed while cached
+//!   decisions keep being served, so cheap lookups never queue behind
+//!   expensive ones
+//!
+//! `evaluation_timeout_ms` bounds how long a cache miss waits for its
+//! evaluation, so a pathological rule (a join over a large data document)
+//! can't hold up the requests behind it. An evaluation past it fails with
+//! an `evaluation-timeout` problem, counted in
+//! `sark_gateway_evaluation_timeouts_total` and settled by the fallback
+//! like any other failed evaluation. The engine can't be interrupted, so
+//! the rule runs to completion off the runtime's threads on the engine it
+//! took; requests behind it are evaluated on other engines compiled from
+//! the same policy (see `policy::ActivePolicy`), not kept waiting for it.
+//! A policy has at most `policy.max_engines` engines, `max_evaluations`
+//! unless set, so each evaluation let through has one to run on, or waits
+//! (within its timeout) for one that outlived its own timeout.
+//!
+//! ```toml
+//! [concurrency]
+//! max_in_flight = 2000
+//! max_evaluations = 64
+//! evaluation_timeout_ms = 250
+//! ```
+
+use crate::config::ConcurrencyConfig;
+use crate::problem::Problem;
+use crate::AppState;
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::header::RETRY_AFTER,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::time::Duration;
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+/// Held while a request or evaluation occupies a slot
+pub struct Slot<'a> {
+    _permit: Option<SemaphorePermit<'a>>,
+}
+
+pub struct Shedder {
+    requests: Option<Semaphore>,
+    evaluations: Option<Semaphore>,
+    evaluation_timeout: Option<Duration>,
+}
+
+impl Shedder {
+    pub fn new(config: &ConcurrencyConfig) -> Self {
+        let limit = |max: usize| (max > 0).then(|| Semaphore::new(max));
+        Self {
+            requests: limit(config.max_in_flight),
+            evaluations: limit(config.max_evaluations),
+            evaluation_timeout: (config.evaluation_timeout_ms > 0)
+                .then(|| Duration::from_millis(config.evaluation_timeout_ms)),
+        }
+    }
+
+    /// A slot for one policy evaluation, or `None` at the limit
+    pub fn evaluation(&self) -> Option<Slot<'_>> {
+        acquire(&self.evaluations)
+    }
+
+    /// How long an evaluation may take, if limited
+    pub fn evaluation_timeout(&self) -> Option<Duration> {
+        self.evaluation_timeout
+    }
+}
+
+fn acquire(limit: &Option<Semaphore>) -> Option<Slot<'_>> {
+    match limit {
+        Some(semaphore) => semaphore.try_acquire().ok().map(|permit| Slot {
+            _permit: Some(permit),
+        }),
+        None => Some(Slot { _permit: None }),
+    }
+}
+
+/// Middleware shedding decision requests past `max_in_flight`
+pub async fn limit(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_string())
+        .unwrap_or_default();
+    if !crate::DECISION_ROUTES.contains(&route.as_str()) {
+        return next.run(request).await;
+    }
+
+    let Some(_slot) = acquire(&state.shedder.requests) else {
+        state.metrics.shed(&route, "in_flight");
+        return (
+            [(RETRY_AFTER, "1")],
+            Problem::Overloaded("Overloaded; retry shortly".to_string()),
+        )
+            .into_response();
+    };
+    next.run(request).await
+}
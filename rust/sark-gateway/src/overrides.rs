@@ -0,0 +1,369 @@
+//! Emergency decision overrides
+//!
+//! During an incident, a tool may need to stop being callable in seconds,
+//! not after a policy change makes it through the bundle pipeline. An
+//! override decides gateway authorizations of a server's tool (or of every
+//! tool, `*`) before the cache or the policy is consulted:
+//!
+//! ```toml
+//! [[overrides]]
+//! server = "github"
+//! tool = "delete_repo"
+//! effect = "deny"
+//! reason = "INC-4711: repository deletion disabled"
+//! ```
+//!
+//! The admin API sets and lifts them at runtime, optionally for `ttl`
+//! seconds:
+//!
+//! - `GET /admin/overrides` - the overrides in force, configured and set
+//! - `PUT /admin/overrides/{server}/{tool}` - set one, from `{"effect":
+//!   "deny", "reason": "...", "ttl": 3600}`
+//! - `DELETE /admin/overrides/{server}/{tool}` - lift one set at runtime
+//!
+//! With `--redis-url`, overrides set at runtime are stored in Redis and
+//! announced on the cache invalidation channel, so every replica applies
+//! them at once, and replicas starting (or resubscribing) later read them
+//! back. Without it they hold on the replica that was told only.
+//! Configured overrides are replaced on config reload.
+//!
+//! Where several overrides match, a `deny` wins. An overridden decision
+//! has `policy_revision` `override` and isn't cached; it is logged,
+//! audited and counted like any other, but not compared against the
+//! shadow policy. Overrides apply to `/gateway/authorize` (and the MCP
+//! routes deciding through it), not to A2A authorizations or dry runs.
+
+use crate::cache::{self, RedisTier};
+use crate::config::OverrideConfig;
+use crate::problem::{JsonBody, Problem};
+use crate::{AppState, GatewayAuthResponse};
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+use tracing::{info, warn};
+
+/// `policy_revision` of overridden decisions
+pub const REVISION: &str = "override";
+
+/// Tool name matching every tool of a server
+const EVERY_TOOL: &str = "*";
+
+/// Redis hash holding runtime overrides, by `server/tool`
+const SHARED_KEY: &str = "overrides";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Effect {
+    Allow,
+    Deny,
+}
+
+/// A decision made for a server's tool instead of the policy's
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Override {
+    pub server: String,
+    /// A tool name, or `*` for every tool
+    pub tool: String,
+    pub effect: Effect,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// Unix time the override lapses at, if set with a `ttl`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<i64>,
+}
+
+impl Override {
+    fn from_config(config: &OverrideConfig) -> Self {
+        Self {
+            server: config.server.clone(),
+            tool: config
+                .tool
+                .clone()
+                .unwrap_or_else(|| EVERY_TOOL.to_string()),
+            effect: config.effect,
+            reason: config.reason.clone(),
+            expires_at: None,
+        }
+    }
+
+    fn matches(&self, server: &str, tool: &str, now: i64) -> bool {
+        self.server == server
+            && (self.tool == EVERY_TOOL || self.tool == tool)
+            && self.expires_at.map_or(true, |expires_at| now < expires_at)
+    }
+
+    fn field(&self) -> String {
+        field(&self.server, &self.tool)
+    }
+
+    /// The decision the override makes
+    fn decision(&self) -> GatewayAuthResponse {
+        let what = match self.effect {
+            Effect::Allow => "allowed",
+            Effect::Deny => "denied",
+        };
+        let target = if self.tool == EVERY_TOOL {
+            format!("server {}", self.server)
+        } else {
+            format!("{} on {}", self.tool, self.server)
+        };
+        let reason = match &self.reason {
+            Some(reason) => format!("Override: {} {}: {}", target, what, reason),
+            None => format!("Override: {} {}", target, what),
+        };
+        GatewayAuthResponse {
+            allow: self.effect == Effect::Allow,
+            reason,
+            reason_code: None,
+            filtered_parameters: None,
+            obligations: None,
+            cache_ttl: 0,
+            policy_revision: REVISION.to_string(),
+            dry_run: false,
+            cached: false,
+        }
+    }
+}
+
+fn field(server: &str, tool: &str) -> String {
+    format!("{}/{}", server, tool)
+}
+
+/// The overrides in force
+pub struct Overrides {
+    /// From the config file (replaced on config reload)
+    configured: RwLock<Vec<Override>>,
+    /// Set through the admin API, by `server/tool`
+    runtime: RwLock<BTreeMap<String, Override>>,
+    /// Where runtime overrides are shared with other replicas
+    shared: Option<RedisTier>,
+}
+
+impl Overrides {
+    pub fn new(config: &[OverrideConfig], shared: Option<RedisTier>) -> Self {
+        Self {
+            configured: RwLock::new(config.iter().map(Override::from_config).collect()),
+            runtime: RwLock::new(BTreeMap::new()),
+            shared,
+        }
+    }
+
+    /// Replace the configured overrides
+    pub fn configure(&self, config: &[OverrideConfig]) {
+        *self.configured.write().expect("overrides lock poisoned") =
+            config.iter().map(Override::from_config).collect();
+    }
+
+    /// The decision an override makes for `tool` on `server`, if any does
+    pub fn decide(&self, server: &str, tool: &str) -> Option<GatewayAuthResponse> {
+        let now = Utc::now().timestamp();
+        let configured = self.configured.read().expect("overrides lock poisoned");
+        let runtime = self.runtime.read().expect("overrides lock poisoned");
+        let mut matching = configured
+            .iter()
+            .chain(runtime.values())
+            .filter(|o| o.matches(server, tool, now));
+        let first = matching.next()?;
+        let chosen = match first.effect {
+            Effect::Deny => first,
+            Effect::Allow => matching.find(|o| o.effect == Effect::Deny).unwrap_or(first),
+        };
+        Some(chosen.decision())
+    }
+
+    /// Every override in force, configured ones first
+    fn list(&self) -> Vec<Listed> {
+        let now = Utc::now().timestamp();
+        let configured = self.configured.read().expect("overrides lock poisoned");
+        let runtime = self.runtime.read().expect("overrides lock poisoned");
+        let configured = configured.iter().map(|o| (o, Source::Config));
+        let runtime = runtime
+            .values()
+            .filter(|o| o.expires_at.map_or(true, |expires_at| now < expires_at))
+            .map(|o| (o, Source::Runtime));
+        configured
+            .chain(runtime)
+            .map(|(o, source)| Listed {
+                rule: o.clone(),
+                source,
+            })
+            .collect()
+    }
+
+    /// Apply a runtime override announced by a replica (this one included)
+    pub fn apply(&self, rule: Override) {
+        info!(server = %rule.server, tool = %rule.tool, effect = ?rule.effect, "Override set");
+        self.runtime
+            .write()
+            .expect("overrides lock poisoned")
+            .insert(rule.field(), rule);
+    }
+
+    /// Lift a runtime override announced by a replica
+    pub fn remove(&self, server: &str, tool: &str) -> bool {
+        let removed = self
+            .runtime
+            .write()
+            .expect("overrides lock poisoned")
+            .remove(&field(server, tool))
+            .is_some();
+        if removed {
+            info!(server = %server, tool = %tool, "Override lifted");
+        }
+        removed
+    }
+
+    /// Replace the runtime overrides with those stored in Redis, as after
+    /// (re)subscribing to announcements
+    pub async fn load_shared(&self) {
+        let Some(shared) = &self.shared else {
+            return;
+        };
+        let stored = match shared.hash_all(SHARED_KEY).await {
+            Ok(stored) => stored,
+            Err(e) => {
+                warn!(error = %format!("{:#}", e), "Failed to read shared overrides");
+                return;
+            }
+        };
+        let now = Utc::now().timestamp();
+        let runtime: BTreeMap<String, Override> = stored
+            .into_values()
+            .filter_map(|value| serde_json::from_str::<Override>(&value).ok())
+            .filter(|o| o.expires_at.map_or(true, |expires_at| now < expires_at))
+            .map(|o| (o.field(), o))
+            .collect();
+        if !runtime.is_empty() {
+            info!(overrides = runtime.len(), "Loaded shared overrides");
+        }
+        *self.runtime.write().expect("overrides lock poisoned") = runtime;
+    }
+
+    /// Store and announce a change to the runtime overrides, so other
+    /// replicas apply it too; returns whether it was shared
+    async fn share(&self, change: cache::Invalidation) -> bool {
+        let Some(shared) = &self.shared else {
+            return false;
+        };
+        let stored = match &change {
+            cache::Invalidation::Override(rule) => match serde_json::to_string(rule) {
+                Ok(value) => shared.hash_set(SHARED_KEY, &rule.field(), &value).await,
+                Err(e) => Err(e.into()),
+            },
+            cache::Invalidation::RemoveOverride { server, tool } => {
+                shared.hash_delete(SHARED_KEY, &field(server, tool)).await
+            }
+            _ => Ok(()),
+        };
+        let result = match stored {
+            Ok(()) => shared.announce(&change).await,
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(()) => true,
+            Err(e) => {
+                warn!(error = %format!("{:#}", e), "Failed to share override change");
+                false
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Source {
+    Config,
+    Runtime,
+}
+
+#[derive(Debug, Serialize)]
+struct Listed {
+    #[serde(flatten)]
+    rule: Override,
+    source: Source,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OverridesList {
+    overrides: Vec<Listed>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SetOverride {
+    effect: Effect,
+    #[serde(default)]
+    reason: Option<String>,
+    /// Seconds until the override lapses; it holds until lifted otherwise
+    #[serde(default)]
+    ttl: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OverrideChange {
+    #[serde(rename = "override", skip_serializing_if = "Option::is_none")]
+    rule: Option<Override>,
+    /// Whether other replicas were told
+    shared: bool,
+}
+
+/// `GET /admin/overrides`
+pub async fn list(State(state): State<AppState>) -> Json<OverridesList> {
+    Json(OverridesList {
+        overrides: state.overrides.list(),
+    })
+}
+
+/// `PUT /admin/overrides/{server}/{tool}`
+pub async fn set(
+    State(state): State<AppState>,
+    Path((server, tool)): Path<(String, String)>,
+    JsonBody(request): JsonBody<SetOverride>,
+) -> Result<Json<OverrideChange>, Problem> {
+    if request.ttl == Some(0) {
+        return Err(Problem::InvalidRequest(
+            "An override's ttl must be at least 1".to_string(),
+        ));
+    }
+    let rule = Override {
+        server,
+        tool,
+        effect: request.effect,
+        reason: request.reason,
+        expires_at: request
+            .ttl
+            .map(|ttl| Utc::now().timestamp().saturating_add(ttl as i64)),
+    };
+    state.overrides.apply(rule.clone());
+    let shared = state
+        .overrides
+        .share(cache::Invalidation::Override(rule.clone()))
+        .await;
+    Ok(Json(OverrideChange {
+        rule: Some(rule),
+        shared,
+    }))
+}
+
+/// `DELETE /admin/overrides/{server}/{tool}`
+pub async fn lift(
+    State(state): State<AppState>,
+    Path((server, tool)): Path<(String, String)>,
+) -> Result<Json<OverrideChange>, Problem> {
+    if !state.overrides.remove(&server, &tool) {
+        return Err(Problem::NotFound(format!(
+            "No override set for {} on {}",
+            tool, server
+        )));
+    }
+    let shared = state
+        .overrides
+        .share(cache::Invalidation::RemoveOverride { server, tool })
+        .await;
+    Ok(Json(OverrideChange { rule: None, shared }))
+}
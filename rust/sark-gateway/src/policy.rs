@@ -0,0 +1,900 @@
+//! Policy loading
+//!
+//! Policies reach the gateway either as a directory of `.rego` files or as
+//! an OPA bundle. Both are read into a [`PolicySet`] (modules, data
+//! documents, and an optional revision) which is compiled into a fresh
+//! grid-opa engine, so a failed load never leaves a half-populated engine
+//! behind.
+//!
+//! A baseline can also be compiled into the binary, for deployments with
+//! neither (an air-gapped edge site without a bundle server): building with
+//! `SARK_GATEWAY_EMBEDDED_POLICIES` naming a directory (relative to the
+//! crate) embeds its policy and `data.json` files, which are used only when
+//! no directory or bundle is configured. `policies/baseline` denies by
+//! default, with exceptions for admins and the tools listed in its data:
+//!
+//! ```text
+//! SARK_GATEWAY_EMBEDDED_POLICIES=policies/baseline cargo build --release
+//! ```
+//!
+//! Any source may also hold `.rego.tmpl` templates, rendered with the
+//! configured variables (see [`template`](crate::template)) before the set
+//! compiles, and `.cel.toml` packages written in CEL (see `sark_cel`),
+//! which decide the queries naming them in place of the engine. A package
+//! is written in one language or the other, not both.
+//!
+//! With `rollout.canary_weight`, a newly loaded revision isn't activated
+//! for every caller at once: as the [`Canary`], it decides for that
+//! percent of them while the active revision decides for the rest, until
+//! it is promoted or discarded through the admin API. Tenant policies are
+//! activated outright.
+
+use crate::cache::Namespace;
+use crate::smoke::StartupChecks;
+use crate::template::{self, Vars};
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Utc};
+use grid_opa::OPAEngine;
+use serde::Serialize;
+use serde_json::{Map, Value as JsonValue};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex as StdMutex, RwLock};
+use tokio::sync::Mutex;
+use tracing::{debug, info, warn};
+
+pub use sark_policy::{package_of, validate_policy, Diagnostic};
+
+/// A compiled policy set, identified by its revision
+///
+/// An engine evaluates one query at a time, so the set keeps a pool of
+/// engines compiled from it: an evaluation takes an idle one (compiling
+/// another if every one is busy, up to `policy.max_engines`, past which it
+/// waits for one to be put back) and puts it back when done. Evaluations
+/// hold an [`Evaluator`] rather than the policy lock, so one that runs
+/// past its timeout holds up no one but itself, or, at the limit, the
+/// evaluations waiting for its engine.
+pub struct ActivePolicy {
+    pub set: Arc<PolicySet>,
+    engines: Arc<Engines>,
+    revision: String,
+    activated_at: DateTime<Utc>,
+}
+
+/// Engines compiled from a set, idle until an evaluation takes one
+struct Engines {
+    set: Arc<PolicySet>,
+    /// Most engines compiled from `set` (0 is unlimited)
+    max: usize,
+    pool: StdMutex<Pool>,
+    /// Notified when an engine is put back, or one that was to be compiled
+    /// couldn't be
+    returned: Condvar,
+}
+
+/// An [`Engines`]' engines, under its lock
+struct Pool {
+    idle: Vec<OPAEngine>,
+    /// Engines compiled or being compiled, idle or not
+    compiled: usize,
+}
+
+impl Engines {
+    /// Run `f` on an idle engine, or a newly compiled one if none is and
+    /// the pool isn't full, or else the first one put back
+    fn with_engine<T>(&self, f: impl FnOnce(&mut OPAEngine) -> T) -> Result<T> {
+        let mut pool = self.pool.lock().expect("engine pool lock poisoned");
+        let idle = loop {
+            if let Some(engine) = pool.idle.pop() {
+                break Some(engine);
+            }
+            if self.max == 0 || pool.compiled < self.max {
+                pool.compiled += 1;
+                break None;
+            }
+            debug!(max = self.max, "Every engine busy; waiting for one");
+            pool = self.returned.wait(pool).expect("engine pool lock poisoned");
+        };
+        drop(pool);
+        let mut engine = match idle {
+            Some(engine) => engine,
+            None => {
+                debug!("Every engine busy; compiling another");
+                self.set.build().map_err(|e| {
+                    // Let a waiting evaluation try in its place
+                    self.pool
+                        .lock()
+                        .expect("engine pool lock poisoned")
+                        .compiled -= 1;
+                    self.returned.notify_one();
+                    e
+                })?
+            }
+        };
+        let result = f(&mut engine);
+        self.pool
+            .lock()
+            .expect("engine pool lock poisoned")
+            .idle
+            .push(engine);
+        self.returned.notify_one();
+        Ok(result)
+    }
+
+    fn evaluate(&self, query: &str, input: &JsonValue) -> Result<JsonValue> {
+        if let Some(package) = self.set.cel_package(query) {
+            return Ok(package.evaluate(query, input, &self.set.data)?);
+        }
+        self.with_engine(|engine| evaluate(engine, &self.set, query, input))?
+    }
+}
+
+impl ActivePolicy {
+    /// The active policy `set`, compiled as `engine`, evaluating on at
+    /// most `max_engines` engines (0 is unlimited)
+    pub fn new(engine: OPAEngine, set: PolicySet, max_engines: usize) -> Self {
+        // Directories and edited sets have no manifest revision; identify
+        // them by content so they can still be listed and rolled back to.
+        let revision = set
+            .revision
+            .clone()
+            .unwrap_or_else(|| format!("sha256:{}", &set.digest()[..12]));
+        let set = Arc::new(set);
+        Self {
+            engines: Arc::new(Engines {
+                set: set.clone(),
+                max: max_engines,
+                pool: StdMutex::new(Pool {
+                    idle: vec![engine],
+                    compiled: 1,
+                }),
+                returned: Condvar::new(),
+            }),
+            set,
+            revision,
+            activated_at: Utc::now(),
+        }
+    }
+
+    pub fn revision(&self) -> &str {
+        &self.revision
+    }
+
+    /// Evaluate `query` against `input`, by its CEL package if it has one
+    pub fn evaluate(&self, query: &str, input: &JsonValue) -> Result<JsonValue> {
+        self.engines.evaluate(query, input)
+    }
+
+    /// Run `f` on one of the set's engines, failing only if another had
+    /// to be compiled and couldn't be
+    pub fn with_engine<T>(&self, f: impl FnOnce(&mut OPAEngine) -> T) -> Result<T> {
+        self.engines.with_engine(f)
+    }
+
+    /// What evaluating the policy takes, to evaluate without the lock
+    pub fn evaluator(&self) -> Evaluator {
+        Evaluator {
+            engines: self.engines.clone(),
+            revision: self.revision.clone(),
+        }
+    }
+}
+
+/// An [`ActivePolicy`]'s engines, for evaluating outside the policy lock
+#[derive(Clone)]
+pub struct Evaluator {
+    engines: Arc<Engines>,
+    revision: String,
+}
+
+impl Evaluator {
+    pub fn revision(&self) -> &str {
+        &self.revision
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.engines.set.is_empty()
+    }
+
+    /// Evaluate `query` against `input`, by its CEL package if it has one
+    pub fn evaluate(&self, query: &str, input: &JsonValue) -> Result<JsonValue> {
+        self.engines.evaluate(query, input)
+    }
+}
+
+/// Summary of a stored policy revision
+#[derive(Debug, Serialize)]
+pub struct RevisionInfo {
+    pub revision: String,
+    pub activated_at: DateTime<Utc>,
+    pub active: bool,
+    /// Percent of callers it decides for, as the canary
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub canary_weight: Option<u8>,
+}
+
+/// A revision deciding for a share of callers alongside the active one,
+/// while it is rolled out
+///
+/// Which callers is sticky: a caller's user id, hashed with the canary's
+/// revision, puts them in one of 100 buckets, and the first `weight`
+/// buckets get the canary. Raising the weight only adds callers, and
+/// every rollout picks its own.
+pub struct Canary {
+    pub policy: ActivePolicy,
+    /// Percent of callers it decides for
+    pub weight: u8,
+}
+
+/// Whether `caller` is among the `weight` percent of callers `revision`
+/// decides for as the canary
+fn to_canary(revision: &str, caller: &str, weight: u8) -> bool {
+    let digest = Sha256::new()
+        .chain_update(revision.as_bytes())
+        .chain_update([0])
+        .chain_update(caller.as_bytes())
+        .finalize();
+    let bucket = u64::from_be_bytes(digest[..8].try_into().expect("8 bytes")) % 100;
+    bucket < u64::from(weight)
+}
+
+#[derive(Debug, Default)]
+struct Serving {
+    active: String,
+    /// The canary's revision and weight, while one is rolled out
+    canary: Option<(String, u8)>,
+}
+
+/// The revisions deciding, readable without waiting for the policy lock
+/// (which activations and profiling hold)
+#[derive(Debug, Clone)]
+pub struct CurrentRevision(Arc<RwLock<Serving>>);
+
+impl CurrentRevision {
+    /// Whether `revision` is the active one, or the canary's
+    pub fn is(&self, revision: &str) -> bool {
+        let serving = self.0.read().expect("revision lock poisoned");
+        serving.active == revision
+            || serving
+                .canary
+                .as_ref()
+                .is_some_and(|(canary, _)| canary == revision)
+    }
+
+    /// Whether `revision` is the one deciding for `caller`
+    pub fn serves(&self, revision: &str, caller: &str) -> bool {
+        let serving = self.0.read().expect("revision lock poisoned");
+        match &serving.canary {
+            Some((canary, weight)) if to_canary(canary, caller, *weight) => canary == revision,
+            _ => serving.active == revision,
+        }
+    }
+
+    /// Whether a canary is being rolled out
+    pub fn rolling_out(&self) -> bool {
+        self.0
+            .read()
+            .expect("revision lock poisoned")
+            .canary
+            .is_some()
+    }
+
+    fn set(&self, active: &str, canary: Option<&Canary>) {
+        *self.0.write().expect("revision lock poisoned") = Serving {
+            active: active.to_string(),
+            canary: canary.map(|c| (c.policy.revision.clone(), c.weight)),
+        };
+    }
+}
+
+/// The active policy plus the last few it replaced
+///
+/// Previous revisions stay compiled, so rolling back a bad policy push is
+/// a swap rather than a reload. Every activation (new bundle, directory
+/// reload, data update, rollback) pushes the outgoing revision onto the
+/// history, which keeps at most `keep` entries.
+///
+/// With a canary weight set, sets compiled by [`activate`] (reloads,
+/// directory changes, new bundles) start out as the [`Canary`] instead,
+/// until promoted; one arriving during a rollout replaces the canary.
+pub struct PolicyStore {
+    active: ActivePolicy,
+    /// Most recent first
+    history: VecDeque<ActivePolicy>,
+    keep: usize,
+    canary: Option<Canary>,
+    /// Weight new sets start out as the canary at (0 activates them)
+    canary_weight: u8,
+    current: CurrentRevision,
+    /// Data documents from external sources, by path, laid over every set
+    /// activated through [`activate`] so a policy reload doesn't lose them
+    external: BTreeMap<String, JsonValue>,
+    /// Checks every set activated through [`activate`] must pass, if
+    /// configured (see `smoke`)
+    checks: Option<Arc<StartupChecks>>,
+    /// Engines each set activated through the store evaluates on at most
+    /// (0 is unlimited)
+    max_engines: usize,
+}
+
+impl PolicyStore {
+    pub fn new(active: ActivePolicy, keep: usize) -> Self {
+        let current = CurrentRevision(Arc::new(RwLock::new(Serving::default())));
+        current.set(&active.revision, None);
+        Self {
+            active,
+            history: VecDeque::new(),
+            keep,
+            canary: None,
+            canary_weight: 0,
+            current,
+            external: BTreeMap::new(),
+            checks: None,
+            max_engines: 0,
+        }
+    }
+
+    /// Refuse sets [`activate`] compiles unless they pass `checks`
+    pub fn set_checks(&mut self, checks: Option<Arc<StartupChecks>>) {
+        self.checks = checks;
+    }
+
+    pub fn checks(&self) -> Option<&Arc<StartupChecks>> {
+        self.checks.as_ref()
+    }
+
+    /// Handle that follows the active revision through activations
+    pub fn current_revision(&self) -> CurrentRevision {
+        self.current.clone()
+    }
+
+    pub fn active(&self) -> &ActivePolicy {
+        &self.active
+    }
+
+    pub fn canary(&self) -> Option<&Canary> {
+        self.canary.as_ref()
+    }
+
+    /// The policy deciding for `caller`: the canary's, if they are among
+    /// its callers, or the active one
+    pub fn serving(&self, caller: &str) -> &ActivePolicy {
+        match &self.canary {
+            Some(canary) if to_canary(&canary.policy.revision, caller, canary.weight) => {
+                &canary.policy
+            }
+            _ => &self.active,
+        }
+    }
+
+    /// Make `next` the active policy
+    pub fn activate(&mut self, mut next: ActivePolicy) {
+        next.activated_at = Utc::now();
+        let previous = std::mem::replace(&mut self.active, next);
+        self.history.push_front(previous);
+        self.history.truncate(self.keep);
+        self.current
+            .set(&self.active.revision, self.canary.as_ref());
+    }
+
+    /// Set the weight sets compiled from then on start out as the canary
+    /// at; 0 activates them outright
+    pub fn set_canary_weight(&mut self, weight: u8) {
+        self.canary_weight = weight;
+    }
+
+    pub fn set_max_engines(&mut self, max: usize) {
+        self.max_engines = max;
+    }
+
+    /// Roll `next` out as the canary if a canary weight is set, or else
+    /// activate it. A set with the active revision is activated either
+    /// way, as there is nothing to roll out.
+    fn start(&mut self, mut next: ActivePolicy) {
+        if self.canary_weight == 0 || next.revision == self.active.revision {
+            self.activate(next);
+            return;
+        }
+        next.activated_at = Utc::now();
+        if let Some(replaced) = &self.canary {
+            info!(revision = %replaced.policy.revision, "Canary policy replaced");
+        }
+        info!(
+            revision = %next.revision,
+            weight = self.canary_weight,
+            "Rolling out policy as canary"
+        );
+        self.canary = Some(Canary {
+            policy: next,
+            weight: self.canary_weight,
+        });
+        self.current
+            .set(&self.active.revision, self.canary.as_ref());
+    }
+
+    /// Change the share of callers the canary decides for
+    pub fn weigh_canary(&mut self, weight: u8) -> Result<()> {
+        let canary = self
+            .canary
+            .as_mut()
+            .context("No canary policy rolled out")?;
+        canary.weight = weight;
+        self.current
+            .set(&self.active.revision, self.canary.as_ref());
+        Ok(())
+    }
+
+    /// Make the canary the active policy for every caller, returning its
+    /// revision. Data refreshed from external sources meanwhile is laid
+    /// over it first.
+    pub fn promote_canary(&mut self) -> Result<String> {
+        let canary = self.canary.take().context("No canary policy rolled out")?;
+        let revision = canary.policy.revision.clone();
+        let set = self.with_external(PolicySet::clone(&canary.policy.set))?;
+        let next = if set.data() == canary.policy.set.data() {
+            canary.policy
+        } else {
+            ActivePolicy::new(set.compile()?, set, self.max_engines)
+        };
+        self.activate(next);
+        Ok(revision)
+    }
+
+    /// Stop rolling the canary out, keeping it in the history so it can be
+    /// activated later; returns its revision
+    pub fn discard_canary(&mut self) -> Result<String> {
+        let canary = self.canary.take().context("No canary policy rolled out")?;
+        let revision = canary.policy.revision.clone();
+        self.history.push_front(canary.policy);
+        self.history.truncate(self.keep);
+        self.current.set(&self.active.revision, None);
+        Ok(revision)
+    }
+
+    /// Re-activate a stored revision
+    pub fn activate_revision(&mut self, revision: &str) -> Result<()> {
+        let index = self
+            .history
+            .iter()
+            .position(|p| p.revision == revision)
+            .with_context(|| format!("Unknown policy revision {}", revision))?;
+        let next = self.history.remove(index).expect("index from position");
+        self.activate(next);
+        Ok(())
+    }
+
+    /// Re-activate the revision the current one replaced, returning it
+    pub fn rollback(&mut self) -> Result<String> {
+        let next = self
+            .history
+            .pop_front()
+            .context("No previous policy revision to roll back to")?;
+        let revision = next.revision.clone();
+        self.activate(next);
+        Ok(revision)
+    }
+
+    /// Active revision first, then the canary, then history, most recent
+    /// first
+    pub fn revisions(&self) -> Vec<RevisionInfo> {
+        let info = |p: &ActivePolicy, active, canary_weight| RevisionInfo {
+            revision: p.revision.clone(),
+            activated_at: p.activated_at,
+            active,
+            canary_weight,
+        };
+        std::iter::once(info(&self.active, true, None))
+            .chain(
+                self.canary
+                    .iter()
+                    .map(|c| info(&c.policy, false, Some(c.weight))),
+            )
+            .chain(self.history.iter().map(|p| info(p, false, None)))
+            .collect()
+    }
+
+    /// Apply `update` to a copy of the active data and activate the result;
+    /// on any error the current policy stays active unchanged
+    pub fn update_data(&mut self, update: impl FnOnce(&mut PolicySet) -> Result<()>) -> Result<()> {
+        let mut set = PolicySet::clone(&self.active.set);
+        update(&mut set)?;
+        // The edited set no longer matches the bundle's manifest revision
+        set.revision = None;
+        let engine = set.compile()?;
+        self.activate(ActivePolicy::new(engine, set, self.max_engines));
+        Ok(())
+    }
+
+    /// Replace the data at `path` with `value` fetched from an external
+    /// source, now and in sets activated later; `false` if that is what
+    /// it already was
+    pub fn refresh_data(&mut self, path: &str, value: JsonValue) -> Result<bool> {
+        if self.external.get(path) == Some(&value) {
+            return Ok(false);
+        }
+        self.update_data(|set| set.set_data(path, value.clone()))?;
+        self.external.insert(path.to_string(), value);
+        Ok(true)
+    }
+
+    /// `set` with the external data documents laid over its own
+    fn with_external(&self, mut set: PolicySet) -> Result<PolicySet> {
+        for (path, value) in &self.external {
+            set.set_data(path, value.clone())
+                .with_context(|| format!("Failed to apply refreshed data at {}", path))?;
+        }
+        Ok(set)
+    }
+}
+
+/// An evaluated package as JSON; undefined rules are simply absent
+pub fn document(value: &grid_opa::Value) -> JsonValue {
+    value
+        .to_json_str()
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+/// Evaluate `query` against `input` on `engine`, compiled from `set`, or
+/// by the set's CEL package the query names
+pub fn evaluate(
+    engine: &mut OPAEngine,
+    set: &PolicySet,
+    query: &str,
+    input: &JsonValue,
+) -> Result<JsonValue> {
+    if let Some(package) = set.cel_package(query) {
+        return Ok(package.evaluate(query, input, &set.data)?);
+    }
+    let input = grid_opa::Value::from_json_str(&input.to_string())
+        .map_err(|e| anyhow!("Failed to build OPA input: {}", e))?;
+    let result = engine
+        .evaluate(query, input)
+        .map_err(|e| anyhow!("{}", e))?;
+    Ok(document(&result))
+}
+
+/// Compile `set`, with any externally refreshed data over it, and make it
+/// the active policy (or, with a canary weight set, the canary), unless it
+/// fails the store's startup checks
+///
+/// Compilation happens outside the lock so requests keep being served by
+/// the current engine meanwhile. Cached decisions are dropped on success
+/// since they were made under the old policy; any that slip back in (from
+/// an evaluation that was already running, or another replica sharing the
+/// Redis tier) are bypassed by their revision on lookup.
+pub async fn activate(
+    policy: &Mutex<PolicyStore>,
+    decisions: &[Namespace],
+    set: PolicySet,
+) -> Result<()> {
+    let (set, checks, max_engines) = {
+        let store = policy.lock().await;
+        (
+            store.with_external(set)?,
+            store.checks.clone(),
+            store.max_engines,
+        )
+    };
+    let next = ActivePolicy::new(set.compile()?, set, max_engines);
+    if let Some(checks) = checks {
+        checks.verify(&next)?;
+    }
+    policy.lock().await.start(next);
+    for namespace in decisions {
+        namespace.clear().await;
+    }
+    Ok(())
+}
+
+/// `(path, contents)` of the policy files compiled into the binary
+const EMBEDDED: &[(&str, &[u8])] = include!(concat!(env!("OUT_DIR"), "/embedded_policies.rs"));
+
+/// Policy modules and data documents to compile into an engine
+#[derive(Debug, Default, Clone)]
+pub struct PolicySet {
+    /// Revision from the bundle manifest, if any
+    pub revision: Option<String>,
+    /// `(name, source)` pairs, named after their path without `.rego`
+    modules: Vec<(String, String)>,
+    /// `(name, template)` pairs not yet rendered into `modules`, named
+    /// after their path without `.rego.tmpl`
+    templates: Vec<(String, String)>,
+    /// `(name, package)` pairs, named after their path without `.cel.toml`
+    cel: Vec<(String, Arc<sark_cel::Package>)>,
+    /// Merged data document (`data.json` files, keyed by directory)
+    data: Map<String, JsonValue>,
+}
+
+impl PolicySet {
+    /// Read every `.rego`, `.rego.tmpl`, `.cel.toml` and `data.json` file
+    /// under `dir` (recursively)
+    pub fn from_dir(dir: &Path) -> Result<Self> {
+        let mut paths = Vec::new();
+        collect_files(dir, &mut paths)
+            .with_context(|| format!("Failed to read policy directory {}", dir.display()))?;
+        paths.sort();
+
+        let mut files = Vec::with_capacity(paths.len());
+        for path in paths {
+            let contents =
+                fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+            let relative = path.strip_prefix(dir).unwrap_or(&path);
+            files.push((relative_name(relative), contents));
+        }
+
+        Self::from_files(files)
+    }
+
+    /// The policies compiled into the binary, if it was built with any
+    pub fn embedded() -> Result<Option<Self>> {
+        if EMBEDDED.is_empty() {
+            return Ok(None);
+        }
+        let files = EMBEDDED
+            .iter()
+            .map(|(path, contents)| (path.to_string(), contents.to_vec()));
+        Self::from_files(files)
+            .context("Failed to load embedded policies")
+            .map(Some)
+    }
+
+    /// Whether the binary was built with policies of its own
+    pub fn has_embedded() -> bool {
+        !EMBEDDED.is_empty()
+    }
+
+    /// Build a policy set from `(path, contents)` pairs laid out like an
+    /// OPA bundle
+    pub fn from_files(files: impl IntoIterator<Item = (String, Vec<u8>)>) -> Result<Self> {
+        let mut set = Self::default();
+
+        for (path, contents) in files {
+            let path = path.trim_start_matches("./").trim_start_matches('/');
+            let (dir, file) = match path.rsplit_once('/') {
+                Some((dir, file)) => (dir, file),
+                None => ("", path),
+            };
+
+            if let Some(name) = path.strip_suffix(".rego") {
+                let source = String::from_utf8(contents)
+                    .with_context(|| format!("Policy {} is not valid UTF-8", path))?;
+                set.modules.push((name.to_string(), source));
+            } else if let Some(name) = path.strip_suffix(".rego.tmpl") {
+                let template = String::from_utf8(contents)
+                    .with_context(|| format!("Policy template {} is not valid UTF-8", path))?;
+                set.templates.push((name.to_string(), template));
+            } else if let Some(name) = path.strip_suffix(".cel.toml") {
+                let source = String::from_utf8(contents)
+                    .with_context(|| format!("CEL package {} is not valid UTF-8", path))?;
+                let package = sark_cel::Package::parse(&source)
+                    .with_context(|| format!("Failed to load CEL package {}", path))?;
+                set.cel.push((name.to_string(), Arc::new(package)));
+            } else if file == "data.json" {
+                let document: JsonValue = serde_json::from_slice(&contents)
+                    .with_context(|| format!("Failed to parse {}", path))?;
+                merge_data(&mut set.data, dir, document)
+                    .with_context(|| format!("Failed to merge {}", path))?;
+            } else if path == ".manifest" {
+                let manifest: JsonValue =
+                    serde_json::from_slice(&contents).context("Failed to parse bundle manifest")?;
+                set.revision = manifest
+                    .get("revision")
+                    .and_then(JsonValue::as_str)
+                    .map(str::to_string);
+            } else if path.ends_with(".wasm") {
+                warn!(path = %path, "Ignoring WASM policy; only rego source is supported");
+            } else {
+                debug!(path = %path, "Ignoring non-policy file");
+            }
+        }
+
+        if set.modules.is_empty() && set.templates.is_empty() && set.cel.is_empty() {
+            bail!("No .rego or .cel.toml policies found");
+        }
+        Ok(set)
+    }
+
+    /// The set with its templates rendered into policy modules with `vars`
+    pub fn rendered(mut self, vars: &Vars) -> Result<Self> {
+        for (name, source) in std::mem::take(&mut self.templates) {
+            let rendered = template::render(&source, vars)
+                .with_context(|| format!("Failed to render policy template {}.rego.tmpl", name))?;
+            self.modules.push((name, rendered));
+        }
+        Ok(self)
+    }
+
+    /// Policy modules as `(name, source)` pairs
+    pub fn modules(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.modules
+            .iter()
+            .map(|(name, source)| (name.as_str(), source.as_str()))
+    }
+
+    /// CEL packages as `(name, package)` pairs
+    pub fn cel_packages(&self) -> impl Iterator<Item = (&str, &sark_cel::Package)> {
+        self.cel
+            .iter()
+            .map(|(name, package)| (name.as_str(), package.as_ref()))
+    }
+
+    /// Whether the set has any policy, rego or CEL
+    pub fn is_empty(&self) -> bool {
+        self.modules.is_empty() && self.cel.is_empty()
+    }
+
+    /// Every rule the modules define, as fully qualified `data.<package>.<rule>`
+    /// queries in source order
+    pub fn rules(&self) -> Vec<String> {
+        sark_policy::rules(self.modules().map(|(_, source)| source))
+    }
+
+    /// The merged data document
+    pub fn data(&self) -> &Map<String, JsonValue> {
+        &self.data
+    }
+
+    /// Replace the data document at slash-separated `path` (`""` is the
+    /// root, which must be an object)
+    pub fn set_data(&mut self, path: &str, value: JsonValue) -> Result<()> {
+        Ok(sark_policy::set_data(&mut self.data, path, value)?)
+    }
+
+    /// Remove the data document at `path`, returning whether it existed
+    pub fn remove_data(&mut self, path: &str) -> bool {
+        sark_policy::remove_data(&mut self.data, path)
+    }
+
+    /// Apply an RFC 6902 JSON Patch to the data document (paths are
+    /// relative to `data`, e.g. `/roles/admins/-`)
+    pub fn patch_data(&mut self, patch: &json_patch::Patch) -> Result<()> {
+        Ok(sark_policy::patch_data(&mut self.data, patch)?)
+    }
+
+    /// Hex SHA-256 over the modules, CEL packages and data
+    fn digest(&self) -> String {
+        let mut hasher = Sha256::new();
+        for (name, source) in &self.modules {
+            hasher.update(name.as_bytes());
+            hasher.update([0]);
+            hasher.update(source.as_bytes());
+            hasher.update([0]);
+        }
+        for (name, package) in &self.cel {
+            hasher.update(name.as_bytes());
+            hasher.update(b".cel\0");
+            hasher.update(package.source().as_bytes());
+            hasher.update([0]);
+        }
+        hasher.update(JsonValue::Object(self.data.clone()).to_string().as_bytes());
+        hex::encode(hasher.finalize())
+    }
+
+    /// Compile the set into a new engine
+    pub fn compile(&self) -> Result<OPAEngine> {
+        let engine = self.build()?;
+        info!(
+            policies = self.modules.len(),
+            cel_packages = self.cel.len(),
+            revision = self.revision.as_deref().unwrap_or("-"),
+            "Compiled policy set"
+        );
+        Ok(engine)
+    }
+
+    /// The CEL package answering `query`, if one does
+    fn cel_package(&self, query: &str) -> Option<&sark_cel::Package> {
+        self.cel
+            .iter()
+            .find(|(_, package)| package.answers(query))
+            .map(|(_, package)| &**package)
+    }
+
+    /// Compile the set into a new engine, quietly
+    fn build(&self) -> Result<OPAEngine> {
+        if let Some((name, _)) = self.templates.first() {
+            bail!("Policy template {}.rego.tmpl was never rendered", name);
+        }
+        for (name, package) in &self.cel {
+            if let Some((module, _)) = self
+                .modules
+                .iter()
+                .find(|(_, source)| package_of(source) == Some(package.name()))
+            {
+                bail!(
+                    "CEL package {}.cel.toml and policy {}.rego are both package {}",
+                    name,
+                    module,
+                    package.name()
+                );
+            }
+        }
+        let mut engine = OPAEngine::new().context("Failed to initialize OPA engine")?;
+
+        for (name, source) in &self.modules {
+            engine
+                .load_policy(name.clone(), source.clone())
+                .with_context(|| format!("Failed to compile policy {}.rego", name))?;
+        }
+
+        for (name, source) in sark_policy::data_modules(&self.data)? {
+            engine
+                .load_policy(name.clone(), source)
+                .with_context(|| format!("Failed to load data document {}", name))?;
+        }
+
+        Ok(engine)
+    }
+}
+
+/// Every `.rego` and `.rego.tmpl` file under `dir`, sorted
+pub fn rego_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    collect_files(dir, &mut files)
+        .with_context(|| format!("Failed to read policy directory {}", dir.display()))?;
+    files.retain(|path| path.extension().is_some_and(|ext| ext == "rego") || is_template(path));
+    files.sort();
+    Ok(files)
+}
+
+/// Slash-separated form of a relative path, independent of platform
+fn relative_name(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else if path.extension().is_some_and(|ext| ext == "rego")
+            || is_template(&path)
+            || path.to_string_lossy().ends_with(".cel.toml")
+            || path.file_name().is_some_and(|name| name == "data.json")
+        {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Whether `path` is a policy template
+pub fn is_template(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.ends_with(".rego.tmpl"))
+}
+
+/// Merge `document` into `root` at the slash-separated `dir`
+fn merge_data(root: &mut Map<String, JsonValue>, dir: &str, document: JsonValue) -> Result<()> {
+    let mut node = root;
+    for segment in dir.split('/').filter(|s| !s.is_empty()) {
+        node = match node
+            .entry(segment.to_string())
+            .or_insert_with(|| JsonValue::Object(Map::new()))
+        {
+            JsonValue::Object(map) => map,
+            _ => bail!("data path {} conflicts with a non-object value", dir),
+        };
+    }
+
+    match document {
+        JsonValue::Object(map) => {
+            for (key, value) in map {
+                if node.contains_key(&key) {
+                    bail!("data key {}/{} is defined more than once", dir, key);
+                }
+                node.insert(key, value);
+            }
+            Ok(())
+        }
+        _ => Err(anyhow!("data document must be a JSON object")),
+    }
+}
@@ -0,0 +1,113 @@
+//! Persisted policy check results
+//!
+//! Compiling hundreds of modules on every CI run repeats the same work for
+//! files that didn't change. `check-policy --policy-cache-dir <dir>` keeps
+//! what compiling each file found in `<dir>`, keyed by a digest of its name
+//! and (rendered) source, and only compiles the files it has no entry for:
+//!
+//! ```text
+//! sark-gateway check-policy policies/ --policy-cache-dir .cache/sark-policy
+//! ```
+//!
+//! Entries live in a directory per regorus version (`regorus-0.2.8/`),
+//! since another regorus may judge the same source differently; opening
+//! the cache with a different version removes the others. Template errors
+//! are found again on each run, as rendering is cheap.
+//!
+//! The gateway itself still compiles a policy set when it activates it:
+//! grid-opa's engine has no form that can be written out and loaded back.
+
+use crate::policy::{self, Diagnostic};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+/// regorus version entries are made with
+const REGORUS: &str = env!("SARK_REGORUS_VERSION");
+
+/// A diagnostic as stored; its file is the one it is looked up for
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    message: String,
+    line: Option<u32>,
+    column: Option<u32>,
+}
+
+/// Check results by file content, for one regorus version
+pub struct PolicyCache {
+    dir: PathBuf,
+}
+
+impl PolicyCache {
+    /// Open the cache under `root`, dropping entries other regorus versions
+    /// made
+    pub fn open(root: &Path) -> Result<Self> {
+        let current = format!("regorus-{}", REGORUS);
+        let dir = root.join(&current);
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create policy cache {}", dir.display()))?;
+        for entry in fs::read_dir(root)?.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.starts_with("regorus-") && name != current {
+                debug!(dir = %entry.path().display(), "Removing stale policy cache");
+                if let Err(e) = fs::remove_dir_all(entry.path()) {
+                    warn!(error = %e, dir = %entry.path().display(), "Failed to remove stale policy cache");
+                }
+            }
+        }
+        Ok(Self { dir })
+    }
+
+    /// The diagnostics of `file` with `source`, compiling it only if they
+    /// aren't cached
+    pub fn validate(&self, file: &str, source: &str) -> Result<Vec<Diagnostic>> {
+        let path = self.dir.join(format!("{}.json", key(file, source)));
+        let cached = fs::read(&path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice::<Vec<Entry>>(&bytes).ok());
+        if let Some(entries) = cached {
+            return Ok(entries
+                .into_iter()
+                .map(|entry| Diagnostic {
+                    severity: "error",
+                    message: entry.message,
+                    file: file.to_string(),
+                    line: entry.line,
+                    column: entry.column,
+                })
+                .collect());
+        }
+
+        let diagnostics = policy::validate_policy(file, source)?;
+        let entries: Vec<Entry> = diagnostics
+            .iter()
+            .map(|d| Entry {
+                message: d.message.clone(),
+                line: d.line,
+                column: d.column,
+            })
+            .collect();
+        // Written aside and renamed, so a concurrent run never reads half
+        let partial = path.with_extension(format!("{}.partial", std::process::id()));
+        let written = serde_json::to_vec(&entries)
+            .map_err(anyhow::Error::from)
+            .and_then(|bytes| Ok(fs::write(&partial, bytes)?))
+            .and_then(|()| Ok(fs::rename(&partial, &path)?));
+        if let Err(e) = written {
+            warn!(error = %format!("{:#}", e), file = %file, "Failed to cache policy check");
+        }
+        Ok(diagnostics)
+    }
+}
+
+/// Hex SHA-256 over a file's name and source
+fn key(file: &str, source: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(file.as_bytes());
+    hasher.update([0]);
+    hasher.update(source.as_bytes());
+    hex::encode(hasher.finalize())
+}
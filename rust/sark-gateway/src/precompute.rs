@@ -0,0 +1,207 @@
+//! Decision precomputation after the cache is cleared
+//!
+//! Activating a policy (a reload, directory change, new bundle, rollback
+//! or data update) drops every decision cached under the old one, so for a
+//! while after it nearly every request waits for an evaluation. With
+//! `[cache.precompute]`, the gateway counts lookups of each decision it
+//! caches and, whenever a decision namespace is cleared, evaluates the
+//! inputs looked up most often again in the background and caches the new
+//! decisions before callers ask for them:
+//!
+//! ```toml
+//! [cache.precompute]
+//! top_n = 500
+//! tracked = 5000
+//! ```
+//!
+//! Lookups are counted for up to `tracked` distinct inputs per gateway;
+//! when that many are, every count is halved and the inputs left at 0 make
+//! room, so inputs that stop being asked for age out. After a clear, the
+//! `top_n` inputs of the namespace looked up most often are evaluated one
+//! at a time, taking turns with requests for the policy, through the same
+//! path as a cache miss: a decision live traffic already cached is
+//! skipped, and a miss for an input being evaluated waits for its result.
+//! Nothing is logged to the decision or audit log for them, and
+//! `sark_gateway_cache_precomputed_total` counts them by result.
+//!
+//! Counts survive clears, as keys don't depend on the policy revision. A
+//! cache flush through the admin API or a Redis invalidation is followed
+//! the same way, and tenants' namespaces are precomputed with their own
+//! policies.
+
+use crate::config::PrecomputeConfig;
+use crate::{AppState, Endpoint};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Instant;
+use tokio::sync::mpsc;
+use tracing::{debug, info};
+
+/// Lookup counts of the inputs decisions were cached for
+pub struct Precompute {
+    top_n: usize,
+    tracked: usize,
+    /// By namespace and cache key
+    entries: Mutex<HashMap<(String, String), Tracked>>,
+}
+
+struct Tracked {
+    endpoint: Endpoint,
+    input: Value,
+    lookups: u64,
+}
+
+/// An input to evaluate again
+struct Hot {
+    endpoint: Endpoint,
+    cache_key: String,
+    input: Value,
+}
+
+impl Precompute {
+    /// Counting as `config` says, unless it disables precomputation
+    pub fn new(config: &PrecomputeConfig) -> Option<Self> {
+        (config.top_n > 0).then(|| Self {
+            top_n: config.top_n,
+            tracked: config.tracked(),
+            entries: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Count a lookup of `cache_key` in `namespace`, for `input`
+    pub fn record(&self, namespace: &str, endpoint: Endpoint, cache_key: &str, input: &Value) {
+        let mut entries = self.entries.lock().expect("precompute lock poisoned");
+        let key = (namespace.to_string(), cache_key.to_string());
+        if let Some(entry) = entries.get_mut(&key) {
+            entry.lookups += 1;
+            return;
+        }
+        while entries.len() >= self.tracked {
+            entries.retain(|_, entry| {
+                entry.lookups /= 2;
+                entry.lookups > 0
+            });
+        }
+        entries.insert(
+            key,
+            Tracked {
+                endpoint,
+                input: input.clone(),
+                lookups: 1,
+            },
+        );
+    }
+
+    /// The `top_n` inputs of `namespace` looked up most often, most first
+    fn hottest(&self, namespace: &str) -> Vec<Hot> {
+        let entries = self.entries.lock().expect("precompute lock poisoned");
+        let mut hot: Vec<(&(String, String), &Tracked)> = entries
+            .iter()
+            .filter(|((name, _), _)| name == namespace)
+            .collect();
+        hot.sort_unstable_by(|a, b| b.1.lookups.cmp(&a.1.lookups));
+        hot.into_iter()
+            .take(self.top_n)
+            .map(|((_, cache_key), entry)| Hot {
+                endpoint: entry.endpoint,
+                cache_key: cache_key.clone(),
+                input: entry.input.clone(),
+            })
+            .collect()
+    }
+}
+
+/// Precompute after every clear of a decision namespace, the tenants'
+/// included, for the life of the process
+pub fn start(state: &AppState) {
+    if state.precompute.is_none() {
+        return;
+    }
+    let (cleared, mut clears) = mpsc::unbounded_channel();
+    let namespaces = state
+        .decision_caches()
+        .into_iter()
+        .chain(state.tenants.decision_caches());
+    for namespace in namespaces {
+        let mut watched = namespace.cleared();
+        let cleared = cleared.clone();
+        let name = namespace.name().to_string();
+        tokio::spawn(async move {
+            while watched.changed().await.is_ok() {
+                if cleared.send(name.clone()).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    let state = state.clone();
+    tokio::spawn(async move {
+        while let Some(namespace) = clears.recv().await {
+            precompute(&state, &namespace).await;
+        }
+    });
+}
+
+/// Evaluate and cache the decisions of `namespace` looked up most often
+async fn precompute(state: &AppState, namespace: &str) {
+    let Some(precompute) = &state.precompute else {
+        return;
+    };
+    let hottest = precompute.hottest(namespace);
+    if hottest.is_empty() {
+        return;
+    }
+    let started = Instant::now();
+    let (mut cached, mut skipped, mut failed) = (0, 0, 0);
+    for hot in hottest {
+        let result = match state.tenants.of(&hot.input) {
+            Ok(tenant) => {
+                let cache = hot.endpoint.cache(state, tenant);
+                if cache.peek(&hot.cache_key).is_some() {
+                    Ok(false)
+                } else {
+                    let inflight_key = format!("{}:{}", cache.name(), hot.cache_key);
+                    state
+                        .inflight
+                        .run(&inflight_key, || {
+                            crate::evaluate_and_cache(
+                                state,
+                                hot.endpoint,
+                                hot.cache_key.clone(),
+                                hot.input,
+                            )
+                        })
+                        .await
+                        .map(|decision| decision.cache_ttl > 0)
+                }
+            }
+            Err(problem) => Err(problem),
+        };
+        let outcome = match result {
+            Ok(true) => {
+                cached += 1;
+                "cached"
+            }
+            Ok(false) => {
+                skipped += 1;
+                "skipped"
+            }
+            Err(problem) => {
+                debug!(namespace = namespace, error = %problem, "Precomputation failed");
+                failed += 1;
+                "error"
+            }
+        };
+        state.metrics.precomputed(namespace, outcome);
+    }
+    info!(
+        namespace = namespace,
+        cached = cached,
+        skipped = skipped,
+        failed = failed,
+        elapsed_ms = started.elapsed().as_millis() as u64,
+        "Precomputed decisions after cache clear"
+    );
+}
@@ -0,0 +1,261 @@
+//! Error responses
+//!
+//! Requests the gateway can't answer fail with an RFC 7807
+//! `application/problem+json` body, so clients can tell kinds of failure
+//! apart without matching on message text:
+//!
+//! ```json
+//! {
+//!   "type": "urn:sark:problem:policy-evaluation",
+//!   "title": "Policy evaluation failed",
+//!   "status": 500,
+//!   "detail": "Policy evaluation error: ...",
+//!   "request_id": "4bf92f3577b34da6a3ce929d0e0e4736",
+//!   "retryable": false
+//! }
+//! ```
+//!
+//! `type` names the kind of problem and is stable; `detail` is for people.
+//! `retryable` says whether the same request may succeed if sent again
+//! later. A denied authorization is a decision, not a problem, and MCP
+//! requests through the proxy still fail with JSON-RPC errors.
+
+use crate::stepup::Challenge;
+use axum::{
+    body::Body,
+    extract::{rejection::JsonRejection, FromRequest},
+    http::{
+        header::{CONTENT_TYPE, RETRY_AFTER, WWW_AUTHENTICATE},
+        HeaderValue, StatusCode,
+    },
+    response::{IntoResponse, Response},
+};
+use serde_json::{json, Value};
+
+const CONTENT_TYPE_PROBLEM: &str = "application/problem+json";
+
+/// Headers describing the quota a `quota-exceeded` problem ran out of
+const QUOTA_LIMIT_HEADER: &str = "x-quota-limit";
+const QUOTA_REMAINING_HEADER: &str = "x-quota-remaining";
+/// Seconds until the quota's window resets
+const QUOTA_RESET_HEADER: &str = "x-quota-reset";
+
+/// A failed request
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum Problem {
+    #[error("{0}")]
+    Unauthenticated(String),
+    #[error("{0}")]
+    Forbidden(String),
+    /// The request is malformed, or its policy input fails the schema
+    #[error("{0}")]
+    InvalidRequest(String),
+    #[error("{0}")]
+    UnsupportedMediaType(String),
+    #[error("{0}")]
+    PayloadTooLarge(String),
+    #[error("{0}")]
+    RequestTimeout(String),
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{0}")]
+    Conflict(String),
+    /// Policies (or data) that don't compile
+    #[error("{0}")]
+    InvalidPolicy(String),
+    #[error("{0}")]
+    InvalidConfig(String),
+    #[error("{0}")]
+    RateLimited(String),
+    /// A policy-set quota is used up, until `reset` seconds from now
+    #[error("{message}")]
+    QuotaExceeded {
+        message: String,
+        limit: u64,
+        reset: u64,
+    },
+    /// Shed at a concurrency limit
+    #[error("{0}")]
+    Overloaded(String),
+    /// The decision obliges a step-up the caller's token doesn't meet
+    #[error("{message}")]
+    StepUpRequired {
+        message: String,
+        challenge: Challenge,
+    },
+    /// The policy failed to evaluate, or none is loaded
+    #[error("{0}")]
+    PolicyEvaluation(String),
+    /// The policy took longer than `concurrency.evaluation_timeout_ms`
+    #[error("{0}")]
+    EvaluationTimeout(String),
+    /// The policy's result doesn't match `--result-schema`
+    #[error("{0}")]
+    MalformedResult(String),
+    /// A proxied MCP server couldn't be reached
+    #[error("{0}")]
+    Upstream(String),
+    /// A proxied MCP server's response couldn't be redacted as obliged
+    #[error("{0}")]
+    Unredactable(String),
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl Problem {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Problem::Unauthenticated(_) | Problem::StepUpRequired { .. } => {
+                StatusCode::UNAUTHORIZED
+            }
+            Problem::Forbidden(_) => StatusCode::FORBIDDEN,
+            Problem::InvalidRequest(_) => StatusCode::BAD_REQUEST,
+            Problem::UnsupportedMediaType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Problem::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            Problem::RequestTimeout(_) => StatusCode::REQUEST_TIMEOUT,
+            Problem::NotFound(_) => StatusCode::NOT_FOUND,
+            Problem::Conflict(_) => StatusCode::CONFLICT,
+            Problem::InvalidPolicy(_) | Problem::InvalidConfig(_) => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            Problem::RateLimited(_) | Problem::QuotaExceeded { .. } => {
+                StatusCode::TOO_MANY_REQUESTS
+            }
+            Problem::Overloaded(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Problem::EvaluationTimeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            Problem::PolicyEvaluation(_) | Problem::MalformedResult(_) | Problem::Internal(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+            Problem::Upstream(_) | Problem::Unredactable(_) => StatusCode::BAD_GATEWAY,
+        }
+    }
+
+    /// The last part of the problem's `type`
+    fn kind(&self) -> &'static str {
+        match self {
+            Problem::Unauthenticated(_) => "unauthenticated",
+            Problem::Forbidden(_) => "forbidden",
+            Problem::InvalidRequest(_) => "invalid-request",
+            Problem::UnsupportedMediaType(_) => "unsupported-media-type",
+            Problem::PayloadTooLarge(_) => "payload-too-large",
+            Problem::RequestTimeout(_) => "request-timeout",
+            Problem::NotFound(_) => "not-found",
+            Problem::Conflict(_) => "conflict",
+            Problem::InvalidPolicy(_) => "invalid-policy",
+            Problem::InvalidConfig(_) => "invalid-config",
+            Problem::RateLimited(_) => "rate-limited",
+            Problem::QuotaExceeded { .. } => "quota-exceeded",
+            Problem::Overloaded(_) => "overloaded",
+            Problem::StepUpRequired { .. } => "step-up-required",
+            Problem::PolicyEvaluation(_) => "policy-evaluation",
+            Problem::EvaluationTimeout(_) => "evaluation-timeout",
+            Problem::MalformedResult(_) => "malformed-result",
+            Problem::Upstream(_) => "upstream-unreachable",
+            Problem::Unredactable(_) => "unredactable",
+            Problem::Internal(_) => "internal",
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            Problem::Unauthenticated(_) => "Authentication required",
+            Problem::Forbidden(_) => "Forbidden",
+            Problem::InvalidRequest(_) => "Malformed request",
+            Problem::UnsupportedMediaType(_) => "Unsupported media type",
+            Problem::PayloadTooLarge(_) => "Request body too large",
+            Problem::RequestTimeout(_) => "Request timed out",
+            Problem::NotFound(_) => "Not found",
+            Problem::Conflict(_) => "Conflict",
+            Problem::InvalidPolicy(_) => "Invalid policy",
+            Problem::InvalidConfig(_) => "Invalid config",
+            Problem::RateLimited(_) => "Rate limit exceeded",
+            Problem::QuotaExceeded { .. } => "Quota exceeded",
+            Problem::Overloaded(_) => "Overloaded",
+            Problem::StepUpRequired { .. } => "Step-up authentication required",
+            Problem::PolicyEvaluation(_) => "Policy evaluation failed",
+            Problem::EvaluationTimeout(_) => "Policy evaluation timed out",
+            Problem::MalformedResult(_) => "Malformed policy result",
+            Problem::Upstream(_) => "MCP server unreachable",
+            Problem::Unredactable(_) => "Response cannot be redacted",
+            Problem::Internal(_) => "Internal error",
+        }
+    }
+
+    /// Whether the same request may succeed later
+    pub fn retryable(&self) -> bool {
+        matches!(
+            self,
+            Problem::RequestTimeout(_)
+                | Problem::RateLimited(_)
+                | Problem::QuotaExceeded { .. }
+                | Problem::Overloaded(_)
+                | Problem::Upstream(_)
+        )
+    }
+
+    /// The problem document, naming `request_id` if known
+    pub fn body(&self, request_id: Option<&str>) -> Body {
+        let mut problem = json!({
+            "type": format!("urn:sark:problem:{}", self.kind()),
+            "title": self.title(),
+            "status": self.status().as_u16(),
+            "detail": self.to_string(),
+            "retryable": self.retryable(),
+        });
+        if let Some(request_id) = request_id {
+            problem["request_id"] = request_id.into();
+        }
+        if let Problem::StepUpRequired { challenge, .. } = self {
+            if let Value::Object(members) = json!(challenge) {
+                problem
+                    .as_object_mut()
+                    .expect("problem is an object")
+                    .extend(members);
+            }
+        }
+        Body::from(problem.to_string())
+    }
+}
+
+/// Rendered without the request id, which `telemetry::trace_request` adds
+/// from the problem it finds in the response's extensions
+impl IntoResponse for Problem {
+    fn into_response(self) -> Response {
+        let mut response = (
+            self.status(),
+            [(CONTENT_TYPE, CONTENT_TYPE_PROBLEM)],
+            self.body(None),
+        )
+            .into_response();
+        if let Problem::QuotaExceeded { limit, reset, .. } = &self {
+            let headers = response.headers_mut();
+            headers.insert(QUOTA_LIMIT_HEADER, HeaderValue::from(*limit));
+            headers.insert(QUOTA_REMAINING_HEADER, HeaderValue::from(0));
+            headers.insert(QUOTA_RESET_HEADER, HeaderValue::from(*reset));
+            headers.insert(RETRY_AFTER, HeaderValue::from(*reset));
+        }
+        if let Problem::StepUpRequired { message, challenge } = &self {
+            if let Ok(value) = HeaderValue::from_str(&challenge.www_authenticate(message)) {
+                response.headers_mut().insert(WWW_AUTHENTICATE, value);
+            }
+        }
+        response.extensions_mut().insert(self);
+        response
+    }
+}
+
+impl From<JsonRejection> for Problem {
+    fn from(rejection: JsonRejection) -> Self {
+        let detail = rejection.body_text();
+        match rejection.status() {
+            StatusCode::UNSUPPORTED_MEDIA_TYPE => Problem::UnsupportedMediaType(detail),
+            StatusCode::PAYLOAD_TOO_LARGE => Problem::PayloadTooLarge(detail),
+            _ => Problem::InvalidRequest(detail),
+        }
+    }
+}
+
+/// A JSON request body, rejected as a problem when it isn't one
+#[derive(FromRequest)]
+#[from_request(via(axum::Json), rejection(Problem))]
+pub struct JsonBody<T>(pub T);
@@ -0,0 +1,247 @@
+//! Policy profiling
+//!
+//! `POST /admin/policies/profile` runs inputs against the active policy and
+//! reports how long each rule takes, to find the one that made p99 jump
+//! after a bundle update. It takes inputs, each with the rule or package to
+//! evaluate (by default the package a gateway authorization of it would
+//! be), or a sample of the last decisions kept for replay (see `replay`):
+//!
+//! ```json
+//! {"inputs": [{"input": {"user": {...}, "action": "gateway:tool:invoke"}}], "runs": 5}
+//! {"sample": 50}
+//! ```
+//!
+//! Every input is evaluated `runs` times (1 by default) against its query
+//! and against each rule the policy set defines, `test_*` rules aside.
+//! The engine exposes no expression-level profile, so a rule's time is
+//! that of evaluating it alone, the rules it depends on included: the
+//! rule to look at is a slow one whose dependencies are fast. Queries and
+//! rules are listed slowest (by total time) first, per policy: the
+//! default one, and each tenant's an input's `user.tenant` names.
+//!
+//! Each input holds its policy's engine while its rules run, so decisions
+//! queue behind a profile; keep inputs and runs few on a busy replica.
+//! Profiles are not decision logged, cached or counted in evaluation
+//! metrics.
+
+use crate::problem::{JsonBody, Problem};
+use crate::{AppState, Endpoint};
+use axum::{extract::State, Json};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::time::Instant;
+use tracing::info;
+
+/// Route profiles are served on, behind the admin token
+pub const ROUTE: &str = "/admin/policies/profile";
+
+/// Most inputs, and runs of each, one profile takes
+const MAX_INPUTS: usize = 100;
+const MAX_RUNS: u32 = 100;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProfileRequest {
+    #[serde(default)]
+    inputs: Vec<ProfileInput>,
+    /// Also profile the last this many decisions kept for replay
+    sample: Option<usize>,
+    /// Evaluations of each query and rule per input
+    runs: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProfileInput {
+    input: Value,
+    /// Rule or package the input is evaluated against
+    query: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProfileResponse {
+    runs: u32,
+    policies: Vec<PolicyProfile>,
+}
+
+#[derive(Debug, Serialize)]
+struct PolicyProfile {
+    /// The tenant whose policy this is, if not the default one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tenant: Option<String>,
+    revision: String,
+    /// Inputs profiled against it
+    inputs: usize,
+    queries: Vec<Timing>,
+    rules: Vec<Timing>,
+}
+
+/// Time spent evaluating one query or rule
+#[derive(Debug, Serialize)]
+struct Timing {
+    query: String,
+    evaluations: u64,
+    /// Evaluations that failed, still timed
+    errors: u64,
+    total_us: u64,
+    mean_us: u64,
+    max_us: u64,
+}
+
+/// A policy's timings as they accumulate
+struct Profile {
+    revision: String,
+    inputs: usize,
+    queries: BTreeMap<String, Timing>,
+    rules: BTreeMap<String, Timing>,
+}
+
+pub async fn profile(
+    State(state): State<AppState>,
+    JsonBody(request): JsonBody<ProfileRequest>,
+) -> Result<Json<ProfileResponse>, Problem> {
+    let runs = request.runs.unwrap_or(1);
+    if runs == 0 || runs > MAX_RUNS {
+        return Err(Problem::InvalidRequest(format!(
+            "runs must be between 1 and {}",
+            MAX_RUNS
+        )));
+    }
+
+    let mut inputs: Vec<(String, Value)> = request
+        .inputs
+        .into_iter()
+        .map(|entry| {
+            let query = entry.query.unwrap_or_else(|| {
+                Endpoint::Authorize
+                    .query(&state.queries, &entry.input)
+                    .to_string()
+            });
+            (query, entry.input)
+        })
+        .collect();
+    if let Some(count) = request.sample {
+        let Some(retained) = state.decision_log.as_ref().and_then(|log| log.inputs()) else {
+            return Err(Problem::NotFound(
+                "Decision inputs are not kept for replay; give inputs to profile instead"
+                    .to_string(),
+            ));
+        };
+        inputs.extend(retained.recent(count));
+    }
+    if inputs.is_empty() {
+        return Err(Problem::InvalidRequest(
+            "Give inputs or a sample to profile".to_string(),
+        ));
+    }
+    if inputs.len() > MAX_INPUTS {
+        return Err(Problem::InvalidRequest(format!(
+            "A profile takes at most {} inputs",
+            MAX_INPUTS
+        )));
+    }
+    if let Some((query, _)) = inputs.iter().find(|(query, _)| !query.starts_with("data.")) {
+        return Err(Problem::InvalidRequest(format!(
+            "Query {:?} is not a data.* rule",
+            query
+        )));
+    }
+
+    let mut profiles: BTreeMap<Option<String>, Profile> = BTreeMap::new();
+    for (query, input) in &inputs {
+        let tenant = state.tenants.of(input)?;
+        let policy = tenant.map_or(&state.policy, |tenant| &tenant.policy);
+        let name = tenant.and(input["user"]["tenant"].as_str().map(str::to_string));
+        let input = input.to_string();
+
+        let policy = policy.lock().await;
+        let active = policy.active();
+        let revision = active.revision().to_string();
+        let profile = profiles.entry(name).or_insert_with(|| Profile {
+            revision: revision.clone(),
+            inputs: 0,
+            queries: BTreeMap::new(),
+            rules: BTreeMap::new(),
+        });
+        if profile.revision != revision {
+            return Err(Problem::Conflict(
+                "The policy changed while profiling; profile again".to_string(),
+            ));
+        }
+        profile.inputs += 1;
+
+        let rules = active.set.rules();
+        active
+            .with_engine(|engine| -> Result<(), Problem> {
+                time(engine, query, &input, runs, &mut profile.queries)?;
+                for rule in rules.iter().filter(|rule| !is_test(rule)) {
+                    time(engine, rule, &input, runs, &mut profile.rules)?;
+                }
+                Ok(())
+            })
+            .map_err(|e| Problem::Internal(format!("{:#}", e)))??;
+    }
+
+    info!(inputs = inputs.len(), runs = runs, "Profiled policies");
+    let policies = profiles
+        .into_iter()
+        .map(|(tenant, profile)| PolicyProfile {
+            tenant,
+            revision: profile.revision,
+            inputs: profile.inputs,
+            queries: slowest_first(profile.queries),
+            rules: slowest_first(profile.rules),
+        })
+        .collect();
+    Ok(Json(ProfileResponse { runs, policies }))
+}
+
+/// Evaluate `query` for `input` `runs` times, adding to its timing in
+/// `timings`
+fn time(
+    engine: &mut grid_opa::OPAEngine,
+    query: &str,
+    input: &str,
+    runs: u32,
+    timings: &mut BTreeMap<String, Timing>,
+) -> Result<(), Problem> {
+    let timing = timings.entry(query.to_string()).or_insert_with(|| Timing {
+        query: query.to_string(),
+        evaluations: 0,
+        errors: 0,
+        total_us: 0,
+        mean_us: 0,
+        max_us: 0,
+    });
+    for _ in 0..runs {
+        let opa_input = grid_opa::Value::from_json_str(input)
+            .map_err(|e| Problem::InvalidRequest(format!("Invalid policy input: {}", e)))?;
+        let started = Instant::now();
+        let result = engine.evaluate(query, opa_input);
+        let elapsed = started.elapsed().as_micros() as u64;
+        timing.evaluations += 1;
+        timing.errors += u64::from(result.is_err());
+        timing.total_us += elapsed;
+        timing.max_us = timing.max_us.max(elapsed);
+    }
+    Ok(())
+}
+
+fn is_test(rule: &str) -> bool {
+    rule.rsplit('.')
+        .next()
+        .is_some_and(|name| name.starts_with("test_"))
+}
+
+fn slowest_first(timings: BTreeMap<String, Timing>) -> Vec<Timing> {
+    let mut timings: Vec<Timing> = timings
+        .into_values()
+        .map(|timing| Timing {
+            mean_us: timing.total_us / timing.evaluations.max(1),
+            ..timing
+        })
+        .collect();
+    timings.sort_by(|a, b| b.total_us.cmp(&a.total_us));
+    timings
+}
@@ -0,0 +1,96 @@
+//! Decision metadata in response headers
+//!
+//! Services behind the gateway and tools debugging it need to know which
+//! decision a response carries, and where it came from, without parsing
+//! the body. Each header `[response_headers]` turns on is set on decision
+//! responses:
+//!
+//! ```toml
+//! [response_headers]
+//! decision = true         # X-SARK-Decision: allow|deny
+//! policy_revision = true  # X-SARK-Policy-Revision
+//! cache = true            # X-SARK-Cache: hit|miss
+//! request_id = true       # X-SARK-Request-Id
+//! ```
+//!
+//! `/gateway/authorize` and `/gateway/authorize-a2a` describe their one
+//! decision. For an MCP message (`/gateway/mcp/<server>`), the decision is
+//! `allow` only if every request in it is, and the cache `hit` only if
+//! every decision was cached; the revision is the first decision's. Batch
+//! responses, whose decisions each stand alone, carry only the request id.
+//!
+//! Requests the MCP proxy forwards carry the same headers, for the server
+//! to log. Problems carry none of them; `X-Request-Id` is on every
+//! response regardless. gRPC and Envoy answers are not affected.
+
+use crate::config::ResponseHeadersConfig;
+use crate::GatewayAuthResponse;
+use axum::http::{HeaderMap, HeaderValue};
+use tracing::warn;
+
+pub const DECISION_HEADER: &str = "x-sark-decision";
+pub const POLICY_REVISION_HEADER: &str = "x-sark-policy-revision";
+pub const CACHE_HEADER: &str = "x-sark-cache";
+pub const REQUEST_ID_HEADER: &str = "x-sark-request-id";
+
+/// Sets the configured decision headers
+pub struct DecisionHeaders {
+    decision: bool,
+    policy_revision: bool,
+    cache: bool,
+    request_id: bool,
+}
+
+impl DecisionHeaders {
+    pub fn new(config: &ResponseHeadersConfig) -> Self {
+        Self {
+            decision: config.decision,
+            policy_revision: config.policy_revision,
+            cache: config.cache,
+            request_id: config.request_id,
+        }
+    }
+
+    /// The headers describing `decisions`, made for request `request_id`
+    pub fn headers(&self, decisions: &[GatewayAuthResponse], request_id: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        self.insert(&mut headers, decisions, request_id);
+        headers
+    }
+
+    /// Set the headers describing `decisions` in `headers`; with no
+    /// decisions, only the request id
+    pub fn insert(
+        &self,
+        headers: &mut HeaderMap,
+        decisions: &[GatewayAuthResponse],
+        request_id: &str,
+    ) {
+        let mut insert = |name: &'static str, value: &str| match HeaderValue::from_str(value) {
+            Ok(value) => {
+                headers.insert(name, value);
+            }
+            Err(_) => warn!(
+                header = name,
+                "Not setting a header that isn't a valid value"
+            ),
+        };
+        if self.request_id {
+            insert(REQUEST_ID_HEADER, request_id);
+        }
+        let Some(first) = decisions.first() else {
+            return;
+        };
+        if self.decision {
+            let allow = decisions.iter().all(|d| d.allow);
+            insert(DECISION_HEADER, if allow { "allow" } else { "deny" });
+        }
+        if self.policy_revision {
+            insert(POLICY_REVISION_HEADER, &first.policy_revision);
+        }
+        if self.cache {
+            let hit = decisions.iter().all(|d| d.cached);
+            insert(CACHE_HEADER, if hit { "hit" } else { "miss" });
+        }
+    }
+}
@@ -0,0 +1,405 @@
+//! Authorizing reverse proxy for MCP servers
+//!
+//! With upstreams configured, the gateway also fronts the MCP servers
+//! themselves: a request to `/mcp/<server>` (or `/mcp/<server>/<path>`) is
+//! authorized and, if allowed, forwarded to that server, so deployments
+//! don't need a proxy tier of their own:
+//!
+//! ```toml
+//! [proxy.servers.github]
+//! url = "http://github-mcp:8080/mcp"
+//! sensitivity = "high"
+//! ```
+//!
+//! Each JSON-RPC request in the body is authorized as a
+//! `/gateway/authorize` request for the server (see `mcp`). Requests
+//! carrying none (the SSE `GET`, session `DELETE`, replies to the server)
+//! are authorized once as `gateway:server:info`. A denial answers with a
+//! JSON-RPC error and HTTP 403; nothing is forwarded unless every request
+//! in the body is allowed, and `filtered_parameters` replace a call's
+//! arguments before it is.
+//!
+//! Forwarded requests carry `X-Sark-User-Id`, `X-Sark-Policy-Revision`,
+//! `X-Sark-Decision-Reason` (for a single decision), `X-Request-Id` and the
+//! decision headers `[response_headers]` turns on (see `provenance`); the
+//! caller's own `X-Sark-*` headers and bearer token (or API key) are
+//! dropped unless the server sets `forward_token`. Connections to upstreams
+//! are pooled, and responses (including SSE streams) are streamed back as
+//! they arrive. Request bodies are read whole to be authorized, within the
+//! route's `[requests]` limits; the timeout there covers the upstream's
+//! response headers, not the streamed body. `redact` obligations on the
+//! decisions are applied to the results on their way back (see `redact`);
+//! calls obliged to step up or be watermarked, which the proxy can't do,
+//! are refused like denials.
+
+use crate::auth::UserContext;
+use crate::clientip::ClientIp;
+use crate::config::{ProxyConfig, UpstreamConfig};
+use crate::mcp;
+use crate::problem::Problem;
+use crate::provenance::DecisionHeaders;
+use crate::redact::{Redaction, Redactor};
+use crate::tls::ClientIdentity;
+use crate::{AppState, GatewayAuthResponse};
+use anyhow::{Context, Result};
+use axum::{
+    body::{Body, Bytes},
+    extract::{Path, State},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use futures::StreamExt;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+pub const ROUTE: &str = "/mcp/:server";
+pub const SUBPATH_ROUTE: &str = "/mcp/:server/*path";
+
+/// JSON-RPC error codes
+const PARSE_ERROR: i32 = -32700;
+/// Server-defined range; what MCP gateways commonly use for denials
+const FORBIDDEN: i32 = -32001;
+
+/// Headers that describe a single hop and aren't forwarded
+const HOP_BY_HOP: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+pub struct Proxy {
+    client: reqwest::Client,
+    servers: HashMap<String, UpstreamConfig>,
+    /// Largest JSON response read whole to be redacted
+    max_response_bytes: usize,
+}
+
+impl Proxy {
+    /// The proxy `config` describes, if it names any servers
+    pub fn new(config: &ProxyConfig) -> Result<Option<Self>> {
+        if config.servers.is_empty() {
+            return Ok(None);
+        }
+        let client = reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(config.connect_timeout))
+            .pool_max_idle_per_host(config.pool_max_idle)
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .context("Failed to build proxy HTTP client")?;
+        Ok(Some(Self {
+            client,
+            servers: config.servers.clone(),
+            max_response_bytes: config.max_response_bytes,
+        }))
+    }
+
+    /// Sensitivity level of `server`'s tools, if it is proxied and has one
+    pub fn sensitivity(&self, server: &str) -> Option<&str> {
+        self.servers.get(server)?.sensitivity.as_deref()
+    }
+}
+
+/// Proxy endpoint
+#[allow(clippy::too_many_arguments)]
+pub async fn forward(
+    State(state): State<AppState>,
+    Path(params): Path<HashMap<String, String>>,
+    client: Option<Extension<ClientIdentity>>,
+    client_ip: Option<Extension<ClientIp>>,
+    verified: Option<Extension<UserContext>>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, Problem> {
+    let server = params.get("server").cloned().unwrap_or_default();
+    let Some((proxy, upstream)) = state
+        .proxy
+        .as_ref()
+        .and_then(|proxy| Some((proxy, proxy.servers.get(&server)?)))
+    else {
+        return Err(Problem::NotFound(format!(
+            "Unknown MCP server {:?}",
+            server
+        )));
+    };
+    let user = crate::authenticate(&state, verified, &headers).await?;
+    let client = client.map(|Extension(c)| c);
+    let client_ip = client_ip.map(|Extension(ClientIp(ip))| ip);
+
+    let mut message = if method == Method::POST && !body.is_empty() {
+        match serde_json::from_slice::<Value>(&body) {
+            Ok(message) => Some(message),
+            Err(e) => {
+                warn!(server = %server, error = %e, "Rejected malformed MCP request");
+                return Ok(rpc_error(
+                    StatusCode::BAD_REQUEST,
+                    Value::Null,
+                    PARSE_ERROR,
+                    "Parse error",
+                ));
+            }
+        }
+    } else {
+        None
+    };
+    let calls = message.as_ref().map(mcp::calls).unwrap_or_default();
+
+    let request_id = crate::request_id(&headers);
+    let context = crate::request_context(&headers, client_ip, &user);
+    let requests = mcp::requests(
+        &server,
+        upstream.sensitivity.as_deref(),
+        &method,
+        &calls,
+        message.as_ref(),
+    );
+    let decisions = mcp::decide(
+        &state,
+        &user,
+        client.as_ref(),
+        &context,
+        request_id.clone(),
+        requests,
+    )
+    .await?;
+
+    if let Some((i, denied)) = decisions.iter().enumerate().find(|(_, d)| !d.allow) {
+        info!(server = %server, reason = %denied.reason, "MCP request denied");
+        return Ok(match calls.get(i) {
+            Some(call) => rpc_error(
+                StatusCode::FORBIDDEN,
+                call.id.clone(),
+                FORBIDDEN,
+                &denied.reason,
+            ),
+            None => Problem::Forbidden(denied.reason.clone()).into_response(),
+        });
+    }
+
+    let unfulfillable = decisions.iter().enumerate().find_map(|(i, decision)| {
+        let names = decision.obligations.as_ref()?.unfulfillable();
+        (!names.is_empty()).then_some((i, names))
+    });
+    if let Some((i, names)) = unfulfillable {
+        let reason = format!(
+            "Decision requires {}, which the proxy cannot fulfil",
+            names.join(" and ")
+        );
+        info!(server = %server, reason = %reason, "MCP request refused");
+        return Ok(match calls.get(i) {
+            Some(call) => rpc_error(StatusCode::FORBIDDEN, call.id.clone(), FORBIDDEN, &reason),
+            None => Problem::Forbidden(reason).into_response(),
+        });
+    }
+
+    let mut redactor = Redactor::default();
+    for (i, decision) in decisions.iter().enumerate() {
+        let redactions = Redaction::from_obligations(decision.obligations.as_ref())
+            .map_err(Problem::PolicyEvaluation)?;
+        redactor.add(calls.get(i).map(|call| &call.id), redactions);
+    }
+
+    let mut body = body;
+    if let Some(message) = message.as_mut() {
+        let mut filtered = false;
+        for (call, decision) in calls.iter().zip(&decisions) {
+            let (Some(arguments), "tools/call") =
+                (&decision.filtered_parameters, call.method.as_str())
+            else {
+                continue;
+            };
+            let params = mcp::entry_mut(message, call.index)
+                .get_mut("params")
+                .and_then(Value::as_object_mut);
+            if let Some(params) = params {
+                params.insert("arguments".to_string(), arguments.clone());
+                filtered = true;
+            }
+        }
+        if filtered {
+            body = serde_json::to_vec(message)
+                .expect("JSON values serialize")
+                .into();
+        }
+    }
+
+    let mut url = upstream.url.trim_end_matches('/').to_string();
+    if let Some(path) = params.get("path") {
+        url = format!("{}/{}", url, path);
+    }
+    if let Some(query) = uri.query() {
+        url = format!("{}?{}", url, query);
+    }
+    let upstream_headers = upstream_headers(
+        &headers,
+        upstream,
+        &state.decision_headers,
+        &user,
+        &request_id,
+        &decisions,
+    );
+
+    let response = match proxy
+        .client
+        .request(method.clone(), &url)
+        .headers(upstream_headers)
+        .body(body)
+        .send()
+        .await
+    {
+        Ok(response) => response,
+        Err(e) => {
+            warn!(server = %server, error = %e, "MCP server unreachable");
+            return Err(Problem::Upstream(format!(
+                "MCP server {:?} unreachable",
+                server
+            )));
+        }
+    };
+    info!(
+        server = %server,
+        method = %method,
+        status = response.status().as_u16(),
+        "Proxied MCP request"
+    );
+
+    let redacting = !redactor.is_empty();
+    let mut builder = Response::builder().status(response.status());
+    for (name, value) in response.headers() {
+        // Redaction changes the length
+        let stale = redacting && *name == header::CONTENT_LENGTH;
+        if !stale && !HOP_BY_HOP.contains(&name.as_str()) {
+            builder = builder.header(name, value);
+        }
+    }
+    let body = if redacting {
+        redacted(proxy, &server, Arc::new(redactor), response).await?
+    } else {
+        Body::from_stream(response.bytes_stream())
+    };
+    Ok(builder
+        .body(body)
+        .expect("upstream status and headers are valid"))
+}
+
+/// The upstream's response body with `redactor`'s obligations applied
+async fn redacted(
+    proxy: &Proxy,
+    server: &str,
+    redactor: Arc<Redactor>,
+    response: reqwest::Response,
+) -> Result<Body, Problem> {
+    let refuse = |message: String| {
+        warn!(server = %server, error = %message, "Refused MCP response");
+        Problem::Unredactable(message)
+    };
+    let content_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+
+    if content_type.starts_with("text/event-stream") {
+        let events = redactor.events(Box::pin(response.bytes_stream()));
+        return Ok(Body::from_stream(events));
+    }
+    if !content_type.starts_with("application/json") {
+        return Err(refuse(format!(
+            "Can't redact a {:?} response",
+            content_type
+        )));
+    }
+
+    let limit = proxy.max_response_bytes;
+    if response
+        .content_length()
+        .is_some_and(|length| length > limit as u64)
+    {
+        return Err(refuse(format!(
+            "Response to redact exceeds {} bytes",
+            limit
+        )));
+    }
+    let mut body = Vec::new();
+    let mut chunks = response.bytes_stream();
+    while let Some(chunk) = chunks.next().await {
+        let chunk = chunk.map_err(|e| refuse(format!("Failed to read response: {}", e)))?;
+        if body.len() + chunk.len() > limit {
+            return Err(refuse(format!(
+                "Response to redact exceeds {} bytes",
+                limit
+            )));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    let mut message: Value = serde_json::from_slice(&body)
+        .map_err(|e| refuse(format!("Invalid JSON response: {}", e)))?;
+    let fields = redactor.apply(&mut message);
+    if fields > 0 {
+        info!(server = %server, fields = fields, "Redacted MCP response");
+        body = serde_json::to_vec(&message).expect("JSON values serialize");
+    }
+    Ok(Body::from(body))
+}
+
+/// The caller's headers as the upstream gets them, with the metadata of
+/// the (allowing) `decisions` added
+fn upstream_headers(
+    headers: &HeaderMap,
+    upstream: &UpstreamConfig,
+    decision_headers: &DecisionHeaders,
+    user: &UserContext,
+    request_id: &str,
+    decisions: &[GatewayAuthResponse],
+) -> HeaderMap {
+    let mut forwarded: HeaderMap = headers
+        .iter()
+        .filter(|(name, _)| {
+            !HOP_BY_HOP.contains(&name.as_str())
+                && *name != header::HOST
+                && *name != header::CONTENT_LENGTH
+                && (*name != header::AUTHORIZATION || upstream.forward_token)
+                && (*name != crate::apikey::HEADER || upstream.forward_token)
+                && !name.as_str().starts_with("x-sark-")
+        })
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+
+    let mut insert = |name: &'static str, value: &str| match HeaderValue::from_str(value) {
+        Ok(value) => {
+            forwarded.insert(name, value);
+        }
+        Err(_) => warn!(
+            header = name,
+            "Not forwarding a header that isn't a valid value"
+        ),
+    };
+    insert("x-sark-user-id", &user.user_id);
+    insert("x-sark-policy-revision", &decisions[0].policy_revision);
+    if let [decision] = decisions {
+        insert("x-sark-decision-reason", &decision.reason);
+    }
+    insert(crate::REQUEST_ID_HEADER, request_id);
+    decision_headers.insert(&mut forwarded, decisions, request_id);
+    forwarded
+}
+
+fn rpc_error(status: StatusCode, id: Value, code: i32, message: &str) -> Response {
+    let body = json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    });
+    (status, Json(body)).into_response()
+}
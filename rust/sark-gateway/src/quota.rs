@@ -0,0 +1,136 @@
+//! Quotas
+//!
+//! Policies can cap how often a caller does something over a period by
+//! returning a `quota` obligation with an allow, one object or a list:
+//!
+//! ```rego
+//! obligations := {"quota": {
+//!     "key": sprintf("user:%s:tool:%s", [input.user.id, input.resource.tool]),
+//!     "limit": 100,
+//!     "window": "1d",
+//! }}
+//! ```
+//!
+//! Each allowed request counts once against every quota its decision
+//! carries, cached decisions included. Once a count passes its `limit`
+//! the request is refused with 429 and `X-Quota-Limit`,
+//! `X-Quota-Remaining` and `X-Quota-Reset` (seconds until the window
+//! resets) headers, until the window ends. Windows are fixed, aligned to
+//! the epoch, and given in seconds or as `30s`, `15m`, `12h` or `7d`.
+//! Keys are scoped to the caller's tenant, if it has one.
+//!
+//! Counts live in the decision cache, shared through Redis when an L2 tier
+//! is configured and per replica otherwise. They are pinned there, so a
+//! full cache evicts decisions rather than counts, up to
+//! `cache.max_pinned` of them; counts past that are stored unpinned and
+//! can be evicted early, like cached decisions. Refused requests count
+//! too, and dry runs don't.
+
+use crate::cache::Namespace;
+use crate::obligations::Obligations;
+use crate::problem::Problem;
+use serde::Deserialize;
+use serde_json::Value;
+use tracing::warn;
+
+/// One quota a decision obliges the gateway to enforce
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Quota {
+    key: String,
+    limit: u64,
+    #[serde(deserialize_with = "window")]
+    window: u64,
+}
+
+impl Quota {
+    /// The quotas a decision's `obligations` carry
+    pub fn from_obligations(obligations: Option<&Obligations>) -> Result<Vec<Self>, String> {
+        let quotas = match obligations.and_then(|o| o.get("quota")) {
+            None => return Ok(Vec::new()),
+            Some(Value::Array(quotas)) => quotas.clone(),
+            Some(quota) => vec![quota.clone()],
+        };
+        let quotas: Vec<Self> = quotas
+            .into_iter()
+            .map(serde_json::from_value)
+            .collect::<Result<_, _>>()
+            .map_err(|e| format!("Invalid quota obligation: {}", e))?;
+        if let Some(bad) = quotas.iter().find(|q| q.key.is_empty() || q.window == 0) {
+            return Err(format!("Invalid quota obligation for {:?}", bad.key));
+        }
+        Ok(quotas)
+    }
+}
+
+/// Seconds in a window given as a number or `<n>s|m|h|d`
+fn window<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Window {
+        Seconds(u64),
+        Text(String),
+    }
+
+    let text = match Window::deserialize(deserializer)? {
+        Window::Seconds(seconds) => return Ok(seconds),
+        Window::Text(text) => text,
+    };
+    let invalid = || serde::de::Error::custom(format!("invalid window {:?}", text));
+    let split = text.len().checked_sub(1).ok_or_else(invalid)?;
+    let (count, unit) = text.split_at(split);
+    let unit = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86_400,
+        _ => return Err(invalid()),
+    };
+    count
+        .parse::<u64>()
+        .ok()
+        .and_then(|count| count.checked_mul(unit))
+        .ok_or_else(invalid)
+}
+
+/// Counts of quota use, in their own cache namespace
+pub struct Quotas {
+    counters: Namespace,
+}
+
+impl Quotas {
+    pub fn new(counters: Namespace) -> Self {
+        Self { counters }
+    }
+
+    /// Count a request against `quotas`, refusing it if any is used up
+    pub async fn consume(&self, tenant: Option<&str>, quotas: &[Quota]) -> Result<(), Problem> {
+        let now = chrono::Utc::now().timestamp().max(0) as u64;
+        let mut exceeded = None;
+        for quota in quotas {
+            let started = now - now % quota.window;
+            let reset = started + quota.window - now;
+            let key = match tenant {
+                Some(tenant) => format!("{}/{}:{}", tenant, quota.key, started),
+                None => format!("{}:{}", quota.key, started),
+            };
+            let count = self.counters.increment(&key, reset).await;
+            if count > quota.limit && exceeded.is_none() {
+                exceeded = Some((quota, reset));
+            }
+        }
+
+        let Some((quota, reset)) = exceeded else {
+            return Ok(());
+        };
+        warn!(key = %quota.key, limit = quota.limit, "Quota exceeded");
+        Err(Problem::QuotaExceeded {
+            message: format!(
+                "Quota {} of {} per {}s used up; resets in {}s",
+                quota.key, quota.limit, quota.window, reset
+            ),
+            limit: quota.limit,
+            reset,
+        })
+    }
+}
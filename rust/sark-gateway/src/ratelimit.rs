@@ -0,0 +1,464 @@
+//! Per-client rate limiting
+//!
+//! Each client gets a token bucket per limited route: `burst` requests at
+//! once, refilled at `rate` per second. Clients are told apart by the user
+//! id of a verified bearer token, falling back to the client address (see
+//! `clientip`) for requests without one, so one misbehaving agent runs out of budget
+//! before it can saturate policy evaluation for everyone else. Requests
+//! over the limit get 429 with `Retry-After`.
+//!
+//! ```toml
+//! [rate_limit.routes."/gateway/authorize"]
+//! rate = 50
+//! burst = 100
+//! ```
+//!
+//! API keys with a `rate_limit` of their own get it on every decision
+//! route instead (see `apikey`). Limits are replaced on config reload.
+//!
+//! Buckets are per replica, so behind a load balancer a client gets each
+//! replica's budget. With `rate_limit.shared` (and `--redis-url`), buckets
+//! live in Redis instead and the limits hold across the cluster. A replica
+//! takes up to `lease` tokens from a shared bucket at a time and spends
+//! them locally, going back to Redis once they are used up (or after a
+//! second), so a busy client costs a Redis round trip every few requests
+//! rather than on each. Leased tokens a replica doesn't spend in time are
+//! lost, which errs on the side of the limit. If Redis fails, the replica's
+//! own buckets stand in until it answers again.
+
+use crate::cache::RedisTier;
+use crate::clientip::ClientIp;
+use crate::config::{RateLimitConfig, RouteLimit};
+use crate::problem::Problem;
+use crate::AppState;
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::header::RETRY_AFTER,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+/// How long tokens leased from a shared bucket can be spent
+const LEASE_TTL: Duration = Duration::from_secs(1);
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    /// The limit last applied, the route's or the client's own
+    limit: RouteLimit,
+}
+
+impl Bucket {
+    /// Top up for the time passed since the last update
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.limit.rate).min(self.limit.burst as f64);
+        self.updated = now;
+    }
+}
+
+/// Tokens taken from a shared bucket, for this replica to spend
+struct Lease {
+    tokens: u32,
+    expires: Instant,
+}
+
+impl Lease {
+    /// Spend one leased token, if any are left and still good
+    fn spend(&mut self, now: Instant) -> bool {
+        if self.tokens > 0 && now < self.expires {
+            self.tokens -= 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Add `granted` tokens but the one spent at once, and keep them for
+    /// another `LEASE_TTL`; tokens past their time are dropped first
+    fn extend(&mut self, granted: u32, now: Instant) {
+        // What is left of a lease that ran out
+        if now >= self.expires {
+            self.tokens = 0;
+        }
+        self.tokens += granted - 1;
+        self.expires = now + LEASE_TTL;
+    }
+}
+
+/// Buckets kept in Redis for every replica
+struct Shared {
+    tier: RedisTier,
+    /// Most tokens taken at a time
+    lease: u32,
+    /// Keyed by route, then client
+    leases: Mutex<HashMap<(String, String), Lease>>,
+}
+
+pub struct RateLimiter {
+    limits: RwLock<HashMap<String, RouteLimit>>,
+    /// Keyed by route, then client
+    buckets: Mutex<HashMap<(String, String), Bucket>>,
+    /// Where buckets are shared with other replicas, if they are
+    shared: Option<Shared>,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig, l2: Option<RedisTier>) -> Self {
+        let shared = l2.filter(|_| config.shared).map(|tier| Shared {
+            tier,
+            lease: config.lease,
+            leases: Mutex::new(HashMap::new()),
+        });
+        Self {
+            limits: RwLock::new(config.routes.clone()),
+            buckets: Mutex::new(HashMap::new()),
+            shared,
+        }
+    }
+
+    /// Replace the limits; every client starts again with a full bucket
+    /// on this replica (shared buckets keep their tokens)
+    pub fn set_limits(&self, limits: HashMap<String, RouteLimit>) {
+        *self.limits.write().expect("limits lock poisoned") = limits;
+        self.buckets.lock().expect("buckets lock poisoned").clear();
+        if let Some(shared) = &self.shared {
+            shared.leases.lock().expect("leases lock poisoned").clear();
+        }
+    }
+
+    fn limit(&self, route: &str) -> Option<RouteLimit> {
+        self.limits
+            .read()
+            .expect("limits lock poisoned")
+            .get(route)
+            .copied()
+    }
+
+    /// Take one request from `client`'s budget on `route`, shared or this
+    /// replica's, or say how long until the next one is available
+    async fn acquire(
+        &self,
+        route: &str,
+        client: String,
+        limit: &RouteLimit,
+    ) -> Result<(), Duration> {
+        let Some(shared) = &self.shared else {
+            return self.acquire_local(route, client, limit);
+        };
+        let key = (route.to_string(), client);
+        {
+            let mut leases = shared.leases.lock().expect("leases lock poisoned");
+            if let Some(lease) = leases.get_mut(&key) {
+                if lease.spend(Instant::now()) {
+                    return Ok(());
+                }
+            }
+        }
+
+        let bucket = format!("ratelimit:{}:{}", key.0, key.1);
+        let wanted = shared.lease.min(limit.burst).max(1);
+        match shared
+            .tier
+            .take_tokens(&bucket, limit.rate, limit.burst, wanted)
+            .await
+        {
+            Ok((0, wait)) => Err(wait),
+            Ok((granted, _)) => {
+                let now = Instant::now();
+                let mut leases = shared.leases.lock().expect("leases lock poisoned");
+                leases
+                    .entry(key)
+                    .or_insert(Lease {
+                        tokens: 0,
+                        expires: now,
+                    })
+                    .extend(granted, now);
+                Ok(())
+            }
+            Err(e) => {
+                warn!(error = %format!("{:#}", e), "Shared rate limit unavailable; limiting locally");
+                self.acquire_local(&key.0, key.1, limit)
+            }
+        }
+    }
+
+    /// Take one request from this replica's own bucket
+    fn acquire_local(
+        &self,
+        route: &str,
+        client: String,
+        limit: &RouteLimit,
+    ) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().expect("buckets lock poisoned");
+        let bucket = buckets
+            .entry((route.to_string(), client))
+            .or_insert(Bucket {
+                tokens: limit.burst as f64,
+                updated: now,
+                limit: *limit,
+            });
+        bucket.limit = *limit;
+        bucket.refill(now);
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / limit.rate))
+        }
+    }
+
+    /// Forget clients whose buckets have refilled, as they'd start full
+    /// anyway, and leases that have expired
+    fn sweep(&self) {
+        let now = Instant::now();
+        if let Some(shared) = &self.shared {
+            shared
+                .leases
+                .lock()
+                .expect("leases lock poisoned")
+                .retain(|_, lease| now < lease.expires);
+        }
+        self.buckets
+            .lock()
+            .expect("buckets lock poisoned")
+            .retain(|_, bucket| {
+                bucket.refill(now);
+                bucket.tokens < bucket.limit.burst as f64
+            });
+    }
+}
+
+/// Periodically drop idle clients so the table doesn't grow without bound
+pub async fn sweeper(limiter: Arc<RateLimiter>, every: Duration) {
+    let mut interval = tokio::time::interval(every);
+    loop {
+        interval.tick().await;
+        limiter.sweep();
+    }
+}
+
+/// Middleware enforcing the matched route's limit
+///
+/// A verified caller is passed on to the handler as an extension, so the
+/// token isn't verified twice.
+pub async fn limit(State(state): State<AppState>, mut request: Request, next: Next) -> Response {
+    let Some(route) = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_string())
+    else {
+        return next.run(request).await;
+    };
+    let keys = state.api_keys();
+    let key_user = keys.verify(request.headers()).ok().flatten();
+    let key_limit = key_user
+        .as_ref()
+        .and_then(|user| user.api_key.as_deref())
+        .and_then(|name| keys.rate_limit(name))
+        .filter(|_| crate::DECISION_ROUTES.contains(&route.as_str()));
+    let Some(limit) = key_limit.or_else(|| state.rate_limit.limit(&route)) else {
+        return next.run(request).await;
+    };
+
+    let user = match (key_user, state.jwt()) {
+        (Some(user), _) => Some(user),
+        (None, Some(jwt)) => jwt.verify(request.headers()).await.ok(),
+        (None, None) => None,
+    };
+    let client = match user {
+        Some(user) => {
+            let client = format!("user:{}", user.user_id);
+            request.extensions_mut().insert(user);
+            client
+        }
+        None => match request.extensions().get::<ClientIp>() {
+            Some(ClientIp(ip)) => format!("ip:{}", ip),
+            None => "unknown".to_string(),
+        },
+    };
+
+    if let Err(wait) = state
+        .rate_limit
+        .acquire(&route, client.clone(), &limit)
+        .await
+    {
+        debug!(route = %route, client = %client, "Rate limited");
+        state.metrics.rate_limited(&route);
+        let retry_after = wait.as_secs_f64().ceil().max(1.0) as u64;
+        return (
+            [(RETRY_AFTER, retry_after.to_string())],
+            Problem::RateLimited("Rate limit exceeded".to_string()),
+        )
+            .into_response();
+    }
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROUTE: &str = "/gateway/authorize";
+
+    fn limit(rate: f64, burst: u32) -> RouteLimit {
+        RouteLimit { rate, burst }
+    }
+
+    fn limiter() -> RateLimiter {
+        let config = RateLimitConfig {
+            routes: HashMap::from([(ROUTE.to_string(), limit(1.0, 3))]),
+            ..RateLimitConfig::default()
+        };
+        RateLimiter::new(&config, None)
+    }
+
+    async fn take(limiter: &RateLimiter, client: &str, limit: &RouteLimit) -> Result<(), Duration> {
+        limiter.acquire(ROUTE, client.to_string(), limit).await
+    }
+
+    #[test]
+    fn limits_are_looked_up_by_route() {
+        let limiter = limiter();
+        assert_eq!(limiter.limit(ROUTE), Some(limit(1.0, 3)));
+        assert_eq!(limiter.limit("/gateway/authorize/batch"), None);
+    }
+
+    #[tokio::test]
+    async fn burst_is_allowed_then_limited() {
+        let limiter = limiter();
+        let limit = limit(4.0, 3);
+        for _ in 0..3 {
+            take(&limiter, "user:alice", &limit).await.unwrap();
+        }
+        // A quarter of a second until the next token, less what has passed
+        let wait = take(&limiter, "user:alice", &limit).await.unwrap_err();
+        assert!(wait > Duration::ZERO && wait <= Duration::from_millis(250));
+    }
+
+    #[tokio::test]
+    async fn clients_and_routes_have_buckets_of_their_own() {
+        let limiter = limiter();
+        let limit = limit(0.001, 1);
+        take(&limiter, "user:alice", &limit).await.unwrap();
+        take(&limiter, "user:alice", &limit).await.unwrap_err();
+        take(&limiter, "ip:192.0.2.7", &limit).await.unwrap();
+        limiter
+            .acquire("/gateway/authorize/batch", "user:alice".to_string(), &limit)
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn buckets_refill_at_the_rate_up_to_the_burst() {
+        let now = Instant::now();
+        let mut bucket = Bucket {
+            tokens: 0.0,
+            updated: now,
+            limit: limit(2.0, 3),
+        };
+        bucket.refill(now + Duration::from_millis(500));
+        assert_eq!(bucket.tokens, 1.0);
+        bucket.refill(now + Duration::from_secs(10));
+        assert_eq!(bucket.tokens, 3.0);
+    }
+
+    #[tokio::test]
+    async fn a_lower_limit_caps_an_existing_bucket() {
+        let limiter = limiter();
+        take(&limiter, "user:alice", &limit(0.001, 10))
+            .await
+            .unwrap();
+        let lower = limit(0.001, 2);
+        take(&limiter, "user:alice", &lower).await.unwrap();
+        take(&limiter, "user:alice", &lower).await.unwrap();
+        take(&limiter, "user:alice", &lower).await.unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn new_limits_refill_every_bucket() {
+        let limiter = limiter();
+        let limit = limit(0.001, 1);
+        take(&limiter, "user:alice", &limit).await.unwrap();
+        take(&limiter, "user:alice", &limit).await.unwrap_err();
+        limiter.set_limits(HashMap::new());
+        assert_eq!(limiter.limit(ROUTE), None);
+        take(&limiter, "user:alice", &limit).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn sweeping_forgets_only_full_buckets() {
+        let limiter = limiter();
+        take(&limiter, "user:drained", &limit(0.001, 2))
+            .await
+            .unwrap();
+        take(&limiter, "user:refilled", &limit(1000.0, 1))
+            .await
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(10));
+        limiter.sweep();
+        let buckets = limiter.buckets.lock().unwrap();
+        assert_eq!(buckets.len(), 1);
+        assert!(buckets.contains_key(&(ROUTE.to_string(), "user:drained".to_string())));
+    }
+
+    #[tokio::test]
+    async fn shared_limits_without_redis_are_per_replica() {
+        let config = RateLimitConfig {
+            shared: true,
+            ..RateLimitConfig::default()
+        };
+        let limiter = RateLimiter::new(&config, None);
+        assert!(limiter.shared.is_none());
+        let limit = limit(0.001, 1);
+        take(&limiter, "user:alice", &limit).await.unwrap();
+        take(&limiter, "user:alice", &limit).await.unwrap_err();
+    }
+
+    fn lease(granted: u32, now: Instant) -> Lease {
+        let mut lease = Lease {
+            tokens: 0,
+            expires: now,
+        };
+        lease.extend(granted, now);
+        lease
+    }
+
+    #[test]
+    fn leased_tokens_are_spent_until_used_up() {
+        let now = Instant::now();
+        // One of the five is spent on the request that leased them
+        let mut lease = lease(5, now);
+        for _ in 0..4 {
+            assert!(lease.spend(now));
+        }
+        assert!(!lease.spend(now));
+    }
+
+    #[test]
+    fn leased_tokens_are_lost_once_expired() {
+        let now = Instant::now();
+        let mut lease = lease(5, now);
+        assert!(!lease.spend(now + LEASE_TTL));
+
+        lease.extend(2, now + 2 * LEASE_TTL);
+        assert_eq!(lease.tokens, 1);
+        assert_eq!(lease.expires, now + 3 * LEASE_TTL);
+    }
+
+    #[test]
+    fn leases_granted_meanwhile_add_up() {
+        let now = Instant::now();
+        let mut lease = lease(3, now);
+        let later = now + Duration::from_millis(100);
+        lease.extend(3, later);
+        assert_eq!(lease.tokens, 4);
+        assert_eq!(lease.expires, later + LEASE_TTL);
+    }
+}
@@ -0,0 +1,154 @@
+//! Reason codes and their messages
+//!
+//! A policy's free-text `reason` changes with every edit of the policy, so
+//! clients can't branch on it. A policy can also give a `reason_code`, a
+//! stable code like `SARK-POL-017`, which decisions carry as `reason_code`:
+//!
+//! ```rego
+//! reason_code := "SARK-POL-017" if not tool_allowed
+//! ```
+//!
+//! With a catalog configured, the `reason` of a decision with a code is the
+//! catalog's message for that code, in the language the caller asks for
+//! with `Accept-Language`:
+//!
+//! ```toml
+//! [reasons]
+//! catalog = "/etc/sark/reasons.toml"
+//! default_locale = "en"
+//! ```
+//!
+//! ```toml
+//! ["SARK-POL-017"]
+//! en = "This tool is not available to your role"
+//! de = "Dieses Werkzeug steht Ihrer Rolle nicht zur Verfügung"
+//! ```
+//!
+//! Like the config, the file may be YAML or JSON by extension, and codes
+//! match whatever their case. The caller's languages are tried by
+//! preference, each as given (`de-AT`) and then by its primary language
+//! (`de`), followed by `default_locale`. A code the catalog has no message
+//! for in any of them, or doesn't list, keeps the policy's own reason. The
+//! language chosen is sent back as `Content-Language` on
+//! `/gateway/authorize` and `/gateway/authorize-a2a`; batch entries and
+//! gRPC responses are localized the same way.
+//!
+//! Messages are chosen per response, after the cache, so callers asking
+//! for different languages share cached decisions, and the decision and
+//! audit logs keep the policy's own reason. The catalog is read again on
+//! config reload.
+
+use crate::config::ReasonsConfig;
+use crate::GatewayAuthResponse;
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+use tracing::info;
+
+/// Messages for reason codes, by uppercase code and then lowercase locale
+#[derive(Debug, Default, PartialEq)]
+pub struct Catalog {
+    messages: HashMap<String, HashMap<String, String>>,
+    default_locale: Option<String>,
+}
+
+impl Catalog {
+    /// The configured catalog, empty if none is
+    pub fn load(config: &ReasonsConfig) -> Result<Self> {
+        let Some(path) = &config.catalog else {
+            return Ok(Self::default());
+        };
+        if let Some(locale) = config.default_locale.as_deref() {
+            if !is_language_tag(locale) {
+                bail!("Invalid reasons.default_locale {:?}", locale);
+            }
+        }
+        let parsed: HashMap<String, HashMap<String, String>> = config::Config::builder()
+            .add_source(crate::config::file(path))
+            .build()
+            .with_context(|| format!("Failed to read reason catalog {}", path.display()))?
+            .try_deserialize()
+            .with_context(|| format!("Invalid reason catalog {}", path.display()))?;
+
+        let mut messages = HashMap::with_capacity(parsed.len());
+        for (code, locales) in parsed {
+            if code.is_empty() || !code.bytes().all(|b| b.is_ascii_graphic()) {
+                bail!("Invalid reason code {:?} in {}", code, path.display());
+            }
+            let mut by_locale = HashMap::with_capacity(locales.len());
+            for (locale, message) in locales {
+                if !is_language_tag(&locale) {
+                    bail!(
+                        "Invalid locale {:?} for {} in {}",
+                        locale,
+                        code,
+                        path.display()
+                    );
+                }
+                by_locale.insert(locale.to_ascii_lowercase(), message);
+            }
+            messages.insert(code.to_ascii_uppercase(), by_locale);
+        }
+        info!(
+            path = %path.display(),
+            codes = messages.len(),
+            "Loaded reason catalog"
+        );
+        Ok(Self {
+            messages,
+            default_locale: config
+                .default_locale
+                .as_deref()
+                .map(str::to_ascii_lowercase),
+        })
+    }
+
+    /// Replace `decision`'s reason with the message for its code in the
+    /// first of `accept_language`'s languages (an `Accept-Language` value)
+    /// the catalog has one in, returning that language
+    pub fn localize(
+        &self,
+        decision: &mut GatewayAuthResponse,
+        accept_language: Option<&str>,
+    ) -> Option<String> {
+        let code = decision.reason_code.as_deref()?.to_ascii_uppercase();
+        let locales = self.messages.get(&code)?;
+        let wanted = accept_language.map(preferences).unwrap_or_default();
+        let (locale, message) = wanted
+            .iter()
+            .flat_map(|tag| {
+                let primary = tag.split_once('-').map(|(primary, _)| primary);
+                std::iter::once(tag.as_str()).chain(primary)
+            })
+            .chain(self.default_locale.as_deref())
+            .find_map(|locale| locales.get_key_value(locale))?;
+        decision.reason = message.clone();
+        Some(locale.clone())
+    }
+}
+
+/// Languages of an `Accept-Language` value, lowercase, most preferred
+/// first; `*` and those weighted 0 are left out
+fn preferences(accept_language: &str) -> Vec<String> {
+    let mut weighted: Vec<(f32, String)> = accept_language
+        .split(',')
+        .filter_map(|range| {
+            let mut parts = range.split(';').map(str::trim);
+            let tag = parts.next().filter(|tag| is_language_tag(tag))?;
+            let q = parts
+                .find_map(|param| param.strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.parse::<f32>().ok())?;
+            (q > 0.0).then(|| (q, tag.to_ascii_lowercase()))
+        })
+        .collect();
+    // Stable, so equally weighted languages keep the caller's order
+    weighted.sort_by(|a, b| b.0.total_cmp(&a.0));
+    weighted.into_iter().map(|(_, tag)| tag).collect()
+}
+
+/// Whether `tag` looks like a BCP 47 language tag (`en`, `pt-BR`)
+fn is_language_tag(tag: &str) -> bool {
+    !tag.is_empty()
+        && tag.split('-').all(|part| {
+            (1..=8).contains(&part.len()) && part.bytes().all(|b| b.is_ascii_alphanumeric())
+        })
+}
@@ -0,0 +1,252 @@
+//! Redaction of MCP results
+//!
+//! A gateway decision may oblige the proxy to redact what the server
+//! returns, through a `redact` list in its `obligations`:
+//!
+//! ```rego
+//! obligations := {"redact": [
+//!     {"path": "structuredContent.customer.ssn", "mask": "***"},
+//!     {"path": "structuredContent.rows.*.email"},
+//! ]}
+//! ```
+//!
+//! Paths are dotted, relative to the JSON-RPC `result` of the response to
+//! the decided request; `*` matches every member of an object or item of
+//! an array and a number indexes an array. A field with a `mask` has its
+//! value replaced by it, one without is removed. Paths that match nothing
+//! are skipped.
+//!
+//! Obligations are applied to JSON and SSE responses alike, SSE event by
+//! event as they stream. A response that can't be redacted (any other
+//! content type, or a JSON body over `proxy.max_response_bytes`) is
+//! refused with 502 rather than passed on unredacted. An invalid `redact`
+//! list fails the request with 500 before it is forwarded.
+
+use crate::obligations::Obligations;
+use axum::body::Bytes;
+use futures::{Stream, StreamExt};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// One field to redact
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Redaction {
+    path: String,
+    /// Replacement value; the field is removed without one
+    mask: Option<Value>,
+}
+
+impl Redaction {
+    /// The redactions a decision's `obligations` require
+    pub fn from_obligations(obligations: Option<&Obligations>) -> Result<Vec<Self>, String> {
+        let Some(redact) = obligations.and_then(|o| o.get("redact")) else {
+            return Ok(Vec::new());
+        };
+        let redactions: Vec<Self> = serde_json::from_value(redact.clone())
+            .map_err(|e| format!("Invalid redact obligation: {}", e))?;
+        if let Some(bad) = redactions
+            .iter()
+            .find(|r| r.path.is_empty() || r.path.split('.').any(str::is_empty))
+        {
+            return Err(format!("Invalid redact obligation path {:?}", bad.path));
+        }
+        Ok(redactions)
+    }
+
+    fn apply(&self, result: &mut Value) -> usize {
+        let segments: Vec<&str> = self.path.split('.').collect();
+        redact(result, &segments, self.mask.as_ref())
+    }
+}
+
+/// Replace the fields a dotted `path` reaches from `value` with `with`, as
+/// a `redact` obligation with a mask would, returning how many there were
+pub fn mask(value: &mut Value, path: &str, with: &Value) -> usize {
+    let segments: Vec<&str> = path.split('.').collect();
+    redact(value, &segments, Some(with))
+}
+
+/// Redact the fields `segments` reach from `value`, returning how many were
+fn redact(value: &mut Value, segments: &[&str], mask: Option<&Value>) -> usize {
+    let [segment, rest @ ..] = segments else {
+        return 0;
+    };
+    if rest.is_empty() {
+        return match (value, *segment, mask) {
+            (Value::Object(map), "*", Some(mask)) => {
+                map.values_mut().for_each(|v| *v = mask.clone());
+                map.len()
+            }
+            (Value::Object(map), "*", None) => std::mem::take(map).len(),
+            (Value::Array(items), "*", Some(mask)) => {
+                items.iter_mut().for_each(|v| *v = mask.clone());
+                items.len()
+            }
+            (Value::Array(items), "*", None) => std::mem::take(items).len(),
+            (Value::Object(map), key, Some(mask)) => match map.get_mut(key) {
+                Some(field) => {
+                    *field = mask.clone();
+                    1
+                }
+                None => 0,
+            },
+            (Value::Object(map), key, None) => usize::from(map.remove(key).is_some()),
+            (Value::Array(items), index, mask) => match index.parse::<usize>() {
+                Ok(i) if i < items.len() => {
+                    match mask {
+                        Some(mask) => items[i] = mask.clone(),
+                        None => {
+                            items.remove(i);
+                        }
+                    }
+                    1
+                }
+                _ => 0,
+            },
+            _ => 0,
+        };
+    }
+    match (value, *segment) {
+        (Value::Object(map), "*") => map.values_mut().map(|v| redact(v, rest, mask)).sum(),
+        (Value::Array(items), "*") => items.iter_mut().map(|v| redact(v, rest, mask)).sum(),
+        (Value::Object(map), key) => map.get_mut(key).map_or(0, |v| redact(v, rest, mask)),
+        (Value::Array(items), index) => index
+            .parse::<usize>()
+            .ok()
+            .and_then(|i| items.get_mut(i))
+            .map_or(0, |v| redact(v, rest, mask)),
+        _ => 0,
+    }
+}
+
+/// The redactions owed to each decided request's response
+#[derive(Debug, Default)]
+pub struct Redactor {
+    /// By the JSON-RPC request id (as JSON text)
+    by_id: HashMap<String, Vec<Redaction>>,
+    /// Owed to every result, when the request carried no JSON-RPC request
+    /// (an SSE stream opened with `GET`)
+    all: Vec<Redaction>,
+}
+
+impl Redactor {
+    /// Owe `redactions` to the response to request `id`, or to every
+    /// result without one
+    pub fn add(&mut self, id: Option<&Value>, redactions: Vec<Redaction>) {
+        match id {
+            Some(id) => self
+                .by_id
+                .entry(id.to_string())
+                .or_default()
+                .extend(redactions),
+            None => self.all.extend(redactions),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_id.values().all(Vec::is_empty) && self.all.is_empty()
+    }
+
+    /// Redact the results in a JSON-RPC message or batch, returning how
+    /// many fields were
+    pub fn apply(&self, message: &mut Value) -> usize {
+        if let Value::Array(messages) = message {
+            return messages.iter_mut().map(|m| self.apply(m)).sum();
+        }
+        let owed = message
+            .get("id")
+            .and_then(|id| self.by_id.get(&id.to_string()))
+            .into_iter()
+            .flatten()
+            .chain(&self.all);
+        let Some(result) = message.get_mut("result") else {
+            return 0;
+        };
+        owed.map(|redaction| redaction.apply(result)).sum()
+    }
+
+    /// Redact the JSON-RPC messages in an SSE stream as its events arrive
+    pub fn events<S, E>(self: Arc<Self>, body: S) -> impl Stream<Item = Result<Bytes, E>>
+    where
+        S: Stream<Item = Result<Bytes, E>> + Unpin,
+    {
+        futures::stream::unfold(
+            (body, Vec::new(), false),
+            move |(mut body, mut pending, mut ended)| {
+                let redactor = self.clone();
+                async move {
+                    loop {
+                        if let Some(end) = event_end(&pending) {
+                            let event: Vec<u8> = pending.drain(..end).collect();
+                            let event = redactor.event(&event);
+                            return Some((Ok(event), (body, pending, ended)));
+                        }
+                        if ended {
+                            if pending.is_empty() {
+                                return None;
+                            }
+                            let event = redactor.event(&std::mem::take(&mut pending));
+                            return Some((Ok(event), (body, pending, ended)));
+                        }
+                        match body.next().await {
+                            Some(Ok(chunk)) => pending.extend_from_slice(&chunk),
+                            Some(Err(e)) => return Some((Err(e), (body, Vec::new(), true))),
+                            None => ended = true,
+                        }
+                    }
+                }
+            },
+        )
+    }
+
+    /// One SSE event (with its terminating blank line) with its data
+    /// redacted; events whose data isn't JSON pass unchanged
+    fn event(&self, event: &[u8]) -> Bytes {
+        let Ok(text) = std::str::from_utf8(event) else {
+            return Bytes::copy_from_slice(event);
+        };
+        let data: Vec<&str> = text
+            .lines()
+            .filter_map(|line| line.strip_prefix("data:"))
+            .map(|data| data.strip_prefix(' ').unwrap_or(data))
+            .collect();
+        let Ok(mut message) = serde_json::from_str::<Value>(&data.join("\n")) else {
+            return Bytes::copy_from_slice(event);
+        };
+        if self.apply(&mut message) == 0 {
+            return Bytes::copy_from_slice(event);
+        }
+
+        let mut redacted = String::new();
+        let mut written = false;
+        for line in text.lines().filter(|line| !line.is_empty()) {
+            if !line.starts_with("data:") {
+                redacted.push_str(line);
+                redacted.push('\n');
+            } else if !written {
+                redacted.push_str("data: ");
+                redacted.push_str(&message.to_string());
+                redacted.push('\n');
+                written = true;
+            }
+        }
+        redacted.push('\n');
+        Bytes::from(redacted)
+    }
+}
+
+/// Length of the first complete event in `pending`, blank line included
+fn event_end(pending: &[u8]) -> Option<usize> {
+    [&b"\n\n"[..], b"\r\n\r\n", b"\r\r"]
+        .iter()
+        .filter_map(|delimiter| {
+            pending
+                .windows(delimiter.len())
+                .position(|w| w == *delimiter)
+                .map(|at| at + delimiter.len())
+        })
+        .min()
+}
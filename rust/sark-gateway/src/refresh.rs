@@ -0,0 +1,191 @@
+//! Data documents refreshed from HTTP endpoints
+//!
+//! Policies often decide by data that lives elsewhere: role mappings, the
+//! server registry kept by the SARK API. Each `[data.sources.<name>]`
+//! entry is fetched every `interval` seconds and replaces the data at its
+//! `path` in the default policy, which is recompiled and has its cached
+//! decisions dropped, as with `PUT /admin/data`:
+//!
+//! ```rego
+//! allow if {
+//!     some role in data.sark.roles[input.user.id]
+//!     role in data.sark.registry[input.resource.server].roles
+//! }
+//! ```
+//!
+//! Fetches are conditional on the last `ETag`, so an unchanged document
+//! costs a `304` and no recompile; one that comes back the same without
+//! an `ETag` is compared and skipped too. A failed fetch (unreachable, not
+//! 2xx, not JSON, or not compiling) keeps the last good document in place
+//! and retries after twice the previous wait, up to `max_backoff`; the
+//! source shows as degraded in `/health` until a fetch succeeds again.
+//!
+//! Refreshed documents outlive policy reloads: each is laid over every
+//! policy set activated after it. Tenants' policies don't see them.
+
+use crate::bundle::SyncStatus;
+use crate::config::{DataConfig, DataSourceConfig};
+use crate::AppState;
+use anyhow::{bail, Context, Result};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, ETAG, IF_NONE_MATCH};
+use reqwest::StatusCode;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+const DEFAULT_INTERVAL: u64 = 60;
+const DEFAULT_MAX_BACKOFF: u64 = 600;
+const DEFAULT_TIMEOUT: u64 = 10;
+
+/// The configured sources, ready to start once the gateway's state exists
+pub struct Refreshers {
+    sources: Vec<Source>,
+}
+
+struct Source {
+    name: String,
+    url: String,
+    path: String,
+    interval: Duration,
+    max_backoff: Duration,
+    client: reqwest::Client,
+    etag: Option<String>,
+    status: Arc<SyncStatus>,
+}
+
+impl Refreshers {
+    pub fn new(config: &DataConfig) -> Result<Self> {
+        let sources = config
+            .sources
+            .iter()
+            .map(|(name, source)| Source::new(name, source))
+            .collect::<Result<_>>()?;
+        Ok(Self { sources })
+    }
+
+    /// How each source is keeping up, by name
+    pub fn statuses(&self) -> BTreeMap<String, Arc<SyncStatus>> {
+        self.sources
+            .iter()
+            .map(|source| (source.name.clone(), source.status.clone()))
+            .collect()
+    }
+
+    /// Fetch each source now and then on its interval, for the life of the
+    /// process
+    pub fn start(self, state: AppState) {
+        for source in self.sources {
+            info!(source = %source.name, url = %source.url, "Refreshing data source");
+            tokio::spawn(source.run(state.clone()));
+        }
+    }
+}
+
+impl Source {
+    fn new(name: &str, config: &DataSourceConfig) -> Result<Self> {
+        let mut headers = HeaderMap::new();
+        for (header, value) in &config.headers {
+            headers.insert(
+                HeaderName::from_bytes(header.as_bytes())?,
+                HeaderValue::from_str(value)?,
+            );
+        }
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(
+                config.timeout.unwrap_or(DEFAULT_TIMEOUT),
+            ))
+            .default_headers(headers)
+            .build()
+            .context("Failed to build data source HTTP client")?;
+
+        Ok(Self {
+            name: name.to_string(),
+            url: config.url.clone(),
+            path: config.path.trim_matches('/').to_string(),
+            interval: Duration::from_secs(config.interval.unwrap_or(DEFAULT_INTERVAL)),
+            max_backoff: Duration::from_secs(config.max_backoff.unwrap_or(DEFAULT_MAX_BACKOFF)),
+            client,
+            etag: None,
+            status: Arc::default(),
+        })
+    }
+
+    async fn run(mut self, state: AppState) {
+        let mut wait = self.interval;
+        loop {
+            match self.refresh(&state).await {
+                Ok(()) => {
+                    self.status.synced();
+                    tokio::time::sleep(self.interval).await;
+                    wait = self.interval;
+                }
+                Err(e) => {
+                    let error = format!("{:#}", e);
+                    warn!(
+                        source = %self.name,
+                        error = %error,
+                        retry_secs = wait.as_secs(),
+                        "Data refresh failed"
+                    );
+                    self.status.failed(error);
+                    tokio::time::sleep(wait).await;
+                    wait = (wait * 2).min(self.max_backoff.max(self.interval));
+                }
+            }
+        }
+    }
+
+    /// Fetch the document and activate it if it changed
+    async fn refresh(&mut self, state: &AppState) -> Result<()> {
+        let Some((document, etag)) = self.fetch().await? else {
+            debug!(source = %self.name, "Data source unchanged");
+            return Ok(());
+        };
+
+        let changed = state
+            .policy
+            .lock()
+            .await
+            .refresh_data(&self.path, document)
+            .with_context(|| format!("Refreshed data at {} failed to compile", self.path))?;
+        // Only once the document is active, so a failure refetches it
+        self.etag = etag;
+        if changed {
+            state.clear_decisions().await;
+            info!(source = %self.name, path = %self.path, "Refreshed policy data");
+        }
+        Ok(())
+    }
+
+    /// The document and its `ETag`, or `None` if it is unchanged since the
+    /// last one activated
+    async fn fetch(&self) -> Result<Option<(Value, Option<String>)>> {
+        let mut request = self.client.get(&self.url);
+        if let Some(etag) = &self.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        let response = request
+            .send()
+            .await
+            .with_context(|| format!("Failed to fetch {}", self.url))?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            bail!("{} returned {}", self.url, response.status());
+        }
+
+        let etag = response
+            .headers()
+            .get(ETAG)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        let document = response
+            .json::<Value>()
+            .await
+            .with_context(|| format!("{} did not return JSON", self.url))?;
+        Ok(Some((document, etag)))
+    }
+}
@@ -0,0 +1,456 @@
+//! Config reload
+//!
+//! On SIGHUP or `POST /admin/config/reload`, the config file and
+//! environment are read again (with the same command-line flags on top)
+//! and the settings that can change at runtime are applied in place:
+//!
+//! - log level
+//! - cache TTLs, for decisions cached from then on
+//! - the decision cache size outside namespace partitions, evicting at
+//!   once if it shrinks
+//! - JWKS URL, introspection endpoint, audience, issuer, claim mapping and
+//!   verified-token cache
+//! - API keys, including the key file
+//! - policy directory or bundle, including watching and polling
+//! - the canary weight new policy revisions roll out at
+//! - rate limits
+//! - trusted proxies and client address allow/deny lists
+//! - enrichment databases, read again even if their paths are unchanged
+//! - the reason code catalog, read again even if its path is unchanged
+//! - sensitivity classification rules
+//! - configured decision overrides
+//!
+//! Everything that can fail (parsing, validation, fetching the new key set,
+//! loading the new policies) happens before anything is applied, so a bad
+//! config leaves the running settings untouched. The listen address, socket
+//! mode and connection settings, TLS, log format, cache eviction, shards,
+//! pinned entries and Bloom filter, sweep interval, key fields, namespace
+//! partitions, precomputation, drain timeout, rate limit sharing, admin
+//! API, concurrency limits and evaluation timeout, request limits,
+//! parameter scanning, credential detection, admission webhook, step-up
+//! enforcement, fallback, proxy servers, tenants, the signing key, response
+//! headers, SPIFFE settings, the PROXY protocol, policy queries, metric
+//! label bounds, data sources and decision capture are read at startup
+//! only; changes to them are reported and wait for a restart. Connections
+//! and requests in flight are unaffected either way.
+
+use crate::apikey::ApiKeys;
+use crate::auth::{JwtVerifier, TokenCache};
+use crate::bundle::{self, BundleLoader, BundleVerifier};
+use crate::cache::{Namespace, Ttls};
+use crate::clientip::ClientIps;
+use crate::config::{GatewayConfig, JwtConfig, PolicyConfig};
+use crate::enrich::Enricher;
+use crate::introspection::Introspector;
+use crate::policy::{self, PolicySet, PolicyStore};
+use crate::reasons::Catalog;
+use crate::telemetry::{self, LogFilter};
+use crate::{watch, AppState, Args};
+use anyhow::{bail, Context, Result};
+use clap::{ArgMatches, FromArgMatches};
+use sark_classify::Classifier;
+use sark_jwt::ClaimMapping;
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::task::AbortHandle;
+use tracing::{error, info, warn};
+
+/// What a reload changed
+#[derive(Debug, Default, Serialize)]
+pub struct ReloadReport {
+    pub applied: Vec<&'static str>,
+    /// Changed settings that only take effect on restart
+    pub restart_required: Vec<&'static str>,
+}
+
+pub struct Reloader {
+    path: PathBuf,
+    /// Whether the config file must exist (it was named explicitly)
+    required: bool,
+    matches: ArgMatches,
+    log_filter: LogFilter,
+    /// Config the process started with, for startup-only settings
+    startup: GatewayConfig,
+    /// Held for the whole reload, so reloads don't interleave
+    running: Mutex<Running>,
+}
+
+/// The config in effect and the tasks following its policy source
+struct Running {
+    config: GatewayConfig,
+    followers: Vec<AbortHandle>,
+}
+
+impl Reloader {
+    pub fn new(
+        path: PathBuf,
+        required: bool,
+        matches: ArgMatches,
+        log_filter: LogFilter,
+        config: GatewayConfig,
+        followers: Vec<AbortHandle>,
+    ) -> Self {
+        Self {
+            path,
+            required,
+            matches,
+            log_filter,
+            startup: config.clone(),
+            running: Mutex::new(Running { config, followers }),
+        }
+    }
+
+    /// Re-read the config and apply what changed to `state`
+    pub async fn reload(&self, state: &AppState) -> Result<ReloadReport> {
+        match self.try_reload(state).await {
+            Ok(report) => {
+                if !report.restart_required.is_empty() {
+                    warn!(
+                        settings = ?report.restart_required,
+                        "Changed settings take effect on restart"
+                    );
+                }
+                info!(applied = ?report.applied, "Reloaded config");
+                Ok(report)
+            }
+            Err(e) => {
+                error!(
+                    error = %format!("{:#}", e),
+                    "Config reload failed; keeping current settings"
+                );
+                Err(e)
+            }
+        }
+    }
+
+    async fn try_reload(&self, state: &AppState) -> Result<ReloadReport> {
+        let mut running = self.running.lock().await;
+
+        let args = Args::from_arg_matches(&self.matches)?;
+        let mut config = GatewayConfig::load(&self.path, self.required)?;
+        crate::apply_flags(&mut config, &args, &self.matches);
+        crate::check_config(&config, &args)?;
+
+        let mut report = ReloadReport::default();
+        let startup = &self.startup;
+        for (setting, changed) in [
+            ("listen", config.listen != startup.listen),
+            ("unix_socket", config.unix_socket != startup.unix_socket),
+            ("connections", config.connections != startup.connections),
+            ("tls", config.tls != startup.tls),
+            ("log.format", config.log.format != startup.log.format),
+            (
+                "cache.eviction",
+                config.cache.eviction != startup.cache.eviction,
+            ),
+            ("cache.shards", config.cache.shards != startup.cache.shards),
+            (
+                "cache.max_pinned",
+                config.cache.max_pinned != startup.cache.max_pinned,
+            ),
+            (
+                "cache.bloom_fp_rate",
+                config.cache.bloom_fp_rate != startup.cache.bloom_fp_rate,
+            ),
+            (
+                "cache.cleanup_interval",
+                config.cache.cleanup_interval != startup.cache.cleanup_interval,
+            ),
+            (
+                "cache.key_fields",
+                config.cache.key_fields != startup.cache.key_fields,
+            ),
+            (
+                "cache.namespaces",
+                config.cache.namespaces != startup.cache.namespaces,
+            ),
+            (
+                "cache.precompute",
+                config.cache.precompute != startup.cache.precompute,
+            ),
+            (
+                "shutdown.drain_timeout",
+                config.shutdown.drain_timeout != startup.shutdown.drain_timeout,
+            ),
+            (
+                "rate_limit.shared",
+                config.rate_limit.shared != startup.rate_limit.shared
+                    || config.rate_limit.lease != startup.rate_limit.lease,
+            ),
+            ("step_up", config.step_up != startup.step_up),
+            ("admin", config.admin != startup.admin),
+            ("concurrency", config.concurrency != startup.concurrency),
+            ("requests", config.requests != startup.requests),
+            ("parameters", config.parameters != startup.parameters),
+            ("detection", config.detection != startup.detection),
+            ("admission", config.admission != startup.admission),
+            ("fallback", config.fallback != startup.fallback),
+            ("proxy", config.proxy != startup.proxy),
+            ("tenants", config.tenants != startup.tenants),
+            ("signing", config.signing != startup.signing),
+            (
+                "response_headers",
+                config.response_headers != startup.response_headers,
+            ),
+            ("spiffe", config.spiffe != startup.spiffe),
+            ("queries", config.queries != startup.queries),
+            ("metrics", config.metrics != startup.metrics),
+            ("data", config.data != startup.data),
+            ("capture", config.capture != startup.capture),
+            (
+                "client_ip.proxy_protocol",
+                config.client_ip.proxy_protocol != startup.client_ip.proxy_protocol,
+            ),
+        ] {
+            if changed {
+                report.restart_required.push(setting);
+            }
+        }
+
+        // Prepare everything that can fail before applying anything
+        let current = &running.config;
+        let log_level_changed = config.log.level != current.log.level;
+        let rate_limit_changed = config.rate_limit.routes != current.rate_limit.routes;
+        let jwt = if config.jwt != current.jwt || config.claims != current.claims {
+            Some(verifier(&config.jwt, &config.claims).await?)
+        } else {
+            None
+        };
+        // The key file may have changed even if the config hasn't
+        let api_keys = ApiKeys::load(&config.api_keys)?;
+        let enricher = Enricher::load(&config.enrich)?;
+        let reasons = Catalog::load(&config.reasons)?;
+        let classifier = if config.classify != current.classify {
+            Some(Classifier::new(&config.classify)?)
+        } else {
+            None
+        };
+        let policies = if config.policy != current.policy {
+            Some(load_policies(&config.policy, &args).await?)
+        } else {
+            None
+        };
+
+        // Before any new policies, so they roll out at the new weight
+        if config.rollout != current.rollout {
+            state
+                .policy
+                .lock()
+                .await
+                .set_canary_weight(config.rollout.canary_weight);
+            report.applied.push("rollout");
+        }
+
+        if let Some((set, loader)) = policies {
+            let bundle = loader.as_ref().map(BundleLoader::status);
+            let decisions = state.decision_caches();
+            let followers =
+                follow_policies(&config.policy, loader, state.policy.clone(), &decisions)?;
+            if let Err(e) = policy::activate(&state.policy, &decisions, set).await {
+                followers.iter().for_each(AbortHandle::abort);
+                return Err(e);
+            }
+            for follower in std::mem::replace(&mut running.followers, followers) {
+                follower.abort();
+            }
+            *state.bundle.write().expect("bundle lock poisoned") = bundle;
+            report.applied.push("policy");
+        }
+
+        if log_level_changed {
+            telemetry::set_level(&self.log_filter, &config.log.level)?;
+            report.applied.push("log.level");
+        }
+
+        let ttls = Ttls::from(&config.cache);
+        if ttls != state.ttls() {
+            *state.ttls.write().expect("ttls lock poisoned") = ttls;
+            report.applied.push("cache ttls");
+        }
+
+        if config.cache.max_entries != running.config.cache.max_entries {
+            let evicted = state.cache.resize(config.cache.max_entries);
+            info!(
+                max_entries = config.cache.max_entries,
+                evicted, "Resized the decision cache"
+            );
+            report.applied.push("cache.max_entries");
+        }
+
+        if let Some(jwt) = jwt {
+            *state.jwt.write().expect("jwt lock poisoned") = jwt;
+            report.applied.push("jwt");
+        }
+
+        if api_keys != *state.api_keys() {
+            *state.api_keys.write().expect("api keys lock poisoned") = Arc::new(api_keys);
+            report.applied.push("api_keys");
+        }
+
+        if rate_limit_changed {
+            state
+                .rate_limit
+                .set_limits(config.rate_limit.routes.clone());
+            report.applied.push("rate_limit");
+        }
+
+        if !enricher.same_as(&state.enricher()) {
+            *state.enricher.write().expect("enricher lock poisoned") = Arc::new(enricher);
+            report.applied.push("enrich");
+        }
+
+        if reasons != *state.reasons() {
+            *state.reasons.write().expect("reasons lock poisoned") = Arc::new(reasons);
+            report.applied.push("reasons");
+        }
+
+        if let Some(classifier) = classifier {
+            *state.classifier.write().expect("classifier lock poisoned") = Arc::new(classifier);
+            report.applied.push("classify");
+        }
+
+        let client_ips = ClientIps::new(&config.client_ip);
+        if client_ips != *state.client_ips() {
+            *state.client_ips.write().expect("client ips lock poisoned") = Arc::new(client_ips);
+            report.applied.push("client_ip");
+        }
+
+        if config.overrides != running.config.overrides {
+            state.overrides.configure(&config.overrides);
+            report.applied.push("overrides");
+        }
+
+        running.config = config;
+        Ok(report)
+    }
+
+    /// The config in effect
+    pub async fn config(&self) -> GatewayConfig {
+        self.running.lock().await.config.clone()
+    }
+
+    /// Load the configured policy directory or bundle again and activate
+    /// it, without re-reading the config
+    pub async fn reload_policies(&self, state: &AppState) -> Result<()> {
+        let running = self.running.lock().await;
+        let config = &running.config.policy;
+        if config.dir.is_none() && config.bundle_url.is_none() {
+            bail!("No policy directory or bundle configured");
+        }
+
+        let args = Args::from_arg_matches(&self.matches)?;
+        let (set, _) = load_policies(config, &args).await?;
+        policy::activate(&state.policy, &state.decision_caches(), set).await
+    }
+}
+
+/// Reload on every SIGHUP
+#[cfg(unix)]
+pub async fn on_hangup(state: AppState) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            warn!(error = %e, "Failed to install SIGHUP handler; reload via the admin API only");
+            return;
+        }
+    };
+    while hangup.recv().await.is_some() {
+        info!("SIGHUP received; reloading config");
+        let _ = state.reloader.reload(&state).await;
+    }
+}
+
+/// The policies at `config`'s source, and the loader to keep polling a
+/// bundle with (the embedded policies, or none, without a source)
+pub async fn load_policies(
+    config: &PolicyConfig,
+    args: &Args,
+) -> Result<(PolicySet, Option<BundleLoader>)> {
+    if let Some(dir) = &config.dir {
+        let set = PolicySet::from_dir(dir)
+            .and_then(|set| set.rendered(&config.vars))
+            .with_context(|| format!("Failed to load policies from {}", dir.display()))?;
+        return Ok((set, None));
+    }
+
+    let Some(url) = &config.bundle_url else {
+        let set = PolicySet::embedded()?.unwrap_or_default();
+        return Ok((set.rendered(&config.vars)?, None));
+    };
+    let mut loader = BundleLoader::new(url)?.with_vars(config.vars.clone());
+    if let Some(key) = &args.bundle_verification_key {
+        loader = loader.with_verifier(BundleVerifier::from_key_file(
+            key,
+            &args.bundle_verification_alg,
+            args.bundle_require_signature,
+        )?);
+    }
+    let set = loader
+        .fetch()
+        .await
+        .with_context(|| format!("Failed to load bundle {}", url))?
+        .context("Bundle returned no content")?;
+    loader.status().synced();
+    Ok((set, Some(loader)))
+}
+
+/// Watch `config`'s policy directory or poll its bundle, as configured
+pub fn follow_policies(
+    config: &PolicyConfig,
+    loader: Option<BundleLoader>,
+    policy: Arc<Mutex<PolicyStore>>,
+    decisions: &[Namespace],
+) -> Result<Vec<AbortHandle>> {
+    let mut followers = Vec::new();
+    if let Some(dir) = config.dir.clone().filter(|_| config.watch) {
+        followers.push(watch::start(
+            dir,
+            config.vars.clone(),
+            policy.clone(),
+            decisions.to_vec(),
+        )?);
+    }
+    if let Some(loader) = loader.filter(|_| config.bundle_poll_interval > 0) {
+        let poll = tokio::spawn(bundle::poll(
+            loader,
+            policy,
+            decisions.to_vec(),
+            Duration::from_secs(config.bundle_poll_interval),
+        ));
+        followers.push(poll.abort_handle());
+    }
+    Ok(followers)
+}
+
+/// Token verifier for `config`, fetching its key set (none without a JWKS
+/// URL or introspection endpoint)
+pub async fn verifier(
+    config: &JwtConfig,
+    claims: &ClaimMapping,
+) -> Result<Option<Arc<JwtVerifier>>> {
+    let introspector = Introspector::new(
+        &config.introspection,
+        config.audience.clone(),
+        config.issuer.clone(),
+    )?;
+    if config.jwks_url.is_none() && introspector.is_none() {
+        warn!("No JWKS URL configured; every authorization request will be rejected");
+        return Ok(None);
+    }
+    let verifier = JwtVerifier::new(
+        config.jwks_url.as_deref(),
+        introspector,
+        config.audience.clone(),
+        config.issuer.clone(),
+        claims.clone(),
+        Duration::from_secs(config.refresh_interval),
+        TokenCache::new(config.cache_entries, config.cache_ttl),
+    )
+    .await?;
+    Ok(Some(Arc::new(verifier)))
+}
@@ -0,0 +1,239 @@
+//! Decision replay
+//!
+//! `POST /admin/replay` evaluates a decision again against the policy
+//! active now, to see why it came out as it did or whether a policy change
+//! would decide it differently. It takes either the id of a logged
+//! decision or a raw input document:
+//!
+//! ```json
+//! {"decision_id": "3f2a..."}
+//! {"input": {"user": {...}, "action": "gateway:tool:invoke"}, "query": "data.mcp.gateway"}
+//! ```
+//!
+//! Decision logs hold only a hash of each input, so the inputs of the last
+//! `--decision-log-replay-buffer` logged decisions are kept in memory, on
+//! this replica, for replay by id; older ones must be replayed from their
+//! input. Inputs are only kept while both the decision log and the admin
+//! API are enabled.
+//!
+//! The response carries the whole document the policy's package evaluates
+//! to, every rule's value rather than just `allow` and `reason`. The
+//! engine exposes no expression-level trace, so this is as far as a
+//! replay explains a decision. Replays by id also return the logged
+//! decision and whether the verdict changed. Replays are not decision
+//! logged, cached or audited. An input with a `user.tenant` is replayed
+//! against that tenant's policy.
+
+use crate::decision_log::DecisionRecord;
+use crate::problem::{JsonBody, Problem};
+use crate::{AppState, Endpoint};
+use axum::{extract::State, Json};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use tracing::info;
+
+/// Route replays are served on, behind the admin token
+pub const ROUTE: &str = "/admin/replay";
+
+/// Default decisions whose inputs are kept for replay
+pub const DEFAULT_BUFFER: usize = 1000;
+
+/// A logged decision, with the input it was made for
+#[derive(Debug, Clone, Serialize)]
+pub struct Retained {
+    decision_id: String,
+    timestamp: DateTime<Utc>,
+    #[serde(skip)]
+    query: String,
+    #[serde(skip)]
+    input: Value,
+    result: Option<Value>,
+    error: Option<String>,
+    cached: bool,
+    revision: Option<String>,
+}
+
+/// The inputs of the most recently logged decisions, by decision id
+pub struct Inputs {
+    capacity: usize,
+    /// Decisions by id, and their ids oldest first
+    retained: Mutex<(HashMap<String, Retained>, VecDeque<String>)>,
+}
+
+impl Inputs {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            retained: Mutex::new((HashMap::new(), VecDeque::new())),
+        }
+    }
+
+    /// Keep `record`'s input, forgetting the oldest beyond capacity
+    pub fn keep(&self, record: &mut DecisionRecord) {
+        let Some(input) = record.input.take() else {
+            return;
+        };
+        let retained = Retained {
+            decision_id: record.decision_id.clone(),
+            timestamp: record.timestamp,
+            query: record.query.clone(),
+            input,
+            result: record.result.clone(),
+            error: record.error.clone(),
+            cached: record.cached,
+            revision: record.revision.clone(),
+        };
+        let mut guard = self.retained.lock().expect("replay lock poisoned");
+        let (by_id, order) = &mut *guard;
+        order.push_back(retained.decision_id.clone());
+        by_id.insert(retained.decision_id.clone(), retained);
+        while order.len() > self.capacity {
+            if let Some(oldest) = order.pop_front() {
+                by_id.remove(&oldest);
+            }
+        }
+    }
+
+    /// The queries and inputs of the last `count` decisions, newest first
+    pub fn recent(&self, count: usize) -> Vec<(String, Value)> {
+        let guard = self.retained.lock().expect("replay lock poisoned");
+        let (by_id, order) = &*guard;
+        order
+            .iter()
+            .rev()
+            .take(count)
+            .filter_map(|id| by_id.get(id))
+            .map(|retained| (retained.query.clone(), retained.input.clone()))
+            .collect()
+    }
+
+    fn get(&self, decision_id: &str) -> Option<Retained> {
+        let guard = self.retained.lock().expect("replay lock poisoned");
+        guard.0.get(decision_id).cloned()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReplayRequest {
+    /// A logged decision to replay
+    decision_id: Option<String>,
+    /// Or an input document to evaluate
+    input: Option<Value>,
+    /// Rule the input is evaluated against, with `input` only; by default
+    /// the package a gateway authorization of it would be
+    query: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReplayResponse {
+    query: String,
+    /// Revision of the policy the replay was evaluated with
+    revision: String,
+    input: Value,
+    allow: bool,
+    reason: String,
+    /// Everything the query's package evaluated to
+    document: Value,
+    /// The decision as logged, when replaying by id
+    #[serde(skip_serializing_if = "Option::is_none")]
+    original: Option<Retained>,
+    /// Whether the verdict differs from the logged one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    changed: Option<bool>,
+}
+
+pub async fn replay(
+    State(state): State<AppState>,
+    JsonBody(request): JsonBody<ReplayRequest>,
+) -> Result<Json<ReplayResponse>, Problem> {
+    let (query, input, original) = match request {
+        ReplayRequest {
+            decision_id: Some(id),
+            input: None,
+            query: None,
+        } => {
+            let Some(inputs) = state.decision_log.as_ref().and_then(|log| log.inputs()) else {
+                return Err(Problem::NotFound(
+                    "Decision inputs are not kept for replay; \
+                     replay from the input instead"
+                        .to_string(),
+                ));
+            };
+            let retained = inputs.get(&id).ok_or_else(|| {
+                Problem::NotFound(format!(
+                    "Decision {} is not among the last {} logged; \
+                     replay from the input instead",
+                    id, inputs.capacity
+                ))
+            })?;
+            (
+                retained.query.clone(),
+                retained.input.clone(),
+                Some(retained),
+            )
+        }
+        ReplayRequest {
+            decision_id: None,
+            input: Some(input),
+            query,
+        } => {
+            let query = query.unwrap_or_else(|| {
+                Endpoint::Authorize
+                    .query(&state.queries, &input)
+                    .to_string()
+            });
+            (query, input, None)
+        }
+        ReplayRequest {
+            decision_id: Some(_),
+            ..
+        } => {
+            return Err(Problem::InvalidRequest(
+                "A replay by decision_id takes neither input nor query".to_string(),
+            ))
+        }
+        ReplayRequest { .. } => {
+            return Err(Problem::InvalidRequest(
+                "Give a decision_id or an input to replay".to_string(),
+            ))
+        }
+    };
+    if !query.starts_with("data.") {
+        return Err(Problem::InvalidRequest(format!(
+            "Query {:?} is not a data.* rule",
+            query
+        )));
+    }
+
+    let (document, revision) = crate::evaluate(&state, &query, ROUTE, &input).await?;
+    let (allow, reason) = crate::verdict(&document);
+    let changed = original.as_ref().map(|original| {
+        let logged = original
+            .result
+            .as_ref()
+            .map(|result| crate::verdict(result).0);
+        logged != Some(allow)
+    });
+    info!(
+        query = %query,
+        decision_id = original.as_ref().map(|o| o.decision_id.as_str()),
+        allow = allow,
+        changed = changed,
+        "Replayed decision"
+    );
+
+    Ok(Json(ReplayResponse {
+        query,
+        revision,
+        input,
+        allow,
+        reason,
+        document,
+        original,
+        changed,
+    }))
+}
@@ -0,0 +1,282 @@
+//! Parameter scanning
+//!
+//! Tool parameters can run to megabytes (file contents, long prompts) that
+//! policies never read whole, yet each would be copied into the policy
+//! input and serialized again for the engine. With
+//! `parameters.max_string_bytes`, the decision routes scan `parameters`
+//! as the body is parsed instead of building it as a value. A longer
+//! string is only measured, never copied, and reaches the policy as a
+//! summary in its place:
+//!
+//! ```json
+//! "content": {"$summary": {"bytes": 5242880, "lines": 80412, "entropy": 4.71, "prefix": "..."}}
+//! ```
+//!
+//! `entropy` is Shannon entropy in bits per byte, from 0 to 8. Text sits
+//! around 4 to 5; compressed, encrypted or random data (keys, tokens)
+//! nears 8. `prefix` is the string's first `prefix_bytes`. Fields under a
+//! `keep` path, dotted from `parameters` with `*` matching every member or
+//! item, are kept whole, for the fields policies match on:
+//!
+//! ```toml
+//! [parameters]
+//! max_string_bytes = 65536
+//! keep = ["path", "query", "files.*.name"]
+//! ```
+//!
+//! This covers `/gateway/authorize`, its batch form and
+//! `/gateway/authorize/a2a`. A policy that builds `filtered_parameters`
+//! from `input.parameters` returns the summaries too, so callers applying
+//! them should keep their own copy of long fields. The MCP proxy forwards
+//! the bodies it parses, so it doesn't scan them.
+
+use crate::config::ParametersConfig;
+use crate::problem::{JsonBody, Problem};
+use crate::AppState;
+use axum::{
+    body::Bytes,
+    extract::{
+        rejection::{JsonRejection, MissingJsonContentType},
+        FromRequest, Request,
+    },
+    http::{header::CONTENT_TYPE, HeaderMap},
+    Json,
+};
+use serde::de::{self, DeserializeOwned, DeserializeSeed, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+use serde_json::{json, Map, Value};
+use std::cell::RefCell;
+use std::fmt;
+use std::sync::Arc;
+
+thread_local! {
+    /// The scanner of the body being parsed on this thread, if any
+    static ACTIVE: RefCell<Option<Arc<Scanner>>> = const { RefCell::new(None) };
+}
+
+/// Summarizes long strings in parameters
+pub struct Scanner {
+    max_string_bytes: usize,
+    prefix_bytes: usize,
+    keep: Vec<Vec<String>>,
+}
+
+impl Scanner {
+    /// The scanner `config` describes, if it sets a limit
+    pub fn new(config: &ParametersConfig) -> Option<Self> {
+        (config.max_string_bytes > 0).then(|| Self {
+            max_string_bytes: config.max_string_bytes,
+            prefix_bytes: config.prefix_bytes,
+            keep: config
+                .keep
+                .iter()
+                .map(|path| path.split('.').map(str::to_string).collect())
+                .collect(),
+        })
+    }
+
+    /// `value`, found at `path` under `parameters`, as the policy sees it
+    fn string(&self, value: &str, path: &[String]) -> Value {
+        if value.len() <= self.max_string_bytes || self.kept(path) {
+            return Value::from(value);
+        }
+        let mut end = self.prefix_bytes.min(value.len());
+        while !value.is_char_boundary(end) {
+            end -= 1;
+        }
+        json!({"$summary": {
+            "bytes": value.len(),
+            "lines": value.lines().count(),
+            "entropy": entropy(value.as_bytes()),
+            "prefix": &value[..end],
+        }})
+    }
+
+    /// Whether `path` is, or is under, a `keep` path
+    fn kept(&self, path: &[String]) -> bool {
+        self.keep.iter().any(|keep| {
+            keep.len() <= path.len()
+                && keep
+                    .iter()
+                    .zip(path)
+                    .all(|(segment, field)| segment == "*" || segment == field)
+        })
+    }
+}
+
+/// Shannon entropy of `bytes` in bits per byte, to two decimals
+pub fn entropy(bytes: &[u8]) -> f64 {
+    let mut counts = [0u64; 256];
+    for &byte in bytes {
+        counts[byte as usize] += 1;
+    }
+    let total = bytes.len() as f64;
+    let bits: f64 = counts
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / total;
+            -p * p.log2()
+        })
+        .sum();
+    (bits * 100.0).round() / 100.0
+}
+
+/// Deserialize `parameters`, scanned if a scanner is active on this thread
+pub fn parameters<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Value>, D::Error> {
+    let Some(scanner) = ACTIVE.with(|active| active.borrow().clone()) else {
+        return Option::<Value>::deserialize(deserializer);
+    };
+    let value = Scan {
+        scanner: &scanner,
+        path: &mut Vec::new(),
+    }
+    .deserialize(deserializer)?;
+    Ok(Some(value).filter(|value| !value.is_null()))
+}
+
+/// Builds a value as it is parsed, summarizing long strings
+struct Scan<'a> {
+    scanner: &'a Scanner,
+    /// Where the value is under `parameters`
+    path: &'a mut Vec<String>,
+}
+
+impl<'de> DeserializeSeed<'de> for Scan<'_> {
+    type Value = Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for Scan<'_> {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a JSON value")
+    }
+
+    fn visit_bool<E: de::Error>(self, value: bool) -> Result<Value, E> {
+        Ok(Value::Bool(value))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Value, E> {
+        Ok(Value::from(value))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Value, E> {
+        Ok(Value::from(value))
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<Value, E> {
+        Ok(Value::from(value))
+    }
+
+    /// Borrowed strings included; only summaries are copied
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Value, E> {
+        Ok(self.scanner.string(value, self.path))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        self.deserialize(deserializer)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let Scan { scanner, path } = self;
+        let mut items = Vec::new();
+        loop {
+            path.push(items.len().to_string());
+            let item = seq.next_element_seed(Scan {
+                scanner,
+                path: &mut *path,
+            });
+            path.pop();
+            match item? {
+                Some(item) => items.push(item),
+                None => return Ok(Value::Array(items)),
+            }
+        }
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let Scan { scanner, path } = self;
+        let mut object = Map::new();
+        while let Some(key) = map.next_key::<String>()? {
+            path.push(key);
+            let value = map.next_value_seed(Scan {
+                scanner,
+                path: &mut *path,
+            });
+            let key = path.pop().expect("key was pushed");
+            object.insert(key, value?);
+        }
+        Ok(Value::Object(object))
+    }
+}
+
+/// Marks a scanner active on this thread until dropped
+struct Active;
+
+impl Active {
+    fn set(scanner: Arc<Scanner>) -> Self {
+        ACTIVE.with(|active| *active.borrow_mut() = Some(scanner));
+        Active
+    }
+}
+
+impl Drop for Active {
+    fn drop(&mut self) {
+        ACTIVE.with(|active| active.borrow_mut().take());
+    }
+}
+
+/// A JSON request body with its `parameters` scanned as they are parsed,
+/// rejected as a problem when it isn't one
+pub struct ScannedJson<T>(pub T);
+
+#[axum::async_trait]
+impl<T: DeserializeOwned> FromRequest<AppState> for ScannedJson<T> {
+    type Rejection = Problem;
+
+    async fn from_request(request: Request, state: &AppState) -> Result<Self, Problem> {
+        let Some(scanner) = state.scanner.clone() else {
+            let JsonBody(value) = JsonBody::<T>::from_request(request, state).await?;
+            return Ok(Self(value));
+        };
+        if !json_content_type(request.headers()) {
+            return Err(JsonRejection::from(MissingJsonContentType::default()).into());
+        }
+        let body = Bytes::from_request(request, state)
+            .await
+            .map_err(JsonRejection::from)?;
+
+        // Parsing doesn't yield, so the scanner stays this body's
+        let _active = Active::set(scanner);
+        let Json(value) = Json::<T>::from_bytes(&body)?;
+        Ok(Self(value))
+    }
+}
+
+/// Whether the request says its body is JSON, as `Json` requires
+fn json_content_type(headers: &HeaderMap) -> bool {
+    let Some(content_type) = headers.get(CONTENT_TYPE).and_then(|v| v.to_str().ok()) else {
+        return false;
+    };
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    essence == "application/json"
+        || (essence.starts_with("application/") && essence.ends_with("+json"))
+}
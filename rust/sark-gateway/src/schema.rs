@@ -0,0 +1,112 @@
+//! Policy input and result validation
+//!
+//! With `--input-schema`, every policy input document is checked against a
+//! JSON Schema before evaluation. A malformed request gets a 400 naming the
+//! offending fields instead of falling through to rego, where missing
+//! fields just make rules undefined and produce a default deny with no
+//! useful reason.
+//!
+//! With `--result-schema`, every document a policy returns is checked too,
+//! so a policy bug (`allow` a string, `reason` an object, a misspelled
+//! rule leaving `allow` undefined) shows up instead of being read as a
+//! deny. For example:
+//!
+//! ```json
+//! {
+//!   "type": "object",
+//!   "required": ["allow"],
+//!   "properties": {
+//!     "allow": { "type": "boolean" },
+//!     "reason": { "type": "string" },
+//!     "filtered_parameters": { "type": "object" }
+//!   }
+//! }
+//! ```
+//!
+//! A result that fails it is counted in
+//! `sark_gateway_malformed_results_total` and, per `--malformed-result`,
+//! either becomes an uncached deny whose reason names the violations
+//! (`deny`, the default) or fails the request with a `malformed-result`
+//! problem (`error`), which the fallback doesn't settle.
+
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use sark_policy::Schema;
+use serde_json::{json, Value};
+use std::path::Path;
+
+/// A compiled JSON Schema for policy input
+pub struct InputSchema {
+    schema: Schema,
+}
+
+impl InputSchema {
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(Self {
+            schema: compile(path, "input")?,
+        })
+    }
+
+    /// Check `input`, describing each violation as `<path>: <problem>`
+    pub fn check(&self, input: &Value) -> Result<(), String> {
+        match self.schema.violations(input) {
+            Some(violations) => Err(format!("Malformed input: {}", violations)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// What a result failing its schema turns into
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum MalformedResult {
+    /// A deny, not cached
+    #[default]
+    Deny,
+    /// A `malformed-result` problem
+    Error,
+}
+
+/// A compiled JSON Schema for policy results
+pub struct ResultSchema {
+    schema: Schema,
+    on_malformed: MalformedResult,
+}
+
+impl ResultSchema {
+    pub fn load(path: &Path, on_malformed: MalformedResult) -> Result<Self> {
+        Ok(Self {
+            schema: compile(path, "result")?,
+            on_malformed,
+        })
+    }
+
+    /// Check `document`, describing each violation as `<path>: <problem>`
+    pub fn check(&self, document: &Value) -> Result<(), String> {
+        match self.schema.violations(document) {
+            Some(violations) => Err(format!("Malformed policy result: {}", violations)),
+            None => Ok(()),
+        }
+    }
+
+    /// The document to decide by in place of a malformed result described
+    /// by `message`, or `None` to fail with it
+    pub fn replacement(&self, message: &str) -> Option<Value> {
+        match self.on_malformed {
+            MalformedResult::Deny => Some(json!({
+                "allow": false,
+                "reason": message,
+                "cache_ttl": 0,
+            })),
+            MalformedResult::Error => None,
+        }
+    }
+}
+
+fn compile(path: &Path, what: &str) -> Result<Schema> {
+    let raw = std::fs::read(path)
+        .with_context(|| format!("Failed to read {} schema {}", what, path.display()))?;
+    let document: Value = serde_json::from_slice(&raw)
+        .with_context(|| format!("Failed to parse {} schema {}", what, path.display()))?;
+    Schema::compile(&document)
+        .map_err(|e| anyhow!("Invalid {} schema {}: {}", what, path.display(), e))
+}
@@ -0,0 +1,109 @@
+//! Shadow (dry-run) policy evaluation
+//!
+//! With `--shadow-policy-dir`, a candidate policy set is compiled alongside
+//! the active one. Every authorization is also evaluated against the
+//! candidate in the background; the caller always gets the active
+//! decision, and any disagreement (allow, filtered parameters,
+//! obligations) is logged and counted. Once the candidate has run clean it
+//! can be promoted through the admin API without a restart.
+
+use crate::policy::ActivePolicy;
+use crate::GatewayAuthResponse;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::Mutex;
+use tracing::{debug, warn};
+
+/// Candidate policy and its comparison counters
+pub struct Shadow {
+    /// Taken out when promoted or discarded
+    candidate: Mutex<Option<ActivePolicy>>,
+    evaluations: AtomicU64,
+    mismatches: AtomicU64,
+    errors: AtomicU64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ShadowStats {
+    pub revision: Option<String>,
+    pub evaluations: u64,
+    pub mismatches: u64,
+    pub errors: u64,
+}
+
+impl Shadow {
+    pub fn new(candidate: ActivePolicy) -> Self {
+        Self {
+            candidate: Mutex::new(Some(candidate)),
+            evaluations: AtomicU64::new(0),
+            mismatches: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+        }
+    }
+
+    /// Evaluate `input` against the candidate and compare with what the
+    /// active policy decided
+    pub async fn compare(
+        &self,
+        query: &str,
+        input: &serde_json::Value,
+        active: &GatewayAuthResponse,
+    ) {
+        let mut candidate = self.candidate.lock().await;
+        let Some(candidate) = candidate.as_mut() else {
+            return;
+        };
+
+        let result = candidate.evaluate(query, input);
+        self.evaluations.fetch_add(1, Ordering::Relaxed);
+
+        let document = match result {
+            Ok(document) => document,
+            Err(e) => {
+                self.errors.fetch_add(1, Ordering::Relaxed);
+                warn!(revision = %candidate.revision(), error = %format!("{:#}", e), "Shadow evaluation failed");
+                return;
+            }
+        };
+
+        let allow = matches!(document.get("allow"), Some(serde_json::Value::Bool(true)));
+        let filtered_parameters = document.get("filtered_parameters");
+        let obligations = crate::obligations::Obligations::from_document(&document);
+
+        if allow != active.allow
+            || filtered_parameters != active.filtered_parameters.as_ref()
+            || obligations.as_ref() != Ok(&active.obligations)
+        {
+            self.mismatches.fetch_add(1, Ordering::Relaxed);
+            warn!(
+                active_revision = %active.policy_revision,
+                shadow_revision = %candidate.revision(),
+                active_allow = active.allow,
+                shadow_allow = allow,
+                input_hash = %crate::decision_log::input_digest(input),
+                "Shadow policy disagrees with active policy"
+            );
+        } else {
+            debug!(shadow_revision = %candidate.revision(), "Shadow policy agrees");
+        }
+    }
+
+    /// Remove the candidate (to promote or discard it)
+    pub async fn take(&self) -> Option<ActivePolicy> {
+        self.candidate.lock().await.take()
+    }
+
+    pub async fn stats(&self) -> ShadowStats {
+        ShadowStats {
+            revision: self
+                .candidate
+                .lock()
+                .await
+                .as_ref()
+                .map(|c| c.revision().to_string()),
+            evaluations: self.evaluations.load(Ordering::Relaxed),
+            mismatches: self.mismatches.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -0,0 +1,224 @@
+//! Signed decisions
+//!
+//! MCP servers enforcing decisions themselves need to know a decision came
+//! from the gateway unaltered. With a signing key configured, responses to
+//! `/gateway/authorize`, `/gateway/authorize/batch` and
+//! `/gateway/authorize-a2a` carry a detached JWS (RFC 7515, appendix F) of
+//! their body in `X-Sark-Signature`:
+//!
+//! ```toml
+//! [signing]
+//! key = "/etc/sark/signing-key.pem"
+//! algorithm = "ES256"
+//! ```
+//!
+//! The header is `<protected header>..<signature>`; to verify it, put the
+//! base64url encoding of the exact response body between the dots and
+//! check the result as a compact JWS. The protected header carries `alg`,
+//! `kid`, `iat` and the `request_id` the decision answers, so a signed
+//! decision can't be passed off as the answer to another request. The
+//! public key is served as a JWK set at `/.well-known/jwks.json`.
+//!
+//! Only successful responses are signed; problems, gRPC, Envoy and proxied
+//! responses are not. The key also signs audit log checkpoints (see
+//! `audit`). It is read at startup.
+
+use crate::config::SigningConfig;
+use crate::problem::Problem;
+use anyhow::{bail, Context, Result};
+use axum::{
+    body::Body,
+    extract::{MatchedPath, Request, State},
+    http::HeaderValue,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use jsonwebtoken::jwk::{Jwk, JwkSet, PublicKeyUse, ThumbprintHash};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey};
+use serde_json::{json, Value};
+use std::path::Path;
+use std::sync::Arc;
+use tracing::{error, info};
+
+/// Header carrying a response's signature
+pub const HEADER: &str = "x-sark-signature";
+
+/// Route serving the public key
+pub const JWKS_ROUTE: &str = "/.well-known/jwks.json";
+
+/// Routes whose responses are signed
+const SIGNED_ROUTES: &[&str] = &[
+    crate::Endpoint::Authorize.route(),
+    crate::BATCH_ROUTE,
+    crate::Endpoint::AuthorizeA2a.route(),
+];
+
+/// Signed responses are decisions, well under this
+const MAX_SIGNED_BYTES: usize = 64 * 1024 * 1024;
+
+pub struct Signer {
+    key: EncodingKey,
+    algorithm: Algorithm,
+    key_id: String,
+    /// The public key, as served
+    jwks: JwkSet,
+}
+
+impl Signer {
+    /// The signer `config` describes, if it names a key
+    pub fn load(config: &SigningConfig) -> Result<Option<Self>> {
+        let Some(path) = &config.key else {
+            return Ok(None);
+        };
+        let pem = std::fs::read(path)
+            .with_context(|| format!("Failed to read signing key {}", path.display()))?;
+        let key = match config.algorithm {
+            Algorithm::ES256 | Algorithm::ES384 => EncodingKey::from_ec_pem(&pem),
+            _ => EncodingKey::from_rsa_pem(&pem),
+        }
+        .with_context(|| format!("Invalid {:?} signing key", config.algorithm))?;
+
+        let mut jwk = Jwk::from_encoding_key(&key, config.algorithm)
+            .context("Failed to derive the signing key's public key")?;
+        let key_id = config
+            .key_id
+            .clone()
+            .unwrap_or_else(|| jwk.thumbprint(ThumbprintHash::SHA256));
+        jwk.common.key_id = Some(key_id.clone());
+        jwk.common.public_key_use = Some(PublicKeyUse::Signature);
+        info!(algorithm = ?config.algorithm, key_id = %key_id, "Signing decisions");
+        Ok(Some(Self {
+            key,
+            algorithm: config.algorithm,
+            key_id,
+            jwks: JwkSet { keys: vec![jwk] },
+        }))
+    }
+
+    pub fn jwks(&self) -> &JwkSet {
+        &self.jwks
+    }
+
+    /// Detached JWS of `body`, answering `request_id`
+    fn sign(&self, body: &[u8], request_id: Option<&str>) -> Result<String> {
+        self.detached(body, json!({ "request_id": request_id }))
+    }
+
+    /// Detached JWS of `body`, with the members of `claims` added to its
+    /// protected header
+    pub fn detached(&self, body: &[u8], claims: Value) -> Result<String> {
+        let mut header = json!({
+            "alg": self.algorithm,
+            "kid": self.key_id,
+            "iat": chrono::Utc::now().timestamp(),
+        });
+        if let (Some(header), Value::Object(claims)) = (header.as_object_mut(), claims) {
+            header.extend(claims);
+        }
+        let header = URL_SAFE_NO_PAD.encode(header.to_string());
+        let input = format!("{}.{}", header, URL_SAFE_NO_PAD.encode(body));
+        let signature = jsonwebtoken::crypto::sign(input.as_bytes(), &self.key, self.algorithm)
+            .context("Signing failed")?;
+        Ok(format!("{}..{}", header, signature))
+    }
+}
+
+/// Checks detached signatures against a public key: a PEM file, or a JWK
+/// set (as served at `/.well-known/jwks.json`) whose key is picked by `kid`
+pub struct Verifier {
+    keys: VerifyingKeys,
+}
+
+enum VerifyingKeys {
+    Pem(Vec<u8>),
+    Jwks(JwkSet),
+}
+
+impl Verifier {
+    pub fn load(path: &Path) -> Result<Self> {
+        let bytes = std::fs::read(path)
+            .with_context(|| format!("Failed to read public key {}", path.display()))?;
+        let keys = match serde_json::from_slice::<JwkSet>(&bytes) {
+            Ok(jwks) => VerifyingKeys::Jwks(jwks),
+            Err(_) => VerifyingKeys::Pem(bytes),
+        };
+        Ok(Self { keys })
+    }
+
+    /// Check `jws`, a detached JWS as `Signer::detached` makes, against
+    /// `body`. Returns its protected header.
+    pub fn verify(&self, jws: &str, body: &[u8]) -> Result<Value> {
+        let Some((encoded, signature)) = jws.split_once("..") else {
+            bail!("Not a detached JWS");
+        };
+        let header: Value = serde_json::from_slice(
+            &URL_SAFE_NO_PAD
+                .decode(encoded)
+                .context("Invalid JWS header encoding")?,
+        )
+        .context("Invalid JWS header")?;
+        let algorithm: Algorithm =
+            serde_json::from_value(header["alg"].clone()).context("Unsupported JWS algorithm")?;
+        let key = match &self.keys {
+            VerifyingKeys::Pem(pem) => match algorithm {
+                Algorithm::ES256 | Algorithm::ES384 => DecodingKey::from_ec_pem(pem),
+                _ => DecodingKey::from_rsa_pem(pem),
+            }
+            .context("Invalid public key")?,
+            VerifyingKeys::Jwks(jwks) => {
+                let kid = header["kid"].as_str().unwrap_or_default();
+                let jwk = jwks
+                    .find(kid)
+                    .with_context(|| format!("No key {:?} in the key set", kid))?;
+                DecodingKey::from_jwk(jwk).context("Invalid public key")?
+            }
+        };
+        let input = format!("{}.{}", encoded, URL_SAFE_NO_PAD.encode(body));
+        if !jsonwebtoken::crypto::verify(signature, input.as_bytes(), &key, algorithm)
+            .context("Verification failed")?
+        {
+            bail!("Signature does not match");
+        }
+        Ok(header)
+    }
+}
+
+/// Middleware signing successful responses of the decision routes
+pub async fn sign(State(signer): State<Arc<Signer>>, request: Request, next: Next) -> Response {
+    let signed = request
+        .extensions()
+        .get::<MatchedPath>()
+        .is_some_and(|route| SIGNED_ROUTES.contains(&route.as_str()));
+    if !signed {
+        return next.run(request).await;
+    }
+    let request_id = request
+        .headers()
+        .get(crate::REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+
+    let response = next.run(request).await;
+    if !response.status().is_success() {
+        return response;
+    }
+    let (mut parts, body) = response.into_parts();
+    let signature = match axum::body::to_bytes(body, MAX_SIGNED_BYTES).await {
+        Ok(bytes) => signer
+            .sign(&bytes, request_id.as_deref())
+            .and_then(|signature| Ok((HeaderValue::try_from(signature)?, bytes))),
+        Err(e) => Err(anyhow::Error::new(e).context("Failed to read response body")),
+    };
+    match signature {
+        Ok((signature, bytes)) => {
+            parts.headers.insert(HEADER, signature);
+            Response::from_parts(parts, Body::from(bytes))
+        }
+        Err(e) => {
+            error!(error = %format!("{:#}", e), "Failed to sign decision");
+            Problem::Internal("Failed to sign decision".to_string()).into_response()
+        }
+    }
+}
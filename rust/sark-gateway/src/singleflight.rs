@@ -0,0 +1,63 @@
+//! Request coalescing for the authorization hot path
+//!
+//! When a popular cache key expires, every concurrent request for it misses
+//! at the same moment and would otherwise evaluate the same policy input in
+//! parallel. `SingleFlight` lets the first caller compute the value while the
+//! others wait for and share its result.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use tokio::sync::OnceCell;
+
+/// Coalesces concurrent computations that share a key
+pub struct SingleFlight<T> {
+    inflight: Mutex<HashMap<String, Arc<OnceCell<T>>>>,
+}
+
+impl<T: Clone> SingleFlight<T> {
+    pub fn new() -> Self {
+        Self {
+            inflight: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Run `compute` for `key`, or wait for an identical in-flight call
+    ///
+    /// If the caller driving the computation is cancelled, one of the
+    /// waiting callers takes over, so a dropped connection never strands
+    /// the others.
+    pub async fn run<F, Fut>(&self, key: &str, compute: F) -> T
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        let cell = {
+            let mut inflight = self.inflight.lock().expect("singleflight lock poisoned");
+            inflight
+                .entry(key.to_string())
+                .or_insert_with(|| Arc::new(OnceCell::new()))
+                .clone()
+        };
+
+        let value = cell.get_or_init(compute).await.clone();
+
+        // Only the flight we joined may be removed; a newer one may already
+        // have been registered under the same key.
+        let mut inflight = self.inflight.lock().expect("singleflight lock poisoned");
+        if inflight
+            .get(key)
+            .is_some_and(|current| Arc::ptr_eq(current, &cell))
+        {
+            inflight.remove(key);
+        }
+
+        value
+    }
+}
+
+impl<T: Clone> Default for SingleFlight<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
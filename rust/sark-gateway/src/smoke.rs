@@ -0,0 +1,227 @@
+//! Policy smoke tests
+//!
+//! A bundle that denies everything (a renamed package, data left out of
+//! the build) compiles and activates like a good one. `--startup-checks
+//! <dir>` names a directory of canned inputs and what the policies must
+//! decide for them:
+//!
+//! ```json
+//! [
+//!     {"name": "admins may invoke tools",
+//!      "input": {"user": {"roles": ["admin"]}, "action": "tool:invoke",
+//!                "tool": {"name": "query_db"}},
+//!      "expect": {"allow": true}},
+//!     {"name": "unknown tools are denied", "endpoint": "authorize",
+//!      "input": {"user": {"roles": ["dev"]}, "action": "tool:invoke",
+//!                "tool": {"name": "rm_rf"}},
+//!      "expect": {"allow": false, "reason_code": "SARK-POL-017"}}
+//! ]
+//! ```
+//!
+//! Each `.json` file in the directory holds one check or an array of them.
+//! `input` is the policy input as built for the request (as `POST
+//! /admin/cache/warm` takes it), evaluated against the package a request
+//! to `endpoint` (`authorize`, the default, or `a2a`) would be, per
+//! `[queries]`, unless `query` names another. Each member of `expect` must
+//! equal the evaluated document's; `allow` counts as `false` where the
+//! policy leaves it undefined.
+//!
+//! The checks run against the policies loaded at startup, and until they
+//! pass `/readyz` reports the gateway not ready and no `READY=1` is sent
+//! to systemd, so no traffic reaches a broken policy. They run again
+//! before every policy set [`activate`] compiles (config reloads,
+//! directory changes, new bundles): a set that fails them is refused and
+//! the current policy stays active, and one that passes them makes a
+//! gateway that failed at startup ready. Revisions activated from the
+//! history, rollbacks and data updates aren't checked, nor are tenants'
+//! policies. The directory is read at startup only.
+//!
+//! [`activate`]: crate::policy::activate
+
+use crate::config::QueriesConfig;
+use crate::policy::ActivePolicy;
+use crate::Endpoint;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use tokio::sync::watch;
+use tracing::{error, info};
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Fixtures {
+    One(Fixture),
+    Many(Vec<Fixture>),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Fixture {
+    name: Option<String>,
+    endpoint: Option<Endpoint>,
+    query: Option<String>,
+    input: Value,
+    expect: Map<String, Value>,
+}
+
+/// A fixture, its query resolved
+struct Check {
+    name: String,
+    query: String,
+    input: Value,
+    expect: Map<String, Value>,
+}
+
+/// The checks of `--startup-checks`, and how the active policy fared
+pub struct StartupChecks {
+    dir: PathBuf,
+    checks: Vec<Check>,
+    /// Failures of the last policy checked while none has passed; empty
+    /// once one has
+    status: RwLock<Status>,
+    /// Whether a policy has passed, for [`StartupChecks::passed`]
+    passing: watch::Sender<bool>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Status {
+    pub passed: bool,
+    pub failures: Vec<String>,
+}
+
+impl StartupChecks {
+    /// Read the checks in `dir`, their queries resolved with `queries`
+    pub fn load(dir: &Path, queries: &QueriesConfig) -> Result<Self> {
+        let mut files: Vec<PathBuf> = std::fs::read_dir(dir)
+            .with_context(|| format!("Failed to read startup checks {}", dir.display()))?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<std::io::Result<_>>()
+            .with_context(|| format!("Failed to read startup checks {}", dir.display()))?;
+        files.retain(|path| path.extension().is_some_and(|ext| ext == "json"));
+        files.sort();
+
+        let mut checks = Vec::new();
+        for path in files {
+            let text = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            let fixtures = match serde_json::from_str(&text)
+                .with_context(|| format!("Invalid startup checks in {}", path.display()))?
+            {
+                Fixtures::One(fixture) => vec![fixture],
+                Fixtures::Many(fixtures) => fixtures,
+            };
+            let file = path.file_name().unwrap_or_default().to_string_lossy();
+            for (index, fixture) in fixtures.into_iter().enumerate() {
+                let query = match fixture.query {
+                    Some(query) => query,
+                    None => fixture
+                        .endpoint
+                        .unwrap_or(Endpoint::Authorize)
+                        .query(queries, &fixture.input)
+                        .to_string(),
+                };
+                checks.push(Check {
+                    name: fixture
+                        .name
+                        .unwrap_or_else(|| format!("{}[{}]", file, index)),
+                    query,
+                    input: fixture.input,
+                    expect: fixture.expect,
+                });
+            }
+        }
+        if checks.is_empty() {
+            bail!("No startup checks in {}", dir.display());
+        }
+        info!(dir = %dir.display(), checks = checks.len(), "Loaded startup checks");
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            checks,
+            status: RwLock::new(Status::default()),
+            passing: watch::Sender::new(false),
+        })
+    }
+
+    pub fn count(&self) -> usize {
+        self.checks.len()
+    }
+
+    /// Return once some policy has passed the checks
+    pub async fn passed(&self) {
+        let mut passing = self.passing.subscribe();
+        // The sender lives as long as `self`, so this can't fail
+        let _ = passing.wait_for(|passed| *passed).await;
+    }
+
+    pub fn status(&self) -> Status {
+        self.status
+            .read()
+            .expect("startup checks lock poisoned")
+            .clone()
+    }
+
+    /// Run the checks against `policy`, failing with what they found
+    /// wrong; until some policy passes, the last failures are kept for
+    /// `/readyz`
+    pub fn verify(&self, policy: &ActivePolicy) -> Result<()> {
+        let failures: Vec<String> = self
+            .checks
+            .iter()
+            .filter_map(|check| check.run(policy).err())
+            .collect();
+        let mut status = self.status.write().expect("startup checks lock poisoned");
+        if failures.is_empty() {
+            if !status.passed {
+                info!(
+                    revision = %policy.revision(),
+                    checks = self.checks.len(),
+                    "Policy passed startup checks"
+                );
+            }
+            *status = Status {
+                passed: true,
+                failures: Vec::new(),
+            };
+            self.passing.send_replace(true);
+            return Ok(());
+        }
+
+        for failure in &failures {
+            error!(revision = %policy.revision(), failure = %failure, "Startup check failed");
+        }
+        if !status.passed {
+            status.failures = failures.clone();
+        }
+        bail!(
+            "{} of {} startup checks in {} failed: {}",
+            failures.len(),
+            self.checks.len(),
+            self.dir.display(),
+            failures.join("; ")
+        )
+    }
+}
+
+impl Check {
+    /// What's wrong with `policy`'s decision, if anything
+    fn run(&self, policy: &ActivePolicy) -> Result<(), String> {
+        let document = policy
+            .evaluate(&self.query, &self.input)
+            .map_err(|e| format!("{}: {:#}", self.name, e))?;
+        for (field, expected) in &self.expect {
+            let actual = match field.as_str() {
+                "allow" => Value::Bool(document.get("allow") == Some(&Value::Bool(true))),
+                _ => document.get(field).cloned().unwrap_or(Value::Null),
+            };
+            if actual != *expected {
+                return Err(format!(
+                    "{}: expected {} = {}, got {}",
+                    self.name, field, expected, actual
+                ));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,377 @@
+//! Replica state export and import
+//!
+//! A new replica can start from a peer's state instead of from nothing:
+//! `GET /admin/state/export` on the peer's admin port streams what it has,
+//! and `POST /admin/state/import` on the new one takes the same stream,
+//! so the two can be piped together:
+//!
+//! ```text
+//! curl -sfH "Authorization: Bearer $TOKEN" http://peer:8081/admin/state/export \
+//!   | curl -sfH "Authorization: Bearer $TOKEN" -X POST -T - http://new:8081/admin/state/import
+//! ```
+//!
+//! The snapshot is newline-delimited JSON, one `kind` per line: the
+//! `policy` line (the active revision, when it was activated, its module
+//! names and when the snapshot was taken) first, then the `data` line (the
+//! whole data document), then a `cache` line for each cached decision
+//! (`namespace`, `key`, `value` and the seconds it has left as `ttl`).
+//!
+//! Import applies the lines as they arrive. A data document other than the
+//! importer's own replaces it, as `PUT /admin/data/` would. Policy modules
+//! aren't carried over; both replicas should load the same directory or
+//! bundle. Cached decisions are kept only if made by the policy revision
+//! active on the importer once the data is in place, and are cached for the
+//! time they have left, less the time since the snapshot was taken, up to
+//! `cache.max_ttl`; the others are counted as skipped. A malformed line
+//! stops the import with 400, keeping what came before it.
+//!
+//! Only decisions cached in process are exported, and only with the admin
+//! API or `--cache-snapshot` enabled (the key of every entry set is kept
+//! for listing, up to `cache.max_entries`); replicas sharing a Redis cache
+//! already share their decisions. Tenants' policies and caches are not
+//! part of the snapshot.
+//!
+//! With `--cache-snapshot <path>`, the gateway also writes its cached
+//! decisions to `path` once connections have drained on shutdown (the
+//! policy line and the `cache` lines; the data document comes from the
+//! config), and imports the file on boot, before serving. Decisions the
+//! restarted gateway's policy wouldn't make, and those that expired while
+//! it was down, are dropped as on import.
+
+use crate::cache::CachedDecision;
+use crate::policy::PolicyStore;
+use crate::problem::Problem;
+use crate::{AppState, GatewayAuthResponse};
+use anyhow::{Context, Result};
+use axum::{
+    body::Body,
+    extract::State,
+    http::header::CONTENT_TYPE,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use futures::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::convert::Infallible;
+use std::io::Write;
+use std::path::Path;
+use tracing::info;
+
+/// Longest snapshot line `import` accepts (the data document is one line)
+const MAX_LINE: usize = 64 * 1024 * 1024;
+
+/// One line of a snapshot
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase", deny_unknown_fields)]
+enum Line {
+    Policy {
+        revision: String,
+        activated_at: DateTime<Utc>,
+        modules: Vec<String>,
+        /// Absent from snapshots of gateways that didn't record it
+        #[serde(default)]
+        exported_at: Option<DateTime<Utc>>,
+    },
+    Data {
+        data: Map<String, Value>,
+    },
+    Cache {
+        namespace: String,
+        key: String,
+        value: String,
+        ttl: u64,
+    },
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ImportReport {
+    /// Policy revision the peer had active
+    revision: String,
+    /// Policy revision active here after the import
+    active_revision: String,
+    /// Whether the peer's data document replaced this replica's
+    data_replaced: bool,
+    /// Decisions cached
+    cached: usize,
+    /// Decisions left out, as made by another revision or for no
+    /// namespace here
+    skipped: usize,
+}
+
+/// `GET /admin/state/export`: stream this replica's policy revision, data
+/// document and cached decisions
+pub async fn export(State(state): State<AppState>) -> Response {
+    let (policy, data) = {
+        let store = state.policy.lock().await;
+        (
+            policy_line(&store),
+            Line::Data {
+                data: store.active().set.data().clone(),
+            },
+        )
+    };
+    if let Line::Policy { revision, .. } = &policy {
+        info!(revision = %revision, "Exporting gateway state");
+    }
+
+    // Values are read as the stream reaches them, so entries that expire
+    // or are dropped meanwhile are left out
+    let lines = stream::iter([policy, data])
+        .chain(stream::iter(cache_lines(&state)))
+        .map(|line| Ok::<_, Infallible>(encode(&line)));
+    (
+        [(CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(lines),
+    )
+        .into_response()
+}
+
+/// Write the policy line and cached decisions to `path`
+/// (`--cache-snapshot`), through a temporary file renamed over it, so a
+/// crash while writing leaves the last snapshot in place
+pub async fn save(state: &AppState, path: &Path) -> Result<()> {
+    let policy = policy_line(&*state.policy.lock().await);
+    let tmp = path.with_extension("tmp");
+    let mut file = std::io::BufWriter::new(
+        std::fs::File::create(&tmp)
+            .with_context(|| format!("Failed to create cache snapshot {}", tmp.display()))?,
+    );
+    file.write_all(&encode(&policy))?;
+    let mut cached = 0;
+    for line in cache_lines(state) {
+        file.write_all(&encode(&line))?;
+        cached += 1;
+    }
+    file.into_inner()
+        .map_err(|e| e.into_error())?
+        .sync_all()
+        .with_context(|| format!("Failed to write cache snapshot {}", tmp.display()))?;
+    std::fs::rename(&tmp, path)
+        .with_context(|| format!("Failed to replace cache snapshot {}", path.display()))?;
+    info!(path = %path.display(), cached = cached, "Saved cache snapshot");
+    Ok(())
+}
+
+/// Import the snapshot `save` wrote to `path`, if there is one
+pub async fn load(state: &AppState, path: &Path) -> Result<()> {
+    let text = match std::fs::read(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            info!(path = %path.display(), "No cache snapshot to load");
+            return Ok(());
+        }
+        Err(e) => {
+            return Err(e)
+                .with_context(|| format!("Failed to read cache snapshot {}", path.display()))
+        }
+    };
+    let mut import = Import::new(state);
+    for line in text.split(|&b| b == b'\n') {
+        import
+            .line(line)
+            .await
+            .with_context(|| format!("Invalid cache snapshot {}", path.display()))?;
+    }
+    info!(
+        path = %path.display(),
+        revision = %import.report.revision,
+        cached = import.report.cached,
+        skipped = import.report.skipped,
+        "Loaded cache snapshot"
+    );
+    Ok(())
+}
+
+/// The active revision's policy line, stamped with the current time
+fn policy_line(store: &PolicyStore) -> Line {
+    let active = store.active();
+    Line::Policy {
+        revision: active.revision().to_string(),
+        activated_at: store.revisions()[0].activated_at,
+        modules: active
+            .set
+            .modules()
+            .map(|(name, _)| name.to_string())
+            .collect(),
+        exported_at: Some(Utc::now()),
+    }
+}
+
+/// A `cache` line for each journaled decision still cached, its value
+/// read as the iterator reaches it
+fn cache_lines(state: &AppState) -> impl Iterator<Item = Line> {
+    state.decision_caches().into_iter().flat_map(|namespace| {
+        namespace.keys().into_iter().filter_map(move |(key, ttl)| {
+            namespace.peek(&key).map(|value| Line::Cache {
+                namespace: namespace.name().to_string(),
+                key,
+                value,
+                ttl,
+            })
+        })
+    })
+}
+
+fn encode(line: &Line) -> Vec<u8> {
+    let mut bytes = serde_json::to_vec(line).expect("snapshot lines serialize");
+    bytes.push(b'\n');
+    bytes
+}
+
+/// `POST /admin/state/import`: take a peer's exported state
+pub async fn import(
+    State(state): State<AppState>,
+    body: Body,
+) -> Result<Json<ImportReport>, Problem> {
+    let mut import = Import::new(&state);
+    let mut chunks = body.into_data_stream();
+    let mut buf = Vec::new();
+    // Bytes of `buf` already searched for a newline
+    let mut scanned = 0;
+    while let Some(chunk) = chunks.next().await {
+        let chunk = chunk
+            .map_err(|_| Problem::InvalidRequest("Failed to read request body".to_string()))?;
+        buf.extend_from_slice(&chunk);
+        while let Some(end) = buf[scanned..].iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = buf.drain(..=scanned + end).collect();
+            scanned = 0;
+            import.line(&line).await?;
+        }
+        scanned = buf.len();
+        if buf.len() > MAX_LINE {
+            return Err(Problem::PayloadTooLarge(format!(
+                "Snapshot line {} is longer than {} bytes",
+                import.lines + 1,
+                MAX_LINE
+            )));
+        }
+    }
+    import.line(&buf).await?;
+    if import.report.revision.is_empty() {
+        return Err(Problem::InvalidRequest("Snapshot is empty".to_string()));
+    }
+
+    let mut report = import.report;
+    report.active_revision = state.policy.lock().await.active().revision().to_string();
+    info!(
+        revision = %report.revision,
+        active_revision = %report.active_revision,
+        data_replaced = report.data_replaced,
+        cached = report.cached,
+        skipped = report.skipped,
+        "Imported gateway state"
+    );
+    Ok(Json(report))
+}
+
+struct Import<'a> {
+    state: &'a AppState,
+    report: ImportReport,
+    /// Lines read so far
+    lines: usize,
+    /// Seconds between the snapshot being taken and its policy line read
+    elapsed: u64,
+}
+
+impl<'a> Import<'a> {
+    fn new(state: &'a AppState) -> Self {
+        Self {
+            state,
+            report: ImportReport::default(),
+            lines: 0,
+            elapsed: 0,
+        }
+    }
+
+    async fn line(&mut self, line: &[u8]) -> Result<(), Problem> {
+        self.lines += 1;
+        if line.iter().all(u8::is_ascii_whitespace) {
+            return Ok(());
+        }
+        let line: Line = serde_json::from_slice(line).map_err(|e| {
+            Problem::InvalidRequest(format!("Invalid snapshot line {}: {}", self.lines, e))
+        })?;
+
+        match line {
+            Line::Policy {
+                revision,
+                exported_at,
+                ..
+            } if self.report.revision.is_empty() => {
+                self.report.revision = revision;
+                if let Some(exported_at) = exported_at {
+                    self.elapsed = (Utc::now() - exported_at).num_seconds().max(0) as u64;
+                }
+                Ok(())
+            }
+            Line::Policy { .. } => Err(Problem::InvalidRequest(
+                "Snapshot has more than one policy line".to_string(),
+            )),
+            _ if self.report.revision.is_empty() => Err(Problem::InvalidRequest(
+                "Snapshot must start with its policy line".to_string(),
+            )),
+            Line::Data { data } => {
+                if *self.state.policy.lock().await.active().set.data() == data {
+                    return Ok(());
+                }
+                crate::admin::update_data(self.state, |set| set.set_data("", Value::Object(data)))
+                    .await?;
+                self.report.data_replaced = true;
+                Ok(())
+            }
+            Line::Cache {
+                namespace,
+                key,
+                value,
+                ttl,
+            } => {
+                if self.cache(&namespace, &key, value, ttl).await? {
+                    self.report.cached += 1;
+                } else {
+                    self.report.skipped += 1;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Cache a peer's decision, if it was made by the revision active here;
+    /// whether it was
+    async fn cache(
+        &self,
+        namespace: &str,
+        key: &str,
+        value: String,
+        ttl: u64,
+    ) -> Result<bool, Problem> {
+        let Some(cache) = self
+            .state
+            .decision_caches()
+            .into_iter()
+            .find(|cache| cache.name() == namespace)
+        else {
+            return Ok(false);
+        };
+        let decision: CachedDecision<GatewayAuthResponse> =
+            serde_json::from_str(&value).map_err(|e| {
+                Problem::InvalidRequest(format!(
+                    "Invalid cached decision on snapshot line {}: {}",
+                    self.lines, e
+                ))
+            })?;
+        if !self.state.revision.is(&decision.decision.policy_revision) {
+            return Ok(false);
+        }
+        let ttl = ttl.saturating_sub(self.elapsed).min(self.state.ttls().max);
+        if ttl == 0 {
+            return Ok(false);
+        }
+        cache
+            .set(key, value, ttl)
+            .await
+            .map_err(|e| Problem::Internal(format!("Failed to cache decision: {}", e)))?;
+        Ok(true)
+    }
+}
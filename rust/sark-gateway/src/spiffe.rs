@@ -0,0 +1,346 @@
+//! SPIFFE workload identity
+//!
+//! With a SPIFFE Workload API socket configured (the SPIRE agent's), the
+//! gateway serves TLS with its own X.509 SVID instead of `[tls]` files, and
+//! requires clients to present SVIDs of an accepted trust domain:
+//!
+//! ```toml
+//! [spiffe]
+//! socket = "unix:///run/spire/sockets/agent.sock"
+//! trust_domains = ["example.org", "partner.example"]
+//! agent_identity = true
+//! ```
+//!
+//! The SVID and trust bundles (the gateway's own trust domain's and
+//! federated ones) are streamed from the agent and rotated as it renews
+//! them; while the agent is unreachable the current ones keep serving. A
+//! client certificate is accepted if it chains to the bundle of the trust
+//! domain its SPIFFE ID names, which must be one of `trust_domains` (the
+//! gateway's own if empty). The client's SPIFFE ID is `input.client`'s
+//! `spiffe_id`, as with `tls.client_ca`.
+//!
+//! With `agent_identity`, which also works with `tls.client_ca`, A2A
+//! requests must come over mutual TLS and the source agent is the client:
+//! its `id` becomes the client's SPIFFE ID, and a request naming another
+//! source is refused with 403 rather than trusted on the bearer token's
+//! word.
+
+use crate::tls::{CertResolver, ClientIdentity, ALPN};
+use anyhow::{anyhow, bail, Context, Result};
+use hyper_util::rt::TokioIo;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::net::UnixStream;
+use tokio_rustls::rustls::client::danger::HandshakeSignatureValid;
+use tokio_rustls::rustls::crypto::{self, ring, CryptoProvider};
+use tokio_rustls::rustls::pki_types::{
+    CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, UnixTime,
+};
+use tokio_rustls::rustls::server::danger::{ClientCertVerified, ClientCertVerifier};
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::sign::CertifiedKey;
+use tokio_rustls::rustls::{
+    CertificateError, DigitallySignedStruct, DistinguishedName, Error, RootCertStore, ServerConfig,
+    SignatureScheme,
+};
+use tonic::transport::{Endpoint, Uri};
+use tonic::Streaming;
+use tracing::{error, info, warn};
+
+/// The SPIFFE Workload API client
+pub mod pb {
+    include!(concat!(env!("OUT_DIR"), "/_.rs"));
+}
+
+use pb::spiffe_workload_api_client::SpiffeWorkloadApiClient;
+
+type Client = SpiffeWorkloadApiClient<tonic::transport::Channel>;
+
+/// How long to wait at startup for the agent to issue an SVID
+const FIRST_SVID_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Bounds on the wait between attempts to reach the agent again
+const RETRY_MIN: Duration = Duration::from_secs(1);
+const RETRY_MAX: Duration = Duration::from_secs(30);
+
+/// The trust domain a SPIFFE ID belongs to
+pub fn trust_domain(spiffe_id: &str) -> Option<&str> {
+    spiffe_id
+        .strip_prefix("spiffe://")?
+        .split('/')
+        .next()
+        .filter(|domain| !domain.is_empty())
+}
+
+/// Server config presenting the gateway's SVID, obtained from the Workload
+/// API at `socket` and kept current for as long as the process runs
+pub async fn server_config(socket: &Path, trust_domains: &[String]) -> Result<Arc<ServerConfig>> {
+    let provider = Arc::new(ring::default_provider());
+    let socket = socket.to_path_buf();
+    // The URI is required but unused; every connection goes to the socket
+    let channel = Endpoint::from_static("http://localhost").connect_with_connector_lazy(
+        tower::service_fn(move |_: Uri| {
+            let socket = socket.clone();
+            async move { UnixStream::connect(socket).await.map(TokioIo::new) }
+        }),
+    );
+    let mut client = SpiffeWorkloadApiClient::new(channel);
+
+    let first = async {
+        let mut updates = fetch(&mut client).await?;
+        let response = updates
+            .message()
+            .await
+            .context("Workload API stream failed")?
+            .context("Workload API stream ended")?;
+        Ok::<_, anyhow::Error>((updates, response))
+    };
+    let (updates, response) = tokio::time::timeout(FIRST_SVID_TIMEOUT, first)
+        .await
+        .map_err(|_| anyhow!("No X.509 SVID from the Workload API"))??;
+    let svid = Svid::from_response(response, trust_domains, &provider)?;
+    info!(
+        spiffe_id = %svid.spiffe_id,
+        trust_domains = ?svid.verifiers.keys().collect::<Vec<_>>(),
+        "Serving X.509 SVID"
+    );
+
+    let resolver = Arc::new(CertResolver::new(svid.key));
+    let verifier = Arc::new(SvidVerifier {
+        provider: provider.clone(),
+        by_trust_domain: RwLock::new(svid.verifiers),
+    });
+    tokio::spawn(follow(
+        client,
+        updates,
+        trust_domains.to_vec(),
+        provider.clone(),
+        resolver.clone(),
+        verifier.clone(),
+    ));
+
+    let mut config = ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()?
+        .with_client_cert_verifier(verifier)
+        .with_cert_resolver(resolver);
+    config.alpn_protocols = ALPN.map(<[u8]>::to_vec).to_vec();
+    Ok(Arc::new(config))
+}
+
+async fn fetch(client: &mut Client) -> Result<Streaming<pb::X509svidResponse>> {
+    let mut request = tonic::Request::new(pb::X509svidRequest {});
+    request.metadata_mut().insert(
+        "workload.spiffe.io",
+        "true".parse().expect("valid metadata"),
+    );
+    let updates = client
+        .fetch_x509svid(request)
+        .await
+        .context("Workload API unreachable")?
+        .into_inner();
+    Ok(updates)
+}
+
+/// Apply each SVID and bundle update, reconnecting whenever the stream
+/// breaks
+async fn follow(
+    mut client: Client,
+    mut updates: Streaming<pb::X509svidResponse>,
+    trust_domains: Vec<String>,
+    provider: Arc<CryptoProvider>,
+    resolver: Arc<CertResolver>,
+    verifier: Arc<SvidVerifier>,
+) {
+    loop {
+        match updates.message().await {
+            Ok(Some(response)) => {
+                match Svid::from_response(response, &trust_domains, &provider) {
+                    Ok(svid) => {
+                        if resolver.replace(svid.key) {
+                            info!(spiffe_id = %svid.spiffe_id, "Rotated X.509 SVID");
+                        }
+                        *verifier
+                            .by_trust_domain
+                            .write()
+                            .expect("bundle lock poisoned") = svid.verifiers;
+                    }
+                    Err(e) => error!(
+                        error = %format!("{:#}", e),
+                        "Unusable SVID update; keeping current SVID"
+                    ),
+                }
+                continue;
+            }
+            Ok(None) => warn!("Workload API stream ended; keeping current SVID"),
+            Err(status) => {
+                warn!(error = %status, "Workload API stream failed; keeping current SVID")
+            }
+        }
+
+        let mut delay = RETRY_MIN;
+        updates = loop {
+            tokio::time::sleep(delay).await;
+            match fetch(&mut client).await {
+                Ok(updates) => break updates,
+                Err(e) => {
+                    warn!(error = %format!("{:#}", e), "Retrying the Workload API");
+                    delay = (delay * 2).min(RETRY_MAX);
+                }
+            }
+        };
+    }
+}
+
+/// The parts of a Workload API update the gateway serves with
+struct Svid {
+    spiffe_id: String,
+    key: CertifiedKey,
+    /// Verifiers of clients of each accepted trust domain with a bundle
+    verifiers: HashMap<String, Arc<dyn ClientCertVerifier>>,
+}
+
+impl Svid {
+    fn from_response(
+        response: pb::X509svidResponse,
+        trust_domains: &[String],
+        provider: &Arc<CryptoProvider>,
+    ) -> Result<Self> {
+        let Some(svid) = response.svids.into_iter().next() else {
+            bail!("Workload API returned no SVIDs");
+        };
+        let own = trust_domain(&svid.spiffe_id)
+            .with_context(|| format!("Invalid SPIFFE ID {:?}", svid.spiffe_id))?
+            .to_string();
+        let key = CertifiedKey::from_der(
+            certificates(&svid.x509_svid)?,
+            PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(svid.x509_svid_key)),
+            provider,
+        )
+        .context("Unusable X.509 SVID")?;
+
+        let mut bundles: HashMap<String, Vec<u8>> = response
+            .federated_bundles
+            .into_iter()
+            .filter_map(|(id, bundle)| Some((trust_domain(&id)?.to_string(), bundle)))
+            .collect();
+        bundles.insert(own.clone(), svid.bundle);
+
+        let accepted = match trust_domains {
+            [] => std::slice::from_ref(&own),
+            domains => domains,
+        };
+        let mut verifiers = HashMap::new();
+        for domain in accepted {
+            let Some(bundle) = bundles.get(domain) else {
+                warn!(trust_domain = %domain, "No bundle for trust domain; refusing its workloads");
+                continue;
+            };
+            let mut roots = RootCertStore::empty();
+            for ca in certificates(bundle)? {
+                roots
+                    .add(ca)
+                    .with_context(|| format!("Unusable CA certificate of {}", domain))?;
+            }
+            let verifier =
+                WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+                    .build()
+                    .with_context(|| format!("Failed to build verifier for {}", domain))?;
+            verifiers.insert(domain.clone(), verifier);
+        }
+
+        Ok(Self {
+            spiffe_id: svid.spiffe_id,
+            key,
+            verifiers,
+        })
+    }
+}
+
+/// Split concatenated DER certificates
+fn certificates(mut der: &[u8]) -> Result<Vec<CertificateDer<'static>>> {
+    let mut certificates = Vec::new();
+    while !der.is_empty() {
+        let (rest, _) = x509_parser::parse_x509_certificate(der)
+            .map_err(|e| anyhow!("Invalid certificate from the Workload API: {}", e))?;
+        let (certificate, _) = der.split_at(der.len() - rest.len());
+        certificates.push(CertificateDer::from(certificate.to_vec()));
+        der = rest;
+    }
+    if certificates.is_empty() {
+        bail!("No certificates from the Workload API");
+    }
+    Ok(certificates)
+}
+
+/// Verifies client SVIDs against the bundle of the trust domain they name
+#[derive(Debug)]
+struct SvidVerifier {
+    provider: Arc<CryptoProvider>,
+    by_trust_domain: RwLock<HashMap<String, Arc<dyn ClientCertVerifier>>>,
+}
+
+impl ClientCertVerifier for SvidVerifier {
+    fn root_hint_subjects(&self) -> &[DistinguishedName] {
+        &[]
+    }
+
+    fn verify_client_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        now: UnixTime,
+    ) -> Result<ClientCertVerified, Error> {
+        let refused =
+            || Error::InvalidCertificate(CertificateError::ApplicationVerificationFailure);
+        let identity = ClientIdentity::from_der(end_entity).map_err(|_| refused())?;
+        let domain = identity
+            .spiffe_id
+            .as_deref()
+            .and_then(trust_domain)
+            .ok_or_else(refused)?;
+        let verifier = self
+            .by_trust_domain
+            .read()
+            .expect("bundle lock poisoned")
+            .get(domain)
+            .cloned()
+            .ok_or(Error::InvalidCertificate(CertificateError::UnknownIssuer))?;
+        verifier.verify_client_cert(end_entity, intermediates, now)
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
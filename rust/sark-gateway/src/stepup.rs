@@ -0,0 +1,190 @@
+//! Step-up authentication
+//!
+//! A policy can require the caller to authenticate again, more strongly or
+//! more recently, with a `step_up` (or `require_step_up`) obligation (see
+//! `obligations`):
+//!
+//! ```rego
+//! obligations := {"require_step_up": {"acr": "mfa", "max_age": 300}} if {
+//!     input.resource.sensitivity == "critical"
+//! }
+//! ```
+//!
+//! By default the gateway passes the obligation on, for the enforcement
+//! point. With `step_up.enforce`, the gateway checks it against the token
+//! the caller presented. The token meets the requirement when:
+//!
+//! - its `acr` claim is the class required, or one listed after it in
+//!   `step_up.acr_levels`
+//! - its `amr` claim lists each method required
+//! - its `auth_time` claim is at most `max_age` seconds ago
+//!
+//! A decision the token doesn't meet, allowed or denied, is answered with
+//! a `step-up-required` problem (401) instead. The challenge goes in the
+//! problem and, as RFC 9470 has it, in `WWW-Authenticate`:
+//!
+//! ```text
+//! WWW-Authenticate: Bearer error="insufficient_user_authentication",
+//!     error_description="Decision requires ...", acr_values="mfa", max_age="300"
+//! ```
+//!
+//! ```json
+//! {"type": "urn:sark:problem:step-up-required", "status": 401, ...,
+//!  "acr_values": "mfa", "max_age": 300,
+//!  "auth_url": "https://idp.example.com/authorize?acr_values=mfa&max_age=300"}
+//! ```
+//!
+//! The caller authenticates at `auth_url` and retries with its new token.
+//! Once the requirement is met, the decision stands as the policy made it,
+//! without the `step_up` obligation, so the MCP proxy goes ahead with it.
+//! Quotas are counted only then. Policies see the claims too, as
+//! `input.user.acr`, `amr` and `auth_time`. API key callers can't step
+//! up, so they get a 403.
+
+use crate::auth::UserContext;
+use crate::config::StepUpConfig;
+use crate::obligations::StepUp;
+use crate::problem::Problem;
+use chrono::Utc;
+use reqwest::Url;
+use serde::Serialize;
+
+/// What the caller must do to meet a step-up
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Challenge {
+    /// Authentication context class required
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub acr_values: Option<String>,
+    /// Authentication methods required
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub amr: Vec<String>,
+    /// Longest ago, in seconds, the caller may have authenticated
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_age: Option<u64>,
+    /// Where to authenticate, requirement included
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auth_url: Option<String>,
+}
+
+impl Challenge {
+    /// The `WWW-Authenticate` value for the challenge, as RFC 9470 describes
+    pub fn www_authenticate(&self, description: &str) -> String {
+        let mut value = format!(
+            "Bearer error=\"insufficient_user_authentication\", error_description=\"{}\"",
+            description.replace(['"', '\\'], "'")
+        );
+        if let Some(acr) = &self.acr_values {
+            value.push_str(&format!(", acr_values=\"{}\"", acr));
+        }
+        if let Some(max_age) = self.max_age {
+            value.push_str(&format!(", max_age=\"{}\"", max_age));
+        }
+        value
+    }
+}
+
+/// Checks step-up obligations against callers' tokens
+pub struct StepUps {
+    enforce: bool,
+    auth_url: Option<Url>,
+    acr_levels: Vec<String>,
+}
+
+impl StepUps {
+    pub fn new(config: &StepUpConfig) -> Self {
+        Self {
+            enforce: config.enforce,
+            // Checked by validation
+            auth_url: config
+                .auth_url
+                .as_deref()
+                .and_then(|url| Url::parse(url).ok()),
+            acr_levels: config.acr_levels.clone(),
+        }
+    }
+
+    /// Whether the gateway checks step-ups itself, rather than pass them on
+    pub fn enforced(&self) -> bool {
+        self.enforce
+    }
+
+    /// Check `step_up` against `user`'s token. Returns the problem to
+    /// answer with if the token doesn't meet it.
+    pub fn check(&self, user: &UserContext, step_up: &StepUp) -> Result<(), Problem> {
+        let authentication = &user.authentication;
+        let mut unmet = Vec::new();
+        if let Some(acr) = &step_up.acr {
+            if !self.acr_meets(authentication.acr.as_deref(), acr) {
+                unmet.push(format!("authentication context {}", acr));
+            }
+        }
+        let missing: Vec<&str> = step_up
+            .amr
+            .iter()
+            .filter(|method| !authentication.amr.contains(method))
+            .map(String::as_str)
+            .collect();
+        if !missing.is_empty() {
+            unmet.push(format!("authentication by {}", missing.join(", ")));
+        }
+        if let Some(max_age) = step_up.max_age {
+            let age = authentication
+                .auth_time
+                .map(|auth_time| Utc::now().timestamp() - auth_time);
+            if !age.is_some_and(|age| age <= max_age as i64) {
+                unmet.push(format!("authentication within {}s", max_age));
+            }
+        }
+        if unmet.is_empty() {
+            return Ok(());
+        }
+
+        let message = format!("Decision requires {}", unmet.join(" and "));
+        if user.api_key.is_some() {
+            return Err(Problem::Forbidden(format!(
+                "{}, which API key callers cannot step up to",
+                message
+            )));
+        }
+        Err(Problem::StepUpRequired {
+            message,
+            challenge: self.challenge(step_up),
+        })
+    }
+
+    /// Whether a token at class `presented` meets a requirement of
+    /// `required`
+    fn acr_meets(&self, presented: Option<&str>, required: &str) -> bool {
+        let Some(presented) = presented else {
+            return false;
+        };
+        let level = |acr: &str| self.acr_levels.iter().position(|level| level == acr);
+        presented == required
+            || matches!(
+                (level(presented), level(required)),
+                (Some(presented), Some(required)) if presented >= required
+            )
+    }
+
+    fn challenge(&self, step_up: &StepUp) -> Challenge {
+        let auth_url = self.auth_url.as_ref().map(|url| {
+            let mut url = url.clone();
+            if step_up.acr.is_some() || step_up.max_age.is_some() {
+                let mut query = url.query_pairs_mut();
+                if let Some(acr) = &step_up.acr {
+                    query.append_pair("acr_values", acr);
+                }
+                if let Some(max_age) = step_up.max_age {
+                    query.append_pair("max_age", &max_age.to_string());
+                }
+            }
+            url.to_string()
+        });
+        Challenge {
+            acr_values: step_up.acr.clone(),
+            amr: step_up.amr.clone(),
+            max_age: step_up.max_age,
+            auth_url,
+        }
+    }
+}
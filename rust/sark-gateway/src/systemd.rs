@@ -0,0 +1,127 @@
+//! systemd integration
+//!
+//! Run as a `Type=notify` service, the gateway reports `READY=1` once its
+//! policies have compiled (and passed `--startup-checks`, if given) and
+//! its listeners are bound, `STOPPING=1` when shutdown begins, and, with
+//! `WatchdogSec=` set, sends `WATCHDOG=1` keepalives at half the watchdog
+//! interval for as long as the runtime is responsive.
+//!
+//! With socket activation (a `.socket` unit passing `LISTEN_FDS`), the
+//! first socket systemd passes is served in place of binding `listen`;
+//! it may be a TCP or Unix stream socket and keeps the mode and ownership
+//! the socket unit gave it. The admin listener still binds its own
+//! address.
+//!
+//! ```ini
+//! [Service]
+//! Type=notify
+//! WatchdogSec=30
+//! ExecStart=/usr/bin/sark-gateway --config /etc/sark/gateway.toml
+//! ```
+//!
+//! Outside systemd (none of these variables set) all of this is a no-op.
+
+use crate::listen::Listener;
+use anyhow::{bail, Context, Result};
+use std::env;
+use std::os::fd::{FromRawFd, IntoRawFd, RawFd};
+use std::os::unix::net::UnixDatagram;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+/// First file descriptor systemd passes (`SD_LISTEN_FDS_START`)
+const LISTEN_FDS_START: RawFd = 3;
+
+/// Whether a variable systemd scoped to a PID was meant for this process
+fn for_us(pid_var: &str) -> bool {
+    env::var(pid_var).map_or(true, |pid| pid == std::process::id().to_string())
+}
+
+/// The socket systemd passed, if the gateway was socket activated
+///
+/// The activation variables are cleared so child processes don't take
+/// them as theirs.
+pub fn listener() -> Result<Option<Listener>> {
+    let Ok(count) = env::var("LISTEN_FDS") else {
+        return Ok(None);
+    };
+    let ours = env::var("LISTEN_PID").is_ok() && for_us("LISTEN_PID");
+    for var in ["LISTEN_FDS", "LISTEN_PID", "LISTEN_FDNAMES"] {
+        env::remove_var(var);
+    }
+    if !ours {
+        return Ok(None);
+    }
+
+    let count: RawFd = count
+        .parse()
+        .with_context(|| format!("Invalid LISTEN_FDS {:?}", count))?;
+    if count < 1 {
+        return Ok(None);
+    }
+    if count > 1 {
+        warn!(
+            sockets = count,
+            "systemd passed several sockets; serving the first only"
+        );
+    }
+
+    // SAFETY: systemd passes `count` open descriptors starting at
+    // LISTEN_FDS_START, and this is the only place that takes ownership of
+    // the first one (the variables naming it were just cleared)
+    let tcp = unsafe { std::net::TcpListener::from_raw_fd(LISTEN_FDS_START) };
+    let listener = if tcp.local_addr().is_ok() {
+        tcp.set_nonblocking(true)?;
+        Listener::Tcp(tokio::net::TcpListener::from_std(tcp)?)
+    } else {
+        // SAFETY: the descriptor was just released by `tcp`
+        let unix = unsafe { std::os::unix::net::UnixListener::from_raw_fd(tcp.into_raw_fd()) };
+        if unix.local_addr().is_err() {
+            bail!("Socket passed by systemd is neither a TCP nor a Unix socket");
+        }
+        unix.set_nonblocking(true)?;
+        Listener::Unix(tokio::net::UnixListener::from_std(unix)?, None)
+    };
+    info!("Using the socket passed by systemd");
+    Ok(Some(listener))
+}
+
+/// Report `state` (e.g. `READY=1`) to the service manager, if it asked for
+/// notifications
+pub fn notify(state: &str) {
+    let Ok(path) = env::var("NOTIFY_SOCKET") else {
+        return;
+    };
+    let sent = UnixDatagram::unbound().and_then(|socket| match path.strip_prefix('@') {
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            socket.send_to_addr(state.as_bytes(), &addr)
+        }
+        #[cfg(not(target_os = "linux"))]
+        Some(_) => Err(std::io::ErrorKind::Unsupported.into()),
+        None => socket.send_to(state.as_bytes(), &path),
+    });
+    if let Err(e) = sent {
+        debug!(state = %state, error = %e, "Failed to notify systemd");
+    }
+}
+
+/// Keepalive interval the service manager expects, if its watchdog is on
+pub fn watchdog_interval() -> Option<Duration> {
+    if !for_us("WATCHDOG_PID") {
+        return None;
+    }
+    let usec: u64 = env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    (usec > 0).then(|| Duration::from_micros(usec / 2))
+}
+
+/// Send watchdog keepalives every `every`
+pub async fn watchdog(every: Duration) {
+    let mut interval = tokio::time::interval(every);
+    loop {
+        interval.tick().await;
+        notify("WATCHDOG=1");
+    }
+}
@@ -0,0 +1,165 @@
+//! Logging and distributed tracing
+//!
+//! Log lines always go to stdout, as text (`pretty`, for local use) or, for
+//! log pipelines, one JSON object per line (`--log-format json`). JSON
+//! lines carry event fields typed under `fields` (a decision's `allow`,
+//! `cached` and `cache_ttl` as a boolean and numbers), and the spans they
+//! were logged in under `spans`. With `--otlp-endpoint`, spans are also
+//! exported over OTLP/gRPC, and each request's root span continues the
+//! trace named by an incoming W3C `traceparent` header, so gateway time
+//! shows up inside the caller's distributed trace. Within a request,
+//! token verification, cache lookup, policy evaluation and cache writes
+//! get their own spans.
+//!
+//! Every request is correlated by its `X-Request-ID`: the caller's if it
+//! sent one, a fresh one otherwise. The root span carries it, and the
+//! caller's `user_id` once verified, so every log line a request produces
+//! names them; the request id is also in the response's
+//! `X-Request-ID` (and error body), the audit record, the policy input
+//! (`input.request_id`) and requests made on its behalf (the fallback,
+//! proxied MCP servers). The trace id of an incoming `traceparent` is in
+//! the policy input too, as `input.context.trace_id` (see `sark_context`).
+
+use crate::problem::Problem;
+use anyhow::{Context, Result};
+use axum::{
+    extract::{MatchedPath, Request},
+    http::{HeaderMap, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use opentelemetry::propagation::Extractor;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::TracerProvider;
+use opentelemetry_sdk::{runtime, Resource};
+use serde::{Deserialize, Serialize};
+use tracing::{info_span, Instrument};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+/// Handle for changing the log level at runtime
+pub type LogFilter = reload::Handle<EnvFilter, Registry>;
+
+/// How log lines are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    #[serde(alias = "pretty")]
+    #[value(alias = "pretty")]
+    Text,
+    Json,
+}
+
+/// Install the log subscriber, exporting spans to `otlp_endpoint` if set
+///
+/// The returned provider must be shut down before exit to flush spans.
+pub fn init(
+    log_level: &str,
+    format: LogFormat,
+    otlp_endpoint: Option<&str>,
+) -> Result<(Option<TracerProvider>, LogFilter)> {
+    let provider = match otlp_endpoint {
+        Some(endpoint) => {
+            let exporter = opentelemetry_otlp::SpanExporter::builder()
+                .with_tonic()
+                .with_endpoint(endpoint)
+                .build()
+                .context("Failed to build OTLP span exporter")?;
+            let provider = TracerProvider::builder()
+                .with_batch_exporter(exporter, runtime::Tokio)
+                .with_resource(Resource::new([
+                    KeyValue::new("service.name", "sark-gateway"),
+                    KeyValue::new("service.version", env!("CARGO_PKG_VERSION")),
+                ]))
+                .build();
+            opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+            Some(provider)
+        }
+        None => None,
+    };
+
+    let otel = provider
+        .as_ref()
+        .map(|p| tracing_opentelemetry::layer().with_tracer(p.tracer("sark-gateway")));
+
+    let (filter, log_filter) = reload::Layer::new(EnvFilter::new(log_level));
+    tracing_subscriber::registry()
+        .with(filter)
+        .with((format == LogFormat::Text).then(tracing_subscriber::fmt::layer))
+        .with((format == LogFormat::Json).then(|| tracing_subscriber::fmt::layer().json()))
+        .with(otel)
+        .init();
+
+    Ok((provider, log_filter))
+}
+
+/// Switch the log level (or filter directive) of a running subscriber
+pub fn set_level(log_filter: &LogFilter, log_level: &str) -> Result<()> {
+    let filter = EnvFilter::try_new(log_level)
+        .with_context(|| format!("Invalid log level {:?}", log_level))?;
+    log_filter
+        .reload(filter)
+        .context("Failed to change log level")
+}
+
+/// Middleware opening each request's root span, parented to the caller's
+/// trace when it sent one, and settling its request id
+pub async fn trace_request(mut request: Request, next: Next) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_string())
+        .unwrap_or_default();
+
+    let span = info_span!(
+        "request",
+        otel.name = %format!("{} {}", request.method(), route),
+        http.method = %request.method(),
+        http.route = %route,
+        http.status_code = tracing::field::Empty,
+        request_id = tracing::field::Empty,
+        user_id = tracing::field::Empty,
+    );
+    let parent = opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(request.headers()))
+    });
+    span.set_parent(parent);
+
+    // Handlers read the id back from the request's headers
+    let request_id = crate::request_id(request.headers());
+    span.record("request_id", tracing::field::display(&request_id));
+    let request_id = HeaderValue::from_str(&request_id).ok();
+    if let Some(id) = &request_id {
+        request
+            .headers_mut()
+            .insert(crate::REQUEST_ID_HEADER, id.clone());
+    }
+
+    let mut response = next.run(request).instrument(span.clone()).await;
+    span.record("http.status_code", response.status().as_u16());
+    if let Some(id) = request_id {
+        if let Some(problem) = response.extensions_mut().remove::<Problem>() {
+            *response.body_mut() = problem.body(id.to_str().ok());
+        }
+        response.headers_mut().insert(crate::REQUEST_ID_HEADER, id);
+    }
+    response
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|v| v.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|k| k.as_str()).collect()
+    }
+}
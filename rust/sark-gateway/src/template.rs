@@ -0,0 +1,114 @@
+//! Policy templates
+//!
+//! A `.rego.tmpl` file is a policy with `{{ name }}` placeholders, filled
+//! in from `policy.vars` before it is compiled, so one source can serve
+//! every environment:
+//!
+//! ```text
+//! package sark.gateway
+//!
+//! environment := "{{ environment }}"
+//!
+//! allow if {
+//!     input.tool.sensitivity_level <= {{ thresholds.max_sensitivity }}
+//! }
+//! ```
+//!
+//! ```toml
+//! [policy.vars]
+//! environment = "staging"
+//! thresholds = { max_sensitivity = 2 }
+//! ```
+//!
+//! A dotted name reaches into a table. Strings are inserted as they are
+//! (quote them in the template where Rego wants a string); numbers,
+//! booleans, arrays and tables as their JSON, which is also Rego. A
+//! placeholder naming no variable fails the whole policy set, as does an
+//! unclosed `{{`, so a missing variable can't compile into a policy that
+//! quietly compares against nothing. Rego's own `{{` (a set of objects)
+//! needs a space in templates: `{ {`.
+
+use serde_json::Value as JsonValue;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Template variables, by name
+pub type Vars = HashMap<String, JsonValue>;
+
+/// Why a template didn't render, and where
+#[derive(Debug)]
+pub struct TemplateError {
+    /// 1-based line of the placeholder
+    pub line: u32,
+    pub message: String,
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+/// `template` with every placeholder replaced from `vars`
+pub fn render(template: &str, vars: &Vars) -> Result<String, TemplateError> {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        rendered.push_str(&rest[..start]);
+        let line = line_at(template, template.len() - rest.len() + start);
+        let after = &rest[start + 2..];
+        let fail = |message: String| TemplateError { line, message };
+        let Some(end) = after.find("}}") else {
+            return Err(fail("unclosed {{".to_string()));
+        };
+
+        let name = after[..end].trim();
+        if name.is_empty() || !name.split('.').all(is_name) {
+            return Err(fail(format!(
+                "invalid placeholder {{{{{}}}}}",
+                &after[..end]
+            )));
+        }
+        match lookup(vars, name) {
+            Some(JsonValue::String(value)) => rendered.push_str(value),
+            Some(value) => rendered.push_str(&value.to_string()),
+            None => return Err(fail(format!("undefined variable {:?}", name))),
+        }
+        rest = &after[end + 2..];
+    }
+
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+/// `vars` overlaid with `overrides`, which win for the names they set
+pub fn overlay(vars: &Vars, overrides: &Vars) -> Vars {
+    let mut merged = vars.clone();
+    merged.extend(overrides.iter().map(|(k, v)| (k.clone(), v.clone())));
+    merged
+}
+
+fn lookup<'a>(vars: &'a Vars, name: &str) -> Option<&'a JsonValue> {
+    let mut segments = name.split('.');
+    let mut value = vars.get(segments.next()?)?;
+    for segment in segments {
+        value = value.get(segment)?;
+    }
+    Some(value)
+}
+
+fn is_name(segment: &str) -> bool {
+    let mut chars = segment.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// 1-based line of the byte at `offset`
+fn line_at(text: &str, offset: usize) -> u32 {
+    text[..offset].matches('\n').count() as u32 + 1
+}
@@ -0,0 +1,239 @@
+//! Tenant policy isolation
+//!
+//! One gateway can serve several tenants (business units) whose policies
+//! must not mix. Each tenant gets its own policy directory, compiled into
+//! an engine of its own with its own data documents, revision history and
+//! decision cache namespaces (`auth@<tenant>`, `a2a@<tenant>`):
+//!
+//! ```toml
+//! [claims]
+//! tenant = "org_id"
+//!
+//! [tenants]
+//! header = "x-sark-tenant"
+//!
+//! [tenants.policies.finance]
+//! dir = "/etc/sark/tenants/finance"
+//! watch = true
+//! ```
+//!
+//! A caller's tenant is the one its token claims (the `[claims]` `tenant`
+//! path) or its API key names, else the one in `header`, if configured. A
+//! header naming a different tenant than the token is refused with 403, as
+//! is an unknown tenant and, with `required`, a request naming none.
+//! Requests without a tenant are decided by the default policy as before.
+//!
+//! The tenant is `input.user.tenant`, which also labels the
+//! `sark_gateway_decisions_total` metric. The set of tenants is read at
+//! startup; a tenant's directory is reloaded when watched or on
+//! `POST /admin/tenants/{tenant}/policies/reload`. The other policy and
+//! data admin endpoints, shadow evaluation and the fallback apply to the
+//! default policy only.
+
+use crate::auth::UserContext;
+use crate::bloom::Sizing;
+use crate::cache::{Namespace, RedisTier};
+use crate::config::{TenantPolicyConfig, TenantsConfig};
+use crate::policy::{self, ActivePolicy, CurrentRevision, PolicySet, PolicyStore};
+use crate::problem::Problem;
+use crate::template::{self, Vars};
+use crate::watch;
+use anyhow::{Context, Result};
+use axum::http::{HeaderMap, HeaderName};
+use sark_store::Store;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::info;
+
+/// One tenant's policies and cached decisions
+pub struct Tenant {
+    pub dir: PathBuf,
+    /// Variables the tenant's templates are rendered with
+    vars: Vars,
+    pub policy: Arc<Mutex<PolicyStore>>,
+    /// Revision of the tenant's active policy
+    pub revision: CurrentRevision,
+    pub decisions: Namespace,
+    pub a2a_decisions: Namespace,
+}
+
+impl Tenant {
+    #[allow(clippy::too_many_arguments)]
+    fn load(
+        name: &str,
+        config: &TenantPolicyConfig,
+        vars: &Vars,
+        cache: &Arc<Store>,
+        l2: &Option<RedisTier>,
+        bloom: Option<Sizing>,
+        keep: usize,
+        max_engines: usize,
+    ) -> Result<Self> {
+        let vars = template::overlay(vars, &config.vars);
+        let set = PolicySet::from_dir(&config.dir)
+            .and_then(|set| set.rendered(&vars))
+            .with_context(|| format!("Failed to load policies of tenant {:?}", name))?;
+        let active = ActivePolicy::new(
+            set.compile()
+                .with_context(|| format!("Failed to compile policies of tenant {:?}", name))?,
+            set,
+            max_engines,
+        );
+        info!(tenant = %name, revision = %active.revision(), "Tenant policy active");
+        let mut store = PolicyStore::new(active, keep);
+        store.set_max_engines(max_engines);
+        let filtered = |namespace: Namespace| match bloom {
+            Some(sizing) => namespace.filtered(sizing),
+            None => namespace,
+        };
+        Ok(Self {
+            dir: config.dir.clone(),
+            vars,
+            revision: store.current_revision(),
+            policy: Arc::new(Mutex::new(store)),
+            decisions: filtered(Namespace::new(
+                cache.clone(),
+                &format!("auth@{}", name),
+                l2.clone(),
+            )),
+            a2a_decisions: filtered(Namespace::new(
+                cache.clone(),
+                &format!("a2a@{}", name),
+                l2.clone(),
+            )),
+        })
+    }
+
+    pub fn decision_caches(&self) -> Vec<Namespace> {
+        vec![self.decisions.clone(), self.a2a_decisions.clone()]
+    }
+
+    /// Load the tenant's directory again and make it the active policy
+    pub async fn reload(&self) -> Result<()> {
+        let set = PolicySet::from_dir(&self.dir)?.rendered(&self.vars)?;
+        policy::activate(&self.policy, &self.decision_caches(), set).await
+    }
+}
+
+/// The configured tenants
+pub struct Tenants {
+    header: Option<HeaderName>,
+    required: bool,
+    by_name: BTreeMap<String, Tenant>,
+}
+
+impl Tenants {
+    /// Compile each tenant's policies, rendering templates with `vars`
+    /// under the tenant's own, and watch the directories that ask for it
+    pub fn load(
+        config: &TenantsConfig,
+        vars: &Vars,
+        cache: &Arc<Store>,
+        l2: &Option<RedisTier>,
+        bloom: Option<Sizing>,
+        keep: usize,
+        max_engines: usize,
+    ) -> Result<Self> {
+        let mut by_name = BTreeMap::new();
+        for (name, policies) in &config.policies {
+            let tenant = Tenant::load(name, policies, vars, cache, l2, bloom, keep, max_engines)?;
+            if policies.watch {
+                // Runs for the life of the process, as tenants can't change
+                watch::start(
+                    policies.dir.clone(),
+                    tenant.vars.clone(),
+                    tenant.policy.clone(),
+                    tenant.decision_caches(),
+                )?;
+            }
+            by_name.insert(name.clone(), tenant);
+        }
+        let header = config
+            .header
+            .as_deref()
+            .map(|header| HeaderName::from_bytes(header.as_bytes()))
+            .transpose()
+            .context("Invalid tenants.header")?;
+        Ok(Self {
+            header,
+            required: config.required,
+            by_name,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_name.len()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Tenant> {
+        self.by_name.get(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Tenant)> {
+        self.by_name
+            .iter()
+            .map(|(name, tenant)| (name.as_str(), tenant))
+    }
+
+    /// Every tenant's decision caches
+    pub fn decision_caches(&self) -> Vec<Namespace> {
+        self.by_name
+            .values()
+            .flat_map(Tenant::decision_caches)
+            .collect()
+    }
+
+    /// Settle which tenant `user` is decided under, from what its token
+    /// claims and `headers` name
+    pub fn resolve(&self, user: &mut UserContext, headers: &HeaderMap) -> Result<(), Problem> {
+        if self.by_name.is_empty() {
+            user.tenant = None;
+            return Ok(());
+        }
+        let named = match &self.header {
+            Some(header) => headers
+                .get(header)
+                .map(|value| {
+                    value
+                        .to_str()
+                        .map_err(|_| Problem::InvalidRequest(format!("Invalid {} header", header)))
+                })
+                .transpose()?,
+            None => None,
+        };
+        let tenant = match (user.tenant.as_deref(), named) {
+            (Some(claimed), Some(named)) if claimed != named => {
+                return Err(Problem::Forbidden(format!(
+                    "Caller belongs to tenant {:?}, not {:?}",
+                    claimed, named
+                )))
+            }
+            (Some(tenant), _) | (None, Some(tenant)) => Some(tenant.to_string()),
+            (None, None) if self.required => {
+                return Err(Problem::Forbidden(
+                    "Requests must name a tenant".to_string(),
+                ))
+            }
+            (None, None) => None,
+        };
+        if let Some(tenant) = tenant.as_deref().filter(|t| !self.by_name.contains_key(*t)) {
+            return Err(Problem::Forbidden(format!("Unknown tenant {:?}", tenant)));
+        }
+        user.tenant = tenant;
+        Ok(())
+    }
+
+    /// The tenant a policy input is decided under, or `None` for the
+    /// default policy
+    pub fn of(&self, input: &serde_json::Value) -> Result<Option<&Tenant>, Problem> {
+        match input["user"]["tenant"].as_str() {
+            Some(name) => self
+                .get(name)
+                .map(Some)
+                .ok_or_else(|| Problem::Forbidden(format!("Unknown tenant {:?}", name))),
+            None => Ok(None),
+        }
+    }
+}
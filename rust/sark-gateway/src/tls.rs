@@ -0,0 +1,332 @@
+//! TLS termination
+//!
+//! With `--tls-cert`/`--tls-key` the gateway serves HTTPS itself. The
+//! certificate and key are watched and reloaded when they change, so a
+//! cert-manager rotation (which swaps a symlinked directory in the secret
+//! mount) takes effect without a restart. New handshakes get the new
+//! certificate; a pair that fails to load (or whose key doesn't match the
+//! certificate, e.g. caught between two writes) is logged and the current
+//! one keeps serving.
+//!
+//! With `--tls-client-ca`, clients must also present a certificate issued
+//! by that CA. The verified certificate's identity (SPIFFE ID, URI and DNS
+//! SANs, subject) is passed to policies as `input.client`, so rules can
+//! authorize the calling workload as well as the token's user. The CA
+//! bundle is read once at startup.
+
+use crate::listen::Connections;
+use anyhow::{bail, Context, Result};
+use axum::{extract::ConnectInfo, http::Request, Router};
+use hyper_util::rt::TokioIo;
+use hyper_util::server::graceful::GracefulShutdown;
+use hyper_util::service::TowerToHyperService;
+use notify::{EventKind, RecursiveMode, Watcher};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio_rustls::rustls::crypto::{ring, CryptoProvider};
+use tokio_rustls::rustls::pki_types::CertificateDer;
+use tokio_rustls::rustls::server::{ClientHello, ResolvesServerCert, WebPkiClientVerifier};
+use tokio_rustls::rustls::sign::CertifiedKey;
+use tokio_rustls::rustls::{RootCertStore, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+use tower::ServiceExt;
+use tracing::{debug, error, info, warn};
+
+/// Quiet period before reloading, so the cert and key being replaced one
+/// after the other trigger a single reload
+const DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// Connections that haven't completed a handshake by now are dropped
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Protocols offered to clients, HTTP/2 first for gRPC
+pub const ALPN: [&[u8]; 2] = [b"h2", b"http/1.1"];
+
+/// Serves whichever certificate was loaded last
+#[derive(Debug)]
+pub struct CertResolver {
+    current: RwLock<Arc<CertifiedKey>>,
+}
+
+impl CertResolver {
+    pub fn new(key: CertifiedKey) -> Self {
+        Self {
+            current: RwLock::new(Arc::new(key)),
+        }
+    }
+
+    /// Serve `key` from now on, returning whether its certificate is new
+    pub fn replace(&self, key: CertifiedKey) -> bool {
+        let mut current = self.current.write().expect("cert lock poisoned");
+        if current.cert == key.cert {
+            return false;
+        }
+        *current = Arc::new(key);
+        true
+    }
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current.read().expect("cert lock poisoned").clone())
+    }
+}
+
+/// Identity of a verified client certificate
+#[derive(Debug, Clone, Serialize)]
+pub struct ClientIdentity {
+    /// The `spiffe://` URI SAN, if any
+    pub spiffe_id: Option<String>,
+    pub uris: Vec<String>,
+    pub dns_names: Vec<String>,
+    pub subject: String,
+}
+
+impl ClientIdentity {
+    pub fn from_der(der: &CertificateDer<'_>) -> Result<Self> {
+        use x509_parser::prelude::{FromDer, GeneralName, X509Certificate};
+
+        let (_, cert) = X509Certificate::from_der(der).context("Unparseable client certificate")?;
+        let mut uris = Vec::new();
+        let mut dns_names = Vec::new();
+        if let Some(san) = cert
+            .subject_alternative_name()
+            .context("Invalid subjectAltName in client certificate")?
+        {
+            for name in &san.value.general_names {
+                match name {
+                    GeneralName::URI(uri) => uris.push(uri.to_string()),
+                    GeneralName::DNSName(dns) => dns_names.push(dns.to_string()),
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(Self {
+            spiffe_id: uris.iter().find(|u| u.starts_with("spiffe://")).cloned(),
+            uris,
+            dns_names,
+            subject: cert.subject().to_string(),
+        })
+    }
+}
+
+/// Server config for `cert`/`key`, reloading them when they change, and
+/// requiring client certificates issued by `client_ca` if given
+pub fn server_config(
+    cert: PathBuf,
+    key: PathBuf,
+    client_ca: Option<&Path>,
+) -> Result<Arc<ServerConfig>> {
+    let provider = Arc::new(ring::default_provider());
+    let resolver = Arc::new(CertResolver::new(load(&cert, &key, &provider)?));
+    watch(cert, key, provider.clone(), resolver.clone())?;
+
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?;
+    let builder = match client_ca {
+        Some(path) => {
+            let mut roots = RootCertStore::empty();
+            for ca in rustls_pemfile::certs(&mut read(path)?.as_slice()) {
+                let ca =
+                    ca.with_context(|| format!("Invalid CA certificate {}", path.display()))?;
+                roots
+                    .add(ca)
+                    .with_context(|| format!("Unusable CA certificate in {}", path.display()))?;
+            }
+            if roots.is_empty() {
+                bail!("No CA certificates in {}", path.display());
+            }
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                .build()
+                .context("Failed to build client certificate verifier")?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let mut config = builder.with_cert_resolver(resolver);
+    config.alpn_protocols = ALPN.map(<[u8]>::to_vec).to_vec();
+    Ok(Arc::new(config))
+}
+
+fn load(cert: &Path, key: &Path, provider: &CryptoProvider) -> Result<CertifiedKey> {
+    let chain = rustls_pemfile::certs(&mut read(cert)?.as_slice())
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Invalid certificate {}", cert.display()))?;
+    if chain.is_empty() {
+        bail!("No certificates in {}", cert.display());
+    }
+    let Some(private_key) = rustls_pemfile::private_key(&mut read(key)?.as_slice())
+        .with_context(|| format!("Invalid private key {}", key.display()))?
+    else {
+        bail!("No private key in {}", key.display());
+    };
+
+    CertifiedKey::from_der(chain, private_key, provider)
+        .with_context(|| format!("Unusable key pair {} / {}", cert.display(), key.display()))
+}
+
+fn read(path: &Path) -> Result<Vec<u8>> {
+    std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))
+}
+
+/// Reload into `resolver` whenever the directory of `cert` or `key` changes
+///
+/// Directories rather than the files are watched: secret mounts replace
+/// files by renaming a new directory into place, which a watch on the old
+/// file would never see.
+fn watch(
+    cert: PathBuf,
+    key: PathBuf,
+    provider: Arc<CryptoProvider>,
+    resolver: Arc<CertResolver>,
+) -> Result<()> {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+        if let Ok(event) = res {
+            if !matches!(event.kind, EventKind::Access(_)) {
+                let _ = tx.send(());
+            }
+        }
+    })
+    .context("Failed to create certificate watcher")?;
+
+    let dirs: HashSet<&Path> = [&cert, &key]
+        .into_iter()
+        .map(|p| {
+            p.parent()
+                .filter(|d| !d.as_os_str().is_empty())
+                .unwrap_or(Path::new("."))
+        })
+        .collect();
+    for dir in dirs {
+        watcher
+            .watch(dir, RecursiveMode::NonRecursive)
+            .with_context(|| format!("Failed to watch {}", dir.display()))?;
+    }
+
+    tokio::spawn(async move {
+        // Keep the watcher alive for as long as the task runs
+        let _watcher = watcher;
+
+        while rx.recv().await.is_some() {
+            tokio::time::sleep(DEBOUNCE).await;
+            while rx.try_recv().is_ok() {}
+
+            match load(&cert, &key, &provider) {
+                Ok(loaded) => {
+                    if resolver.replace(loaded) {
+                        info!(cert = %cert.display(), "Reloaded TLS certificate");
+                    }
+                }
+                Err(e) => error!(
+                    error = %format!("{:#}", e),
+                    "TLS certificate reload failed; keeping current certificate"
+                ),
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Serve `app` over TLS on `listener` (after a PROXY protocol header with
+/// `proxy_protocol`) until `shutdown` resolves, then wait for open
+/// connections to finish
+pub async fn serve(
+    listener: TcpListener,
+    app: Router,
+    config: Arc<ServerConfig>,
+    proxy_protocol: bool,
+    connections: Arc<Connections>,
+    shutdown: impl Future<Output = ()>,
+) -> Result<()> {
+    let acceptor = TlsAcceptor::from(config);
+    let graceful = GracefulShutdown::new();
+    tokio::pin!(shutdown);
+
+    loop {
+        let (mut stream, peer) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    // Typically out of file descriptors; back off like
+                    // axum::serve does rather than spin
+                    warn!(error = %e, "Failed to accept connection");
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    continue;
+                }
+            },
+            _ = &mut shutdown => break,
+        };
+
+        connections.accepted(&stream);
+
+        let acceptor = acceptor.clone();
+        let app = app.clone();
+        let connections = connections.clone();
+        let watcher = graceful.watcher();
+        tokio::spawn(async move {
+            let peer = match proxy_protocol {
+                true => match crate::clientip::accept_proxied(&mut stream, peer).await {
+                    Some(peer) => peer,
+                    None => return,
+                },
+                false => peer,
+            };
+            let stream =
+                match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                    Ok(Ok(stream)) => stream,
+                    Ok(Err(e)) => {
+                        debug!(peer = %peer, error = %e, "TLS handshake failed");
+                        return;
+                    }
+                    Err(_) => {
+                        debug!(peer = %peer, "TLS handshake timed out");
+                        return;
+                    }
+                };
+
+            // Verified by the handshake; only present with --tls-client-ca
+            // or a SPIFFE Workload API
+            let client = match stream.get_ref().1.peer_certificates() {
+                Some([leaf, ..]) => match ClientIdentity::from_der(leaf) {
+                    Ok(identity) => Some(identity),
+                    Err(e) => {
+                        warn!(peer = %peer, error = %format!("{:#}", e), "Rejected client certificate");
+                        return;
+                    }
+                },
+                _ => None,
+            };
+            let service =
+                TowerToHyperService::new(app.map_request(move |mut request: Request<_>| {
+                    request.extensions_mut().insert(ConnectInfo(peer));
+                    if let Some(client) = &client {
+                        request.extensions_mut().insert(client.clone());
+                    }
+                    request
+                }));
+
+            let connection = connections
+                .builder()
+                .serve_connection(TokioIo::new(stream), service)
+                .into_owned();
+            if let Err(e) = watcher.watch(connection).await {
+                debug!(peer = %peer, error = %e, "Connection closed with error");
+            }
+        });
+    }
+
+    drop(listener);
+    graceful.shutdown().await;
+    Ok(())
+}
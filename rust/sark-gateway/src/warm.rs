@@ -0,0 +1,158 @@
+//! Decision cache warm-up
+//!
+//! A new instance starts with an empty cache, so the first requests after
+//! a blue/green cutover all pay for evaluation. `POST /admin/cache/warm` on
+//! the admin port, and `--cache-preload <file>` at startup, fill the cache
+//! with decisions already made, each given with the policy input it was
+//! made for:
+//!
+//! ```json
+//! {"decisions": [
+//!     {"endpoint": "authorize", "input": {"user": {...}, "action": "..."},
+//!      "decision": {"allow": true, "reason": "...", "cache_ttl": 300,
+//!                   "policy_revision": "sha256:..."}}
+//! ]}
+//! ```
+//!
+//! `endpoint` is `authorize` (the default) or `a2a`; `input` is the policy
+//! input as built for the request (a `request_id` in it is ignored) and
+//! `decision` the response body it got; a replay by id (see `replay`)
+//! returns both (as `input` and `original.result`) for recent decisions. Each decision is cached under the
+//! key the request would look it up by, for its `cache_ttl` (or `ttl`,
+//! if given, up to `cache.max_ttl`). Decisions from a policy revision that
+//! isn't active here (or the input's tenant's), dry runs and decisions
+//! with a TTL of 0 are skipped, so a warm-up can't carry over decisions
+//! the policy in force wouldn't make. The preload file is read once,
+//! before the listener opens, and takes the same document.
+
+use crate::cache::CachedDecision;
+use crate::problem::{JsonBody, Problem};
+use crate::{decision_log, AppState, Endpoint, GatewayAuthResponse};
+use anyhow::{Context, Result};
+use axum::{extract::State, Json};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::Path;
+use tracing::{info, warn};
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WarmRequest {
+    decisions: Vec<WarmEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WarmEntry {
+    endpoint: Option<Endpoint>,
+    input: Value,
+    decision: GatewayAuthResponse,
+    /// Seconds to cache for, instead of the decision's `cache_ttl`
+    ttl: Option<u64>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct WarmReport {
+    /// Decisions cached
+    warmed: usize,
+    /// Decisions left out, and why
+    skipped: Vec<Skipped>,
+}
+
+#[derive(Debug, Serialize)]
+struct Skipped {
+    /// Position in `decisions`
+    index: usize,
+    reason: String,
+}
+
+/// `POST /admin/cache/warm`: cache the given decisions
+pub async fn warm_cache(
+    State(state): State<AppState>,
+    JsonBody(request): JsonBody<WarmRequest>,
+) -> Json<WarmReport> {
+    let report = warm(&state, request).await;
+    info!(
+        warmed = report.warmed,
+        skipped = report.skipped.len(),
+        "Warmed decision cache"
+    );
+    Json(report)
+}
+
+/// Cache the decisions in the file at `path`, as `--cache-preload` does
+pub async fn preload(state: &AppState, path: &Path) -> Result<()> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read cache preload file {}", path.display()))?;
+    let request: WarmRequest = serde_json::from_str(&text)
+        .with_context(|| format!("Invalid cache preload file {}", path.display()))?;
+    let report = warm(state, request).await;
+    for skipped in &report.skipped {
+        warn!(
+            index = skipped.index,
+            reason = %skipped.reason,
+            "Skipped preloaded decision"
+        );
+    }
+    info!(
+        path = %path.display(),
+        warmed = report.warmed,
+        skipped = report.skipped.len(),
+        "Preloaded decision cache"
+    );
+    Ok(())
+}
+
+async fn warm(state: &AppState, request: WarmRequest) -> WarmReport {
+    let mut report = WarmReport::default();
+    for (index, entry) in request.decisions.into_iter().enumerate() {
+        match warm_one(state, entry).await {
+            Ok(()) => report.warmed += 1,
+            Err(problem) => report.skipped.push(Skipped {
+                index,
+                reason: problem.to_string(),
+            }),
+        }
+    }
+    report
+}
+
+async fn warm_one(state: &AppState, entry: WarmEntry) -> Result<(), Problem> {
+    let endpoint = entry.endpoint.unwrap_or(Endpoint::Authorize);
+    let mut input = entry.input;
+    if let Some(input) = input.as_object_mut() {
+        input.remove("request_id");
+    }
+    let tenant = state.tenants.of(&input)?;
+
+    if entry.decision.dry_run {
+        return Err(Problem::InvalidRequest(
+            "Dry-run decisions are not cached".to_string(),
+        ));
+    }
+    let revision = tenant.map_or(&state.revision, |tenant| &tenant.revision);
+    if !revision.is(&entry.decision.policy_revision) {
+        return Err(Problem::InvalidRequest(format!(
+            "Decided by policy revision {:?}, which is not active",
+            entry.decision.policy_revision
+        )));
+    }
+    let ttl = entry
+        .ttl
+        .unwrap_or(entry.decision.cache_ttl.into())
+        .min(state.ttls().max);
+    if ttl == 0 {
+        return Err(Problem::InvalidRequest(
+            "Decision is not cacheable (TTL 0)".to_string(),
+        ));
+    }
+
+    let key = endpoint.cache_key(state, &input, &decision_log::input_digest(&input));
+    let value = serde_json::to_string(&CachedDecision::new(&entry.decision))
+        .map_err(|e| Problem::Internal(e.to_string()))?;
+    endpoint
+        .cache(state, tenant)
+        .set(&key, value, ttl)
+        .await
+        .map_err(|e| Problem::Internal(format!("Failed to cache decision: {}", e)))
+}
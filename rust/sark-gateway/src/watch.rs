@@ -0,0 +1,79 @@
+//! Policy directory hot reload
+//!
+//! Watches the `--policy-dir` tree and recompiles it after changes to
+//! `.rego`, `.rego.tmpl` or `data.json` files. A policy set that fails to
+//! compile is logged and the previous one keeps serving, so a bad edit (or
+//! a GitOps sync caught half-way) never takes the gateway down.
+
+use crate::cache::Namespace;
+use crate::policy::{self, PolicySet, PolicyStore};
+use crate::template::Vars;
+use anyhow::{Context, Result};
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::AbortHandle;
+use tracing::{error, info};
+
+/// Quiet period before reloading, so a burst of writes (an editor save, a
+/// git checkout) triggers a single recompile
+const DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// Start watching `dir`, reloading it into `policy` (with its templates
+/// rendered from `vars`) on change, until the returned handle is aborted
+pub fn start(
+    dir: PathBuf,
+    vars: Vars,
+    policy: Arc<Mutex<PolicyStore>>,
+    decisions: Vec<Namespace>,
+) -> Result<AbortHandle> {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+        if let Ok(event) = res {
+            if !matches!(event.kind, EventKind::Access(_))
+                && event.paths.iter().any(|p| is_policy_file(p))
+            {
+                let _ = tx.send(());
+            }
+        }
+    })
+    .context("Failed to create policy watcher")?;
+    watcher
+        .watch(&dir, RecursiveMode::Recursive)
+        .with_context(|| format!("Failed to watch {}", dir.display()))?;
+
+    info!(dir = %dir.display(), "Watching policy directory");
+
+    let task = tokio::spawn(async move {
+        // Keep the watcher alive for as long as the task runs
+        let _watcher = watcher;
+
+        while rx.recv().await.is_some() {
+            tokio::time::sleep(DEBOUNCE).await;
+            while rx.try_recv().is_ok() {}
+
+            let result = match PolicySet::from_dir(&dir).and_then(|set| set.rendered(&vars)) {
+                Ok(set) => policy::activate(&policy, &decisions, set).await,
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(()) => info!(dir = %dir.display(), "Reloaded policy directory"),
+                Err(e) => error!(
+                    error = %format!("{:#}", e),
+                    "Policy reload failed; keeping current policy"
+                ),
+            }
+        }
+    });
+
+    Ok(task.abort_handle())
+}
+
+fn is_policy_file(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "rego")
+        || policy::is_template(path)
+        || path.file_name().is_some_and(|name| name == "data.json")
+}
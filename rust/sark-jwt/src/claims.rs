@@ -0,0 +1,224 @@
+//! Token claims to identity mapping
+//!
+//! Identity providers disagree on where identity lives in a token: roles
+//! may be in `roles`, `groups`, `realm_access.roles` or a namespaced claim
+//! like `https://example.com/roles`. A [`ClaimMapping`] names where to find
+//! each field, as JSONPath-style paths (the gateway's `[claims]` section):
+//!
+//! ```toml
+//! [claims]
+//! user_id = "sub"
+//! email = "email"
+//! roles = ["realm_access.roles", "groups"]
+//! permissions = ["permissions", "scope"]
+//! ```
+//!
+//! A field may list several paths; the first that is present wins. Paths
+//! are dot-separated keys with an optional leading `$.`, `[N]` indexes and
+//! `["..."]` for keys containing dots. List fields accept an array of
+//! strings or a single string, which is split on whitespace (so a `scope`
+//! claim works as-is).
+//!
+//! `tenant` has no default; set it to the claim naming the caller's tenant.
+
+use crate::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Where each identity field is read from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClaimMapping {
+    #[serde(deserialize_with = "paths")]
+    pub user_id: Vec<ClaimPath>,
+    #[serde(deserialize_with = "paths")]
+    pub email: Vec<ClaimPath>,
+    #[serde(deserialize_with = "paths")]
+    pub roles: Vec<ClaimPath>,
+    #[serde(deserialize_with = "paths")]
+    pub permissions: Vec<ClaimPath>,
+    #[serde(deserialize_with = "paths")]
+    pub tenant: Vec<ClaimPath>,
+}
+
+impl Default for ClaimMapping {
+    fn default() -> Self {
+        let path = |p: &str| ClaimPath::parse(p).expect("valid default claim path");
+        Self {
+            user_id: vec![path("sub")],
+            email: vec![path("email")],
+            roles: vec![path("roles")],
+            permissions: vec![path("permissions"), path("scope")],
+            tenant: Vec::new(),
+        }
+    }
+}
+
+/// The caller a token's claims describe
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Identity {
+    pub user_id: String,
+    pub email: String,
+    pub roles: Vec<String>,
+    pub permissions: Vec<String>,
+    pub tenant: Option<String>,
+}
+
+impl ClaimMapping {
+    /// Read the caller's identity from verified `claims`
+    pub fn identity(&self, claims: &serde_json::Value) -> Result<Identity, Error> {
+        let Some(user_id) = first(&self.user_id, claims).and_then(|v| v.as_str()) else {
+            return Err(Error::Claims(format!(
+                "Token has no user id claim ({})",
+                describe(&self.user_id)
+            )));
+        };
+
+        Ok(Identity {
+            user_id: user_id.to_string(),
+            email: first(&self.email, claims)
+                .and_then(|v| v.as_str())
+                .unwrap_or_default()
+                .to_string(),
+            roles: strings(first(&self.roles, claims)),
+            permissions: strings(first(&self.permissions, claims)),
+            tenant: first(&self.tenant, claims)
+                .and_then(|v| v.as_str())
+                .map(str::to_string),
+        })
+    }
+}
+
+/// One step into a claims document
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+/// A parsed JSONPath-style claim location
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClaimPath {
+    source: String,
+    segments: Vec<Segment>,
+}
+
+impl ClaimPath {
+    pub fn parse(source: &str) -> Result<Self, Error> {
+        let mut rest = source.strip_prefix('$').unwrap_or(source);
+        let mut segments = Vec::new();
+
+        while !rest.is_empty() {
+            if let Some(after) = rest.strip_prefix("[\"") {
+                let Some(end) = after.find("\"]") else {
+                    return Err(Error::ClaimPath(format!(
+                        "Unterminated [\"...\"] in claim path {:?}",
+                        source
+                    )));
+                };
+                segments.push(Segment::Key(after[..end].to_string()));
+                rest = &after[end + 2..];
+            } else if let Some(after) = rest.strip_prefix('[') {
+                let Some(end) = after.find(']') else {
+                    return Err(Error::ClaimPath(format!(
+                        "Unterminated [...] in claim path {:?}",
+                        source
+                    )));
+                };
+                let Ok(index) = after[..end].trim().parse() else {
+                    return Err(Error::ClaimPath(format!(
+                        "Invalid index [{}] in claim path {:?}",
+                        &after[..end],
+                        source
+                    )));
+                };
+                segments.push(Segment::Index(index));
+                rest = &after[end + 1..];
+            } else {
+                // A leading `.` is allowed ("$.sub"); elsewhere it separates keys
+                let key = rest.strip_prefix('.').unwrap_or(rest);
+                let end = key.find(['.', '[']).unwrap_or(key.len());
+                if end == 0 {
+                    return Err(Error::ClaimPath(format!(
+                        "Empty key in claim path {:?}",
+                        source
+                    )));
+                }
+                segments.push(Segment::Key(key[..end].to_string()));
+                rest = &key[end..];
+            }
+        }
+
+        if segments.is_empty() {
+            return Err(Error::ClaimPath("Empty claim path".to_string()));
+        }
+        Ok(Self {
+            source: source.to_string(),
+            segments,
+        })
+    }
+
+    fn resolve<'a>(&self, claims: &'a serde_json::Value) -> Option<&'a serde_json::Value> {
+        self.segments
+            .iter()
+            .try_fold(claims, |value, segment| match segment {
+                Segment::Key(key) => value.get(key.as_str()),
+                Segment::Index(index) => value.get(*index),
+            })
+            .filter(|v| !v.is_null())
+    }
+}
+
+impl Serialize for ClaimPath {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.source)
+    }
+}
+
+fn first<'a>(paths: &[ClaimPath], claims: &'a serde_json::Value) -> Option<&'a serde_json::Value> {
+    paths.iter().find_map(|path| path.resolve(claims))
+}
+
+fn describe(paths: &[ClaimPath]) -> String {
+    paths
+        .iter()
+        .map(|p| p.source.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// A claim as a list of strings; a string is split on whitespace
+fn strings(value: Option<&serde_json::Value>) -> Vec<String> {
+    match value {
+        Some(serde_json::Value::Array(items)) => items
+            .iter()
+            .filter_map(|v| v.as_str())
+            .map(str::to_string)
+            .collect(),
+        Some(serde_json::Value::String(s)) => s.split_whitespace().map(str::to_string).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// A path or list of fallback paths
+fn paths<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<ClaimPath>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    let sources = match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(source) => vec![source],
+        OneOrMany::Many(sources) => sources,
+    };
+    if sources.is_empty() {
+        return Err(serde::de::Error::custom(
+            "at least one claim path is required",
+        ));
+    }
+    sources
+        .iter()
+        .map(|source| ClaimPath::parse(source).map_err(serde::de::Error::custom))
+        .collect()
+}
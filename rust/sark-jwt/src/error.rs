@@ -0,0 +1,29 @@
+//! Validation errors
+
+use jsonwebtoken::Algorithm;
+
+/// Why a token (or the validator) was refused
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Malformed token")]
+    Malformed,
+    /// HMAC, or an algorithm a JWKS key can't verify
+    #[error("Unsupported token algorithm {0:?}")]
+    UnsupportedAlgorithm(Algorithm),
+    /// The token's `kid` isn't in the key set, even after refetching it
+    #[error("Unknown token signing key")]
+    UnknownKey,
+    /// Bad signature, expired, or for another audience or issuer
+    #[error("Invalid token: {0}")]
+    Invalid(#[source] jsonwebtoken::errors::Error),
+    /// The claims don't name the caller as a [`ClaimMapping`] expects
+    ///
+    /// [`ClaimMapping`]: crate::ClaimMapping
+    #[error("{0}")]
+    Claims(String),
+    #[error("{0}")]
+    ClaimPath(String),
+    /// The key set couldn't be fetched or used
+    #[error("{0}")]
+    Jwks(String),
+}
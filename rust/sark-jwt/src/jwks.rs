@@ -0,0 +1,93 @@
+//! Signing keys fetched from a JWKS endpoint
+
+use crate::Error;
+use chrono::{DateTime, Utc};
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::DecodingKey;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// Signing keys from the last fetch
+#[derive(Clone, Default)]
+pub(crate) struct KeySet {
+    pub keys: HashMap<String, DecodingKey>,
+    /// Key used for tokens without a `kid`, when the set has exactly one
+    pub only: Option<DecodingKey>,
+    pub fetched_at: Option<Instant>,
+    /// When the keys were last fetched successfully
+    pub synced_at: Option<DateTime<Utc>>,
+    /// Why a refetch failed since then
+    pub last_error: Option<String>,
+}
+
+impl KeySet {
+    pub fn get(&self, kid: Option<&str>) -> Option<DecodingKey> {
+        match kid {
+            Some(kid) => self.keys.get(kid).cloned(),
+            None => self.only.clone(),
+        }
+    }
+
+    pub fn older_than(&self, age: Duration) -> bool {
+        self.fetched_at.map_or(true, |at| at.elapsed() >= age)
+    }
+
+    /// The key set published at `url`
+    pub async fn fetch(client: &reqwest::Client, url: &str) -> Result<Self, Error> {
+        let response = client
+            .get(url)
+            .send()
+            .await
+            .map_err(|e| Error::Jwks(format!("JWKS fetch failed: {}", e)))?;
+        if !response.status().is_success() {
+            return Err(Error::Jwks(format!(
+                "JWKS fetch returned {}",
+                response.status()
+            )));
+        }
+        let set: JwkSet = response
+            .json()
+            .await
+            .map_err(|e| Error::Jwks(format!("Invalid JWKS document: {}", e)))?;
+
+        let mut keys = HashMap::new();
+        let mut only = None;
+        for jwk in &set.keys {
+            let key = match DecodingKey::from_jwk(jwk) {
+                Ok(key) => key,
+                Err(e) => {
+                    warn!(kid = ?jwk.common.key_id, error = %e, "Skipping unusable JWKS key");
+                    continue;
+                }
+            };
+            if set.keys.len() == 1 {
+                only = Some(key.clone());
+            }
+            if let Some(kid) = &jwk.common.key_id {
+                keys.insert(kid.clone(), key);
+            }
+        }
+        if keys.is_empty() && only.is_none() {
+            return Err(Error::Jwks("JWKS contains no usable keys".to_string()));
+        }
+
+        Ok(Self {
+            keys,
+            only,
+            fetched_at: Some(Instant::now()),
+            synced_at: Some(Utc::now()),
+            last_error: None,
+        })
+    }
+}
+
+/// The JWKS as last fetched
+#[derive(Debug, Default, Serialize)]
+pub struct KeyStatus {
+    pub keys: usize,
+    pub synced_at: Option<DateTime<Utc>>,
+    /// Why the last refetch failed, if it did
+    pub last_error: Option<String>,
+}
@@ -0,0 +1,25 @@
+//! JWT validation against a cached JWKS
+//!
+//! Tokens are verified (signature, `exp`, and `aud`/`iss` when configured)
+//! against the signing keys published at a JWKS URL. The key set is cached
+//! and refetched every refresh interval, or sooner when a token names a key
+//! id it doesn't contain (the IdP rotated keys). A failed refetch keeps the
+//! previous keys. Verified claims are mapped to the caller's identity by a
+//! [`ClaimMapping`].
+//!
+//! The gateway's auth middleware is built on this, and with the `python`
+//! feature it is exposed to the Python API as `RustJWTValidator`, so both
+//! accept exactly the same tokens.
+
+mod claims;
+mod error;
+mod jwks;
+mod validator;
+
+#[cfg(feature = "python")]
+pub mod python;
+
+pub use claims::{ClaimMapping, ClaimPath, Identity};
+pub use error::Error;
+pub use jwks::KeyStatus;
+pub use validator::Validator;
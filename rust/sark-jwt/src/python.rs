@@ -0,0 +1,107 @@
+//! Python bindings
+//!
+//! `RustJWTValidator` validates tokens for the Python API, in place of
+//! python-jose:
+//!
+//! ```python
+//! from sark.sark_rust import JWTValidationError, RustJWTValidator
+//!
+//! validator = RustJWTValidator(
+//!     "https://idp.example.com/.well-known/jwks.json",
+//!     audience="sark",
+//!     claims={"roles": ["realm_access.roles", "groups"]},
+//! )
+//! claims = validator.validate(token)          # verified claims, as a dict
+//! user = validator.extract_claims(token)      # user_id, email, roles, ...
+//! ```
+//!
+//! Calls release the GIL, and block only when the key set has to be
+//! refetched. Invalid tokens raise `JWTValidationError` with the same
+//! messages the gateway answers 401 with.
+
+use crate::{ClaimMapping, Error, Validator};
+use pyo3::create_exception;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pythonize::{depythonize, pythonize};
+use std::time::Duration;
+
+create_exception!(
+    sark_rust,
+    JWTValidationError,
+    PyValueError,
+    "The token (or the key set) was refused"
+);
+
+/// Validates JWTs against a cached JWKS
+#[pyclass(module = "sark_rust")]
+pub struct RustJWTValidator {
+    validator: Validator,
+    claims: ClaimMapping,
+}
+
+#[pymethods]
+impl RustJWTValidator {
+    /// Fetch the key set at `jwks_url`, raising if it can't be. `claims`
+    /// maps identity fields to claim paths, as the gateway's `[claims]`.
+    #[new]
+    #[pyo3(signature = (jwks_url, *, audience = None, issuer = None, refresh_interval = 300, claims = None))]
+    fn new(
+        py: Python<'_>,
+        jwks_url: String,
+        audience: Option<String>,
+        issuer: Option<String>,
+        refresh_interval: u64,
+        claims: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<Self> {
+        let claims = match claims {
+            Some(claims) => depythonize(claims)
+                .map_err(|e| PyValueError::new_err(format!("Invalid claim mapping: {}", e)))?,
+            None => ClaimMapping::default(),
+        };
+        let runtime = pyo3_async_runtimes::tokio::get_runtime();
+        let validator = py
+            .allow_threads(|| {
+                runtime.block_on(Validator::new(
+                    &jwks_url,
+                    audience,
+                    issuer,
+                    Duration::from_secs(refresh_interval),
+                ))
+            })
+            .map_err(to_py_err)?;
+        Ok(Self { validator, claims })
+    }
+
+    /// The verified claims of `token`
+    fn validate<'py>(&self, py: Python<'py>, token: &str) -> PyResult<Bound<'py, PyAny>> {
+        let claims = self.claims_of(py, token)?;
+        Ok(pythonize(py, &claims)?)
+    }
+
+    /// The caller `token` identifies (`user_id`, `email`, `roles`,
+    /// `permissions`, `tenant`), once verified
+    fn extract_claims<'py>(&self, py: Python<'py>, token: &str) -> PyResult<Bound<'py, PyAny>> {
+        let claims = self.claims_of(py, token)?;
+        let identity = self.claims.identity(&claims).map_err(to_py_err)?;
+        Ok(pythonize(py, &identity)?)
+    }
+
+    /// Signing keys currently held
+    fn key_count(&self, py: Python<'_>) -> usize {
+        let runtime = pyo3_async_runtimes::tokio::get_runtime();
+        py.allow_threads(|| runtime.block_on(self.validator.key_count()))
+    }
+}
+
+impl RustJWTValidator {
+    fn claims_of(&self, py: Python<'_>, token: &str) -> PyResult<serde_json::Value> {
+        let runtime = pyo3_async_runtimes::tokio::get_runtime();
+        py.allow_threads(|| runtime.block_on(self.validator.validate(token)))
+            .map_err(to_py_err)
+    }
+}
+
+fn to_py_err(error: Error) -> PyErr {
+    JWTValidationError::new_err(error.to_string())
+}
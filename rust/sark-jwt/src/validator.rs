@@ -0,0 +1,163 @@
+//! Token validation against a cached key set
+
+use crate::jwks::{KeySet, KeyStatus};
+use crate::Error;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, RwLock};
+use tracing::{debug, info, warn};
+
+/// Minimum gap between refetches triggered by unknown key ids, so tokens
+/// with made-up `kid`s can't hammer the IdP
+const MIN_REFETCH: Duration = Duration::from_secs(30);
+
+/// Asymmetric algorithms accepted from token headers. HMAC is never
+/// accepted: a JWKS carries public keys only.
+const ALGORITHMS: &[Algorithm] = &[
+    Algorithm::RS256,
+    Algorithm::RS384,
+    Algorithm::RS512,
+    Algorithm::PS256,
+    Algorithm::PS384,
+    Algorithm::PS512,
+    Algorithm::ES256,
+    Algorithm::ES384,
+    Algorithm::EdDSA,
+];
+
+/// Validates JWTs against the keys published at a JWKS URL
+pub struct Validator {
+    jwks_url: String,
+    client: reqwest::Client,
+    audience: Option<String>,
+    issuer: Option<String>,
+    refresh: Duration,
+    keys: RwLock<Arc<KeySet>>,
+    /// Serializes refetches so a burst of requests triggers one fetch
+    refreshing: Mutex<()>,
+}
+
+impl Validator {
+    /// Fetch the key set once, failing if the IdP is unreachable. Tokens
+    /// must be for `audience` and from `issuer` when they are given, and
+    /// the keys are refetched every `refresh`.
+    pub async fn new(
+        jwks_url: &str,
+        audience: Option<String>,
+        issuer: Option<String>,
+        refresh: Duration,
+    ) -> Result<Self, Error> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .map_err(|e| Error::Jwks(format!("Failed to build JWKS client: {}", e)))?;
+
+        let keys = KeySet::fetch(&client, jwks_url).await?;
+        info!(url = %jwks_url, keys = keys.keys.len(), "JWKS loaded");
+        Ok(Self {
+            jwks_url: jwks_url.to_string(),
+            client,
+            audience,
+            issuer,
+            refresh,
+            keys: RwLock::new(Arc::new(keys)),
+            refreshing: Mutex::new(()),
+        })
+    }
+
+    /// Whether `token` looks like a JWT at all, rather than an opaque
+    /// token for introspection
+    pub fn is_jwt(token: &str) -> bool {
+        jsonwebtoken::decode_header(token).is_ok()
+    }
+
+    /// Verify `token`'s signature, `exp` and (when configured) `aud` and
+    /// `iss`, returning its claims
+    pub async fn validate(&self, token: &str) -> Result<serde_json::Value, Error> {
+        let header = jsonwebtoken::decode_header(token).map_err(|_| Error::Malformed)?;
+        if !ALGORITHMS.contains(&header.alg) {
+            return Err(Error::UnsupportedAlgorithm(header.alg));
+        }
+
+        let key = self
+            .key(header.kid.as_deref())
+            .await
+            .ok_or(Error::UnknownKey)?;
+
+        let mut validation = Validation::new(header.alg);
+        match &self.audience {
+            Some(audience) => validation.set_audience(&[audience]),
+            None => validation.validate_aud = false,
+        }
+        if let Some(issuer) = &self.issuer {
+            validation.set_issuer(&[issuer]);
+        }
+
+        jsonwebtoken::decode::<serde_json::Value>(token, &key, &validation)
+            .map(|data| data.claims)
+            .map_err(Error::Invalid)
+    }
+
+    /// Whether the key `kid` names is still in the set, refetching it as
+    /// `validate` would; a token signed with a key rotated out is no longer
+    /// to be trusted, however it verified before
+    pub async fn trusts(&self, kid: Option<&str>) -> bool {
+        self.key(kid).await.is_some()
+    }
+
+    /// Key for `kid`, refetching the set when it is stale or (rate-limited)
+    /// when `kid` isn't in it
+    async fn key(&self, kid: Option<&str>) -> Option<DecodingKey> {
+        let keys = self.keys.read().await.clone();
+        if let Some(key) = keys.get(kid).filter(|_| !keys.older_than(self.refresh)) {
+            return Some(key);
+        }
+
+        let _guard = self.refreshing.lock().await;
+        // Another request may have refetched while we waited
+        let keys = self.keys.read().await.clone();
+        let needs_refetch = match keys.get(kid) {
+            Some(_) => keys.older_than(self.refresh),
+            None => keys.older_than(MIN_REFETCH),
+        };
+        if !needs_refetch {
+            return keys.get(kid);
+        }
+
+        let fresh = match KeySet::fetch(&self.client, &self.jwks_url).await {
+            Ok(fresh) => {
+                debug!(keys = fresh.keys.len(), "JWKS refreshed");
+                fresh
+            }
+            Err(e) => {
+                // Keep the current keys until the next refresh, rather than
+                // refetching on every request while the IdP is down
+                warn!(error = %e, "JWKS refresh failed; keeping current keys");
+                KeySet {
+                    fetched_at: Some(Instant::now()),
+                    last_error: Some(e.to_string()),
+                    ..(*keys).clone()
+                }
+            }
+        };
+        let key = fresh.get(kid);
+        *self.keys.write().await = Arc::new(fresh);
+        key
+    }
+
+    /// Signing keys currently held
+    pub async fn key_count(&self) -> usize {
+        self.keys.read().await.keys.len()
+    }
+
+    /// How the key set is keeping up, for health checks
+    pub async fn key_status(&self) -> KeyStatus {
+        let keys = self.keys.read().await;
+        KeyStatus {
+            keys: keys.keys.len(),
+            synced_at: keys.synced_at,
+            last_error: keys.last_error.clone(),
+        }
+    }
+}
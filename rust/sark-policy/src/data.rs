@@ -0,0 +1,141 @@
+//! Data documents
+//!
+//! grid-opa's engine only accepts policy sources, so data is loaded as
+//! generated modules whose rules hold the JSON values: `data.json` with
+//! `{"roles": {"admins": ["alice"]}}` becomes `package roles` with
+//! `admins := ["alice"]`, which policies read as `data.roles.admins`
+//! exactly as they would with OPA.
+
+use crate::Error;
+use serde_json::{Map, Value};
+
+/// Replace the document at slash-separated `path` in `data` (`""` is the
+/// root, which must be an object)
+pub fn set_data(data: &mut Map<String, Value>, path: &str, value: Value) -> Result<(), Error> {
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    let Some((last, parents)) = segments.split_last() else {
+        match value {
+            Value::Object(map) => *data = map,
+            _ => return Err(Error::DataRoot),
+        }
+        return Ok(());
+    };
+
+    let mut node = data;
+    for segment in parents {
+        node = match node
+            .entry(segment.to_string())
+            .or_insert_with(|| Value::Object(Map::new()))
+        {
+            Value::Object(map) => map,
+            _ => return Err(Error::DataConflict(path.to_string())),
+        };
+    }
+    node.insert(last.to_string(), value);
+    Ok(())
+}
+
+/// Remove the document at `path` from `data`, returning whether it
+/// existed
+pub fn remove_data(data: &mut Map<String, Value>, path: &str) -> bool {
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    let Some((last, parents)) = segments.split_last() else {
+        let existed = !data.is_empty();
+        data.clear();
+        return existed;
+    };
+
+    let mut node = data;
+    for segment in parents {
+        match node.get_mut(*segment) {
+            Some(Value::Object(map)) => node = map,
+            _ => return false,
+        }
+    }
+    node.remove(*last).is_some()
+}
+
+/// Apply an RFC 6902 JSON Patch to `data` (paths are relative to `data`,
+/// e.g. `/roles/admins/-`); on an error `data` is left as it was
+pub fn patch_data(data: &mut Map<String, Value>, patch: &json_patch::Patch) -> Result<(), Error> {
+    let mut document = Value::Object(data.clone());
+    json_patch::patch(&mut document, patch)?;
+    match document {
+        Value::Object(map) => *data = map,
+        _ => return Err(Error::DataRoot),
+    }
+    Ok(())
+}
+
+/// Render `data` as `(name, source)` rego modules
+///
+/// Objects whose keys are all rego identifiers become packages; anything
+/// else becomes a rule in the enclosing package. Top-level values must be
+/// objects since rego has no rules outside a package; an empty one (left
+/// by removing the last document under it) renders nothing.
+pub fn data_modules(data: &Map<String, Value>) -> Result<Vec<(String, String)>, Error> {
+    let mut modules = Vec::new();
+
+    for (key, value) in data {
+        if value.as_object().is_some_and(Map::is_empty) {
+            continue;
+        }
+        if !is_package_node(value) || !is_identifier(key) {
+            return Err(Error::DataKey(key.clone()));
+        }
+        render_package(&mut vec![key.as_str()], value, &mut modules);
+    }
+
+    Ok(modules)
+}
+
+fn render_package<'a>(
+    path: &mut Vec<&'a str>,
+    node: &'a Value,
+    modules: &mut Vec<(String, String)>,
+) {
+    let Value::Object(map) = node else {
+        return;
+    };
+
+    let mut rules = String::new();
+    for (key, value) in map {
+        if is_package_node(value) {
+            path.push(key);
+            render_package(path, value, modules);
+            path.pop();
+        } else {
+            rules.push_str(&format!("{} := {}\n", key, value));
+        }
+    }
+
+    if !rules.is_empty() {
+        modules.push((
+            format!("__data__/{}", path.join("/")),
+            format!("package {}\n\n{}", path.join("."), rules),
+        ));
+    }
+}
+
+/// Whether `value` should become its own package rather than a rule
+fn is_package_node(value: &Value) -> bool {
+    match value {
+        Value::Object(map) => !map.is_empty() && map.keys().all(|k| is_identifier(k)),
+        _ => false,
+    }
+}
+
+/// Rego keywords that can't be used as rule or package names
+const REGO_KEYWORDS: &[&str] = &[
+    "as", "contains", "default", "else", "every", "false", "if", "import", "in", "not", "null",
+    "package", "some", "true", "with",
+];
+
+fn is_identifier(key: &str) -> bool {
+    let mut chars = key.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !REGO_KEYWORDS.contains(&key)
+}
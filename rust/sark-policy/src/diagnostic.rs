@@ -0,0 +1,59 @@
+//! Compile diagnostics
+
+use crate::Error;
+use grid_opa::OPAEngine;
+use serde::Serialize;
+
+/// A problem found while compiling a policy
+#[derive(Debug, Serialize)]
+pub struct Diagnostic {
+    pub severity: &'static str,
+    pub message: String,
+    pub file: String,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+}
+
+impl Diagnostic {
+    /// The compile error `error` reported for `file`
+    ///
+    /// grid-opa reports compile errors as text; the location and message
+    /// are recovered from regorus' `--> file:line:col` / `error: ...`
+    /// layout, and anything unrecognized is reported whole without a
+    /// location.
+    pub fn of(file: &str, error: &str) -> Self {
+        let location = error.lines().find_map(|line| {
+            let (rest, column) = line.trim().strip_prefix("--> ")?.rsplit_once(':')?;
+            let (_, line) = rest.rsplit_once(':')?;
+            Some((line.parse().ok()?, column.parse().ok()?))
+        });
+        let message = error
+            .lines()
+            .find_map(|line| line.trim().strip_prefix("error: "))
+            .unwrap_or(error.trim())
+            .to_string();
+
+        Self {
+            severity: "error",
+            message,
+            file: file.to_string(),
+            line: location.map(|(line, _)| line),
+            column: location.map(|(_, column)| column),
+        }
+    }
+}
+
+/// Compile `source` on its own and report any errors as diagnostics
+pub fn validate_policy(file: &str, source: &str) -> Result<Vec<Diagnostic>, Error> {
+    // Policies are named without their extension, as the gateway names
+    // them
+    let name = file
+        .strip_suffix(".rego.tmpl")
+        .or_else(|| file.strip_suffix(".rego"))
+        .unwrap_or(file);
+    let mut engine = OPAEngine::new().map_err(|e| Error::Engine(e.to_string()))?;
+    match engine.load_policy(name.to_string(), source.to_string()) {
+        Ok(_) => Ok(Vec::new()),
+        Err(e) => Ok(vec![Diagnostic::of(file, &e.to_string())]),
+    }
+}
@@ -0,0 +1,48 @@
+//! Canonical JSON and input digests
+
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+/// `value` serialized with sorted keys and no whitespace, so equal
+/// documents always produce the same bytes
+pub fn canonical_json(value: &Value) -> String {
+    let mut canonical = String::new();
+    write_canonical(value, &mut canonical);
+    canonical
+}
+
+fn write_canonical(value: &Value, out: &mut String) {
+    match value {
+        Value::Object(map) => {
+            let mut keys: Vec<_> = map.keys().collect();
+            keys.sort();
+            out.push('{');
+            for (i, key) in keys.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::from(key.as_str()).to_string());
+                out.push(':');
+                write_canonical(&map[key], out);
+            }
+            out.push('}');
+        }
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out);
+            }
+            out.push(']');
+        }
+        scalar => out.push_str(&scalar.to_string()),
+    }
+}
+
+/// Hex SHA-256 of a policy input document, in canonical form, as
+/// decision logs record it in place of the input
+pub fn input_digest(input: &Value) -> String {
+    hex::encode(Sha256::digest(canonical_json(input).as_bytes()))
+}
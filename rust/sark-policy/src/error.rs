@@ -0,0 +1,23 @@
+//! Policy errors
+
+/// Why a policy source couldn't be handled
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Failed to initialize OPA engine: {0}")]
+    Engine(String),
+    #[error("root data document must be an object")]
+    DataRoot,
+    #[error("data path {0} conflicts with a non-object value")]
+    DataConflict(String),
+    #[error("top-level data key {0:?} must be an object with identifier keys")]
+    DataKey(String),
+    #[error("Failed to apply JSON patch: {0}")]
+    Patch(#[from] json_patch::PatchError),
+    #[error("Failed to compile policy {name}: {message}")]
+    Compile { name: String, message: String },
+    #[error("Failed to read test coverage: {0}")]
+    Coverage(String),
+    /// The document isn't a valid JSON Schema
+    #[error("{0}")]
+    Schema(String),
+}
@@ -0,0 +1,31 @@
+//! Rego policy sources
+//!
+//! The gateway and `RustOPAEngine` in the Python API both compile rego
+//! with grid-opa. What they do with it beyond compiling and evaluating is
+//! kept here, so the two read policies alike: a compile error is recovered
+//! as a [`Diagnostic`] with the file, line and column regorus reports, for
+//! `check-policy` and for the exception `RustOPAEngine` raises, and data
+//! documents are set, patched and rendered into modules the same way (see
+//! [`data_modules`]). Decision logs record an input by its
+//! [`input_digest`], so the two log the same input under the same hash.
+//! Both run a set's `test_*` rules, with coverage, by [`run_tests`], and
+//! check input against a JSON [`Schema`] with the same messages, and
+//! count evaluations by [`Outcome`] into the same latency buckets.
+
+mod data;
+mod diagnostic;
+mod digest;
+mod error;
+mod metrics;
+mod rules;
+mod schema;
+mod testing;
+
+pub use data::{data_modules, patch_data, remove_data, set_data};
+pub use diagnostic::{validate_policy, Diagnostic};
+pub use digest::{canonical_json, input_digest};
+pub use error::Error;
+pub use metrics::{Outcome, QueryMetrics, EVALUATION_BUCKETS};
+pub use rules::{package_of, rules};
+pub use schema::Schema;
+pub use testing::{run_tests, FileCoverage, TestReport, TestResult};
@@ -0,0 +1,95 @@
+//! Evaluation metrics
+//!
+//! The gateway exports these through Prometheus; `RustOPAEngine` keeps
+//! them itself and returns them from `metrics()`. Both count outcomes the
+//! same way and use the same latency buckets, so the two read alike.
+
+use serde::Serialize;
+use serde_json::Value;
+use std::time::Duration;
+
+/// Policy evaluation buckets, in seconds; evaluations are expected well
+/// under 1ms
+pub const EVALUATION_BUCKETS: &[f64] = &[
+    0.00005, 0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.1,
+];
+
+/// How an evaluation ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Allow,
+    Deny,
+    Error,
+}
+
+impl Outcome {
+    /// The outcome of a query that evaluated to `document`: allow if it is
+    /// `true` (a rule) or has an `allow` member that is (a package)
+    pub fn of(document: &Value) -> Self {
+        let allow = match document {
+            Value::Bool(allow) => *allow,
+            document => document["allow"] == Value::Bool(true),
+        };
+        if allow {
+            Outcome::Allow
+        } else {
+            Outcome::Deny
+        }
+    }
+
+    /// Metric label
+    pub fn label(self) -> &'static str {
+        match self {
+            Outcome::Allow => "allow",
+            Outcome::Deny => "deny",
+            Outcome::Error => "error",
+        }
+    }
+}
+
+/// One query's evaluations: counts by outcome, and how long those that
+/// didn't fail took
+#[derive(Debug, Clone, Serialize)]
+pub struct QueryMetrics {
+    pub allow: u64,
+    pub deny: u64,
+    pub error: u64,
+    /// Seconds the decisions took, in total
+    pub latency_seconds_sum: f64,
+    /// `(bound, count)` for each of [`EVALUATION_BUCKETS`]: decisions that
+    /// took at most `bound` seconds
+    pub latency_seconds_buckets: Vec<(f64, u64)>,
+}
+
+impl Default for QueryMetrics {
+    fn default() -> Self {
+        Self {
+            allow: 0,
+            deny: 0,
+            error: 0,
+            latency_seconds_sum: 0.0,
+            latency_seconds_buckets: EVALUATION_BUCKETS.iter().map(|&bound| (bound, 0)).collect(),
+        }
+    }
+}
+
+impl QueryMetrics {
+    /// Count an evaluation that ended in `outcome` after `elapsed` (`None`
+    /// for an error)
+    pub fn record(&mut self, outcome: Outcome, elapsed: Option<Duration>) {
+        match outcome {
+            Outcome::Allow => self.allow += 1,
+            Outcome::Deny => self.deny += 1,
+            Outcome::Error => self.error += 1,
+        }
+        if let Some(elapsed) = elapsed {
+            let seconds = elapsed.as_secs_f64();
+            self.latency_seconds_sum += seconds;
+            for (bound, count) in &mut self.latency_seconds_buckets {
+                if seconds <= *bound {
+                    *count += 1;
+                }
+            }
+        }
+    }
+}
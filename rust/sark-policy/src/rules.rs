@@ -0,0 +1,37 @@
+//! Rules read from module sources
+
+/// The package a module declares
+pub fn package_of(source: &str) -> Option<&str> {
+    source
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("package "))
+        .map(str::trim)
+}
+
+/// Every rule `sources` define, as fully qualified `data.<package>.<rule>`
+/// queries in source order
+pub fn rules<'a>(sources: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let mut rules = Vec::new();
+    for source in sources {
+        let Some(package) = package_of(source) else {
+            continue;
+        };
+        for line in source.lines() {
+            // Rule heads start at column 0; anything indented is a body
+            let head = line.strip_prefix("default ").unwrap_or(line);
+            let name_len = head
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(head.len());
+            let name = &head[..name_len];
+            if name.is_empty() || matches!(name, "package" | "import" | "else") {
+                continue;
+            }
+            let query = format!("data.{}.{}", package, name);
+            // Incremental definitions repeat the head; list each rule once
+            if !rules.contains(&query) {
+                rules.push(query);
+            }
+        }
+    }
+    rules
+}
@@ -0,0 +1,42 @@
+//! JSON Schemas for policy input and results
+
+use crate::Error;
+use jsonschema::JSONSchema;
+use serde_json::Value;
+
+/// Violations reported per rejected document
+const MAX_ERRORS: usize = 10;
+
+/// A compiled JSON Schema
+pub struct Schema {
+    schema: JSONSchema,
+}
+
+impl Schema {
+    pub fn compile(document: &Value) -> Result<Self, Error> {
+        let schema = JSONSchema::compile(document).map_err(|e| Error::Schema(e.to_string()))?;
+        Ok(Self { schema })
+    }
+
+    /// Each way `document` fails the schema (up to `MAX_ERRORS` of them),
+    /// as `<path>: <problem>` joined by `; `, or `None` if it doesn't
+    pub fn violations(&self, document: &Value) -> Option<String> {
+        let Err(errors) = self.schema.validate(document) else {
+            return None;
+        };
+
+        let messages: Vec<String> = errors
+            .take(MAX_ERRORS)
+            .map(|e| {
+                let path = e.instance_path.to_string();
+                let path = if path.is_empty() {
+                    "/".to_string()
+                } else {
+                    path
+                };
+                format!("{}: {}", path, e)
+            })
+            .collect();
+        Some(messages.join("; "))
+    }
+}
@@ -0,0 +1,138 @@
+//! Rego unit tests
+//!
+//! A test is a rule named `test_*`, passing when it evaluates to `true`,
+//! as with `opa test`. grid-opa's engine has no coverage to report, so
+//! tests run on regorus' own, the engine grid-opa wraps: the modules
+//! compile and evaluate there as they do in grid-opa, and regorus records
+//! which of their lines the tests evaluated.
+
+use crate::{data_modules, rules, Error};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::time::Instant;
+
+/// The outcome of one test rule
+#[derive(Debug, Serialize)]
+pub struct TestResult {
+    /// The rule, as a `data.<package>.test_<name>` query
+    pub name: String,
+    pub passed: bool,
+    /// What the rule evaluated to, as JSON, if not `true`
+    pub got: Option<String>,
+    /// Why the rule failed to evaluate
+    pub error: Option<String>,
+    pub duration_us: u64,
+}
+
+/// Lines of one module the tests evaluated, and those they didn't
+#[derive(Debug, Serialize)]
+pub struct FileCoverage {
+    /// The module's name
+    pub file: String,
+    pub covered: Vec<u32>,
+    pub not_covered: Vec<u32>,
+}
+
+impl FileCoverage {
+    /// Percent of the module's lines the tests evaluated (100 for one with
+    /// no lines to evaluate)
+    pub fn percent(&self) -> f64 {
+        let lines = self.covered.len() + self.not_covered.len();
+        if lines == 0 {
+            return 100.0;
+        }
+        self.covered.len() as f64 * 100.0 / lines as f64
+    }
+}
+
+/// Every test's outcome, in source order, and each module's coverage
+#[derive(Debug, Serialize)]
+pub struct TestReport {
+    pub results: Vec<TestResult>,
+    pub coverage: Vec<FileCoverage>,
+}
+
+impl TestReport {
+    /// Tests that passed
+    pub fn passed(&self) -> usize {
+        self.results.iter().filter(|result| result.passed).count()
+    }
+
+    /// Tests that failed
+    pub fn failed(&self) -> usize {
+        self.results.len() - self.passed()
+    }
+}
+
+/// Run every `test_*` rule `modules` define, `(name, source)` pairs, with
+/// `data` loaded as the data document
+pub fn run_tests(
+    modules: &[(String, String)],
+    data: &Map<String, Value>,
+) -> Result<TestReport, Error> {
+    let mut engine = regorus::Engine::new();
+    engine.set_enable_coverage(true);
+    for (name, source) in modules {
+        engine
+            .add_policy(name.clone(), source.clone())
+            .map_err(|e| Error::Compile {
+                name: name.clone(),
+                message: e.to_string(),
+            })?;
+    }
+    for (name, source) in data_modules(data)? {
+        engine
+            .add_policy(name.clone(), source)
+            .map_err(|e| Error::Compile {
+                name,
+                message: e.to_string(),
+            })?;
+    }
+
+    let tests = rules(modules.iter().map(|(_, source)| source.as_str()))
+        .into_iter()
+        .filter(|rule| {
+            rule.rsplit('.')
+                .next()
+                .is_some_and(|name| name.starts_with("test_"))
+        });
+    let mut results = Vec::new();
+    for name in tests {
+        engine.set_input(regorus::Value::new_object());
+        let started = Instant::now();
+        let result = engine.eval_rule(name.clone());
+        let duration_us = started.elapsed().as_micros() as u64;
+        let (got, error) = match result {
+            Ok(regorus::Value::Bool(true)) => (None, None),
+            Ok(value) => {
+                let got = value
+                    .to_json_str()
+                    .unwrap_or_else(|_| "undefined".to_string());
+                (Some(got), None)
+            }
+            Err(e) => (None, Some(e.to_string())),
+        };
+        results.push(TestResult {
+            name,
+            passed: got.is_none() && error.is_none(),
+            got,
+            error,
+            duration_us,
+        });
+    }
+
+    let report = engine
+        .get_coverage_report()
+        .map_err(|e| Error::Coverage(e.to_string()))?;
+    let coverage = report
+        .files
+        .into_iter()
+        .filter(|file| modules.iter().any(|(name, _)| *name == file.path))
+        .map(|file| FileCoverage {
+            file: file.path,
+            covered: file.covered.into_iter().collect(),
+            not_covered: file.not_covered.into_iter().collect(),
+        })
+        .collect();
+    Ok(TestReport { results, coverage })
+}
@@ -0,0 +1,14 @@
+//! Store errors
+
+/// Why the store refused an entry
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// Pinning the entry would pin more than the store's `max_pinned`, or
+    /// half its size
+    #[error("Cache store pins as many entries as max_pinned allows")]
+    PinLimit,
+    /// The entry is larger than the store's `max_bytes`, or its shard's
+    /// share of it
+    #[error("Cache entry of {size} bytes is larger than the {max_bytes} the store may hold")]
+    TooLarge { size: usize, max_bytes: usize },
+}
@@ -0,0 +1,1180 @@
+//! The in-process store behind the gateway's cache namespaces and the
+//! Python API's `RustCache`
+//!
+//! A full store makes room by its eviction (`cache.eviction` in the
+//! gateway): with `"lru"` (the default) the least recently used entry goes
+//! first. With `"cost"` it evicts by Greedy-Dual-Size-Frequency instead: an
+//! entry's priority is the store's clock plus hits × cost ÷ size, where
+//! cost is how long the decision took to evaluate and size is its key and
+//! value in bytes, and the lowest priority goes first. Each eviction
+//! advances the clock to the evicted priority, so entries that stop being
+//! hit age out however costly they were. A 20ms decision outlives a 0.2ms
+//! one hit as often; entries stored without a measured cost (counters, L2
+//! promotions, snapshot restores) count as costing `DEFAULT_COST_MS`.
+//!
+//! Either way the store is split into `cache.shards` shards by key hash
+//! (16 by default, fewer for small stores), each with its own lock, clock
+//! and eviction order. A lookup locks only its key's shard, so with many
+//! worker threads lookups of different keys rarely contend; `sark-gateway
+//! bench-cache` measures by how much.
+//!
+//! Namespaces configured under `[cache.namespaces.<name>]` get a partition
+//! of their own, with its own size, eviction and TTL ceiling, so a flood of
+//! short-lived decisions can't evict entries meant to last, nor the other
+//! way round:
+//!
+//! ```toml
+//! [cache.namespaces.quota]
+//! max_entries = 50000
+//! max_ttl = 86400
+//!
+//! [cache.namespaces.a2a]
+//! max_entries = 2000
+//! eviction = "cost"
+//! max_ttl = 30
+//! ```
+//!
+//! The rest share the store of `cache.max_entries`. It is still one `Store`
+//! every namespace is handed, routing each key by its namespace prefix.
+//!
+//! Entries set pinned (quota counters, whose eviction would reset a
+//! caller's quota) are never evicted to make room, only deleted or
+//! expired. Each store and partition pins at most `cache.max_pinned`
+//! entries and never more than half its size, so pinned entries can't
+//! starve the rest; past that they are refused, and stored unpinned.
+//!
+//! The shared store can be resized in place (`cache.max_entries` on
+//! reload): each shard takes its share of the new size, and a shrink
+//! evicts down to it at once, in eviction order. Pinned entries stay
+//! until they expire, even past a smaller store's pinning allowance.
+//!
+//! A store built with `record_removals` keeps the entries it evicts or
+//! finds expired, with why, until `removed` takes them, so a caller can
+//! pass them on (to listeners, say) once it holds no lock of the store's.
+//! Deleted and cleared entries aren't recorded.
+//!
+//! `jittered_ttl` spreads the TTLs of entries written in a burst
+//! (`cache.ttl_jitter_pct` in the gateway), so they don't all expire, and
+//! get re-evaluated, at once.
+//!
+//! The gateway stores decisions as strings; any `Value` that can say how
+//! many bytes it takes can be stored.
+
+mod error;
+
+pub use error::Error;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::BuildHasher;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Cost of entries stored without one, in milliseconds
+const DEFAULT_COST_MS: f64 = 1.0;
+
+/// TTL of entries set without one in a partition with no `max_ttl`, in
+/// seconds; every namespace sets its own
+const DEFAULT_PARTITION_TTL: u64 = 300;
+
+/// Which entry a full store evicts to make room
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum Eviction {
+    /// The least recently used
+    #[default]
+    Lru,
+    /// The cheapest to recompute for their size and hits: decisions are
+    /// weighted by how long the policy took to evaluate them
+    Cost,
+}
+
+/// Why an entry left the store without being deleted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Removal {
+    /// To make room for others
+    Evicted,
+    /// Found expired, by a lookup, a set over it or a sweep
+    Expired,
+}
+
+/// A value the store can hold
+pub trait Value: Clone {
+    /// Bytes the value takes, as far as eviction is concerned
+    fn bytes(&self) -> usize;
+}
+
+impl Value for String {
+    fn bytes(&self) -> usize {
+        self.len()
+    }
+}
+
+pub struct Store<V = String> {
+    /// Where namespaces without a partition of their own keep entries
+    shared: Sharded<V>,
+    /// Namespaces given their own size, eviction and TTL ceiling, by name
+    partitions: HashMap<String, Partition<V>>,
+    /// Shards of the shared store and of each partition
+    shards: usize,
+    /// Entries the shared store and each partition may pin
+    max_pinned: usize,
+}
+
+struct Partition<V> {
+    store: Sharded<V>,
+    max_ttl: Option<u64>,
+}
+
+impl<V: Value> Store<V> {
+    /// A store of at most `max_entries` in up to `shards` shards, each
+    /// kept `ttl` seconds unless set with its own TTL, up to `max_pinned`
+    /// of them pinned
+    pub fn new(
+        eviction: Eviction,
+        max_entries: usize,
+        ttl: u64,
+        shards: usize,
+        max_pinned: usize,
+    ) -> Self {
+        Self {
+            shared: Sharded::new(eviction, max_entries, ttl, shards, max_pinned),
+            partitions: HashMap::new(),
+            shards,
+            max_pinned,
+        }
+    }
+
+    /// Keep the entries of namespace `name` apart, at most `max_entries`
+    /// of them evicted by `eviction` and none kept past `max_ttl` seconds
+    ///
+    /// Keys are routed to a partition by their namespace prefix (`{name}:`),
+    /// and a tenant's namespace (`auth@acme`) without a partition of its
+    /// own shares its base namespace's (`auth`), if that has one.
+    pub fn partition(
+        mut self,
+        name: &str,
+        eviction: Eviction,
+        max_entries: usize,
+        max_ttl: Option<u64>,
+    ) -> Self {
+        let ttl = max_ttl.unwrap_or(DEFAULT_PARTITION_TTL);
+        self.partitions.insert(
+            name.to_string(),
+            Partition {
+                store: Sharded::new(eviction, max_entries, ttl, self.shards, self.max_pinned),
+                max_ttl,
+            },
+        );
+        self
+    }
+
+    /// Also hold at most `max_bytes` of keys and values (as `Value::bytes`
+    /// counts them) in the store shared by namespaces without a partition,
+    /// each shard its share; a full store evicts until a new entry fits
+    /// both limits, and refuses one larger than its shard's share
+    pub fn max_bytes(self, max_bytes: usize) -> Self {
+        let count = self.shared.shards.len();
+        for (i, shard) in self.shared.shards.iter().enumerate() {
+            let share = max_bytes / count + usize::from(i < max_bytes % count);
+            shard.lock().expect("cache shard lock poisoned").max_bytes = share;
+        }
+        self
+    }
+
+    /// Keep the entries evicted or expired from now on for `removed`
+    pub fn record_removals(self) -> Self {
+        for shard in self.backends().flat_map(|backend| &backend.shards) {
+            shard.lock().expect("cache shard lock poisoned").removed = Some(Vec::new());
+        }
+        self
+    }
+
+    /// Take the entries evicted or expired since the last call, with why;
+    /// none unless the store records them
+    pub fn removed(&self) -> Vec<(String, V, Removal)> {
+        self.backends()
+            .flat_map(|backend| &backend.shards)
+            .flat_map(|shard| {
+                let mut shard = shard.lock().expect("cache shard lock poisoned");
+                shard
+                    .removed
+                    .as_mut()
+                    .map(std::mem::take)
+                    .unwrap_or_default()
+            })
+            .collect()
+    }
+
+    /// Longest namespace `name` keeps an entry, if its partition caps it
+    pub fn max_ttl(&self, name: &str) -> Option<u64> {
+        self.partition_of(name)?.max_ttl
+    }
+
+    fn partition_of(&self, name: &str) -> Option<&Partition<V>> {
+        self.partitions.get(name).or_else(|| {
+            let (base, _tenant) = name.split_once('@')?;
+            self.partitions.get(base)
+        })
+    }
+
+    /// The store holding `key`, a namespace-scoped key
+    fn backend(&self, key: &str) -> &Sharded<V> {
+        if self.partitions.is_empty() {
+            return &self.shared;
+        }
+        let name = key.split_once(':').map_or(key, |(name, _)| name);
+        self.partition_of(name)
+            .map_or(&self.shared, |partition| &partition.store)
+    }
+
+    fn backends(&self) -> impl Iterator<Item = &Sharded<V>> {
+        std::iter::once(&self.shared).chain(self.partitions.values().map(|p| &p.store))
+    }
+
+    pub fn get(&self, key: &str) -> Option<V> {
+        self.backend(key).get(key)
+    }
+
+    pub fn set(&self, key: String, value: V, ttl: Option<u64>) -> Result<()> {
+        self.set_with_cost(key, value, ttl, None)
+    }
+
+    /// `set`, recording what the value cost to compute; only cost
+    /// eviction uses it
+    pub fn set_with_cost(
+        &self,
+        key: String,
+        value: V,
+        ttl: Option<u64>,
+        cost: Option<Duration>,
+    ) -> Result<()> {
+        self.backend(&key).set(key, value, ttl, cost)
+    }
+
+    /// `set`, never evicting the entry to make room for others; fails if
+    /// its store already pins as many as it may
+    pub fn set_pinned(&self, key: String, value: V, ttl: Option<u64>) -> Result<()> {
+        self.backend(&key).set_pinned(key, value, ttl)
+    }
+
+    pub fn delete(&self, key: &str) -> bool {
+        self.backend(key).delete(key)
+    }
+
+    /// Change `key`'s value in place by `f`, if it is held and hasn't
+    /// expired, returning what `f` did; the entry keeps its expiry, its
+    /// pin and its place in the eviction order
+    pub fn update<R>(&self, key: &str, f: impl FnOnce(&mut V) -> R) -> Option<R> {
+        self.backend(key).shard(key).update(key, f, Instant::now())
+    }
+
+    /// When `key` expires, if held, even if that has passed and it hasn't
+    /// been swept
+    pub fn expiry(&self, key: &str) -> Option<Instant> {
+        self.backend(key).shard(key).expiry(key)
+    }
+
+    /// Expire `key` at `expires` instead, if it is held and hasn't
+    /// expired, keeping its value and place in the eviction order
+    pub fn expire(&self, key: &str, expires: Instant) -> bool {
+        self.backend(key)
+            .shard(key)
+            .expire(key, expires, Instant::now())
+    }
+
+    /// Every live entry with when it expires, in no particular order
+    pub fn entries(&self) -> Vec<(String, V, Instant)> {
+        let now = Instant::now();
+        let mut entries = Vec::new();
+        for backend in self.backends() {
+            for shard in &backend.shards {
+                entries.extend(shard.lock().expect("cache shard lock poisoned").live(now));
+            }
+        }
+        entries
+    }
+
+    /// Hold at most `max_entries` in the store shared by namespaces
+    /// without a partition, returning how many shrinking it evicted
+    pub fn resize(&self, max_entries: usize) -> usize {
+        self.shared.resize(max_entries)
+    }
+
+    /// Drop expired entries, returning how many there were
+    pub fn cleanup_expired(&self) -> usize {
+        self.backends().map(Sharded::cleanup_expired).sum()
+    }
+
+    /// Drop every entry, pinned or not and expired or not, that `matches`,
+    /// returning how many there were
+    pub fn remove_where(&self, mut matches: impl FnMut(&str, &V) -> bool) -> usize {
+        let mut removed = 0;
+        for backend in self.backends() {
+            for shard in &backend.shards {
+                removed += shard
+                    .lock()
+                    .expect("cache shard lock poisoned")
+                    .remove_where(|key, entry| matches(key, &entry.value));
+            }
+        }
+        removed
+    }
+
+    /// Drop every entry, pinned or not
+    pub fn clear(&self) {
+        for backend in self.backends() {
+            for shard in &backend.shards {
+                shard.lock().expect("cache shard lock poisoned").clear();
+            }
+        }
+    }
+
+    /// Entries held, expired or not
+    pub fn size(&self) -> usize {
+        self.backends().map(Sharded::size).sum()
+    }
+
+    /// Bytes of the entries held, expired or not, as `max_bytes` counts
+    /// them
+    pub fn bytes(&self) -> usize {
+        self.backends()
+            .flat_map(|backend| &backend.shards)
+            .map(|shard| shard.lock().expect("cache shard lock poisoned").bytes)
+            .sum()
+    }
+}
+
+/// TTL cache split into shards by key hash, each evicting on its own
+struct Sharded<V> {
+    shards: Vec<Mutex<Shard<V>>>,
+    hasher: RandomState,
+    ttl: u64,
+    /// Entries the whole store may pin, before its size caps it
+    max_pinned: usize,
+}
+
+impl<V: Value> Sharded<V> {
+    fn new(
+        eviction: Eviction,
+        max_entries: usize,
+        ttl: u64,
+        shards: usize,
+        max_pinned: usize,
+    ) -> Self {
+        let count = shards.clamp(1, max_entries.max(1));
+        let shards = limits(max_entries, count, max_pinned)
+            .map(|(capacity, pinned)| Mutex::new(Shard::new(eviction, capacity, pinned)))
+            .collect();
+        Self {
+            shards,
+            hasher: RandomState::new(),
+            ttl,
+            max_pinned,
+        }
+    }
+
+    /// Spread `max_entries` over the shards anew, evicting from those
+    /// left over capacity
+    fn resize(&self, max_entries: usize) -> usize {
+        limits(max_entries, self.shards.len(), self.max_pinned)
+            .zip(&self.shards)
+            .map(|((capacity, pinned), shard)| {
+                shard
+                    .lock()
+                    .expect("cache shard lock poisoned")
+                    .resize(capacity, pinned)
+            })
+            .sum()
+    }
+
+    fn shard(&self, key: &str) -> std::sync::MutexGuard<'_, Shard<V>> {
+        let index = self.hasher.hash_one(key) as usize % self.shards.len();
+        self.shards[index]
+            .lock()
+            .expect("cache shard lock poisoned")
+    }
+
+    fn get(&self, key: &str) -> Option<V> {
+        self.shard(key).get(key, Instant::now())
+    }
+
+    fn set(&self, key: String, value: V, ttl: Option<u64>, cost: Option<Duration>) -> Result<()> {
+        let now = Instant::now();
+        let expires = now + Duration::from_secs(ttl.unwrap_or(self.ttl));
+        let cost = cost.map_or(DEFAULT_COST_MS, |cost| cost.as_secs_f64() * 1000.0);
+        self.shard(&key).set(key, value, expires, cost, now)
+    }
+
+    fn set_pinned(&self, key: String, value: V, ttl: Option<u64>) -> Result<()> {
+        let now = Instant::now();
+        let expires = now + Duration::from_secs(ttl.unwrap_or(self.ttl));
+        self.shard(&key).set_pinned(key, value, expires, now)
+    }
+
+    fn delete(&self, key: &str) -> bool {
+        self.shard(key).remove(key).is_some()
+    }
+
+    fn cleanup_expired(&self) -> usize {
+        let now = Instant::now();
+        self.shards
+            .iter()
+            .map(|shard| {
+                shard
+                    .lock()
+                    .expect("cache shard lock poisoned")
+                    .cleanup(now)
+            })
+            .sum()
+    }
+
+    fn size(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| {
+                shard
+                    .lock()
+                    .expect("cache shard lock poisoned")
+                    .entries
+                    .len()
+            })
+            .sum()
+    }
+}
+
+struct Shard<V> {
+    eviction: Eviction,
+    capacity: usize,
+    /// Priority of the last eviction; new priorities start from it
+    clock: f64,
+    entries: HashMap<String, Entry<V>>,
+    /// Keys by priority, lowest (next to evict) first; `seq` breaks ties
+    /// oldest first. Pinned entries aren't in it.
+    order: BTreeMap<(Priority, u64), String>,
+    seq: u64,
+    /// Pinned entries held, and how many may be
+    pinned: usize,
+    max_pinned: usize,
+    /// Sizes of the entries held, and the most they may add up to
+    bytes: usize,
+    max_bytes: usize,
+    /// Entries evicted or expired and not yet taken, if recorded
+    removed: Option<Vec<(String, V, Removal)>>,
+}
+
+struct Entry<V> {
+    value: V,
+    /// Bytes it takes, as far as eviction is concerned
+    size: usize,
+    expires: Instant,
+    /// Milliseconds the value took to compute
+    cost: f64,
+    hits: u64,
+    /// None for pinned entries, which are never evicted
+    rank: Option<(Priority, u64)>,
+}
+
+/// An `f64` priority, totally ordered
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Priority(f64);
+
+impl Eq for Priority {}
+
+impl PartialOrd for Priority {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Priority {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+impl<V: Value> Shard<V> {
+    fn new(eviction: Eviction, capacity: usize, max_pinned: usize) -> Self {
+        Self {
+            eviction,
+            capacity,
+            clock: 0.0,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            seq: 0,
+            pinned: 0,
+            max_pinned,
+            bytes: 0,
+            max_bytes: usize::MAX,
+            removed: None,
+        }
+    }
+
+    fn get(&mut self, key: &str, now: Instant) -> Option<V> {
+        let entry = self.entries.get(key)?;
+        if entry.expires <= now {
+            let entry = self.remove(key)?;
+            self.record(key.to_string(), entry.value, Removal::Expired);
+            return None;
+        }
+        let Some(old) = entry.rank else {
+            return Some(entry.value.clone());
+        };
+        let (hits, cost, size) = (entry.hits + 1, entry.cost, entry.size);
+        let rank = self.rank(hits, cost, size);
+        let entry = self.entries.get_mut(key)?;
+        entry.rank = Some(rank);
+        entry.hits = hits;
+        let value = entry.value.clone();
+        self.order.remove(&old);
+        self.order.insert(rank, key.to_string());
+        Some(value)
+    }
+
+    fn set(
+        &mut self,
+        key: String,
+        value: V,
+        expires: Instant,
+        cost: f64,
+        now: Instant,
+    ) -> Result<()> {
+        let size = self.fitting(&key, &value)?;
+        // A replaced entry keeps its hits, so a refreshed hot decision
+        // isn't treated as new
+        let hits = self.replace(&key, now);
+        self.make_room(size);
+
+        let rank = self.rank(hits, cost, size);
+        self.order.insert(rank, key.clone());
+        self.bytes += size;
+        self.entries.insert(
+            key,
+            Entry {
+                value,
+                size,
+                expires,
+                cost,
+                hits,
+                rank: Some(rank),
+            },
+        );
+        Ok(())
+    }
+
+    /// Store `key` where eviction can't reach it, unless that would pin
+    /// more entries than the shard may
+    fn set_pinned(&mut self, key: String, value: V, expires: Instant, now: Instant) -> Result<()> {
+        let repinned = self
+            .entries
+            .get(&key)
+            .is_some_and(|entry| entry.rank.is_none());
+        if !repinned && self.pinned >= self.max_pinned {
+            return Err(Error::PinLimit);
+        }
+        let size = self.fitting(&key, &value)?;
+        let hits = self.replace(&key, now);
+        self.make_room(size);
+
+        self.pinned += 1;
+        self.bytes += size;
+        self.entries.insert(
+            key,
+            Entry {
+                value,
+                size,
+                expires,
+                cost: DEFAULT_COST_MS,
+                hits,
+                rank: None,
+            },
+        );
+        Ok(())
+    }
+
+    /// Remove `key` to set it anew, returning the hits it keeps (1 for a
+    /// new key); an expired entry set over counts as expired
+    fn replace(&mut self, key: &str, now: Instant) -> u64 {
+        let Some(entry) = self.remove(key) else {
+            return 1;
+        };
+        let hits = entry.hits;
+        if entry.expires <= now {
+            self.record(key.to_string(), entry.value, Removal::Expired);
+        }
+        hits
+    }
+
+    /// The size of `key` and `value`, if the shard can ever hold it
+    fn fitting(&self, key: &str, value: &V) -> Result<usize> {
+        let size = size(key, value);
+        if size > self.max_bytes {
+            return Err(Error::TooLarge {
+                size,
+                max_bytes: self.max_bytes,
+            });
+        }
+        Ok(size)
+    }
+
+    fn update<R>(&mut self, key: &str, f: impl FnOnce(&mut V) -> R, now: Instant) -> Option<R> {
+        let entry = self.entries.get_mut(key)?;
+        if entry.expires <= now {
+            return None;
+        }
+        let updated = f(&mut entry.value);
+        let size = size(key, &entry.value);
+        self.bytes = self.bytes - entry.size + size;
+        entry.size = size;
+        // Grown past the byte limit, the entry makes room like any set,
+        // though it may be the one evicted
+        if self.bytes > self.max_bytes {
+            self.evict_to(self.capacity, self.max_bytes);
+        }
+        Some(updated)
+    }
+
+    fn expiry(&self, key: &str) -> Option<Instant> {
+        Some(self.entries.get(key)?.expires)
+    }
+
+    fn expire(&mut self, key: &str, expires: Instant, now: Instant) -> bool {
+        match self.entries.get_mut(key) {
+            Some(entry) if entry.expires > now => {
+                entry.expires = expires;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn live(&self, now: Instant) -> impl Iterator<Item = (String, V, Instant)> + '_ {
+        self.entries
+            .iter()
+            .filter(move |(_, entry)| entry.expires > now)
+            .map(|(key, entry)| (key.clone(), entry.value.clone(), entry.expires))
+    }
+
+    /// Evict until there is room for one more entry of `size` bytes
+    fn make_room(&mut self, size: usize) {
+        self.evict_to(self.capacity - 1, self.max_bytes - size);
+    }
+
+    fn resize(&mut self, capacity: usize, max_pinned: usize) -> usize {
+        self.capacity = capacity;
+        self.max_pinned = max_pinned;
+        self.evict_to(capacity, self.max_bytes)
+    }
+
+    /// Evict until at most `len` entries of at most `bytes` are left, or
+    /// only pinned ones, returning how many were
+    fn evict_to(&mut self, len: usize, bytes: usize) -> usize {
+        let mut evicted = 0;
+        while self.entries.len() > len || self.bytes > bytes {
+            let Some(((priority, _), key)) = self.order.pop_first() else {
+                break;
+            };
+            self.clock = priority.0;
+            if let Some(entry) = self.entries.remove(&key) {
+                self.bytes -= entry.size;
+                self.record(key, entry.value, Removal::Evicted);
+            }
+            evicted += 1;
+        }
+        evicted
+    }
+
+    fn remove(&mut self, key: &str) -> Option<Entry<V>> {
+        let entry = self.entries.remove(key)?;
+        self.bytes -= entry.size;
+        match &entry.rank {
+            Some(rank) => {
+                self.order.remove(rank);
+            }
+            None => self.pinned -= 1,
+        }
+        Some(entry)
+    }
+
+    fn cleanup(&mut self, now: Instant) -> usize {
+        let expired: Vec<String> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.expires <= now)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &expired {
+            if let Some(entry) = self.remove(key) {
+                self.record(key.clone(), entry.value, Removal::Expired);
+            }
+        }
+        expired.len()
+    }
+
+    /// Keep an entry evicted or expired for `removed`, if recording
+    fn record(&mut self, key: String, value: V, removal: Removal) {
+        if let Some(removed) = &mut self.removed {
+            removed.push((key, value, removal));
+        }
+    }
+
+    fn remove_where(&mut self, mut matches: impl FnMut(&str, &Entry<V>) -> bool) -> usize {
+        let matched: Vec<String> = self
+            .entries
+            .iter()
+            .filter(|(key, entry)| matches(key, entry))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &matched {
+            self.remove(key);
+        }
+        matched.len()
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+        self.pinned = 0;
+        self.bytes = 0;
+        self.clock = 0.0;
+    }
+
+    /// Priority of an entry hit `hits` times, costing `cost` ms and `size`
+    /// bytes, with a fresh tie-breaker; by LRU, every priority is the same
+    /// and the tie-breaker, the order of use, decides
+    fn rank(&mut self, hits: u64, cost: f64, size: usize) -> (Priority, u64) {
+        self.seq += 1;
+        let priority = match self.eviction {
+            Eviction::Lru => 0.0,
+            Eviction::Cost => self.clock + hits as f64 * cost / size as f64,
+        };
+        (Priority(priority), self.seq)
+    }
+}
+
+/// Each of `count` shards' capacity and pinning allowance, the
+/// capacities adding up to `max_entries` exactly
+fn limits(
+    max_entries: usize,
+    count: usize,
+    max_pinned: usize,
+) -> impl Iterator<Item = (usize, usize)> {
+    let pinned = max_pinned.min(max_entries / 2).div_ceil(count);
+    (0..count).map(move |i| {
+        let capacity = max_entries / count + usize::from(i < max_entries % count);
+        (capacity.max(1), pinned)
+    })
+}
+
+/// Randomize `ttl` uniformly within +/- `jitter_pct` percent
+///
+/// Never returns less than one second, so a jittered entry is always
+/// cacheable.
+pub fn jittered_ttl(ttl: u64, jitter_pct: u8) -> u64 {
+    if jitter_pct == 0 || ttl == 0 {
+        return ttl;
+    }
+
+    let spread = ttl as f64 * f64::from(jitter_pct) / 100.0;
+    let offset = rand::thread_rng().gen_range(-spread..=spread);
+    ((ttl as f64 + offset).round() as u64).max(1)
+}
+
+/// Bytes an entry takes, as far as eviction is concerned
+fn size(key: &str, value: &impl Value) -> usize {
+    (key.len() + value.bytes()).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A single shard, so every entry competes for the same room
+    fn store(eviction: Eviction, max_entries: usize) -> Store {
+        Store::new(eviction, max_entries, 300, 1, 0)
+    }
+
+    fn set(store: &Store, key: &str, cost_ms: u64) {
+        let cost = Duration::from_millis(cost_ms);
+        store
+            .set_with_cost(key.to_string(), "v".to_string(), None, Some(cost))
+            .unwrap();
+    }
+
+    fn held(store: &Store, keys: &[&str]) -> Vec<bool> {
+        keys.iter().map(|key| store.get(key).is_some()).collect()
+    }
+
+    #[test]
+    fn lru_evicts_the_least_recently_used() {
+        let store = store(Eviction::Lru, 3);
+        for key in ["auth:a", "auth:b", "auth:c"] {
+            set(&store, key, 1);
+        }
+        store.get("auth:a");
+        set(&store, "auth:d", 1);
+        assert_eq!(store.size(), 3);
+        assert_eq!(
+            held(&store, &["auth:a", "auth:b", "auth:c", "auth:d"]),
+            [true, false, true, true]
+        );
+    }
+
+    #[test]
+    fn cost_evicts_the_cheapest_first() {
+        let store = store(Eviction::Cost, 3);
+        set(&store, "auth:a", 20);
+        set(&store, "auth:b", 1);
+        set(&store, "auth:c", 5);
+        set(&store, "auth:d", 10);
+        assert_eq!(
+            held(&store, &["auth:a", "auth:b", "auth:c", "auth:d"]),
+            [true, false, true, true]
+        );
+    }
+
+    #[test]
+    fn cost_weighs_hits() {
+        let store = store(Eviction::Cost, 3);
+        for key in ["auth:a", "auth:b", "auth:c"] {
+            set(&store, key, 1);
+        }
+        // Hit twice, a outranks c, hit once; b was never hit
+        store.get("auth:a");
+        store.get("auth:a");
+        store.get("auth:c");
+        set(&store, "auth:d", 1);
+        assert_eq!(held(&store, &["auth:b"]), [false]);
+        set(&store, "auth:e", 1);
+        assert_eq!(held(&store, &["auth:a", "auth:c"]), [true, false]);
+    }
+
+    #[test]
+    fn cost_weighs_size() {
+        let store = store(Eviction::Cost, 2);
+        set(&store, "auth:a", 10);
+        store
+            .set_with_cost(
+                "auth:b".to_string(),
+                "v".repeat(100),
+                None,
+                Some(Duration::from_millis(10)),
+            )
+            .unwrap();
+        set(&store, "auth:c", 10);
+        assert_eq!(
+            held(&store, &["auth:a", "auth:b", "auth:c"]),
+            [true, false, true]
+        );
+    }
+
+    #[test]
+    fn replaced_entries_keep_their_hits() {
+        let store = store(Eviction::Cost, 2);
+        set(&store, "auth:a", 1);
+        set(&store, "auth:b", 1);
+        store.get("auth:a");
+        store.get("auth:b");
+        set(&store, "auth:a", 1);
+        set(&store, "auth:c", 1);
+        assert_eq!(held(&store, &["auth:a", "auth:b"]), [true, false]);
+    }
+
+    #[test]
+    fn costly_entries_age_out_once_no_longer_hit() {
+        // Whether a 20ms decision, never hit again, outlasts `cheap` 1ms ones
+        let survives = |cheap: usize| {
+            let store = store(Eviction::Cost, 2);
+            set(&store, "auth:costly", 20);
+            for i in 0..cheap {
+                set(&store, &format!("auth:{:06}", i), 1);
+            }
+            store.get("auth:costly").is_some()
+        };
+        assert!(survives(5));
+        assert!(!survives(50));
+    }
+
+    #[test]
+    fn expired_entries_are_not_returned() {
+        let store = store(Eviction::Lru, 3);
+        store
+            .set("auth:a".to_string(), "v".to_string(), Some(0))
+            .unwrap();
+        assert_eq!(store.get("auth:a"), None);
+        assert_eq!(store.size(), 0);
+    }
+
+    #[test]
+    fn partitions_evict_apart_from_the_shared_store() {
+        let store = store(Eviction::Lru, 2).partition("quota", Eviction::Lru, 1, Some(60));
+        set(&store, "auth:a", 1);
+        set(&store, "quota:a", 1);
+        set(&store, "auth:b", 1);
+        // A tenant's namespace shares its base namespace's partition
+        set(&store, "quota@acme:b", 1);
+        assert_eq!(
+            held(&store, &["auth:a", "auth:b", "quota:a", "quota@acme:b"]),
+            [true, true, false, true]
+        );
+        assert_eq!(store.max_ttl("quota@acme"), Some(60));
+        assert_eq!(store.max_ttl("auth"), None);
+    }
+
+    fn pin(store: &Store, key: &str, ttl: Option<u64>) -> Result<()> {
+        store.set_pinned(key.to_string(), "1".to_string(), ttl)
+    }
+
+    #[test]
+    fn pinned_entries_are_never_evicted() {
+        let store = Store::new(Eviction::Lru, 4, 300, 1, 2);
+        pin(&store, "quota:p", None).unwrap();
+        for key in ["auth:a", "auth:b", "auth:c", "auth:d", "auth:e"] {
+            set(&store, key, 1);
+        }
+        assert_eq!(store.size(), 4);
+        assert_eq!(
+            held(&store, &["quota:p", "auth:a", "auth:b", "auth:e"]),
+            [true, false, false, true]
+        );
+    }
+
+    #[test]
+    fn pinning_is_capped() {
+        // By cache.max_pinned
+        let store = Store::new(Eviction::Lru, 10, 300, 1, 2);
+        pin(&store, "quota:p", None).unwrap();
+        pin(&store, "quota:q", None).unwrap();
+        assert!(pin(&store, "quota:r", None).is_err());
+        // Updating a pinned entry takes no more room
+        pin(&store, "quota:p", None).unwrap();
+        assert!(store.delete("quota:p"));
+        pin(&store, "quota:r", None).unwrap();
+
+        // And by half the store's size
+        let store = Store::new(Eviction::Lru, 4, 300, 1, 100);
+        pin(&store, "quota:p", None).unwrap();
+        pin(&store, "quota:q", None).unwrap();
+        assert!(pin(&store, "quota:r", None).is_err());
+    }
+
+    #[test]
+    fn expired_pinned_entries_free_their_slot() {
+        let store = Store::new(Eviction::Lru, 4, 300, 1, 1);
+        pin(&store, "quota:p", Some(0)).unwrap();
+        assert_eq!(store.get("quota:p"), None);
+        pin(&store, "quota:q", None).unwrap();
+        assert_eq!(store.cleanup_expired(), 0);
+        assert_eq!(store.size(), 1);
+    }
+
+    #[test]
+    fn pinning_an_entry_takes_it_out_of_eviction() {
+        let store = Store::new(Eviction::Lru, 2, 300, 1, 1);
+        set(&store, "quota:p", 1);
+        pin(&store, "quota:p", None).unwrap();
+        set(&store, "auth:a", 1);
+        set(&store, "auth:b", 1);
+        assert_eq!(
+            held(&store, &["quota:p", "auth:a", "auth:b"]),
+            [true, false, true]
+        );
+    }
+
+    #[test]
+    fn shrinking_evicts_in_eviction_order() {
+        let store = store(Eviction::Lru, 4);
+        for key in ["auth:a", "auth:b", "auth:c", "auth:d"] {
+            set(&store, key, 1);
+        }
+        store.get("auth:a");
+        assert_eq!(store.resize(2), 2);
+        assert_eq!(
+            held(&store, &["auth:a", "auth:b", "auth:c", "auth:d"]),
+            [true, false, false, true]
+        );
+
+        // Growing evicts nothing, and makes room again
+        assert_eq!(store.resize(4), 0);
+        set(&store, "auth:e", 1);
+        set(&store, "auth:f", 1);
+        assert_eq!(store.size(), 4);
+    }
+
+    #[test]
+    fn shrinking_keeps_pinned_entries() {
+        let store = Store::new(Eviction::Lru, 4, 300, 1, 2);
+        pin(&store, "quota:p", None).unwrap();
+        pin(&store, "quota:q", None).unwrap();
+        set(&store, "auth:a", 1);
+        set(&store, "auth:b", 1);
+        assert_eq!(store.resize(1), 2);
+        assert_eq!(held(&store, &["quota:p", "quota:q"]), [true, true]);
+    }
+
+    #[test]
+    fn resizing_spreads_the_size_over_every_shard() {
+        let store = Store::new(Eviction::Lru, 100, 300, 4, 0);
+        for i in 0..100 {
+            set(&store, &format!("auth:{}", i), 1);
+        }
+        let before = store.size();
+        assert_eq!(store.resize(10), before - 10);
+        assert_eq!(store.size(), 10);
+    }
+
+    #[test]
+    fn resizing_leaves_partitions_alone() {
+        let store = store(Eviction::Lru, 2).partition("quota", Eviction::Lru, 2, None);
+        for key in ["auth:a", "auth:b", "quota:a", "quota:b"] {
+            set(&store, key, 1);
+        }
+        assert_eq!(store.resize(1), 1);
+        assert_eq!(held(&store, &["quota:a", "quota:b"]), [true, true]);
+    }
+
+    #[test]
+    fn entries_lists_only_live_entries() {
+        let store = store(Eviction::Lru, 4);
+        set(&store, "auth:a", 1);
+        store
+            .set("auth:b".to_string(), "v".to_string(), Some(0))
+            .unwrap();
+        let keys: Vec<String> = store.entries().into_iter().map(|(key, ..)| key).collect();
+        assert_eq!(keys, ["auth:a"]);
+        // An expired entry's expiry is known until it is swept
+        assert!(store.expiry("auth:b").is_some());
+        store.clear();
+        assert_eq!(store.size(), 0);
+    }
+
+    #[test]
+    fn remove_where_reaches_every_partition() {
+        let store =
+            Store::new(Eviction::Lru, 4, 300, 1, 2).partition("quota", Eviction::Lru, 4, None);
+        for key in ["auth:a", "auth:b", "quota:a"] {
+            set(&store, key, 1);
+        }
+        pin(&store, "auth:p", None).unwrap();
+        assert_eq!(store.remove_where(|key, _| key.ends_with(":a")), 2);
+        assert_eq!(store.remove_where(|key, _| key == "auth:p"), 1);
+        assert_eq!(held(&store, &["auth:b"]), [true]);
+        assert_eq!(store.size(), 1);
+    }
+
+    #[test]
+    fn max_bytes_evicts_until_a_new_entry_fits() {
+        // Each entry is a 6-byte key and a value of 10 or 30
+        let store = store(Eviction::Lru, 100).max_bytes(70);
+        let value = |len: usize| "v".repeat(len);
+        for key in ["auth:a", "auth:b", "auth:c"] {
+            store.set(key.to_string(), value(10), None).unwrap();
+        }
+        assert_eq!(store.bytes(), 48);
+        store.get("auth:a");
+        store.set("auth:d".to_string(), value(30), None).unwrap();
+        assert_eq!(
+            held(&store, &["auth:a", "auth:b", "auth:c", "auth:d"]),
+            [true, false, true, true]
+        );
+        assert_eq!(store.bytes(), 68);
+
+        assert!(matches!(
+            store.set("auth:e".to_string(), value(65), None),
+            Err(Error::TooLarge { size: 71, .. })
+        ));
+        assert_eq!(store.size(), 3);
+    }
+
+    #[test]
+    fn updates_count_against_max_bytes() {
+        let store = store(Eviction::Lru, 100).max_bytes(40);
+        set(&store, "auth:a", 1);
+        set(&store, "auth:b", 1);
+        store.update("auth:b", |value| value.push_str(&"v".repeat(30)));
+        assert_eq!(held(&store, &["auth:a", "auth:b"]), [false, true]);
+        assert_eq!(store.bytes(), 37);
+        store.delete("auth:b");
+        assert_eq!(store.bytes(), 0);
+    }
+
+    #[test]
+    fn update_changes_live_entries_in_place() {
+        let store = Store::new(Eviction::Lru, 2, 300, 1, 1);
+        pin(&store, "quota:p", None).unwrap();
+        let expires = store.expiry("quota:p");
+        assert_eq!(store.update("quota:p", |value| value.push('1')), Some(()));
+        assert_eq!(store.get("quota:p").as_deref(), Some("11"));
+        assert_eq!(store.expiry("quota:p"), expires);
+        // Still pinned
+        set(&store, "auth:a", 1);
+        set(&store, "auth:b", 1);
+        assert_eq!(held(&store, &["quota:p"]), [true]);
+
+        store
+            .set("auth:c".to_string(), "v".to_string(), Some(0))
+            .unwrap();
+        assert_eq!(store.update("auth:c", |_| ()), None);
+        assert_eq!(store.update("auth:missing", |_| ()), None);
+    }
+
+    #[test]
+    fn expire_keeps_the_entry_in_place() {
+        let store = store(Eviction::Lru, 2);
+        set(&store, "auth:a", 1);
+        set(&store, "auth:b", 1);
+        let later = Instant::now() + Duration::from_secs(600);
+        assert!(store.expire("auth:a", later));
+        assert_eq!(store.expiry("auth:a"), Some(later));
+        assert!(!store.expire("auth:missing", later));
+        // Re-expiring isn't a use: a is still the least recently used
+        set(&store, "auth:c", 1);
+        assert_eq!(held(&store, &["auth:a", "auth:b"]), [false, true]);
+    }
+
+    #[test]
+    fn records_evictions_and_expirations() {
+        let store = store(Eviction::Lru, 2).record_removals();
+        let expired = |key: &str| {
+            store
+                .set(key.to_string(), "v".to_string(), Some(0))
+                .unwrap()
+        };
+        set(&store, "auth:a", 1);
+        set(&store, "auth:b", 1);
+        set(&store, "auth:c", 1);
+        assert_eq!(
+            store.removed(),
+            [("auth:a".to_string(), "v".to_string(), Removal::Evicted)]
+        );
+        assert!(store.removed().is_empty());
+
+        expired("auth:b");
+        assert!(store.get("auth:b").is_none());
+        expired("auth:c");
+        set(&store, "auth:c", 1);
+        expired("auth:d");
+        store.cleanup_expired();
+        store.delete("auth:c");
+        let mut removed = store.removed();
+        removed.sort_by(|a, b| a.0.cmp(&b.0));
+        let removal = |key: &str| (key.to_string(), "v".to_string(), Removal::Expired);
+        assert_eq!(
+            removed,
+            [removal("auth:b"), removal("auth:c"), removal("auth:d")]
+        );
+    }
+
+    #[test]
+    fn records_nothing_unless_asked() {
+        let store = store(Eviction::Lru, 1);
+        set(&store, "auth:a", 1);
+        set(&store, "auth:b", 1);
+        assert!(store.removed().is_empty());
+    }
+}
@@ -0,0 +1,23 @@
+//! `build_info()`, what this build of the module is
+//!
+//! The same `BuildInfo` the gateway reports on `/health`, from sark-build,
+//! with the capabilities the Python module was built with.
+
+use pyo3::prelude::*;
+use pythonize::pythonize;
+
+/// Version, git sha, Cargo profile, rustc and regorus versions, and
+/// `features`: each capability and whether it was built in
+#[pyfunction]
+pub fn build_info(py: Python<'_>) -> PyResult<PyObject> {
+    let info = sark_build::build_info! {
+        "redis" => true,
+        "wasm" => false,
+        "tracing" => true,
+        "shared_cache" => true,
+        "gateway_client" => true,
+        "jwt" => true,
+        "cel" => true,
+    };
+    Ok(pythonize(py, &info)?.unbind())
+}
@@ -0,0 +1,1712 @@
+//! `RustCache`, with the GIL released
+//!
+//! grid-core's binding for `LRUTTLCache` holds the GIL for the whole of
+//! each call, so Python worker threads sharing a cache take turns even
+//! though the cache itself is concurrent. This binding has the same
+//! interface, and releases the GIL around every operation on the cache;
+//! only converting keys and values to and from Python objects holds it.
+//! Lookups, insertions, evictions and expirations are counted for
+//! `stats()`. The store doesn't report what it evicts, so a set of a new
+//! key into a full cache counts as one eviction; expirations are the
+//! entries `cleanup_expired` drops and expired ones overwritten.
+//!
+//! The entries are held in the gateway's decision store (`sark-store`),
+//! in a single shard, so `max_size` bounds the cache exactly and the least
+//! recently used entry is the one evicted. `set_pinned` stores an entry
+//! eviction can't reach (a quota counter, say, whose eviction would reset
+//! it), gone only once deleted or expired, or set again unpinned. As in
+//! the gateway, at most `max_pinned` entries and never more than half of
+//! `max_size` are pinned, so they can't starve the rest; past that
+//! `set_pinned` raises `SarkCacheError`.
+//!
+//! `set` takes `tags` (`["user:123", "server:db01"]`), and
+//! `invalidate_tag` drops every entry carrying one, so the decisions for a
+//! user whose roles changed can be purged without clearing the rest. It
+//! looks at every entry, under the write lock. Tags, like versions, aren't
+//! pickled.
+//!
+//! `set` also takes `meta`, a JSON-like dict describing the entry (the
+//! policy revision a decision was evaluated under, when, the hash of its
+//! input), which `get_with_meta` returns with the value, so the gateway
+//! can pass over decisions computed under an older bundle. It is kept as
+//! JSON beside the value; replacing the value (`incr`) keeps it, and like
+//! tags it isn't pickled.
+//!
+//! `delete_prefix` and `delete_matching` (a glob, `*` matching any run of
+//! characters and `?` any one) drop the keys of one server's decisions,
+//! say, when its policy changes, in the same way.
+//!
+//! With `sliding=True`, every lookup (`get`, `get_versioned`, `get_many`
+//! or `cache[key]`) keeps the entry another TTL, the one it was set with,
+//! making the cache an idle-timeout store for sessions that activity
+//! keeps alive. `in`, `ttl` and listing entries don't.
+//!
+//! With `max_bytes`, the cache also holds at most that many bytes of keys
+//! and values, for values whose sizes vary too much for `max_size` alone
+//! to bound its memory; a full cache evicts until a new entry fits, and a
+//! value larger than `max_bytes` by itself raises `SarkCacheError`. A
+//! value counts as its length (a `str`'s UTF-8, a `bytes`' and a pickle's
+//! bytes, up to twice over as stored, see below) unless a
+//! `weigher(key, value)` callable, called on every set, says what it
+//! weighs; counters are always weighed by length, and tags and metadata
+//! count as their own. `memory_usage()` reads the bytes held, as the
+//! limit counts them.
+//!
+//! With `compress_threshold`, values of that many bytes or more as stored
+//! (a cached decision with `filtered_parameters`, say, of tens of KB) are
+//! deflated on set, outside the GIL, and inflated on get; a value that
+//! doesn't shrink is kept as it was. Compressed values count against
+//! `max_bytes` at their compressed size, and `stats()` counts the
+//! compressions and the bytes they saved. The deflated bytes are held one
+//! character per byte, like `bytes` values (see below).
+//!
+//! `add_listener(callback)` has `callback(key, value, cause)` called with
+//! each entry evicted to make room (`cause` `"evicted"`, by a set or
+//! `resize`) or expired (`"expired"`), to mirror evictions into metrics
+//! or a second store. Expired entries are found lazily, by a lookup of
+//! the key, a set over it or `cleanup_expired`, so one never looked at
+//! again is reported at the next sweep. Listeners are called on the
+//! calling thread once the operation is done, with the GIL and no lock of
+//! the cache's held, so they may use the cache; what they raise is
+//! reported through `sys.unraisablehook`. Deletes and `clear` aren't
+//! reported.
+//!
+//! `get_or_compute(key, compute, ttl=None)` returns the value at `key`,
+//! or calls `compute()` for it and sets what it returns. Concurrent calls
+//! for a missing key coalesce: the first computes it and the rest wait,
+//! without the GIL, for its value, so a hot authorization key expiring
+//! under load is evaluated once rather than by every worker thread at
+//! once. If `compute` raises, that call raises, and a caller still
+//! waiting computes in its place. `compute` may use the cache, but not
+//! `get_or_compute` the key it is computing, which raises
+//! `SarkCacheError` rather than waiting on itself.
+//!
+//! `start_janitor(interval_secs)` sweeps expired entries every interval
+//! on a thread of the cache's own, as `cleanup_expired` does, so they
+//! don't linger (and hold memory) until their key is next looked at;
+//! `stop_janitor()` stops it, and so does the cache's collection. The
+//! thread takes the GIL only to call listeners with what it swept. It
+//! isn't pickled.
+//!
+//! With `ttl_jitter_pct`, every set's TTL (the cache's or its own) is
+//! randomized within +/- that percent, as the gateway's
+//! `cache.ttl_jitter_pct` does, so entries written in a burst don't all
+//! expire, and hit OPA again, at the same instant. A sliding cache slides
+//! entries by the TTL they were set with, unjittered.
+//!
+//! With `soft_ttl_secs`, an entry is stale that long after it was set,
+//! though still held until its TTL: `get_stale` returns the value and
+//! whether it is, for stale-while-revalidate callers that serve a stale
+//! decision while they refresh it in the background, as the gateway does
+//! with `cache.soft_ttl`. `get` doesn't tell the two apart.
+//!
+//! Negative results (a deny, a not-found) can be cached with
+//! `set_negative(key)`, for `negative_ttl_secs` (shorter than `ttl_secs`,
+//! so a permission fix takes effect quickly while a retry storm still
+//! hits the cache). A negative is stored as `None`, and setting `None`
+//! stores one too, so `get` can't tell it from a miss; `probe` can,
+//! returning `(held, value)`: `(True, None)` for a negative and
+//! `(False, None)` for a miss. Negatives pickle like any entry.
+//!
+//! `resize` changes `max_size` in place, evicting down to a smaller size
+//! at once, least recently used first, as the gateway does on reload.
+//!
+//! The cache pickles with its entries and their remaining TTLs, so a
+//! pre-warmed cache can be handed to `multiprocessing` or spawned worker
+//! processes. Pinned entries are unpickled unpinned.
+//!
+//! `save_snapshot(path)` writes the live entries to a file, with their
+//! remaining TTLs, tags and metadata, and `load_snapshot(path)` sets them
+//! again, in this process or a restarted one, each for what it had left
+//! less the time since the save; entries that expired meanwhile are left
+//! out. The file is binary: `SARKRC\0`, a format version byte and the
+//! save's Unix time, then each entry's key, value (as stored, but never
+//! compressed), TTL, tags and metadata, strings after their length.
+//! Values are pickled only as the saving cache pickles them, so load a
+//! snapshot into a cache with the same `serializer`. Pinned entries load
+//! unpinned, as they unpickle.
+//!
+//! `get_many`, `set_many` and `delete_many` take a batch of keys in one
+//! call, converting them all before releasing the GIL once and taking the
+//! write lock once, for warming a cache with thousands of entries.
+//!
+//! Values are `str`, `bytes` or `None` (a negative), and, with
+//! `serializer="pickle"`, any object `pickle` can serialize; the type is kept with the value, so
+//! each comes back as what was set. The store holds strings, so bytes
+//! (and pickles) are kept one character per byte, up to twice their size.
+//!
+//! `ttl` reads the seconds an entry has left (0 once it has expired, until
+//! `cleanup_expired` sweeps it, so an expiry can be told from a key never
+//! set), and `touch` and `expire_at` give a held entry a new expiry
+//! without rewriting its value or changing its version.
+//!
+//! It also works as a mapping, for code written against a `dict` or a
+//! `cachetools` cache: `cache[key]` (raising `KeyError` for a missing or
+//! expired key), assignment with the cache's TTL, `del`, `in`, `len`, and
+//! `keys()`, `items()` and iteration over a snapshot of the live entries,
+//! as pickling does.
+//!
+//! For read-modify-write updates from several threads (a session's state,
+//! a counter), `get_versioned` returns an entry with its version, and
+//! `set_if_version` stores a new value only if the entry is still at that
+//! version (or, given `None`, still missing), returning the new version or
+//! `None` if another writer got there first:
+//!
+//! ```python
+//! while True:
+//!     entry = cache.get_versioned("count")
+//!     count, version = (int(entry[0]), entry[1]) if entry else (0, None)
+//!     if cache.set_if_version("count", str(count + 1), version) is not None:
+//!         break
+//! ```
+//!
+//! For counters (requests per user, a quota), `incr` and `decr` add to
+//! the integer at a key in one step, starting a missing key from 0 for the
+//! TTL given and keeping a held one's expiry and pin, so a counter's window
+//! doesn't move with each increment. The count is stored as a `str`, and
+//! `get` reads it back as one.
+//!
+//! Every write gives the entry a new version, never one it or another key
+//! had before, so an entry deleted and set again doesn't match an old
+//! version. Versions aren't pickled; an unpickled cache's entries get new
+//! ones.
+
+use crate::errors::SarkCacheError;
+use flate2::read::{ZlibDecoder, ZlibEncoder};
+use flate2::Compression;
+use pyo3::exceptions::{PyKeyError, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyIterator, PyList, PyString};
+use pyo3::{PyTraverseError, PyVisit};
+use pythonize::{depythonize, pythonize};
+use sark_store::{jittered_ttl, Eviction, Removal, Store};
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle, ThreadId};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// A pickled cache's entries: key, value and remaining TTL in seconds
+type Entries<T> = Vec<(String, T, u64)>;
+
+/// First bytes of a `save_snapshot` file, the last its format's version
+const SNAPSHOT_MAGIC: &[u8; 8] = b"SARKRC\0\x01";
+
+/// Bytes an entry takes beyond its key and value, for the memory
+/// estimate: the store's map and eviction-order slots, string headers,
+/// expiry, hit count and version
+const ENTRY_OVERHEAD: u64 = 128;
+
+/// First character of a stored `str`, `bytes` or pickled value; values
+/// stored before they were tagged read back as `str`
+const STR: char = '\u{1}';
+const BYTES: char = '\u{2}';
+const PICKLED: char = '\u{3}';
+/// First character of a compressed value, the deflated bytes of one of
+/// the above following it
+const COMPRESSED: char = '\u{4}';
+/// The whole of a cached negative, stored for `None`
+const NEGATIVE: char = '\u{5}';
+
+/// How values other than `str` and `bytes` are stored
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Serializer {
+    Pickle,
+}
+
+/// A value as stored, tagged with its type, with its version, tags and
+/// metadata
+#[derive(Clone)]
+struct Stored {
+    value: String,
+    version: u64,
+    tags: Vec<String>,
+    /// `meta` as JSON
+    meta: Option<String>,
+    /// Seconds it was set for, which a sliding cache keeps it from each
+    /// lookup
+    ttl: u64,
+    /// Bytes the weigher gave the value, counted instead of its length
+    weight: Option<usize>,
+    /// When it was set, for its soft TTL
+    set_at: Instant,
+}
+
+impl sark_store::Value for Stored {
+    fn bytes(&self) -> usize {
+        self.weight.unwrap_or(self.value.len())
+            + self.tags.iter().map(String::len).sum::<usize>()
+            + self.meta.as_ref().map_or(0, String::len)
+    }
+}
+
+/// How an entry is stored, beyond its value and TTL
+#[derive(Default)]
+struct Options {
+    /// Never evicted to make room
+    pinned: bool,
+    tags: Vec<String>,
+    /// As JSON
+    meta: Option<String>,
+    /// Bytes the weigher gave the value
+    weight: Option<usize>,
+}
+
+/// Thread-safe in-memory LRU cache with per-entry TTLs
+#[pyclass(module = "sark_rust")]
+pub struct RustCache {
+    /// Shared with the janitor
+    cache: Arc<Store<Stored>>,
+    /// Changed by `resize`
+    max_size: AtomicUsize,
+    ttl_secs: u64,
+    /// As given; the store pins at most half its size whatever it is
+    max_pinned: Option<usize>,
+    /// Whether lookups keep entries another TTL
+    sliding: bool,
+    max_bytes: Option<usize>,
+    /// Called with each key and value set, for the bytes it counts as
+    weigher: Option<PyObject>,
+    /// Size from which values are compressed
+    compress_threshold: Option<usize>,
+    /// Percent each set's TTL is randomized by, either way
+    ttl_jitter_pct: u8,
+    /// Seconds after which entries are stale, if not 0
+    soft_ttl_secs: u64,
+    /// TTL of negatives set without their own
+    negative_ttl_secs: Option<u64>,
+    serializer: Option<Serializer>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    insertions: AtomicU64,
+    evictions: AtomicU64,
+    expirations: Arc<AtomicU64>,
+    /// Key and value bytes of every insertion, for the mean entry size
+    inserted_bytes: AtomicU64,
+    compressions: AtomicU64,
+    /// Bytes compression took off the values it compressed
+    compression_saved_bytes: AtomicU64,
+    /// Called with each entry evicted or found expired
+    listeners: Arc<Mutex<Vec<PyObject>>>,
+    /// The thread sweeping expired entries, if started
+    janitor: Mutex<Option<Janitor>>,
+    /// Keys being computed by `get_or_compute`
+    flights: Mutex<HashMap<String, Arc<Flight>>>,
+    /// Held across every write, so a compare-and-swap sees no write
+    /// between its check and its set
+    writes: Mutex<()>,
+    /// Last version given to a write
+    version: AtomicU64,
+}
+
+#[pymethods]
+impl RustCache {
+    /// A cache of at most `max_size` entries and, if given, `max_bytes`
+    /// of keys and values (as `weigher(key, value)` weighs the values),
+    /// each kept `ttl_secs` unless set with its own TTL (from its last
+    /// lookup, if `sliding`), and up to `max_pinned` of them pinned (half
+    /// of `max_size` if not given), each set's TTL randomized within
+    /// +/- `ttl_jitter_pct` percent and stale after `soft_ttl_secs` (if
+    /// not 0), negatives kept `negative_ttl_secs` (`ttl_secs` if not
+    /// given); values of `compress_threshold` bytes
+    /// or more are compressed, and with `serializer="pickle"`, values may
+    /// be any object pickle can serialize
+    #[new]
+    #[pyo3(signature = (
+        max_size,
+        ttl_secs,
+        serializer = None,
+        max_pinned = None,
+        sliding = false,
+        max_bytes = None,
+        weigher = None,
+        compress_threshold = None,
+        ttl_jitter_pct = 0,
+        soft_ttl_secs = 0,
+        negative_ttl_secs = None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        max_size: usize,
+        ttl_secs: u64,
+        serializer: Option<&str>,
+        max_pinned: Option<usize>,
+        sliding: bool,
+        max_bytes: Option<usize>,
+        weigher: Option<PyObject>,
+        compress_threshold: Option<usize>,
+        ttl_jitter_pct: u8,
+        soft_ttl_secs: u64,
+        negative_ttl_secs: Option<u64>,
+    ) -> PyResult<Self> {
+        if ttl_jitter_pct > 100 {
+            return Err(PyValueError::new_err(format!(
+                "ttl_jitter_pct must be between 0 and 100, not {}",
+                ttl_jitter_pct
+            )));
+        }
+        let serializer = match serializer {
+            None => None,
+            Some("pickle") => Some(Serializer::Pickle),
+            Some(other) => {
+                return Err(PyValueError::new_err(format!(
+                    "Unknown serializer {:?}; only \"pickle\" is supported",
+                    other
+                )))
+            }
+        };
+        let pinned = max_pinned.unwrap_or(usize::MAX);
+        let mut cache = Store::new(Eviction::Lru, max_size, ttl_secs, 1, pinned).record_removals();
+        if let Some(max_bytes) = max_bytes {
+            cache = cache.max_bytes(max_bytes);
+        }
+        Ok(Self {
+            cache: Arc::new(cache),
+            max_size: AtomicUsize::new(max_size),
+            ttl_secs,
+            max_pinned,
+            sliding,
+            max_bytes,
+            weigher,
+            compress_threshold,
+            ttl_jitter_pct,
+            soft_ttl_secs,
+            negative_ttl_secs,
+            serializer,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            insertions: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+            expirations: Arc::new(AtomicU64::new(0)),
+            inserted_bytes: AtomicU64::new(0),
+            compressions: AtomicU64::new(0),
+            compression_saved_bytes: AtomicU64::new(0),
+            listeners: Arc::new(Mutex::new(Vec::new())),
+            janitor: Mutex::new(None),
+            flights: Mutex::new(HashMap::new()),
+            writes: Mutex::new(()),
+            version: AtomicU64::new(0),
+        })
+    }
+
+    /// The value at `key`, or `None` if it is missing or expired
+    fn get(&self, py: Python<'_>, key: &str) -> PyResult<Option<PyObject>> {
+        self.get_encoded(py, key)
+            .map(|value| decode(py, value))
+            .transpose()
+    }
+
+    /// The value at `key` and its version, or `None` if it is missing or
+    /// expired
+    fn get_versioned(&self, py: Python<'_>, key: &str) -> PyResult<Option<(PyObject, u64)>> {
+        let entry = py.allow_threads(|| self.lookup(key));
+        self.notify(py);
+        let counter = if entry.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        entry
+            .map(|stored| Ok((decode(py, stored.value)?, stored.version)))
+            .transpose()
+    }
+
+    /// Store `value` at `key`, for `ttl` seconds if given, carrying `tags`
+    /// for `invalidate_tag` and `meta` for `get_with_meta`
+    #[pyo3(signature = (key, value, ttl = None, tags = None, meta = None))]
+    fn set(
+        &self,
+        py: Python<'_>,
+        key: String,
+        value: &Bound<'_, PyAny>,
+        ttl: Option<u64>,
+        tags: Option<Vec<String>>,
+        meta: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<()> {
+        let (value, mut options) = self.encode_at(&key, value)?;
+        options.tags = tags.unwrap_or_default();
+        options.meta = match meta {
+            Some(meta) => Some(depythonize::<serde_json::Value>(meta)?.to_string()),
+            None => None,
+        };
+        let stored = py.allow_threads(|| {
+            let _writes = self.writes();
+            self.store(key, value, ttl, options).map(|_| ())
+        });
+        self.notify(py);
+        stored
+    }
+
+    /// Cache that `key` has nothing (a denied or not-found result) for
+    /// `ttl` seconds if given, else `negative_ttl_secs`
+    #[pyo3(signature = (key, ttl = None))]
+    fn set_negative(&self, py: Python<'_>, key: String, ttl: Option<u64>) -> PyResult<()> {
+        let ttl = ttl.or(self.negative_ttl_secs);
+        let stored = py.allow_threads(|| {
+            let _writes = self.writes();
+            let value = NEGATIVE.to_string();
+            self.store(key, value, ttl, Options::default()).map(|_| ())
+        });
+        self.notify(py);
+        stored
+    }
+
+    /// Whether `key` is held and its value: `(False, None)` if it is
+    /// missing or expired, `(True, None)` for a cached negative
+    fn probe(&self, py: Python<'_>, key: &str) -> PyResult<(bool, Option<PyObject>)> {
+        match self.get(py, key)? {
+            Some(value) if value.is_none(py) => Ok((true, None)),
+            Some(value) => Ok((true, Some(value))),
+            None => Ok((false, None)),
+        }
+    }
+
+    /// `set`, where eviction can't reach the entry; raises
+    /// `SarkCacheError` if the cache already pins `max_pinned` entries
+    #[pyo3(signature = (key, value, ttl = None))]
+    fn set_pinned(
+        &self,
+        py: Python<'_>,
+        key: String,
+        value: &Bound<'_, PyAny>,
+        ttl: Option<u64>,
+    ) -> PyResult<()> {
+        let (value, mut options) = self.encode_at(&key, value)?;
+        options.pinned = true;
+        let stored = py.allow_threads(|| {
+            let _writes = self.writes();
+            self.store(key, value, ttl, options).map(|_| ())
+        });
+        self.notify(py);
+        stored
+    }
+
+    /// The value at `key` and whether it is stale, set `soft_ttl_secs` or
+    /// more ago (never, with no soft TTL), or `None` if it is missing or
+    /// expired
+    fn get_stale(&self, py: Python<'_>, key: &str) -> PyResult<Option<(PyObject, bool)>> {
+        let entry = py.allow_threads(|| self.lookup(key));
+        self.notify(py);
+        let counter = if entry.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        entry
+            .map(|stored| {
+                let age = stored.set_at.elapsed();
+                let stale =
+                    self.soft_ttl_secs > 0 && age >= Duration::from_secs(self.soft_ttl_secs);
+                Ok((decode(py, stored.value)?, stale))
+            })
+            .transpose()
+    }
+
+    /// The value at `key` and the `meta` it was set with (`None` if it was
+    /// set without), or `None` if it is missing or expired
+    fn get_with_meta(
+        &self,
+        py: Python<'_>,
+        key: &str,
+    ) -> PyResult<Option<(PyObject, Option<PyObject>)>> {
+        let entry = py.allow_threads(|| self.lookup(key));
+        self.notify(py);
+        let counter = if entry.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        let Some(stored) = entry else {
+            return Ok(None);
+        };
+        let meta = match stored.meta {
+            Some(meta) => {
+                let meta: serde_json::Value = serde_json::from_str(&meta)
+                    .map_err(|e| SarkCacheError::new_err(format!("Cache metadata: {}", e)))?;
+                Some(pythonize(py, &meta)?.unbind())
+            }
+            None => None,
+        };
+        Ok(Some((decode(py, stored.value)?, meta)))
+    }
+
+    /// The value at `key`, or if it is missing or expired, `compute()`'s,
+    /// set for `ttl` seconds if given. Concurrent calls for the same key
+    /// wait for the first to compute it rather than each computing it;
+    /// if that raises, the error is its own, and the next one waiting
+    /// computes it instead.
+    #[pyo3(signature = (key, compute, ttl = None))]
+    fn get_or_compute(
+        &self,
+        py: Python<'_>,
+        key: String,
+        compute: &Bound<'_, PyAny>,
+        ttl: Option<u64>,
+    ) -> PyResult<PyObject> {
+        if let Some(value) = self.get(py, &key)? {
+            return Ok(value);
+        }
+        let flight = loop {
+            let computing = {
+                let mut flights = self.flights.lock().expect("cache flights lock poisoned");
+                match flights.get(&key) {
+                    Some(flight) if flight.leader == thread::current().id() => {
+                        return Err(SarkCacheError::new_err(format!(
+                            "get_or_compute of {} called again while computing it",
+                            key
+                        )));
+                    }
+                    Some(flight) => Arc::clone(flight),
+                    None => {
+                        let flight = Arc::new(Flight::new());
+                        flights.insert(key.clone(), Arc::clone(&flight));
+                        break flight;
+                    }
+                }
+            };
+            py.allow_threads(|| computing.wait());
+            let stored = py.allow_threads(|| self.lookup(&key));
+            self.notify(py);
+            if let Some(stored) = stored {
+                return decode(py, stored.value);
+            }
+        };
+        let computed = compute.call0().and_then(|value| {
+            self.set(py, key.clone(), &value, ttl, None, None)?;
+            Ok(value.unbind())
+        });
+        self.flights
+            .lock()
+            .expect("cache flights lock poisoned")
+            .remove(&key);
+        flight.finish();
+        computed
+    }
+
+    /// Store `value` at `key`, for `ttl` seconds if given, only if the
+    /// entry is at `version` (missing, for `None`); returns the entry's
+    /// new version, or `None` if it was at another
+    #[pyo3(signature = (key, value, version, ttl = None))]
+    fn set_if_version(
+        &self,
+        py: Python<'_>,
+        key: String,
+        value: &Bound<'_, PyAny>,
+        version: Option<u64>,
+        ttl: Option<u64>,
+    ) -> PyResult<Option<u64>> {
+        let (value, options) = self.encode_at(&key, value)?;
+        let stored = py.allow_threads(|| {
+            let _writes = self.writes();
+            let current = self.cache.get(&key).map(|stored| stored.version);
+            if current != version {
+                return Ok(None);
+            }
+            self.store(key, value, ttl, options).map(Some)
+        });
+        self.notify(py);
+        stored
+    }
+
+    /// Add `delta` to the integer at `key`, atomically, returning the
+    /// result; a missing key starts from 0, kept for `ttl` seconds if
+    /// given, and a held one keeps its expiry. Raises `SarkCacheError` if
+    /// the value held isn't an integer, or the result overflows.
+    #[pyo3(signature = (key, delta = 1, ttl = None))]
+    fn incr(&self, py: Python<'_>, key: String, delta: i64, ttl: Option<u64>) -> PyResult<i64> {
+        let count = py.allow_threads(|| {
+            let _writes = self.writes();
+            let version = self.next_version();
+            let updated = self.cache.update(&key, |stored| {
+                let count = parse_count(&stored.value)?
+                    .checked_add(delta)
+                    .ok_or_else(|| overflow(&key))?;
+                stored.value = format!("{}{}", STR, count);
+                stored.version = version;
+                stored.weight = None;
+                Ok(count)
+            });
+            match updated {
+                Some(Ok(count)) => {
+                    self.insertions.fetch_add(1, Ordering::Relaxed);
+                    Ok(count)
+                }
+                Some(Err(e)) => Err(e),
+                None => {
+                    let value = format!("{}{}", STR, delta);
+                    self.store(key, value, ttl, Options::default())?;
+                    Ok(delta)
+                }
+            }
+        });
+        self.notify(py);
+        count
+    }
+
+    /// `incr` by `-delta`
+    #[pyo3(signature = (key, delta = 1))]
+    fn decr(&self, py: Python<'_>, key: String, delta: i64) -> PyResult<i64> {
+        let delta = delta.checked_neg().ok_or_else(|| overflow(&key))?;
+        self.incr(py, key, delta, None)
+    }
+
+    /// The values of those of `keys` held and not expired
+    fn get_many(&self, py: Python<'_>, keys: Vec<String>) -> PyResult<HashMap<String, PyObject>> {
+        let (mut hits, mut misses) = (0, 0);
+        let found: Vec<(String, String)> = py.allow_threads(|| {
+            keys.into_iter()
+                .filter_map(|key| {
+                    let stored = self.lookup(&key);
+                    match stored {
+                        Some(_) => hits += 1,
+                        None => misses += 1,
+                    }
+                    Some((key, stored?.value))
+                })
+                .collect()
+        });
+        self.notify(py);
+        self.hits.fetch_add(hits, Ordering::Relaxed);
+        self.misses.fetch_add(misses, Ordering::Relaxed);
+        found
+            .into_iter()
+            .map(|(key, value)| Ok((key, decode(py, value)?)))
+            .collect()
+    }
+
+    /// Store every value of `items` at its key, for `ttl` seconds if
+    /// given
+    #[pyo3(signature = (items, ttl = None))]
+    fn set_many(
+        &self,
+        py: Python<'_>,
+        items: HashMap<String, Bound<'_, PyAny>>,
+        ttl: Option<u64>,
+    ) -> PyResult<()> {
+        let items = items
+            .into_iter()
+            .map(|(key, value)| {
+                let (value, options) = self.encode_at(&key, &value)?;
+                Ok((key, value, options))
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+        let stored = py.allow_threads(|| {
+            let _writes = self.writes();
+            items.into_iter().try_for_each(|(key, value, options)| {
+                self.store(key, value, ttl, options).map(|_| ())
+            })
+        });
+        self.notify(py);
+        stored
+    }
+
+    /// Remove every one of `keys`, returning how many were there
+    fn delete_many(&self, py: Python<'_>, keys: Vec<String>) -> usize {
+        py.allow_threads(|| {
+            let _writes = self.writes();
+            keys.iter().filter(|key| self.cache.delete(key)).count()
+        })
+    }
+
+    /// Seconds `key` has left, rounded up, or 0 if it has expired and not
+    /// been swept; `None` if it isn't held
+    fn ttl(&self, py: Python<'_>, key: &str) -> Option<u64> {
+        py.allow_threads(|| {
+            let expires = self.cache.expiry(key)?;
+            Some(whole_secs(
+                expires.saturating_duration_since(Instant::now()),
+            ))
+        })
+    }
+
+    /// Keep `key` another `ttl` seconds (the cache's TTL if not given) from
+    /// now, returning whether it was held
+    #[pyo3(signature = (key, ttl = None))]
+    fn touch(&self, py: Python<'_>, key: &str, ttl: Option<u64>) -> bool {
+        py.allow_threads(|| self.reexpire(key, ttl.unwrap_or(self.ttl_secs)))
+    }
+
+    /// Expire `key` at `timestamp` (Unix seconds, rounded up to the next
+    /// whole second from now), dropping it if that has passed; returns
+    /// whether it was held
+    fn expire_at(&self, py: Python<'_>, key: &str, timestamp: f64) -> bool {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        let left = timestamp - now;
+        py.allow_threads(|| {
+            if left <= 0.0 {
+                let _writes = self.writes();
+                return self.cache.delete(key);
+            }
+            self.reexpire(key, left.ceil() as u64)
+        })
+    }
+
+    /// Remove every entry set with `tag`, returning how many there were
+    fn invalidate_tag(&self, py: Python<'_>, tag: &str) -> usize {
+        py.allow_threads(|| {
+            let _writes = self.writes();
+            self.cache
+                .remove_where(|_, stored| stored.tags.iter().any(|t| t == tag))
+        })
+    }
+
+    /// Remove every key starting with `prefix`, returning how many there
+    /// were
+    fn delete_prefix(&self, py: Python<'_>, prefix: &str) -> usize {
+        py.allow_threads(|| {
+            let _writes = self.writes();
+            self.cache.remove_where(|key, _| key.starts_with(prefix))
+        })
+    }
+
+    /// Remove every key matching `pattern`, where `*` matches any run of
+    /// characters and `?` any one, returning how many there were
+    fn delete_matching(&self, py: Python<'_>, pattern: &str) -> usize {
+        let pattern: Vec<char> = pattern.chars().collect();
+        py.allow_threads(|| {
+            let _writes = self.writes();
+            self.cache.remove_where(|key, _| glob(&pattern, key))
+        })
+    }
+
+    /// Remove `key`, returning whether it was there
+    pub(crate) fn delete(&self, py: Python<'_>, key: &str) -> bool {
+        py.allow_threads(|| {
+            let _writes = self.writes();
+            self.cache.delete(key)
+        })
+    }
+
+    /// Live keys, in no particular order
+    fn keys(&self, py: Python<'_>) -> Vec<String> {
+        py.allow_threads(|| self.live())
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect()
+    }
+
+    /// Live keys and their values, in no particular order
+    fn items(&self, py: Python<'_>) -> PyResult<Vec<(String, PyObject)>> {
+        py.allow_threads(|| self.live())
+            .into_iter()
+            .map(|(key, stored)| Ok((key, decode(py, stored.value)?)))
+            .collect()
+    }
+
+    fn __getitem__(&self, py: Python<'_>, key: &str) -> PyResult<PyObject> {
+        self.get(py, key)?
+            .ok_or_else(|| PyKeyError::new_err(key.to_string()))
+    }
+
+    /// `set`, with the cache's TTL
+    fn __setitem__(&self, py: Python<'_>, key: String, value: &Bound<'_, PyAny>) -> PyResult<()> {
+        self.set(py, key, value, None, None, None)
+    }
+
+    fn __delitem__(&self, py: Python<'_>, key: &str) -> PyResult<()> {
+        if self.delete(py, key) {
+            Ok(())
+        } else {
+            Err(PyKeyError::new_err(key.to_string()))
+        }
+    }
+
+    /// Whether `key` is held and not expired; not counted as a lookup
+    fn __contains__(&self, py: Python<'_>, key: &str) -> bool {
+        let held = py.allow_threads(|| self.cache.get(key).is_some());
+        self.notify(py);
+        held
+    }
+
+    /// Live keys, as `keys()` lists them
+    fn __len__(&self, py: Python<'_>) -> usize {
+        py.allow_threads(|| self.live().len())
+    }
+
+    fn __iter__<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyIterator>> {
+        PyList::new(py, self.keys(py))?.try_iter()
+    }
+
+    /// Entries held, expired or not
+    fn size(&self, py: Python<'_>) -> usize {
+        py.allow_threads(|| self.cache.size())
+    }
+
+    /// Bytes the entries held take, their keys' length plus their values'
+    /// (or what the weigher gave them), as `max_bytes` counts them
+    fn memory_usage(&self, py: Python<'_>) -> usize {
+        py.allow_threads(|| self.cache.bytes())
+    }
+
+    /// Hold at most `max_size` entries from now on, evicting the least
+    /// recently used down to it at once; returns how many were evicted.
+    /// Pinned entries stay until they expire, even past half the new size.
+    fn resize(&self, py: Python<'_>, max_size: usize) -> usize {
+        let evicted = py.allow_threads(|| {
+            let _writes = self.writes();
+            self.max_size.store(max_size, Ordering::Relaxed);
+            let evicted = self.cache.resize(max_size);
+            self.evictions.fetch_add(evicted as u64, Ordering::Relaxed);
+            evicted
+        });
+        self.notify(py);
+        evicted
+    }
+
+    /// Drop expired entries, returning how many there were
+    fn cleanup_expired(&self, py: Python<'_>) -> usize {
+        let expired = py.allow_threads(|| sweep(&self.cache, &self.expirations));
+        self.notify(py);
+        expired
+    }
+
+    /// Sweep expired entries every `interval_secs`, as `cleanup_expired`
+    /// does, on a thread of the cache's own until `stop_janitor`; started
+    /// again, it sweeps at the new interval
+    fn start_janitor(&self, py: Python<'_>, interval_secs: f64) -> PyResult<()> {
+        let interval = Duration::try_from_secs_f64(interval_secs)
+            .ok()
+            .filter(|interval| !interval.is_zero())
+            .ok_or_else(|| {
+                PyValueError::new_err(format!(
+                    "Janitor interval must be a positive number of seconds, not {}",
+                    interval_secs
+                ))
+            })?;
+        let (cache, expirations) = (Arc::clone(&self.cache), Arc::clone(&self.expirations));
+        let listeners = Arc::clone(&self.listeners);
+        let janitor = Janitor::start(interval, move || {
+            sweep(&cache, &expirations);
+            let removed = cache.removed();
+            if !removed.is_empty() {
+                Python::with_gil(|py| call_listeners(py, &listeners, removed));
+            }
+        });
+        let replaced = self
+            .janitor
+            .lock()
+            .expect("cache janitor lock poisoned")
+            .replace(janitor);
+        if let Some(replaced) = replaced {
+            py.allow_threads(|| replaced.stop());
+        }
+        Ok(())
+    }
+
+    /// Stop the janitor, waiting out a sweep in progress; returns whether
+    /// one was running
+    fn stop_janitor(&self, py: Python<'_>) -> bool {
+        let janitor = self
+            .janitor
+            .lock()
+            .expect("cache janitor lock poisoned")
+            .take();
+        match janitor {
+            // The sweep may be waiting for the GIL to call listeners
+            Some(janitor) => {
+                py.allow_threads(|| janitor.stop());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Call `listener(key, value, cause)` with each entry evicted to make
+    /// room (`cause` `"evicted"`) or found expired (`"expired"`) from now
+    /// on
+    fn add_listener(&self, listener: PyObject) {
+        self.listeners
+            .lock()
+            .expect("cache listeners lock poisoned")
+            .push(listener);
+    }
+
+    /// The weigher and listeners, for the cycle collector: a listener
+    /// that mirrors into the cache it listens to refers back to it
+    fn __traverse__(&self, visit: PyVisit<'_>) -> Result<(), PyTraverseError> {
+        if let Some(weigher) = &self.weigher {
+            visit.call(weigher)?;
+        }
+        // Held only while the GIL is, so never while collecting
+        if let Ok(listeners) = self.listeners.try_lock() {
+            for listener in listeners.iter() {
+                visit.call(listener)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn __clear__(&mut self) {
+        self.weigher = None;
+        if let Ok(mut listeners) = self.listeners.lock() {
+            listeners.clear();
+        }
+    }
+
+    /// Stop calling `listener`, returning whether it was added
+    fn remove_listener(&self, listener: &Bound<'_, PyAny>) -> PyResult<bool> {
+        let mut listeners = self
+            .listeners
+            .lock()
+            .expect("cache listeners lock poisoned");
+        for (i, added) in listeners.iter().enumerate() {
+            if listener.eq(added)? {
+                listeners.remove(i);
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    pub(crate) fn clear(&self, py: Python<'_>) {
+        py.allow_threads(|| {
+            let _writes = self.writes();
+            self.cache.clear();
+        });
+    }
+
+    /// Size, limits, counts since the cache was created and an estimate
+    /// of the memory it holds
+    pub(crate) fn stats(&self, py: Python<'_>) -> CacheStats {
+        let size = py.allow_threads(|| self.cache.size());
+        let insertions = self.insertions.load(Ordering::Relaxed);
+        let mean_bytes = self.inserted_bytes.load(Ordering::Relaxed) / insertions.max(1);
+        CacheStats {
+            size,
+            max_size: self.max_size.load(Ordering::Relaxed),
+            ttl_secs: self.ttl_secs,
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            insertions,
+            evictions: self.evictions.load(Ordering::Relaxed),
+            expirations: self.expirations.load(Ordering::Relaxed),
+            memory_bytes: size as u64 * (mean_bytes + ENTRY_OVERHEAD),
+            compressions: self.compressions.load(Ordering::Relaxed),
+            compression_saved_bytes: self.compression_saved_bytes.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Write the live entries, with their remaining TTLs, tags and meta,
+    /// to `path`, through a temporary file renamed over it; returns how
+    /// many were written
+    fn save_snapshot(&self, py: Python<'_>, path: PathBuf) -> PyResult<usize> {
+        py.allow_threads(|| {
+            let now = Instant::now();
+            let entries = self.cache.entries();
+            let mut snapshot = SNAPSHOT_MAGIC.to_vec();
+            snapshot.extend_from_slice(&unix_secs().to_le_bytes());
+            for (key, stored, expires) in &entries {
+                put_str(&mut snapshot, key);
+                put_str(&mut snapshot, &uncompressed(&stored.value)?);
+                let ttl = whole_secs(expires.saturating_duration_since(now));
+                snapshot.extend_from_slice(&ttl.to_le_bytes());
+                snapshot.extend_from_slice(&(stored.tags.len() as u32).to_le_bytes());
+                for tag in &stored.tags {
+                    put_str(&mut snapshot, tag);
+                }
+                match &stored.meta {
+                    Some(meta) => {
+                        snapshot.push(1);
+                        put_str(&mut snapshot, meta);
+                    }
+                    None => snapshot.push(0),
+                }
+            }
+            let tmp = path.with_extension("tmp");
+            std::fs::write(&tmp, &snapshot)
+                .and_then(|()| std::fs::rename(&tmp, &path))
+                .map_err(|e| {
+                    SarkCacheError::new_err(format!(
+                        "Failed to write cache snapshot {}: {}",
+                        path.display(),
+                        e
+                    ))
+                })?;
+            Ok(entries.len())
+        })
+    }
+
+    /// Set each entry `save_snapshot` wrote to `path` for the TTL it had
+    /// left less the time since, leaving out those that expired meanwhile;
+    /// returns how many were set
+    fn load_snapshot(&self, py: Python<'_>, path: PathBuf) -> PyResult<usize> {
+        let entries = py.allow_threads(|| read_snapshot(&path))?;
+        let entries = entries
+            .into_iter()
+            .map(|entry| {
+                let weight = match &self.weigher {
+                    Some(weigher) => {
+                        let value = decode(py, entry.value.clone())?;
+                        Some(weigher.call1(py, (&entry.key, value))?.extract(py)?)
+                    }
+                    None => None,
+                };
+                Ok((entry, weight))
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+        let loaded = py.allow_threads(|| {
+            let _writes = self.writes();
+            entries.into_iter().try_fold(0, |loaded, (entry, weight)| {
+                let options = Options {
+                    tags: entry.tags,
+                    meta: entry.meta,
+                    weight,
+                    ..Options::default()
+                };
+                self.store(entry.key, entry.value, Some(entry.ttl), options)
+                    .map(|_| loaded + 1)
+            })
+        });
+        self.notify(py);
+        loaded
+    }
+
+    /// `max_size` and `ttl_secs`, and the options given by keyword
+    fn __getnewargs_ex__<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<((usize, u64), Bound<'py, PyDict>)> {
+        let options = PyDict::new(py);
+        let serializer = self.serializer.map(|Serializer::Pickle| "pickle");
+        options.set_item("serializer", serializer)?;
+        options.set_item("max_pinned", self.max_pinned)?;
+        options.set_item("sliding", self.sliding)?;
+        options.set_item("max_bytes", self.max_bytes)?;
+        options.set_item("weigher", &self.weigher)?;
+        options.set_item("compress_threshold", self.compress_threshold)?;
+        options.set_item("ttl_jitter_pct", self.ttl_jitter_pct)?;
+        options.set_item("soft_ttl_secs", self.soft_ttl_secs)?;
+        options.set_item("negative_ttl_secs", self.negative_ttl_secs)?;
+        let max_size = self.max_size.load(Ordering::Relaxed);
+        Ok(((max_size, self.ttl_secs), options))
+    }
+
+    /// Live entries with their remaining TTLs; hit and miss counts aren't
+    /// kept
+    fn __getstate__(&self, py: Python<'_>) -> PyResult<Entries<PyObject>> {
+        let entries: Entries<String> = py.allow_threads(|| {
+            let now = Instant::now();
+            self.cache
+                .entries()
+                .into_iter()
+                // Rounded up, so an entry with part of a second left isn't
+                // restored as already expired
+                .map(|(key, stored, expires)| {
+                    let ttl = whole_secs(expires.saturating_duration_since(now));
+                    (key, stored.value, ttl)
+                })
+                .collect()
+        });
+        entries
+            .into_iter()
+            .map(|(key, value, ttl)| Ok((key, decode(py, value)?, ttl)))
+            .collect()
+    }
+
+    fn __setstate__(&self, py: Python<'_>, entries: Entries<Bound<'_, PyAny>>) -> PyResult<()> {
+        let entries = entries
+            .into_iter()
+            .map(|(key, value, ttl)| {
+                let (value, options) = self.encode_at(&key, &value)?;
+                Ok((key, value, ttl, options))
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+        let stored = py.allow_threads(|| {
+            let _writes = self.writes();
+            entries
+                .into_iter()
+                .try_for_each(|(key, value, ttl, options)| {
+                    self.store(key, value, Some(ttl), options).map(|_| ())
+                })
+        });
+        self.notify(py);
+        stored
+    }
+}
+
+impl RustCache {
+    /// A cache with none of the options, as `RustTieredCache`'s L1
+    pub(crate) fn plain(
+        max_size: usize,
+        ttl_secs: u64,
+        serializer: Option<&str>,
+    ) -> PyResult<Self> {
+        Self::new(
+            max_size, ttl_secs, serializer, None, false, None, None, None, 0, 0, None,
+        )
+    }
+
+    /// The value at `key` as stored, or `None` if it is missing or
+    /// expired; counted as a lookup
+    pub(crate) fn get_encoded(&self, py: Python<'_>, key: &str) -> Option<String> {
+        let stored = py.allow_threads(|| self.lookup(key));
+        self.notify(py);
+        let counter = if stored.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        stored.map(|stored| stored.value)
+    }
+
+    /// Store `value`, as `encode` made it, at `key`, for `ttl` seconds if
+    /// given
+    pub(crate) fn set_encoded(
+        &self,
+        py: Python<'_>,
+        key: String,
+        value: String,
+        ttl: Option<u64>,
+    ) -> PyResult<()> {
+        let stored = py.allow_threads(|| {
+            let _writes = self.writes();
+            self.store(key, value, ttl, Options::default()).map(|_| ())
+        });
+        self.notify(py);
+        stored
+    }
+
+    /// `key`'s entry if it is live, kept another of its TTL if the cache
+    /// is sliding
+    fn lookup(&self, key: &str) -> Option<Stored> {
+        let stored = self.cache.get(key)?;
+        if self.sliding {
+            let expires = Instant::now() + Duration::from_secs(stored.ttl);
+            self.cache.expire(key, expires);
+        }
+        Some(stored)
+    }
+
+    /// Live entries, in no particular order
+    fn live(&self) -> Vec<(String, Stored)> {
+        self.cache
+            .entries()
+            .into_iter()
+            .map(|(key, stored, _)| (key, stored))
+            .collect()
+    }
+
+    /// Keep `key`, if held, `ttl` more seconds, at the same version
+    fn reexpire(&self, key: &str, ttl: u64) -> bool {
+        self.cache
+            .expire(key, Instant::now() + Duration::from_secs(ttl))
+    }
+
+    /// `value` as stored at `key`, and the options a plain set gives it:
+    /// its weight, if the cache has a weigher
+    fn encode_at(&self, key: &str, value: &Bound<'_, PyAny>) -> PyResult<(String, Options)> {
+        let weight = match &self.weigher {
+            Some(weigher) => Some(weigher.bind(value.py()).call1((key, value))?.extract()?),
+            None => None,
+        };
+        let options = Options {
+            weight,
+            ..Options::default()
+        };
+        Ok((self.encode(value)?, options))
+    }
+
+    /// `value` as stored, tagged with its type
+    pub(crate) fn encode(&self, value: &Bound<'_, PyAny>) -> PyResult<String> {
+        if value.is_none() {
+            return Ok(NEGATIVE.to_string());
+        }
+        if let Ok(text) = value.downcast::<PyString>() {
+            let text = text.to_str()?;
+            let mut stored = String::with_capacity(text.len() + 1);
+            stored.push(STR);
+            stored.push_str(text);
+            return Ok(stored);
+        }
+        if let Ok(bytes) = value.downcast::<PyBytes>() {
+            return Ok(tagged_bytes(BYTES, bytes.as_bytes()));
+        }
+        match self.serializer {
+            Some(Serializer::Pickle) => {
+                let pickled = value
+                    .py()
+                    .import("pickle")?
+                    .call_method1("dumps", (value,))?;
+                Ok(tagged_bytes(
+                    PICKLED,
+                    pickled.downcast::<PyBytes>()?.as_bytes(),
+                ))
+            }
+            None => Err(PyTypeError::new_err(format!(
+                "RustCache values must be str, bytes or None, not {}; pass serializer=\"pickle\" \
+                 to cache other objects",
+                value.get_type().name()?
+            ))),
+        }
+    }
+
+    /// Store `value` at `key` as `options` say, under the held write lock,
+    /// returning the entry's new version
+    fn store(
+        &self,
+        key: String,
+        value: String,
+        ttl: Option<u64>,
+        options: Options,
+    ) -> PyResult<u64> {
+        let value = self.compress(value);
+        let bytes = (key.len() + value.len()) as u64;
+        // A key the store doesn't hold live either expired in place, and
+        // is overwritten, or is new, and evicts an entry if the store is
+        // full
+        let replaced = match self.cache.expiry(&key) {
+            Some(expires) if expires <= Instant::now() => Some(&*self.expirations),
+            Some(_) => None,
+            None if self.cache.size() >= self.max_size.load(Ordering::Relaxed) => {
+                Some(&self.evictions)
+            }
+            None => None,
+        };
+        let version = self.next_version();
+        let ttl = ttl.unwrap_or(self.ttl_secs);
+        let stored = Stored {
+            value,
+            version,
+            tags: options.tags,
+            meta: options.meta,
+            ttl,
+            weight: options.weight,
+            set_at: Instant::now(),
+        };
+        let ttl = Some(jittered_ttl(ttl, self.ttl_jitter_pct));
+        if options.pinned {
+            self.cache.set_pinned(key, stored, ttl)
+        } else {
+            self.cache.set(key, stored, ttl)
+        }
+        .map_err(|e| SarkCacheError::new_err(e.to_string()))?;
+        if let Some(counter) = replaced {
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+        self.insertions.fetch_add(1, Ordering::Relaxed);
+        self.inserted_bytes.fetch_add(bytes, Ordering::Relaxed);
+        Ok(version)
+    }
+
+    /// `value` deflated, if it's as large as the cache compresses and
+    /// deflating makes it smaller
+    fn compress(&self, value: String) -> String {
+        match self.compress_threshold {
+            Some(threshold) if value.len() >= threshold => {}
+            _ => return value,
+        }
+        let mut deflated = Vec::new();
+        // Reading from a slice can't fail
+        if ZlibEncoder::new(value.as_bytes(), Compression::fast())
+            .read_to_end(&mut deflated)
+            .is_err()
+        {
+            return value;
+        }
+        let compressed = tagged_bytes(COMPRESSED, &deflated);
+        if compressed.len() >= value.len() {
+            return value;
+        }
+        let saved = (value.len() - compressed.len()) as u64;
+        self.compressions.fetch_add(1, Ordering::Relaxed);
+        self.compression_saved_bytes
+            .fetch_add(saved, Ordering::Relaxed);
+        compressed
+    }
+
+    /// Call the listeners with each entry the store evicted or found
+    /// expired since last time, without the store's locks or the write
+    /// lock, so a listener may use the cache; the operation having gone
+    /// through, what a listener raises is reported as unraisable
+    fn notify(&self, py: Python<'_>) {
+        let removed = py.allow_threads(|| self.cache.removed());
+        if !removed.is_empty() {
+            call_listeners(py, &self.listeners, removed);
+        }
+    }
+
+    fn next_version(&self) -> u64 {
+        self.version.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    fn writes(&self) -> MutexGuard<'_, ()> {
+        self.writes.lock().expect("cache write lock poisoned")
+    }
+}
+
+/// Whether `key` matches `pattern`, where `*` matches any run of
+/// characters and `?` any one
+fn glob(pattern: &[char], key: &str) -> bool {
+    let key: Vec<char> = key.chars().collect();
+    let (mut p, mut k) = (0, 0);
+    // The last `*` seen, and where in the key its run would end next
+    let mut star = None;
+    while k < key.len() {
+        match pattern.get(p).copied() {
+            Some('*') => {
+                star = Some((p, k));
+                p += 1;
+            }
+            Some(c) if c == '?' || c == key[k] => {
+                p += 1;
+                k += 1;
+            }
+            _ => match star {
+                // Let the last `*` match one more character
+                Some((star_p, star_k)) => {
+                    star = Some((star_p, star_k + 1));
+                    p = star_p + 1;
+                    k = star_k + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// The integer `stored` holds, as `incr` stores it
+/// Drop `cache`'s expired entries, counting them in `expirations`
+fn sweep(cache: &Store<Stored>, expirations: &AtomicU64) -> usize {
+    let expired = cache.cleanup_expired();
+    expirations.fetch_add(expired as u64, Ordering::Relaxed);
+    expired
+}
+
+/// Call `listeners` with each of the entries `removed`, reporting what
+/// they raise as unraisable
+fn call_listeners(
+    py: Python<'_>,
+    listeners: &Mutex<Vec<PyObject>>,
+    removed: Vec<(String, Stored, Removal)>,
+) {
+    let listeners: Vec<PyObject> = listeners
+        .lock()
+        .expect("cache listeners lock poisoned")
+        .iter()
+        .map(|listener| listener.clone_ref(py))
+        .collect();
+    if listeners.is_empty() {
+        return;
+    }
+    for (key, stored, removal) in removed {
+        let cause = match removal {
+            Removal::Evicted => "evicted",
+            Removal::Expired => "expired",
+        };
+        let value = match decode(py, stored.value) {
+            Ok(value) => value,
+            Err(e) => {
+                e.write_unraisable(py, None);
+                continue;
+            }
+        };
+        for listener in &listeners {
+            if let Err(e) = listener.call1(py, (&key, &value, cause)) {
+                e.write_unraisable(py, Some(listener.bind(py)));
+            }
+        }
+    }
+}
+
+fn parse_count(stored: &str) -> PyResult<i64> {
+    let text = stored.strip_prefix(STR).unwrap_or(stored);
+    text.parse()
+        .map_err(|_| SarkCacheError::new_err("Cache value is not an integer"))
+}
+
+fn overflow(key: &str) -> PyErr {
+    SarkCacheError::new_err(format!("Counter {} would overflow", key))
+}
+
+/// `left` in seconds, rounded up
+fn whole_secs(left: Duration) -> u64 {
+    left.as_secs() + u64::from(left.subsec_nanos() > 0)
+}
+
+/// `tag` then one character per byte of `bytes`
+fn tagged_bytes(tag: char, bytes: &[u8]) -> String {
+    let mut stored = String::with_capacity(bytes.len() * 2 + 1);
+    stored.push(tag);
+    stored.extend(bytes.iter().map(|&byte| char::from(byte)));
+    stored
+}
+
+/// The value `compressed` holds, following its tag
+fn inflate(compressed: &str) -> PyResult<String> {
+    let deflated: Vec<u8> = compressed.chars().map(|c| c as u8).collect();
+    let mut inflated = String::new();
+    ZlibDecoder::new(&deflated[..])
+        .read_to_string(&mut inflated)
+        .map_err(|e| {
+            SarkCacheError::new_err(format!("Cache value can't be decompressed: {}", e))
+        })?;
+    Ok(inflated)
+}
+
+/// `stored` inflated if it was compressed, so a snapshot doesn't depend
+/// on the saving cache's `compress_threshold`
+fn uncompressed(stored: &str) -> PyResult<String> {
+    match stored.strip_prefix(COMPRESSED) {
+        Some(compressed) => inflate(compressed),
+        None => Ok(stored.to_string()),
+    }
+}
+
+fn unix_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// `text` after its length, as a snapshot holds strings
+fn put_str(snapshot: &mut Vec<u8>, text: &str) {
+    snapshot.extend_from_slice(&(text.len() as u32).to_le_bytes());
+    snapshot.extend_from_slice(text.as_bytes());
+}
+
+/// An entry of a snapshot, its TTL what it had left at loading
+struct SnapshotEntry {
+    key: String,
+    value: String,
+    ttl: u64,
+    tags: Vec<String>,
+    meta: Option<String>,
+}
+
+/// The entries of the snapshot at `path` that haven't expired since it
+/// was saved
+fn read_snapshot(path: &Path) -> PyResult<Vec<SnapshotEntry>> {
+    let bytes = std::fs::read(path).map_err(|e| {
+        SarkCacheError::new_err(format!(
+            "Failed to read cache snapshot {}: {}",
+            path.display(),
+            e
+        ))
+    })?;
+    let invalid =
+        || SarkCacheError::new_err(format!("{} is not a RustCache snapshot", path.display()));
+    let mut snapshot = Snapshot(bytes.strip_prefix(SNAPSHOT_MAGIC).ok_or_else(invalid)?);
+    let saved_at = snapshot.u64().ok_or_else(invalid)?;
+    let elapsed = unix_secs().saturating_sub(saved_at);
+    let mut entries = Vec::new();
+    while !snapshot.0.is_empty() {
+        let entry = snapshot.entry(elapsed).ok_or_else(invalid)?;
+        if entry.ttl > 0 {
+            entries.push(entry);
+        }
+    }
+    Ok(entries)
+}
+
+/// The rest of a snapshot being read
+struct Snapshot<'a>(&'a [u8]);
+
+impl Snapshot<'_> {
+    fn take(&mut self, len: usize) -> Option<&[u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(taken)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take(8)?.try_into().ok()?))
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.take(len)?.to_vec()).ok()
+    }
+
+    /// The next entry, `elapsed` seconds taken off its TTL
+    fn entry(&mut self, elapsed: u64) -> Option<SnapshotEntry> {
+        let key = self.string()?;
+        let value = self.string()?;
+        let ttl = self.u64()?.saturating_sub(elapsed);
+        let tags = (0..self.u32()?)
+            .map(|_| self.string())
+            .collect::<Option<_>>()?;
+        let meta = match self.take(1)?[0] {
+            0 => None,
+            1 => Some(self.string()?),
+            _ => return None,
+        };
+        Some(SnapshotEntry {
+            key,
+            value,
+            ttl,
+            tags,
+            meta,
+        })
+    }
+}
+
+/// The Python value `stored` holds
+pub(crate) fn decode(py: Python<'_>, stored: String) -> PyResult<PyObject> {
+    let mut chars = stored.chars();
+    let bytes = |chars: std::str::Chars<'_>| -> Vec<u8> { chars.map(|c| c as u8).collect() };
+    match chars.next() {
+        Some(STR) => Ok(PyString::new(py, chars.as_str()).into_any().unbind()),
+        Some(BYTES) => Ok(PyBytes::new(py, &bytes(chars)).into_any().unbind()),
+        Some(PICKLED) => {
+            let pickled = PyBytes::new(py, &bytes(chars));
+            Ok(py
+                .import("pickle")?
+                .call_method1("loads", (pickled,))?
+                .unbind())
+        }
+        Some(NEGATIVE) => Ok(py.None()),
+        Some(COMPRESSED) => decode(py, inflate(chars.as_str())?),
+        _ => Ok(PyString::new(py, &stored).into_any().unbind()),
+    }
+}
+
+impl Drop for RustCache {
+    /// Let the janitor finish; joining it here, with the GIL held, could
+    /// wait on a sweep waiting for the GIL
+    fn drop(&mut self) {
+        if let Ok(Some(janitor)) = self.janitor.get_mut().map(Option::take) {
+            janitor.signal();
+        }
+    }
+}
+
+/// A key being computed by `get_or_compute`, which other calls for it
+/// wait out
+struct Flight {
+    /// The thread computing it
+    leader: ThreadId,
+    done: Mutex<bool>,
+    finished: Condvar,
+}
+
+impl Flight {
+    fn new() -> Self {
+        Self {
+            leader: thread::current().id(),
+            done: Mutex::new(false),
+            finished: Condvar::new(),
+        }
+    }
+
+    fn wait(&self) {
+        let done = self.done.lock().expect("cache flight lock poisoned");
+        let _done = self
+            .finished
+            .wait_while(done, |done| !*done)
+            .expect("cache flight lock poisoned");
+    }
+
+    fn finish(&self) {
+        *self.done.lock().expect("cache flight lock poisoned") = true;
+        self.finished.notify_all();
+    }
+}
+
+/// A thread calling `sweep` every interval until stopped
+struct Janitor {
+    /// Set to stop it, waking it if it's waiting out the interval
+    stopped: Arc<(Mutex<bool>, Condvar)>,
+    thread: JoinHandle<()>,
+}
+
+impl Janitor {
+    fn start(interval: Duration, sweep: impl Fn() + Send + 'static) -> Self {
+        let stopped = Arc::new((Mutex::new(false), Condvar::new()));
+        let signal = Arc::clone(&stopped);
+        let thread = thread::Builder::new()
+            .name("sark-cache-janitor".to_string())
+            .spawn(move || {
+                let (stopped, wake) = &*signal;
+                let mut guard = stopped.lock().expect("janitor lock poisoned");
+                loop {
+                    guard = wake
+                        .wait_timeout_while(guard, interval, |stopped| !*stopped)
+                        .expect("janitor lock poisoned")
+                        .0;
+                    if *guard {
+                        return;
+                    }
+                    drop(guard);
+                    sweep();
+                    guard = stopped.lock().expect("janitor lock poisoned");
+                }
+            })
+            .expect("failed to spawn the cache janitor");
+        Self { stopped, thread }
+    }
+
+    fn signal(&self) {
+        let (stopped, wake) = &*self.stopped;
+        *stopped.lock().expect("janitor lock poisoned") = true;
+        wake.notify_one();
+    }
+
+    /// Signal the thread and wait for it to finish
+    fn stop(self) {
+        self.signal();
+        // A panicking sweep has already been reported on stderr
+        let _ = self.thread.join();
+    }
+}
+
+/// A snapshot of a `RustCache`'s state
+#[pyclass(module = "sark_rust", frozen, get_all)]
+pub struct CacheStats {
+    /// Entries held, expired or not
+    pub(crate) size: usize,
+    pub(crate) max_size: usize,
+    /// TTL of entries set without their own
+    pub(crate) ttl_secs: u64,
+    pub(crate) hits: u64,
+    /// Lookups of missing or expired keys
+    pub(crate) misses: u64,
+    /// Sets, new keys or not
+    pub(crate) insertions: u64,
+    /// Live entries dropped to make room for new ones
+    pub(crate) evictions: u64,
+    /// Entries dropped or overwritten after expiring
+    pub(crate) expirations: u64,
+    /// Estimated bytes the entries take
+    pub(crate) memory_bytes: u64,
+    /// Values stored compressed
+    pub(crate) compressions: u64,
+    /// Bytes compression took off those values
+    pub(crate) compression_saved_bytes: u64,
+}
+
+#[pymethods]
+impl CacheStats {
+    /// Share of lookups that hit, from 0.0 to 1.0 (0.0 before any)
+    #[getter]
+    fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            total => self.hits as f64 / total as f64,
+        }
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "CacheStats(size={}, max_size={}, hits={}, misses={}, evictions={})",
+            self.size, self.max_size, self.hits, self.misses, self.evictions
+        )
+    }
+}
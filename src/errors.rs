@@ -0,0 +1,69 @@
+//! Exceptions raised by `sark_rust`
+//!
+//! ```text
+//! SarkError
+//! ├── SarkCacheError
+//! ├── SarkPolicyError
+//! │   ├── PolicyCompileError
+//! │   └── PolicyEvalError
+//! │       └── MalformedInputError
+//! └── GatewayError
+//! ```
+//!
+//! Arguments of the wrong shape (OPA input that isn't JSON-like, a token
+//! and an API key together) still raise `ValueError`, and
+//! `JWTValidationError` (from sark-jwt, shared with the gateway) stays a
+//! `ValueError` of its own.
+
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+
+create_exception!(
+    sark_rust,
+    SarkError,
+    PyException,
+    "Base class of the errors sark_rust raises"
+);
+create_exception!(
+    sark_rust,
+    SarkCacheError,
+    SarkError,
+    "The cache refused an operation"
+);
+create_exception!(
+    sark_rust,
+    SarkPolicyError,
+    SarkError,
+    "The policy engine failed"
+);
+create_exception!(
+    sark_rust,
+    PolicyCompileError,
+    SarkPolicyError,
+    "A policy module didn't compile"
+);
+create_exception!(
+    sark_rust,
+    PolicyEvalError,
+    SarkPolicyError,
+    "A query couldn't be evaluated"
+);
+create_exception!(
+    sark_rust,
+    MalformedInputError,
+    PolicyEvalError,
+    "The input failed the engine's input schema"
+);
+
+/// Add the exceptions to the module
+pub fn register(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    let py = m.py();
+    m.add("SarkError", py.get_type::<SarkError>())?;
+    m.add("SarkCacheError", py.get_type::<SarkCacheError>())?;
+    m.add("SarkPolicyError", py.get_type::<SarkPolicyError>())?;
+    m.add("PolicyCompileError", py.get_type::<PolicyCompileError>())?;
+    m.add("PolicyEvalError", py.get_type::<PolicyEvalError>())?;
+    m.add("MalformedInputError", py.get_type::<MalformedInputError>())?;
+    Ok(())
+}
@@ -0,0 +1,246 @@
+//! Async client for the Rust gateway, for the Python API
+//!
+//! `GatewayClient` wraps `sark-client` so the Python API can ask the
+//! gateway's hot path for decisions without a Python HTTP stack in the
+//! way. Calls return awaitables driven on a tokio runtime with the GIL
+//! released, over a pooled connection to the gateway:
+//!
+//! ```python
+//! from sark.sark_rust import GatewayClient
+//!
+//! client = GatewayClient("http://sark-gateway:8080", fail_open=False)
+//! decision = await client.authorize(
+//!     {"action": "tool:invoke", "server_name": "github", "tool_name": "create_issue"},
+//!     token=user_token,
+//! )
+//! ```
+//!
+//! Decisions come back as dicts shaped like the gateway's responses, and
+//! are cached in grid-core's `LRUTTLCache`, for the `cache_ttl` the
+//! gateway gave them, keyed by endpoint, token and request.
+//! Batches aren't cached. Requests the gateway didn't decide raise
+//! `GatewayError`, unless it was unavailable and the client fails open.
+
+use crate::errors::SarkError;
+use grid_cache::LRUTTLCache;
+use pyo3::create_exception;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pythonize::{depythonize, pythonize};
+use sark_client::{
+    A2aRequest, AuthorizeRequest, Client, Decision, Error, FailureMode, RetryPolicy,
+    FAIL_OPEN_REVISION,
+};
+use std::sync::Arc;
+use std::time::Duration;
+
+create_exception!(
+    sark_rust,
+    GatewayError,
+    SarkError,
+    "The gateway didn't decide the request"
+);
+
+/// A decision to ask the gateway for
+enum Query {
+    Authorize(AuthorizeRequest),
+    A2a(A2aRequest),
+}
+
+impl Query {
+    fn route(&self) -> &'static str {
+        match self {
+            Query::Authorize(_) => "/gateway/authorize",
+            Query::A2a(_) => "/gateway/authorize-a2a",
+        }
+    }
+
+    fn dry_run(&self) -> bool {
+        matches!(self, Query::Authorize(request) if request.dry_run)
+    }
+}
+
+/// Async client for the gateway's decision endpoints
+#[pyclass(module = "sark_rust")]
+pub struct GatewayClient {
+    client: Client,
+    /// `None` with `cache_size=0`
+    cache: Option<Arc<LRUTTLCache>>,
+}
+
+#[pymethods]
+impl GatewayClient {
+    /// Client for the gateway at `base_url`, authenticating with `token`
+    /// (a JWT) or `api_key` unless a call passes its own token
+    #[new]
+    #[pyo3(signature = (
+        base_url,
+        *,
+        token = None,
+        api_key = None,
+        timeout_ms = 2000,
+        max_retries = 2,
+        pool_size = 32,
+        cache_size = 10_000,
+        cache_ttl_secs = 300,
+        fail_open = false,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        base_url: String,
+        token: Option<String>,
+        api_key: Option<String>,
+        timeout_ms: u64,
+        max_retries: u32,
+        pool_size: usize,
+        cache_size: usize,
+        cache_ttl_secs: u64,
+        fail_open: bool,
+    ) -> PyResult<Self> {
+        let mut builder = Client::builder(base_url)
+            .timeout(Duration::from_millis(timeout_ms))
+            .pool_max_idle_per_host(pool_size)
+            .retry(RetryPolicy {
+                max_retries,
+                ..RetryPolicy::default()
+            })
+            // Cached here instead, in the store RustCache uses
+            .cache_entries(0)
+            .failure_mode(if fail_open {
+                FailureMode::Open
+            } else {
+                FailureMode::Closed
+            });
+        match (token, api_key) {
+            (Some(_), Some(_)) => {
+                return Err(PyValueError::new_err("pass token or api_key, not both"))
+            }
+            (Some(token), None) => builder = builder.bearer_token(token),
+            (None, Some(key)) => builder = builder.api_key(key),
+            (None, None) => {}
+        }
+        let client = builder.build().map_err(to_py_err)?;
+        let cache =
+            (cache_size > 0).then(|| Arc::new(LRUTTLCache::new(cache_size, cache_ttl_secs.max(1))));
+        Ok(Self { client, cache })
+    }
+
+    /// Authorize a tool invocation, given as a dict shaped like the
+    /// gateway's request, as `token` if given
+    #[pyo3(signature = (request, *, token = None))]
+    fn authorize<'py>(
+        &self,
+        py: Python<'py>,
+        request: &Bound<'py, PyAny>,
+        token: Option<String>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let query = Query::Authorize(depythonize(request)?);
+        self.decide(py, query, token)
+    }
+
+    /// Authorize a request from one agent to another, as `token` if given
+    #[pyo3(signature = (request, *, token = None))]
+    fn authorize_a2a<'py>(
+        &self,
+        py: Python<'py>,
+        request: &Bound<'py, PyAny>,
+        token: Option<String>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let query = Query::A2a(depythonize(request)?);
+        self.decide(py, query, token)
+    }
+
+    /// Authorize a list of tool invocations, as `token` if given; each
+    /// result is a decision or `{"error": ...}`, in request order
+    #[pyo3(signature = (requests, *, token = None))]
+    fn authorize_batch<'py>(
+        &self,
+        py: Python<'py>,
+        requests: &Bound<'py, PyAny>,
+        token: Option<String>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let requests: Vec<AuthorizeRequest> = depythonize(requests)?;
+        let client = self.client_for(token.as_deref())?;
+        pyo3_async_runtimes::tokio::future_into_py(py, async move {
+            let results = client.authorize_batch(&requests).await.map_err(to_py_err)?;
+            Python::with_gil(|py| Ok(pythonize(py, &results)?.unbind()))
+        })
+    }
+
+    /// Drop every cached decision
+    fn clear_cache(&self) {
+        if let Some(cache) = &self.cache {
+            cache.clear();
+        }
+    }
+}
+
+impl GatewayClient {
+    fn client_for(&self, token: Option<&str>) -> PyResult<Client> {
+        match token {
+            Some(token) => self.client.with_bearer_token(token).map_err(to_py_err),
+            None => Ok(self.client.clone()),
+        }
+    }
+
+    /// An awaitable for `query`'s decision, from the cache or the gateway
+    fn decide<'py>(
+        &self,
+        py: Python<'py>,
+        query: Query,
+        token: Option<String>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let client = self.client_for(token.as_deref())?;
+        let cache = self.cache.clone();
+        let body = match &query {
+            Query::Authorize(request) => serde_json::to_string(request),
+            Query::A2a(request) => serde_json::to_string(request),
+        }
+        .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let key = format!(
+            "{}\n{}\n{body}",
+            query.route(),
+            token.as_deref().unwrap_or_default()
+        );
+        pyo3_async_runtimes::tokio::future_into_py(py, async move {
+            let cached = cache
+                .as_ref()
+                .and_then(|cache| cache.get(&key))
+                .and_then(|value| serde_json::from_str::<Decision>(&value).ok());
+            let decision = match cached {
+                Some(decision) => decision,
+                None => {
+                    let decision = match &query {
+                        Query::Authorize(request) => client.authorize(request).await,
+                        Query::A2a(request) => client.authorize_a2a(request).await,
+                    }
+                    .map_err(to_py_err)?;
+                    if let Some(cache) = &cache {
+                        store(cache, key, &query, &decision);
+                    }
+                    decision
+                }
+            };
+            Python::with_gil(|py| Ok(pythonize(py, &decision)?.unbind()))
+        })
+    }
+}
+
+/// Cache `decision` for its TTL, unless it mustn't be
+fn store(cache: &LRUTTLCache, key: String, query: &Query, decision: &Decision) {
+    if decision.cache_ttl == 0 || query.dry_run() || decision.policy_revision == FAIL_OPEN_REVISION
+    {
+        return;
+    }
+    if let Ok(value) = serde_json::to_string(decision) {
+        // A full or failing cache only costs the next call a round trip
+        let _ = cache.set(key, value, Some(decision.cache_ttl.into()));
+    }
+}
+
+fn to_py_err(error: Error) -> PyErr {
+    match error {
+        Error::Config(message) => PyValueError::new_err(message),
+        error => GatewayError::new_err(error.to_string()),
+    }
+}
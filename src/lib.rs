@@ -1,24 +1,84 @@
 use pyo3::prelude::*;
 
-// Import types from grid-core (shared Rust components)
-// See grid-core/README.md for documentation
-use grid_cache::python::RustCache;
-use grid_opa::python::RustOPAEngine;
+mod build_info;
+mod cache;
+mod errors;
+mod gateway_client;
+mod logging;
+mod opa;
+mod shared_cache;
+mod tiered_cache;
+
+// SARK's own bindings of grid-core's engine and cache (shared Rust
+// components, see grid-core/README.md), with typed results and the GIL
+// released
+use cache::{CacheStats, RustCache};
+use opa::{PolicyDecision, RustOPAEngine};
+
+use gateway_client::{GatewayClient, GatewayError};
+use sark_classify::python::PySensitivityClassifier;
+use sark_context::python::PyRequestContext;
+use sark_jwt::python::{JWTValidationError, RustJWTValidator};
+use shared_cache::RustSharedCache;
+use tiered_cache::RustTieredCache;
 
 /// SARK Rust Extensions
 ///
 /// This module provides high-performance Rust implementations for SARK,
-/// including OPA policy evaluation and in-memory caching.
+/// including OPA policy evaluation, in-memory, cross-process and
+/// Redis-backed tiered caching, JWT validation, request context for
+/// policy input, sensitivity classification of tool calls and an async
+/// client for the Rust gateway.
 ///
 /// The underlying implementations are from grid-core, the shared Rust
 /// component library used by both SARK and YORI projects.
-#[pymodule]
+///
+/// The module supports free-threaded Python (3.13t) without re-enabling
+/// the GIL: `RustCache`, `RustSharedCache`, `GatewayClient` and
+/// `RustJWTValidator` take `&self` over concurrent or locked state, and `RustOPAEngine`'s
+/// mutating methods are guarded by PyO3's borrow checking, which raises
+/// rather than races (`RustOPAClient` serializes its calls).
+#[pymodule(gil_used = false)]
 fn sark_rust(m: &Bound<'_, PyModule>) -> PyResult<()> {
-    // Add OPA engine class
+    // Add the exception hierarchy (SarkError and its subclasses)
+    errors::register(m)?;
+
+    // Add OPA engine class and its decisions
     m.add_class::<RustOPAEngine>()?;
+    m.add_class::<PolicyDecision>()?;
 
-    // Add Cache class
+    // Add Cache class and its stats
     m.add_class::<RustCache>()?;
+    m.add_class::<CacheStats>()?;
+
+    // Add cache shared between worker processes
+    m.add_class::<RustSharedCache>()?;
+
+    // Add cache with a Redis tier shared between replicas
+    m.add_class::<RustTieredCache>()?;
+
+    // Add gateway client and its error
+    m.add_class::<GatewayClient>()?;
+    m.add("GatewayError", m.py().get_type::<GatewayError>())?;
+
+    // Add JWT validator and its error (shared with the gateway's auth)
+    m.add_class::<RustJWTValidator>()?;
+    m.add(
+        "JWTValidationError",
+        m.py().get_type::<JWTValidationError>(),
+    )?;
+
+    // Add request context for policy input (shared with the gateway)
+    m.add_class::<PyRequestContext>()?;
+
+    // Add sensitivity classification of tool calls (shared with the gateway)
+    m.add_class::<PySensitivityClassifier>()?;
+
+    // Build and capability introspection
+    m.add_function(wrap_pyfunction!(build_info::build_info, m)?)?;
+
+    // Opt-in forwarding of Rust tracing events to Python logging
+    m.add_function(wrap_pyfunction!(logging::enable_logging, m)?)?;
 
     Ok(())
 }
@@ -0,0 +1,126 @@
+//! Rust `tracing` events forwarded to Python `logging`
+//!
+//! Warnings and errors from the Rust side (a JWKS refresh failing, a
+//! gateway retry) are emitted as `tracing` events, which go nowhere unless
+//! a subscriber is installed. Calling `enable_logging()` once installs one
+//! that hands each event to the Python logger named after its module,
+//! under `sark_rust` (`sark_jwt::validator` logs to
+//! `sark_rust.sark_jwt.validator`), at the matching level:
+//!
+//! ```python
+//! import logging
+//! from sark.sark_rust import enable_logging
+//!
+//! logging.basicConfig(level=logging.INFO)
+//! enable_logging("info")
+//! ```
+//!
+//! Events below `level` are dropped in Rust, before the GIL is taken;
+//! Python's own levels and handlers apply to the rest. Fields other than
+//! the message are appended as `key=value`, and passed as `extra` under
+//! `rust_fields` for structured handlers.
+
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use std::fmt::{self, Write};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Layer;
+
+/// Root of the Python loggers events are forwarded to
+const LOGGER_ROOT: &str = "sark_rust";
+
+/// Forward Rust events at `level` (`trace`, `debug`, `info`, `warn` or
+/// `error`) and above to Python logging. Can only be enabled once per
+/// process.
+#[pyfunction]
+#[pyo3(signature = (level = "info"))]
+pub fn enable_logging(level: &str) -> PyResult<()> {
+    let filter: LevelFilter = level
+        .parse()
+        .map_err(|_| PyValueError::new_err(format!("Unknown log level {:?}", level)))?;
+    tracing_subscriber::registry()
+        .with(PythonLogging.with_filter(filter))
+        .try_init()
+        .map_err(|e| PyRuntimeError::new_err(format!("Rust logging already set up: {}", e)))
+}
+
+struct PythonLogging;
+
+impl<S: Subscriber> Layer<S> for PythonLogging {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        let level = python_level(metadata.level());
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+
+        Python::with_gil(|py| {
+            // Logging must never raise into the Rust code that emitted it
+            let _ = forward(py, metadata.target(), level, &fields);
+        });
+    }
+}
+
+fn forward(py: Python<'_>, target: &str, level: u8, fields: &Fields) -> PyResult<()> {
+    let name = format!("{}.{}", LOGGER_ROOT, target.replace("::", "."));
+    let logger = py.import("logging")?.call_method1("getLogger", (name,))?;
+    if !logger
+        .call_method1("isEnabledFor", (level,))?
+        .extract::<bool>()?
+    {
+        return Ok(());
+    }
+
+    let mut message = fields.message.clone();
+    let rust_fields = PyDict::new(py);
+    for (key, value) in &fields.others {
+        let _ = write!(message, " {}={}", key, value);
+        rust_fields.set_item(*key, value)?;
+    }
+    let extra = PyDict::new(py);
+    extra.set_item("rust_fields", rust_fields)?;
+    let kwargs = PyDict::new(py);
+    kwargs.set_item("extra", extra)?;
+    // `%` in the message must not be taken for a format argument
+    logger.call_method("log", (level, "%s", message), Some(&kwargs))?;
+    Ok(())
+}
+
+/// Python's number for `level`; it has no trace, so that is debug
+fn python_level(level: &Level) -> u8 {
+    match *level {
+        Level::ERROR => 40,
+        Level::WARN => 30,
+        Level::INFO => 20,
+        Level::DEBUG | Level::TRACE => 10,
+    }
+}
+
+/// An event's message and other fields, as text
+#[derive(Default)]
+struct Fields {
+    message: String,
+    others: Vec<(&'static str, String)>,
+}
+
+impl Visit for Fields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_string();
+        } else {
+            self.others.push((field.name(), value.to_string()));
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{:?}", value);
+        } else {
+            self.others.push((field.name(), format!("{:?}", value)));
+        }
+    }
+}
@@ -0,0 +1,1256 @@
+//! `RustOPAEngine`, returning typed decisions
+//!
+//! grid-core's binding returns whatever the query evaluated to, as a bare
+//! bool or dict, leaving every caller to work out which it got. This
+//! binding has the same interface, but `evaluate` returns a
+//! `PolicyDecision` read the way the gateway reads a decision document:
+//! `allow` is the result when the query is a rule, or its `allow` member
+//! when it is a package, with `reason`, `filtered_parameters` and
+//! `obligations` alongside.
+//!
+//! `add_policy_file` compiles a `.rego` file as the module named after it,
+//! and `load_policy_dir` every `.rego` file under a directory, each named
+//! after its path there without the extension (`mcp/gateway`), as the
+//! gateway's `--policy-dir` does, along with its `.cel.toml` packages;
+//! either loads all it reads or, if one fails, none. A module that doesn't
+//! compile raises `PolicyCompileError` with the `file`, `line` and
+//! `column` of the error as attributes (see `sark_policy::Diagnostic`;
+//! `line` and `column` are `None` where regorus gives none).
+//!
+//! `set_input_schema(schema)` checks every input against a JSON Schema
+//! before it is evaluated, as the gateway's `--input-schema` does: an
+//! input that fails it raises `MalformedInputError` (a `PolicyEvalError`)
+//! naming each violation as `<path>: <problem>`, rather than reaching rego,
+//! where a missing field only leaves rules undefined and the decision a
+//! deny with no reason. In `evaluate_batch` such an input fails alone.
+//!
+//! `validate_policy(source)` compiles a module without loading it and
+//! returns what `add_policy_file` would raise as diagnostics, a list of
+//! `severity`, `message`, `file`, `line` and `column` dicts, for editors
+//! and pre-commit hooks.
+//!
+//! `load_cel_policy` loads a package written in CEL instead (see
+//! `sark_cel`); a query naming it, or one of its rules, is evaluated by it
+//! rather than by the engine, into the same `PolicyDecision`.
+//!
+//! `set_data`, `patch_data` and `remove_data` change the data document
+//! policies read as `data`, for external data such as role mappings: it is
+//! rendered into generated modules as the gateway renders its `data.json`
+//! files (see `sark_policy::data_modules`), and CEL packages see it too.
+//! The engine can't unload a module, so a change recompiles the engine
+//! from the loaded sources; the policies aren't read or parsed again, and
+//! a change that doesn't compile leaves the engine as it was.
+//!
+//! `run_tests(path=None)` runs the `test_*` rules of the loaded modules,
+//! and of the `.rego` files under `path` (tests usually live beside the
+//! policies, not in the engine), as `sark-gateway test-policies` does: a
+//! test passes if it evaluates to `true`. The report has each test's
+//! result and each module's line coverage (see `sark_policy::run_tests`).
+//!
+//! `set_decision_log(sink)` has `sink(record)` called with a record of
+//! every evaluation, for audit, as the gateway's `--decision-log` writes
+//! them: a dict of `decision_id`, `timestamp` (Unix seconds), `query`,
+//! `input_hash` (the hex SHA-256 of the input in canonical form, the same
+//! hash the gateway logs; see `sark_policy::input_digest`), `result`,
+//! `error`, `latency_us` and `revision`. The decision carries the
+//! `decision_id` it was logged under. The sink is called with the GIL
+//! held once the evaluation is done (for `evaluate_batch`, once the whole
+//! batch is); it writes the record wherever the caller wants it, a file
+//! or a collector, and what it raises is reported through
+//! `sys.unraisablehook` rather than failing the evaluation.
+//!
+//! `metrics()` returns what the engine has evaluated, by query, as the
+//! gateway exports it at `/metrics`: counts of `allow`, `deny` and `error`
+//! outcomes, and a latency histogram of the decisions, the total
+//! `latency_seconds_sum` and `latency_seconds_buckets`, `(bound, count)`
+//! pairs of the decisions that took at most `bound` seconds (see
+//! `sark_policy::QueryMetrics`). Every evaluation counts, async and batch
+//! ones too; inputs refused before they reach the engine don't.
+//!
+//! Input and results cross the boundary as Python objects, converted in
+//! Rust (pythonize) rather than through `json.dumps` and `json.loads`.
+//!
+//! `evaluate_many` evaluates several queries against one input, converted
+//! once; queries naming rules of the same package are answered from one
+//! evaluation of the package, as the gateway reads `allow`, `reason`,
+//! `filtered_parameters` and `obligations` from one document.
+//!
+//! `evaluate` releases the GIL while the engine evaluates.
+//! `evaluate_batch` spreads a batch over a rayon pool with the GIL
+//! released. An engine evaluates one query at a time, so each worker has
+//! its own replica with the same modules, compiled on first use and kept
+//! until the policies change.
+//!
+//! Compiled engines outlive the `RustOPAEngine` that compiled them: when
+//! one is dropped, or its policies or data change, its engines are kept in
+//! a process-wide cache keyed by a digest of the modules and data they
+//! were compiled with, and an engine that loads the same content again
+//! (another instance, a reload, a replica) takes one instead of compiling
+//! it. grid-opa's engines can't be copied, so an engine serves one
+//! instance at a time, nor written out, so the cache is not kept on disk.
+//! `compile_cache_stats()` reports its `engines`, `hits` and `misses`, and
+//! `clear_compile_cache()` empties it.
+//!
+//! `evaluate_async` returns an awaitable instead, for async handlers that
+//! mustn't block the event loop: the evaluation runs on the tokio blocking
+//! pool (that of pyo3-async-runtimes), on an engine taken from a pool of
+//! its own. That pool, too, is compiled as needed and replaced when the
+//! policies change; evaluations already running finish on the old ones.
+
+use crate::errors::{MalformedInputError, PolicyCompileError, PolicyEvalError, SarkPolicyError};
+use grid_opa::OPAEngine;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::{PyTraverseError, PyVisit};
+use pythonize::{depythonize, pythonize};
+use rayon::prelude::*;
+use sark_policy::{Diagnostic, Outcome};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::collections::VecDeque;
+use std::iter;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Embedded OPA engine (regorus)
+#[pyclass(module = "sark_rust")]
+pub struct RustOPAEngine {
+    engine: OPAEngine,
+    /// Loaded modules by name, to list, digest and reload them
+    policies: BTreeMap<String, String>,
+    /// Loaded CEL packages by name
+    cel: BTreeMap<String, Arc<sark_cel::Package>>,
+    /// The data document, loaded as generated modules
+    data: Map<String, Value>,
+    /// Digest of `policies`, `cel` and `data`, naming the policy decisions
+    /// were made under
+    revision: String,
+    /// Copies of `engine` for `evaluate_batch`'s other workers
+    replicas: Vec<OPAEngine>,
+    /// Engines for `evaluate_async`, with the modules loaded now
+    pool: Arc<Pool>,
+    /// Called with a record of every evaluation
+    decision_log: Option<PyObject>,
+    /// Checked against every input before it is evaluated
+    input_schema: Option<sark_policy::Schema>,
+    /// Evaluations so far
+    metrics: Arc<Metrics>,
+}
+
+/// Evaluations by query
+type Metrics = Mutex<BTreeMap<String, sark_policy::QueryMetrics>>;
+
+/// Most engines the compile cache keeps
+const COMPILE_CACHE_CAPACITY: usize = 16;
+
+/// Compiled engines no `RustOPAEngine` is using, for any to take
+static COMPILED: Mutex<Compiled> = Mutex::new(Compiled {
+    engines: VecDeque::new(),
+    hits: 0,
+    misses: 0,
+});
+
+struct Compiled {
+    /// Engines by the `compiled_key` of their modules and data, oldest
+    /// first
+    engines: VecDeque<(String, OPAEngine)>,
+    hits: u64,
+    misses: u64,
+}
+
+/// Engines with the same modules, for evaluations running apart from
+/// the GIL holder's `RustOPAEngine`
+#[derive(Default)]
+struct Pool {
+    /// `compiled_key` of `policies` and `data`
+    key: String,
+    policies: BTreeMap<String, String>,
+    data: Map<String, Value>,
+    /// Engines not evaluating
+    idle: Mutex<Vec<OPAEngine>>,
+}
+
+#[pymethods]
+impl RustOPAEngine {
+    #[new]
+    fn new() -> PyResult<Self> {
+        let mut engine = Self {
+            engine: new_engine()?,
+            policies: BTreeMap::new(),
+            cel: BTreeMap::new(),
+            data: Map::new(),
+            revision: String::new(),
+            replicas: Vec::new(),
+            pool: Arc::default(),
+            decision_log: None,
+            input_schema: None,
+            metrics: Arc::default(),
+        };
+        engine.revise();
+        Ok(engine)
+    }
+
+    /// Compile `rego` as the module `name`, replacing any of that name
+    fn load_policy(&mut self, name: String, rego: String) -> PyResult<()> {
+        let file = name.clone();
+        self.load_files(vec![PolicyFile::Rego { name, rego, file }])
+            .map(drop)
+    }
+
+    /// Compile the `.rego` file (or parse the `.cel.toml` package) at
+    /// `path` as the one named after it, `authz.rego` as `authz`, replacing
+    /// any of that name; returns the name
+    fn add_policy_file(&mut self, path: PathBuf) -> PyResult<String> {
+        let file = policy_file(&path, &path)?.ok_or_else(|| {
+            SarkPolicyError::new_err(format!(
+                "{} is not a .rego or .cel.toml policy",
+                path.display()
+            ))
+        })?;
+        let mut names = self.load_files(vec![file])?;
+        Ok(names.remove(0))
+    }
+
+    /// Compile every `.rego` file (and parse every `.cel.toml` package)
+    /// under `path`, recursively, each named after its path relative to
+    /// `path` without the extension; returns the names, sorted
+    fn load_policy_dir(&mut self, path: PathBuf) -> PyResult<Vec<String>> {
+        let files = policy_files(&path)?;
+        self.load_files(files)
+    }
+
+    /// Compile `source` on its own, as read from `file`, without loading
+    /// it: a list of the errors found, each a dict of `severity`,
+    /// `message`, `file`, `line` and `column`, as `sark-gateway
+    /// check-policy --format json` reports them; empty if it compiles
+    #[staticmethod]
+    #[pyo3(signature = (source, file = "policy.rego"))]
+    fn validate_policy(py: Python<'_>, source: &str, file: &str) -> PyResult<PyObject> {
+        let diagnostics = py
+            .allow_threads(|| sark_policy::validate_policy(file, source))
+            .map_err(|e| SarkPolicyError::new_err(e.to_string()))?;
+        Ok(pythonize(py, &diagnostics)?.unbind())
+    }
+
+    /// Parse `source`, a CEL package file, as the package `name`,
+    /// replacing any of that name
+    fn load_cel_policy(&mut self, name: String, source: String) -> PyResult<()> {
+        let file = name.clone();
+        self.load_files(vec![PolicyFile::Cel { name, source, file }])
+            .map(drop)
+    }
+
+    /// Replace the data document at slash-separated `path` (`""` is the
+    /// root, which must be a dict) with `value`
+    fn set_data(&mut self, path: &str, value: &Bound<'_, PyAny>) -> PyResult<()> {
+        let value: Value = depythonize(value)
+            .map_err(|e| PyValueError::new_err(format!("Invalid data document: {}", e)))?;
+        let mut data = self.data.clone();
+        sark_policy::set_data(&mut data, path, value)
+            .map_err(|e| SarkPolicyError::new_err(e.to_string()))?;
+        self.load_data(data)
+    }
+
+    /// Apply `patch`, an RFC 6902 JSON Patch as a list of operations, to
+    /// the data document (paths are relative to `data`, e.g.
+    /// `/roles/admins/-`)
+    fn patch_data(&mut self, patch: &Bound<'_, PyAny>) -> PyResult<()> {
+        let patch: json_patch::Patch = depythonize(patch)
+            .map_err(|e| PyValueError::new_err(format!("Invalid JSON patch: {}", e)))?;
+        let mut data = self.data.clone();
+        sark_policy::patch_data(&mut data, &patch)
+            .map_err(|e| SarkPolicyError::new_err(e.to_string()))?;
+        self.load_data(data)
+    }
+
+    /// Remove the data document at `path`, returning whether it existed
+    fn remove_data(&mut self, path: &str) -> PyResult<bool> {
+        let mut data = self.data.clone();
+        if !sark_policy::remove_data(&mut data, path) {
+            return Ok(false);
+        }
+        self.load_data(data)?;
+        Ok(true)
+    }
+
+    /// The data document
+    #[getter]
+    fn data(&self, py: Python<'_>) -> PyResult<PyObject> {
+        to_python(py, Some(&Value::Object(self.data.clone())))
+    }
+
+    /// Evaluate `query` (e.g. `data.sark.gateway` or
+    /// `data.sark.gateway.allow`) against `input`: dicts, lists, strings,
+    /// numbers, bools and `None`
+    fn evaluate(
+        &mut self,
+        py: Python<'_>,
+        query: &str,
+        input: &Bound<'_, PyAny>,
+    ) -> PyResult<PolicyDecision> {
+        // Converted straight from the Python objects, not through JSON
+        // text, with the GIL held
+        let input: Value = depythonize(input)
+            .map_err(|e| PyValueError::new_err(format!("Invalid OPA input: {}", e)))?;
+        self.check_input(&input)?;
+        let input_hash = self
+            .decision_log
+            .as_ref()
+            .map(|_| sark_policy::input_digest(&input));
+        let revision = self.revision.as_str();
+        let mut outcome = match cel_package(&self.cel, query) {
+            Some(package) => {
+                let data = &self.data;
+                py.allow_threads(|| decide_cel(package, query, &input, data, revision))
+            }
+            None => {
+                let engine = &mut self.engine;
+                py.allow_threads(|| decide_json(engine, query, input, revision))
+            }
+        };
+        record(&self.metrics, query, &outcome);
+        if let (Some(sink), Some(input_hash)) = (&self.decision_log, input_hash) {
+            log_decision(py, sink, query, input_hash, revision, &mut outcome);
+        }
+        outcome.map_err(PolicyEvalError::new_err)
+    }
+
+    /// Evaluate each of `queries` against `input`, converted once, with the
+    /// GIL released; rules of one package (`data.mcp.gateway.allow` and
+    /// `data.mcp.gateway.reason`) are read from one evaluation of it.
+    /// Results are in query order, each a `PolicyDecision` or the
+    /// `PolicyEvalError` that query failed with, returned rather than
+    /// raised.
+    fn evaluate_many(
+        &mut self,
+        py: Python<'_>,
+        queries: Vec<String>,
+        input: &Bound<'_, PyAny>,
+    ) -> PyResult<Vec<PyObject>> {
+        let input: Value = depythonize(input)
+            .map_err(|e| PyValueError::new_err(format!("Invalid OPA input: {}", e)))?;
+        self.check_input(&input)?;
+        let input_hash = self
+            .decision_log
+            .as_ref()
+            .map(|_| sark_policy::input_digest(&input));
+        let packages: Vec<&str> = self
+            .policies
+            .values()
+            .filter_map(|source| sark_policy::package_of(source))
+            .collect();
+        let revision = self.revision.as_str();
+        let (cel, data) = (&self.cel, &self.data);
+        let engine = &mut self.engine;
+        let outcomes = py.allow_threads(|| {
+            decide_many(engine, cel, data, &packages, &queries, &input, revision)
+        });
+
+        queries
+            .iter()
+            .zip(outcomes)
+            .map(|(query, mut outcome)| {
+                record(&self.metrics, query, &outcome);
+                if let (Some(sink), Some(input_hash)) = (&self.decision_log, &input_hash) {
+                    let input_hash = input_hash.clone();
+                    log_decision(py, sink, query, input_hash, revision, &mut outcome);
+                }
+                match outcome {
+                    Ok(decision) => Ok(Py::new(py, decision)?.into_any()),
+                    Err(message) => Ok(PolicyEvalError::new_err(message).into_value(py).into_any()),
+                }
+            })
+            .collect()
+    }
+
+    /// `evaluate`, as an awaitable run off the event loop
+    fn evaluate_async<'py>(
+        &self,
+        py: Python<'py>,
+        query: String,
+        input: &Bound<'py, PyAny>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let input: Value = depythonize(input)
+            .map_err(|e| PyValueError::new_err(format!("Invalid OPA input: {}", e)))?;
+        self.check_input(&input)?;
+        let logged = self
+            .decision_log
+            .as_ref()
+            .map(|sink| (sink.clone_ref(py), sark_policy::input_digest(&input)));
+        let revision = self.revision.clone();
+        let package = cel_package(&self.cel, &query).cloned();
+        let pool = self.pool.clone();
+        let metrics = self.metrics.clone();
+        pyo3_async_runtimes::tokio::future_into_py(py, async move {
+            let (evaluated, revised) = (query.clone(), revision.clone());
+            let mut outcome = pyo3_async_runtimes::tokio::get_runtime()
+                .spawn_blocking(move || match package {
+                    Some(package) => Ok(decide_cel(
+                        &package, &evaluated, &input, &pool.data, &revised,
+                    )),
+                    None => {
+                        pool.with_engine(|engine| decide_json(engine, &evaluated, input, &revised))
+                    }
+                })
+                .await
+                .map_err(|e| {
+                    SarkPolicyError::new_err(format!("Policy evaluation failed: {}", e))
+                })??;
+            record(&metrics, &query, &outcome);
+            Python::with_gil(|py| {
+                if let Some((sink, input_hash)) = logged {
+                    log_decision(py, &sink, &query, input_hash, &revision, &mut outcome);
+                }
+                Py::new(py, outcome.map_err(PolicyEvalError::new_err)?)
+            })
+        })
+    }
+
+    /// Evaluate `query` against each of `inputs`, on up to `max_parallel`
+    /// workers (default: one per CPU) with the GIL released. Results are
+    /// in input order, each a `PolicyDecision` or the exception that item
+    /// failed with (`ValueError` or `PolicyEvalError`), returned rather
+    /// than raised.
+    #[pyo3(signature = (query, inputs, max_parallel = None))]
+    fn evaluate_batch(
+        &mut self,
+        py: Python<'_>,
+        query: &str,
+        inputs: Vec<Bound<'_, PyAny>>,
+        max_parallel: Option<usize>,
+    ) -> PyResult<Vec<PyObject>> {
+        if inputs.is_empty() {
+            return Ok(Vec::new());
+        }
+        // Converted with the GIL held, to values that can cross threads;
+        // unconvertible and malformed items fail alone
+        let inputs: Vec<Result<Value, PyErr>> = inputs
+            .iter()
+            .map(|input| {
+                let input: Value = depythonize(input)
+                    .map_err(|e| PyValueError::new_err(format!("Invalid OPA input: {}", e)))?;
+                self.check_input(&input)?;
+                Ok(input)
+            })
+            .collect();
+
+        let workers = max_parallel
+            .unwrap_or_else(rayon::current_num_threads)
+            .clamp(1, inputs.len());
+        while self.replicas.len() < workers - 1 {
+            self.replicas.push(compile(&self.policies, &self.data)?);
+        }
+
+        let chunk = inputs.len().div_ceil(workers);
+        let revision = self.revision.as_str();
+        let cel = cel_package(&self.cel, query);
+        let data = &self.data;
+        let engines: Vec<&mut OPAEngine> = iter::once(&mut self.engine)
+            .chain(self.replicas.iter_mut())
+            .collect();
+        let decisions: Vec<Option<Result<PolicyDecision, String>>> = py.allow_threads(|| {
+            engines
+                .into_par_iter()
+                .zip(inputs.par_chunks(chunk))
+                .flat_map_iter(|(engine, items)| {
+                    items
+                        .iter()
+                        .map(|item| {
+                            let input = item.as_ref().ok()?;
+                            if let Some(package) = cel {
+                                return Some(decide_cel(package, query, input, data, revision));
+                            }
+                            Some(decide_json(engine, query, input.clone(), revision))
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .collect()
+        });
+
+        inputs
+            .into_iter()
+            .zip(decisions)
+            .map(|(input, decision)| match (input, decision) {
+                (Err(error), _) => Ok(error.into_value(py).into_any()),
+                (Ok(input), Some(mut outcome)) => {
+                    record(&self.metrics, query, &outcome);
+                    if let Some(sink) = &self.decision_log {
+                        let input_hash = sark_policy::input_digest(&input);
+                        log_decision(py, sink, query, input_hash, revision, &mut outcome);
+                    }
+                    match outcome {
+                        Ok(decision) => Ok(Py::new(py, decision)?.into_any()),
+                        Err(message) => {
+                            Ok(PolicyEvalError::new_err(message).into_value(py).into_any())
+                        }
+                    }
+                }
+                (Ok(_), None) => unreachable!("converted inputs are evaluated"),
+            })
+            .collect()
+    }
+
+    /// Names of the loaded modules and CEL packages
+    fn loaded_policies(&self) -> Vec<String> {
+        self.policies
+            .keys()
+            .chain(self.cel.keys())
+            .cloned()
+            .collect()
+    }
+
+    fn has_policy(&self, name: &str) -> bool {
+        self.policies.contains_key(name) || self.cel.contains_key(name)
+    }
+
+    /// Unload every module and CEL package, keeping the data document
+    fn clear_policies(&mut self) -> PyResult<()> {
+        let engine = compile(&BTreeMap::new(), &self.data)?;
+        self.replace_engine(engine);
+        self.policies.clear();
+        self.cel.clear();
+        self.revise();
+        Ok(())
+    }
+
+    /// Digest of the loaded modules, CEL packages and data document
+    #[getter]
+    fn revision(&self) -> &str {
+        &self.revision
+    }
+
+    /// Check every input against `schema`, a JSON Schema as a dict, before
+    /// it is evaluated, or, for `None`, stop
+    fn set_input_schema(&mut self, schema: Option<&Bound<'_, PyAny>>) -> PyResult<()> {
+        self.input_schema = match schema {
+            Some(schema) => {
+                let schema: Value = depythonize(schema)
+                    .map_err(|e| PyValueError::new_err(format!("Invalid input schema: {}", e)))?;
+                let schema = sark_policy::Schema::compile(&schema)
+                    .map_err(|e| PyValueError::new_err(format!("Invalid input schema: {}", e)))?;
+                Some(schema)
+            }
+            None => None,
+        };
+        Ok(())
+    }
+
+    /// Run every `test_*` rule of the loaded modules, and of the `.rego`
+    /// files under `path` if given (read for the run, not loaded), with
+    /// the data document: a dict of each test's result and each module's
+    /// line coverage
+    #[pyo3(signature = (path = None))]
+    fn run_tests(&self, py: Python<'_>, path: Option<PathBuf>) -> PyResult<PyObject> {
+        let mut modules = self.policies.clone();
+        let mut files = BTreeMap::new();
+        if let Some(path) = path {
+            for file in policy_files(&path)? {
+                if let PolicyFile::Rego { name, rego, file } = file {
+                    files.insert(name.clone(), file);
+                    modules.insert(name, rego);
+                }
+            }
+        }
+        let modules: Vec<(String, String)> = modules.into_iter().collect();
+        let data = &self.data;
+        let report = py
+            .allow_threads(|| sark_policy::run_tests(&modules, data))
+            .map_err(|e| match &e {
+                sark_policy::Error::Compile { name, message } => {
+                    let file = files.get(name).unwrap_or(name);
+                    compile_error(e.to_string(), &Diagnostic::of(file, message))
+                }
+                _ => SarkPolicyError::new_err(e.to_string()),
+            })?;
+        Ok(pythonize(py, &report)?.unbind())
+    }
+
+    /// What the engine has evaluated, by query: a dict of each query's
+    /// outcome counts and decision latencies
+    fn metrics(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let metrics = self.metrics.lock().expect("engine metrics lock poisoned");
+        Ok(pythonize(py, &*metrics)?.unbind())
+    }
+
+    /// How many compiled engines the process-wide compile cache holds, and
+    /// how often compiling was, or wasn't, saved by it
+    #[staticmethod]
+    fn compile_cache_stats(py: Python<'_>) -> PyResult<PyObject> {
+        let compiled = COMPILED.lock().expect("compile cache lock poisoned");
+        let stats = serde_json::json!({
+            "engines": compiled.engines.len(),
+            "hits": compiled.hits,
+            "misses": compiled.misses,
+        });
+        to_python(py, Some(&stats))
+    }
+
+    /// Drop the engines in the compile cache
+    #[staticmethod]
+    fn clear_compile_cache() {
+        COMPILED
+            .lock()
+            .expect("compile cache lock poisoned")
+            .engines
+            .clear();
+    }
+
+    /// Call `sink(record)` with a record of every evaluation from now on,
+    /// or, for `None`, stop
+    fn set_decision_log(&mut self, sink: Option<PyObject>) {
+        self.decision_log = sink;
+    }
+
+    /// The decision log sink, for the cycle collector: a sink that logs
+    /// through the engine's owner refers back to it
+    fn __traverse__(&self, visit: PyVisit<'_>) -> Result<(), PyTraverseError> {
+        if let Some(sink) = &self.decision_log {
+            visit.call(sink)?;
+        }
+        Ok(())
+    }
+
+    fn __clear__(&mut self) {
+        self.decision_log = None;
+    }
+}
+
+impl RustOPAEngine {
+    /// Make `engine`, compiled with the modules and data now loaded, the
+    /// engine, caching the one it replaces
+    fn replace_engine(&mut self, engine: OPAEngine) {
+        let replaced = mem::replace(&mut self.engine, engine);
+        cache_compiled(&self.pool.key, iter::once(replaced));
+    }
+
+    /// Load `files`, or, if one of them fails to load, none of them;
+    /// returns their names
+    fn load_files(&mut self, files: Vec<PolicyFile>) -> PyResult<Vec<String>> {
+        let mut names = Vec::with_capacity(files.len());
+        let mut cel = self.cel.clone();
+        let mut rego = Vec::new();
+        for file in files {
+            match file {
+                PolicyFile::Rego {
+                    name,
+                    rego: source,
+                    file,
+                } => {
+                    names.push(name.clone());
+                    rego.push((name, source, file));
+                }
+                PolicyFile::Cel { name, source, file } => {
+                    let package = sark_cel::Package::parse(&source).map_err(|e| {
+                        compile_error(
+                            format!("Failed to load CEL package {}: {}", name, e),
+                            &Diagnostic::of(&file, &e.to_string()),
+                        )
+                    })?;
+                    names.push(name.clone());
+                    cel.insert(name, Arc::new(package));
+                }
+            }
+        }
+
+        let mut policies = self.policies.clone();
+        policies.extend(
+            rego.iter()
+                .map(|(name, source, _)| (name.clone(), source.clone())),
+        );
+        if let Some(engine) = take_compiled(&compiled_key(&policies, &self.data)) {
+            self.replace_engine(engine);
+        } else if rego
+            .iter()
+            .any(|(name, _, _)| self.policies.contains_key(name))
+        {
+            // The engine can't unload a module; rebuild it with the new
+            // sources
+            let mut engine = new_engine()?;
+            for (name, source) in &policies {
+                let file = rego
+                    .iter()
+                    .find(|(loaded, _, _)| loaded == name)
+                    .map_or(name, |(_, _, file)| file);
+                load(&mut engine, name, source, file)?;
+            }
+            load_data(&mut engine, &self.data)?;
+            self.replace_engine(engine);
+        } else {
+            for (name, source, file) in &rego {
+                if let Err(e) = load(&mut self.engine, name, source, file) {
+                    // Drop the modules loaded before it
+                    self.engine = compile(&self.policies, &self.data)?;
+                    return Err(e);
+                }
+            }
+        }
+        self.policies = policies;
+        self.cel = cel;
+        self.revise();
+        Ok(names)
+    }
+
+    /// Refuse `input` if it fails the input schema
+    fn check_input(&self, input: &Value) -> PyResult<()> {
+        let violations = self
+            .input_schema
+            .as_ref()
+            .and_then(|schema| schema.violations(input));
+        match violations {
+            Some(violations) => Err(MalformedInputError::new_err(format!(
+                "Malformed input: {}",
+                violations
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Make `data` the data document, recompiling the engine with it
+    fn load_data(&mut self, data: Map<String, Value>) -> PyResult<()> {
+        let engine = compile(&self.policies, &data)?;
+        self.replace_engine(engine);
+        self.data = data;
+        self.revise();
+        Ok(())
+    }
+
+    fn revise(&mut self) {
+        let mut hasher = Sha256::new();
+        for (name, rego) in &self.policies {
+            hasher.update(name.as_bytes());
+            hasher.update([0]);
+            hasher.update(rego.as_bytes());
+            hasher.update([0]);
+        }
+        for (name, package) in &self.cel {
+            hasher.update(name.as_bytes());
+            hasher.update(b".cel\0");
+            hasher.update(package.source().as_bytes());
+            hasher.update([0]);
+        }
+        hasher.update(Value::Object(self.data.clone()).to_string().as_bytes());
+        self.revision = hex::encode(hasher.finalize());
+        let key = compiled_key(&self.policies, &self.data);
+        if key == self.pool.key {
+            // Only CEL packages changed
+            return;
+        }
+        // Replicas hold the old modules, as does the old pool, which caches
+        // its engines once evaluations running on it are done
+        cache_compiled(&self.pool.key, self.replicas.drain(..));
+        self.pool = Arc::new(Pool {
+            key,
+            policies: self.policies.clone(),
+            data: self.data.clone(),
+            idle: Mutex::default(),
+        });
+    }
+}
+
+impl Drop for RustOPAEngine {
+    fn drop(&mut self) {
+        // An empty engine costs nothing to make; the compiled one is kept
+        if let Ok(empty) = OPAEngine::new() {
+            let engine = mem::replace(&mut self.engine, empty);
+            cache_compiled(&self.pool.key, iter::once(engine));
+        }
+        cache_compiled(&self.pool.key, self.replicas.drain(..));
+    }
+}
+
+impl Drop for Pool {
+    fn drop(&mut self) {
+        if let Ok(idle) = self.idle.get_mut() {
+            cache_compiled(&self.key, idle.drain(..));
+        }
+    }
+}
+
+impl Pool {
+    /// Run `f` on an idle engine, or a newly compiled one if none is
+    fn with_engine<T>(&self, f: impl FnOnce(&mut OPAEngine) -> T) -> PyResult<T> {
+        let idle = self.idle.lock().expect("engine pool lock poisoned").pop();
+        let mut engine = match idle {
+            Some(engine) => engine,
+            None => compile(&self.policies, &self.data)?,
+        };
+        let result = f(&mut engine);
+        self.idle
+            .lock()
+            .expect("engine pool lock poisoned")
+            .push(engine);
+        Ok(result)
+    }
+}
+
+fn new_engine() -> PyResult<OPAEngine> {
+    OPAEngine::new()
+        .map_err(|e| SarkPolicyError::new_err(format!("Failed to initialize OPA engine: {}", e)))
+}
+
+/// An engine with `policies` and the `data` document loaded, from the
+/// compile cache if it has one
+fn compile(policies: &BTreeMap<String, String>, data: &Map<String, Value>) -> PyResult<OPAEngine> {
+    if let Some(engine) = take_compiled(&compiled_key(policies, data)) {
+        return Ok(engine);
+    }
+    let mut engine = new_engine()?;
+    for (name, rego) in policies {
+        load(&mut engine, name, rego, name)?;
+    }
+    load_data(&mut engine, data)?;
+    Ok(engine)
+}
+
+/// Digest of `policies` and `data`, keying the engines compiled with them
+fn compiled_key(policies: &BTreeMap<String, String>, data: &Map<String, Value>) -> String {
+    let mut hasher = Sha256::new();
+    for (name, rego) in policies {
+        hasher.update(name.as_bytes());
+        hasher.update([0]);
+        hasher.update(rego.as_bytes());
+        hasher.update([0]);
+    }
+    hasher.update(Value::Object(data.clone()).to_string().as_bytes());
+    hex::encode(hasher.finalize())
+}
+
+/// An engine compiled with the content digested as `key`, if the compile
+/// cache has one
+fn take_compiled(key: &str) -> Option<OPAEngine> {
+    let mut compiled = COMPILED.lock().expect("compile cache lock poisoned");
+    match compiled
+        .engines
+        .iter()
+        .position(|(cached, _)| cached == key)
+    {
+        Some(at) => {
+            compiled.hits += 1;
+            compiled.engines.remove(at).map(|(_, engine)| engine)
+        }
+        None => {
+            compiled.misses += 1;
+            None
+        }
+    }
+}
+
+/// Keep `engines`, compiled with the content digested as `key`, in the
+/// compile cache, dropping the oldest past its capacity
+fn cache_compiled(key: &str, engines: impl Iterator<Item = OPAEngine>) {
+    // A poisoned lock only costs the engines their reuse
+    let Ok(mut compiled) = COMPILED.lock() else {
+        return;
+    };
+    for engine in engines {
+        compiled.engines.push_back((key.to_string(), engine));
+    }
+    while compiled.engines.len() > COMPILE_CACHE_CAPACITY {
+        compiled.engines.pop_front();
+    }
+}
+
+/// Load the `data` document into `engine` as generated modules
+fn load_data(engine: &mut OPAEngine, data: &Map<String, Value>) -> PyResult<()> {
+    let modules =
+        sark_policy::data_modules(data).map_err(|e| SarkPolicyError::new_err(e.to_string()))?;
+    for (name, source) in modules {
+        engine.load_policy(name.clone(), source).map_err(|e| {
+            SarkPolicyError::new_err(format!("Failed to load data document {}: {}", name, e))
+        })?;
+    }
+    Ok(())
+}
+
+/// Evaluate `query` against `input` on `engine`, as a decision under
+/// `revision`
+fn decide(
+    engine: &mut OPAEngine,
+    query: &str,
+    input: grid_opa::Value,
+    revision: &str,
+) -> Result<PolicyDecision, String> {
+    let started = Instant::now();
+    let result = engine
+        .evaluate(query, input)
+        .map_err(|e| format!("Policy evaluation error: {}", e))?;
+    let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+    Ok(PolicyDecision::new(
+        document(&result),
+        latency_ms,
+        revision.to_string(),
+    ))
+}
+
+/// What an engine's result is as JSON (`null` for an undefined one)
+fn document(result: &grid_opa::Value) -> Value {
+    result
+        .to_json_str()
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+/// Evaluate each of `queries` against `input` on `engine`, or by the CEL
+/// package in `cel` it names, as decisions under `revision`; a package of
+/// `packages` that two or more of them are rules of is evaluated once,
+/// and those rules read from its document
+fn decide_many(
+    engine: &mut OPAEngine,
+    cel: &BTreeMap<String, Arc<sark_cel::Package>>,
+    data: &Map<String, Value>,
+    packages: &[&str],
+    queries: &[String],
+    input: &Value,
+    revision: &str,
+) -> Vec<Result<PolicyDecision, String>> {
+    let converted: grid_opa::Value = match serde_json::from_value(input.clone()) {
+        Ok(converted) => converted,
+        Err(e) => {
+            let message = format!("Policy evaluation error: {}", e);
+            return queries.iter().map(|_| Err(message.clone())).collect();
+        }
+    };
+
+    let mut rules: BTreeMap<&str, usize> = BTreeMap::new();
+    for query in queries {
+        if let Some((package, _)) = rule_of(query, packages) {
+            *rules.entry(package).or_default() += 1;
+        }
+    }
+    let mut documents = BTreeMap::new();
+    for (package, _) in rules.into_iter().filter(|(_, count)| *count > 1) {
+        let started = Instant::now();
+        // If the package fails to evaluate, its rules are evaluated one
+        // by one, so only those that fail report an error
+        if let Ok(result) = engine.evaluate(&format!("data.{}", package), converted.clone()) {
+            let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+            documents.insert(package, (document(&result), latency_ms));
+        }
+    }
+
+    queries
+        .iter()
+        .map(|query| {
+            if let Some(package) = cel_package(cel, query) {
+                return decide_cel(package, query, input, data, revision);
+            }
+            let read = rule_of(query, packages)
+                .and_then(|(package, rule)| Some((documents.get(package)?, rule)));
+            match read {
+                // Undefined rules are absent from the package document
+                Some(((document, latency_ms), rule)) => Ok(PolicyDecision::new(
+                    document.get(rule).cloned().unwrap_or_default(),
+                    *latency_ms,
+                    revision.to_string(),
+                )),
+                None => decide(engine, query, converted.clone(), revision),
+            }
+        })
+        .collect()
+}
+
+/// The package of `packages` and the rule in it `query` names, if it
+/// names a rule of one (`data.mcp.gateway.allow`, of `mcp.gateway`)
+fn rule_of<'q>(query: &'q str, packages: &[&str]) -> Option<(&'q str, &'q str)> {
+    let (package, rule) = query.strip_prefix("data.")?.rsplit_once('.')?;
+    packages.contains(&package).then_some((package, rule))
+}
+
+/// `decide`, for input converted from Python objects
+fn decide_json(
+    engine: &mut OPAEngine,
+    query: &str,
+    input: Value,
+    revision: &str,
+) -> Result<PolicyDecision, String> {
+    serde_json::from_value(input)
+        .map_err(|e| format!("Policy evaluation error: {}", e))
+        .and_then(|input| decide(engine, query, input, revision))
+}
+
+/// The CEL package `query` names, if any
+fn cel_package<'a>(
+    cel: &'a BTreeMap<String, Arc<sark_cel::Package>>,
+    query: &str,
+) -> Option<&'a Arc<sark_cel::Package>> {
+    cel.values().find(|package| package.answers(query))
+}
+
+/// Count `outcome`, of evaluating `query`, in `metrics`
+fn record(metrics: &Metrics, query: &str, outcome: &Result<PolicyDecision, String>) {
+    let (outcome, elapsed) = match outcome {
+        Ok(decision) => (
+            if decision.allow {
+                Outcome::Allow
+            } else {
+                Outcome::Deny
+            },
+            Some(Duration::from_secs_f64(decision.latency_ms / 1000.0)),
+        ),
+        Err(_) => (Outcome::Error, None),
+    };
+    let mut metrics = metrics.lock().expect("engine metrics lock poisoned");
+    metrics
+        .entry(query.to_string())
+        .or_default()
+        .record(outcome, elapsed);
+}
+
+/// Record `outcome`, of evaluating `query` against the input digested as
+/// `input_hash` under `revision`, with `sink`, giving a decision the id it
+/// is logged under; what `sink` raises is reported as unraisable
+fn log_decision(
+    py: Python<'_>,
+    sink: &PyObject,
+    query: &str,
+    input_hash: String,
+    revision: &str,
+    outcome: &mut Result<PolicyDecision, String>,
+) {
+    let decision_id = format!("{:032x}", rand::random::<u128>());
+    let (result, error, latency_us) = match outcome {
+        Ok(decision) => {
+            decision.decision_id = Some(decision_id.clone());
+            let latency_us = (decision.latency_ms * 1000.0) as u64;
+            (Some(decision.result.clone()), None, Some(latency_us))
+        }
+        Err(message) => (None, Some(message.clone()), None),
+    };
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |elapsed| elapsed.as_secs_f64());
+    let record = serde_json::json!({
+        "decision_id": decision_id,
+        "timestamp": timestamp,
+        "query": query,
+        "input_hash": input_hash,
+        "result": result,
+        "error": error,
+        "latency_us": latency_us,
+        "revision": revision,
+    });
+    let logged = pythonize(py, &record)
+        .map_err(PyErr::from)
+        .and_then(|record| sink.call1(py, (record,)));
+    if let Err(e) = logged {
+        e.write_unraisable(py, Some(sink.bind(py)));
+    }
+}
+
+/// Evaluate `query` against `input` and `data` by `package`, as a
+/// decision under `revision`
+fn decide_cel(
+    package: &sark_cel::Package,
+    query: &str,
+    input: &Value,
+    data: &Map<String, Value>,
+    revision: &str,
+) -> Result<PolicyDecision, String> {
+    let started = Instant::now();
+    let document = package
+        .evaluate(query, input, data)
+        .map_err(|e| format!("Policy evaluation error: {}", e))?;
+    let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+    Ok(PolicyDecision::new(
+        document,
+        latency_ms,
+        revision.to_string(),
+    ))
+}
+
+/// Compile `rego`, read from `file`, into `engine` as the module `name`
+fn load(engine: &mut OPAEngine, name: &str, rego: &str, file: &str) -> PyResult<()> {
+    engine
+        .load_policy(name.to_string(), rego.to_string())
+        .map(drop)
+        .map_err(|e| {
+            compile_error(
+                format!("Failed to compile policy {}: {}", name, e),
+                &Diagnostic::of(file, &e.to_string()),
+            )
+        })
+}
+
+/// A `PolicyCompileError` saying `message`, with the file, line and
+/// column of `diagnostic` as attributes
+fn compile_error(message: String, diagnostic: &Diagnostic) -> PyErr {
+    Python::with_gil(|py| {
+        let error = PolicyCompileError::new_err(message);
+        let value = error.value(py);
+        let located = value
+            .setattr("file", &diagnostic.file)
+            .and_then(|()| value.setattr("line", diagnostic.line))
+            .and_then(|()| value.setattr("column", diagnostic.column));
+        match located {
+            Ok(()) => error,
+            Err(e) => e,
+        }
+    })
+}
+
+/// A policy file to load
+enum PolicyFile {
+    Rego {
+        name: String,
+        rego: String,
+        /// Where it was read from, for compile errors
+        file: String,
+    },
+    Cel {
+        name: String,
+        source: String,
+        file: String,
+    },
+}
+
+/// The policy at `path`, named after its path relative to `root` without
+/// the extension, or `None` if it isn't a `.rego` or `.cel.toml` file
+fn policy_file(path: &Path, root: &Path) -> PyResult<Option<PolicyFile>> {
+    let relative = if path == root {
+        Path::new(path.file_name().unwrap_or_default())
+    } else {
+        path.strip_prefix(root).unwrap_or(path)
+    };
+    let relative = relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+    let read = || {
+        std::fs::read_to_string(path).map_err(|e| {
+            SarkPolicyError::new_err(format!("Failed to read {}: {}", path.display(), e))
+        })
+    };
+    let file = path.display().to_string();
+    if let Some(name) = relative.strip_suffix(".rego") {
+        return Ok(Some(PolicyFile::Rego {
+            name: name.to_string(),
+            rego: read()?,
+            file,
+        }));
+    }
+    if let Some(name) = relative.strip_suffix(".cel.toml") {
+        return Ok(Some(PolicyFile::Cel {
+            name: name.to_string(),
+            source: read()?,
+            file,
+        }));
+    }
+    Ok(None)
+}
+
+/// The policies under `dir`, recursively, in path order
+fn policy_files(dir: &Path) -> PyResult<Vec<PolicyFile>> {
+    let mut paths = Vec::new();
+    collect_files(dir, &mut paths).map_err(|e| {
+        SarkPolicyError::new_err(format!(
+            "Failed to read policy directory {}: {}",
+            dir.display(),
+            e
+        ))
+    })?;
+    paths.sort();
+    paths
+        .iter()
+        .filter_map(|file| policy_file(file, dir).transpose())
+        .collect()
+}
+
+/// Every file under `dir`, recursively
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// The outcome of evaluating a query
+///
+/// Truthy when the query allows, so `if engine.evaluate(...)` reads as it
+/// did when rules evaluated to a bare bool.
+#[pyclass(module = "sark_rust", frozen)]
+pub struct PolicyDecision {
+    #[pyo3(get)]
+    allow: bool,
+    #[pyo3(get)]
+    reason: Option<String>,
+    filtered_parameters: Option<Value>,
+    obligations: Option<Value>,
+    /// Milliseconds the engine took to evaluate
+    #[pyo3(get)]
+    latency_ms: f64,
+    /// Revision of the policy that made the decision
+    #[pyo3(get)]
+    revision: String,
+    /// Id the decision was logged under, if a decision log is set
+    #[pyo3(get)]
+    decision_id: Option<String>,
+    /// What the query evaluated to, whole
+    result: Value,
+}
+
+impl PolicyDecision {
+    fn new(result: Value, latency_ms: f64, revision: String) -> Self {
+        let member = |name: &str| result.get(name).filter(|v| !v.is_null()).cloned();
+        let allow = Outcome::of(&result) == Outcome::Allow;
+        Self {
+            allow,
+            reason: member("reason").and_then(|v| v.as_str().map(str::to_string)),
+            filtered_parameters: member("filtered_parameters"),
+            obligations: member("obligations"),
+            latency_ms,
+            revision,
+            decision_id: None,
+            result,
+        }
+    }
+}
+
+#[pymethods]
+impl PolicyDecision {
+    #[getter]
+    fn filtered_parameters(&self, py: Python<'_>) -> PyResult<PyObject> {
+        to_python(py, self.filtered_parameters.as_ref())
+    }
+
+    #[getter]
+    fn obligations(&self, py: Python<'_>) -> PyResult<PyObject> {
+        to_python(py, self.obligations.as_ref())
+    }
+
+    #[getter]
+    fn result(&self, py: Python<'_>) -> PyResult<PyObject> {
+        to_python(py, Some(&self.result))
+    }
+
+    fn __bool__(&self) -> bool {
+        self.allow
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "PolicyDecision(allow={}, reason={:?}, latency_ms={:.3}, revision={:?})",
+            if self.allow { "True" } else { "False" },
+            self.reason.as_deref().unwrap_or_default(),
+            self.latency_ms,
+            self.revision
+        )
+    }
+}
+
+/// `value` as Python objects, `None` if absent
+fn to_python(py: Python<'_>, value: Option<&Value>) -> PyResult<PyObject> {
+    match value {
+        Some(value) => Ok(pythonize(py, value)?.unbind()),
+        None => Ok(py.None()),
+    }
+}
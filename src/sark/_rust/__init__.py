@@ -5,6 +5,13 @@ performance-critical SARK components:
 
 - OPA policy engine with regorus
 - Thread-safe caching with DashMap
+- A decision cache shared by worker processes (memory-mapped file)
+- A tiered cache with a Redis L2 shared between replicas
+- Async client for the Rust gateway's authorization endpoints
+- JWT validation against a cached JWKS (shared with the gateway)
+- Request context for policy input, shaped as the gateway shapes it
+- Sensitivity classification of tools, by the gateway's rules
+- Opt-in forwarding of Rust log events to Python logging
 
 The Rust extensions are optional. If not built, SARK will fall back
 to pure-Python implementations where available.
@@ -14,9 +21,46 @@ to pure-Python implementations where available.
 RUST_AVAILABLE = False
 RustOPAEngine = None
 RustCache = None
+RustSharedCache = None
+RustTieredCache = None
+PolicyDecision = None
+CacheStats = None
+GatewayClient = None
+GatewayError = None
+RustJWTValidator = None
+JWTValidationError = None
+enable_logging = None
+build_info = None
+SarkError = None
+SarkCacheError = None
+SarkPolicyError = None
+PolicyCompileError = None
+PolicyEvalError = None
+RequestContext = None
+SensitivityClassifier = None
 
 try:
-    from sark.sark_rust import RustCache, RustOPAEngine
+    from sark.sark_rust import (
+        CacheStats,
+        GatewayClient,
+        GatewayError,
+        JWTValidationError,
+        PolicyCompileError,
+        PolicyDecision,
+        PolicyEvalError,
+        RequestContext,
+        RustCache,
+        RustJWTValidator,
+        RustOPAEngine,
+        RustSharedCache,
+        RustTieredCache,
+        SarkCacheError,
+        SarkError,
+        SarkPolicyError,
+        SensitivityClassifier,
+        build_info,
+        enable_logging,
+    )
 
     RUST_AVAILABLE = True
 except ImportError as e:
@@ -32,4 +76,25 @@ except ImportError as e:
         stacklevel=2,
     )
 
-__all__ = ["RUST_AVAILABLE", "RustCache", "RustOPAEngine"]
+__all__ = [
+    "RUST_AVAILABLE",
+    "CacheStats",
+    "GatewayClient",
+    "GatewayError",
+    "JWTValidationError",
+    "PolicyCompileError",
+    "PolicyDecision",
+    "PolicyEvalError",
+    "RequestContext",
+    "RustCache",
+    "RustJWTValidator",
+    "RustOPAEngine",
+    "RustSharedCache",
+    "RustTieredCache",
+    "SarkCacheError",
+    "SarkError",
+    "SarkPolicyError",
+    "SensitivityClassifier",
+    "build_info",
+    "enable_logging",
+]
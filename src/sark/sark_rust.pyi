@@ -0,0 +1,468 @@
+"""Type stubs for the sark_rust extension module.
+
+Kept in step with the pyclasses in src/*.rs, rust/sark-jwt/src/python.rs and
+rust/sark-context/src/python.rs.
+"""
+
+import os
+from typing import Any, Callable, Iterator, Literal, TypedDict
+
+class SarkError(Exception):
+    """Base class of the errors sark_rust raises."""
+
+class SarkCacheError(SarkError):
+    """The cache refused an operation."""
+
+class SarkPolicyError(SarkError):
+    """The policy engine failed."""
+
+class PolicyCompileError(SarkPolicyError):
+    """A policy module didn't compile."""
+
+    file: str
+    line: int | None
+    column: int | None
+
+class PolicyEvalError(SarkPolicyError):
+    """A query couldn't be evaluated."""
+
+class MalformedInputError(PolicyEvalError):
+    """The input failed the engine's input schema."""
+
+class PolicyDecision:
+    """The outcome of evaluating a query; truthy when it allows."""
+
+    @property
+    def allow(self) -> bool: ...
+    @property
+    def reason(self) -> str | None: ...
+    @property
+    def filtered_parameters(self) -> Any | None: ...
+    @property
+    def obligations(self) -> dict[str, Any] | None: ...
+    @property
+    def latency_ms(self) -> float: ...
+    @property
+    def revision(self) -> str: ...
+    @property
+    def decision_id(self) -> str | None:
+        """Id the decision was logged under, if a decision log is set."""
+    @property
+    def result(self) -> Any:
+        """What the query evaluated to, whole."""
+    def __bool__(self) -> bool: ...
+
+class DecisionRecord(TypedDict):
+    decision_id: str
+    timestamp: float
+    query: str
+    input_hash: str
+    result: Any | None
+    error: str | None
+    latency_us: int | None
+    revision: str
+
+class PolicyDiagnostic(TypedDict):
+    severity: Literal["error"]
+    message: str
+    file: str
+    line: int | None
+    column: int | None
+
+class PolicyTestResult(TypedDict):
+    name: str
+    passed: bool
+    got: str | None
+    error: str | None
+    duration_us: int
+
+class PolicyCoverage(TypedDict):
+    file: str
+    covered: list[int]
+    not_covered: list[int]
+
+class PolicyTestReport(TypedDict):
+    results: list[PolicyTestResult]
+    coverage: list[PolicyCoverage]
+
+class QueryMetrics(TypedDict):
+    allow: int
+    deny: int
+    error: int
+    latency_seconds_sum: float
+    latency_seconds_buckets: list[tuple[float, int]]
+
+class CompileCacheStats(TypedDict):
+    engines: int
+    hits: int
+    misses: int
+
+class RustOPAEngine:
+    """Embedded OPA engine (regorus)."""
+
+    def __init__(self) -> None: ...
+    def load_policy(self, name: str, rego: str) -> None: ...
+    def add_policy_file(self, path: str | os.PathLike[str]) -> str:
+        """Load the .rego or .cel.toml file at path as the policy named after it."""
+    def load_policy_dir(self, path: str | os.PathLike[str]) -> list[str]:
+        """Load every .rego and .cel.toml file under path, or none if one fails."""
+    @staticmethod
+    def validate_policy(source: str, file: str = "policy.rego") -> list[PolicyDiagnostic]:
+        """Compile source on its own and return its errors, without loading it."""
+    def load_cel_policy(self, name: str, source: str) -> None:
+        """Load a CEL package; queries naming it are evaluated by it."""
+    def set_data(self, path: str, value: Any) -> None:
+        """Replace the data document at slash-separated path ("" for the root)."""
+    def patch_data(self, patch: list[dict[str, Any]]) -> None:
+        """Apply an RFC 6902 JSON Patch to the data document."""
+    def remove_data(self, path: str) -> bool:
+        """Remove the data document at path, returning whether it existed."""
+    @property
+    def data(self) -> dict[str, Any]: ...
+    def evaluate(self, query: str, input: Any) -> PolicyDecision: ...
+    def evaluate_many(self, queries: list[str], input: Any) -> list[PolicyDecision | Exception]:
+        """Evaluate each query against one input; failed queries are returned as exceptions."""
+    async def evaluate_async(self, query: str, input: Any) -> PolicyDecision:
+        """evaluate, run off the event loop."""
+    def evaluate_batch(
+        self, query: str, inputs: list[Any], max_parallel: int | None = None
+    ) -> list[PolicyDecision | Exception]:
+        """Evaluate each input in parallel; failed items are returned as exceptions."""
+    def loaded_policies(self) -> list[str]: ...
+    def has_policy(self, name: str) -> bool: ...
+    def clear_policies(self) -> None: ...
+    @property
+    def revision(self) -> str:
+        """Digest of the loaded modules, CEL packages and data document."""
+    def set_input_schema(self, schema: dict[str, Any] | None) -> None:
+        """Check every input against a JSON Schema before evaluating it; None stops."""
+    def run_tests(self, path: str | os.PathLike[str] | None = None) -> PolicyTestReport:
+        """Run the test_* rules of the loaded modules and of the .rego files under path."""
+    def metrics(self) -> dict[str, QueryMetrics]:
+        """Outcome counts and decision latencies of every evaluation, by query."""
+    @staticmethod
+    def compile_cache_stats() -> CompileCacheStats:
+        """Size and hit counts of the process-wide cache of compiled engines."""
+    @staticmethod
+    def clear_compile_cache() -> None: ...
+    def set_decision_log(self, sink: Callable[[DecisionRecord], object] | None) -> None:
+        """Call sink with a record of every evaluation; None stops."""
+
+class CacheStats:
+    """A snapshot of a RustCache's state."""
+
+    @property
+    def size(self) -> int: ...
+    @property
+    def max_size(self) -> int: ...
+    @property
+    def ttl_secs(self) -> int: ...
+    @property
+    def hits(self) -> int: ...
+    @property
+    def misses(self) -> int: ...
+    @property
+    def insertions(self) -> int: ...
+    @property
+    def evictions(self) -> int: ...
+    @property
+    def expirations(self) -> int: ...
+    @property
+    def memory_bytes(self) -> int:
+        """Estimated bytes the entries take (the whole mapping, for RustSharedCache)."""
+    @property
+    def compressions(self) -> int:
+        """Values stored compressed (0 for RustSharedCache)."""
+    @property
+    def compression_saved_bytes(self) -> int:
+        """Bytes compression took off those values."""
+    @property
+    def hit_rate(self) -> float: ...
+
+class RustCache:
+    """Thread-safe in-memory LRU cache with per-entry TTLs."""
+
+    def __init__(
+        self,
+        max_size: int,
+        ttl_secs: int,
+        serializer: Literal["pickle"] | None = None,
+        max_pinned: int | None = None,
+        sliding: bool = False,
+        max_bytes: int | None = None,
+        weigher: Callable[[str, Any], int] | None = None,
+        compress_threshold: int | None = None,
+        ttl_jitter_pct: int = 0,
+        soft_ttl_secs: int = 0,
+        negative_ttl_secs: int | None = None,
+    ) -> None:
+        """Values are str or bytes, or with serializer="pickle" any picklable object.
+
+        At most max_pinned entries (and half of max_size) can be pinned. With
+        sliding=True, lookups keep an entry another TTL. With max_bytes, keys
+        and values (as weigher weighs them, if given) take at most that many.
+        Values of compress_threshold bytes or more are stored compressed. Each
+        set's TTL is randomized within +/- ttl_jitter_pct percent. Entries are
+        stale soft_ttl_secs after they are set (never, for 0), and negatives
+        (None) kept negative_ttl_secs, if given.
+        """
+    def get(self, key: str) -> Any | None: ...
+    def get_versioned(self, key: str) -> tuple[Any, int] | None:
+        """The value at key and its version."""
+    def set(
+        self,
+        key: str,
+        value: Any,
+        ttl: int | None = None,
+        tags: list[str] | None = None,
+        meta: dict[str, Any] | None = None,
+    ) -> None: ...
+    def get_stale(self, key: str) -> tuple[Any, bool] | None:
+        """The value at key and whether it has outlived soft_ttl_secs."""
+    def get_with_meta(self, key: str) -> tuple[Any, dict[str, Any] | None] | None:
+        """The value at key and the meta it was set with."""
+    def get_or_compute(
+        self, key: str, compute: Callable[[], Any], ttl: int | None = None
+    ) -> Any:
+        """The value at key, or compute()'s, set; concurrent callers share one compute."""
+    def set_negative(self, key: str, ttl: int | None = None) -> None:
+        """Cache that key has no value, for ttl or negative_ttl_secs."""
+    def probe(self, key: str) -> tuple[bool, Any]:
+        """(held, value): (True, None) for a cached negative, (False, None) for a miss."""
+    def set_pinned(self, key: str, value: Any, ttl: int | None = None) -> None:
+        """Set where eviction can't reach it; SarkCacheError past max_pinned."""
+    def set_if_version(
+        self, key: str, value: Any, version: int | None, ttl: int | None = None
+    ) -> int | None:
+        """Set only if the entry is at version (missing, for None); the new version, or None."""
+    def incr(self, key: str, delta: int = 1, ttl: int | None = None) -> int:
+        """Add delta to the integer at key, starting from 0; the new count."""
+    def decr(self, key: str, delta: int = 1) -> int:
+        """Subtract delta from the integer at key, starting from 0; the new count."""
+    def get_many(self, keys: list[str]) -> dict[str, Any]:
+        """The values of those of keys held and not expired."""
+    def set_many(self, items: dict[str, Any], ttl: int | None = None) -> None: ...
+    def delete_many(self, keys: list[str]) -> int:
+        """Remove every one of keys, returning how many were there."""
+    def ttl(self, key: str) -> int | None:
+        """Seconds key has left; 0 once expired (until swept), None if not held."""
+    def touch(self, key: str, ttl: int | None = None) -> bool:
+        """Keep key another ttl seconds, returning whether it was held."""
+    def expire_at(self, key: str, timestamp: float) -> bool:
+        """Expire key at a Unix timestamp, returning whether it was held."""
+    def invalidate_tag(self, tag: str) -> int:
+        """Remove every entry set with tag, returning how many there were."""
+    def delete_prefix(self, prefix: str) -> int:
+        """Remove every key starting with prefix, returning how many there were."""
+    def delete_matching(self, pattern: str) -> int:
+        """Remove every key matching a glob of * and ?, returning how many there were."""
+    def delete(self, key: str) -> bool: ...
+    def keys(self) -> list[str]:
+        """Snapshot of the live keys."""
+    def items(self) -> list[tuple[str, Any]]:
+        """Snapshot of the live entries."""
+    def __getitem__(self, key: str) -> Any: ...
+    def __setitem__(self, key: str, value: Any) -> None: ...
+    def __delitem__(self, key: str) -> None: ...
+    def __contains__(self, key: str) -> bool: ...
+    def __len__(self) -> int: ...
+    def __iter__(self) -> Iterator[str]: ...
+    def size(self) -> int: ...
+    def memory_usage(self) -> int:
+        """Bytes of keys and values held, as max_bytes counts them."""
+    def resize(self, max_size: int) -> int:
+        """Hold at most max_size entries, returning how many shrinking evicted."""
+    def save_snapshot(self, path: str | os.PathLike[str]) -> int:
+        """Write the live entries and their remaining TTLs to path; how many."""
+    def load_snapshot(self, path: str | os.PathLike[str]) -> int:
+        """Set the entries save_snapshot wrote that haven't expired since; how many."""
+    def cleanup_expired(self) -> int: ...
+    def start_janitor(self, interval_secs: float) -> None:
+        """Sweep expired entries every interval_secs on a background thread."""
+    def stop_janitor(self) -> bool:
+        """Stop the janitor, returning whether it was running."""
+    def add_listener(
+        self, listener: Callable[[str, Any, Literal["evicted", "expired"]], object]
+    ) -> None:
+        """Call listener(key, value, cause) with each entry evicted or found expired."""
+    def remove_listener(self, listener: Callable[..., object]) -> bool: ...
+    def clear(self) -> None: ...
+    def stats(self) -> CacheStats: ...
+    def __getnewargs_ex__(self) -> tuple[tuple[int, int], dict[str, Any]]: ...
+    def __getstate__(self) -> list[tuple[str, Any, int]]:
+        """Live entries as (key, value, remaining TTL in seconds)."""
+    def __setstate__(self, state: list[tuple[str, Any, int]]) -> None: ...
+
+class RustSharedCache:
+    """LRU-by-expiry cache shared between processes through a mapped file."""
+
+    def __init__(
+        self,
+        path: str,
+        max_size: int = 65536,
+        ttl_secs: int = 300,
+        slot_size: int = 1024,
+    ) -> None: ...
+    def get(self, key: str) -> str | None: ...
+    def set(self, key: str, value: str, ttl: int | None = None) -> None: ...
+    def delete(self, key: str) -> bool: ...
+    def size(self) -> int: ...
+    def cleanup_expired(self) -> int: ...
+    def clear(self) -> None: ...
+    def stats(self) -> CacheStats: ...
+    @property
+    def path(self) -> str: ...
+
+class RustTieredCache:
+    """RustCache in front of a Redis tier shared between processes."""
+
+    def __init__(
+        self,
+        max_size: int,
+        ttl_secs: int,
+        redis_url: str,
+        prefix: str = "sark:cache:",
+        serializer: Literal["pickle"] | None = None,
+    ) -> None:
+        """Redis errors count as misses (and in l2_errors), never raise."""
+    def get(self, key: str) -> Any | None:
+        """From L1, else from Redis, promoting it into L1."""
+    def set(self, key: str, value: Any, ttl: int | None = None) -> None:
+        """Write both tiers."""
+    def delete(self, key: str) -> bool: ...
+    def clear(self) -> None:
+        """Drop L1 and every Redis key under prefix."""
+    def stats(self) -> CacheStats:
+        """L1's stats."""
+    @property
+    def l2_hits(self) -> int: ...
+    @property
+    def l2_errors(self) -> int: ...
+
+class GatewayError(SarkError):
+    """The gateway didn't decide the request."""
+
+class GatewayClient:
+    """Async client for the Rust gateway's decision endpoints."""
+
+    def __init__(
+        self,
+        base_url: str,
+        *,
+        token: str | None = None,
+        api_key: str | None = None,
+        timeout_ms: int = 2000,
+        max_retries: int = 2,
+        pool_size: int = 32,
+        cache_size: int = 10_000,
+        cache_ttl_secs: int = 300,
+        fail_open: bool = False,
+    ) -> None: ...
+    async def authorize(
+        self, request: dict[str, Any], *, token: str | None = None
+    ) -> dict[str, Any]: ...
+    async def authorize_a2a(
+        self, request: dict[str, Any], *, token: str | None = None
+    ) -> dict[str, Any]: ...
+    async def authorize_batch(
+        self, requests: list[dict[str, Any]], *, token: str | None = None
+    ) -> list[dict[str, Any]]: ...
+    def clear_cache(self) -> None: ...
+
+class JWTValidationError(ValueError):
+    """The token (or the key set) was refused."""
+
+class RustJWTValidator:
+    """Validates JWTs against a cached JWKS."""
+
+    def __init__(
+        self,
+        jwks_url: str,
+        *,
+        audience: str | None = None,
+        issuer: str | None = None,
+        refresh_interval: int = 300,
+        claims: dict[str, str | list[str]] | None = None,
+    ) -> None: ...
+    def validate(self, token: str) -> dict[str, Any]: ...
+    def extract_claims(self, token: str) -> dict[str, Any]: ...
+    def key_count(self) -> int: ...
+
+class RequestContext:
+    """Where a request comes from, as policy input's ``context`` gets it."""
+
+    def __init__(
+        self,
+        *,
+        trace_id: str | None = None,
+        tenant: str | None = None,
+        session_id: str | None = None,
+        client_ip: str | None = None,
+        user_agent: str | None = None,
+    ) -> None: ...
+    @staticmethod
+    def from_request(
+        request: Any, *, tenant: str | None = None, session_id: str | None = None
+    ) -> RequestContext:
+        """The context of a FastAPI request, read from its headers and client."""
+    @property
+    def trace_id(self) -> str | None: ...
+    @property
+    def tenant(self) -> str | None: ...
+    @property
+    def session_id(self) -> str | None: ...
+    @property
+    def client_ip(self) -> str | None: ...
+    @property
+    def user_agent(self) -> str | None: ...
+    def to_input(self, input: dict[str, Any]) -> dict[str, Any]:
+        """A copy of input with the context merged into input["context"]."""
+    def to_dict(self) -> dict[str, str]: ...
+
+class SensitivityClassifier:
+    """Sensitivity levels of tool calls, by the gateway's classify rules."""
+
+    levels: list[str]
+
+    def __init__(
+        self,
+        rules: list[dict[str, Any]] | None = None,
+        *,
+        default_level: str = "medium",
+        builtin_rules: bool = True,
+    ) -> None: ...
+    def classify(
+        self,
+        tool_name: str,
+        description: str | None = None,
+        parameters: dict[str, Any] | None = None,
+        *,
+        server: str = "",
+    ) -> str:
+        """The level of a call; parameters may be arguments or a JSON schema."""
+    def explain(
+        self,
+        tool_name: str,
+        description: str | None = None,
+        parameters: dict[str, Any] | None = None,
+        *,
+        server: str = "",
+    ) -> tuple[str, str | None]:
+        """The level of a call and the rule that gave it (None for the default)."""
+    @property
+    def default_level(self) -> str: ...
+    def rule_count(self) -> int: ...
+
+def enable_logging(level: str = "info") -> None:
+    """Forward Rust log events at ``level`` and above to Python logging."""
+
+class BuildInfo(TypedDict):
+    version: str
+    git_sha: str
+    profile: str
+    rustc: str
+    regorus: str
+    features: dict[str, bool]
+
+def build_info() -> BuildInfo:
+    """What this build of the module is: version, commit and capabilities."""
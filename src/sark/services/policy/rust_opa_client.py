@@ -1,6 +1,7 @@
 """Rust-based OPA client for high-performance policy evaluation."""
 
 from pathlib import Path
+import threading
 import time
 from typing import Any
 
@@ -94,6 +95,11 @@ class RustOPAClient:
         # Initialize the Rust engine
         self.engine = RustOPAEngine()
 
+        # The engine's methods borrow it mutably. Under the GIL calls never
+        # overlap; on free-threaded Python, calls from other threads would
+        # fail with "Already borrowed" instead of waiting, so serialize them.
+        self._engine_lock = threading.Lock()
+
         # Set policy directory
         self.policy_dir = policy_dir or Path("opa/policies")
 
@@ -121,10 +127,11 @@ class RustOPAClient:
             rego_code: The Rego policy source code
 
         Raises:
-            OPACompilationError: If the policy cannot be compiled
+            PolicyCompileError: If the policy cannot be compiled
         """
         try:
-            self.engine.load_policy(name, rego_code)
+            with self._engine_lock:
+                self.engine.load_policy(name, rego_code)
             self._loaded_policies.add(name)
 
             logger.debug("policy_loaded", policy_name=name)
@@ -135,18 +142,30 @@ class RustOPAClient:
 
     async def load_policy_from_file(self, policy_path: Path) -> None:
         """
-        Load a policy from a .rego file.
+        Load a policy from a .rego file, or a CEL package from a .cel.toml file.
 
         Args:
-            policy_path: Path to the .rego file
+            policy_path: Path to the .rego or .cel.toml file
 
         Raises:
             FileNotFoundError: If the policy file doesn't exist
-            OPACompilationError: If the policy cannot be compiled
+            PolicyCompileError: If the policy cannot be compiled
         """
         if not policy_path.exists():
             raise FileNotFoundError(f"Policy file not found: {policy_path}")
 
+        if policy_path.name.endswith(".cel.toml"):
+            policy_name = policy_path.name.removesuffix(".cel.toml")
+            try:
+                with self._engine_lock:
+                    self.engine.load_cel_policy(policy_name, policy_path.read_text())
+                self._loaded_policies.add(policy_name)
+                logger.debug("cel_policy_loaded", policy_name=policy_name)
+            except Exception as e:
+                logger.error("policy_load_failed", policy_name=policy_name, error=str(e))
+                raise
+            return
+
         policy_name = policy_path.stem
         rego_code = policy_path.read_text()
 
@@ -244,29 +263,18 @@ class RustOPAClient:
             input_data = auth_input.model_dump()
 
             # Evaluate the policy using Rust engine
-            result = self.engine.evaluate(policy_query, input_data)
+            with self._engine_lock:
+                policy_decision = self.engine.evaluate(policy_query, input_data)
 
             evaluation_latency_ms = (time.time() - start_time) * 1000
 
-            # Parse the result
-            # Regorus returns the raw evaluation result, which might be a boolean or object
-            if isinstance(result, bool):
-                allow = result
-                reason = "Policy evaluation completed"
-                filtered_parameters = None
-                audit_id = None
-            elif isinstance(result, dict):
-                allow = result.get("allow", False)
-                reason = result.get("reason", "Policy evaluation completed")
-                filtered_parameters = result.get("filtered_parameters")
-                audit_id = result.get("audit_id")
-            else:
-                # Unexpected result type, default to deny
-                logger.warning("unexpected_result_type", result_type=type(result).__name__)
-                allow = False
-                reason = "Unexpected policy result format"
-                filtered_parameters = None
-                audit_id = None
+            # The engine reads allow (from a rule, or a package's allow
+            # member), reason and filtered_parameters out of the result
+            result = policy_decision.result
+            audit_id = result.get("audit_id") if isinstance(result, dict) else None
+            allow = policy_decision.allow
+            reason = policy_decision.reason or "Policy evaluation completed"
+            filtered_parameters = policy_decision.filtered_parameters
 
             decision = AuthorizationDecision(
                 allow=allow,
@@ -432,7 +440,8 @@ class RustOPAClient:
 
         try:
             # Simple health check - try to get loaded policies
-            _ = self.engine.loaded_policies()
+            with self._engine_lock:
+                _ = self.engine.loaded_policies()
         except Exception as e:
             logger.error("rust_engine_health_check_failed", error=str(e))
             engine_healthy = False
@@ -453,4 +462,5 @@ class RustOPAClient:
         Returns:
             List of policy names
         """
-        return self.engine.loaded_policies()
+        with self._engine_lock:
+            return self.engine.loaded_policies()
@@ -0,0 +1,524 @@
+//! `RustSharedCache`, one cache for every worker process
+//!
+//! A `RustCache` lives in the process that made it, so N gunicorn or
+//! uvicorn workers hold N caches and each evaluates policy for its own
+//! misses. A `RustSharedCache` is a fixed-size table in a memory-mapped
+//! file (under `/dev/shm` for a RAM-backed one); every process that opens
+//! the same path reads and writes the same entries, and forked workers
+//! keep sharing the mapping they inherit.
+//!
+//! The table holds `max_size` slots of `slot_size` bytes, grouped by key
+//! hash into buckets of four. A set takes the key's own slot, else an
+//! empty or expired one, else the one expiring soonest; a key and its
+//! value must fit in one slot together. Each slot is a seqlock: a writer
+//! takes it by making its sequence odd, and readers copy it and retry if
+//! the sequence moved, so reads never block. A writer that can't take a
+//! slot within a bounded spin skips the set, as a miss costs only an
+//! evaluation. Expiry is wall-clock time, since processes share no
+//! monotonic clock; hit, miss, insertion, eviction and expiration counts
+//! are per process, and the memory in `stats()` is the whole mapping.
+
+use crate::cache::CacheStats;
+use crate::errors::SarkCacheError;
+use memmap2::{MmapOptions, MmapRaw};
+use pyo3::prelude::*;
+use pyo3::types::PyType;
+use std::fs::OpenOptions;
+use std::hint::spin_loop;
+use std::ops::Range;
+use std::ptr;
+use std::sync::atomic::{fence, AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// First word of an initialized file
+const MAGIC: u64 = u64::from_le_bytes(*b"SARKSHC1");
+/// First word while the creating process writes the header
+const INITIALIZING: u64 = 1;
+/// File header: magic, slot count, slot size
+const HEADER: usize = 64;
+/// Slot header: sequence, key hash, expiry (ms since the epoch), key and
+/// value lengths. An expiry of 0 marks an empty slot.
+const SLOT_HEADER: usize = 32;
+/// Slots per bucket
+const WAYS: usize = 4;
+/// Attempts at taking a slot, or at a consistent read of one
+const SPINS: u32 = 1000;
+/// How long to wait for another process to initialize the file
+const INIT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A slot's contents, copied out consistently
+struct Entry {
+    hash: u64,
+    expires_ms: u64,
+    /// Empty unless the slot's hash was the one asked for
+    key: Vec<u8>,
+    value: Vec<u8>,
+}
+
+/// LRU-by-expiry cache shared between processes through a mapped file
+#[pyclass(module = "sark_rust")]
+pub struct RustSharedCache {
+    map: MmapRaw,
+    path: String,
+    slots: usize,
+    slot_size: usize,
+    ttl_secs: u64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    insertions: AtomicU64,
+    evictions: AtomicU64,
+    expirations: AtomicU64,
+}
+
+#[pymethods]
+impl RustSharedCache {
+    /// Open the cache at `path`, creating it with `max_size` slots of
+    /// `slot_size` bytes if it doesn't exist. Entries set without their
+    /// own TTL are kept `ttl_secs`.
+    #[new]
+    #[pyo3(signature = (path, max_size = 65536, ttl_secs = 300, slot_size = 1024))]
+    fn new(path: String, max_size: usize, ttl_secs: u64, slot_size: usize) -> PyResult<Self> {
+        if slot_size <= SLOT_HEADER {
+            return Err(SarkCacheError::new_err(format!(
+                "slot_size must be more than {} bytes",
+                SLOT_HEADER
+            )));
+        }
+        let slot_size = slot_size.next_multiple_of(8);
+        let slots = max_size.max(WAYS).next_multiple_of(WAYS);
+        let len = HEADER + slots * slot_size;
+
+        let io_error = |e: std::io::Error| {
+            SarkCacheError::new_err(format!("Failed to open shared cache {}: {}", path, e))
+        };
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .map_err(io_error)?;
+        if file.metadata().map_err(io_error)?.len() == 0 {
+            file.set_len(len as u64).map_err(io_error)?;
+        }
+        let file_len = file.metadata().map_err(io_error)?.len() as usize;
+        if file_len < HEADER {
+            return Err(SarkCacheError::new_err(format!(
+                "{} is not a SARK shared cache",
+                path
+            )));
+        }
+        let map = MmapOptions::new()
+            .len(file_len)
+            .map_raw(&file)
+            .map_err(io_error)?;
+
+        let cache = Self {
+            map,
+            path,
+            slots,
+            slot_size,
+            ttl_secs,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            insertions: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+            expirations: AtomicU64::new(0),
+        };
+        cache.attach(file_len)?;
+        Ok(cache)
+    }
+
+    /// The value at `key`, or `None` if it is missing or expired
+    fn get(&self, py: Python<'_>, key: &str) -> Option<String> {
+        let value = py.allow_threads(|| self.lookup(key.as_bytes()));
+        let counter = if value.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        value
+    }
+
+    /// Store `value` at `key`, for `ttl` seconds if given
+    #[pyo3(signature = (key, value, ttl = None))]
+    fn set(&self, py: Python<'_>, key: &str, value: &str, ttl: Option<u64>) -> PyResult<()> {
+        let ttl = ttl.unwrap_or(self.ttl_secs);
+        py.allow_threads(|| self.store(key.as_bytes(), value.as_bytes(), ttl))
+    }
+
+    /// Remove `key`, returning whether it was there
+    fn delete(&self, py: Python<'_>, key: &str) -> bool {
+        let key = key.as_bytes();
+        let hash = hash(key);
+        py.allow_threads(|| {
+            let mut deleted = false;
+            // A race between two sets of a new key can leave it in two slots
+            for index in self.bucket(hash) {
+                deleted |= self.clear_slot(index, |entry| {
+                    entry.hash == hash && entry.key == key && entry.expires_ms > now_ms()
+                });
+            }
+            deleted
+        })
+    }
+
+    /// Entries held, expired or not
+    fn size(&self, py: Python<'_>) -> usize {
+        py.allow_threads(|| self.held())
+    }
+
+    /// Drop expired entries, returning how many there were
+    fn cleanup_expired(&self, py: Python<'_>) -> usize {
+        py.allow_threads(|| {
+            let now = now_ms();
+            let expired = (0..self.slots)
+                .filter(|&index| {
+                    self.clear_slot(index, |entry| {
+                        entry.expires_ms != 0 && entry.expires_ms <= now
+                    })
+                })
+                .count();
+            self.expirations
+                .fetch_add(expired as u64, Ordering::Relaxed);
+            expired
+        })
+    }
+
+    /// Empty the cache, for every process sharing it
+    fn clear(&self, py: Python<'_>) {
+        py.allow_threads(|| {
+            for index in 0..self.slots {
+                self.clear_slot(index, |entry| entry.expires_ms != 0);
+            }
+        });
+    }
+
+    /// Size, limits and memory of the shared table, and this process's
+    /// counts
+    fn stats(&self, py: Python<'_>) -> CacheStats {
+        CacheStats {
+            size: py.allow_threads(|| self.held()),
+            max_size: self.slots,
+            ttl_secs: self.ttl_secs,
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            insertions: self.insertions.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            expirations: self.expirations.load(Ordering::Relaxed),
+            memory_bytes: (HEADER + self.slots * self.slot_size) as u64,
+            compressions: 0,
+            compression_saved_bytes: 0,
+        }
+    }
+
+    /// Path of the mapped file
+    #[getter]
+    fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Pickles as its path and geometry, so a spawned worker reattaches
+    /// to the same table
+    fn __reduce__<'py>(
+        slf: &Bound<'py, Self>,
+    ) -> (Bound<'py, PyType>, (String, usize, u64, usize)) {
+        let this = slf.borrow();
+        (
+            slf.get_type(),
+            (this.path.clone(), this.slots, this.ttl_secs, this.slot_size),
+        )
+    }
+}
+
+impl RustSharedCache {
+    /// Initialize the header of a new file, or check an existing one was
+    /// made with the same geometry
+    fn attach(&self, file_len: usize) -> PyResult<()> {
+        let magic = self.word(0);
+        if magic
+            .compare_exchange(0, INITIALIZING, Ordering::Acquire, Ordering::Acquire)
+            .is_ok()
+        {
+            self.word(8).store(self.slots as u64, Ordering::Relaxed);
+            self.word(16)
+                .store(self.slot_size as u64, Ordering::Relaxed);
+            magic.store(MAGIC, Ordering::Release);
+        }
+
+        let started = Instant::now();
+        while magic.load(Ordering::Acquire) == INITIALIZING {
+            if started.elapsed() > INIT_TIMEOUT {
+                return Err(SarkCacheError::new_err(format!(
+                    "Timed out waiting for {} to be initialized",
+                    self.path
+                )));
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        if magic.load(Ordering::Acquire) != MAGIC {
+            return Err(SarkCacheError::new_err(format!(
+                "{} is not a SARK shared cache",
+                self.path
+            )));
+        }
+
+        let slots = self.word(8).load(Ordering::Relaxed) as usize;
+        let slot_size = self.word(16).load(Ordering::Relaxed) as usize;
+        if slots != self.slots
+            || slot_size != self.slot_size
+            || file_len != HEADER + slots * slot_size
+        {
+            return Err(SarkCacheError::new_err(format!(
+                "{} was created with max_size={} and slot_size={}",
+                self.path, slots, slot_size
+            )));
+        }
+        Ok(())
+    }
+
+    fn lookup(&self, key: &[u8]) -> Option<String> {
+        let hash = hash(key);
+        let now = now_ms();
+        self.bucket(hash)
+            .filter_map(|index| self.read(index, hash))
+            .find(|entry| entry.hash == hash && entry.key == key && entry.expires_ms > now)
+            .and_then(|entry| String::from_utf8(entry.value).ok())
+    }
+
+    fn store(&self, key: &[u8], value: &[u8], ttl: u64) -> PyResult<()> {
+        let capacity = self.slot_size - SLOT_HEADER;
+        if key.len() + value.len() > capacity {
+            return Err(SarkCacheError::new_err(format!(
+                "Entry of {} bytes doesn't fit a shared cache slot ({} bytes)",
+                key.len() + value.len(),
+                capacity
+            )));
+        }
+
+        let hash = hash(key);
+        let now = now_ms();
+        let expires_ms = now.saturating_add(ttl.saturating_mul(1000));
+
+        // The key's own slot, else the emptiest: empty or expired slots
+        // sort first, then those expiring soonest
+        let mut target = None;
+        let mut best = u64::MAX;
+        // What the chosen slot holds: nothing, an expired entry, or a live
+        // one set aside
+        let mut replaced = None;
+        for index in self.bucket(hash) {
+            let Some(entry) = self.read(index, hash) else {
+                continue;
+            };
+            if entry.hash == hash && entry.key == key {
+                target = Some(index);
+                replaced =
+                    (entry.expires_ms != 0 && entry.expires_ms <= now).then_some(&self.expirations);
+                break;
+            }
+            let rank = if entry.expires_ms <= now {
+                0
+            } else {
+                entry.expires_ms
+            };
+            if rank < best {
+                best = rank;
+                target = Some(index);
+                replaced = match entry.expires_ms {
+                    0 => None,
+                    expires_ms if expires_ms <= now => Some(&self.expirations),
+                    _ => Some(&self.evictions),
+                };
+            }
+        }
+        let Some(index) = target else {
+            return Ok(());
+        };
+
+        let Some(locked) = self.lock(index) else {
+            return Ok(());
+        };
+        let slot = self.slot(index);
+        // Safety: the slot lies within the mapping, its header fields are
+        // 8-byte aligned, the data fits its capacity, and the odd
+        // sequence keeps other writers out
+        unsafe {
+            ptr::write_volatile(slot.add(8) as *mut u64, hash);
+            ptr::write_volatile(slot.add(16) as *mut u64, expires_ms);
+            ptr::write_volatile(slot.add(24) as *mut u32, key.len() as u32);
+            ptr::write_volatile(slot.add(28) as *mut u32, value.len() as u32);
+            let data = slot.add(SLOT_HEADER);
+            ptr::copy_nonoverlapping(key.as_ptr(), data, key.len());
+            ptr::copy_nonoverlapping(value.as_ptr(), data.add(key.len()), value.len());
+        }
+        self.unlock(index, locked);
+        if let Some(counter) = replaced {
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+        self.insertions.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Empty slot `index` if `matches` its contents, returning whether it
+    /// did
+    fn clear_slot(&self, index: usize, matches: impl Fn(&Entry) -> bool) -> bool {
+        let Some(locked) = self.lock(index) else {
+            return false;
+        };
+        let slot = self.slot(index);
+        // Safety: as in `store`; the slot is locked, so it can't change
+        // while being read
+        let cleared = unsafe {
+            let hash = ptr::read_volatile(slot.add(8) as *const u64);
+            let entry = Entry {
+                hash,
+                expires_ms: ptr::read_volatile(slot.add(16) as *const u64),
+                key: self.copy_key(slot),
+                value: Vec::new(),
+            };
+            let cleared = matches(&entry);
+            if cleared {
+                ptr::write_volatile(slot.add(16) as *mut u64, 0);
+                ptr::write_volatile(slot.add(24) as *mut u32, 0);
+                ptr::write_volatile(slot.add(28) as *mut u32, 0);
+            }
+            cleared
+        };
+        self.unlock(index, locked);
+        cleared
+    }
+
+    /// A consistent copy of slot `index`, with its key and value if its
+    /// hash is `hash`; `None` if it is being written throughout
+    fn read(&self, index: usize, hash: u64) -> Option<Entry> {
+        let slot = self.slot(index);
+        let seq = self.seq(index);
+        for _ in 0..SPINS {
+            let before = seq.load(Ordering::Acquire);
+            if before & 1 == 1 {
+                spin_loop();
+                continue;
+            }
+            // Safety: the slot lies within the mapping. A writer may be
+            // changing it meanwhile; the copy is only used if the
+            // sequence didn't move, and lengths are clamped to the slot.
+            let entry = unsafe {
+                let slot_hash = ptr::read_volatile(slot.add(8) as *const u64);
+                let expires_ms = ptr::read_volatile(slot.add(16) as *const u64);
+                let (key, value) = if slot_hash == hash && expires_ms != 0 {
+                    let key = self.copy_key(slot);
+                    let value_len = (ptr::read_volatile(slot.add(28) as *const u32) as usize)
+                        .min(self.slot_size - SLOT_HEADER - key.len());
+                    let mut value = vec![0; value_len];
+                    ptr::copy_nonoverlapping(
+                        slot.add(SLOT_HEADER + key.len()),
+                        value.as_mut_ptr(),
+                        value_len,
+                    );
+                    (key, value)
+                } else {
+                    (Vec::new(), Vec::new())
+                };
+                Entry {
+                    hash: slot_hash,
+                    expires_ms,
+                    key,
+                    value,
+                }
+            };
+            fence(Ordering::Acquire);
+            if seq.load(Ordering::Relaxed) == before {
+                return Some(entry);
+            }
+        }
+        None
+    }
+
+    /// Copy of the key in `slot`, its length clamped to the slot
+    ///
+    /// Safety: `slot` must be a slot of the mapping
+    unsafe fn copy_key(&self, slot: *mut u8) -> Vec<u8> {
+        let len = (ptr::read_volatile(slot.add(24) as *const u32) as usize)
+            .min(self.slot_size - SLOT_HEADER);
+        let mut key = vec![0; len];
+        ptr::copy_nonoverlapping(slot.add(SLOT_HEADER), key.as_mut_ptr(), len);
+        key
+    }
+
+    /// Take slot `index` for writing, returning its (odd) sequence
+    fn lock(&self, index: usize) -> Option<u64> {
+        let seq = self.seq(index);
+        for _ in 0..SPINS {
+            let current = seq.load(Ordering::Relaxed);
+            if current & 1 == 0
+                && seq
+                    .compare_exchange_weak(
+                        current,
+                        current + 1,
+                        Ordering::Acquire,
+                        Ordering::Relaxed,
+                    )
+                    .is_ok()
+            {
+                // Readers must not see the writes before the odd sequence
+                fence(Ordering::Release);
+                return Some(current + 1);
+            }
+            spin_loop();
+        }
+        None
+    }
+
+    fn unlock(&self, index: usize, locked: u64) {
+        self.seq(index).store(locked + 1, Ordering::Release);
+    }
+
+    /// Slots counted by `size`: set, expired or not
+    fn held(&self) -> usize {
+        (0..self.slots)
+            .filter(|&index| {
+                self.read(index, 0)
+                    .is_some_and(|entry| entry.expires_ms != 0)
+            })
+            .count()
+    }
+
+    fn bucket(&self, hash: u64) -> Range<usize> {
+        let first = (hash % (self.slots / WAYS) as u64) as usize * WAYS;
+        first..first + WAYS
+    }
+
+    fn slot(&self, index: usize) -> *mut u8 {
+        debug_assert!(index < self.slots);
+        // Safety: `attach` checked the mapping holds every slot
+        unsafe { self.map.as_mut_ptr().add(HEADER + index * self.slot_size) }
+    }
+
+    fn seq(&self, index: usize) -> &AtomicU64 {
+        // Safety: slots start 8-byte aligned, and the sequence is only
+        // accessed atomically
+        unsafe { &*(self.slot(index) as *const AtomicU64) }
+    }
+
+    /// The header word at `offset`
+    fn word(&self, offset: usize) -> &AtomicU64 {
+        // Safety: the mapping is at least `HEADER` bytes and page-aligned
+        unsafe { &*(self.map.as_mut_ptr().add(offset) as *const AtomicU64) }
+    }
+}
+
+/// FNV-1a, the same in every process (unlike std's randomly keyed hashers)
+fn hash(key: &[u8]) -> u64 {
+    key.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
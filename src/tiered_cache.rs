@@ -0,0 +1,231 @@
+//! `RustTieredCache`, a `RustCache` in front of Redis
+//!
+//! Each process's `RustCache` evaluates policy for its own misses; with
+//! several replicas behind a load balancer, every one of them evaluates
+//! the same hot decisions. A `RustTieredCache` keeps an in-memory L1 (a
+//! `RustCache` of `max_size` entries) and a Redis L2 shared by every
+//! replica given the same `redis_url` and `prefix`: a set writes both
+//! tiers, and an L1 miss falls through to Redis, promoting what it finds
+//! into L1 for the TTL it has left there.
+//!
+//! As in the gateway's Redis tier, Redis is an optimization, never a
+//! dependency of a decision: the client connects on first use, and a
+//! Redis error (or a call over `REDIS_TIMEOUT`) is logged and counted in
+//! `l2_errors`, and costs only an L2 hit or write; the next call
+//! reconnects. Values go to Redis as L1 stores them, tagged with their
+//! type, so a `bytes` or pickled value comes back as what was set. Calls
+//! share one connection, each releasing the GIL for its round trip.
+
+use crate::cache::{decode, CacheStats, RustCache};
+use crate::errors::SarkCacheError;
+use pyo3::prelude::*;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::warn;
+
+/// Longest a Redis round trip (or connecting) may take before it counts
+/// as an error
+const REDIS_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Keys `SCAN` looks at per round trip when `clear` drops the L2 keys
+const SCAN_COUNT: usize = 500;
+
+/// In-memory cache backed by a Redis tier shared between processes
+#[pyclass(module = "sark_rust")]
+pub struct RustTieredCache {
+    l1: RustCache,
+    client: redis::Client,
+    /// Reused between calls; dropped on an error, so the next reconnects
+    conn: Mutex<Option<redis::Connection>>,
+    /// Prepended to every key in Redis
+    prefix: String,
+    ttl_secs: u64,
+    l2_hits: AtomicU64,
+    l2_errors: AtomicU64,
+}
+
+#[pymethods]
+impl RustTieredCache {
+    /// An L1 of at most `max_size` entries in front of the Redis at
+    /// `redis_url`, keys under `prefix`, each kept `ttl_secs` unless set
+    /// with its own TTL; `serializer` is `RustCache`'s
+    #[new]
+    #[pyo3(signature = (max_size, ttl_secs, redis_url, prefix = "sark:cache:", serializer = None))]
+    fn new(
+        max_size: usize,
+        ttl_secs: u64,
+        redis_url: &str,
+        prefix: &str,
+        serializer: Option<&str>,
+    ) -> PyResult<Self> {
+        let client = redis::Client::open(redis_url)
+            .map_err(|e| SarkCacheError::new_err(format!("Invalid Redis URL: {}", e)))?;
+        Ok(Self {
+            l1: RustCache::plain(max_size, ttl_secs, serializer)?,
+            client,
+            conn: Mutex::new(None),
+            prefix: prefix.to_string(),
+            ttl_secs,
+            l2_hits: AtomicU64::new(0),
+            l2_errors: AtomicU64::new(0),
+        })
+    }
+
+    /// The value at `key` in L1, else in Redis, or `None` if neither
+    /// holds it
+    fn get(&self, py: Python<'_>, key: &str) -> PyResult<Option<PyObject>> {
+        if let Some(value) = self.l1.get_encoded(py, key) {
+            return decode(py, value).map(Some);
+        }
+        let redis_key = self.redis_key(key);
+        let found = py.allow_threads(|| {
+            let found: Option<(Option<String>, i64)> = self.query("lookup", |conn| {
+                redis::pipe().get(&redis_key).ttl(&redis_key).query(conn)
+            });
+            match found {
+                // A negative TTL means no expiry (-1) or a race with
+                // expiry (-2); neither is safe to promote with a deadline
+                Some((Some(value), ttl)) if ttl > 0 => Some((value, ttl as u64)),
+                _ => None,
+            }
+        });
+        let Some((value, ttl)) = found else {
+            return Ok(None);
+        };
+        self.l2_hits.fetch_add(1, Ordering::Relaxed);
+        self.l1
+            .set_encoded(py, key.to_string(), value.clone(), Some(ttl))?;
+        decode(py, value).map(Some)
+    }
+
+    /// Store `value` at `key` in both tiers, for `ttl` seconds if given
+    #[pyo3(signature = (key, value, ttl = None))]
+    fn set(
+        &self,
+        py: Python<'_>,
+        key: String,
+        value: &Bound<'_, PyAny>,
+        ttl: Option<u64>,
+    ) -> PyResult<()> {
+        let value = self.l1.encode(value)?;
+        let redis_key = self.redis_key(&key);
+        self.l1.set_encoded(py, key, value.clone(), ttl)?;
+        let ttl = ttl.unwrap_or(self.ttl_secs).max(1);
+        py.allow_threads(|| {
+            self.query::<()>("write", |conn| {
+                redis::cmd("SET")
+                    .arg(&redis_key)
+                    .arg(&value)
+                    .arg("EX")
+                    .arg(ttl)
+                    .query(conn)
+            })
+        });
+        Ok(())
+    }
+
+    /// Remove `key` from both tiers, returning whether either held it
+    fn delete(&self, py: Python<'_>, key: &str) -> bool {
+        let held = self.l1.delete(py, key);
+        let redis_key = self.redis_key(key);
+        let unlinked = py.allow_threads(|| {
+            self.query::<u64>("delete", |conn| {
+                redis::cmd("UNLINK").arg(&redis_key).query(conn)
+            })
+        });
+        held || unlinked.unwrap_or(0) > 0
+    }
+
+    /// Drop every entry of both tiers: L1's, and the keys under `prefix`
+    /// in Redis, found with `SCAN` so Redis is never blocked on a large
+    /// keyspace
+    fn clear(&self, py: Python<'_>) {
+        self.l1.clear(py);
+        let pattern = format!("{}*", self.prefix);
+        py.allow_threads(|| {
+            let mut cursor: u64 = 0;
+            loop {
+                let page: Option<(u64, Vec<String>)> = self.query("prefix scan", |conn| {
+                    redis::cmd("SCAN")
+                        .arg(cursor)
+                        .arg("MATCH")
+                        .arg(&pattern)
+                        .arg("COUNT")
+                        .arg(SCAN_COUNT)
+                        .query(conn)
+                });
+                let Some((next, keys)) = page else {
+                    return;
+                };
+                if !keys.is_empty() {
+                    self.query::<()>("prefix delete", |conn| {
+                        redis::cmd("UNLINK").arg(&keys).query(conn)
+                    });
+                }
+                if next == 0 {
+                    return;
+                }
+                cursor = next;
+            }
+        });
+    }
+
+    /// L1's size, limits and counts; its hits don't include L2's
+    fn stats(&self, py: Python<'_>) -> CacheStats {
+        self.l1.stats(py)
+    }
+
+    /// L1 misses Redis held
+    #[getter]
+    fn l2_hits(&self) -> u64 {
+        self.l2_hits.load(Ordering::Relaxed)
+    }
+
+    /// Redis calls that failed or timed out
+    #[getter]
+    fn l2_errors(&self) -> u64 {
+        self.l2_errors.load(Ordering::Relaxed)
+    }
+}
+
+impl RustTieredCache {
+    fn redis_key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+
+    /// Run `op` on the connection, connecting first if there is none;
+    /// on an error, logged as a failed `what`, the connection is dropped
+    /// and `None` returned
+    fn query<T>(
+        &self,
+        what: &str,
+        op: impl FnOnce(&mut redis::Connection) -> redis::RedisResult<T>,
+    ) -> Option<T> {
+        let mut held = self.conn.lock().expect("Redis connection lock poisoned");
+        let result = match held.take() {
+            Some(conn) => Ok(conn),
+            None => self.connect(),
+        }
+        .and_then(|mut conn| {
+            let result = op(&mut conn)?;
+            *held = Some(conn);
+            Ok(result)
+        });
+        match result {
+            Ok(result) => Some(result),
+            Err(e) => {
+                self.l2_errors.fetch_add(1, Ordering::Relaxed);
+                warn!(error = %e, "Redis cache {} failed", what);
+                None
+            }
+        }
+    }
+
+    fn connect(&self) -> redis::RedisResult<redis::Connection> {
+        let conn = self.client.get_connection_with_timeout(REDIS_TIMEOUT)?;
+        conn.set_read_timeout(Some(REDIS_TIMEOUT))?;
+        conn.set_write_timeout(Some(REDIS_TIMEOUT))?;
+        Ok(conn)
+    }
+}
@@ -0,0 +1,71 @@
+"""RustTieredCache against a real Valkey.
+
+Run with:
+    pytest tests/integration/test_rust_tiered_cache.py -v
+"""
+
+from uuid import uuid4
+
+import pytest
+
+from sark._rust import RUST_AVAILABLE
+
+pytest_plugins = ["tests.fixtures.integration_docker"]
+
+pytestmark = [
+    pytest.mark.integration,
+    pytest.mark.skipif(not RUST_AVAILABLE, reason="Rust extensions not available"),
+]
+
+
+@pytest.fixture
+def make_tiered(valkey_service):
+    """RustTieredCaches sharing one Valkey under a prefix of the test's own."""
+    from sark._rust import RustTieredCache
+
+    prefix = f"sark:test:{uuid4().hex}:"
+    made = []
+
+    def make(**kwargs):
+        kwargs.setdefault("max_size", 100)
+        kwargs.setdefault("ttl_secs", 60)
+        cache = RustTieredCache(redis_url=valkey_service["url"], prefix=prefix, **kwargs)
+        made.append(cache)
+        return cache
+
+    yield make
+    if made:
+        made[0].clear()
+
+
+def test_replicas_share_decisions(make_tiered):
+    first, second = make_tiered(), make_tiered()
+    first.set("decision", "allow")
+
+    assert second.get("decision") == "allow"
+    assert second.l2_hits == 1
+    # Promoted into the second replica's L1
+    assert second.get("decision") == "allow"
+    assert second.l2_hits == 1
+    assert (first.l2_errors, second.l2_errors) == (0, 0)
+
+
+def test_values_keep_their_type(make_tiered):
+    first = make_tiered(serializer="pickle")
+    second = make_tiered(serializer="pickle")
+    first.set("raw", bytes(range(256)))
+    first.set("decision", {"allow": True, "reasons": ["policy"]})
+
+    assert second.get("raw") == bytes(range(256))
+    assert second.get("decision") == {"allow": True, "reasons": ["policy"]}
+
+
+def test_delete_and_clear_reach_redis(make_tiered):
+    first, second = make_tiered(), make_tiered()
+    first.set("a", "1")
+    first.set("b", "2")
+
+    assert first.delete("a") is True
+    assert second.get("a") is None
+    first.clear()
+    assert second.get("b") is None
@@ -8,6 +8,11 @@ Acceptance Criteria:
 - Rust 10-50x faster than Redis
 """
 
+from concurrent.futures import ThreadPoolExecutor
+import os
+import threading
+import time
+
 import pytest
 
 # ==============================================================================
@@ -432,6 +437,75 @@ async def test_cache_rust_eviction_lru(benchmark, sample_decision):
     benchmark(lambda: pytest.helpers.run_async(set_with_eviction))
 
 
+# ==============================================================================
+# Multi-threaded Access Benchmarks
+# ==============================================================================
+
+
+def _rust_cache_class():
+    """The raw RustCache binding, skipping the test when it isn't built."""
+    if os.getenv("RUST_ENABLED", "false").lower() != "true":
+        pytest.skip("RUST_ENABLED is not set")
+    from sark._rust import RUST_AVAILABLE, RustCache
+
+    if not RUST_AVAILABLE:
+        pytest.skip("Rust extensions not built")
+    return RustCache
+
+
+def _threaded_gets(cache, keys, threads, rounds):
+    """Run `rounds` GETs of every key in each of `threads` threads; returns seconds."""
+    barrier = threading.Barrier(threads + 1)
+
+    def worker():
+        barrier.wait()
+        for _ in range(rounds):
+            for key in keys:
+                cache.get(key)
+
+    with ThreadPoolExecutor(max_workers=threads) as pool:
+        futures = [pool.submit(worker) for _ in range(threads)]
+        barrier.wait()
+        start = time.perf_counter()
+        for future in futures:
+            future.result()
+        return time.perf_counter() - start
+
+
+@pytest.mark.benchmark(group="cache-threads")
+@pytest.mark.parametrize("threads", [4])
+def test_cache_rust_get_threads_do_not_serialize(benchmark, threads):
+    """
+    Benchmark RustCache GETs from several Python threads at once.
+
+    RustCache releases the GIL around every cache operation, so threads
+    reading large values copy them out of the cache in parallel. With the
+    GIL held, N threads take N times as long as one for N times the work;
+    released, they must take clearly less.
+    """
+    if (os.cpu_count() or 1) < threads:
+        pytest.skip(f"needs {threads} CPUs")
+    RustCache = _rust_cache_class()
+
+    cache = RustCache(max_size=1000, ttl_secs=3600)
+    # Large values, so time in the cache dominates time in the interpreter
+    value = "x" * (1 << 20)
+    keys = [f"decision-{i}" for i in range(16)]
+    for key in keys:
+        cache.set(key, value)
+    rounds = 20
+
+    single = _threaded_gets(cache, keys, 1, rounds * threads)
+    parallel = benchmark.pedantic(
+        _threaded_gets, args=(cache, keys, threads, rounds), rounds=5, iterations=1
+    )
+
+    speedup = single / parallel
+    benchmark.extra_info["speedup"] = round(speedup, 2)
+    # Fully serialized would be ~1.0x
+    assert speedup > 1.3, f"{threads} threads only {speedup:.2f}x faster than one"
+
+
 # ==============================================================================
 # Helpers
 # ==============================================================================
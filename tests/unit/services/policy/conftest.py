@@ -0,0 +1,21 @@
+"""Fixtures for the RustCache tests."""
+
+import pytest
+
+from sark._rust import RUST_AVAILABLE
+
+
+@pytest.fixture
+def make_cache():
+    """RustCache's constructor, skipping the test without the Rust extensions."""
+    if not RUST_AVAILABLE:
+        pytest.skip("Rust extensions not available")
+    from sark._rust import RustCache
+
+    return RustCache
+
+
+@pytest.fixture
+def cache(make_cache):
+    """An empty cache of 100 entries with a 60s TTL."""
+    return make_cache(max_size=100, ttl_secs=60)
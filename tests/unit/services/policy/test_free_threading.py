@@ -0,0 +1,138 @@
+"""Concurrency tests for the Rust extensions on free-threaded Python.
+
+These run on any build, but only exercise real parallelism on a
+free-threaded (3.13t) interpreter, where the extension must not re-enable
+the GIL on import.
+"""
+
+from concurrent.futures import ThreadPoolExecutor
+import sys
+import sysconfig
+import threading
+
+import pytest
+
+from sark._rust import RUST_AVAILABLE
+
+pytestmark = pytest.mark.skipif(not RUST_AVAILABLE, reason="Rust extensions not available")
+
+FREE_THREADED = bool(sysconfig.get_config_var("Py_GIL_DISABLED"))
+
+THREADS = 8
+
+
+def run_in_threads(fn, threads=THREADS):
+    """Run fn(index) in each of `threads` threads at once; returns results in order."""
+    barrier = threading.Barrier(threads)
+
+    def worker(index):
+        barrier.wait()
+        return fn(index)
+
+    with ThreadPoolExecutor(max_workers=threads) as pool:
+        return list(pool.map(worker, range(threads)))
+
+
+@pytest.mark.skipif(not FREE_THREADED, reason="Requires a free-threaded Python build")
+def test_import_keeps_gil_disabled():
+    """Importing sark_rust does not turn the GIL back on."""
+    import sark.sark_rust  # noqa: F401
+
+    assert not sys._is_gil_enabled()
+
+
+class TestRustCacheConcurrency:
+    """RustCache under concurrent readers and writers."""
+
+    def test_concurrent_set_get(self):
+        from sark._rust import RustCache
+
+        cache = RustCache(max_size=100_000, ttl_secs=60)
+
+        def work(index):
+            for i in range(1000):
+                cache.set(f"t{index}:k{i}", f"v{i}")
+            return all(cache.get(f"t{index}:k{i}") == f"v{i}" for i in range(1000))
+
+        assert all(run_in_threads(work))
+        assert cache.size() == THREADS * 1000
+
+    def test_concurrent_writes_to_shared_keys(self):
+        from sark._rust import RustCache
+
+        cache = RustCache(max_size=100, ttl_secs=60)
+
+        def work(index):
+            for i in range(2000):
+                cache.set(f"k{i % 10}", str(index))
+                cache.get(f"k{(i + 5) % 10}")
+                if i % 100 == 0:
+                    cache.delete(f"k{i % 10}")
+
+        run_in_threads(work)
+        values = [cache.get(f"k{i}") for i in range(10)]
+        assert all(v is None or v in {str(t) for t in range(THREADS)} for v in values)
+
+    def test_eviction_under_contention_respects_max_size(self):
+        from sark._rust import RustCache
+
+        cache = RustCache(max_size=50, ttl_secs=60)
+
+        def work(index):
+            for i in range(500):
+                cache.set(f"t{index}:k{i}", "v")
+
+        run_in_threads(work)
+        assert cache.size() <= 50
+
+
+class TestRustOPAClientConcurrency:
+    """RustOPAClient evaluating from several threads at once."""
+
+    def test_concurrent_evaluations(self, tmp_path):
+        import asyncio
+        from unittest.mock import AsyncMock, Mock
+
+        from sark.services.policy.rust_opa_client import AuthorizationInput, RustOPAClient
+
+        cache = Mock()
+        cache.enabled = False
+        cache.get = AsyncMock(return_value=None)
+        cache.set = AsyncMock()
+        cache.record_opa_latency = Mock()
+        cache.use_optimized_ttl = False
+        client = RustOPAClient(policy_dir=tmp_path, cache=cache, cache_enabled=False)
+        asyncio.run(
+            client.load_policy(
+                "gateway_authorization",
+                """
+                package sark.gateway
+                default allow = false
+                allow {
+                    input.user.role == "admin"
+                }
+                """,
+            )
+        )
+
+        def work(index):
+            role = "admin" if index % 2 == 0 else "viewer"
+            decisions = []
+            for _ in range(50):
+                decision = asyncio.run(
+                    client.evaluate_policy(
+                        AuthorizationInput(
+                            user={"id": f"user{index}", "role": role},
+                            action="gateway:tool:invoke",
+                            tool={"name": "db-query"},
+                            context={},
+                        ),
+                        use_cache=False,
+                    )
+                )
+                decisions.append(decision.allow)
+            return decisions
+
+        results = run_in_threads(work)
+        for index, decisions in enumerate(results):
+            assert decisions == [index % 2 == 0] * 50
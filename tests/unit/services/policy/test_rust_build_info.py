@@ -0,0 +1,31 @@
+"""Tests for sark_rust.build_info()."""
+
+import pytest
+
+from sark._rust import RUST_AVAILABLE
+
+pytestmark = pytest.mark.skipif(not RUST_AVAILABLE, reason="Rust extensions not available")
+
+
+def test_build_info_fields():
+    from sark._rust import build_info
+
+    info = build_info()
+
+    assert set(info) == {"version", "git_sha", "profile", "rustc", "regorus", "features"}
+    assert info["version"]
+    assert info["profile"] in {"debug", "release"}
+    assert info["rustc"].startswith("rustc ")
+    assert info["regorus"] != "unknown"
+
+
+def test_build_info_features():
+    from sark._rust import build_info
+
+    features = build_info()["features"]
+
+    assert all(isinstance(enabled, bool) for enabled in features.values())
+    assert features["shared_cache"] is True
+    assert features["wasm"] is False
+    assert {"redis", "wasm", "tracing"} <= set(features)
+
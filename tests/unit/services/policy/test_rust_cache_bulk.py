@@ -0,0 +1,45 @@
+"""Tests for RustCache's bulk operations."""
+
+import time
+
+
+def test_set_many_then_get_many(make_cache):
+    cache = make_cache(max_size=10_000, ttl_secs=60)
+    cache.set_many({f"k{i}": str(i) for i in range(1000)})
+
+    found = cache.get_many(["k1", "k999", "missing"])
+
+    assert found == {"k1": "1", "k999": "999"}
+    assert cache.size() == 1000
+
+
+def test_get_many_counts_each_lookup(cache):
+    cache.set("a", "1")
+
+    cache.get_many(["a", "a", "b"])
+
+    stats = cache.stats()
+    assert (stats.hits, stats.misses) == (2, 1)
+
+
+def test_set_many_with_ttl(cache):
+    cache.set_many({"a": "1", "b": "2"}, ttl=1)
+    time.sleep(1.1)
+
+    assert cache.get_many(["a", "b"]) == {}
+
+
+def test_delete_many_returns_how_many_were_there(cache):
+    cache.set_many({"a": "1", "b": "2", "c": "3"})
+
+    assert cache.delete_many(["a", "b", "missing"]) == 2
+    assert cache.get_many(["a", "b", "c"]) == {"c": "3"}
+
+
+def test_set_many_versions_every_entry(cache):
+    cache.set_many({"a": "1", "b": "2"})
+
+    _, a = cache.get_versioned("a")
+    _, b = cache.get_versioned("b")
+
+    assert a != b
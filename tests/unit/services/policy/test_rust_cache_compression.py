@@ -0,0 +1,65 @@
+"""Tests for compressing large RustCache values."""
+
+import json
+import pickle
+
+DECISION = json.dumps(
+    {"allow": True, "filtered_parameters": {f"field_{i}": "redacted" for i in range(500)}}
+)
+
+
+def test_large_values_come_back_as_set(make_cache):
+    cache = make_cache(max_size=100, ttl_secs=60, compress_threshold=1024)
+    cache.set("text", DECISION)
+    cache.set("raw", DECISION.encode())
+
+    assert cache.get("text") == DECISION
+    assert cache.get("raw") == DECISION.encode()
+    assert cache.stats().compressions == 2
+
+
+def test_small_values_are_not_compressed(make_cache):
+    cache = make_cache(max_size=100, ttl_secs=60, compress_threshold=1024)
+    cache.set("a", "x" * 100)
+
+    assert cache.get("a") == "x" * 100
+    assert cache.stats().compressions == 0
+
+
+def test_compressed_values_count_at_their_compressed_size(make_cache):
+    plain = make_cache(max_size=100, ttl_secs=60)
+    compressed = make_cache(max_size=100, ttl_secs=60, compress_threshold=1024)
+    plain.set("a", DECISION)
+    compressed.set("a", DECISION)
+
+    saved = compressed.stats().compression_saved_bytes
+    assert saved > 0
+    assert compressed.memory_usage() == plain.memory_usage() - saved
+
+
+def test_pickled_values_are_compressed(make_cache):
+    cache = make_cache(
+        max_size=100, ttl_secs=60, serializer="pickle", compress_threshold=1024
+    )
+    value = {"rows": [f"field_{i}" for i in range(1000)]}
+    cache.set("a", value)
+
+    assert cache.get("a") == value
+    assert cache.stats().compressions == 1
+
+
+def test_without_a_threshold_nothing_is_compressed(cache):
+    cache.set("a", DECISION)
+
+    assert cache.get("a") == DECISION
+    stats = cache.stats()
+    assert (stats.compressions, stats.compression_saved_bytes) == (0, 0)
+
+
+def test_compressed_entries_survive_pickling(make_cache):
+    cache = make_cache(max_size=100, ttl_secs=60, compress_threshold=1024)
+    cache.set("a", DECISION)
+
+    copy = pickle.loads(pickle.dumps(cache))
+    assert copy.get("a") == DECISION
+    assert copy.stats().compressions == 1
@@ -0,0 +1,88 @@
+"""Tests for RustCache.get_or_compute."""
+
+import threading
+import time
+
+import pytest
+
+from sark._rust import SarkCacheError
+
+
+def test_computes_a_missing_key_once(cache):
+    calls = []
+
+    def compute():
+        calls.append(1)
+        return "allow"
+
+    assert cache.get_or_compute("decision", compute) == "allow"
+    assert cache.get_or_compute("decision", compute) == "allow"
+    assert cache.get("decision") == "allow"
+    assert len(calls) == 1
+
+
+def test_computed_values_take_the_ttl(cache):
+    cache.get_or_compute("decision", lambda: "allow", ttl=5)
+
+    assert cache.ttl("decision") <= 5
+
+
+def test_concurrent_callers_share_one_computation(cache):
+    calls = []
+    start = threading.Barrier(8)
+
+    def compute():
+        calls.append(1)
+        time.sleep(0.2)
+        return "allow"
+
+    results = []
+
+    def call():
+        start.wait()
+        results.append(cache.get_or_compute("decision", compute))
+
+    threads = [threading.Thread(target=call) for _ in range(8)]
+    for thread in threads:
+        thread.start()
+    for thread in threads:
+        thread.join()
+
+    assert results == ["allow"] * 8
+    assert len(calls) == 1
+
+
+def test_errors_propagate_and_are_not_cached(cache):
+    def broken():
+        raise RuntimeError("policy unavailable")
+
+    with pytest.raises(RuntimeError):
+        cache.get_or_compute("decision", broken)
+    assert cache.get_or_compute("decision", lambda: "deny") == "deny"
+
+
+def test_a_waiter_computes_when_the_first_call_fails(cache):
+    entered = threading.Event()
+
+    def broken():
+        entered.set()
+        time.sleep(0.2)
+        raise RuntimeError("policy unavailable")
+
+    def first():
+        with pytest.raises(RuntimeError):
+            cache.get_or_compute("decision", broken)
+
+    thread = threading.Thread(target=first)
+    thread.start()
+    entered.wait()
+    assert cache.get_or_compute("decision", lambda: "deny") == "deny"
+    thread.join()
+
+
+def test_computing_the_same_key_again_raises(cache):
+    def compute():
+        return cache.get_or_compute("decision", lambda: "allow")
+
+    with pytest.raises(SarkCacheError):
+        cache.get_or_compute("decision", compute)
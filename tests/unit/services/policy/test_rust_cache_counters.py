@@ -0,0 +1,76 @@
+"""Tests for RustCache's atomic counters."""
+
+import threading
+import time
+
+import pytest
+
+from sark._rust import SarkCacheError
+
+
+def test_incr_starts_from_zero(cache):
+    assert cache.incr("count") == 1
+    assert cache.incr("count", 5) == 6
+    assert cache.decr("count") == 5
+    assert cache.decr("count", 10) == -5
+    assert cache.get("count") == "-5"
+
+
+def test_decr_starts_from_zero(cache):
+    assert cache.decr("count", 3) == -3
+
+
+def test_incr_counts_on_an_integer_value(cache):
+    cache.set("count", "41")
+
+    assert cache.incr("count") == 42
+
+
+def test_incr_rejects_other_values(cache):
+    cache.set("name", "alice")
+
+    with pytest.raises(SarkCacheError):
+        cache.incr("name")
+    assert cache.get("name") == "alice"
+
+
+def test_incr_rejects_overflow(cache):
+    cache.set("count", str(2**63 - 1))
+
+    with pytest.raises(SarkCacheError):
+        cache.incr("count")
+
+
+def test_counter_keeps_its_first_expiry(cache):
+    cache.incr("count", ttl=2)
+    time.sleep(1.1)
+    cache.incr("count", ttl=60)
+
+    assert cache.ttl("count") == 1
+    time.sleep(1.0)
+    assert cache.get("count") is None
+    assert cache.incr("count") == 1
+
+
+def test_incr_keeps_a_pin(make_cache):
+    cache = make_cache(max_size=2, ttl_secs=60)
+    cache.set_pinned("count", "0")
+    cache.incr("count")
+    cache.set("a", "x")
+    cache.set("b", "x")
+
+    assert cache.get("count") == "1"
+
+
+def test_concurrent_increments_are_not_lost(cache):
+    def bump():
+        for _ in range(1000):
+            cache.incr("count")
+
+    threads = [threading.Thread(target=bump) for _ in range(8)]
+    for thread in threads:
+        thread.start()
+    for thread in threads:
+        thread.join()
+
+    assert cache.get("count") == "8000"
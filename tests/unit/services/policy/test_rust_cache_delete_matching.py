@@ -0,0 +1,37 @@
+"""Tests for deleting RustCache keys by prefix and pattern."""
+
+
+def _fill(cache):
+    for key in ("server:db01:read", "server:db01:write", "server:db02:read", "user:1"):
+        cache.set(key, "allow")
+
+
+def test_delete_prefix(cache):
+    _fill(cache)
+
+    assert cache.delete_prefix("server:db01:") == 2
+    assert sorted(cache.keys()) == ["server:db02:read", "user:1"]
+    assert cache.delete_prefix("missing:") == 0
+
+
+def test_delete_matching_star(cache):
+    _fill(cache)
+
+    assert cache.delete_matching("server:*:read") == 2
+    assert sorted(cache.keys()) == ["server:db01:write", "user:1"]
+
+
+def test_delete_matching_question_mark(cache):
+    _fill(cache)
+
+    assert cache.delete_matching("server:db0?:write") == 1
+    assert cache.delete_matching("user:?") == 1
+    assert cache.delete_matching("user:??") == 0
+    assert len(cache) == 2
+
+
+def test_delete_matching_is_anchored(cache):
+    _fill(cache)
+
+    assert cache.delete_matching("db01") == 0
+    assert cache.delete_matching("*db01*") == 2
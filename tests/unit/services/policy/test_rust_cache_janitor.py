@@ -0,0 +1,58 @@
+"""Tests for RustCache's background sweep of expired entries."""
+
+import time
+
+import pytest
+
+
+def test_janitor_sweeps_expired_entries(cache):
+    cache.set("short", "1", ttl=1)
+    cache.set("long", "2")
+    cache.start_janitor(0.1)
+    try:
+        time.sleep(1.5)
+        assert cache.size() == 1
+        assert cache.stats().expirations == 1
+    finally:
+        assert cache.stop_janitor()
+
+
+def test_janitor_reports_to_listeners(cache):
+    removed = []
+    cache.add_listener(lambda *event: removed.append(event))
+    cache.set("short", "1", ttl=1)
+    cache.start_janitor(0.1)
+    try:
+        time.sleep(1.5)
+    finally:
+        cache.stop_janitor()
+
+    assert removed == [("short", "1", "expired")]
+
+
+def test_stopped_janitor_leaves_expired_entries(cache):
+    cache.start_janitor(0.1)
+    cache.stop_janitor()
+    cache.set("short", "1", ttl=1)
+    time.sleep(1.3)
+
+    assert cache.size() == 1
+    assert not cache.stop_janitor()
+
+
+def test_restarting_replaces_the_janitor(cache):
+    cache.start_janitor(60)
+    cache.start_janitor(0.1)
+    cache.set("short", "1", ttl=1)
+    try:
+        time.sleep(1.5)
+        assert cache.size() == 0
+    finally:
+        assert cache.stop_janitor()
+    assert not cache.stop_janitor()
+
+
+@pytest.mark.parametrize("interval", [0, -1, float("nan")])
+def test_interval_must_be_positive(cache, interval):
+    with pytest.raises(ValueError):
+        cache.start_janitor(interval)
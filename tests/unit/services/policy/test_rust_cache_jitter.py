@@ -0,0 +1,43 @@
+"""Tests for jittering RustCache's TTLs."""
+
+import pickle
+
+import pytest
+
+
+def test_ttls_spread_within_the_jitter(make_cache):
+    cache = make_cache(max_size=1000, ttl_secs=1000, ttl_jitter_pct=10)
+    for i in range(200):
+        cache.set(f"k{i}", "v")
+
+    ttls = {cache.ttl(f"k{i}") for i in range(200)}
+    assert len(ttls) > 1
+    assert all(900 <= ttl <= 1101 for ttl in ttls)
+
+
+def test_own_ttls_are_jittered_too(make_cache):
+    cache = make_cache(max_size=1000, ttl_secs=60, ttl_jitter_pct=50)
+    for i in range(200):
+        cache.set(f"k{i}", "v", ttl=100)
+
+    ttls = {cache.ttl(f"k{i}") for i in range(200)}
+    assert len(ttls) > 1
+    assert all(50 <= ttl <= 151 for ttl in ttls)
+
+
+def test_no_jitter_by_default(cache):
+    for i in range(20):
+        cache.set(f"k{i}", "v")
+
+    assert {cache.ttl(f"k{i}") for i in range(20)} == {60}
+
+
+def test_jitter_must_be_a_percentage(make_cache):
+    with pytest.raises(ValueError):
+        make_cache(max_size=10, ttl_secs=60, ttl_jitter_pct=101)
+
+
+def test_jitter_survives_pickling(make_cache):
+    cache = make_cache(max_size=10, ttl_secs=60, ttl_jitter_pct=20)
+
+    assert pickle.loads(pickle.dumps(cache)).__getnewargs_ex__()[1]["ttl_jitter_pct"] == 20
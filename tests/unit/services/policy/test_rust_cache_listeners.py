@@ -0,0 +1,87 @@
+"""Tests for RustCache's eviction and expiry listeners."""
+
+import time
+
+
+def test_evictions_are_reported(make_cache):
+    cache = make_cache(max_size=2, ttl_secs=60)
+    removed = []
+    cache.add_listener(lambda *event: removed.append(event))
+    cache.set("a", "1")
+    cache.set("b", b"2")
+    cache.set("c", "3")
+    cache.resize(1)
+
+    assert removed == [("a", "1", "evicted"), ("b", b"2", "evicted")]
+
+
+def test_expirations_are_reported(cache):
+    removed = []
+    cache.add_listener(lambda *event: removed.append(event))
+    cache.set("looked_up", "1", ttl=1)
+    cache.set("swept", "2", ttl=1)
+    time.sleep(1.1)
+
+    assert cache.get("looked_up") is None
+    cache.cleanup_expired()
+    assert sorted(removed) == [("looked_up", "1", "expired"), ("swept", "2", "expired")]
+
+
+def test_deletes_are_not_reported(cache):
+    removed = []
+    cache.add_listener(lambda *event: removed.append(event))
+    cache.set("a", "1", tags=["t"])
+    cache.set("b", "2")
+    cache.delete("b")
+    cache.invalidate_tag("t")
+    cache.clear()
+
+    assert removed == []
+
+
+def test_listeners_may_use_the_cache(make_cache):
+    cache = make_cache(max_size=1, ttl_secs=60)
+    seen = []
+    cache.add_listener(lambda key, value, cause: seen.append(cache.get("b")))
+    mirror = make_cache(max_size=10, ttl_secs=60)
+    cache.add_listener(lambda key, value, cause: mirror.set(key, value))
+    cache.set("a", "1")
+    cache.set("b", "2")
+
+    assert seen == ["2"]
+    assert mirror.get("a") == "1"
+
+
+def test_listener_errors_do_not_fail_the_operation(make_cache, monkeypatch):
+    cache = make_cache(max_size=1, ttl_secs=60)
+    unraisable = []
+    monkeypatch.setattr("sys.unraisablehook", unraisable.append)
+
+    def broken(key, value, cause):
+        raise RuntimeError("listener failed")
+
+    removed = []
+    cache.add_listener(broken)
+    cache.add_listener(lambda *event: removed.append(event))
+    cache.set("a", "1")
+    cache.set("b", "2")
+
+    assert cache.get("b") == "2"
+    assert removed == [("a", "1", "evicted")]
+    assert isinstance(unraisable[0].exc_value, RuntimeError)
+
+
+def test_removed_listeners_are_not_called(make_cache):
+    cache = make_cache(max_size=1, ttl_secs=60)
+    removed = []
+
+    def listener(*event):
+        removed.append(event)
+
+    cache.add_listener(listener)
+    assert cache.remove_listener(listener)
+    assert not cache.remove_listener(listener)
+    cache.set("a", "1")
+    cache.set("b", "2")
+
+    assert removed == []
@@ -0,0 +1,57 @@
+"""Tests for RustCache's dict-like protocol."""
+
+import time
+
+import pytest
+
+
+def test_item_access(cache):
+    cache["a"] = "1"
+
+    assert cache["a"] == "1"
+    assert "a" in cache
+    assert "b" not in cache
+    with pytest.raises(KeyError):
+        cache["b"]
+
+
+def test_del(cache):
+    cache["a"] = "1"
+
+    del cache["a"]
+
+    assert "a" not in cache
+    with pytest.raises(KeyError):
+        del cache["a"]
+
+
+def test_len_keys_items_and_iteration(cache):
+    cache["a"] = "1"
+    cache["b"] = "2"
+    cache.set("gone", "3", ttl=1)
+    time.sleep(1.1)
+
+    assert len(cache) == 2
+    assert sorted(cache.keys()) == ["a", "b"]
+    assert sorted(cache.items()) == [("a", "1"), ("b", "2")]
+    assert sorted(cache) == ["a", "b"]
+    assert dict(cache.items()) == {"a": "1", "b": "2"}
+
+
+def test_iteration_is_a_snapshot(cache):
+    cache["a"] = "1"
+    cache["b"] = "2"
+
+    for key in cache:
+        del cache[key]
+
+    assert len(cache) == 0
+
+
+def test_expired_key_is_missing(cache):
+    cache.set("a", "1", ttl=1)
+    time.sleep(1.1)
+
+    assert "a" not in cache
+    with pytest.raises(KeyError):
+        cache["a"]
@@ -0,0 +1,75 @@
+"""Tests for bounding RustCache by bytes."""
+
+import pickle
+
+import pytest
+
+from sark._rust import SarkCacheError
+
+
+def weigh_ten(key, value):
+    return 10
+
+
+def test_memory_usage_counts_keys_and_values(cache):
+    assert cache.memory_usage() == 0
+
+    cache.set("a", "x" * 100)
+    assert cache.memory_usage() > 100
+
+    cache.delete("a")
+    assert cache.memory_usage() == 0
+
+
+def test_max_bytes_evicts_the_least_recently_used(make_cache):
+    cache = make_cache(max_size=100, ttl_secs=60, max_bytes=250)
+    cache.set("a", "x" * 100)
+    cache.set("b", "x" * 100)
+    cache.get("a")
+
+    cache.set("c", "x" * 100)
+    assert cache.get("a") is not None
+    assert cache.get("b") is None
+    assert cache.get("c") is not None
+    assert cache.memory_usage() <= 250
+
+
+def test_value_larger_than_max_bytes_raises(make_cache):
+    cache = make_cache(max_size=100, ttl_secs=60, max_bytes=50)
+    cache.set("a", "x")
+
+    with pytest.raises(SarkCacheError):
+        cache.set("b", "x" * 100)
+    assert cache.get("a") == "x"
+
+
+def test_weigher_says_what_a_value_weighs(make_cache):
+    cache = make_cache(max_size=100, ttl_secs=60, max_bytes=25, weigher=weigh_ten)
+    cache.set("a", "x" * 1000)
+    assert cache.memory_usage() == 11
+
+    cache.set("b", "x")
+    cache.set("c", "x")
+    assert cache.get("a") is None
+    assert cache.size() == 2
+
+
+def test_weigher_errors_propagate(make_cache):
+    def broken(key, value):
+        raise ValueError("no weight")
+
+    cache = make_cache(max_size=100, ttl_secs=60, weigher=broken)
+    with pytest.raises(ValueError):
+        cache.set("a", "x")
+    assert cache.get("a") is None
+
+
+def test_limit_and_weigher_survive_pickling(make_cache):
+    cache = make_cache(max_size=100, ttl_secs=60, max_bytes=25, weigher=weigh_ten)
+    cache.set("a", "x")
+
+    copy = pickle.loads(pickle.dumps(cache))
+    assert copy.memory_usage() == 11
+    copy.set("b", "x")
+    copy.set("c", "x")
+    assert copy.size() == 2
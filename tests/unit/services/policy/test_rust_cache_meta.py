@@ -0,0 +1,50 @@
+"""Tests for per-entry metadata in RustCache."""
+
+import pickle
+
+import pytest
+
+META = {"policy_revision": "r42", "evaluated_at": 1760400000.5, "input_hash": "ab12"}
+
+
+def test_meta_comes_back_with_the_value(cache):
+    cache.set("decision", "allow", meta=META)
+
+    assert cache.get_with_meta("decision") == ("allow", META)
+    assert cache.get("decision") == "allow"
+
+
+def test_entries_set_without_meta_have_none(cache):
+    cache.set("decision", "allow")
+
+    assert cache.get_with_meta("decision") == ("allow", None)
+    assert cache.get_with_meta("missing") is None
+
+
+def test_setting_again_replaces_the_meta(cache):
+    cache.set("decision", "allow", meta=META)
+    cache.set("decision", "deny")
+
+    assert cache.get_with_meta("decision") == ("deny", None)
+
+
+def test_meta_must_be_json_like(cache):
+    with pytest.raises(Exception):
+        cache.set("decision", "allow", meta={"when": object()})
+    assert cache.get("decision") is None
+
+
+def test_meta_counts_against_max_bytes(make_cache):
+    cache = make_cache(max_size=100, ttl_secs=60)
+    cache.set("a", "x")
+    plain = cache.memory_usage()
+    cache.set("a", "x", meta=META)
+
+    assert cache.memory_usage() > plain
+
+
+def test_meta_is_not_pickled(cache):
+    cache.set("decision", "allow", meta=META)
+
+    copy = pickle.loads(pickle.dumps(cache))
+    assert copy.get_with_meta("decision") == ("allow", None)
@@ -0,0 +1,50 @@
+"""Tests for caching negative results in RustCache."""
+
+import pickle
+
+
+def test_probe_tells_a_negative_from_a_miss(cache):
+    cache.set_negative("denied")
+    cache.set("allowed", "allow")
+
+    assert cache.probe("denied") == (True, None)
+    assert cache.probe("allowed") == (True, "allow")
+    assert cache.probe("missing") == (False, None)
+    assert cache.get("denied") is None
+
+
+def test_negatives_take_their_own_ttl(make_cache):
+    cache = make_cache(max_size=10, ttl_secs=300, negative_ttl_secs=30)
+    cache.set_negative("denied")
+    cache.set_negative("briefly", ttl=5)
+    cache.set("allowed", "allow")
+
+    assert cache.ttl("denied") == 30
+    assert cache.ttl("briefly") == 5
+    assert cache.ttl("allowed") == 300
+
+
+def test_negatives_default_to_the_cache_ttl(cache):
+    cache.set_negative("denied")
+
+    assert cache.ttl("denied") == 60
+
+
+def test_setting_none_caches_a_negative(cache):
+    cache["denied"] = None
+
+    assert cache.probe("denied") == (True, None)
+    assert "denied" in cache
+
+
+def test_a_value_replaces_a_negative(cache):
+    cache.set_negative("key")
+    cache.set("key", "allow")
+
+    assert cache.probe("key") == (True, "allow")
+
+
+def test_negatives_survive_pickling(cache):
+    cache.set_negative("denied")
+
+    assert pickle.loads(pickle.dumps(cache)).probe("denied") == (True, None)
@@ -0,0 +1,73 @@
+"""Tests for pickling RustCache with its entries."""
+
+import multiprocessing
+import pickle
+import time
+
+
+def _read_in_child(cache, key, queue):
+    queue.put(cache.get(key))
+
+
+def test_round_trip_keeps_entries_and_limits(cache):
+    cache.set("a", "1")
+    cache.set("b", '{"allow": true}', ttl=300)
+
+    restored = pickle.loads(pickle.dumps(cache))
+
+    assert restored.get("a") == "1"
+    assert restored.get("b") == '{"allow": true}'
+    stats = restored.stats()
+    assert (stats.max_size, stats.ttl_secs) == (100, 60)
+    assert stats.size == 2
+
+
+def test_remaining_ttl_is_kept(cache):
+    cache.set("short", "x", ttl=2)
+    cache.set("long", "y")
+    time.sleep(1.1)
+
+    state = dict((key, ttl) for key, _, ttl in cache.__getstate__())
+    assert state["short"] <= 1
+    assert 58 <= state["long"] <= 60
+
+    restored = pickle.loads(pickle.dumps(cache))
+    time.sleep(1.1)
+    assert restored.get("short") is None
+    assert restored.get("long") == "y"
+
+
+def test_deleted_cleared_and_expired_entries_are_not_pickled(cache):
+    cache.set("kept", "1")
+    cache.set("deleted", "2")
+    cache.set("expired", "3", ttl=1)
+    cache.delete("deleted")
+    time.sleep(1.1)
+
+    assert [key for key, _, _ in cache.__getstate__()] == ["kept"]
+
+    cache.clear()
+    assert cache.__getstate__() == []
+
+
+def test_evicted_entries_are_not_pickled(make_cache):
+    cache = make_cache(max_size=10, ttl_secs=60)
+    for i in range(50):
+        cache.set(f"k{i}", str(i))
+
+    entries = cache.__getstate__()
+    assert len(entries) <= 10
+    for key, value, _ in entries:
+        assert key == f"k{value}"
+
+
+def test_spawned_process_starts_warm(cache):
+    cache.set("decision", "allow")
+
+    ctx = multiprocessing.get_context("spawn")
+    queue = ctx.Queue()
+    child = ctx.Process(target=_read_in_child, args=(cache, "decision", queue))
+    child.start()
+    child.join(timeout=30)
+
+    assert queue.get(timeout=5) == "allow"
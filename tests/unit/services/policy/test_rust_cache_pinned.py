@@ -0,0 +1,64 @@
+"""Tests for RustCache's pinned entries."""
+
+import pickle
+
+import pytest
+
+from sark._rust import SarkCacheError
+
+
+def test_pinned_entries_are_never_evicted(make_cache):
+    cache = make_cache(max_size=4, ttl_secs=60)
+    cache.set_pinned("quota", "1")
+    for key in ("a", "b", "c", "d", "e"):
+        cache.set(key, "x")
+
+    assert cache.size() == 4
+    assert cache.get("quota") == "1"
+    assert cache.get("a") is None
+    assert cache.get("e") == "x"
+
+
+def test_pinning_is_capped_by_max_pinned(make_cache):
+    cache = make_cache(max_size=10, ttl_secs=60, max_pinned=2)
+    cache.set_pinned("p", "1")
+    cache.set_pinned("q", "1")
+
+    with pytest.raises(SarkCacheError):
+        cache.set_pinned("r", "1")
+
+    # Updating a pinned entry takes no more room, and deleting one frees it
+    cache.set_pinned("p", "2")
+    cache.delete("p")
+    cache.set_pinned("r", "1")
+
+
+def test_pinning_is_capped_by_half_the_size(make_cache):
+    cache = make_cache(max_size=4, ttl_secs=60, max_pinned=100)
+    cache.set_pinned("p", "1")
+    cache.set_pinned("q", "1")
+
+    with pytest.raises(SarkCacheError):
+        cache.set_pinned("r", "1")
+
+
+def test_set_unpins(make_cache):
+    cache = make_cache(max_size=2, ttl_secs=60, max_pinned=1)
+    cache.set_pinned("p", "1")
+    cache.set("p", "2")
+    cache.set("a", "x")
+    cache.set("b", "x")
+
+    assert cache.get("p") is None
+    cache.set_pinned("q", "1")
+
+
+def test_max_pinned_survives_pickling(make_cache):
+    cache = make_cache(max_size=10, ttl_secs=60, max_pinned=1)
+    cache.set_pinned("p", "1")
+
+    restored = pickle.loads(pickle.dumps(cache))
+    assert restored.get("p") == "1"
+    restored.set_pinned("q", "1")
+    with pytest.raises(SarkCacheError):
+        restored.set_pinned("r", "1")
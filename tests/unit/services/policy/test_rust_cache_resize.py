@@ -0,0 +1,49 @@
+"""Tests for resizing RustCache in place."""
+
+import pickle
+
+
+def test_shrinking_evicts_the_least_recently_used(make_cache):
+    cache = make_cache(max_size=4, ttl_secs=60)
+    for key in ("a", "b", "c", "d"):
+        cache.set(key, "x")
+    cache.get("a")
+
+    assert cache.resize(2) == 2
+    assert cache.size() == 2
+    assert cache.get("a") == "x"
+    assert cache.get("b") is None
+    assert cache.get("c") is None
+    assert cache.get("d") == "x"
+
+    stats = cache.stats()
+    assert (stats.max_size, stats.evictions) == (2, 2)
+
+
+def test_growing_makes_room(make_cache):
+    cache = make_cache(max_size=2, ttl_secs=60)
+    cache.set("a", "x")
+    cache.set("b", "x")
+
+    assert cache.resize(4) == 0
+    cache.set("c", "x")
+    cache.set("d", "x")
+    assert cache.size() == 4
+
+
+def test_shrinking_keeps_pinned_entries(make_cache):
+    cache = make_cache(max_size=4, ttl_secs=60)
+    cache.set_pinned("p", "1")
+    cache.set_pinned("q", "1")
+    cache.set("a", "x")
+    cache.set("b", "x")
+
+    assert cache.resize(1) == 2
+    assert (cache.get("p"), cache.get("q")) == ("1", "1")
+
+
+def test_new_size_survives_pickling(make_cache):
+    cache = make_cache(max_size=4, ttl_secs=60)
+    cache.resize(8)
+
+    assert pickle.loads(pickle.dumps(cache)).stats().max_size == 8
@@ -0,0 +1,64 @@
+"""Tests for RustCache's sliding TTLs."""
+
+import pickle
+import time
+
+
+def test_lookups_keep_entries_alive(make_cache):
+    cache = make_cache(max_size=10, ttl_secs=60, sliding=True)
+    cache.set("session", "state", ttl=2)
+
+    for _ in range(3):
+        time.sleep(0.8)
+        assert cache.get("session") == "state"
+
+    time.sleep(2.1)
+    assert cache.get("session") is None
+
+
+def test_each_entry_slides_by_its_own_ttl(make_cache):
+    cache = make_cache(max_size=10, ttl_secs=60, sliding=True)
+    cache.set("short", "x", ttl=2)
+    cache.set("long", "y")
+    time.sleep(1.1)
+
+    cache.get_many(["short", "long"])
+
+    assert cache.ttl("short") == 2
+    assert cache.ttl("long") == 60
+
+
+def test_unread_entries_still_expire(make_cache):
+    cache = make_cache(max_size=10, ttl_secs=60, sliding=True)
+    cache.set("idle", "x", ttl=1)
+    time.sleep(1.1)
+
+    assert "idle" not in cache
+    assert cache.get("idle") is None
+
+
+def test_membership_and_ttl_dont_slide(make_cache):
+    cache = make_cache(max_size=10, ttl_secs=60, sliding=True)
+    cache.set("a", "x", ttl=2)
+    time.sleep(1.1)
+
+    assert "a" in cache
+    assert cache.ttl("a") == 1
+
+
+def test_fixed_ttls_by_default(cache):
+    cache.set("a", "x", ttl=2)
+    time.sleep(1.1)
+
+    cache.get("a")
+    assert cache.ttl("a") == 1
+
+
+def test_sliding_survives_pickling(make_cache):
+    cache = make_cache(max_size=10, ttl_secs=60, sliding=True)
+    cache.set("a", "x", ttl=2)
+
+    restored = pickle.loads(pickle.dumps(cache))
+    time.sleep(1.1)
+    restored.get("a")
+    assert restored.ttl("a") == 2
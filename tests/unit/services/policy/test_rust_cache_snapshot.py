@@ -0,0 +1,146 @@
+"""Tests for RustCache snapshots saved to and loaded from a file."""
+
+import struct
+import time
+
+import pytest
+
+from sark._rust import SarkCacheError
+
+
+def test_entries_survive_a_save_and_load(make_cache, tmp_path):
+    saved = make_cache(max_size=100, ttl_secs=60)
+    saved.set("text", "allow")
+    saved.set("raw", b"\x00\xff")
+    saved.set_negative("denied")
+    path = tmp_path / "cache.snapshot"
+
+    assert saved.save_snapshot(path) == 3
+
+    loaded = make_cache(max_size=100, ttl_secs=60)
+    assert loaded.load_snapshot(path) == 3
+    assert loaded.get("text") == "allow"
+    assert loaded.get("raw") == b"\x00\xff"
+    assert loaded.probe("denied") == (True, None)
+
+
+def test_remaining_ttls_are_kept(make_cache, tmp_path):
+    saved = make_cache(max_size=100, ttl_secs=60)
+    saved.set("short", "a", ttl=5)
+    saved.set("long", "b", ttl=3600)
+    path = tmp_path / "cache.snapshot"
+    saved.save_snapshot(path)
+
+    loaded = make_cache(max_size=100, ttl_secs=60)
+    loaded.load_snapshot(path)
+
+    assert 0 < loaded.ttl("short") <= 5
+    assert 3590 < loaded.ttl("long") <= 3600
+
+
+def test_entries_expired_since_the_save_are_dropped(make_cache, tmp_path):
+    saved = make_cache(max_size=100, ttl_secs=60)
+    saved.set("brief", "a", ttl=1)
+    saved.set("kept", "b")
+    path = tmp_path / "cache.snapshot"
+    saved.save_snapshot(path)
+    time.sleep(2.1)
+
+    loaded = make_cache(max_size=100, ttl_secs=60)
+
+    assert loaded.load_snapshot(path) == 1
+    assert loaded.get("brief") is None
+    assert loaded.get("kept") == "b"
+
+
+def test_an_old_save_time_counts_against_the_ttls(make_cache, tmp_path):
+    saved = make_cache(max_size=100, ttl_secs=60)
+    saved.set("a", "x", ttl=100)
+    path = tmp_path / "cache.snapshot"
+    saved.save_snapshot(path)
+    data = bytearray(path.read_bytes())
+    (saved_at,) = struct.unpack_from("<Q", data, 8)
+    struct.pack_into("<Q", data, 8, saved_at - 60)
+    path.write_bytes(bytes(data))
+
+    loaded = make_cache(max_size=100, ttl_secs=60)
+    loaded.load_snapshot(path)
+
+    assert 30 < loaded.ttl("a") <= 40
+
+
+def test_tags_and_meta_are_kept(make_cache, tmp_path):
+    saved = make_cache(max_size=100, ttl_secs=60)
+    saved.set("a", "x", tags=["user:1"], meta={"policy_revision": "r1"})
+    path = tmp_path / "cache.snapshot"
+    saved.save_snapshot(path)
+
+    loaded = make_cache(max_size=100, ttl_secs=60)
+    loaded.load_snapshot(path)
+
+    assert loaded.get_with_meta("a") == ("x", {"policy_revision": "r1"})
+    assert loaded.invalidate_tag("user:1") == 1
+
+
+def test_loads_into_a_cache_compressing_differently(make_cache, tmp_path):
+    saved = make_cache(max_size=100, ttl_secs=60, compress_threshold=64)
+    saved.set("big", "a" * 4096)
+    path = tmp_path / "cache.snapshot"
+    saved.save_snapshot(path)
+
+    loaded = make_cache(max_size=100, ttl_secs=60)
+    loaded.load_snapshot(path)
+
+    assert loaded.get("big") == "a" * 4096
+    assert loaded.stats().compressions == 0
+
+
+def test_pickled_values_load_into_a_pickling_cache(make_cache, tmp_path):
+    saved = make_cache(max_size=100, ttl_secs=60, serializer="pickle")
+    saved.set("decision", {"allow": True})
+    path = tmp_path / "cache.snapshot"
+    saved.save_snapshot(path)
+
+    loaded = make_cache(max_size=100, ttl_secs=60, serializer="pickle")
+    loaded.load_snapshot(path)
+
+    assert loaded.get("decision") == {"allow": True}
+
+
+def test_a_failed_save_leaves_the_last_snapshot(cache, tmp_path):
+    path = tmp_path / "cache.snapshot"
+    cache.set("a", "x")
+    cache.save_snapshot(path)
+    before = path.read_bytes()
+
+    with pytest.raises(SarkCacheError):
+        cache.save_snapshot(tmp_path / "missing" / "cache.snapshot")
+
+    assert path.read_bytes() == before
+
+
+def test_a_missing_file_raises(cache, tmp_path):
+    with pytest.raises(SarkCacheError, match="Failed to read cache snapshot"):
+        cache.load_snapshot(tmp_path / "missing.snapshot")
+
+
+@pytest.mark.parametrize("contents", [b"", b"not a snapshot", b"SARKRC\x00\x01\x00"])
+def test_other_files_raise(cache, tmp_path, contents):
+    path = tmp_path / "cache.snapshot"
+    path.write_bytes(contents)
+
+    with pytest.raises(SarkCacheError, match="is not a RustCache snapshot"):
+        cache.load_snapshot(path)
+
+
+def test_a_truncated_snapshot_raises_and_loads_nothing(cache, make_cache, tmp_path):
+    cache.set("a", "x")
+    cache.set("b", "y")
+    path = tmp_path / "cache.snapshot"
+    cache.save_snapshot(path)
+    path.write_bytes(path.read_bytes()[:-3])
+
+    loaded = make_cache(max_size=100, ttl_secs=60)
+    with pytest.raises(SarkCacheError):
+        loaded.load_snapshot(path)
+    assert len(loaded) == 0
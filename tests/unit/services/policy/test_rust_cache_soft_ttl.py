@@ -0,0 +1,38 @@
+"""Tests for RustCache's soft TTL."""
+
+import time
+
+
+def test_entries_go_stale_before_they_expire(make_cache):
+    cache = make_cache(max_size=10, ttl_secs=60, soft_ttl_secs=1)
+    cache.set("decision", "allow")
+    assert cache.get_stale("decision") == ("allow", False)
+
+    time.sleep(1.1)
+    assert cache.get_stale("decision") == ("allow", True)
+    assert cache.get("decision") == "allow"
+
+
+def test_setting_again_freshens_the_entry(make_cache):
+    cache = make_cache(max_size=10, ttl_secs=60, soft_ttl_secs=1)
+    cache.set("decision", "allow")
+    time.sleep(1.1)
+
+    cache.set("decision", "deny")
+    assert cache.get_stale("decision") == ("deny", False)
+
+
+def test_nothing_is_stale_without_a_soft_ttl(cache):
+    cache.set("decision", "allow", ttl=2)
+    time.sleep(1.1)
+
+    assert cache.get_stale("decision") == ("allow", False)
+
+
+def test_expired_entries_are_missing(make_cache):
+    cache = make_cache(max_size=10, ttl_secs=60, soft_ttl_secs=1)
+    cache.set("decision", "allow", ttl=1)
+    time.sleep(1.1)
+
+    assert cache.get_stale("decision") is None
+    assert cache.get_stale("missing") is None
@@ -0,0 +1,50 @@
+"""Tests for RustCache's statistics."""
+
+import time
+
+
+def test_counts_insertions_and_lookups(cache):
+    cache.set("a", "1")
+    cache.set("a", "2")
+    cache.get("a")
+    cache.get("missing")
+
+    stats = cache.stats()
+    assert stats.insertions == 2
+    assert (stats.hits, stats.misses) == (1, 1)
+    assert (stats.evictions, stats.expirations) == (0, 0)
+
+
+def test_counts_evictions_from_a_full_cache(make_cache):
+    cache = make_cache(max_size=2, ttl_secs=60)
+    for key in ("a", "b", "c", "d"):
+        cache.set(key, "x")
+    cache.set("d", "y")
+
+    stats = cache.stats()
+    assert stats.insertions == 5
+    assert stats.evictions == 2
+
+
+def test_counts_expirations_swept_and_overwritten(cache):
+    cache.set("swept", "1", ttl=1)
+    cache.set("overwritten", "2", ttl=1)
+    time.sleep(1.1)
+
+    cache.set("overwritten", "3")
+    cache.cleanup_expired()
+
+    stats = cache.stats()
+    assert stats.expirations == 2
+    assert stats.evictions == 0
+
+
+def test_memory_estimate_grows_with_entries(cache):
+    assert cache.stats().memory_bytes == 0
+
+    cache.set("a", "x" * 1000)
+    one = cache.stats().memory_bytes
+    cache.set("b", "x" * 1000)
+
+    assert one > 1000
+    assert cache.stats().memory_bytes == 2 * one
@@ -0,0 +1,31 @@
+"""Tests for RustCache's tag invalidation."""
+
+
+def test_invalidate_tag_drops_every_tagged_entry(cache):
+    cache.set("decision:1", "allow", tags=["user:123", "server:db01"])
+    cache.set("decision:2", "deny", tags=["user:123"])
+    cache.set("decision:3", "allow", tags=["user:456", "server:db01"])
+    cache.set("untagged", "allow")
+
+    assert cache.invalidate_tag("user:123") == 2
+
+    assert cache.get("decision:1") is None
+    assert cache.get("decision:2") is None
+    assert cache.get("decision:3") == "allow"
+    assert cache.get("untagged") == "allow"
+
+
+def test_invalidate_unknown_tag(cache):
+    cache.set("a", "1", tags=["user:123"])
+
+    assert cache.invalidate_tag("user:999") == 0
+    assert cache.get("a") == "1"
+
+
+def test_setting_again_replaces_the_tags(cache):
+    cache.set("a", "1", tags=["user:123"])
+    cache.set("a", "2", tags=["user:456"])
+
+    assert cache.invalidate_tag("user:123") == 0
+    assert cache.invalidate_tag("user:456") == 1
+
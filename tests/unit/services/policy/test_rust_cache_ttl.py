@@ -0,0 +1,54 @@
+"""Tests for RustCache's TTL introspection and refresh."""
+
+import time
+
+
+def test_ttl_of_held_entries(cache):
+    cache.set("default", "1")
+    cache.set("own", "2", ttl=10)
+
+    assert 59 <= cache.ttl("default") <= 60
+    assert 9 <= cache.ttl("own") <= 10
+
+
+def test_ttl_tells_an_expiry_from_a_key_never_set(cache):
+    cache.set("short", "1", ttl=1)
+    time.sleep(1.1)
+
+    assert cache.get("short") is None
+    assert cache.ttl("short") == 0
+    assert cache.ttl("never") is None
+
+    cache.cleanup_expired()
+    assert cache.ttl("short") is None
+
+
+def test_ttl_of_deleted_entry(cache):
+    cache.set("a", "1")
+    cache.delete("a")
+
+    assert cache.ttl("a") is None
+
+
+def test_touch_extends_without_rewriting(cache):
+    cache.set("hot", "1", ttl=1)
+    _, version = cache.get_versioned("hot")
+
+    assert cache.touch("hot", ttl=30) is True
+    time.sleep(1.1)
+
+    assert cache.get("hot") == "1"
+    assert 28 <= cache.ttl("hot") <= 30
+    assert cache.get_versioned("hot") == ("1", version)
+    assert cache.touch("missing") is False
+
+
+def test_expire_at(cache):
+    cache.set("a", "1")
+
+    assert cache.expire_at("a", time.time() + 10) is True
+    assert 9 <= cache.ttl("a") <= 11
+
+    assert cache.expire_at("a", time.time() - 1) is True
+    assert cache.get("a") is None
+    assert cache.expire_at("a", time.time() + 10) is False
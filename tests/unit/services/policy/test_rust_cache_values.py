@@ -0,0 +1,61 @@
+"""Tests for RustCache's bytes and pickled values."""
+
+import pickle
+
+import pytest
+
+
+def test_bytes_come_back_as_bytes(cache):
+    cache.set("raw", bytes(range(256)))
+    cache.set("text", "café")
+
+    assert cache.get("raw") == bytes(range(256))
+    assert cache.get("text") == "café"
+
+
+def test_str_with_a_control_character_prefix_round_trips(cache):
+    cache.set("a", "\x02not bytes")
+
+    assert cache.get("a") == "\x02not bytes"
+
+
+def test_other_objects_need_a_serializer(cache):
+    with pytest.raises(TypeError, match="serializer"):
+        cache.set("a", {"allow": True})
+
+
+def test_unknown_serializer_is_refused(make_cache):
+    with pytest.raises(ValueError, match="msgpack"):
+        make_cache(max_size=100, ttl_secs=60, serializer="msgpack")
+
+
+def test_pickle_serializer_round_trips_objects(make_cache):
+    cache = make_cache(max_size=100, ttl_secs=60, serializer="pickle")
+    decision = {"allow": True, "reasons": ["policy"], "ttl": 300}
+    cache.set("decision", decision)
+    cache.set_many({"t": (1, 2), "s": "plain"})
+
+    assert cache.get("decision") == decision
+    assert cache.get_many(["t", "s"]) == {"t": (1, 2), "s": "plain"}
+
+
+def test_versioned_entries_keep_their_type(make_cache):
+    cache = make_cache(max_size=100, ttl_secs=60, serializer="pickle")
+    assert cache.set_if_version("count", 1, None) is not None
+
+    value, version = cache.get_versioned("count")
+    assert value == 1
+    assert cache.set_if_version("count", value + 1, version) is not None
+    assert cache.get("count") == 2
+
+
+def test_pickling_keeps_serializer_and_values(make_cache):
+    cache = make_cache(max_size=100, ttl_secs=60, serializer="pickle")
+    cache.set("raw", b"\x00\xff")
+    cache.set("obj", [1, "two"])
+
+    restored = pickle.loads(pickle.dumps(cache))
+
+    assert restored.get("raw") == b"\x00\xff"
+    assert restored.get("obj") == [1, "two"]
+    restored.set("more", {"a": 1})
@@ -0,0 +1,74 @@
+"""Tests for RustCache's versioned compare-and-swap."""
+
+import threading
+
+
+def test_get_versioned_returns_value_and_version(cache):
+    cache.set("a", "1")
+
+    value, version = cache.get_versioned("a")
+
+    assert value == "1"
+    assert version > 0
+    assert cache.get_versioned("missing") is None
+
+
+def test_every_write_gives_a_new_version(cache):
+    cache.set("a", "1")
+    _, first = cache.get_versioned("a")
+    cache.set("a", "1")
+    _, second = cache.get_versioned("a")
+
+    assert second != first
+
+
+def test_set_if_version_matching(cache):
+    cache.set("a", "1")
+    _, version = cache.get_versioned("a")
+
+    new_version = cache.set_if_version("a", "2", version)
+
+    assert new_version is not None
+    assert cache.get_versioned("a") == ("2", new_version)
+
+
+def test_set_if_version_stale(cache):
+    cache.set("a", "1")
+    _, version = cache.get_versioned("a")
+    cache.set("a", "other writer")
+
+    assert cache.set_if_version("a", "2", version) is None
+    assert cache.get("a") == "other writer"
+
+
+def test_none_means_missing(cache):
+    assert cache.set_if_version("a", "1", None) is not None
+    assert cache.set_if_version("a", "2", None) is None
+    assert cache.get("a") == "1"
+
+
+def test_deleted_and_set_again_does_not_match(cache):
+    cache.set("a", "1")
+    _, version = cache.get_versioned("a")
+    cache.delete("a")
+    cache.set("a", "1")
+
+    assert cache.set_if_version("a", "2", version) is None
+
+
+def test_concurrent_increments_are_not_lost(cache):
+    def increment(times):
+        for _ in range(times):
+            while True:
+                entry = cache.get_versioned("count")
+                count, version = (int(entry[0]), entry[1]) if entry else (0, None)
+                if cache.set_if_version("count", str(count + 1), version) is not None:
+                    break
+
+    threads = [threading.Thread(target=increment, args=(200,)) for _ in range(8)]
+    for thread in threads:
+        thread.start()
+    for thread in threads:
+        thread.join()
+
+    assert cache.get("count") == "1600"
@@ -0,0 +1,75 @@
+"""Tests for CEL policy packages in RustOPAEngine."""
+
+import pytest
+
+from sark._rust import RUST_AVAILABLE
+
+pytestmark = pytest.mark.skipif(not RUST_AVAILABLE, reason="Rust extensions not available")
+
+PACKAGE = """
+package = "sark.cel"
+
+[rules]
+allow = '"admin" in input.user.roles'
+reason = '"admin" in input.user.roles ? "admin access" : "not an admin"'
+obligations = '{"log_level": "warn"}'
+"""
+
+
+@pytest.fixture
+def engine():
+    from sark._rust import RustOPAEngine
+
+    engine = RustOPAEngine()
+    engine.load_cel_policy("cel", PACKAGE)
+    return engine
+
+
+class TestCelPackages:
+    """Queries naming a CEL package are evaluated by it."""
+
+    def test_package_document(self, engine):
+        decision = engine.evaluate("data.sark.cel", {"user": {"roles": ["admin"]}})
+
+        assert decision.allow is True
+        assert decision.reason == "admin access"
+        assert decision.obligations == {"log_level": "warn"}
+        assert decision.revision == engine.revision
+
+    def test_rule_result(self, engine):
+        decision = engine.evaluate("data.sark.cel.allow", {"user": {"roles": ["viewer"]}})
+
+        assert decision.allow is False
+        assert decision.result is False
+
+    def test_alongside_rego(self, engine):
+        engine.load_policy("rego", "package sark.rego\n\ndefault allow = true\n")
+
+        assert engine.evaluate("data.sark.rego", {}).allow is True
+        assert engine.evaluate("data.sark.cel", {"user": {"roles": []}}).allow is False
+        assert sorted(engine.loaded_policies()) == ["cel", "rego"]
+
+    def test_batch(self, engine):
+        inputs = [{"user": {"roles": ["admin"]}}, {"user": {"roles": []}}]
+
+        decisions = engine.evaluate_batch("data.sark.cel", inputs, max_parallel=2)
+
+        assert [d.allow for d in decisions] == [True, False]
+
+    def test_revision_changes_with_packages(self, engine):
+        before = engine.revision
+        engine.load_cel_policy("cel", PACKAGE.replace("warn", "info"))
+
+        assert engine.revision != before
+
+    def test_evaluation_errors(self, engine):
+        from sark._rust import PolicyEvalError
+
+        with pytest.raises(PolicyEvalError):
+            engine.evaluate("data.sark.cel", {})
+
+    def test_invalid_package(self, engine):
+        from sark._rust import PolicyCompileError
+
+        with pytest.raises(PolicyCompileError):
+            engine.load_cel_policy("broken", 'package = "sark.broken"\n\n[rules]\nallow = "1 +"\n')
@@ -0,0 +1,108 @@
+"""Tests for SensitivityClassifier, the gateway's classification of tool calls."""
+
+import pytest
+
+from sark._rust import RUST_AVAILABLE
+
+pytestmark = pytest.mark.skipif(not RUST_AVAILABLE, reason="Rust extensions not available")
+
+
+@pytest.mark.parametrize(
+    ("tool_name", "description", "expected"),
+    [
+        ("process_payment", None, "critical"),
+        ("get_user", None, "low"),
+        ("delete_user", "Deletes a user", "high"),
+        ("update_profile", None, "medium"),
+        ("list_files", "List files, or delete them", "high"),
+        ("unrelated", "Does something", "medium"),
+    ],
+)
+def test_builtin_keywords_match_the_tool_registry(tool_name, description, expected):
+    from sark._rust import SensitivityClassifier
+
+    assert SensitivityClassifier().classify(tool_name, description) == expected
+
+
+def test_keywords_match_words_and_parameter_names():
+    from sark._rust import SensitivityClassifier
+
+    classifier = SensitivityClassifier()
+
+    # "keyboard" and "monkey" contain "key" but not as a word
+    assert classifier.classify("keyboard_layout") == "medium"
+    assert classifier.classify("monkey") == "medium"
+    # Underscores match spaces too
+    assert classifier.classify("charge", "Charge a credit card") == "critical"
+    # Parameter names, of call arguments or a JSON schema
+    assert classifier.classify("lookup", parameters={"api_key": "x"}) == "critical"
+    schema = {"type": "object", "properties": {"password": {"type": "string"}}}
+    assert classifier.classify("lookup", parameters=schema) == "critical"
+
+
+def test_configured_rules_take_precedence_over_builtin_keywords():
+    from sark._rust import SensitivityClassifier
+
+    classifier = SensitivityClassifier(
+        [
+            {"name": "payments", "level": "critical", "servers": ["payments-*"]},
+            {"name": "token info", "level": "low", "tools": ["get_token_info"]},
+        ]
+    )
+
+    assert classifier.explain("get_invoice", server="payments-eu") == ("critical", "payments")
+    assert classifier.explain("get_invoice", server="billing") == ("low", "builtin:low")
+    assert classifier.explain("get_token_info") == ("low", "token info")
+    assert classifier.explain("unrelated") == ("medium", None)
+
+
+def test_parameter_patterns_match_nested_values():
+    from sark._rust import SensitivityClassifier
+
+    classifier = SensitivityClassifier(
+        [
+            {
+                "name": "destructive sql",
+                "level": "high",
+                "tools": ["run_sql"],
+                "parameters": [r"(?i)\b(drop|truncate)\b"],
+            }
+        ],
+        builtin_rules=False,
+    )
+
+    nested = {"statements": [{"sql": "select 1"}, {"sql": "DROP TABLE users"}]}
+    assert classifier.classify("run_sql", parameters=nested) == "high"
+    assert classifier.classify("run_sql", parameters={"sql": "select 1"}) == "medium"
+    # Every criterion has to match
+    assert classifier.classify("other_tool", parameters=nested) == "medium"
+    assert classifier.classify("run_sql") == "medium"
+
+
+def test_default_level_and_builtin_rules_switch():
+    from sark._rust import SensitivityClassifier
+
+    classifier = SensitivityClassifier(default_level="high", builtin_rules=False)
+
+    assert classifier.default_level == "high"
+    assert classifier.rule_count() == 0
+    assert classifier.classify("delete_everything") == "high"
+    assert SensitivityClassifier.levels == ["low", "medium", "high", "critical"]
+
+
+@pytest.mark.parametrize(
+    ("rules", "kwargs"),
+    [
+        ([{"level": "high"}], {}),
+        ([{"level": "extreme", "tools": ["x"]}], {}),
+        ([{"level": "high", "parameters": ["("]}], {}),
+        ([{"level": "high", "keywords": [" "]}], {}),
+        ([{"level": "high", "tools": ["x"], "unknown": True}], {}),
+        (None, {"default_level": "extreme"}),
+    ],
+)
+def test_invalid_rules_raise_value_error(rules, kwargs):
+    from sark._rust import SensitivityClassifier
+
+    with pytest.raises(ValueError):
+        SensitivityClassifier(rules, **kwargs)
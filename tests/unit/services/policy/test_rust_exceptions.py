@@ -0,0 +1,64 @@
+"""Tests for the exception hierarchy exported by sark_rust."""
+
+import pytest
+
+from sark._rust import RUST_AVAILABLE
+
+pytestmark = pytest.mark.skipif(not RUST_AVAILABLE, reason="Rust extensions not available")
+
+
+def test_hierarchy():
+    from sark._rust import (
+        GatewayError,
+        PolicyCompileError,
+        PolicyEvalError,
+        SarkCacheError,
+        SarkError,
+        SarkPolicyError,
+    )
+
+    assert issubclass(SarkError, Exception)
+    assert issubclass(SarkCacheError, SarkError)
+    assert issubclass(SarkPolicyError, SarkError)
+    assert issubclass(PolicyCompileError, SarkPolicyError)
+    assert issubclass(PolicyEvalError, SarkPolicyError)
+    assert issubclass(GatewayError, SarkError)
+    assert not issubclass(PolicyCompileError, PolicyEvalError)
+
+
+def test_compile_failure_raises_policy_compile_error():
+    from sark._rust import PolicyCompileError, RustOPAEngine
+
+    engine = RustOPAEngine()
+    with pytest.raises(PolicyCompileError, match="Failed to compile policy broken"):
+        engine.load_policy("broken", "this is not valid rego")
+    assert not engine.has_policy("broken")
+
+
+def test_evaluation_failure_raises_policy_eval_error():
+    from sark._rust import PolicyCompileError, PolicyEvalError, RustOPAEngine
+
+    engine = RustOPAEngine()
+    engine.load_policy("authz", "package authz\n\nallow := true\n")
+    with pytest.raises(PolicyEvalError, match="Policy evaluation error") as excinfo:
+        engine.evaluate("data.authz[", {})
+    assert not isinstance(excinfo.value, PolicyCompileError)
+
+
+def test_policy_errors_are_caught_by_base_class():
+    from sark._rust import RustOPAEngine, SarkError, SarkPolicyError
+
+    engine = RustOPAEngine()
+    with pytest.raises(SarkPolicyError):
+        engine.load_policy("broken", "package")
+    with pytest.raises(SarkError):
+        engine.evaluate("data.authz[", {})
+
+
+def test_invalid_input_is_still_value_error():
+    from sark._rust import RustOPAEngine, SarkError
+
+    engine = RustOPAEngine()
+    with pytest.raises(ValueError) as excinfo:
+        engine.evaluate("data.authz", {"user": object()})
+    assert not isinstance(excinfo.value, SarkError)
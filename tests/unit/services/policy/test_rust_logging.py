@@ -0,0 +1,65 @@
+"""Tests for forwarding Rust tracing events to Python logging."""
+
+import logging
+
+import pytest
+
+from sark._rust import RUST_AVAILABLE
+
+pytestmark = pytest.mark.skipif(not RUST_AVAILABLE, reason="Rust extensions not available")
+
+
+@pytest.fixture(scope="module")
+def rust_logging():
+    """Enable forwarding once for the module; it can't be undone."""
+    from sark._rust import enable_logging
+
+    try:
+        enable_logging("debug")
+    except RuntimeError:
+        pass  # Already enabled by another test module
+
+
+def test_unknown_level_is_rejected():
+    from sark._rust import enable_logging
+
+    with pytest.raises(ValueError, match="Unknown log level"):
+        enable_logging("loud")
+
+
+def test_second_enable_raises(rust_logging):
+    from sark._rust import enable_logging
+
+    with pytest.raises(RuntimeError, match="already set up"):
+        enable_logging("info")
+
+
+async def test_rust_warning_reaches_python_logger(rust_logging, caplog):
+    """Failing open logs a warning from sark_client::, under sark_rust."""
+    from sark._rust import GatewayClient
+
+    # Nothing listens on the discard port
+    client = GatewayClient("http://127.0.0.1:9", max_retries=0, fail_open=True)
+    with caplog.at_level(logging.WARNING, logger="sark_rust"):
+        decision = await client.authorize(
+            {"action": "tool:invoke", "server_name": "github", "tool_name": "create_issue"}
+        )
+
+    assert decision["allow"]
+    records = [r for r in caplog.records if r.name.startswith("sark_rust.sark_client")]
+    assert records, [r.name for r in caplog.records]
+    record = records[0]
+    assert record.levelno == logging.WARNING
+    assert record.getMessage().startswith("gateway unavailable, failing open")
+    assert record.rust_fields["route"]
+
+
+async def test_events_below_python_level_are_not_emitted(rust_logging, caplog):
+    """Python's logger level still applies to forwarded events."""
+    from sark._rust import GatewayClient
+
+    client = GatewayClient("http://127.0.0.1:9", max_retries=0, fail_open=True)
+    with caplog.at_level(logging.ERROR, logger="sark_rust"):
+        await client.authorize({"action": "tool:invoke", "server_name": "github"})
+
+    assert not [r for r in caplog.records if r.name.startswith("sark_rust.")]
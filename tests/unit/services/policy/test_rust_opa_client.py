@@ -102,9 +102,11 @@ class TestPolicyLoading:
     @pytest.mark.asyncio
     async def test_load_policy_invalid(self, rust_client):
         """Test loading an invalid policy raises an error."""
+        from sark._rust import PolicyCompileError
+
         invalid_policy = "this is not valid rego"
 
-        with pytest.raises(Exception):
+        with pytest.raises(PolicyCompileError):
             await rust_client.load_policy("invalid", invalid_policy)
 
     @pytest.mark.asyncio
@@ -0,0 +1,100 @@
+"""Tests for RustOPAEngine's process-wide compile cache."""
+
+import pytest
+
+from sark._rust import RUST_AVAILABLE
+
+pytestmark = pytest.mark.skipif(not RUST_AVAILABLE, reason="Rust extensions not available")
+
+AUTHZ = """package authz
+
+default allow := false
+
+allow if input.user == "alice"
+"""
+
+
+@pytest.fixture(autouse=True)
+def empty_cache():
+    from sark._rust import RustOPAEngine
+
+    RustOPAEngine.clear_compile_cache()
+    yield
+    RustOPAEngine.clear_compile_cache()
+
+
+def loaded():
+    from sark._rust import RustOPAEngine
+
+    engine = RustOPAEngine()
+    engine.load_policy("authz", AUTHZ)
+    return engine
+
+
+def test_a_dropped_engine_is_reused():
+    from sark._rust import RustOPAEngine
+
+    engine = loaded()
+    del engine
+    assert RustOPAEngine.compile_cache_stats()["engines"] >= 1
+    hits = RustOPAEngine.compile_cache_stats()["hits"]
+
+    engine = loaded()
+
+    assert RustOPAEngine.compile_cache_stats()["hits"] == hits + 1
+    assert engine.evaluate("data.authz.allow", {"user": "alice"}).allow
+
+
+def test_other_content_is_compiled():
+    from sark._rust import RustOPAEngine
+
+    engine = loaded()
+    del engine
+    hits = RustOPAEngine.compile_cache_stats()["hits"]
+
+    engine = RustOPAEngine()
+    engine.load_policy("authz", AUTHZ.replace("alice", "bob"))
+
+    assert RustOPAEngine.compile_cache_stats()["hits"] == hits
+    assert engine.evaluate("data.authz.allow", {"user": "bob"}).allow
+    assert not engine.evaluate("data.authz.allow", {"user": "alice"}).allow
+
+
+def test_data_is_part_of_the_key():
+    from sark._rust import RustOPAEngine
+
+    first = RustOPAEngine()
+    first.set_data("roles", {"admins": ["alice"]})
+    first.load_policy("authz", AUTHZ)
+    del first
+    hits = RustOPAEngine.compile_cache_stats()["hits"]
+
+    engine = loaded()
+
+    assert RustOPAEngine.compile_cache_stats()["hits"] == hits
+    assert engine.data == {}
+
+
+def test_replaced_engines_are_reused():
+    from sark._rust import RustOPAEngine
+
+    engine = loaded()
+    engine.set_data("roles", {"admins": ["alice"]})
+    hits = RustOPAEngine.compile_cache_stats()["hits"]
+
+    engine.remove_data("roles")
+
+    # The engine compiled before the data was set is back in use
+    assert RustOPAEngine.compile_cache_stats()["hits"] == hits + 1
+    assert engine.evaluate("data.authz.allow", {"user": "alice"}).allow
+
+
+def test_clearing_the_cache_drops_its_engines():
+    from sark._rust import RustOPAEngine
+
+    engine = loaded()
+    del engine
+
+    RustOPAEngine.clear_compile_cache()
+
+    assert RustOPAEngine.compile_cache_stats()["engines"] == 0
@@ -0,0 +1,108 @@
+"""Tests for RustOPAEngine data documents."""
+
+import pytest
+
+from sark._rust import RUST_AVAILABLE
+
+pytestmark = pytest.mark.skipif(not RUST_AVAILABLE, reason="Rust extensions not available")
+
+AUTHZ = """package authz
+
+allow if input.user in data.roles.admins
+"""
+
+CEL = """
+package = "sark.cel"
+
+[rules]
+allow = 'input.user in data.roles.admins'
+"""
+
+
+@pytest.fixture
+def engine():
+    from sark._rust import RustOPAEngine
+
+    engine = RustOPAEngine()
+    engine.load_policy("authz", AUTHZ)
+    return engine
+
+
+def test_set_data_is_read_by_policies(engine):
+    engine.set_data("roles", {"admins": ["alice"]})
+
+    assert engine.data == {"roles": {"admins": ["alice"]}}
+    assert engine.evaluate("data.authz.allow", {"user": "alice"}).allow
+    assert not engine.evaluate("data.authz.allow", {"user": "bob"}).allow
+
+
+def test_set_data_at_the_root_replaces_the_document(engine):
+    engine.set_data("roles/admins", ["alice"])
+    engine.set_data("", {"roles": {"admins": ["bob"]}})
+
+    assert engine.evaluate("data.authz.allow", {"user": "bob"}).allow
+    assert not engine.evaluate("data.authz.allow", {"user": "alice"}).allow
+
+
+def test_set_data_refuses_a_non_object_root(engine):
+    from sark._rust import SarkPolicyError
+
+    with pytest.raises(SarkPolicyError, match="must be an object"):
+        engine.set_data("", ["alice"])
+
+
+def test_patch_data(engine):
+    engine.set_data("roles", {"admins": ["alice"]})
+    engine.patch_data([{"op": "add", "path": "/roles/admins/-", "value": "bob"}])
+
+    assert engine.data == {"roles": {"admins": ["alice", "bob"]}}
+    assert engine.evaluate("data.authz.allow", {"user": "bob"}).allow
+
+
+def test_failed_patch_leaves_the_data_as_it_was(engine):
+    from sark._rust import SarkPolicyError
+
+    engine.set_data("roles", {"admins": ["alice"]})
+
+    with pytest.raises(SarkPolicyError, match="Failed to apply JSON patch"):
+        engine.patch_data([{"op": "remove", "path": "/roles/users"}])
+    assert engine.data == {"roles": {"admins": ["alice"]}}
+
+
+def test_remove_data(engine):
+    engine.set_data("roles", {"admins": ["alice"]})
+
+    assert engine.remove_data("roles/admins")
+    assert not engine.remove_data("roles/admins")
+    assert not engine.evaluate("data.authz.allow", {"user": "alice"}).allow
+
+
+def test_data_changes_the_revision(engine):
+    revision = engine.revision
+
+    engine.set_data("roles", {"admins": ["alice"]})
+
+    assert engine.revision != revision
+
+
+def test_data_outlives_policy_changes(engine):
+    engine.set_data("roles", {"admins": ["alice"]})
+    engine.load_policy("authz", AUTHZ)
+    engine.load_policy("other", "package other\n\nallow := true\n")
+
+    assert engine.evaluate("data.authz.allow", {"user": "alice"}).allow
+
+
+def test_cel_packages_see_the_data(engine):
+    engine.load_cel_policy("sark", CEL)
+    engine.set_data("roles", {"admins": ["alice"]})
+
+    assert engine.evaluate("data.sark.cel", {"user": "alice"}).allow
+
+
+def test_batches_see_the_data(engine):
+    engine.set_data("roles", {"admins": ["alice"]})
+
+    results = engine.evaluate_batch("data.authz.allow", [{"user": "alice"}] * 8, max_parallel=4)
+
+    assert all(result.allow for result in results)
@@ -0,0 +1,112 @@
+"""Tests for the RustOPAEngine decision log."""
+
+import hashlib
+import json
+
+import pytest
+
+from sark._rust import RUST_AVAILABLE
+
+pytestmark = pytest.mark.skipif(not RUST_AVAILABLE, reason="Rust extensions not available")
+
+AUTHZ = """package authz
+
+default allow := false
+
+allow if input.user == "alice"
+"""
+
+
+@pytest.fixture
+def records():
+    return []
+
+
+@pytest.fixture
+def engine(records):
+    from sark._rust import RustOPAEngine
+
+    engine = RustOPAEngine()
+    engine.load_policy("authz", AUTHZ)
+    engine.set_decision_log(records.append)
+    return engine
+
+
+def test_evaluations_are_logged(engine, records):
+    decision = engine.evaluate("data.authz.allow", {"user": "alice"})
+
+    [record] = records
+    assert record["decision_id"] == decision.decision_id
+    assert record["query"] == "data.authz.allow"
+    assert record["result"] is True
+    assert record["error"] is None
+    assert record["revision"] == engine.revision
+    assert record["latency_us"] >= 0
+    assert record["timestamp"] > 0
+
+
+def test_input_is_logged_by_its_canonical_hash(engine, records):
+    engine.evaluate("data.authz.allow", {"user": "alice", "tool": {"b": 1, "a": 2}})
+
+    canonical = json.dumps(
+        {"tool": {"a": 2, "b": 1}, "user": "alice"}, separators=(",", ":"), sort_keys=True
+    )
+    assert records[0]["input_hash"] == hashlib.sha256(canonical.encode()).hexdigest()
+    assert "alice" not in json.dumps(records[0])
+
+
+def test_decisions_get_their_own_ids(engine, records):
+    engine.evaluate("data.authz.allow", {"user": "alice"})
+    engine.evaluate("data.authz.allow", {"user": "alice"})
+
+    assert records[0]["decision_id"] != records[1]["decision_id"]
+
+
+def test_failed_evaluations_are_logged(engine, records):
+    from sark._rust import PolicyEvalError
+
+    with pytest.raises(PolicyEvalError):
+        engine.evaluate("data.authz[", {})
+
+    [record] = records
+    assert record["result"] is None
+    assert record["error"]
+
+
+def test_batches_are_logged(engine, records):
+    inputs = [{"user": "alice"}, {"user": object()}, {"user": "bob"}]
+
+    results = engine.evaluate_batch("data.authz.allow", inputs, max_parallel=2)
+
+    # The unconvertible input is never evaluated, so never logged
+    assert [record["result"] for record in records] == [True, False]
+    assert [record["decision_id"] for record in records] == [
+        results[0].decision_id,
+        results[2].decision_id,
+    ]
+
+
+async def test_async_evaluations_are_logged(engine, records):
+    decision = await engine.evaluate_async("data.authz.allow", {"user": "alice"})
+
+    assert [record["decision_id"] for record in records] == [decision.decision_id]
+
+
+def test_a_failing_sink_does_not_fail_the_evaluation(engine, monkeypatch):
+    unraisable = []
+    monkeypatch.setattr("sys.unraisablehook", unraisable.append)
+
+    def sink(record):
+        raise RuntimeError("collector down")
+
+    engine.set_decision_log(sink)
+
+    assert engine.evaluate("data.authz.allow", {"user": "alice"}).allow
+    assert isinstance(unraisable[0].exc_value, RuntimeError)
+
+
+def test_no_log_no_decision_id(engine, records):
+    engine.set_decision_log(None)
+
+    assert engine.evaluate("data.authz.allow", {"user": "alice"}).decision_id is None
+    assert records == []
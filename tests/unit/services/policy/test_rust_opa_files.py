@@ -0,0 +1,142 @@
+"""Tests for loading RustOPAEngine policies from files and directories."""
+
+import pytest
+
+from sark._rust import RUST_AVAILABLE
+
+pytestmark = pytest.mark.skipif(not RUST_AVAILABLE, reason="Rust extensions not available")
+
+AUTHZ = """package authz
+
+allow if input.user == "alice"
+"""
+
+GATEWAY = """package mcp.gateway
+
+allow := data.authz.allow
+"""
+
+CEL = """
+package = "sark.cel"
+
+[rules]
+allow = 'input.user == "alice"'
+"""
+
+BROKEN = """package broken
+
+allow if input.user == ^
+"""
+
+
+@pytest.fixture
+def engine():
+    from sark._rust import RustOPAEngine
+
+    return RustOPAEngine()
+
+
+@pytest.fixture
+def policy_dir(tmp_path):
+    (tmp_path / "mcp").mkdir()
+    (tmp_path / "authz.rego").write_text(AUTHZ)
+    (tmp_path / "mcp" / "gateway.rego").write_text(GATEWAY)
+    (tmp_path / "sark.cel.toml").write_text(CEL)
+    (tmp_path / "README.md").write_text("not a policy")
+    return tmp_path
+
+
+def test_add_policy_file_names_the_module_after_the_file(engine, tmp_path):
+    path = tmp_path / "authz.rego"
+    path.write_text(AUTHZ)
+
+    assert engine.add_policy_file(path) == "authz"
+    assert engine.has_policy("authz")
+    assert engine.evaluate("data.authz.allow", {"user": "alice"}).allow
+
+
+def test_add_policy_file_takes_cel_packages(engine, tmp_path):
+    path = tmp_path / "sark.cel.toml"
+    path.write_text(CEL)
+
+    assert engine.add_policy_file(str(path)) == "sark"
+    assert engine.evaluate("data.sark.cel", {"user": "alice"}).allow
+
+
+def test_add_policy_file_refuses_other_files(engine, tmp_path):
+    from sark._rust import SarkPolicyError
+
+    path = tmp_path / "notes.txt"
+    path.write_text(AUTHZ)
+
+    with pytest.raises(SarkPolicyError, match="is not a .rego or .cel.toml policy"):
+        engine.add_policy_file(path)
+
+
+def test_add_policy_file_replaces_a_module_of_the_same_name(engine, tmp_path):
+    path = tmp_path / "authz.rego"
+    path.write_text(AUTHZ)
+    engine.add_policy_file(path)
+    revision = engine.revision
+
+    path.write_text('package authz\n\nallow if input.user == "bob"\n')
+    engine.add_policy_file(path)
+
+    assert engine.revision != revision
+    assert not engine.evaluate("data.authz.allow", {"user": "alice"}).allow
+    assert engine.evaluate("data.authz.allow", {"user": "bob"}).allow
+
+
+def test_load_policy_dir_names_modules_by_relative_path(engine, policy_dir):
+    assert engine.load_policy_dir(policy_dir) == ["authz", "mcp/gateway", "sark"]
+    assert sorted(engine.loaded_policies()) == ["authz", "mcp/gateway", "sark"]
+    assert engine.evaluate("data.mcp.gateway", {"user": "alice"}).allow
+    assert engine.evaluate("data.sark.cel.allow", {"user": "alice"}).allow
+
+
+def test_compile_errors_carry_the_file_and_line(engine, tmp_path):
+    from sark._rust import PolicyCompileError
+
+    path = tmp_path / "broken.rego"
+    path.write_text(BROKEN)
+
+    with pytest.raises(PolicyCompileError, match="Failed to compile policy broken") as excinfo:
+        engine.add_policy_file(path)
+
+    assert excinfo.value.file == str(path)
+    assert excinfo.value.line == 3
+    assert excinfo.value.column is not None
+    assert not engine.has_policy("broken")
+
+
+def test_load_policy_dir_loads_nothing_if_a_file_fails(engine, policy_dir):
+    from sark._rust import PolicyCompileError
+
+    (policy_dir / "mcp" / "broken.rego").write_text(BROKEN)
+    engine.load_policy("existing", "package existing\n\nallow := true\n")
+    revision = engine.revision
+
+    with pytest.raises(PolicyCompileError) as excinfo:
+        engine.load_policy_dir(policy_dir)
+
+    assert excinfo.value.file == str(policy_dir / "mcp" / "broken.rego")
+    assert engine.loaded_policies() == ["existing"]
+    assert engine.revision == revision
+    assert engine.evaluate("data.existing.allow", {}).allow
+
+
+def test_load_policy_errors_are_located_by_module_name(engine):
+    from sark._rust import PolicyCompileError
+
+    with pytest.raises(PolicyCompileError) as excinfo:
+        engine.load_policy("broken", BROKEN)
+
+    assert excinfo.value.file == "broken"
+    assert excinfo.value.line == 3
+
+
+def test_missing_directory_raises(engine, tmp_path):
+    from sark._rust import SarkPolicyError
+
+    with pytest.raises(SarkPolicyError, match="Failed to read policy directory"):
+        engine.load_policy_dir(tmp_path / "missing")
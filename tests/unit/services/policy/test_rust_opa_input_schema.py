@@ -0,0 +1,94 @@
+"""Tests for RustOPAEngine input schemas."""
+
+import pytest
+
+from sark._rust import RUST_AVAILABLE
+
+pytestmark = pytest.mark.skipif(not RUST_AVAILABLE, reason="Rust extensions not available")
+
+AUTHZ = """package authz
+
+default allow := false
+
+allow if input.user.id == "alice"
+"""
+
+SCHEMA = {
+    "type": "object",
+    "required": ["user", "action"],
+    "properties": {
+        "user": {
+            "type": "object",
+            "required": ["id"],
+            "properties": {"id": {"type": "string"}},
+        },
+        "action": {"type": "string"},
+    },
+}
+
+
+@pytest.fixture
+def engine():
+    from sark._rust import RustOPAEngine
+
+    engine = RustOPAEngine()
+    engine.load_policy("authz", AUTHZ)
+    engine.set_input_schema(SCHEMA)
+    return engine
+
+
+def test_malformed_error_is_a_policy_eval_error():
+    from sark._rust import MalformedInputError, PolicyEvalError
+
+    assert issubclass(MalformedInputError, PolicyEvalError)
+
+
+def test_valid_input_is_evaluated(engine):
+    assert engine.evaluate("data.authz.allow", {"user": {"id": "alice"}, "action": "read"}).allow
+
+
+def test_malformed_input_names_the_violations(engine):
+    from sark._rust import MalformedInputError
+
+    with pytest.raises(MalformedInputError, match="Malformed input") as raised:
+        engine.evaluate("data.authz.allow", {"user": {"id": 7}})
+
+    message = str(raised.value)
+    assert "/user/id" in message
+    assert "action" in message
+
+
+def test_malformed_items_fail_alone_in_batches(engine):
+    from sark._rust import MalformedInputError, PolicyDecision
+
+    results = engine.evaluate_batch(
+        "data.authz.allow", [{"user": {"id": "alice"}, "action": "read"}, {"user": {}}]
+    )
+
+    assert isinstance(results[0], PolicyDecision) and results[0].allow
+    assert isinstance(results[1], MalformedInputError)
+
+
+async def test_async_evaluation_checks_the_input(engine):
+    from sark._rust import MalformedInputError
+
+    with pytest.raises(MalformedInputError):
+        await engine.evaluate_async("data.authz.allow", {})
+
+
+def test_evaluate_many_checks_the_input(engine):
+    from sark._rust import MalformedInputError
+
+    with pytest.raises(MalformedInputError):
+        engine.evaluate_many(["data.authz.allow"], {})
+
+
+def test_schema_can_be_removed(engine):
+    engine.set_input_schema(None)
+
+    assert not engine.evaluate("data.authz.allow", {})
+
+
+def test_invalid_schema_is_refused(engine):
+    with pytest.raises(ValueError, match="Invalid input schema"):
+        engine.set_input_schema({"type": 12})
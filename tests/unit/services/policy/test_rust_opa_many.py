@@ -0,0 +1,90 @@
+"""Tests for RustOPAEngine.evaluate_many."""
+
+import pytest
+
+from sark._rust import RUST_AVAILABLE
+
+pytestmark = pytest.mark.skipif(not RUST_AVAILABLE, reason="Rust extensions not available")
+
+GATEWAY = """package mcp.gateway
+
+default allow := false
+
+allow if input.user == "alice"
+
+reason := "alice may" if allow
+
+obligations := {"audit": true}
+"""
+
+CEL = """
+package = "sark.cel"
+
+[rules]
+allow = 'input.user == "alice"'
+"""
+
+QUERIES = [
+    "data.mcp.gateway.allow",
+    "data.mcp.gateway.reason",
+    "data.mcp.gateway.obligations",
+]
+
+
+@pytest.fixture
+def engine():
+    from sark._rust import RustOPAEngine
+
+    engine = RustOPAEngine()
+    engine.load_policy("gateway", GATEWAY)
+    return engine
+
+
+def test_results_are_in_query_order(engine):
+    allow, reason, obligations = engine.evaluate_many(QUERIES, {"user": "alice"})
+
+    assert allow.result is True
+    assert reason.result == "alice may"
+    assert obligations.result == {"audit": True}
+
+
+def test_results_match_single_evaluations(engine):
+    input = {"user": "alice"}
+
+    many = engine.evaluate_many(QUERIES, input)
+
+    assert [d.result for d in many] == [engine.evaluate(q, input).result for q in QUERIES]
+
+
+def test_undefined_rules_are_none(engine):
+    _, reason, _ = engine.evaluate_many(QUERIES, {"user": "mallory"})
+
+    assert reason.result is None
+    assert not reason
+
+
+def test_packages_rules_and_cel_mix(engine):
+    engine.load_cel_policy("sark", CEL)
+
+    package, rule, cel = engine.evaluate_many(
+        ["data.mcp.gateway", "data.mcp.gateway.allow", "data.sark.cel.allow"],
+        {"user": "alice"},
+    )
+
+    assert package.allow and package.reason == "alice may"
+    assert rule.allow
+    assert cel.allow
+
+
+def test_failed_queries_are_returned(engine):
+    from sark._rust import PolicyDecision, PolicyEvalError
+
+    results = engine.evaluate_many(["data.mcp.gateway[", "data.mcp.gateway.allow"], {})
+
+    assert isinstance(results[0], PolicyEvalError)
+    assert isinstance(results[1], PolicyDecision)
+
+
+def test_invalid_input_raises(engine):
+    with pytest.raises(ValueError, match="Invalid OPA input"):
+        engine.evaluate_many(QUERIES, {"user": object()})
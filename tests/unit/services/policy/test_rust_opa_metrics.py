@@ -0,0 +1,91 @@
+"""Tests for RustOPAEngine.metrics."""
+
+import pytest
+
+from sark._rust import RUST_AVAILABLE
+
+pytestmark = pytest.mark.skipif(not RUST_AVAILABLE, reason="Rust extensions not available")
+
+AUTHZ = """package authz
+
+default allow := false
+
+allow if input.user == "alice"
+"""
+
+QUERY = "data.authz.allow"
+
+
+@pytest.fixture
+def engine():
+    from sark._rust import RustOPAEngine
+
+    engine = RustOPAEngine()
+    engine.load_policy("authz", AUTHZ)
+    return engine
+
+
+def test_no_evaluations_no_metrics(engine):
+    assert engine.metrics() == {}
+
+
+def test_outcomes_are_counted_by_query(engine):
+    engine.evaluate(QUERY, {"user": "alice"})
+    engine.evaluate(QUERY, {"user": "mallory"})
+    engine.evaluate(QUERY, {"user": "mallory"})
+    with pytest.raises(Exception):
+        engine.evaluate("data.x[", {})
+
+    metrics = engine.metrics()
+
+    assert (metrics[QUERY]["allow"], metrics[QUERY]["deny"], metrics[QUERY]["error"]) == (1, 2, 0)
+    assert metrics["data.x["]["error"] == 1
+
+
+def test_latencies_are_a_cumulative_histogram(engine):
+    for _ in range(3):
+        engine.evaluate(QUERY, {"user": "alice"})
+
+    metrics = engine.metrics()[QUERY]
+
+    counts = [count for _, count in metrics["latency_seconds_buckets"]]
+    bounds = [bound for bound, _ in metrics["latency_seconds_buckets"]]
+    assert bounds == sorted(bounds)
+    assert counts == sorted(counts)
+    assert counts[-1] <= 3
+    assert metrics["latency_seconds_sum"] > 0
+
+
+def test_errors_have_no_latency(engine):
+    engine.evaluate_many(["data.x["], {})
+
+    metrics = engine.metrics()["data.x["]
+
+    assert metrics["error"] == 1
+    assert metrics["latency_seconds_sum"] == 0
+    assert all(count == 0 for _, count in metrics["latency_seconds_buckets"])
+
+
+def test_batches_count_each_item(engine):
+    engine.evaluate_batch(QUERY, [{"user": "alice"}, {"user": "bob"}, {"user": object()}])
+
+    metrics = engine.metrics()[QUERY]
+
+    # The unconvertible item never reached the engine
+    assert (metrics["allow"], metrics["deny"], metrics["error"]) == (1, 1, 0)
+
+
+async def test_async_evaluations_count(engine):
+    await engine.evaluate_async(QUERY, {"user": "alice"})
+
+    assert engine.metrics()[QUERY]["allow"] == 1
+
+
+def test_malformed_inputs_are_not_counted(engine):
+    from sark._rust import MalformedInputError
+
+    engine.set_input_schema({"type": "object", "required": ["user"]})
+    with pytest.raises(MalformedInputError):
+        engine.evaluate(QUERY, {})
+
+    assert engine.metrics() == {}
@@ -0,0 +1,114 @@
+"""Tests for running rego unit tests with RustOPAEngine."""
+
+import pytest
+
+from sark._rust import RUST_AVAILABLE
+
+pytestmark = pytest.mark.skipif(not RUST_AVAILABLE, reason="Rust extensions not available")
+
+AUTHZ = """package authz
+
+default allow := false
+
+allow if input.user == "alice"
+
+allow if {
+    input.user == "bob"
+    input.admin
+}
+"""
+
+AUTHZ_TEST = """package authz_test
+
+import data.authz
+
+test_alice_allowed if authz.allow with input as {"user": "alice"}
+
+test_mallory_denied if not authz.allow with input as {"user": "mallory"}
+"""
+
+FAILING_TEST = """package failing_test
+
+import data.authz
+
+test_mallory_allowed if authz.allow with input as {"user": "mallory"}
+"""
+
+
+@pytest.fixture
+def engine():
+    from sark._rust import RustOPAEngine
+
+    engine = RustOPAEngine()
+    engine.load_policy("authz", AUTHZ)
+    return engine
+
+
+@pytest.fixture
+def test_dir(tmp_path):
+    (tmp_path / "authz_test.rego").write_text(AUTHZ_TEST)
+    return tmp_path
+
+
+def test_run_tests_passes_true_rules(engine, test_dir):
+    report = engine.run_tests(test_dir)
+
+    assert [result["name"] for result in report["results"]] == [
+        "data.authz_test.test_alice_allowed",
+        "data.authz_test.test_mallory_denied",
+    ]
+    assert all(result["passed"] for result in report["results"])
+
+
+def test_run_tests_reports_failures(engine, test_dir):
+    (test_dir / "failing_test.rego").write_text(FAILING_TEST)
+
+    report = engine.run_tests(test_dir)
+
+    [failed] = [result for result in report["results"] if not result["passed"]]
+    assert failed["name"] == "data.failing_test.test_mallory_allowed"
+    assert failed["error"] is None
+
+
+def test_run_tests_reports_coverage(engine, test_dir):
+    report = engine.run_tests(test_dir)
+
+    [authz] = [file for file in report["coverage"] if file["file"] == "authz"]
+    # Neither test gets past the user check of bob's rule
+    assert 9 in authz["not_covered"]
+    assert 5 in authz["covered"]
+
+
+def test_run_tests_does_not_load_the_tests(engine, test_dir):
+    engine.run_tests(test_dir)
+
+    assert engine.loaded_policies() == ["authz"]
+
+
+def test_run_tests_runs_loaded_tests(engine):
+    engine.load_policy("authz_test", AUTHZ_TEST)
+
+    report = engine.run_tests()
+
+    assert len(report["results"]) == 2
+
+
+def test_run_tests_sees_the_data(engine, tmp_path):
+    (tmp_path / "data_test.rego").write_text(
+        'package data_test\n\ntest_admins if data.roles.admins == ["alice"]\n'
+    )
+    engine.set_data("roles", {"admins": ["alice"]})
+
+    assert engine.run_tests(tmp_path)["results"][0]["passed"]
+
+
+def test_run_tests_raises_located_compile_errors(engine, tmp_path):
+    from sark._rust import PolicyCompileError
+
+    path = tmp_path / "broken_test.rego"
+    path.write_text("package broken_test\n\ntest_x if input.user == ^\n")
+
+    with pytest.raises(PolicyCompileError) as raised:
+        engine.run_tests(tmp_path)
+    assert raised.value.file == str(path)
+    assert raised.value.line == 3
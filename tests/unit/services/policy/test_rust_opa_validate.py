@@ -0,0 +1,44 @@
+"""Tests for RustOPAEngine.validate_policy."""
+
+import pytest
+
+from sark._rust import RUST_AVAILABLE
+
+pytestmark = pytest.mark.skipif(not RUST_AVAILABLE, reason="Rust extensions not available")
+
+AUTHZ = """package authz
+
+allow if input.user == "alice"
+"""
+
+BROKEN = """package broken
+
+allow if input.user == ^
+"""
+
+
+def test_a_valid_policy_has_no_diagnostics():
+    from sark._rust import RustOPAEngine
+
+    assert RustOPAEngine.validate_policy(AUTHZ) == []
+
+
+def test_errors_are_located():
+    from sark._rust import RustOPAEngine
+
+    [diagnostic] = RustOPAEngine.validate_policy(BROKEN, "policies/broken.rego")
+
+    assert diagnostic["severity"] == "error"
+    assert diagnostic["file"] == "policies/broken.rego"
+    assert diagnostic["line"] == 3
+    assert diagnostic["column"] is not None
+    assert diagnostic["message"]
+
+
+def test_validating_does_not_load_the_policy():
+    from sark._rust import RustOPAEngine
+
+    engine = RustOPAEngine()
+
+    assert engine.validate_policy(AUTHZ) == []
+    assert engine.loaded_policies() == []
@@ -0,0 +1,91 @@
+"""Tests for RequestContext, the policy input context shared with the gateway."""
+
+from types import SimpleNamespace
+
+import pytest
+
+from sark._rust import RUST_AVAILABLE
+
+pytestmark = pytest.mark.skipif(not RUST_AVAILABLE, reason="Rust extensions not available")
+
+TRACEPARENT = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"
+
+
+def _request(headers, host="203.0.113.7"):
+    client = SimpleNamespace(host=host) if host is not None else None
+    return SimpleNamespace(headers=headers, client=client)
+
+
+def test_from_request_reads_headers_and_client():
+    from sark._rust import RequestContext
+
+    request = _request(
+        {
+            "traceparent": TRACEPARENT,
+            "x-session-id": "s-1",
+            "user-agent": "agent/1.0",
+        }
+    )
+
+    context = RequestContext.from_request(request, tenant="acme")
+
+    assert context.trace_id == "4bf92f3577b34da6a3ce929d0e0e4736"
+    assert context.tenant == "acme"
+    assert context.session_id == "s-1"
+    assert context.client_ip == "203.0.113.7"
+    assert context.user_agent == "agent/1.0"
+
+
+def test_malformed_traceparent_and_missing_client():
+    from sark._rust import RequestContext
+
+    context = RequestContext.from_request(_request({"traceparent": "nonsense"}, host=None))
+
+    assert context.trace_id is None
+    assert context.client_ip is None
+    assert context.to_dict() == {}
+
+
+def test_session_id_argument_wins():
+    from sark._rust import RequestContext
+
+    request = _request({"x-session-id": "from-header"})
+
+    context = RequestContext.from_request(request, session_id="given")
+
+    assert context.session_id == "given"
+
+
+def test_to_input_merges_into_context():
+    from sark._rust import RequestContext
+
+    context = RequestContext(tenant="acme", client_ip="10.0.0.1")
+    policy_input = {"user": {"id": "u"}, "context": {"client_ip": "spoofed", "extra": 1}}
+
+    merged = context.to_input(policy_input)
+
+    assert merged["context"] == {"client_ip": "10.0.0.1", "extra": 1, "tenant": "acme"}
+    assert merged["user"] == {"id": "u"}
+    assert policy_input["context"]["client_ip"] == "spoofed"
+
+
+def test_to_input_creates_context():
+    from sark._rust import RequestContext
+
+    merged = RequestContext(session_id="s").to_input({"action": "read"})
+
+    assert merged == {"action": "read", "context": {"session_id": "s"}}
+
+
+def test_invalid_client_ip():
+    from sark._rust import RequestContext
+
+    with pytest.raises(ValueError):
+        RequestContext(client_ip="not an address")
+
+
+def test_equality():
+    from sark._rust import RequestContext
+
+    assert RequestContext(tenant="a") == RequestContext(tenant="a")
+    assert RequestContext(tenant="a") != RequestContext(tenant="b")
@@ -0,0 +1,242 @@
+"""Tests for the typed results of the Rust extensions."""
+
+import asyncio
+
+import pytest
+
+from sark._rust import RUST_AVAILABLE
+
+pytestmark = pytest.mark.skipif(not RUST_AVAILABLE, reason="Rust extensions not available")
+
+POLICY = """
+package sark.gateway
+
+default allow = false
+
+allow {
+    input.user.role == "admin"
+}
+
+reason := "admin access" {
+    allow
+}
+
+filtered_parameters := {"limit": 10} {
+    allow
+}
+
+obligations := {"log_level": "warn"} {
+    allow
+}
+"""
+
+
+@pytest.fixture
+def engine():
+    from sark._rust import RustOPAEngine
+
+    engine = RustOPAEngine()
+    engine.load_policy("gateway", POLICY)
+    return engine
+
+
+class TestPolicyDecision:
+    """RustOPAEngine.evaluate returns a PolicyDecision."""
+
+    def test_package_document(self, engine):
+        from sark._rust import PolicyDecision
+
+        decision = engine.evaluate("data.sark.gateway", {"user": {"role": "admin"}})
+
+        assert isinstance(decision, PolicyDecision)
+        assert decision.allow is True
+        assert decision.reason == "admin access"
+        assert decision.filtered_parameters == {"limit": 10}
+        assert decision.obligations == {"log_level": "warn"}
+        assert decision.result["allow"] is True
+        assert decision.latency_ms >= 0
+        assert decision.revision == engine.revision
+
+    def test_rule_result(self, engine):
+        decision = engine.evaluate("data.sark.gateway.allow", {"user": {"role": "viewer"}})
+
+        assert decision.allow is False
+        assert not decision
+        assert decision.reason is None
+        assert decision.filtered_parameters is None
+        assert decision.obligations is None
+        assert decision.result is False
+
+    def test_revision_changes_with_policies(self, engine):
+        before = engine.revision
+        engine.load_policy("other", "package other\nx = 1")
+
+        assert engine.revision != before
+        assert engine.loaded_policies() == ["gateway", "other"]
+
+    def test_reloading_a_policy_replaces_it(self, engine):
+        engine.load_policy("gateway", "package sark.gateway\nallow = true")
+
+        assert engine.evaluate("data.sark.gateway.allow", {}).allow is True
+        assert engine.loaded_policies() == ["gateway"]
+
+
+class TestNativeInput:
+    """evaluate() takes Python objects as input, without JSON text."""
+
+    def test_nested_values(self):
+        from sark._rust import RustOPAEngine
+
+        engine = RustOPAEngine()
+        engine.load_policy(
+            "echo",
+            """
+            package echo
+
+            input_copy := input
+
+            many {
+                count(input.items) == 3
+                input.items[1].n == 2.5
+                input.flag == true
+                input.missing == null
+            }
+            """,
+        )
+        input_data = {
+            "items": [{"n": 1}, {"n": 2.5}, {"n": -3, "tags": ["a", "b"]}],
+            "flag": True,
+            "missing": None,
+            "name": "caf\u00e9",
+        }
+
+        decision = engine.evaluate("data.echo", input_data)
+
+        assert decision.result["input_copy"] == input_data
+        assert decision.result["many"] is True
+
+    def test_unconvertible_input_is_rejected(self, engine):
+        with pytest.raises(ValueError, match="Invalid OPA input"):
+            engine.evaluate("data.sark.gateway", {"user": object()})
+
+
+class TestEvaluateBatch:
+    """RustOPAEngine.evaluate_batch evaluates inputs in parallel, in order."""
+
+    def test_results_in_input_order(self, engine):
+        from sark._rust import PolicyDecision
+
+        roles = ["admin" if i % 3 == 0 else "viewer" for i in range(100)]
+        results = engine.evaluate_batch(
+            "data.sark.gateway", [{"user": {"role": role}} for role in roles], max_parallel=4
+        )
+
+        assert len(results) == 100
+        assert all(isinstance(result, PolicyDecision) for result in results)
+        assert [result.allow for result in results] == [role == "admin" for role in roles]
+        assert {result.revision for result in results} == {engine.revision}
+
+    def test_matches_single_evaluation(self, engine):
+        inputs = [{"user": {"role": "admin"}}, {"user": {"role": "viewer"}}]
+
+        batch = engine.evaluate_batch("data.sark.gateway", inputs)
+        single = [engine.evaluate("data.sark.gateway", input) for input in inputs]
+
+        assert [b.result for b in batch] == [s.result for s in single]
+
+    def test_per_item_errors(self, engine):
+        from sark._rust import PolicyDecision
+
+        results = engine.evaluate_batch(
+            "data.sark.gateway",
+            [{"user": {"role": "admin"}}, {"user": object()}, {"user": {"role": "viewer"}}],
+        )
+
+        assert isinstance(results[0], PolicyDecision) and results[0].allow
+        assert isinstance(results[1], ValueError)
+        assert "Invalid OPA input" in str(results[1])
+        assert isinstance(results[2], PolicyDecision) and not results[2].allow
+
+    def test_evaluation_errors_are_returned(self, engine):
+        from sark._rust import PolicyEvalError
+
+        results = engine.evaluate_batch("data.sark.gateway[", [{}, {}], max_parallel=2)
+
+        assert all(isinstance(result, PolicyEvalError) for result in results)
+
+    def test_empty_batch(self, engine):
+        assert engine.evaluate_batch("data.sark.gateway", []) == []
+
+    def test_replicas_follow_policy_changes(self, engine):
+        inputs = [{"user": {"role": "viewer"}}] * 8
+        assert not any(r.allow for r in engine.evaluate_batch("data.sark.gateway", inputs, 4))
+
+        engine.load_policy("gateway", "package sark.gateway\nallow = true")
+        results = engine.evaluate_batch("data.sark.gateway", inputs, max_parallel=4)
+
+        assert all(result.allow for result in results)
+        assert {result.revision for result in results} == {engine.revision}
+
+
+class TestEvaluateAsync:
+    """RustOPAEngine.evaluate_async evaluates off the event loop."""
+
+    async def test_matches_evaluate(self, engine):
+        from sark._rust import PolicyDecision
+
+        input = {"user": {"role": "admin"}}
+        decision = await engine.evaluate_async("data.sark.gateway", input)
+
+        assert isinstance(decision, PolicyDecision)
+        assert decision.result == engine.evaluate("data.sark.gateway", input).result
+        assert decision.revision == engine.revision
+
+    async def test_concurrent_evaluations(self, engine):
+        roles = ["admin" if i % 2 == 0 else "viewer" for i in range(20)]
+        decisions = await asyncio.gather(
+            *(engine.evaluate_async("data.sark.gateway", {"user": {"role": r}}) for r in roles)
+        )
+
+        assert [decision.allow for decision in decisions] == [r == "admin" for r in roles]
+
+    async def test_follows_policy_changes(self, engine):
+        input = {"user": {"role": "viewer"}}
+        assert not await engine.evaluate_async("data.sark.gateway", input)
+
+        engine.load_policy("gateway", "package sark.gateway\nallow = true")
+
+        assert await engine.evaluate_async("data.sark.gateway", input)
+
+    async def test_errors_are_raised(self, engine):
+        from sark._rust import PolicyEvalError
+
+        with pytest.raises(ValueError, match="Invalid OPA input"):
+            await engine.evaluate_async("data.sark.gateway", {"user": object()})
+        with pytest.raises(PolicyEvalError):
+            await engine.evaluate_async("data.sark.gateway[", {})
+
+
+class TestCacheStats:
+    """RustCache.stats returns a CacheStats."""
+
+    def test_counts_hits_and_misses(self):
+        from sark._rust import CacheStats, RustCache
+
+        cache = RustCache(max_size=100, ttl_secs=60)
+        cache.set("a", "1")
+        cache.get("a")
+        cache.get("a")
+        cache.get("missing")
+
+        stats = cache.stats()
+        assert isinstance(stats, CacheStats)
+        assert stats.size == 1
+        assert stats.max_size == 100
+        assert stats.ttl_secs == 60
+        assert (stats.hits, stats.misses) == (2, 1)
+        assert stats.hit_rate == pytest.approx(2 / 3)
+
+    def test_hit_rate_before_lookups(self):
+        from sark._rust import RustCache
+
+        assert RustCache(max_size=10, ttl_secs=60).stats().hit_rate == 0.0
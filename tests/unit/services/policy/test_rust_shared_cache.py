@@ -0,0 +1,162 @@
+"""Tests for RustSharedCache, the cache shared between processes."""
+
+import multiprocessing
+import pickle
+import time
+
+import pytest
+
+from sark._rust import RUST_AVAILABLE
+
+pytestmark = pytest.mark.skipif(not RUST_AVAILABLE, reason="Rust extensions not available")
+
+
+@pytest.fixture
+def path(tmp_path):
+    return str(tmp_path / "shared-cache")
+
+
+def _set_in_child(path, key, value):
+    from sark._rust import RustSharedCache
+
+    RustSharedCache(path, max_size=64).set(key, value)
+
+
+def _get_in_child(cache, key, queue):
+    queue.put(cache.get(key))
+
+
+class TestSingleProcess:
+    def test_set_get_delete(self, path):
+        from sark._rust import RustSharedCache
+
+        cache = RustSharedCache(path, max_size=64, ttl_secs=60)
+        cache.set("a", "1")
+
+        assert cache.get("a") == "1"
+        assert cache.get("missing") is None
+        assert cache.delete("a") is True
+        assert cache.delete("a") is False
+        assert cache.get("a") is None
+
+    def test_set_replaces_value(self, path):
+        from sark._rust import RustSharedCache
+
+        cache = RustSharedCache(path, max_size=64)
+        cache.set("a", "1")
+        cache.set("a", "2")
+
+        assert cache.get("a") == "2"
+        assert cache.size() == 1
+
+    def test_ttl_expiry_and_cleanup(self, path):
+        from sark._rust import RustSharedCache
+
+        cache = RustSharedCache(path, max_size=64, ttl_secs=60)
+        cache.set("short", "x", ttl=1)
+        cache.set("long", "y")
+        time.sleep(1.1)
+
+        assert cache.get("short") is None
+        assert cache.get("long") == "y"
+        assert cache.cleanup_expired() == 1
+        assert cache.size() == 1
+
+    def test_full_bucket_evicts(self, path):
+        from sark._rust import RustSharedCache
+
+        cache = RustSharedCache(path, max_size=8)
+        for i in range(100):
+            cache.set(f"k{i}", str(i))
+
+        assert cache.size() <= 8
+        assert cache.get("k99") == "99"
+
+    def test_entry_too_large_for_slot(self, path):
+        from sark._rust import RustSharedCache, SarkCacheError
+
+        cache = RustSharedCache(path, max_size=64, slot_size=128)
+        with pytest.raises(SarkCacheError, match="doesn't fit"):
+            cache.set("key", "v" * 200)
+
+    def test_clear_and_stats(self, path):
+        from sark._rust import CacheStats, RustSharedCache
+
+        cache = RustSharedCache(path, max_size=64, ttl_secs=30)
+        cache.set("a", "1")
+        cache.get("a")
+        cache.get("b")
+
+        stats = cache.stats()
+        assert isinstance(stats, CacheStats)
+        assert (stats.size, stats.max_size, stats.ttl_secs) == (1, 64, 30)
+        assert (stats.hits, stats.misses) == (1, 1)
+        assert (stats.insertions, stats.evictions, stats.expirations) == (1, 0, 0)
+        assert stats.memory_bytes >= 64 * 1024
+
+        cache.clear()
+        assert cache.size() == 0
+
+    def test_unicode_values(self, path):
+        from sark._rust import RustSharedCache
+
+        cache = RustSharedCache(path, max_size=64)
+        cache.set("café", "✓ allow")
+
+        assert cache.get("café") == "✓ allow"
+
+
+class TestSharing:
+    def test_two_handles_share_entries(self, path):
+        from sark._rust import RustSharedCache
+
+        first = RustSharedCache(path, max_size=64)
+        second = RustSharedCache(path, max_size=64)
+        first.set("a", "1")
+
+        assert second.get("a") == "1"
+        second.clear()
+        assert first.get("a") is None
+
+    def test_different_geometry_is_rejected(self, path):
+        from sark._rust import RustSharedCache, SarkCacheError
+
+        RustSharedCache(path, max_size=64)
+        with pytest.raises(SarkCacheError, match="was created with max_size=64"):
+            RustSharedCache(path, max_size=128)
+
+    def test_file_that_is_not_a_cache_is_rejected(self, tmp_path):
+        from sark._rust import RustSharedCache, SarkCacheError
+
+        other = tmp_path / "other"
+        other.write_bytes(b"x" * 4096)
+        with pytest.raises(SarkCacheError, match="not a SARK shared cache"):
+            RustSharedCache(str(other), max_size=64)
+
+    def test_entries_set_in_another_process_are_seen(self, path):
+        from sark._rust import RustSharedCache
+
+        cache = RustSharedCache(path, max_size=64)
+        ctx = multiprocessing.get_context("spawn")
+        child = ctx.Process(target=_set_in_child, args=(path, "decision", "allow"))
+        child.start()
+        child.join(timeout=30)
+
+        assert child.exitcode == 0
+        assert cache.get("decision") == "allow"
+
+    def test_pickles_as_a_handle_to_the_same_table(self, path):
+        from sark._rust import RustSharedCache
+
+        cache = RustSharedCache(path, max_size=64)
+        restored = pickle.loads(pickle.dumps(cache))
+        restored.set("a", "1")
+        assert cache.get("a") == "1"
+        assert restored.path == path
+
+        ctx = multiprocessing.get_context("spawn")
+        queue = ctx.Queue()
+        child = ctx.Process(target=_get_in_child, args=(cache, "a", queue))
+        child.start()
+        child.join(timeout=30)
+        assert queue.get(timeout=5) == "1"
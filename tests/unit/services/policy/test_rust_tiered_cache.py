@@ -0,0 +1,49 @@
+"""Tests for RustTieredCache without a Redis to reach."""
+
+import pytest
+
+from sark._rust import RUST_AVAILABLE
+
+pytestmark = pytest.mark.skipif(not RUST_AVAILABLE, reason="Rust extensions not available")
+
+# Nothing listens on port 1, so every Redis call fails fast
+UNREACHABLE = "redis://127.0.0.1:1"
+
+
+@pytest.fixture
+def tiered():
+    from sark._rust import RustTieredCache
+
+    return RustTieredCache(max_size=100, ttl_secs=60, redis_url=UNREACHABLE)
+
+
+def test_l1_serves_without_redis(tiered):
+    tiered.set("decision", "allow")
+    tiered.set("raw", b"\x00\xff")
+
+    assert tiered.get("decision") == "allow"
+    assert tiered.get("raw") == b"\x00\xff"
+    assert tiered.l2_errors == 2
+
+
+def test_redis_errors_are_misses(tiered):
+    assert tiered.get("missing") is None
+    assert tiered.delete("missing") is False
+    assert tiered.l2_hits == 0
+    assert tiered.l2_errors == 2
+
+
+def test_delete_and_clear_reach_l1(tiered):
+    tiered.set("a", "1")
+    tiered.set("b", "2")
+
+    assert tiered.delete("a") is True
+    tiered.clear()
+    assert tiered.stats().size == 0
+
+
+def test_invalid_url_is_refused():
+    from sark._rust import RustTieredCache, SarkCacheError
+
+    with pytest.raises(SarkCacheError, match="Redis URL"):
+        RustTieredCache(max_size=10, ttl_secs=60, redis_url="not a url")